@@ -0,0 +1,613 @@
+//! The compiler as a library: entry points that compile a program and return
+//! its diagnostics as plain values, with their spans already resolved to
+//! files, lines and columns, for editors and other tools embedding csub.
+
+use crate::{
+    ast::Program,
+    driver::{compile, compile_file},
+    emitter::SpanLocs,
+    errors::{self, DiagBag},
+    extensions::Extensions,
+    ice::catch_ice,
+    node_map::NodeMap,
+    parser::parse_program,
+    reparse::{reparse_edit, Edit},
+    scanner::scan_words,
+    sema::{check_program, Builtin},
+    semantic_tokens,
+    source_map::{BytePos, Pos, SourceFile, SourceMap, Span},
+    symbols,
+};
+use std::rc::Rc;
+
+pub use crate::{
+    errors::{Applicability, Phase, Severity},
+    semantic_tokens::TokenKind,
+    symbols::{ScopeKind, SymbolKind},
+};
+
+/// Where a span of source code is. Lines and columns start at 1, columns
+/// count bytes, and the end column points one past the last character.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+}
+
+/// A location pointed at by a diagnostic, optionally explaining its role.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub location: Location,
+    pub message: Option<String>,
+    pub is_primary: bool,
+}
+
+/// A note or help message attached to a diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Child {
+    pub severity: Severity,
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+/// A replacement of the text at `location` that fixes the diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub message: String,
+    pub location: Location,
+    pub replacement: String,
+    pub applicability: Applicability,
+}
+
+/// A diagnostic reported while compiling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The error code, e.g. `E0001`, if the diagnostic has one.
+    pub code: Option<String>,
+    pub severity: Severity,
+    /// The phase of the compiler the diagnostic comes from, if any.
+    pub phase: Option<Phase>,
+    pub message: String,
+    /// Where the diagnostic is reported at, if it's about some source code.
+    pub location: Option<Location>,
+    /// Every location the diagnostic points at, the primary one first.
+    pub labels: Vec<Label>,
+    pub children: Vec<Child>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    fn new(
+        source_map: &SourceMap,
+        diagnostic: &errors::Diagnostic,
+    ) -> Diagnostic {
+        let locate = |span| locate(source_map, span);
+
+        Diagnostic {
+            code: diagnostic.diag.code().map(str::to_owned),
+            severity: diagnostic.severity,
+            phase: diagnostic.diag.phase(),
+            message: diagnostic.diag.message(),
+            location: diagnostic.diag.span().and_then(locate),
+            labels: diagnostic
+                .labels()
+                .into_iter()
+                .filter_map(|label| {
+                    Some(Label {
+                        location: locate(label.span)?,
+                        message: label.message,
+                        is_primary: label.is_primary,
+                    })
+                })
+                .collect(),
+            children: diagnostic
+                .children
+                .iter()
+                .map(|child| Child {
+                    severity: child.severity,
+                    message: child.message.clone(),
+                    location: child.span.and_then(locate),
+                })
+                .collect(),
+            suggestions: diagnostic
+                .suggestions
+                .iter()
+                .filter_map(|suggestion| {
+                    Some(Suggestion {
+                        message: suggestion.message.clone(),
+                        location: locate(suggestion.span)?,
+                        replacement: suggestion.replacement.clone(),
+                        applicability: suggestion.applicability,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self.severity, Severity::Fatal | Severity::Error)
+    }
+}
+
+fn locate(source_map: &SourceMap, span: Span) -> Option<Location> {
+    let source_file = source_map.lookup_file(span.start)?;
+    let locs = SpanLocs::resolve(source_file, span);
+
+    Some(Location {
+        file: source_file.name.clone(),
+        byte_start: (span.start - source_file.start_pos).to_usize(),
+        byte_end: (span.end - source_file.start_pos).to_usize(),
+        line_start: locs.line_start,
+        column_start: locs.column_start,
+        line_end: locs.line_end,
+        column_end: locs.column_end,
+    })
+}
+
+/// Runs `compile` without an error limit, returning its diagnostics in source
+/// order. A panic in the compiler is reported as an internal compiler error.
+fn run(compile: impl FnOnce(&mut SourceMap, &mut DiagBag)) -> Vec<Diagnostic> {
+    let mut source_map = SourceMap::new();
+    let mut diag_bag = DiagBag::new();
+    if let Err(ice) = catch_ice(|| compile(&mut source_map, &mut diag_bag)) {
+        diag_bag.push_unlimited(ice.diagnostic());
+    }
+    diag_bag.sort_by_position();
+
+    diag_bag
+        .iter()
+        .map(|diagnostic| Diagnostic::new(&source_map, diagnostic))
+        .collect()
+}
+
+/// Compiles the program `source`, called `name` in the diagnostics, as
+/// reference C-, without extensions.
+pub fn check_source(name: &str, source: &str) -> Vec<Diagnostic> {
+    run(|source_map, diag_bag| {
+        let source_file = source_map.add_file(name.into(), source.into());
+        compile_file(&source_file, Extensions::none(), diag_bag);
+    })
+}
+
+/// Compiles the program in the file at `path`, as reference C-, without
+/// extensions.
+pub fn check_file(path: &str) -> Vec<Diagnostic> {
+    run(|source_map, diag_bag| {
+        compile(path, Extensions::none(), source_map, diag_bag);
+    })
+}
+
+/// A scope of a program, with the names declared in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    /// The index of the scope it's in, or `None` for the outermost one, of
+    /// the builtins.
+    pub parent: Option<usize>,
+    /// The name of the function it's in, if any.
+    pub function: Option<String>,
+    /// `None` for the builtins and the file.
+    pub location: Option<Location>,
+    pub symbols: Vec<Symbol>,
+}
+
+/// A name declared in a scope.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The type written as in C, e.g. `int [10]` or `int (int *)`.
+    pub ty: String,
+    /// Where the name is declared, or `None` for a builtin.
+    pub location: Option<Location>,
+}
+
+/// The scopes of the program `source`, called `name`, each after the one
+/// it's in, as `--emit=symbols` shows them. Empty if the program couldn't
+/// be parsed.
+pub fn symbol_table(name: &str, source: &str) -> Vec<Scope> {
+    let mut source_map = SourceMap::new();
+    let source_file = source_map.add_file(name.into(), source.into());
+    let program = catch_ice(|| {
+        compile_file(&source_file, Extensions::none(), &mut DiagBag::new())
+    });
+    let table = match program {
+        Ok(Some(program)) => symbols::symbol_table(&program),
+        Ok(None) | Err(_) => return Vec::new(),
+    };
+    let locate = |span: Option<Span>| locate(&source_map, span?);
+
+    table
+        .scopes
+        .into_iter()
+        .map(|scope| Scope {
+            kind: scope.kind,
+            parent: scope.parent,
+            function: scope.fun,
+            location: locate(scope.span),
+            symbols: scope
+                .symbols
+                .into_iter()
+                .map(|symbol| Symbol {
+                    name: symbol.name,
+                    kind: symbol.kind,
+                    ty: symbol.ty,
+                    location: locate(symbol.span),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// A name in a program and what it names, for editors to highlight it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub location: Location,
+    pub kind: TokenKind,
+    /// Whether it names an array, including an array parameter.
+    pub is_array: bool,
+    /// Whether it's the name in the declaration, rather than a use.
+    pub is_declaration: bool,
+}
+
+/// The names in the program `source`, called `name`, that name a function
+/// or a variable, in source order. Names that couldn't be resolved aren't
+/// in it, and it's empty if the program couldn't be scanned.
+pub fn semantic_tokens(name: &str, source: &str) -> Vec<SemanticToken> {
+    Document::new(name, source).semantic_tokens()
+}
+
+/// A program being edited, e.g. in an editor, kept parsed between edits. An
+/// edit inside one declaration only parses that declaration again.
+pub struct Document {
+    source_map: SourceMap,
+    source_file: Rc<SourceFile>,
+    /// `None` if the source couldn't be scanned.
+    program: Option<Program>,
+}
+
+impl Document {
+    /// Parses the program `source`, called `name`, as reference C-.
+    pub fn new(name: &str, source: &str) -> Document {
+        let mut source_map = SourceMap::new();
+        let source_file = source_map.add_file(name.into(), source.into());
+        let program = parse_file(&source_file);
+
+        Document {
+            source_map,
+            source_file,
+            program,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source_file.src
+    }
+
+    /// Replaces the bytes of the source from `byte_start` to `byte_end` with
+    /// `replacement`, and parses what that changes again. Returns the index
+    /// of the declaration the edit is in, if it was enough to parse that one
+    /// again, or `None` if the whole program was.
+    ///
+    /// Panics if the bytes aren't a range of whole characters of the source.
+    pub fn edit(
+        &mut self,
+        byte_start: usize,
+        byte_end: usize,
+        replacement: &str,
+    ) -> Option<usize> {
+        let edit = Edit {
+            span: Span {
+                start: BytePos::from_usize(byte_start),
+                end: BytePos::from_usize(byte_end),
+            },
+            replacement: replacement.into(),
+        };
+        let reparsed = match &mut self.program {
+            Some(program) => catch_ice(|| {
+                reparse_edit(
+                    program,
+                    &self.source_file,
+                    &edit,
+                    Extensions::none(),
+                )
+            })
+            .ok()
+            .flatten(),
+            None => None,
+        };
+
+        let edited_source = match &reparsed {
+            Some(reparsed) => reparsed.source_file.src.to_string(),
+            None => {
+                let mut source = self.source_file.src.to_string();
+                source.replace_range(byte_start..byte_end, replacement);
+                source
+            }
+        };
+        self.source_map = SourceMap::new();
+        self.source_file = self
+            .source_map
+            .add_file(self.source_file.name.clone(), edited_source);
+        if reparsed.is_none() {
+            self.program = parse_file(&self.source_file);
+        }
+        reparsed.map(|reparsed| reparsed.index)
+    }
+
+    /// The names in the program that name a function or a variable, in
+    /// source order, like [`semantic_tokens`].
+    pub fn semantic_tokens(&self) -> Vec<SemanticToken> {
+        let Some(program) = &self.program else {
+            return Vec::new();
+        };
+        let tokens = catch_ice(|| {
+            let resolutions = check_program(program, &Builtin::ALL).resolutions;
+            semantic_tokens::semantic_tokens(program, &resolutions)
+        })
+        .unwrap_or_default();
+
+        tokens
+            .into_iter()
+            .filter_map(|token| {
+                Some(SemanticToken {
+                    location: locate(&self.source_map, token.span)?,
+                    kind: token.kind,
+                    is_array: token.is_array,
+                    is_declaration: token.is_declaration,
+                })
+            })
+            .collect()
+    }
+
+    /// Where the innermost node of the program at byte `offset` of the source
+    /// is, and then each node it's in, up to its top-level declaration, e.g.
+    /// for an editor to grow a selection by. Empty if no node is there.
+    pub fn enclosing_locations(&self, offset: usize) -> Vec<Location> {
+        let Some(program) = &self.program else {
+            return Vec::new();
+        };
+        let node_map = NodeMap::of(program);
+        let Some(node) = node_map.node_at(BytePos::from_usize(offset)) else {
+            return Vec::new();
+        };
+
+        node_map
+            .ancestors(node)
+            .filter_map(|node| locate(&self.source_map, node_map.span(node)?))
+            .collect()
+    }
+}
+
+/// Parses `source_file` as reference C-, returning `None` if it couldn't be
+/// scanned.
+fn parse_file(source_file: &SourceFile) -> Option<Program> {
+    catch_ice(|| {
+        let (words, scan_diags) = scan_words(source_file, Extensions::none());
+        if scan_diags.has_errors() {
+            return None;
+        }
+        Some(parse_program(source_file, &words, Extensions::none()).program)
+    })
+    .ok()
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_file, check_source, semantic_tokens, symbol_table, Applicability,
+        Diagnostic, Document, Location, Phase, ScopeKind, Severity, SymbolKind,
+        TokenKind,
+    };
+    use crate::{
+        errors::{self, Diag},
+        source_map::{BytePos, SourceMap, Span},
+    };
+
+    #[test]
+    fn check_source_resolves_locations() {
+        let diagnostics = check_source("main.cm", "int x;\nx = @;\n");
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.code.as_deref(), Some("E0001"));
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.phase, Some(Phase::Lex));
+        assert_eq!(diagnostic.message, "unknown character '@'");
+        assert_eq!(
+            diagnostic.location,
+            Some(Location {
+                file: "main.cm".into(),
+                byte_start: 11,
+                byte_end: 12,
+                line_start: 2,
+                column_start: 5,
+                line_end: 2,
+                column_end: 6,
+            })
+        );
+        assert_eq!(diagnostic.labels.len(), 1);
+        assert!(diagnostic.labels[0].is_primary);
+        assert!(diagnostic.is_error());
+    }
+
+    #[test]
+    fn check_source_without_problems() {
+        assert_eq!(
+            check_source("main.cm", "int x;\nvoid main(void) { }\n"),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn check_file_reports_unreadable_files() {
+        let diagnostics = check_file("this/file/does/not/exist.cm");
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code.as_deref(), Some("E0007"));
+        assert_eq!(diagnostics[0].severity, Severity::Fatal);
+        assert_eq!(diagnostics[0].location, None);
+    }
+
+    #[test]
+    fn symbol_table_lists_scopes_in_order() {
+        let scopes = symbol_table(
+            "main.cm",
+            "int g;\nvoid main(void) { int x; if (g) { int y; } }\n",
+        );
+
+        let kinds: Vec<_> = scopes
+            .iter()
+            .map(|scope| (scope.kind.clone(), scope.parent))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (ScopeKind::Builtins, None),
+                (ScopeKind::File, Some(0)),
+                (ScopeKind::Function, Some(1)),
+                (ScopeKind::Block, Some(2)),
+            ]
+        );
+        assert_eq!(scopes[3].function.as_deref(), Some("main"));
+        let y = &scopes[3].symbols[0];
+        assert_eq!((y.name.as_str(), y.kind), ("y", SymbolKind::Local));
+        assert_eq!(y.ty, "int");
+        assert_eq!(
+            y.location.as_ref().map(|location| location.column_start),
+            Some(39)
+        );
+        assert_eq!(scopes[0].symbols[0].location, None);
+    }
+
+    #[test]
+    fn symbol_table_of_unscannable_source_is_empty() {
+        assert_eq!(symbol_table("main.cm", "int @;\n"), Vec::new());
+    }
+
+    #[test]
+    fn semantic_tokens_are_located() {
+        let tokens = semantic_tokens(
+            "main.cm",
+            "int v[2];\nvoid main(void) { v[0] = input(); }\n",
+        );
+
+        let kinds: Vec<_> = tokens
+            .iter()
+            .map(|token| {
+                let location = &token.location;
+                (
+                    location.line_start,
+                    location.column_start,
+                    token.kind,
+                    token.is_array,
+                    token.is_declaration,
+                )
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (1, 5, TokenKind::Global, true, true),
+                (2, 6, TokenKind::Function, false, true),
+                (2, 19, TokenKind::Global, true, false),
+                (2, 26, TokenKind::Builtin, false, false),
+            ]
+        );
+        assert_eq!(semantic_tokens("main.cm", "int @;\n"), Vec::new());
+    }
+
+    #[test]
+    fn documents_parse_again_what_an_edit_changes() {
+        let mut document =
+            Document::new("main.cm", "int g;\nvoid main(void) { g = 1; }\n");
+        let names = |document: &Document| {
+            document
+                .semantic_tokens()
+                .iter()
+                .map(|token| {
+                    (token.location.line_start, token.location.column_start)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(document.edit(29, 30, "g + 1"), Some(1));
+        assert_eq!(
+            document.source(),
+            "int g;\nvoid main(void) { g = g + 1; }\n"
+        );
+        assert_eq!(names(&document), [(1, 5), (2, 6), (2, 19), (2, 23)]);
+
+        // Across two declarations.
+        assert_eq!(document.edit(0, 11, "int h;\nvoid"), None);
+        assert_eq!(names(&document), [(1, 5), (2, 6)]);
+
+        assert_eq!(document.edit(4, 5, "@"), None);
+        assert_eq!(names(&document), []);
+    }
+
+    #[test]
+    fn enclosing_locations_go_from_the_innermost_node_out() {
+        let source = "int g;\nvoid main(void) { if (g) g = 1 + 2; }\n";
+        let document = Document::new("main.cm", source);
+
+        let snippets: Vec<_> = document
+            .enclosing_locations(source.find('2').unwrap())
+            .iter()
+            .map(|location| &source[location.byte_start..location.byte_end])
+            .collect();
+
+        assert_eq!(
+            snippets,
+            [
+                "2",
+                "1 + 2",
+                "g = 1 + 2",
+                "g = 1 + 2;",
+                "if (g) g = 1 + 2;",
+                "{ if (g) g = 1 + 2; }",
+                "void main(void) { if (g) g = 1 + 2; }",
+            ]
+        );
+        assert_eq!(document.enclosing_locations(6), []);
+    }
+
+    #[test]
+    fn children_and_suggestions_are_located() {
+        let mut source_map = SourceMap::new();
+        source_map.add_file("main.cm".into(), "int x\n".into());
+        let diagnostic = errors::Diagnostic::builder(Diag::UnknownCharacter {
+            pos: BytePos(0),
+            ch: 'i',
+        })
+        .with_note(Some(Span::with_usizes(4, 5)), "declared here")
+        .with_suggestion(errors::Suggestion::insertion(
+            BytePos(5),
+            ";",
+            "add a semicolon",
+        ))
+        .build();
+
+        let diagnostic = Diagnostic::new(&source_map, &diagnostic);
+
+        assert_eq!(diagnostic.children[0].message, "declared here");
+        assert_eq!(
+            diagnostic.children[0]
+                .location
+                .as_ref()
+                .map(|location| location.column_start),
+            Some(5)
+        );
+        let suggestion = &diagnostic.suggestions[0];
+        assert_eq!(suggestion.replacement, ";");
+        assert_eq!(suggestion.applicability, Applicability::MachineApplicable);
+        assert_eq!(suggestion.location.byte_start, 5);
+        assert_eq!(suggestion.location.byte_end, 5);
+    }
+}
@@ -0,0 +1,941 @@
+//! An assembler of the x86-64 assembly the backend writes, into the contents
+//! of an object file (see `elf`), so `csub build --obj` needs no assembler
+//! of its own.
+//!
+//! It only knows the instructions and directives `x86_64` and `runtime`
+//! write, in the forms they write them: anything else is a bug of the
+//! backend, not of the program, and panics. Each instruction is encoded as
+//! GNU `as` encodes it, but for jumps, which always take 32 bits of
+//! displacement, as they'd have to be assembled again to take 8.
+//!
+//! A jump or call to a label of `.text` is resolved here. A reference to a
+//! label of another section, e.g. `leaq .Lstr0(%rip), %rax`, is left to the
+//! linker, relative to the start of the section, and one to a symbol
+//! that's not defined at all, e.g. `call printf@PLT`, relative to the
+//! symbol, which is then undefined in the object.
+//!
+//! The `.file` and `.loc` directives, of code compiled with `-g`, are made
+//! the line table of the debug info of the object (see `dwarf`).
+
+use crate::{
+    dwarf::add_debug_info,
+    elf::{Object, RelocKind, Relocation, Section, Symbol, SymbolKind, Target},
+};
+use std::collections::{HashMap, HashSet};
+
+/// The size an instruction works on, as its suffix says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Size {
+    Byte,
+    Long,
+    Quad,
+}
+
+impl Size {
+    /// The mnemonic without its suffix, and the size it says.
+    fn split(mnemonic: &str) -> (&str, Size) {
+        let (name, suffix) = mnemonic.split_at(mnemonic.len() - 1);
+        match suffix {
+            "b" => (name, Size::Byte),
+            "l" => (name, Size::Long),
+            "q" => (name, Size::Quad),
+            _ => (mnemonic, Size::Long),
+        }
+    }
+}
+
+/// A register, by its number in the encoding, and the size of the name it
+/// goes by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Reg {
+    number: u8,
+    size: Size,
+}
+
+impl Reg {
+    fn parse(name: &str) -> Option<Reg> {
+        const QUADS: [&str; 8] =
+            ["rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi"];
+        const LONGS: [&str; 8] =
+            ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi"];
+        const BYTES: [&str; 8] =
+            ["al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil"];
+        for (names, size) in [
+            (QUADS, Size::Quad),
+            (LONGS, Size::Long),
+            (BYTES, Size::Byte),
+        ] {
+            if let Some(number) = names.iter().position(|&reg| reg == name) {
+                return Some(Reg {
+                    number: number as u8,
+                    size,
+                });
+            }
+        }
+        let number = name.strip_prefix('r')?;
+        let (number, size) = match number.strip_suffix('d') {
+            Some(number) => (number, Size::Long),
+            None => match number.strip_suffix('b') {
+                Some(number) => (number, Size::Byte),
+                None => (number, Size::Quad),
+            },
+        };
+        match number.parse() {
+            Ok(number @ 8..=15) => Some(Reg { number, size }),
+            _ => None,
+        }
+    }
+
+    /// Whether it can only be named with a REX prefix, as `%sil` is, which
+    /// would otherwise be `%dh`.
+    fn needs_rex(self) -> bool {
+        self.size == Size::Byte && (4..8).contains(&self.number)
+    }
+}
+
+/// A place in memory an instruction reads or writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mem<'a> {
+    /// `disp(%base)`.
+    Base { base: u8, disp: i32 },
+    /// `symbol(%rip)`, or `symbol@GOTPCREL(%rip)`, the entry of the symbol
+    /// in the GOT.
+    Rip { symbol: &'a str, got: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand<'a> {
+    Reg(Reg),
+    Imm(i64),
+    Mem(Mem<'a>),
+    /// What's jumped to or called.
+    Label(&'a str),
+}
+
+impl<'a> Operand<'a> {
+    fn parse(operand: &'a str) -> Option<Operand<'a>> {
+        if let Some(name) = operand.strip_prefix('%') {
+            return Reg::parse(name).map(Operand::Reg);
+        }
+        if let Some(value) = operand.strip_prefix('$') {
+            return value.parse().ok().map(Operand::Imm);
+        }
+        let Some((disp, base)) = operand.split_once('(') else {
+            let label = operand.strip_suffix("@PLT").unwrap_or(operand);
+            return Some(Operand::Label(label));
+        };
+        let base = base.strip_suffix(')')?.strip_prefix('%')?;
+        if base == "rip" {
+            let (symbol, got) = match disp.strip_suffix("@GOTPCREL") {
+                Some(symbol) => (symbol, true),
+                None => (disp, false),
+            };
+            return Some(Operand::Mem(Mem::Rip { symbol, got }));
+        }
+        let base = Reg::parse(base).filter(|reg| reg.size == Size::Quad)?;
+        let disp = if disp.is_empty() {
+            0
+        } else {
+            disp.parse().ok()?
+        };
+        Some(Operand::Mem(Mem::Base {
+            base: base.number,
+            disp,
+        }))
+    }
+
+    /// Whether it's a register or a place in memory, as the operand of a
+    /// ModRM byte may be.
+    fn is_rm(self) -> bool {
+        matches!(self, Operand::Reg(_) | Operand::Mem(_))
+    }
+
+    fn needs_rex(self) -> bool {
+        matches!(self, Operand::Reg(reg) if reg.needs_rex())
+    }
+}
+
+/// A 32-bit displacement relative to the end of its instruction, to fill in
+/// once every label is known.
+struct Fixup<'a> {
+    section: Section,
+    offset: u64,
+    /// Where the instruction ends, which the displacement is relative to.
+    end: u64,
+    symbol: &'a str,
+    kind: RelocKind,
+}
+
+/// The condition codes, by the suffix of `j` and `set`, as they're added to
+/// the opcode.
+const CONDITIONS: [(&str, u8); 12] = [
+    ("b", 0x2),
+    ("ae", 0x3),
+    ("e", 0x4),
+    ("ne", 0x5),
+    ("be", 0x6),
+    ("a", 0x7),
+    ("s", 0x8),
+    ("ns", 0x9),
+    ("l", 0xc),
+    ("ge", 0xd),
+    ("le", 0xe),
+    ("g", 0xf),
+];
+
+fn condition(suffix: &str) -> Option<u8> {
+    CONDITIONS
+        .iter()
+        .find(|&&(name, _)| name == suffix)
+        .map(|&(_, code)| code)
+}
+
+/// The instructions of arithmetic, by the extension of the opcode of their
+/// forms with an immediate, which is also their opcode with registers over
+/// eight.
+const ARITHMETIC: [(&str, u8); 6] = [
+    ("add", 0),
+    ("or", 1),
+    ("and", 4),
+    ("sub", 5),
+    ("xor", 6),
+    ("cmp", 7),
+];
+
+/// Assembles `asm`, as `x86_64::emit_asm` writes it, into an object.
+pub(crate) fn assemble(asm: &str) -> Object {
+    let mut assembler = Assembler {
+        object: Object::default(),
+        section: Section::Text,
+        labels: HashMap::new(),
+        defined: Vec::new(),
+        globals: HashSet::new(),
+        funcs: HashSet::new(),
+        fixups: Vec::new(),
+        file: None,
+        lines: Vec::new(),
+    };
+    for line in asm.lines() {
+        assembler.line(line.trim());
+    }
+    assembler.finish()
+}
+
+struct Assembler<'a> {
+    object: Object,
+    section: Section,
+    /// Where each label is.
+    labels: HashMap<&'a str, (Section, u64)>,
+    /// The labels that are symbols, which are those not starting with
+    /// `.L`, in the order they're defined.
+    defined: Vec<&'a str>,
+    globals: HashSet<&'a str>,
+    /// The symbols `.type` says are functions.
+    funcs: HashSet<&'a str>,
+    fixups: Vec<Fixup<'a>>,
+    /// The source file `.file` names, and where in `.text` each `.loc`
+    /// says the code is of which of its lines.
+    file: Option<Vec<u8>>,
+    lines: Vec<(u64, usize)>,
+}
+
+impl<'a> Assembler<'a> {
+    /// The bytes of the section being assembled into.
+    fn here(&mut self) -> &mut Vec<u8> {
+        &mut self
+            .object
+            .sections
+            .entry(self.section)
+            .or_insert((Vec::new(), 1))
+            .0
+    }
+
+    fn offset(&mut self) -> u64 {
+        self.here().len() as u64
+    }
+
+    fn line(&mut self, line: &'a str) {
+        if line.is_empty() {
+            return;
+        }
+        if let Some(label) = line.strip_suffix(':') {
+            let offset = self.offset();
+            if self.labels.insert(label, (self.section, offset)).is_some() {
+                panic!("`{}` is defined twice", label);
+            }
+            if !label.starts_with(".L") {
+                self.defined.push(label);
+            }
+            return;
+        }
+        let (name, args) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, args)| (name, args.trim()));
+        let assembled = if name.starts_with('.') {
+            self.directive(name, args)
+        } else if line == "rep movsb" {
+            self.here().extend_from_slice(&[0xf3, 0xa4]);
+            true
+        } else {
+            let operands: Option<Vec<Operand>> = args
+                .split(',')
+                .map(str::trim)
+                .filter(|operand| !operand.is_empty())
+                .map(Operand::parse)
+                .collect();
+            operands.is_some_and(|operands| self.inst(name, &operands))
+        };
+        if !assembled {
+            panic!("can't assemble `{}`", line);
+        }
+    }
+
+    /// Assembles the directive `name`, returning whether it's known.
+    fn directive(&mut self, name: &str, args: &'a str) -> bool {
+        match name {
+            ".text" => self.section = Section::Text,
+            ".data" => self.section = Section::Data,
+            ".bss" => self.section = Section::Bss,
+            ".section" if args == ".rodata" => self.section = Section::Rodata,
+            // The object always says so.
+            ".section" if args.starts_with(".note.GNU-stack,") => {}
+            ".globl" => {
+                self.globals.insert(args);
+            }
+            ".type" => match args.split_once(", ") {
+                Some((symbol, "@function")) => {
+                    self.funcs.insert(symbol);
+                }
+                _ => return false,
+            },
+            ".align" => {
+                let Ok(align) = args.parse::<u64>() else {
+                    return false;
+                };
+                let fill = match self.section {
+                    Section::Text => 0x90,
+                    Section::Data
+                    | Section::Rodata
+                    | Section::Bss
+                    | Section::DebugInfo
+                    | Section::DebugAbbrev
+                    | Section::DebugLine => 0,
+                };
+                let section = self.section;
+                let (bytes, max) = self
+                    .object
+                    .sections
+                    .entry(section)
+                    .or_insert((Vec::new(), 1));
+                bytes
+                    .resize(bytes.len().next_multiple_of(align as usize), fill);
+                *max = (*max).max(align);
+            }
+            // Only `.zero` and `.align` can be in `.bss`, which has no
+            // contents but zeros.
+            ".byte" | ".long" | ".quad" | ".asciz"
+                if self.section == Section::Bss =>
+            {
+                return false
+            }
+            ".byte" | ".long" | ".quad" => {
+                let Ok(value) = args.parse::<i64>() else {
+                    return false;
+                };
+                let size = match name {
+                    ".byte" => 1,
+                    ".long" => 4,
+                    _ => 8,
+                };
+                self.here().extend_from_slice(&value.to_le_bytes()[..size]);
+            }
+            ".file" => {
+                let Some(file) = args
+                    .strip_prefix("1 \"")
+                    .and_then(|args| args.strip_suffix('"'))
+                    .and_then(unescape)
+                else {
+                    return false;
+                };
+                self.file = Some(file);
+            }
+            ".loc" => {
+                let Some(Ok(line)) =
+                    args.strip_prefix("1 ").map(str::parse::<usize>)
+                else {
+                    return false;
+                };
+                let offset = self.offset();
+                self.lines.push((offset, line));
+            }
+            ".zero" => {
+                let Ok(size) = args.parse::<usize>() else {
+                    return false;
+                };
+                let bytes = self.here();
+                bytes.resize(bytes.len() + size, 0);
+            }
+            ".asciz" => {
+                let Some(string) = args
+                    .strip_prefix('"')
+                    .and_then(|args| args.strip_suffix('"'))
+                    .and_then(unescape)
+                else {
+                    return false;
+                };
+                self.here().extend_from_slice(&string);
+                self.here().push(0);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Assembles the instruction `name`, returning whether it's known with
+    /// `operands`.
+    fn inst(&mut self, name: &str, operands: &[Operand<'a>]) -> bool {
+        use Operand::{Imm, Label, Mem, Reg};
+
+        let (base, size) = Size::split(name);
+        let w = size == Size::Quad;
+        match (name, operands) {
+            ("ret", []) => self.here().push(0xc3),
+            ("leave", []) => self.here().push(0xc9),
+            ("nop", []) => self.here().push(0x90),
+            ("cltd", []) => self.here().push(0x99),
+            ("pushq", [Reg(reg)]) => {
+                if reg.number >= 8 {
+                    self.here().push(0x41);
+                }
+                self.here().push(0x50 + (reg.number & 7));
+            }
+            ("popq", [Reg(reg)]) => {
+                if reg.number >= 8 {
+                    self.here().push(0x41);
+                }
+                self.here().push(0x58 + (reg.number & 7));
+            }
+            ("call", [Label(label)]) => {
+                self.here().push(0xe8);
+                self.rel32(label, RelocKind::Plt32);
+            }
+            ("jmp", [Label(label)]) => {
+                self.here().push(0xe9);
+                self.rel32(label, RelocKind::Pc32);
+            }
+            ("movabsq", [Imm(value), Reg(dst)]) => {
+                self.here().push(0x48 | dst.number >> 3);
+                self.here().push(0xb8 + (dst.number & 7));
+                self.here().extend_from_slice(&value.to_le_bytes());
+            }
+            ("movslq", [src, Reg(dst)]) if src.is_rm() => {
+                self.modrm(true, false, &[0x63], dst.number, *src, &[]);
+            }
+            ("movzbl", [src, Reg(dst)]) if src.is_rm() => {
+                let rex = src.needs_rex();
+                self.modrm(false, rex, &[0x0f, 0xb6], dst.number, *src, &[]);
+            }
+            ("leaq", [src @ Mem(_), Reg(dst)]) => {
+                self.modrm(true, false, &[0x8d], dst.number, *src, &[]);
+            }
+            ("movb" | "movl" | "movq", [src, dst]) => {
+                let byte = size == Size::Byte;
+                let rex = src.needs_rex() || dst.needs_rex();
+                match (*src, *dst) {
+                    (Reg(src), dst) if dst.is_rm() => {
+                        let opcode = if byte { 0x88 } else { 0x89 };
+                        self.modrm(w, rex, &[opcode], src.number, dst, &[]);
+                    }
+                    (src @ Mem(_), Reg(dst)) => {
+                        let opcode = if byte { 0x8a } else { 0x8b };
+                        self.modrm(w, rex, &[opcode], dst.number, src, &[]);
+                    }
+                    (Imm(value), dst) if dst.is_rm() => {
+                        let (opcode, imm) = if byte {
+                            (0xc6, vec![value as u8])
+                        } else {
+                            let Ok(value) = i32::try_from(value) else {
+                                return false;
+                            };
+                            (0xc7, value.to_le_bytes().to_vec())
+                        };
+                        self.modrm(w, rex, &[opcode], 0, dst, &imm);
+                    }
+                    _ => return false,
+                }
+            }
+            (_, [src, dst])
+                if size != Size::Byte
+                    && ARITHMETIC.iter().any(|&(op, _)| op == base) =>
+            {
+                let (_, ext) =
+                    *ARITHMETIC.iter().find(|&&(op, _)| op == base).unwrap();
+                match (*src, *dst) {
+                    (Reg(src), dst) if dst.is_rm() => {
+                        self.modrm(
+                            w,
+                            false,
+                            &[ext << 3 | 1],
+                            src.number,
+                            dst,
+                            &[],
+                        );
+                    }
+                    (src @ Mem(_), Reg(dst)) => {
+                        self.modrm(
+                            w,
+                            false,
+                            &[ext << 3 | 3],
+                            dst.number,
+                            src,
+                            &[],
+                        );
+                    }
+                    (Imm(value), dst) if dst.is_rm() => {
+                        let Ok(value) = i32::try_from(value) else {
+                            return false;
+                        };
+                        match i8::try_from(value) {
+                            Ok(value) => self.modrm(
+                                w,
+                                false,
+                                &[0x83],
+                                ext,
+                                dst,
+                                &[value as u8],
+                            ),
+                            Err(_) => self.modrm(
+                                w,
+                                false,
+                                &[0x81],
+                                ext,
+                                dst,
+                                &value.to_le_bytes(),
+                            ),
+                        }
+                    }
+                    _ => return false,
+                }
+            }
+            ("testl" | "testq", [Reg(src), dst]) if dst.is_rm() => {
+                self.modrm(w, false, &[0x85], src.number, *dst, &[]);
+            }
+            ("imull" | "imulq", [src, Reg(dst)]) => match *src {
+                Imm(value) => {
+                    let Ok(value) = i32::try_from(value) else {
+                        return false;
+                    };
+                    let (opcode, imm) = match i8::try_from(value) {
+                        Ok(value) => (0x6b, vec![value as u8]),
+                        Err(_) => (0x69, value.to_le_bytes().to_vec()),
+                    };
+                    self.modrm(
+                        w,
+                        false,
+                        &[opcode],
+                        dst.number,
+                        Reg(*dst),
+                        &imm,
+                    );
+                }
+                src if src.is_rm() => {
+                    self.modrm(w, false, &[0x0f, 0xaf], dst.number, src, &[]);
+                }
+                _ => return false,
+            },
+            ("idivl" | "idivq", [src]) if src.is_rm() => {
+                self.modrm(w, false, &[0xf7], 7, *src, &[]);
+            }
+            ("negl" | "negq", [src]) if src.is_rm() => {
+                self.modrm(w, false, &[0xf7], 3, *src, &[]);
+            }
+            (_, [dst]) if name.starts_with("set") && dst.is_rm() => {
+                let Some(code) = condition(&name[3..]) else {
+                    return false;
+                };
+                let rex = dst.needs_rex();
+                self.modrm(false, rex, &[0x0f, 0x90 | code], 0, *dst, &[]);
+            }
+            (_, [Label(label)]) if name.starts_with('j') => {
+                let Some(code) = condition(&name[1..]) else {
+                    return false;
+                };
+                self.here().extend_from_slice(&[0x0f, 0x80 | code]);
+                self.rel32(label, RelocKind::Pc32);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Writes an instruction of `opcode` whose ModRM byte has `reg`, a
+    /// register or an extension of the opcode, and `rm`, followed by
+    /// `imm`, with a REX prefix if `w` says it works on 64 bits, if a
+    /// register is over seven, or if `rex` says it's needed anyway.
+    fn modrm(
+        &mut self,
+        w: bool,
+        rex: bool,
+        opcode: &[u8],
+        reg: u8,
+        rm: Operand<'a>,
+        imm: &[u8],
+    ) {
+        let mut prefix = 0x40 | u8::from(w) << 3 | (reg >> 3) << 2;
+        let reg = (reg & 7) << 3;
+        let mut rest = Vec::new();
+        let mut rip = None;
+        match rm {
+            Operand::Reg(rm) => {
+                prefix |= rm.number >> 3;
+                rest.push(0xc0 | reg | rm.number & 7);
+            }
+            Operand::Mem(Mem::Base { base, disp }) => {
+                prefix |= base >> 3;
+                // `%rbp` and `%r13` can't go without a displacement, as
+                // that would mean `%rip`, and `%rsp` and `%r12` take a SIB
+                // byte.
+                let (mode, disp) = if disp == 0 && base & 7 != 5 {
+                    (0x00, Vec::new())
+                } else if let Ok(disp) = i8::try_from(disp) {
+                    (0x40, vec![disp as u8])
+                } else {
+                    (0x80, disp.to_le_bytes().to_vec())
+                };
+                rest.push(mode | reg | base & 7);
+                if base & 7 == 4 {
+                    rest.push(0x24);
+                }
+                rest.extend(disp);
+            }
+            Operand::Mem(Mem::Rip { symbol, got }) => {
+                rest.push(reg | 5);
+                rip = Some((rest.len(), symbol, got));
+                rest.extend([0; 4]);
+            }
+            Operand::Imm(_) | Operand::Label(_) => {
+                unreachable!("{:?} isn't a register or in memory", rm)
+            }
+        }
+        if prefix != 0x40 || rex {
+            self.here().push(prefix);
+        }
+        self.here().extend_from_slice(opcode);
+        let start = self.offset();
+        self.here().extend_from_slice(&rest);
+        self.here().extend_from_slice(imm);
+        if let Some((index, symbol, got)) = rip {
+            let kind = if got {
+                RelocKind::GotPcRel
+            } else {
+                RelocKind::Pc32
+            };
+            let end = self.offset();
+            self.fixups.push(Fixup {
+                section: self.section,
+                offset: start + index as u64,
+                end,
+                symbol,
+                kind,
+            });
+        }
+    }
+
+    /// Writes a displacement to `label` that ends its instruction.
+    fn rel32(&mut self, label: &'a str, kind: RelocKind) {
+        let offset = self.offset();
+        self.here().extend_from_slice(&[0; 4]);
+        self.fixups.push(Fixup {
+            section: self.section,
+            offset,
+            end: offset + 4,
+            symbol: label,
+            kind,
+        });
+    }
+
+    /// Resolves the fixups, and makes the symbols of the object.
+    fn finish(mut self) -> Object {
+        let mut symbols = HashMap::new();
+        for &label in &self.defined {
+            let (section, offset) = self.labels[label];
+            let kind = if self.funcs.contains(label) {
+                SymbolKind::Func
+            } else if section == Section::Text {
+                SymbolKind::NoType
+            } else {
+                SymbolKind::Object
+            };
+            symbols.insert(label, self.object.symbols.len());
+            self.object.symbols.push(Symbol {
+                name: label.to_string(),
+                def: Some((section, offset)),
+                global: self.globals.contains(label),
+                kind,
+            });
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            // The address is relative to the end of the instruction, which
+            // the linker knows as relative to where the displacement is.
+            let addend = -((fixup.end - fixup.offset) as i64);
+            let target = match (fixup.kind, self.labels.get(fixup.symbol)) {
+                (RelocKind::GotPcRel, _) | (_, None) => {
+                    let index =
+                        *symbols.entry(fixup.symbol).or_insert_with(|| {
+                            self.object.symbols.push(Symbol {
+                                name: fixup.symbol.to_string(),
+                                def: None,
+                                global: true,
+                                kind: SymbolKind::NoType,
+                            });
+                            self.object.symbols.len() - 1
+                        });
+                    Some((Target::Symbol(index), 0))
+                }
+                (_, Some(&(section, offset))) if section == fixup.section => {
+                    let disp = offset as i64 - fixup.end as i64;
+                    let at = fixup.offset as usize..fixup.offset as usize + 4;
+                    self.object.sections.get_mut(&section).unwrap().0[at]
+                        .copy_from_slice(&(disp as i32).to_le_bytes());
+                    None
+                }
+                (_, Some(&(section, offset))) => {
+                    Some((Target::Section(section), offset as i64))
+                }
+            };
+            if let Some((target, offset)) = target {
+                self.object.relocations.push(Relocation {
+                    section: fixup.section,
+                    offset: fixup.offset,
+                    target,
+                    kind: fixup.kind,
+                    addend: offset + addend,
+                });
+            }
+        }
+        if let Some(file) = &self.file {
+            let file = String::from_utf8_lossy(file);
+            add_debug_info(&mut self.object, &file, &self.lines);
+        }
+        self.object
+    }
+}
+
+/// The bytes of the contents of an `.asciz` directive, unless it's not
+/// escaped as `x86_64` and `runtime` escape it.
+fn unescape(string: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut chars = string.bytes().peekable();
+    while let Some(byte) = chars.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match chars.next()? {
+            b'n' => bytes.push(b'\n'),
+            b't' => bytes.push(b'\t'),
+            escaped @ (b'"' | b'\\') => bytes.push(escaped),
+            digit @ b'0'..=b'7' => {
+                let mut value = u32::from(digit - b'0');
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(&digit @ b'0'..=b'7') => {
+                            value = value * 8 + u32::from(digit - b'0');
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            _ => return None,
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use crate::{
+        elf::{write_object, RelocKind, Relocation, Section, Target},
+        regalloc::RegAlloc,
+        test_support::{accept_corpus, check_runs_as_interpreted},
+        x86_64::emit_asm,
+    };
+
+    /// The bytes `line` is assembled into, in hex.
+    fn encode(line: &str) -> String {
+        let object = assemble(&format!("\t.text\n\t{}\n", line));
+        let bytes: Vec<String> = object
+            .bytes(Section::Text)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        bytes.join(" ")
+    }
+
+    #[test]
+    fn instructions_are_encoded_as_gnu_as_encodes_them() {
+        let lines = [
+            ("movl -4(%rbp), %eax", "8b 45 fc"),
+            ("movq %rax, -200(%rbp)", "48 89 85 38 ff ff ff"),
+            ("movl $7, (%r12)", "41 c7 04 24 07 00 00 00"),
+            ("movb %sil, 3(%rbx)", "40 88 73 03"),
+            ("movb $-1, (%rsp)", "c6 04 24 ff"),
+            ("movslq %r13d, %rcx", "49 63 cd"),
+            ("movzbl %al, %r15d", "44 0f b6 f8"),
+            ("movabsq $4294967296, %rdx", "48 ba 00 00 00 00 01 00 00 00"),
+            ("leaq 16(%r13), %rdi", "49 8d 7d 10"),
+            ("addl $1, %ebx", "83 c3 01"),
+            ("subq $1000, %rsp", "48 81 ec e8 03 00 00"),
+            ("cmpl -8(%rbp), %r14d", "44 3b 75 f8"),
+            ("xorl %eax, %eax", "31 c0"),
+            ("testq %rcx, %rcx", "48 85 c9"),
+            ("imull $10, %eax", "6b c0 0a"),
+            ("imull -12(%rbp), %ecx", "0f af 4d f4"),
+            ("idivl %ecx", "f7 f9"),
+            ("negl %r12d", "41 f7 dc"),
+            ("setge %al", "0f 9d c0"),
+            ("cltd", "99"),
+            ("pushq %r15", "41 57"),
+            ("leave", "c9"),
+            ("ret", "c3"),
+            ("rep movsb", "f3 a4"),
+        ];
+
+        for (line, bytes) in lines {
+            assert_eq!(encode(line), bytes, "{}", line);
+        }
+    }
+
+    #[test]
+    fn references_outside_the_text_are_left_to_the_linker() {
+        let object = assemble(
+            "\t.section .rodata\n\
+             .Lstr0:\n\
+             \t.asciz \"a\\\"\\\\\\n\\001\"\n\
+             \t.text\n\
+             \t.globl main\n\
+             \t.type main, @function\n\
+             main:\n\
+             \tleaq .Lstr0(%rip), %rdi\n\
+             \tmovl $0, .Lstr0(%rip)\n\
+             \tcall f\n\
+             \tmovq stderr@GOTPCREL(%rip), %rax\n\
+             \tcall printf@PLT\n\
+             \tjmp main\n\
+             \t.type f, @function\n\
+             f:\n\
+             \tret\n",
+        );
+
+        assert_eq!(object.bytes(Section::Rodata), b"a\"\\\n\x01\0");
+        let text = object.bytes(Section::Text);
+        // `call f`, from 22 to 39, and `jmp main`, from 39 to 0.
+        assert_eq!(text[18..22], 17i32.to_le_bytes());
+        assert_eq!(text[35..39], (-39i32).to_le_bytes());
+        let names: Vec<_> = object
+            .symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.def, symbol.global))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("main", Some((Section::Text, 0)), true),
+                ("f", Some((Section::Text, 39)), false),
+                ("stderr", None, true),
+                ("printf", None, true),
+            ]
+        );
+        assert_eq!(
+            object.relocations,
+            [
+                Relocation {
+                    section: Section::Text,
+                    offset: 3,
+                    target: Target::Section(Section::Rodata),
+                    kind: RelocKind::Pc32,
+                    addend: -4,
+                },
+                // The immediate is after the displacement.
+                Relocation {
+                    section: Section::Text,
+                    offset: 9,
+                    target: Target::Section(Section::Rodata),
+                    kind: RelocKind::Pc32,
+                    addend: -8,
+                },
+                Relocation {
+                    section: Section::Text,
+                    offset: 25,
+                    target: Target::Symbol(2),
+                    kind: RelocKind::GotPcRel,
+                    addend: -4,
+                },
+                Relocation {
+                    section: Section::Text,
+                    offset: 30,
+                    target: Target::Symbol(3),
+                    kind: RelocKind::Plt32,
+                    addend: -4,
+                },
+            ]
+        );
+    }
+
+    /// Links the object of `path` with `cc`, allocating registers as
+    /// `regalloc` says, runs it with `input` and checks it writes what the
+    /// interpreter does. Skipped where there's no `cc`.
+    #[test]
+    fn loc_directives_are_made_a_line_table() {
+        let object = assemble(
+            "\t.file 1 \"main.cm\"\n\
+             \t.text\n\
+             f:\n\
+             \t.loc 1 1\n\
+             \tpushq %rbp\n\
+             \t.loc 1 2\n\
+             \tleave\n\
+             \tret\n",
+        );
+
+        assert!(!object.bytes(Section::DebugLine).is_empty());
+        assert!(object
+            .bytes(Section::DebugInfo)
+            .windows(8)
+            .any(|name| name == b"main.cm\0"));
+        // The address the line program starts at, and the code the unit
+        // is of, are the start of `.text`.
+        let text = object
+            .relocations
+            .iter()
+            .filter(|relocation| {
+                relocation.target == Target::Section(Section::Text)
+                    && relocation.kind == RelocKind::Abs64
+            })
+            .count();
+        assert_eq!(text, 2);
+
+        let object = assemble("\t.text\nf:\n\tret\n");
+        assert!(object.bytes(Section::DebugLine).is_empty());
+    }
+
+    #[test]
+    fn corpus_objects_link_and_run_as_interpreted() {
+        for path in accept_corpus() {
+            for regalloc in [RegAlloc::Linear, RegAlloc::Graph] {
+                check_runs_as_interpreted(
+                    &path,
+                    "5 3 9 1 7 2 8 6 4 10 -1\n",
+                    "prog.o",
+                    |program| {
+                        write_object(&assemble(&emit_asm(program, regalloc)))
+                    },
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,543 @@
+//! The abstract syntax tree of a C- program, as produced by the parser.
+//!
+//! Every node keeps the span of the source code it was parsed from, so that
+//! later phases can point their diagnostics at it.
+//!
+//! Statements, blocks and expressions are stored in arenas, the `Nodes` of
+//! the program, and refer to each other by `NodeId`s. Later phases can keep
+//! what they find out about nodes in tables indexed by their ids, instead of
+//! in the tree.
+
+use crate::source_map::Span;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::{Index, IndexMut},
+};
+
+/// A whole program: the declarations of a source file, in order.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Program {
+    pub(crate) decls: Vec<Decl>,
+    pub(crate) nodes: Nodes,
+}
+
+/// A handle to a node of type `T` in an `Arena`. Serialized as its index.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent, bound = ""))]
+pub(crate) struct NodeId<T> {
+    index: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    node: PhantomData<fn() -> T>,
+}
+
+pub(crate) type ExprId = NodeId<Expr>;
+pub(crate) type StmtId = NodeId<Stmt>;
+pub(crate) type BlockId = NodeId<Block>;
+
+impl<T> NodeId<T> {
+    /// Where the node is in its arena, counting from 0 in the order the nodes
+    /// were allocated. Fit for indexing a side table of the nodes.
+    pub(crate) fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+// Implemented by hand, as deriving them would require `T` to implement them
+// too.
+impl<T> Clone for NodeId<T> {
+    fn clone(&self) -> NodeId<T> {
+        *self
+    }
+}
+
+impl<T> Copy for NodeId<T> {}
+
+impl<T> PartialEq for NodeId<T> {
+    fn eq(&self, other: &NodeId<T>) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for NodeId<T> {}
+
+impl<T> Hash for NodeId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for NodeId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
+
+/// Nodes of type `T`, stored next to each other.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub(crate) struct Arena<T> {
+    nodes: Vec<T>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Arena<T> {
+        Arena { nodes: Vec::new() }
+    }
+}
+
+impl<T> Arena<T> {
+    pub(crate) fn alloc(&mut self, node: T) -> NodeId<T> {
+        let index = u32::try_from(self.nodes.len())
+            .expect("more nodes than fit in a node id");
+        self.nodes.push(node);
+        NodeId {
+            index,
+            node: PhantomData,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The nodes with their ids, in the order they were allocated.
+    #[cfg(test)]
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (NodeId<T>, &T)> {
+        self.nodes.iter().enumerate().map(|(index, node)| {
+            let id = NodeId {
+                index: index as u32,
+                node: PhantomData,
+            };
+            (id, node)
+        })
+    }
+}
+
+/// Panics if `id` is from another arena with fewer nodes.
+impl<T> Index<NodeId<T>> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: NodeId<T>) -> &T {
+        &self.nodes[id.index()]
+    }
+}
+
+impl<T> IndexMut<NodeId<T>> for Arena<T> {
+    fn index_mut(&mut self, id: NodeId<T>) -> &mut T {
+        &mut self.nodes[id.index()]
+    }
+}
+
+/// The arenas the nodes of a program are stored in, indexable by the id of
+/// any of them, e.g. `nodes[expr].span`.
+///
+/// They may hold nodes that aren't part of the tree, e.g. the ones parsed
+/// before a syntax error in the statement they were in.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Nodes {
+    pub(crate) exprs: Arena<Expr>,
+    pub(crate) stmts: Arena<Stmt>,
+    pub(crate) blocks: Arena<Block>,
+}
+
+impl Index<ExprId> for Nodes {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id]
+    }
+}
+
+impl IndexMut<ExprId> for Nodes {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.exprs[id]
+    }
+}
+
+impl Index<StmtId> for Nodes {
+    type Output = Stmt;
+
+    fn index(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id]
+    }
+}
+
+impl IndexMut<StmtId> for Nodes {
+    fn index_mut(&mut self, id: StmtId) -> &mut Stmt {
+        &mut self.stmts[id]
+    }
+}
+
+impl Index<BlockId> for Nodes {
+    type Output = Block;
+
+    fn index(&self, id: BlockId) -> &Block {
+        &self.blocks[id]
+    }
+}
+
+impl IndexMut<BlockId> for Nodes {
+    fn index_mut(&mut self, id: BlockId) -> &mut Block {
+        &mut self.blocks[id]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) enum Decl {
+    Var(VarDecl),
+    Fun(FunDecl),
+    /// From `Extension::Structs`.
+    Struct(StructDecl),
+}
+
+impl Decl {
+    pub(crate) fn span(&self) -> Span {
+        match self {
+            Decl::Var(var_decl) => var_decl.span,
+            Decl::Fun(fun_decl) => fun_decl.span,
+            Decl::Struct(struct_decl) => struct_decl.span,
+        }
+    }
+
+    /// The name the declaration declares.
+    pub(crate) fn name(&self) -> &Ident {
+        match self {
+            Decl::Var(var_decl) => &var_decl.name,
+            Decl::Fun(fun_decl) => &fun_decl.name,
+            Decl::Struct(struct_decl) => &struct_decl.name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) enum TypeSpec {
+    Int,
+    Void,
+    /// From `Extension::Char`.
+    Char,
+    /// `struct name`, from `Extension::Structs`.
+    Struct(Ident),
+}
+
+/// The type specifier as written in the source, e.g. `int` or `struct s`.
+impl fmt::Display for TypeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeSpec::Int => f.write_str("int"),
+            TypeSpec::Void => f.write_str("void"),
+            TypeSpec::Char => f.write_str("char"),
+            TypeSpec::Struct(name) => write!(f, "struct {}", name.name),
+        }
+    }
+}
+
+/// A name as written in the source, e.g. of a variable being declared.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Ident {
+    pub(crate) name: String,
+    pub(crate) span: Span,
+}
+
+/// A number literal, e.g. the size of an array.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Number {
+    /// The value of the literal, saturated at `u64::MAX` for literals too
+    /// big to fit.
+    pub(crate) value: u64,
+    pub(crate) span: Span,
+}
+
+/// `int x;`, `int a[10];` or `int m[3][4];`, at file scope or at the start
+/// of a block, or `int x = 10;` or `const int x = 10;` at file scope.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct VarDecl {
+    /// Whether the type has a `const` before it, from `Extension::Const`.
+    pub(crate) is_const: bool,
+    pub(crate) ty: TypeSpec,
+    /// How many `*`s come before the name, e.g. 2 for `int **p`, from
+    /// `Extension::Pointers`.
+    pub(crate) pointers: u32,
+    pub(crate) name: Ident,
+    /// The number of elements along each dimension of an array, outermost
+    /// first. Empty if it's not an array. Each is a number, or a constant
+    /// expression with `Extension::SizeExpressions`, which sema evaluates.
+    pub(crate) sizes: Vec<ExprId>,
+    /// The initial value, from `Extension::GlobalInit`.
+    pub(crate) init: Option<ExprId>,
+    pub(crate) span: Span,
+}
+
+/// `struct name { ... };`, from `Extension::Structs`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct StructDecl {
+    pub(crate) name: Ident,
+    /// Declared like the local variables of a block, without initializers.
+    pub(crate) fields: Vec<VarDecl>,
+    pub(crate) span: Span,
+}
+
+/// `int f(int a, int b[]) { ... }`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct FunDecl {
+    /// Whether the return type has a `const` before it, as for a variable.
+    pub(crate) return_is_const: bool,
+    pub(crate) return_ty: TypeSpec,
+    /// How many `*`s come before the name, as for a variable.
+    pub(crate) return_pointers: u32,
+    pub(crate) name: Ident,
+    /// The parameters, empty for a `(void)` parameter list.
+    pub(crate) params: Vec<Param>,
+    /// The parameter list, parentheses included.
+    pub(crate) params_span: Span,
+    pub(crate) body: BlockId,
+    pub(crate) span: Span,
+}
+
+/// `int a`, `int a[]`, `int m[][4]`, `int *p` or `const int a` in the
+/// parameter list of a function.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Param {
+    /// Whether the type has a `const` before it, as for a variable.
+    pub(crate) is_const: bool,
+    pub(crate) ty: TypeSpec,
+    /// How many `*`s come before the name, as for a variable.
+    pub(crate) pointers: u32,
+    pub(crate) name: Ident,
+    pub(crate) is_array: bool,
+    /// The number of elements along each dimension of an array but the
+    /// first, which isn't given, e.g. `[4]` for `int m[][4]`, from
+    /// `Extension::MultiDimArrays`. Sizes are as for a variable.
+    pub(crate) sizes: Vec<ExprId>,
+    pub(crate) span: Span,
+}
+
+/// `{ ... }`: local declarations followed by statements.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Block {
+    pub(crate) decls: Vec<VarDecl>,
+    pub(crate) stmts: Vec<StmtId>,
+    pub(crate) span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Stmt {
+    pub(crate) kind: StmtKind,
+    pub(crate) span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) enum StmtKind {
+    /// `expr;`
+    Expr(ExprId),
+    /// `;`
+    Empty,
+    Block(BlockId),
+    If {
+        cond: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    },
+    While {
+        cond: ExprId,
+        body: StmtId,
+    },
+    /// `do body while (cond);`, an extension.
+    DoWhile {
+        body: StmtId,
+        cond: ExprId,
+    },
+    /// `for (init; cond; step) body`, any of the expressions in parentheses
+    /// possibly left out. An extension.
+    For {
+        init: Option<ExprId>,
+        cond: Option<ExprId>,
+        step: Option<ExprId>,
+        body: StmtId,
+    },
+    /// `switch (cond) { case ...: ... }`, an extension.
+    Switch {
+        cond: ExprId,
+        cases: Vec<SwitchCase>,
+    },
+    /// `break;`, an extension.
+    Break,
+    /// `continue;`, an extension.
+    Continue,
+    Return(Option<ExprId>),
+}
+
+/// A `case` or `default` label of a `switch`, and the statements after it up
+/// to the next label.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct SwitchCase {
+    /// The value of a `case`, or `None` for the `default`.
+    pub(crate) value: Option<ExprId>,
+    /// From the `case` or `default` to the `:`.
+    pub(crate) label_span: Span,
+    pub(crate) stmts: Vec<StmtId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Expr {
+    pub(crate) kind: ExprKind,
+    pub(crate) span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) enum ExprKind {
+    Number(u64),
+    /// `'a'`, from `Extension::Char`, holding the ASCII code of the
+    /// character.
+    Char(u8),
+    /// `"abc"`, from `Extension::Char`, holding the text without escapes.
+    Str(String),
+    Var(Ident),
+    /// `array[index]`, where `array` is an `Index` itself for the rows of an
+    /// array of more than one dimension, e.g. `m[i][j]`.
+    Index {
+        array: ExprId,
+        index: ExprId,
+    },
+    /// `base.field`, from `Extension::Structs`.
+    Member {
+        base: ExprId,
+        field: Ident,
+    },
+    /// `callee(args...)`
+    Call {
+        callee: Ident,
+        args: Vec<ExprId>,
+    },
+    /// `target = value`
+    Assign {
+        target: ExprId,
+        value: ExprId,
+    },
+    /// `op operand`, from `Extension::UnaryMinus` or `Extension::Pointers`.
+    Unary {
+        op: UnOp,
+        operand: ExprId,
+    },
+    Binary {
+        op: BinOp,
+        lhs: ExprId,
+        rhs: ExprId,
+    },
+    /// `cond ? then_expr : else_expr`, from `Extension::Ternary`.
+    Conditional {
+        cond: ExprId,
+        then_expr: ExprId,
+        else_expr: ExprId,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) enum UnOp {
+    Neg,
+    /// `*p`, the value `p` points to.
+    Deref,
+    /// `&x`, the address of `x`.
+    AddrOf,
+}
+
+impl UnOp {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            UnOp::Neg => "-",
+            UnOp::Deref => "*",
+            UnOp::AddrOf => "&",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+impl BinOp {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Less => "<",
+            BinOp::LessEqual => "<=",
+            BinOp::Greater => ">",
+            BinOp::GreaterEqual => ">=",
+            BinOp::Equal => "==",
+            BinOp::NotEqual => "!=",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arena, Expr, ExprKind, Nodes};
+    use crate::source_map::Span;
+
+    fn number(value: u64) -> Expr {
+        Expr {
+            kind: ExprKind::Number(value),
+            span: Span::with_usizes(0, 1),
+        }
+    }
+
+    #[test]
+    fn ids_index_their_nodes_in_allocation_order() {
+        let mut nodes = Nodes::default();
+        let first = nodes.exprs.alloc(number(1));
+        let second = nodes.exprs.alloc(number(2));
+
+        assert_eq!((first.index(), second.index()), (0, 1));
+        assert_eq!(nodes[second].kind, ExprKind::Number(2));
+
+        nodes[first].kind = ExprKind::Number(3);
+        let values: Vec<_> = nodes
+            .exprs
+            .iter()
+            .map(|(id, expr)| (id, expr.kind.clone()))
+            .collect();
+        assert_eq!(
+            values,
+            [(first, ExprKind::Number(3)), (second, ExprKind::Number(2))]
+        );
+        assert!(Arena::<Expr>::default().is_empty());
+    }
+}
@@ -0,0 +1,1359 @@
+//! Bytecode: a compact encoding of the three-address code of a program,
+//! and a virtual machine running it. A program built once, with
+//! `csub build -o prog.cbc`, runs anywhere with `csub run prog.cbc`, and
+//! much faster than by walking its HIR.
+//!
+//! Each function is compiled to the code of a stack machine: the operands
+//! of an instruction of the IR are pushed, its operation pops them and
+//! pushes its result, and that is popped into a temporary. So the stack is
+//! empty between the instructions of the IR, and in particular wherever
+//! code jumps to. Temporaries are registers of the frame of the function
+//! being called, and memory is laid out as the interpreter's (see `interp`),
+//! the slots of each call in a frame of its own (see `frame`).
+//!
+//! A file is `CSUBBC` and the version of the format, followed by the
+//! globals, the strings and the functions, with every number in LEB128,
+//! signed for constants. It's checked as it's decoded, so the machine never
+//! runs code reading a temporary, a slot or the stack beyond what there is,
+//! or jumping out of its function.
+
+use crate::{
+    ast::BinOp,
+    errors::Diag,
+    frame::{FrameLayout, VM},
+    interp::{Trap, MAX_CALL_DEPTH, MAX_MEMORY, NULL_GUARD},
+    ir::{self, Callee, Check, Inst, Mem, Operand},
+    runtime::call_native,
+    sema::{Builtin, Layout},
+    types::Type,
+};
+use std::io::{BufRead, Write};
+
+/// What a bytecode file starts with.
+const MAGIC: &[u8] = b"CSUBBC";
+
+/// The version of the format, after `MAGIC`, changed whenever what's
+/// written is.
+const VERSION: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Bytecode {
+    pub(crate) globals: Vec<Global>,
+    /// The string literals of the program, without the `\0` at the end.
+    pub(crate) strings: Vec<String>,
+    pub(crate) funs: Vec<Fun>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Global {
+    pub(crate) layout: Layout,
+    /// Its initial value, 0 if it isn't set.
+    pub(crate) init: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Fun {
+    pub(crate) name: String,
+    /// How many of the first temporaries the arguments are passed in.
+    pub(crate) params: usize,
+    /// How many temporaries it has.
+    pub(crate) temps: usize,
+    /// Whether it returns a value.
+    pub(crate) returns: bool,
+    /// The layout of each of its slots.
+    pub(crate) slots: Vec<Layout>,
+    pub(crate) code: Vec<Op>,
+}
+
+/// An instruction of the machine. Jumps are to the index of an instruction
+/// in the code of the function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Op {
+    /// Pushes a constant.
+    Const(i64),
+    /// Pushes the value of a temporary.
+    Temp(usize),
+    /// Pops a value into a temporary.
+    SetTemp(usize),
+    /// Pushes the address of a slot of the frame.
+    Slot(usize),
+    /// Pushes the address of a global.
+    Global(usize),
+    /// Pushes the address of a string.
+    Str(usize),
+    /// Pops a value, to drop it.
+    Pop,
+    Neg,
+    /// Pops the right operand and then the left one, and pushes the result,
+    /// as `Inst::Binary` says.
+    Binary(BinOp),
+    /// Pops a number of bytes and an address, and pushes the address that
+    /// many bytes after it.
+    Offset,
+    /// Pops two addresses, and pushes how many bytes after the second one
+    /// the first one is.
+    Diff,
+    /// Pops an address, and pushes what's at it.
+    Load(Mem),
+    /// Pops a value and then an address, and writes the value to it.
+    Store(Mem),
+    /// Pops the address to copy from and then the one to copy to.
+    MemCopy(u64),
+    /// Pops the arguments of the function at this index of `Bytecode::funs`,
+    /// the last one first, calls it, and pushes what it returns, if
+    /// anything.
+    Call(usize),
+    CallBuiltin(Builtin),
+    Jump(usize),
+    /// Pops a value, and jumps to `then_addr` if it isn't 0, and to
+    /// `else_addr` if it is.
+    Branch {
+        then_addr: usize,
+        else_addr: usize,
+    },
+    Return,
+    /// Pops a value, and returns it.
+    ReturnValue,
+    /// Pops a value, and stops the program as `check` says if it's 0, at
+    /// `line` of the source.
+    Check {
+        check: Check,
+        line: usize,
+    },
+}
+
+impl Op {
+    /// Whether the instruction after it runs only if it's jumped to.
+    fn is_terminator(self) -> bool {
+        matches!(
+            self,
+            Op::Jump(_) | Op::Branch { .. } | Op::Return | Op::ReturnValue
+        )
+    }
+}
+
+/// Compiles `program`, which is valid.
+pub(crate) fn compile(program: &ir::Program) -> Bytecode {
+    let globals = program
+        .globals
+        .iter()
+        .map(|global| Global {
+            layout: global.layout,
+            init: global.init.unwrap_or(0),
+        })
+        .collect();
+    Bytecode {
+        globals,
+        strings: program.strings.clone(),
+        funs: program
+            .funs
+            .iter()
+            .map(|fun| compile_fun(program, fun))
+            .collect(),
+    }
+}
+
+fn compile_fun(program: &ir::Program, fun: &ir::Fun) -> Fun {
+    // Jumps are to labels until every label is placed, and then to where.
+    let mut code = Vec::new();
+    let mut addrs = vec![0; fun.labels];
+    for inst in &fun.code {
+        for operand in inst.operands() {
+            code.push(push(*operand));
+        }
+        let set = |dst: ir::Temp| Op::SetTemp(dst.0);
+        match *inst {
+            Inst::Copy { dst, .. } => code.push(set(dst)),
+            Inst::Neg { dst, .. } => code.extend([Op::Neg, set(dst)]),
+            Inst::Binary { dst, op, .. } => {
+                code.extend([Op::Binary(op), set(dst)])
+            }
+            Inst::Offset { dst, .. } => code.extend([Op::Offset, set(dst)]),
+            Inst::Diff { dst, .. } => code.extend([Op::Diff, set(dst)]),
+            Inst::Load { dst, mem, .. } => {
+                code.extend([Op::Load(mem), set(dst)])
+            }
+            Inst::Store { mem, .. } => code.push(Op::Store(mem)),
+            Inst::MemCopy { size, .. } => code.push(Op::MemCopy(size)),
+            Inst::Call { dst, callee, .. } => {
+                let returns = match callee {
+                    Callee::Fun(index) => {
+                        code.push(Op::Call(index));
+                        program.funs[index].ret.is_some()
+                    }
+                    Callee::Builtin(builtin) => {
+                        code.push(Op::CallBuiltin(builtin));
+                        builtin_returns(builtin)
+                    }
+                };
+                match (returns, dst) {
+                    (true, Some(dst)) => code.push(set(dst)),
+                    (true, None) => code.push(Op::Pop),
+                    (false, _) => {}
+                }
+            }
+            Inst::Label(label) => addrs[label.0] = code.len(),
+            Inst::Jump(label) => code.push(Op::Jump(label.0)),
+            Inst::Branch {
+                then_label,
+                else_label,
+                ..
+            } => code.push(Op::Branch {
+                then_addr: then_label.0,
+                else_addr: else_label.0,
+            }),
+            Inst::Return(None) => code.push(Op::Return),
+            Inst::Return(Some(_)) => code.push(Op::ReturnValue),
+            Inst::Check { check, line, .. } => {
+                code.push(Op::Check { check, line })
+            }
+            Inst::Line(_) => {}
+        }
+    }
+    for op in &mut code {
+        match op {
+            Op::Jump(addr) => *addr = addrs[*addr],
+            Op::Branch {
+                then_addr,
+                else_addr,
+            } => {
+                *then_addr = addrs[*then_addr];
+                *else_addr = addrs[*else_addr];
+            }
+            _ => {}
+        }
+    }
+
+    Fun {
+        name: fun.name.clone(),
+        params: fun.params,
+        temps: fun.temps.len(),
+        returns: fun.ret.is_some(),
+        slots: fun.slots.iter().map(|slot| slot.layout).collect(),
+        code,
+    }
+}
+
+/// The instruction pushing `operand`.
+fn push(operand: Operand) -> Op {
+    match operand {
+        Operand::Temp(temp) => Op::Temp(temp.0),
+        Operand::Const(value) => Op::Const(value),
+        Operand::Slot(index) => Op::Slot(index),
+        Operand::Global(index) => Op::Global(index),
+        Operand::Str(index) => Op::Str(index),
+    }
+}
+
+fn builtin_returns(builtin: Builtin) -> bool {
+    builtin.return_type() != Type::Void
+}
+
+// The opcodes of the instructions, each written as a byte followed by its
+// operands.
+const CONST: u8 = 0;
+const TEMP: u8 = 1;
+const SET_TEMP: u8 = 2;
+const SLOT: u8 = 3;
+const GLOBAL: u8 = 4;
+const STR: u8 = 5;
+const POP: u8 = 6;
+const NEG: u8 = 7;
+const BINARY: u8 = 8;
+const OFFSET: u8 = 9;
+const DIFF: u8 = 10;
+const LOAD: u8 = 11;
+const STORE: u8 = 12;
+const MEM_COPY: u8 = 13;
+const CALL: u8 = 14;
+const CALL_BUILTIN: u8 = 15;
+const JUMP: u8 = 16;
+const BRANCH: u8 = 17;
+const RETURN: u8 = 18;
+const RETURN_VALUE: u8 = 19;
+const CHECK: u8 = 20;
+
+/// The operators of `Op::Binary`, by the byte each is written as.
+const BIN_OPS: [BinOp; 10] = [
+    BinOp::Add,
+    BinOp::Sub,
+    BinOp::Mul,
+    BinOp::Div,
+    BinOp::Less,
+    BinOp::LessEqual,
+    BinOp::Greater,
+    BinOp::GreaterEqual,
+    BinOp::Equal,
+    BinOp::NotEqual,
+];
+
+/// The sizes of `Op::Load` and `Op::Store`, by the byte each is written as.
+const MEMS: [Mem; 3] = [Mem::I8, Mem::I32, Mem::Ptr];
+
+/// The checks of `Op::Check`, by the byte each is written as.
+const CHECKS: [Check; 2] = Check::ALL;
+
+/// The index of `item` in `items`, as a byte.
+fn byte_of<T: PartialEq>(items: &[T], item: T) -> u8 {
+    items.iter().position(|other| *other == item).unwrap() as u8
+}
+
+/// Encodes `bytecode` as the contents of a file.
+pub(crate) fn encode(bytecode: &Bytecode) -> Vec<u8> {
+    let mut out = Writer(MAGIC.to_vec());
+    out.0.push(VERSION);
+    out.uint(bytecode.globals.len() as u64);
+    for global in &bytecode.globals {
+        out.layout(global.layout);
+        out.int(global.init);
+    }
+    out.uint(bytecode.strings.len() as u64);
+    for string in &bytecode.strings {
+        out.string(string);
+    }
+    out.uint(bytecode.funs.len() as u64);
+    for fun in &bytecode.funs {
+        out.string(&fun.name);
+        out.uint(fun.params as u64);
+        out.uint(fun.temps as u64);
+        out.0.push(u8::from(fun.returns));
+        out.uint(fun.slots.len() as u64);
+        for &slot in &fun.slots {
+            out.layout(slot);
+        }
+        out.uint(fun.code.len() as u64);
+        for &op in &fun.code {
+            out.op(op);
+        }
+    }
+    out.0
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn uint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.0.push(byte);
+                return;
+            }
+            self.0.push(byte | 0x80);
+        }
+    }
+
+    fn int(&mut self, mut value: i64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            let sign_bit = byte & 0x40 != 0;
+            if (value == 0 && !sign_bit) || (value == -1 && sign_bit) {
+                self.0.push(byte);
+                return;
+            }
+            self.0.push(byte | 0x80);
+        }
+    }
+
+    fn string(&mut self, string: &str) {
+        self.uint(string.len() as u64);
+        self.0.extend_from_slice(string.as_bytes());
+    }
+
+    fn layout(&mut self, layout: Layout) {
+        self.uint(layout.size);
+        self.uint(layout.align);
+    }
+
+    fn op(&mut self, op: Op) {
+        match op {
+            Op::Const(value) => {
+                self.0.push(CONST);
+                self.int(value);
+            }
+            Op::Temp(index) => self.op_with(TEMP, index as u64),
+            Op::SetTemp(index) => self.op_with(SET_TEMP, index as u64),
+            Op::Slot(index) => self.op_with(SLOT, index as u64),
+            Op::Global(index) => self.op_with(GLOBAL, index as u64),
+            Op::Str(index) => self.op_with(STR, index as u64),
+            Op::Pop => self.0.push(POP),
+            Op::Neg => self.0.push(NEG),
+            Op::Binary(op) => self.0.extend([BINARY, byte_of(&BIN_OPS, op)]),
+            Op::Offset => self.0.push(OFFSET),
+            Op::Diff => self.0.push(DIFF),
+            Op::Load(mem) => self.0.extend([LOAD, byte_of(&MEMS, mem)]),
+            Op::Store(mem) => self.0.extend([STORE, byte_of(&MEMS, mem)]),
+            Op::MemCopy(size) => self.op_with(MEM_COPY, size),
+            Op::Call(index) => self.op_with(CALL, index as u64),
+            Op::CallBuiltin(builtin) => self
+                .0
+                .extend([CALL_BUILTIN, byte_of(&Builtin::ALL, builtin)]),
+            Op::Jump(addr) => self.op_with(JUMP, addr as u64),
+            Op::Branch {
+                then_addr,
+                else_addr,
+            } => {
+                self.op_with(BRANCH, then_addr as u64);
+                self.uint(else_addr as u64);
+            }
+            Op::Return => self.0.push(RETURN),
+            Op::ReturnValue => self.0.push(RETURN_VALUE),
+            Op::Check { check, line } => {
+                self.0.extend([CHECK, byte_of(&CHECKS, check)]);
+                self.uint(line as u64);
+            }
+        }
+    }
+
+    fn op_with(&mut self, opcode: u8, operand: u64) {
+        self.0.push(opcode);
+        self.uint(operand);
+    }
+}
+
+/// Decodes the contents of a file, checking the code can be run, or says
+/// why it can't be.
+pub(crate) fn decode(bytes: &[u8]) -> Result<Bytecode, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if !bytes.starts_with(MAGIC) {
+        return Err("it doesn't start with `CSUBBC`".into());
+    }
+    reader.pos = MAGIC.len();
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(format!(
+            "it's of version {} of the format, not {}",
+            version, VERSION
+        ));
+    }
+
+    let mut globals = Vec::new();
+    for _ in 0..reader.uint()? {
+        let layout = reader.layout()?;
+        let init = reader.int()?;
+        globals.push(Global { layout, init });
+    }
+    let mut strings = Vec::new();
+    for _ in 0..reader.uint()? {
+        strings.push(reader.string()?);
+    }
+    let mut funs = Vec::new();
+    for _ in 0..reader.uint()? {
+        let name = reader.string()?;
+        let params = reader.index()?;
+        let temps = reader.index()?;
+        let returns = match reader.byte()? {
+            0 => false,
+            1 => true,
+            byte => return Err(format!("invalid flag {}", byte)),
+        };
+        let mut slots = Vec::new();
+        for _ in 0..reader.uint()? {
+            slots.push(reader.layout()?);
+        }
+        let mut code = Vec::new();
+        for _ in 0..reader.uint()? {
+            code.push(reader.op()?);
+        }
+        funs.push(Fun {
+            name,
+            params,
+            temps,
+            returns,
+            slots,
+            code,
+        });
+    }
+    if reader.pos != bytes.len() {
+        return Err("there's more to it after the last function".into());
+    }
+
+    let bytecode = Bytecode {
+        globals,
+        strings,
+        funs,
+    };
+    for fun in &bytecode.funs {
+        check_fun(&bytecode, fun)
+            .map_err(|reason| format!("in `{}`, {}", fun.name, reason))?;
+    }
+    match bytecode.funs.iter().find(|fun| fun.name == "main") {
+        Some(main) if main.params == 0 => Ok(bytecode),
+        Some(_) => Err("`main` takes arguments".into()),
+        None => Err("there's no `main` function".into()),
+    }
+}
+
+/// Checks that `fun` reads and writes only what there is, and that the
+/// stack is empty wherever its code jumps to.
+fn check_fun(bytecode: &Bytecode, fun: &Fun) -> Result<(), String> {
+    let in_range = |what: &str, index: usize, len: usize| {
+        if index < len {
+            Ok(())
+        } else {
+            Err(format!("{} {} isn't there", what, index))
+        }
+    };
+    if fun.params > fun.temps {
+        return Err("there are fewer temporaries than arguments".into());
+    }
+    if fun.code.last().is_none_or(|op| !op.is_terminator()) {
+        return Err("the code doesn't end with a jump or a return".into());
+    }
+
+    // How many values are on the stack before each instruction. Code only
+    // gets after a jump or a return when it's jumped to.
+    let mut heights = Vec::with_capacity(fun.code.len());
+    let mut height = 0usize;
+    for (addr, &op) in fun.code.iter().enumerate() {
+        heights.push(height);
+        let (pops, pushes) = match op {
+            Op::Const(_) => (0, 1),
+            Op::Temp(index) => {
+                in_range("temporary", index, fun.temps)?;
+                (0, 1)
+            }
+            Op::SetTemp(index) => {
+                in_range("temporary", index, fun.temps)?;
+                (1, 0)
+            }
+            Op::Slot(index) => {
+                in_range("slot", index, fun.slots.len())?;
+                (0, 1)
+            }
+            Op::Global(index) => {
+                in_range("global", index, bytecode.globals.len())?;
+                (0, 1)
+            }
+            Op::Str(index) => {
+                in_range("string", index, bytecode.strings.len())?;
+                (0, 1)
+            }
+            Op::Pop | Op::Check { .. } => (1, 0),
+            Op::Store(_) | Op::MemCopy(_) => (2, 0),
+            Op::Neg | Op::Load(_) => (1, 1),
+            Op::Binary(_) | Op::Offset | Op::Diff => (2, 1),
+            Op::Call(index) => {
+                in_range("function", index, bytecode.funs.len())?;
+                let callee = &bytecode.funs[index];
+                (callee.params, usize::from(callee.returns))
+            }
+            Op::CallBuiltin(builtin) => (
+                builtin.param_types().len(),
+                usize::from(builtin_returns(builtin)),
+            ),
+            Op::Jump(target) => {
+                in_range("instruction", target, fun.code.len())?;
+                (0, 0)
+            }
+            Op::Branch {
+                then_addr,
+                else_addr,
+            } => {
+                in_range("instruction", then_addr, fun.code.len())?;
+                in_range("instruction", else_addr, fun.code.len())?;
+                (1, 0)
+            }
+            Op::Return if !fun.returns => (0, 0),
+            Op::ReturnValue if fun.returns => (1, 0),
+            Op::Return | Op::ReturnValue => {
+                return Err(format!(
+                    "instruction {} returns other than the function does",
+                    addr
+                ));
+            }
+        };
+        height = height.checked_sub(pops).ok_or_else(|| {
+            format!("instruction {} pops more than is on the stack", addr)
+        })? + pushes;
+        if op.is_terminator() {
+            if height != 0 {
+                return Err(format!(
+                    "instruction {} leaves values on the stack",
+                    addr
+                ));
+            }
+            height = 0;
+        }
+    }
+    for op in &fun.code {
+        let targets = match *op {
+            Op::Jump(target) => vec![target],
+            Op::Branch {
+                then_addr,
+                else_addr,
+            } => vec![then_addr, else_addr],
+            _ => continue,
+        };
+        for target in targets {
+            if heights[target] != 0 {
+                return Err(format!(
+                    "instruction {} is jumped to with values on the stack",
+                    target
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or("it ends in the middle of something")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn uint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7f);
+            if bits << shift >> shift != bits {
+                break;
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("a number is too large".into())
+    }
+
+    fn int(&mut self) -> Result<i64, String> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 64 {
+                return Err("a number is too large".into());
+            }
+            value |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    /// A count or an index, which fits in memory.
+    fn index(&mut self) -> Result<usize, String> {
+        usize::try_from(self.uint()?)
+            .map_err(|_| "a number is too large".into())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.index()?;
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or("it ends in the middle of a string")?;
+        let string = std::str::from_utf8(&self.bytes[self.pos..end])
+            .map_err(|_| "a string isn't UTF-8")?;
+        self.pos = end;
+        Ok(string.to_owned())
+    }
+
+    fn layout(&mut self) -> Result<Layout, String> {
+        let size = self.uint()?;
+        let align = self.uint()?;
+        if !align.is_power_of_two() {
+            return Err(format!("invalid alignment {}", align));
+        }
+        Ok(Layout { size, align })
+    }
+
+    /// The item of `items` at the index in the next byte.
+    fn item<T: Copy>(&mut self, items: &[T], what: &str) -> Result<T, String> {
+        let byte = self.byte()?;
+        items
+            .get(usize::from(byte))
+            .copied()
+            .ok_or_else(|| format!("invalid {} {}", what, byte))
+    }
+
+    fn op(&mut self) -> Result<Op, String> {
+        let op = match self.byte()? {
+            CONST => Op::Const(self.int()?),
+            TEMP => Op::Temp(self.index()?),
+            SET_TEMP => Op::SetTemp(self.index()?),
+            SLOT => Op::Slot(self.index()?),
+            GLOBAL => Op::Global(self.index()?),
+            STR => Op::Str(self.index()?),
+            POP => Op::Pop,
+            NEG => Op::Neg,
+            BINARY => Op::Binary(self.item(&BIN_OPS, "operator")?),
+            OFFSET => Op::Offset,
+            DIFF => Op::Diff,
+            LOAD => Op::Load(self.item(&MEMS, "size")?),
+            STORE => Op::Store(self.item(&MEMS, "size")?),
+            MEM_COPY => Op::MemCopy(self.uint()?),
+            CALL => Op::Call(self.index()?),
+            CALL_BUILTIN => {
+                Op::CallBuiltin(self.item(&Builtin::ALL, "builtin")?)
+            }
+            JUMP => Op::Jump(self.index()?),
+            BRANCH => Op::Branch {
+                then_addr: self.index()?,
+                else_addr: self.index()?,
+            },
+            RETURN => Op::Return,
+            RETURN_VALUE => Op::ReturnValue,
+            CHECK => Op::Check {
+                check: self.item(&CHECKS, "check")?,
+                line: self.index()?,
+            },
+            opcode => return Err(format!("invalid opcode {}", opcode)),
+        };
+        Ok(op)
+    }
+}
+
+/// Runs the function `main` of `bytecode`, which was decoded or compiled,
+/// with `input` as its standard input and `output` as its standard output.
+pub(crate) fn run(
+    bytecode: &Bytecode,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Result<(), Diag> {
+    let main = bytecode
+        .funs
+        .iter()
+        .position(|fun| fun.name == "main")
+        .expect("running bytecode without a main function");
+    let mut vm = Vm {
+        bytecode,
+        memory: vec![0; NULL_GUARD],
+        globals: Vec::with_capacity(bytecode.globals.len()),
+        strings: Vec::with_capacity(bytecode.strings.len()),
+        layouts: bytecode
+            .funs
+            .iter()
+            .map(|fun| {
+                FrameLayout::new(
+                    &VM,
+                    fun.params,
+                    fun.slots.iter().copied(),
+                    0,
+                    0,
+                )
+            })
+            .collect(),
+        frames: Vec::new(),
+        slots: Vec::new(),
+        input,
+        output,
+    };
+    let result = vm.start(main);
+    // There's nowhere left to report a failure to write the output.
+    let _ = vm.output.flush();
+    result.map_err(|(trap, fun, line)| Diag::BytecodeRuntimeError {
+        trap,
+        fun: bytecode.funs[fun].name.clone(),
+        line,
+    })
+}
+
+/// Where a function was called from.
+struct Frame {
+    fun: usize,
+    /// The instruction to go on from when it returns.
+    pc: usize,
+    /// Where its temporaries start among those of every function being
+    /// called, its slots in `Vm::slots`, and its memory in `Vm::memory`.
+    temps: usize,
+    slots: usize,
+    memory: usize,
+}
+
+struct Vm<'a> {
+    bytecode: &'a Bytecode,
+    memory: Vec<u8>,
+    /// The address of each global and each string.
+    globals: Vec<usize>,
+    strings: Vec<usize>,
+    /// The layout of the frame of each function.
+    layouts: Vec<FrameLayout>,
+    /// The callers of the function being run.
+    frames: Vec<Frame>,
+    /// The addresses of the slots of the function being run, after those
+    /// of its callers.
+    slots: Vec<usize>,
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+}
+
+impl Vm<'_> {
+    /// Lays out the globals and the strings and runs `main`, returning what
+    /// stopped it, if anything did, in which function, and at which line of
+    /// the source, if it was a check.
+    fn start(
+        &mut self,
+        main: usize,
+    ) -> Result<(), (Trap, usize, Option<usize>)> {
+        let bytecode = self.bytecode;
+        for global in &bytecode.globals {
+            let addr = self.alloc(global.layout).ok_or((
+                Trap::OutOfMemory,
+                main,
+                None,
+            ))?;
+            let size = (global.layout.size as usize).min(8);
+            self.memory[addr..addr + size]
+                .copy_from_slice(&global.init.to_le_bytes()[..size]);
+            self.globals.push(addr);
+        }
+        for string in &bytecode.strings {
+            let layout = Layout {
+                size: string.len() as u64 + 1,
+                align: 1,
+            };
+            let addr =
+                self.alloc(layout).ok_or((Trap::OutOfMemory, main, None))?;
+            self.memory[addr..addr + string.len()]
+                .copy_from_slice(string.as_bytes());
+            self.strings.push(addr);
+        }
+        self.execute(main)
+    }
+
+    /// Takes memory for a value with `layout`, after everything else, or
+    /// `None` if there's no memory left.
+    fn alloc(&mut self, layout: Layout) -> Option<usize> {
+        let addr = self.memory.len().next_multiple_of(layout.align as usize);
+        let end = addr.checked_add(usize::try_from(layout.size).ok()?)?;
+        if end > MAX_MEMORY {
+            return None;
+        }
+        self.memory.resize(end, 0);
+        Some(addr)
+    }
+
+    /// Where the `size` bytes at `addr` start, if they're some variable's.
+    fn bytes(&self, addr: i64, size: usize) -> Option<usize> {
+        let addr = usize::try_from(addr).ok().filter(|&a| a >= NULL_GUARD)?;
+        let end = addr.checked_add(size)?;
+        (end <= self.memory.len()).then_some(addr)
+    }
+
+    /// Sets up the frame of the function at index `fun`, after the ones
+    /// of its callers, taking its arguments off `stack` into `temps`.
+    fn enter(
+        &mut self,
+        fun: usize,
+        stack: &mut Vec<i64>,
+        temps: &mut Vec<i64>,
+    ) -> Result<(), Trap> {
+        let callee = &self.bytecode.funs[fun];
+        if self.frames.len() == MAX_CALL_DEPTH {
+            return Err(Trap::StackOverflow);
+        }
+        let args = stack.len() - callee.params;
+        let base = temps.len();
+        temps.extend(stack.drain(args..));
+        temps.resize(base + callee.temps, 0);
+        let layout = &self.layouts[fun];
+        let frame = Layout {
+            size: layout.size,
+            align: layout.align,
+        };
+        let addr = self.alloc(frame).ok_or(Trap::OutOfMemory)?;
+        let layout = &self.layouts[fun];
+        self.slots
+            .extend(layout.slots.iter().map(|&offset| addr + offset as usize));
+        Ok(())
+    }
+
+    fn execute(
+        &mut self,
+        main: usize,
+    ) -> Result<(), (Trap, usize, Option<usize>)> {
+        let bytecode = self.bytecode;
+        // The stack and the temporaries are kept here rather than in the
+        // machine, so they aren't reached through it on every instruction.
+        let mut stack = Vec::new();
+        let mut temps = Vec::new();
+        let mut fun = main;
+        let mut code = &bytecode.funs[main].code[..];
+        let mut pc = 0;
+        let mut base = 0;
+        let mut slots = 0;
+        self.enter(main, &mut stack, &mut temps)
+            .map_err(|trap| (trap, fun, None))?;
+        loop {
+            let op = code[pc];
+            pc += 1;
+            let trap = match op {
+                Op::Const(value) => {
+                    stack.push(value);
+                    continue;
+                }
+                Op::Temp(index) => {
+                    stack.push(temps[base + index]);
+                    continue;
+                }
+                Op::SetTemp(index) => {
+                    temps[base + index] = pop(&mut stack);
+                    continue;
+                }
+                Op::Slot(index) => {
+                    stack.push(self.slots[slots + index] as i64);
+                    continue;
+                }
+                Op::Global(index) => {
+                    stack.push(self.globals[index] as i64);
+                    continue;
+                }
+                Op::Str(index) => {
+                    stack.push(self.strings[index] as i64);
+                    continue;
+                }
+                Op::Pop => {
+                    pop(&mut stack);
+                    continue;
+                }
+                Op::Neg => {
+                    let value = pop(&mut stack) as i32;
+                    stack.push(i64::from(value.wrapping_neg()));
+                    continue;
+                }
+                Op::Binary(op) => {
+                    let rhs = pop(&mut stack);
+                    let lhs = pop(&mut stack);
+                    match binary(op, lhs, rhs) {
+                        Some(value) => {
+                            stack.push(value);
+                            continue;
+                        }
+                        None => Trap::DivisionByZero,
+                    }
+                }
+                Op::Offset => {
+                    let bytes = pop(&mut stack);
+                    let base = pop(&mut stack);
+                    stack.push(base.wrapping_add(bytes));
+                    continue;
+                }
+                Op::Diff => {
+                    let rhs = pop(&mut stack);
+                    let lhs = pop(&mut stack);
+                    stack.push(lhs.wrapping_sub(rhs));
+                    continue;
+                }
+                Op::Load(mem) => {
+                    let addr = pop(&mut stack);
+                    match self.load(mem, addr) {
+                        Some(value) => {
+                            stack.push(value);
+                            continue;
+                        }
+                        None => Trap::InvalidAddress,
+                    }
+                }
+                Op::Store(mem) => {
+                    let value = pop(&mut stack);
+                    let addr = pop(&mut stack);
+                    match self.store(mem, addr, value) {
+                        Some(()) => continue,
+                        None => Trap::InvalidAddress,
+                    }
+                }
+                Op::MemCopy(size) => {
+                    let src = pop(&mut stack);
+                    let dst = pop(&mut stack);
+                    let size = size as usize;
+                    match (self.bytes(src, size), self.bytes(dst, size)) {
+                        (Some(src), Some(dst)) => {
+                            self.memory.copy_within(src..src + size, dst);
+                            continue;
+                        }
+                        _ => Trap::InvalidAddress,
+                    }
+                }
+                Op::Call(callee) => {
+                    self.frames.push(Frame {
+                        fun,
+                        pc,
+                        temps: base,
+                        slots,
+                        memory: self.memory.len(),
+                    });
+                    base = temps.len();
+                    slots = self.slots.len();
+                    match self.enter(callee, &mut stack, &mut temps) {
+                        Ok(()) => {
+                            fun = callee;
+                            code = &bytecode.funs[callee].code;
+                            pc = 0;
+                            continue;
+                        }
+                        Err(trap) => trap,
+                    }
+                }
+                Op::CallBuiltin(builtin) => {
+                    let args = stack.len() - builtin.param_types().len();
+                    let args: Vec<_> = stack.drain(args..).collect();
+                    match call_native(builtin, &args, self.input, self.output) {
+                        Some(value) => {
+                            if builtin_returns(builtin) {
+                                stack.push(value);
+                            }
+                            continue;
+                        }
+                        None => Trap::InvalidInput,
+                    }
+                }
+                Op::Jump(addr) => {
+                    pc = addr;
+                    continue;
+                }
+                Op::Branch {
+                    then_addr,
+                    else_addr,
+                } => {
+                    pc = if pop(&mut stack) != 0 {
+                        then_addr
+                    } else {
+                        else_addr
+                    };
+                    continue;
+                }
+                Op::Check { check, line } => {
+                    if pop(&mut stack) != 0 {
+                        continue;
+                    }
+                    return Err((check.trap(), fun, Some(line)));
+                }
+                Op::Return | Op::ReturnValue => {
+                    // What's returned, if anything, is left on the stack.
+                    temps.truncate(base);
+                    self.slots.truncate(slots);
+                    let Some(frame) = self.frames.pop() else {
+                        return Ok(());
+                    };
+                    self.memory.truncate(frame.memory);
+                    (fun, pc, base, slots) =
+                        (frame.fun, frame.pc, frame.temps, frame.slots);
+                    code = &bytecode.funs[fun].code;
+                    continue;
+                }
+            };
+            return Err((trap, fun, None));
+        }
+    }
+
+    /// What's at `addr`, if it's some variable's.
+    fn load(&self, mem: Mem, addr: i64) -> Option<i64> {
+        let value = match mem {
+            Mem::I8 => {
+                let addr = self.bytes(addr, 1)?;
+                i64::from(self.memory[addr])
+            }
+            Mem::I32 => {
+                let addr = self.bytes(addr, 4)?;
+                let mut word = [0; 4];
+                word.copy_from_slice(&self.memory[addr..addr + 4]);
+                i64::from(i32::from_le_bytes(word))
+            }
+            Mem::Ptr => {
+                let addr = self.bytes(addr, 8)?;
+                let mut word = [0; 8];
+                word.copy_from_slice(&self.memory[addr..addr + 8]);
+                i64::from_le_bytes(word)
+            }
+        };
+        Some(value)
+    }
+
+    /// Writes `value` to `addr`, if it's some variable's.
+    fn store(&mut self, mem: Mem, addr: i64, value: i64) -> Option<()> {
+        let size = match mem {
+            Mem::I8 => 1,
+            Mem::I32 => 4,
+            Mem::Ptr => 8,
+        };
+        let addr = self.bytes(addr, size)?;
+        self.memory[addr..addr + size]
+            .copy_from_slice(&value.to_le_bytes()[..size]);
+        Some(())
+    }
+}
+
+fn pop(stack: &mut Vec<i64>) -> i64 {
+    stack.pop().expect("popped an empty stack")
+}
+
+/// `lhs op rhs`, as `Inst::Binary` says, or `None` for a division by zero.
+fn binary(op: BinOp, lhs: i64, rhs: i64) -> Option<i64> {
+    let (a, b) = (lhs as i32, rhs as i32);
+    let value = match op {
+        BinOp::Add => a.wrapping_add(b),
+        BinOp::Sub => a.wrapping_sub(b),
+        BinOp::Mul => a.wrapping_mul(b),
+        BinOp::Div if b == 0 => return None,
+        BinOp::Div => a.wrapping_div(b),
+        BinOp::Less => i32::from(lhs < rhs),
+        BinOp::LessEqual => i32::from(lhs <= rhs),
+        BinOp::Greater => i32::from(lhs > rhs),
+        BinOp::GreaterEqual => i32::from(lhs >= rhs),
+        BinOp::Equal => i32::from(lhs == rhs),
+        BinOp::NotEqual => i32::from(lhs != rhs),
+    };
+    Some(i64::from(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, decode, encode, run, Bytecode, Fun, Op};
+    use crate::{
+        errors::Diag,
+        extensions::Extensions,
+        hir, interp,
+        interp::{Trap, MAX_CALL_DEPTH},
+        ir::{lower_program, lower_program_with_checks, Checks},
+        parser::ParseResult,
+        passes::{OptLevel, PassManager},
+        sema::{check_program, Builtin},
+        test_support::{accept_corpus, analyze_source, parse_source},
+    };
+    use std::fs;
+
+    fn hir(source: &str) -> hir::Program {
+        let (_, analysis) = analyze_source(source);
+        analysis.hir
+    }
+
+    /// Compiles `source`, optimized as `level` says.
+    fn bytecode(source: &str, level: OptLevel) -> Bytecode {
+        let mut program = lower_program(&hir(source));
+        PassManager::for_level(level).run(&mut program, &mut String::new());
+        compile(&program)
+    }
+
+    /// Runs `bytecode` with `input`, returning what it wrote, and what
+    /// stopped it, if anything did, and where.
+    fn run_bytecode(
+        bytecode: &Bytecode,
+        input: &str,
+    ) -> (String, Option<(Trap, String)>) {
+        let mut output = Vec::new();
+        let result = run(bytecode, &mut input.as_bytes(), &mut output);
+        let trap = result.err().map(|diag| match diag {
+            Diag::BytecodeRuntimeError { trap, fun, .. } => (trap, fun),
+            diag => panic!("expected a runtime error, found {:?}", diag),
+        });
+        (String::from_utf8(output).unwrap(), trap)
+    }
+
+    #[test]
+    fn corpus_programs_run_as_interpreted() {
+        let input = "5 3 9 1 7 2 8 6 4 10 -1\n";
+        for path in accept_corpus() {
+            let source = fs::read_to_string(&path).unwrap();
+            let mut expected = Vec::new();
+            interp::run(&hir(&source), &mut input.as_bytes(), &mut expected)
+                .unwrap();
+
+            for level in [OptLevel::O0, OptLevel::O2] {
+                let bytecode = bytecode(&source, level);
+                let decoded = decode(&encode(&bytecode));
+                assert_eq!(
+                    decoded.as_ref(),
+                    Ok(&bytecode),
+                    "{}",
+                    path.display()
+                );
+                assert_eq!(
+                    run_bytecode(&bytecode, input),
+                    (String::from_utf8(expected.clone()).unwrap(), None),
+                    "{}",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn undefined_behavior_stops_the_program() {
+        let trap = |source: &str, input: &str| {
+            run_bytecode(&bytecode(source, OptLevel::O0), input).1
+        };
+
+        assert_eq!(
+            trap(
+                "int f(int x) { return 10 / x; }\n\
+                 void main(void) { output(f(input())); }",
+                "0"
+            ),
+            Some((Trap::DivisionByZero, "f".to_string()))
+        );
+        assert_eq!(
+            trap("void main(void) { int *p; p = 0; output(*p); }", ""),
+            Some((Trap::InvalidAddress, "main".to_string()))
+        );
+        assert_eq!(
+            trap("void main(void) { output(input()); }", "x"),
+            Some((Trap::InvalidInput, "main".to_string()))
+        );
+        assert_eq!(
+            trap("int big[30000000];\nvoid main(void) { big[0] = 1; }", ""),
+            Some((Trap::OutOfMemory, "main".to_string()))
+        );
+        assert_eq!(
+            trap(
+                "void f(void) { int a[20000000]; a[0] = 1; }\n\
+                 void main(void) { f(); }",
+                ""
+            ),
+            Some((Trap::OutOfMemory, "main".to_string()))
+        );
+        let (output, trap) = run_bytecode(
+            &bytecode(
+                "int f(int n) { output(n); return f(n + 1); }\n\
+                 void main(void) { f(1); }",
+                OptLevel::O0,
+            ),
+            "",
+        );
+        assert_eq!(trap, Some((Trap::StackOverflow, "f".to_string())));
+        assert_eq!(output.lines().count(), MAX_CALL_DEPTH - 1);
+    }
+
+    /// What running `source`, lowered with `checks` and optimized at -O2,
+    /// results in.
+    fn run_checked(source: &str, checks: Checks) -> Result<(), Diag> {
+        let (source_file, ParseResult { program, .. }) =
+            parse_source(source, Extensions::all());
+        let hir = check_program(&program, &Builtin::ALL).hir;
+        let mut program =
+            lower_program_with_checks(&hir, checks, Some(&source_file));
+        PassManager::for_level(OptLevel::O2)
+            .run(&mut program, &mut String::new());
+        let bytecode = compile(&program);
+        assert_eq!(decode(&encode(&bytecode)).as_ref(), Ok(&bytecode));
+        run(&bytecode, &mut "0\n".as_bytes(), &mut Vec::new())
+    }
+
+    #[test]
+    fn failed_checks_stop_the_program_at_their_line() {
+        let source = "void main(void) {\n\
+                      \x20   int a[4]; int i;\n\
+                      \x20   for (i = 0; i < 5; i = i + 1)\n\
+                      \x20       a[i] = i;\n\
+                      }\n";
+        let checks = Checks {
+            bounds: true,
+            ..Checks::default()
+        };
+
+        assert_eq!(
+            run_checked(source, checks),
+            Err(Diag::BytecodeRuntimeError {
+                trap: Trap::IndexOutOfBounds,
+                fun: "main".into(),
+                line: Some(4),
+            })
+        );
+    }
+
+    #[test]
+    fn divisions_by_zero_are_reported_at_their_line_with_division_checks() {
+        let source = "int f(int n) {\n\
+                      \x20   return 10 / n;\n\
+                      }\n\
+                      void main(void) { output(f(input())); }\n";
+        let checks = Checks {
+            division: true,
+            ..Checks::default()
+        };
+
+        assert_eq!(
+            run_checked(source, checks),
+            Err(Diag::BytecodeRuntimeError {
+                trap: Trap::DivisionByZero,
+                fun: "main".into(),
+                line: Some(2),
+            })
+        );
+        assert_eq!(
+            run_checked(source, Checks::default()),
+            Err(Diag::BytecodeRuntimeError {
+                trap: Trap::DivisionByZero,
+                fun: "main".into(),
+                line: None,
+            })
+        );
+    }
+
+    #[test]
+    fn files_that_cant_be_run_are_rejected() {
+        let main = |code| Bytecode {
+            globals: Vec::new(),
+            strings: Vec::new(),
+            funs: vec![Fun {
+                name: "main".into(),
+                params: 0,
+                temps: 1,
+                returns: false,
+                slots: Vec::new(),
+                code,
+            }],
+        };
+        let file = encode(&main(vec![Op::Return]));
+        assert!(decode(&file).is_ok());
+
+        assert_eq!(
+            decode(b"\x7fELF"),
+            Err("it doesn't start with `CSUBBC`".into())
+        );
+        let mut newer = file.clone();
+        newer[6] = 3;
+        assert_eq!(
+            decode(&newer),
+            Err("it's of version 3 of the format, not 2".into())
+        );
+        assert_eq!(
+            decode(&file[..file.len() - 1]),
+            Err("it ends in the middle of something".into())
+        );
+        let reject = |code| decode(&encode(&main(code))).unwrap_err();
+        assert_eq!(
+            reject(vec![Op::Temp(1), Op::Pop, Op::Return]),
+            "in `main`, temporary 1 isn't there"
+        );
+        assert_eq!(
+            reject(vec![Op::Jump(3), Op::Return]),
+            "in `main`, instruction 3 isn't there"
+        );
+        assert_eq!(
+            reject(vec![Op::Const(1), Op::Neg]),
+            "in `main`, the code doesn't end with a jump or a return"
+        );
+        assert_eq!(
+            reject(vec![Op::Const(1), Op::Offset, Op::Return]),
+            "in `main`, instruction 1 pops more than is on the stack"
+        );
+        assert_eq!(
+            reject(vec![Op::Const(1), Op::Jump(1), Op::Return]),
+            "in `main`, instruction 1 leaves values on the stack"
+        );
+        assert_eq!(
+            reject(vec![Op::Const(0), Op::ReturnValue]),
+            "in `main`, instruction 1 returns other than the function does"
+        );
+    }
+}
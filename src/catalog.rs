@@ -0,0 +1,779 @@
+//! The text of every diagnostic, kept in one place so that the wording can be
+//! reviewed as a whole and translated.
+//!
+//! Messages are looked up by id, which is the code of the diagnostic for its
+//! main message (e.g. `E0001`), the code followed by a name for its labels
+//! (e.g. `E0002.start`), and just a name for everything else. Messages may
+//! have `{name}` placeholders, filled in with the arguments given on lookup.
+//!
+//! The catalog in use is picked with `set_language`, e.g. from `--lang`.
+//! Messages missing from a catalog are taken from the English one.
+
+use std::{cell::Cell, fmt};
+
+/// A language diagnostics can be reported in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Language {
+    English,
+    /// Brazilian Portuguese, for the courses the compiler is used in.
+    Portuguese,
+}
+
+impl Language {
+    pub(crate) fn from_name(name: &str) -> Option<Language> {
+        match name {
+            "en" => Some(Language::English),
+            "pt" => Some(Language::Portuguese),
+            _ => None,
+        }
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Language::English => ENGLISH,
+            Language::Portuguese => PORTUGUESE,
+        }
+    }
+}
+
+thread_local! {
+    static LANGUAGE: Cell<Language> = const { Cell::new(Language::English) };
+}
+
+/// Makes diagnostics on this thread be reported in `language`.
+pub(crate) fn set_language(language: Language) {
+    LANGUAGE.with(|current| current.set(language));
+}
+
+fn lookup(catalog: &[(&str, &'static str)], id: &str) -> Option<&'static str> {
+    catalog
+        .iter()
+        .find(|&&(entry_id, _)| entry_id == id)
+        .map(|&(_, text)| text)
+}
+
+/// The message `id` in the current language, with its placeholders replaced by
+/// `args`.
+pub(crate) fn message(id: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let language = LANGUAGE.with(Cell::get);
+    let template = lookup(language.catalog(), id)
+        .or_else(|| lookup(ENGLISH, id))
+        .unwrap_or_else(|| panic!("no message `{}` in the catalog", id));
+
+    args.iter()
+        .fold(template.to_owned(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), &value.to_string())
+        })
+}
+
+const ENGLISH: &[(&str, &str)] = &[
+    ("E0001", "unknown character {ch}"),
+    ("E0001.run", "{count} unknown characters in a row"),
+    ("E0002", "unterminated block comment"),
+    ("E0002.start", "comment started here"),
+    ("E0002.end", "`*/` expected here"),
+    ("E0002.fix", "close the comment"),
+    ("E0003", "invalid number literal"),
+    ("E0003.suffix", "letters aren't allowed in numbers"),
+    ("E0004", "non-ASCII character {ch}"),
+    ("E0004.run", "{count} non-ASCII characters in a row"),
+    (
+        "E0004.char",
+        "only ASCII characters are allowed outside comments",
+    ),
+    ("E0005", "identifier is too long"),
+    (
+        "E0005.len",
+        "this identifier has {len} characters, the maximum is {max}",
+    ),
+    ("E0006", "unterminated string literal"),
+    ("E0006.start", "string started here"),
+    ("E0006.end", "`\"` expected here"),
+    ("E0007", "couldn't read `{path}`: {reason}"),
+    ("E0008", "couldn't write `{path}`: {reason}"),
+    ("E0009", "expected {expected}, found {found}"),
+    ("E0009.expected", "expected {expected}"),
+    ("E0010", "expected {token}, found {found}"),
+    ("E0010.here", "{token} expected here"),
+    ("E0010.fix", "add the missing {token}"),
+    ("E0011", "program is nested too deeply"),
+    ("E0011.limit", "this is nested more than {max} levels deep"),
+    ("E0012", "comparison operators can't be chained"),
+    ("E0012.first", "this comparison comes first"),
+    (
+        "E0012.second",
+        "this compares the result of the first comparison, 0 or 1",
+    ),
+    ("E0013", "array `{name}` has no size"),
+    (
+        "E0013.size",
+        "only parameters can leave out the number of elements",
+    ),
+    ("E0014", "unexpected {found} in the declaration of `{name}`"),
+    ("E0014.stray", "expected `;` before this"),
+    (
+        "E0014.init",
+        "variables can't be initialized where they're declared",
+    ),
+    ("E0014.fix", "remove everything before the `;`"),
+    ("E0015", "empty parameter list"),
+    (
+        "E0015.void",
+        "functions without parameters take `(void)` in C-",
+    ),
+    ("E0015.fix", "write `void` in the list"),
+    ("E0016", "`void` must be the only parameter"),
+    ("E0016.void", "`void` can't stand for a parameter"),
+    ("E0016.fix", "remove the `void`"),
+    (
+        "E0017",
+        "declarations must come before the statements of a block",
+    ),
+    ("E0017.first", "the first statement is here"),
+    ("E0018", "`else` without an `if`"),
+    (
+        "E0018.else",
+        "the statement before this isn't the only one of an `if`",
+    ),
+    (
+        "E0019",
+        "the condition of `{keyword}` must be in parentheses",
+    ),
+    ("E0019.fix", "wrap the condition in parentheses"),
+    ("E0020", "trailing comma in the arguments of a call"),
+    ("E0020.comma", "no argument follows this comma"),
+    ("E0020.fix", "remove the comma"),
+    ("E0021", "invalid left-hand side of assignment"),
+    (
+        "E0021.target",
+        "only variables and array elements can be assigned to",
+    ),
+    ("E0021.equal", "assigned here"),
+    ("E0022", "{feature} is an extension to C-"),
+    ("E0022.flag", "enable it with `--extension={name}`"),
+    ("E0023", "`{keyword}` outside of a loop"),
+    ("E0023.label", "`{keyword}` can only be used in a loop"),
+    ("E0023.break", "`break` outside of a loop or `switch`"),
+    (
+        "E0023.break-label",
+        "`break` can only be used in a loop or a `switch`",
+    ),
+    ("E0024", "missing `;` after `do`-`while`"),
+    (
+        "E0024.here",
+        "a `do`-`while` ends with a `;`, unlike a `while`",
+    ),
+    ("E0024.do", "the `do`-`while` starts here"),
+    ("E0024.fix", "add the missing `;`"),
+    ("E0025", "duplicate `case {value}` in `switch`"),
+    ("E0025.default", "more than one `default` in `switch`"),
+    ("E0025.first", "first used here"),
+    ("E0026", "`case` value is not a constant"),
+    (
+        "E0026.label",
+        "a `case` value can only be made of numbers and operators",
+    ),
+    ("E0027", "initializer of `{name}` is not a constant"),
+    (
+        "E0027.label",
+        "a global variable can only be initialized with numbers and operators",
+    ),
+    ("E0028", "array `{name}` is too large"),
+    (
+        "E0028.label",
+        "its number of elements doesn't fit in 64 bits",
+    ),
+    ("E0029", "unterminated character literal"),
+    ("E0029.start", "character literal started here"),
+    ("E0029.end", "`'` expected here"),
+    ("E0030", "unknown escape sequence `{escape}`"),
+    (
+        "E0030.label",
+        "the escape sequences are `\\n`, `\\t`, `\\0`, `\\\\`, `\\'` and `\\\"`",
+    ),
+    ("E0031", "character literal with {len} characters"),
+    ("E0031.empty", "empty character literal"),
+    (
+        "E0031.label",
+        "a character literal holds exactly one character",
+    ),
+    ("E0032", "can't take the address of this expression"),
+    (
+        "E0032.label",
+        "only variables, array elements and dereferenced pointers have an \
+         address",
+    ),
+    ("E0033", "unknown struct `{name}`"),
+    ("E0033.label", "no `struct {name}` is defined before this"),
+    ("E0034", "field `{name}` is declared more than once"),
+    ("E0034.first", "first declared here"),
+    ("E0035", "can't assign to `{name}`, which is `const`"),
+    ("E0035.decl", "`{name}` is declared `const` here"),
+    ("E0036", "assignment used as a condition"),
+    ("E0036.equal", "this assigns, it doesn't compare"),
+    ("E0036.compare", "use `==` to compare"),
+    (
+        "E0036.parens",
+        "put the assignment in parentheses of its own if it's meant",
+    ),
+    ("E0037", "`{written}` isn't an operator"),
+    ("E0037.fix", "write `{op}`"),
+    ("E0038", "use of undeclared identifier `{name}`"),
+    ("E0038.label", "not declared in this scope"),
+    (
+        "E0038.keyword",
+        "`{name}` is a keyword with `--extension={extension}`",
+    ),
+    ("E0039", "`{name}` is already declared in this scope"),
+    ("E0039.previous", "previously declared here"),
+    ("E0040", "the program has no `main` function to start from"),
+    ("E0041", "`main` must be declared as `void main(void)`"),
+    ("E0041.label", "declared differently here"),
+    ("E0041.fix", "declare it as `void main(void)`"),
+    ("E0042", "`main` isn't the last declaration"),
+    ("E0042.after", "declared after `main`"),
+    ("E0043.array", "`{callee}` takes an array here, not a single value"),
+    ("E0043.scalar", "`{callee}` takes a single value here, not an array"),
+    ("E0043.param", "parameter declared here"),
+    ("E0044", "`{name}` can't be `void`"),
+    ("E0044.array", "`{name}` can't be an array of `void`"),
+    (
+        "E0044.label",
+        "`void` is only for functions that return nothing and empty \
+         parameter lists",
+    ),
+    ("E0045", "`{callee}` returns nothing, so its value can't be used"),
+    ("E0045.label", "this call has no value"),
+    ("E0046", "unreachable code"),
+    ("E0046.jump", "any code after this never runs"),
+    ("E0046.false", "this condition is always false"),
+    ("E0046.true", "this condition is always true"),
+    ("E0047", "size of array `{name}` is not a constant"),
+    (
+        "E0047.label",
+        "an array size can only be made of numbers and operators",
+    ),
+    ("E0048", "array `{name}` has a size of {value}"),
+    ("E0048.label", "an array must have at least one element"),
+    ("E0049", "a function can't be assigned to"),
+    ("E0049.array", "an array can't be assigned to as a whole"),
+    ("E0049.label", "assign to its elements one at a time"),
+    ("E0049.decl", "`{name}` is declared here"),
+    ("E0050", "parameter `{name}` is declared more than once"),
+    ("E0050.first", "first declared here"),
+    ("E0051", "arithmetic overflow in a constant expression"),
+    ("E0051.label", "the result doesn't fit in an `int`"),
+    ("E0052", "division by zero in a constant expression"),
+    ("E0052.divisor", "this is zero"),
+    ("E0053", "mismatched types: expected `{expected}`, found `{found}`"),
+    (
+        "E0053.const",
+        "`{found}` can't be used as `{expected}`, which would drop its `const`",
+    ),
+    ("E0053.because", "expected because of this"),
+    ("E0054", "value assigned to `{name}` is never read"),
+    ("E0054.label", "`{name}` is assigned again or goes away before this is read"),
+    ("E0055", "division by zero"),
+    ("E0055.address", "access to memory outside of any variable"),
+    ("E0055.stack", "more than {max} calls nested in each other"),
+    ("E0055.memory", "more than {max} MiB of memory for variables"),
+    ("E0055.input", "`input()` read something that isn't an integer"),
+    ("E0055.bounds", "index out of the bounds of its array"),
+    ("E0055.fun", "{error}, in the function `{fun}`"),
+    ("E0055.line", "{error}, at line {line} of the function `{fun}`"),
+    ("E0056", "`{path}` isn't bytecode csub can run: {reason}"),
+    ("E0057", "couldn't link `{path}`: {reason}"),
+    ("E0058", "`{name}` is a function, not a variable"),
+    ("E0058.label", "a function can only be called"),
+    ("E0058.decl", "`{name}` is declared here"),
+    ("E0059", "`{name}` is not a function"),
+    ("E0059.label", "a variable can't be called"),
+    ("E0059.decl", "`{name}` is declared here as a variable"),
+    (
+        "E0060",
+        "wrong number of arguments for `{callee}`: expected {expected}, \
+         found {found}",
+    ),
+    ("E0060.params", "parameters declared here"),
+    ("E0061", "variable `{name}` is never used"),
+    ("E0061.param", "parameter `{name}` is never used"),
+    ("E0061.label", "declared here, and never used after"),
+    ("E0062", "`{name}` shadows a declaration of an enclosing scope"),
+    ("E0062.previous", "the `{name}` it shadows"),
+    ("E0063", "subscripted value is not an array"),
+    ("E0063.label", "this is of type `{ty}`"),
+    ("E0064", "no field named `{field}` in `{ty}`"),
+    ("E0065", "operand of `*` is not a pointer"),
+    ("E0065.label", "this is of type `{ty}`"),
+    ("extension.unary-minus", "unary minus"),
+    ("extension.for", "the `for` loop"),
+    ("extension.do-while", "the `do`-`while` loop"),
+    ("extension.switch", "the `switch` statement"),
+    ("extension.break", "the `break` statement"),
+    ("extension.continue", "the `continue` statement"),
+    ("extension.ternary", "the conditional operator `?:`"),
+    (
+        "extension.global-init",
+        "the initializer of a global variable",
+    ),
+    (
+        "extension.multi-dim-arrays",
+        "an array of more than one dimension",
+    ),
+    ("extension.char", "the `char` type"),
+    ("extension.pointers", "a pointer"),
+    ("extension.structs", "a `struct`"),
+    ("extension.const", "the `const` qualifier"),
+    (
+        "extension.declarator-lists",
+        "declaring more than one variable at once",
+    ),
+    ("extension.size-expressions", "an array size that isn't a number"),
+    ("token.ident", "an identifier"),
+    ("token.number", "a number"),
+    ("token.char", "a character literal"),
+    ("token.string", "a string literal"),
+    ("token.eof", "the end of the file"),
+    ("expected.expression", "an expression"),
+    ("expected.declaration", "a declaration"),
+    ("expected.type", "a type (`int` or `void`)"),
+    ("expected.case", "a `case` or `default` label"),
+    ("fixes-applied", "applied {count} fix to `{path}`"),
+    ("fixes-applied.plural", "applied {count} fixes to `{path}`"),
+    (
+        "fixes-skipped",
+        "{count} fixes were left out, as they kept undoing each other",
+    ),
+    (
+        "too-many-errors",
+        "too many errors emitted, stopping now (the limit is {limit})",
+    ),
+    ("ice", "internal compiler error: {message}"),
+    ("ice.span", "the compiler was working on this"),
+    ("ice.panicked", "the compiler panicked at {location}"),
+    (
+        "ice.panicked-in-phase",
+        "the compiler panicked at {location} in the {phase} phase",
+    ),
+    (
+        "ice.report",
+        "this is a bug in csub, please report it at {url} along with the \
+         program that triggers it",
+    ),
+    ("summary.error", "aborting due to previous error"),
+    ("summary.errors", "aborting due to {count} previous errors"),
+    ("summary.warning", "{count} warning emitted"),
+    ("summary.warnings", "{count} warnings emitted"),
+    ("summary.duplicate", "{count} duplicate diagnostic suppressed"),
+    ("summary.duplicates", "{count} duplicate diagnostics suppressed"),
+];
+
+const PORTUGUESE: &[(&str, &str)] = &[
+    ("E0001", "caractere desconhecido {ch}"),
+    ("E0001.run", "{count} caracteres desconhecidos seguidos"),
+    ("E0002", "comentário de bloco não terminado"),
+    ("E0002.start", "o comentário começa aqui"),
+    ("E0002.end", "`*/` esperado aqui"),
+    ("E0002.fix", "feche o comentário"),
+    ("E0003", "literal numérico inválido"),
+    ("E0003.suffix", "números não podem conter letras"),
+    ("E0004", "caractere não ASCII {ch}"),
+    ("E0004.run", "{count} caracteres não ASCII seguidos"),
+    (
+        "E0004.char",
+        "só caracteres ASCII são permitidos fora de comentários",
+    ),
+    ("E0005", "identificador longo demais"),
+    (
+        "E0005.len",
+        "este identificador tem {len} caracteres, o máximo é {max}",
+    ),
+    ("E0006", "literal de string não terminado"),
+    ("E0006.start", "a string começa aqui"),
+    ("E0006.end", "`\"` esperado aqui"),
+    ("E0007", "não foi possível ler `{path}`: {reason}"),
+    ("E0008", "não foi possível escrever `{path}`: {reason}"),
+    ("E0009", "esperado {expected}, encontrado {found}"),
+    ("E0009.expected", "esperado {expected}"),
+    ("E0010", "esperado {token}, encontrado {found}"),
+    ("E0010.here", "{token} esperado aqui"),
+    ("E0010.fix", "adicione o {token} que falta"),
+    ("E0011", "programa aninhado profundamente demais"),
+    ("E0011.limit", "isto está aninhado em mais de {max} níveis"),
+    ("E0012", "operadores de comparação não podem ser encadeados"),
+    ("E0012.first", "esta comparação vem primeiro"),
+    (
+        "E0012.second",
+        "isto compara o resultado da primeira comparação, 0 ou 1",
+    ),
+    ("E0013", "o array `{name}` não tem tamanho"),
+    (
+        "E0013.size",
+        "só parâmetros podem omitir o número de elementos",
+    ),
+    ("E0014", "{found} inesperado na declaração de `{name}`"),
+    ("E0014.stray", "esperado `;` antes disto"),
+    (
+        "E0014.init",
+        "variáveis não podem ser inicializadas na declaração",
+    ),
+    ("E0014.fix", "remova tudo antes do `;`"),
+    ("E0015", "lista de parâmetros vazia"),
+    (
+        "E0015.void",
+        "funções sem parâmetros recebem `(void)` em C-",
+    ),
+    ("E0015.fix", "escreva `void` na lista"),
+    ("E0016", "`void` precisa ser o único parâmetro"),
+    ("E0016.void", "`void` não pode ser um parâmetro"),
+    ("E0016.fix", "remova o `void`"),
+    (
+        "E0017",
+        "declarações precisam vir antes dos comandos de um bloco",
+    ),
+    ("E0017.first", "o primeiro comando está aqui"),
+    ("E0018", "`else` sem um `if`"),
+    (
+        "E0018.else",
+        "o comando antes disto não é o único de um `if`",
+    ),
+    (
+        "E0019",
+        "a condição do `{keyword}` precisa estar entre parênteses",
+    ),
+    ("E0019.fix", "coloque a condição entre parênteses"),
+    ("E0020", "vírgula sobrando nos argumentos de uma chamada"),
+    ("E0020.comma", "nenhum argumento segue esta vírgula"),
+    ("E0020.fix", "remova a vírgula"),
+    ("E0021", "lado esquerdo de atribuição inválido"),
+    (
+        "E0021.target",
+        "só variáveis e elementos de arrays podem receber atribuições",
+    ),
+    ("E0021.equal", "atribuído aqui"),
+    ("E0022", "{feature} é uma extensão de C-"),
+    ("E0022.flag", "habilite com `--extension={name}`"),
+    ("E0023", "`{keyword}` fora de um laço"),
+    ("E0023.label", "`{keyword}` só pode ser usado em um laço"),
+    ("E0023.break", "`break` fora de um laço ou `switch`"),
+    (
+        "E0023.break-label",
+        "`break` só pode ser usado em um laço ou em um `switch`",
+    ),
+    ("E0024", "falta `;` depois do `do`-`while`"),
+    (
+        "E0024.here",
+        "um `do`-`while` termina com `;`, ao contrário de um `while`",
+    ),
+    ("E0024.do", "o `do`-`while` começa aqui"),
+    ("E0024.fix", "adicione o `;` que falta"),
+    ("E0025", "`case {value}` repetido no `switch`"),
+    ("E0025.default", "mais de um `default` no `switch`"),
+    ("E0025.first", "usado primeiro aqui"),
+    ("E0026", "o valor do `case` não é uma constante"),
+    (
+        "E0026.label",
+        "o valor de um `case` só pode ter números e operadores",
+    ),
+    ("E0027", "o inicializador de `{name}` não é uma constante"),
+    (
+        "E0027.label",
+        "uma variável global só pode ser inicializada com números e operadores",
+    ),
+    ("E0028", "o array `{name}` é grande demais"),
+    (
+        "E0028.label",
+        "o número de elementos dele não cabe em 64 bits",
+    ),
+    ("E0029", "literal de caractere não terminado"),
+    ("E0029.start", "o literal de caractere começa aqui"),
+    ("E0029.end", "`'` esperado aqui"),
+    ("E0030", "sequência de escape `{escape}` desconhecida"),
+    (
+        "E0030.label",
+        "as sequências de escape são `\\n`, `\\t`, `\\0`, `\\\\`, `\\'` e `\\\"`",
+    ),
+    ("E0031", "literal de caractere com {len} caracteres"),
+    ("E0031.empty", "literal de caractere vazio"),
+    (
+        "E0031.label",
+        "um literal de caractere tem exatamente um caractere",
+    ),
+    ("E0032", "não é possível obter o endereço desta expressão"),
+    (
+        "E0032.label",
+        "só variáveis, elementos de arrays e ponteiros derreferenciados têm \
+         endereço",
+    ),
+    ("E0033", "struct `{name}` desconhecida"),
+    ("E0033.label", "nenhuma `struct {name}` é definida antes disto"),
+    ("E0034", "o campo `{name}` é declarado mais de uma vez"),
+    ("E0034.first", "declarado primeiro aqui"),
+    ("E0035", "não é possível atribuir a `{name}`, que é `const`"),
+    ("E0035.decl", "`{name}` é declarado `const` aqui"),
+    ("E0036", "atribuição usada como condição"),
+    ("E0036.equal", "isto atribui, não compara"),
+    ("E0036.compare", "use `==` para comparar"),
+    (
+        "E0036.parens",
+        "coloque a atribuição entre parênteses próprios se ela for intencional",
+    ),
+    ("E0037", "`{written}` não é um operador"),
+    ("E0037.fix", "escreva `{op}`"),
+    ("E0038", "uso de identificador não declarado `{name}`"),
+    ("E0038.label", "não declarado neste escopo"),
+    (
+        "E0038.keyword",
+        "`{name}` é uma palavra-chave com `--extension={extension}`",
+    ),
+    ("E0039", "`{name}` já está declarado neste escopo"),
+    ("E0039.previous", "declarado anteriormente aqui"),
+    ("E0040", "o programa não tem uma função `main` por onde começar"),
+    ("E0041", "`main` deve ser declarada como `void main(void)`"),
+    ("E0041.label", "declarada de outro modo aqui"),
+    ("E0041.fix", "declare-a como `void main(void)`"),
+    ("E0042", "`main` não é a última declaração"),
+    ("E0042.after", "declarado depois de `main`"),
+    ("E0043.array", "`{callee}` recebe um arranjo aqui, não um valor único"),
+    ("E0043.scalar", "`{callee}` recebe um valor único aqui, não um arranjo"),
+    ("E0043.param", "parâmetro declarado aqui"),
+    ("E0044", "`{name}` não pode ser `void`"),
+    ("E0044.array", "`{name}` não pode ser um arranjo de `void`"),
+    (
+        "E0044.label",
+        "`void` é só para funções que não retornam nada e listas de \
+         parâmetros vazias",
+    ),
+    ("E0045", "`{callee}` não retorna nada, então seu valor não pode ser usado"),
+    ("E0045.label", "esta chamada não tem valor"),
+    ("E0046", "código inalcançável"),
+    ("E0046.jump", "nenhum código depois disto é executado"),
+    ("E0046.false", "esta condição é sempre falsa"),
+    ("E0046.true", "esta condição é sempre verdadeira"),
+    ("E0047", "o tamanho do array `{name}` não é uma constante"),
+    (
+        "E0047.label",
+        "o tamanho de um array só pode ter números e operadores",
+    ),
+    ("E0048", "o array `{name}` tem tamanho {value}"),
+    ("E0048.label", "um array precisa ter pelo menos um elemento"),
+    ("E0049", "uma função não pode receber atribuições"),
+    ("E0049.array", "um array não pode receber atribuições como um todo"),
+    ("E0049.label", "atribua a cada um de seus elementos"),
+    ("E0049.decl", "`{name}` é declarado aqui"),
+    ("E0050", "o parâmetro `{name}` é declarado mais de uma vez"),
+    ("E0050.first", "declarado primeiro aqui"),
+    ("E0051", "estouro aritmético em uma expressão constante"),
+    ("E0051.label", "o resultado não cabe em um `int`"),
+    ("E0052", "divisão por zero em uma expressão constante"),
+    ("E0052.divisor", "isto é zero"),
+    (
+        "E0053",
+        "tipos incompatíveis: esperado `{expected}`, encontrado `{found}`",
+    ),
+    (
+        "E0053.const",
+        "`{found}` não pode ser usado como `{expected}`, o que descartaria seu `const`",
+    ),
+    ("E0053.because", "esperado por causa disto"),
+    ("E0054", "o valor atribuído a `{name}` nunca é lido"),
+    (
+        "E0054.label",
+        "`{name}` recebe outro valor ou deixa de existir antes que este seja lido",
+    ),
+    ("E0055", "divisão por zero"),
+    ("E0055.address", "acesso à memória fora de qualquer variável"),
+    ("E0055.stack", "mais de {max} chamadas aninhadas umas nas outras"),
+    ("E0055.memory", "mais de {max} MiB de memória para variáveis"),
+    ("E0055.input", "`input()` leu algo que não é um inteiro"),
+    ("E0055.bounds", "índice fora dos limites do seu vetor"),
+    ("E0055.fun", "{error}, na função `{fun}`"),
+    ("E0055.line", "{error}, na linha {line} da função `{fun}`"),
+    ("E0056", "`{path}` não é bytecode que o csub possa executar: {reason}"),
+    ("E0057", "não foi possível ligar `{path}`: {reason}"),
+    ("E0058", "`{name}` é uma função, não uma variável"),
+    ("E0058.label", "uma função só pode ser chamada"),
+    ("E0058.decl", "`{name}` é declarada aqui"),
+    ("E0059", "`{name}` não é uma função"),
+    ("E0059.label", "uma variável não pode ser chamada"),
+    ("E0059.decl", "`{name}` é declarada aqui como uma variável"),
+    (
+        "E0060",
+        "número errado de argumentos para `{callee}`: esperados {expected}, \
+         encontrados {found}",
+    ),
+    ("E0060.params", "parâmetros declarados aqui"),
+    ("E0061", "a variável `{name}` nunca é usada"),
+    ("E0061.param", "o parâmetro `{name}` nunca é usado"),
+    ("E0061.label", "declarado aqui, e nunca usado depois"),
+    ("E0062", "`{name}` esconde uma declaração de um escopo externo"),
+    ("E0062.previous", "o `{name}` que fica escondido"),
+    ("E0063", "o valor indexado não é um vetor"),
+    ("E0063.label", "isto é do tipo `{ty}`"),
+    ("E0064", "não há campo chamado `{field}` em `{ty}`"),
+    ("E0065", "o operando de `*` não é um ponteiro"),
+    ("E0065.label", "isto é do tipo `{ty}`"),
+    ("extension.unary-minus", "o menos unário"),
+    ("extension.for", "o laço `for`"),
+    ("extension.do-while", "o laço `do`-`while`"),
+    ("extension.switch", "o comando `switch`"),
+    ("extension.break", "o comando `break`"),
+    ("extension.continue", "o comando `continue`"),
+    ("extension.ternary", "o operador condicional `?:`"),
+    (
+        "extension.global-init",
+        "o inicializador de uma variável global",
+    ),
+    (
+        "extension.multi-dim-arrays",
+        "um array de mais de uma dimensão",
+    ),
+    ("extension.char", "o tipo `char`"),
+    ("extension.pointers", "um ponteiro"),
+    ("extension.structs", "uma `struct`"),
+    ("extension.const", "o qualificador `const`"),
+    (
+        "extension.declarator-lists",
+        "declarar mais de uma variável de uma vez",
+    ),
+    (
+        "extension.size-expressions",
+        "um tamanho de array que não é um número",
+    ),
+    ("token.ident", "um identificador"),
+    ("token.number", "um número"),
+    ("token.char", "um literal de caractere"),
+    ("token.string", "um literal de string"),
+    ("token.eof", "o fim do arquivo"),
+    ("expected.expression", "uma expressão"),
+    ("expected.declaration", "uma declaração"),
+    ("expected.type", "um tipo (`int` ou `void`)"),
+    ("expected.case", "um rótulo `case` ou `default`"),
+    ("fixes-applied", "{count} correção aplicada em `{path}`"),
+    (
+        "fixes-applied.plural",
+        "{count} correções aplicadas em `{path}`",
+    ),
+    (
+        "fixes-skipped",
+        "{count} correções ficaram de fora, por desfazerem umas às outras",
+    ),
+    (
+        "too-many-errors",
+        "erros demais, parando agora (o limite é {limit})",
+    ),
+    ("ice", "erro interno do compilador: {message}"),
+    ("ice.span", "o compilador estava processando isto"),
+    (
+        "ice.panicked",
+        "o compilador entrou em pânico em {location}",
+    ),
+    (
+        "ice.panicked-in-phase",
+        "o compilador entrou em pânico em {location} na fase `{phase}`",
+    ),
+    (
+        "ice.report",
+        "isto é um bug no csub, por favor reporte em {url} junto com o \
+         programa que o causa",
+    ),
+    ("summary.error", "abortando devido ao erro anterior"),
+    (
+        "summary.errors",
+        "abortando devido a {count} erros anteriores",
+    ),
+    ("summary.warning", "{count} aviso emitido"),
+    ("summary.warnings", "{count} avisos emitidos"),
+    ("summary.duplicate", "{count} diagnóstico duplicado suprimido"),
+    (
+        "summary.duplicates",
+        "{count} diagnósticos duplicados suprimidos",
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{message, set_language, Language, ENGLISH, PORTUGUESE};
+
+    /// The names of the placeholders in `text`, in order.
+    fn placeholders(text: &str) -> Vec<&str> {
+        text.split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+            .collect()
+    }
+
+    #[test]
+    fn language_from_name() {
+        assert_eq!(Language::from_name("en"), Some(Language::English));
+        assert_eq!(Language::from_name("pt"), Some(Language::Portuguese));
+        assert_eq!(Language::from_name("tlh"), None);
+    }
+
+    #[test]
+    fn message_ids_are_unique() {
+        for catalog in [ENGLISH, PORTUGUESE] {
+            for (i, (id, _)) in catalog.iter().enumerate() {
+                assert!(
+                    catalog[i + 1..].iter().all(|(other, _)| other != id),
+                    "`{}` is repeated",
+                    id
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn translations_have_the_placeholders_of_the_english_message() {
+        for (id, text) in PORTUGUESE {
+            let english = ENGLISH
+                .iter()
+                .find(|(english_id, _)| english_id == id)
+                .unwrap_or_else(|| panic!("`{}` isn't in English", id));
+
+            let mut expected = placeholders(english.1);
+            let mut found = placeholders(text);
+            expected.sort_unstable();
+            found.sort_unstable();
+            assert_eq!(found, expected, "placeholders of `{}`", id);
+        }
+    }
+
+    #[test]
+    fn portuguese_translates_every_message() {
+        for (id, _) in ENGLISH {
+            assert!(
+                PORTUGUESE.iter().any(|(pt_id, _)| pt_id == id),
+                "`{}` isn't translated",
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn message_fills_in_placeholders() {
+        assert_eq!(
+            message("E0005.len", &[("len", &64), ("max", &63)]),
+            "this identifier has 64 characters, the maximum is 63"
+        );
+    }
+
+    #[test]
+    fn message_in_the_current_language() {
+        set_language(Language::Portuguese);
+        let text = message("E0001", &[("ch", &"'@'")]);
+        set_language(Language::English);
+
+        assert_eq!(text, "caractere desconhecido '@'");
+        assert_eq!(
+            message("E0001", &[("ch", &"'@'")]),
+            "unknown character '@'"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no message `E9999` in the catalog")]
+    fn unknown_message_id() {
+        message("E9999", &[]);
+    }
+}
@@ -0,0 +1,375 @@
+//! Conditional constant propagation: working out which temporaries and
+//! slots hold a constant wherever they're read, across blocks, and which
+//! blocks may run at all, at once, as Wegman and Zadeck do in "Constant
+//! Propagation with Conditional Branches", in the dense form of their
+//! analysis rather than the sparse one: the code isn't in SSA form, as a
+//! temporary may be assigned more than once, so what's known is kept for
+//! each block, not each temporary.
+//!
+//! The values are optimistic: a block is gone through only once an edge
+//! into it is, and what it's entered with is what the edges gone through
+//! agree on, so a variable that's the same constant on every path that may
+//! run is known to be, even in a loop. A branch on a constant only goes
+//! through the edge it takes, so the code it'd skip isn't reached, and is
+//! dropped.
+//!
+//! Besides `i32` temporaries, the `int` slots whose address is only loaded
+//! from and stored to (see `licm::escaping_slots`) are followed, as locals
+//! are until they're kept in temporaries. Where they're read, known values
+//! are replaced by constants, and each instruction is then folded as
+//! `fold` does, which this subsumes at `-O2`.
+
+use crate::{
+    cfg::Cfg,
+    fold::fold_inst,
+    ir::{Fun, Inst, Mem, Operand, Program, Ty},
+    licm::escaping_slots,
+};
+use std::collections::{BTreeSet, HashSet};
+
+/// What's known of a temporary or a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    Const(i64),
+    /// It may be any value, or different ones on different paths.
+    Varying,
+}
+
+impl Value {
+    /// What's known of something that's `self` on a path and `other` on
+    /// another.
+    fn meet(self, other: Value) -> Value {
+        if self == other {
+            self
+        } else {
+            Value::Varying
+        }
+    }
+}
+
+/// What's known at a point of a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+    temps: Vec<Value>,
+    slots: Vec<Value>,
+}
+
+impl State {
+    fn meet(&mut self, other: &State) {
+        for (value, other) in self
+            .temps
+            .iter_mut()
+            .chain(&mut self.slots)
+            .zip(other.temps.iter().chain(&other.slots))
+        {
+            *value = value.meet(*other);
+        }
+    }
+}
+
+/// Propagates the constants of the functions of `program`, returning
+/// whether anything changed.
+pub(crate) fn ccp_program(program: &mut Program) -> bool {
+    let mut changed = false;
+    for fun in &mut program.funs {
+        changed |= ccp_fun(fun);
+    }
+    changed
+}
+
+/// Propagates the constants of `fun`, dropping the blocks that never run,
+/// returning whether anything changed.
+pub(crate) fn ccp_fun(fun: &mut Fun) -> bool {
+    if fun.code.is_empty() {
+        return false;
+    }
+    let cfg = Cfg::new(fun);
+    let propagation = Propagation::new(fun, &cfg);
+    let ins = propagation.solve();
+
+    let mut code = Vec::with_capacity(fun.code.len());
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        let Some(mut state) = ins[index].clone() else {
+            continue;
+        };
+        for inst in &fun.code[block.insts.clone()] {
+            let inst = propagation.step(inst, &mut state);
+            let passes = matches!(inst, Inst::Check {
+                cond: Operand::Const(value),
+                ..
+            } if value != 0);
+            if !passes {
+                code.push(inst);
+            }
+        }
+    }
+    let changed = code != fun.code;
+    fun.code = code;
+    changed
+}
+
+/// The propagation of the constants of a function.
+struct Propagation<'a> {
+    fun: &'a Fun,
+    cfg: &'a Cfg,
+    /// Whether each slot is followed, as it doesn't escape.
+    followed: Vec<bool>,
+}
+
+impl<'a> Propagation<'a> {
+    fn new(fun: &'a Fun, cfg: &'a Cfg) -> Propagation<'a> {
+        let followed = escaping_slots(fun)
+            .into_iter()
+            .map(|escapes| !escapes)
+            .collect();
+        Propagation { fun, cfg, followed }
+    }
+
+    /// What's known where each block is entered, or `None` for the blocks
+    /// that never run.
+    fn solve(&self) -> Vec<Option<State>> {
+        let blocks = &self.cfg.blocks;
+        // Nothing is known of the arguments, nor of what's in the slots.
+        let entry = State {
+            temps: vec![Value::Varying; self.fun.temps.len()],
+            slots: vec![Value::Varying; self.fun.slots.len()],
+        };
+        let mut ins: Vec<Option<State>> = vec![None; blocks.len()];
+        let mut outs: Vec<Option<State>> = vec![None; blocks.len()];
+        let mut edges = HashSet::new();
+        let mut worklist = BTreeSet::from([self.cfg.entry()]);
+        while let Some(block) = worklist.pop_first() {
+            let mut state = if block == self.cfg.entry() {
+                entry.clone()
+            } else {
+                let mut state: Option<State> = None;
+                for &pred in &blocks[block.0].preds {
+                    let (true, Some(out)) =
+                        (edges.contains(&(pred, block)), &outs[pred.0])
+                    else {
+                        continue;
+                    };
+                    match &mut state {
+                        Some(state) => state.meet(out),
+                        None => state = Some(out.clone()),
+                    }
+                }
+                state.expect("a block gone through without an edge into it")
+            };
+            ins[block.0] = Some(state.clone());
+
+            let mut taken = Vec::new();
+            for index in blocks[block.0].insts.clone() {
+                let inst = self.step(&self.fun.code[index], &mut state);
+                taken = match inst {
+                    Inst::Jump(label) => vec![label],
+                    Inst::Branch {
+                        then_label,
+                        else_label,
+                        ..
+                    } => vec![then_label, else_label],
+                    _ => continue,
+                };
+            }
+            let changed = outs[block.0].as_ref() != Some(&state);
+            outs[block.0] = Some(state);
+            for &succ in &blocks[block.0].succs {
+                let label = blocks[succ.0].label;
+                if !taken.iter().any(|&taken| Some(taken) == label) {
+                    continue;
+                }
+                if edges.insert((block, succ)) || changed {
+                    worklist.insert(succ);
+                }
+            }
+        }
+        ins
+    }
+
+    /// `inst` with what `state` knows of what it reads in place of it,
+    /// folded, updating `state` with what it assigns.
+    fn step(&self, inst: &Inst, state: &mut State) -> Inst {
+        let mut inst = inst.clone();
+        for operand in inst.operands_mut() {
+            if let Operand::Temp(temp) = *operand {
+                if let Value::Const(value) = state.temps[temp.0] {
+                    *operand = Operand::Const(value);
+                }
+            }
+        }
+        if let Inst::Load {
+            dst,
+            mem: Mem::I32,
+            addr: Operand::Slot(slot),
+        } = inst
+        {
+            if let (true, Value::Const(value)) =
+                (self.followed[slot], state.slots[slot])
+            {
+                inst = Inst::Copy {
+                    dst,
+                    src: Operand::Const(value),
+                };
+            }
+        }
+        if let Some(folded) = fold_inst(&inst) {
+            inst = folded;
+        }
+
+        match inst {
+            Inst::Store {
+                mem,
+                addr: Operand::Slot(slot),
+                value,
+            } if self.followed[slot] => {
+                state.slots[slot] = match (mem, value) {
+                    (Mem::I32, Operand::Const(value)) => Value::Const(value),
+                    _ => Value::Varying,
+                };
+            }
+            _ => {
+                if let Some(dst) = inst.dst() {
+                    state.temps[dst.0] = match inst {
+                        Inst::Copy {
+                            src: Operand::Const(value),
+                            ..
+                        } if self.fun.temps[dst.0] == Ty::I32 => {
+                            Value::Const(value)
+                        }
+                        _ => Value::Varying,
+                    };
+                }
+            }
+        }
+        inst
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{passes::Pass, test_support::ir_after};
+
+    #[test]
+    fn constants_are_propagated_across_blocks_and_loops() {
+        // `x` would only change in the loop if it weren't 3, and `y` is 4
+        // either way.
+        let source = "int f(int n) {\n\
+                      \x20   int x; int y;\n\
+                      \x20   x = 3;\n\
+                      \x20   if (n) y = x + 1; else y = 4;\n\
+                      \x20   while (n > 0) {\n\
+                      \x20       if (x != 3) x = n;\n\
+                      \x20       n = n - 1;\n\
+                      \x20   }\n\
+                      \x20   return x * y;\n\
+                      }\n\
+                      void main(void) { output(f(input())); }\n";
+
+        assert_eq!(
+            ir_after(source, &[Pass::Ccp, Pass::Dce]),
+            "fun @f(%0: i32) -> i32 {\n\
+             \x20 slot $0 n: 4, align 4\n\
+             \x20 slot $1 x: 4, align 4\n\
+             \x20 slot $2 y: 4, align 4\n\
+             \x20 store i32 $0, %0\n\
+             \x20 store i32 $1, 3\n\
+             \x20 %1: i32 = load i32 $0\n\
+             \x20 branch %1, L0, L1\n\
+             L0:\n\
+             \x20 store i32 $2, 4\n\
+             \x20 jump L2\n\
+             L1:\n\
+             \x20 store i32 $2, 4\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 jump L3\n\
+             L3:\n\
+             \x20 %4: i32 = load i32 $0\n\
+             \x20 %5: i32 = gt %4, 0\n\
+             \x20 branch %5, L4, L5\n\
+             L4:\n\
+             \x20 jump L7\n\
+             L7:\n\
+             \x20 %9: i32 = load i32 $0\n\
+             \x20 %10: i32 = sub %9, 1\n\
+             \x20 store i32 $0, %10\n\
+             \x20 jump L3\n\
+             L5:\n\
+             \x20 ret 12\n\
+             }\n\
+             \n\
+             fun @main() {\n\
+             \x20 %0: i32 = call @input()\n\
+             \x20 %1: i32 = call @f(%0)\n\
+             \x20 call @output(%1)\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn branches_on_constants_keep_only_the_blocks_they_go_to() {
+        // `x` is 0 wherever it's read, so neither the `then` of the first
+        // `if` nor the body of the loop may run, and `2 < 1` never holds.
+        let source = "void main(void) {\n\
+                      \x20   int x; int n;\n\
+                      \x20   x = 0;\n\
+                      \x20   n = input();\n\
+                      \x20   if (x) output(1); else output(2);\n\
+                      \x20   while (x > 0) { output(n); x = x - 1; }\n\
+                      \x20   if (2 < 1) n = 1;\n\
+                      \x20   output(n);\n\
+                      }\n";
+
+        assert_eq!(
+            ir_after(source, &[Pass::Ccp, Pass::Dce]),
+            "fun @main() {\n\
+             \x20 slot $0 x: 4, align 4\n\
+             \x20 slot $1 n: 4, align 4\n\
+             \x20 store i32 $0, 0\n\
+             \x20 %0: i32 = call @input()\n\
+             \x20 store i32 $1, %0\n\
+             \x20 jump L1\n\
+             L1:\n\
+             \x20 call @output(2)\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 jump L3\n\
+             L3:\n\
+             \x20 jump L5\n\
+             L5:\n\
+             \x20 jump L7\n\
+             L7:\n\
+             \x20 %8: i32 = load i32 $1\n\
+             \x20 call @output(%8)\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn slots_whose_address_is_taken_are_not_followed() {
+        let source = "void main(void) {\n\
+                      \x20   int x; int *p;\n\
+                      \x20   x = 1;\n\
+                      \x20   p = &x;\n\
+                      \x20   *p = 2;\n\
+                      \x20   output(x);\n\
+                      }\n";
+
+        assert_eq!(
+            ir_after(source, &[Pass::Ccp]),
+            "fun @main() {\n\
+             \x20 slot $0 x: 4, align 4\n\
+             \x20 slot $1 p: 8, align 8\n\
+             \x20 store i32 $0, 1\n\
+             \x20 store ptr $1, $0\n\
+             \x20 %0: ptr = load ptr $1\n\
+             \x20 store i32 %0, 2\n\
+             \x20 %1: i32 = load i32 $0\n\
+             \x20 call @output(%1)\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+}
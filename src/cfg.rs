@@ -0,0 +1,472 @@
+//! The control-flow graph of a function: its code split into basic blocks,
+//! runs of instructions only entered at the first and left at the last, and
+//! the edges between the blocks where control goes from one to the next.
+//!
+//! A block starts at a label, or after a terminator that isn't followed by
+//! one, which is code nothing jumps to. As code never falls through to a
+//! label, every block ends with a terminator, and its successors are the
+//! labels it jumps to. The first block is the entry of the function.
+//!
+//! A block dominates another if every path from the entry to the other goes
+//! through it. A loop is natural if a block of it, its header, dominates
+//! all of it, so it's only entered at the header: its blocks are those from
+//! which an edge back to the header is reached without going through it.
+
+use crate::ir::{Fun, Inst, Label};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    ops::Range,
+};
+
+/// The block at this index of `Cfg::blocks`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct BlockId(pub(crate) usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Block {
+    /// The label it starts with, if any.
+    pub(crate) label: Option<Label>,
+    /// Where its instructions are in the code of the function, its label
+    /// included.
+    pub(crate) insts: Range<usize>,
+    /// The blocks control may go to from it, in the order its terminator
+    /// names them.
+    pub(crate) succs: Vec<BlockId>,
+    /// The blocks control may come to it from, in order.
+    pub(crate) preds: Vec<BlockId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Cfg {
+    /// The blocks, in the order their code is in, the entry first.
+    pub(crate) blocks: Vec<Block>,
+    /// The block of each instruction.
+    block_of: Vec<BlockId>,
+}
+
+/// Which blocks of a control-flow graph dominate which.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Dominators {
+    /// The immediate dominator of each block, the entry its own, or `None`
+    /// if it isn't reached from the entry.
+    idoms: Vec<Option<BlockId>>,
+}
+
+impl Dominators {
+    /// The closest block that dominates `block` other than itself, if it's
+    /// reached from the entry and isn't it.
+    pub(crate) fn idom(&self, block: BlockId) -> Option<BlockId> {
+        self.idoms[block.0].filter(|&idom| idom != block)
+    }
+
+    /// Whether `block` is reached from the entry, and only through
+    /// `dominator`. A block dominates itself.
+    pub(crate) fn dominates(&self, dominator: BlockId, block: BlockId) -> bool {
+        if self.idoms[block.0].is_none() {
+            return false;
+        }
+        let mut block = block;
+        loop {
+            if block == dominator {
+                return true;
+            }
+            match self.idom(block) {
+                Some(idom) => block = idom,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// A natural loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Loop {
+    pub(crate) header: BlockId,
+    /// Its blocks, the header included.
+    pub(crate) blocks: BTreeSet<BlockId>,
+}
+
+/// An invariant of a control-flow graph `fun` breaks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum CfgError {
+    /// The entry block is jumped to, so it'd be entered twice.
+    EntryHasPreds,
+    /// A block doesn't end with a terminator, so it falls through to the
+    /// label after it.
+    MissingTerminator(BlockId),
+}
+
+impl fmt::Display for CfgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CfgError::EntryHasPreds => {
+                write!(f, "the entry block is jumped to")
+            }
+            CfgError::MissingTerminator(block) => {
+                write!(f, "block {} doesn't end with a terminator", block.0)
+            }
+        }
+    }
+}
+
+impl Cfg {
+    /// Builds the control-flow graph of `fun`, whose jumps must all be to
+    /// labels in it.
+    pub(crate) fn new(fun: &Fun) -> Cfg {
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut block_of = Vec::with_capacity(fun.code.len());
+        for (index, inst) in fun.code.iter().enumerate() {
+            let after_terminator =
+                index > 0 && fun.code[index - 1].is_terminator();
+            let label = match inst {
+                Inst::Label(label) => Some(*label),
+                _ => None,
+            };
+            if blocks.is_empty() || label.is_some() || after_terminator {
+                blocks.push(Block {
+                    label,
+                    insts: index..index,
+                    succs: Vec::new(),
+                    preds: Vec::new(),
+                });
+            }
+            let last = blocks.len() - 1;
+            blocks[last].insts.end = index + 1;
+            block_of.push(BlockId(last));
+        }
+
+        let labels: HashMap<Label, BlockId> = blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, block)| Some((block.label?, BlockId(index))))
+            .collect();
+        let target = |label: &Label| {
+            *labels.get(label).unwrap_or_else(|| {
+                panic!("jump to {}, which isn't in @{}", label, fun.name)
+            })
+        };
+        for index in 0..blocks.len() {
+            let last = blocks[index].insts.end - 1;
+            let succs = match &fun.code[last] {
+                Inst::Jump(label) => vec![target(label)],
+                Inst::Branch {
+                    then_label,
+                    else_label,
+                    ..
+                } => vec![target(then_label), target(else_label)],
+                Inst::Return(_) => Vec::new(),
+                // Falls through, which `check` reports.
+                _ if index + 1 < blocks.len() => vec![BlockId(index + 1)],
+                _ => Vec::new(),
+            };
+            for succ in &succs {
+                if !blocks[succ.0].preds.contains(&BlockId(index)) {
+                    blocks[succ.0].preds.push(BlockId(index));
+                }
+            }
+            blocks[index].succs = succs;
+        }
+        for block in &mut blocks {
+            block.preds.sort();
+        }
+        Cfg { blocks, block_of }
+    }
+
+    pub(crate) fn entry(&self) -> BlockId {
+        BlockId(0)
+    }
+
+    /// The block instruction `inst` of the function is in.
+    pub(crate) fn block_of(&self, inst: usize) -> BlockId {
+        self.block_of[inst]
+    }
+
+    /// The instructions control may go to right after instruction `inst`
+    /// of `fun`.
+    pub(crate) fn inst_succs(&self, inst: usize) -> Vec<usize> {
+        let block = &self.blocks[self.block_of[inst].0];
+        if inst + 1 < block.insts.end {
+            vec![inst + 1]
+        } else {
+            block
+                .succs
+                .iter()
+                .map(|succ| self.blocks[succ.0].insts.start)
+                .collect()
+        }
+    }
+
+    /// The blocks in reverse postorder from the entry, each before the
+    /// blocks it goes to but by edges back to it. The ones not reached from
+    /// the entry aren't in it.
+    pub(crate) fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut order = Vec::with_capacity(self.blocks.len());
+        if self.blocks.is_empty() {
+            return order;
+        }
+        let mut visited = vec![false; self.blocks.len()];
+        // Each block with how many of its successors were gone to.
+        let mut stack = vec![(self.entry(), 0)];
+        visited[self.entry().0] = true;
+        while let Some((block, next)) = stack.last_mut() {
+            match self.blocks[block.0].succs.get(*next) {
+                Some(&succ) => {
+                    *next += 1;
+                    if !visited[succ.0] {
+                        visited[succ.0] = true;
+                        stack.push((succ, 0));
+                    }
+                }
+                None => {
+                    order.push(*block);
+                    stack.pop();
+                }
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    /// Works out which blocks dominate which, by how Cooper, Harvey and
+    /// Kennedy do in "A Simple, Fast Dominance Algorithm": the immediate
+    /// dominator of a block is where the paths from those of its
+    /// predecessors meet, until that stops changing.
+    pub(crate) fn dominators(&self) -> Dominators {
+        let order = self.reverse_postorder();
+        let mut position = vec![usize::MAX; self.blocks.len()];
+        for (index, block) in order.iter().enumerate() {
+            position[block.0] = index;
+        }
+        let mut idoms: Vec<Option<BlockId>> = vec![None; self.blocks.len()];
+        let Some(&entry) = order.first() else {
+            return Dominators { idoms };
+        };
+        idoms[entry.0] = Some(entry);
+        let meet =
+            |idoms: &[Option<BlockId>], mut a: BlockId, mut b: BlockId| {
+                while a != b {
+                    while position[a.0] > position[b.0] {
+                        a = idoms[a.0].expect("a block is reached");
+                    }
+                    while position[b.0] > position[a.0] {
+                        b = idoms[b.0].expect("a block is reached");
+                    }
+                }
+                a
+            };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order[1..] {
+                let idom = self.blocks[block.0]
+                    .preds
+                    .iter()
+                    .filter(|pred| idoms[pred.0].is_some())
+                    .copied()
+                    .reduce(|a, b| meet(&idoms, a, b));
+                if idom != idoms[block.0] {
+                    idoms[block.0] = idom;
+                    changed = true;
+                }
+            }
+        }
+        Dominators { idoms }
+    }
+
+    /// The natural loops, by their headers, a loop with edges back to its
+    /// header from several blocks being one. A loop inside another has
+    /// fewer blocks.
+    pub(crate) fn loops(&self) -> Vec<Loop> {
+        let dominators = self.dominators();
+        let mut loops: Vec<Loop> = Vec::new();
+        for (index, block) in self.blocks.iter().enumerate() {
+            for &header in &block.succs {
+                if !dominators.dominates(header, BlockId(index)) {
+                    continue;
+                }
+                let mut blocks = BTreeSet::from([header]);
+                let mut stack = vec![BlockId(index)];
+                while let Some(block) = stack.pop() {
+                    // Leaving out the blocks not reached from the entry.
+                    if dominators.dominates(header, block)
+                        && blocks.insert(block)
+                    {
+                        stack.extend(self.blocks[block.0].preds.iter());
+                    }
+                }
+                match loops.iter_mut().find(|lp| lp.header == header) {
+                    Some(lp) => lp.blocks.extend(blocks),
+                    None => loops.push(Loop { header, blocks }),
+                }
+            }
+        }
+        loops.sort_by_key(|lp| lp.header);
+        loops
+    }
+
+    /// Checks the invariants every pass may rely on: that the entry block
+    /// is only entered at the start of the function, and that each block
+    /// ends with a terminator. That a terminator is only at the end of a
+    /// block holds by how blocks are split.
+    pub(crate) fn check(&self, fun: &Fun) -> Result<(), CfgError> {
+        if self
+            .blocks
+            .first()
+            .is_some_and(|entry| !entry.preds.is_empty())
+        {
+            return Err(CfgError::EntryHasPreds);
+        }
+        for (index, block) in self.blocks.iter().enumerate() {
+            let id = BlockId(index);
+            let last = block.insts.end - 1;
+            if !fun.code[last].is_terminator() {
+                return Err(CfgError::MissingTerminator(id));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockId, Cfg, CfgError, Loop};
+    use crate::{
+        ir::{Fun, Inst, Label, Operand, Ty},
+        test_support::{accept_corpus, lower_source},
+    };
+    use std::{collections::BTreeSet, fs};
+
+    fn funs(source: &str) -> Vec<Fun> {
+        lower_source(source).funs
+    }
+
+    #[test]
+    fn loops_and_branches_are_edges_between_blocks() {
+        let fun = &funs(
+            "int f(int x) { while (x) { if (x < 0) return 1; x = x - 1; } \
+             return 0; output(2); }",
+        )[0];
+        let cfg = Cfg::new(fun);
+
+        let edges: Vec<_> = cfg
+            .blocks
+            .iter()
+            .map(|block| {
+                let succs: Vec<_> =
+                    block.succs.iter().map(|succ| succ.0).collect();
+                let preds: Vec<_> =
+                    block.preds.iter().map(|pred| pred.0).collect();
+                (block.label.map(|label| label.0), succs, preds)
+            })
+            .collect();
+        // The entry, the loop's condition, its body, the `if`'s branches,
+        // what's after the loop, and the unreachable `output(2)`.
+        assert_eq!(
+            edges,
+            [
+                (None, vec![1], vec![]),
+                (Some(0), vec![2, 5], vec![0, 4]),
+                (Some(1), vec![3, 4], vec![1]),
+                (Some(3), vec![], vec![2]),
+                (Some(4), vec![1], vec![2]),
+                (Some(2), vec![], vec![1]),
+                (None, vec![], vec![]),
+            ]
+        );
+        assert_eq!(cfg.check(fun), Ok(()));
+        assert_eq!(cfg.block_of(0), BlockId(0));
+    }
+
+    #[test]
+    fn loops_are_found_from_their_headers_dominators() {
+        let fun = &funs(
+            "int f(int x) { while (x) { if (x < 0) return 1; x = x - 1; } \
+             return 0; output(2); }",
+        )[0];
+        let cfg = Cfg::new(fun);
+        let dominators = cfg.dominators();
+
+        let idoms: Vec<_> = (0..cfg.blocks.len())
+            .map(|block| dominators.idom(BlockId(block)).map(|idom| idom.0))
+            .collect();
+        assert_eq!(
+            idoms,
+            [None, Some(0), Some(1), Some(2), Some(2), Some(1), None]
+        );
+        assert!(dominators.dominates(BlockId(1), BlockId(4)));
+        assert!(!dominators.dominates(BlockId(4), BlockId(1)));
+        assert!(!dominators.dominates(BlockId(0), BlockId(6)));
+        assert_eq!(
+            cfg.loops(),
+            [Loop {
+                header: BlockId(1),
+                blocks: BTreeSet::from([BlockId(1), BlockId(2), BlockId(4)]),
+            }]
+        );
+
+        let fun = &funs(
+            "void f(int x) { while (x) { while (x < 10) x = x + 1; \
+             x = x - 1; } }",
+        )[0];
+        let loops: Vec<_> = Cfg::new(fun)
+            .loops()
+            .into_iter()
+            .map(|lp| {
+                let blocks: Vec<_> =
+                    lp.blocks.iter().map(|block| block.0).collect();
+                (lp.header.0, blocks)
+            })
+            .collect();
+        // The inner loop is its condition, at `L3`, and its body.
+        assert_eq!(loops, [(1, vec![1, 2, 3, 4, 5]), (3, vec![3, 4])]);
+    }
+
+    #[test]
+    fn broken_invariants_are_reported() {
+        let fun = |code| Fun {
+            name: "f".into(),
+            params: 0,
+            ret: None,
+            slots: Vec::new(),
+            temps: vec![Ty::I32],
+            labels: 1,
+            code,
+        };
+
+        let jumps_to_entry =
+            fun(vec![Inst::Label(Label(0)), Inst::Jump(Label(0))]);
+        assert_eq!(
+            Cfg::new(&jumps_to_entry).check(&jumps_to_entry),
+            Err(CfgError::EntryHasPreds)
+        );
+
+        let falls_through = fun(vec![
+            Inst::Return(None),
+            Inst::Label(Label(0)),
+            Inst::Copy {
+                dst: crate::ir::Temp(0),
+                src: Operand::Const(1),
+            },
+        ]);
+        assert_eq!(
+            Cfg::new(&falls_through).check(&falls_through),
+            Err(CfgError::MissingTerminator(BlockId(1)))
+        );
+    }
+
+    #[test]
+    fn corpus_programs_have_well_formed_graphs() {
+        for path in accept_corpus() {
+            let source = fs::read_to_string(&path).unwrap();
+            for fun in funs(&source) {
+                assert_eq!(
+                    Cfg::new(&fun).check(&fun),
+                    Ok(()),
+                    "{}",
+                    path.display()
+                );
+            }
+        }
+    }
+}
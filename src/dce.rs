@@ -0,0 +1,161 @@
+//! Dead code elimination: dropping the blocks control never gets to from
+//! the start of a function, and the instructions whose results are never
+//! read, e.g. what folding leaves of a constant it replaced everywhere.
+//!
+//! An instruction is only dropped if it does nothing but assign its
+//! result. A call whose result isn't read is kept, without the result, and
+//! so is a division by anything but a nonzero constant, as it may be one by
+//! zero, which the program stops at. Dropping an instruction may leave what
+//! it read unread, so it's gone through again until nothing's dropped.
+
+use crate::{
+    ast::BinOp,
+    cfg::Cfg,
+    ir::{Fun, Inst, Operand, Program},
+    liveness::live_in,
+};
+
+/// Drops the dead code of the functions of `program`, returning whether
+/// anything changed.
+pub(crate) fn dce_program(program: &mut Program) -> bool {
+    let mut changed = false;
+    for fun in &mut program.funs {
+        changed |= dce_fun(fun);
+    }
+    changed
+}
+
+/// Drops the dead code of `fun`, returning whether anything changed.
+pub(crate) fn dce_fun(fun: &mut Fun) -> bool {
+    let mut changed = drop_unreachable(fun);
+    loop {
+        let cfg = Cfg::new(fun);
+        let live_in = live_in(fun, &cfg);
+        let mut keep = vec![true; fun.code.len()];
+        let mut dropped = false;
+        for (index, inst) in fun.code.iter_mut().enumerate() {
+            let Some(dst) = inst.dst() else {
+                continue;
+            };
+            let is_read = cfg
+                .inst_succs(index)
+                .into_iter()
+                .any(|next| live_in[next].contains(&dst.0));
+            if is_read {
+                continue;
+            }
+            if let Inst::Call { dst, .. } = inst {
+                *dst = None;
+                dropped = true;
+            } else if is_pure(inst) {
+                keep[index] = false;
+                dropped = true;
+            }
+        }
+        if !dropped {
+            return changed;
+        }
+        changed = true;
+        let mut keep = keep.into_iter();
+        fun.code.retain(|_| keep.next().unwrap());
+    }
+}
+
+/// Drops the blocks of `fun` there's no path to from its entry, returning
+/// whether there were any.
+fn drop_unreachable(fun: &mut Fun) -> bool {
+    if fun.code.is_empty() {
+        return false;
+    }
+    let cfg = Cfg::new(fun);
+    let mut reached = vec![false; cfg.blocks.len()];
+    let mut stack = vec![cfg.entry()];
+    while let Some(block) = stack.pop() {
+        if !reached[block.0] {
+            reached[block.0] = true;
+            stack.extend(cfg.blocks[block.0].succs.iter().copied());
+        }
+    }
+    if reached.iter().all(|&reached| reached) {
+        return false;
+    }
+    let mut keep =
+        (0..fun.code.len()).map(|inst| reached[cfg.block_of(inst).0]);
+    fun.code.retain(|_| keep.next().unwrap());
+    true
+}
+
+/// Whether `inst` does nothing but assign its result.
+pub(crate) fn is_pure(inst: &Inst) -> bool {
+    match inst {
+        Inst::Binary {
+            op: BinOp::Div,
+            rhs,
+            ..
+        } => matches!(rhs, Operand::Const(value) if *value != 0),
+        Inst::Copy { .. }
+        | Inst::Neg { .. }
+        | Inst::Binary { .. }
+        | Inst::Offset { .. }
+        | Inst::Diff { .. }
+        | Inst::Load { .. } => true,
+        Inst::Store { .. }
+        | Inst::MemCopy { .. }
+        | Inst::Call { .. }
+        | Inst::Label(_)
+        | Inst::Jump(_)
+        | Inst::Branch { .. }
+        | Inst::Return(_)
+        | Inst::Check { .. }
+        | Inst::Line(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{passes::Pass, test_support::ir_after};
+
+    #[test]
+    fn unread_results_and_unreachable_blocks_are_dropped() {
+        let source = "int v[4];\n\
+                      void main(void) {\n\
+                      \x20   int i;\n\
+                      \x20   i = 2 * 3 - 6;\n\
+                      \x20   v[1] = i + 0;\n\
+                      \x20   if (1 < 2) output(v[1] * 1); else output(-(4 / 0));\n\
+                      \x20   input();\n\
+                      }\n";
+
+        assert_eq!(
+            ir_after(source, &[Pass::Fold, Pass::Dce]),
+            "global @v: 16, align 4\n\
+             \n\
+             fun @main() {\n\
+             \x20 slot $0 i: 4, align 4\n\
+             \x20 store i32 $0, 0\n\
+             \x20 %3: ptr = offset @v, 4\n\
+             \x20 %4: i32 = load i32 $0\n\
+             \x20 %5: i32 = copy %4\n\
+             \x20 store i32 %3, %5\n\
+             \x20 jump L0\n\
+             L0:\n\
+             \x20 %8: ptr = offset @v, 4\n\
+             \x20 %9: i32 = load i32 %8\n\
+             \x20 %10: i32 = copy %9\n\
+             \x20 call @output(%10)\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 call @input()\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn divisions_that_may_be_by_zero_are_kept() {
+        let code =
+            ir_after("void main(void) { 1 / input(); 2 / 2; }", &[Pass::Dce]);
+        assert!(code.contains("= div 1, %"), "{}", code);
+        assert!(!code.contains("div 2, 2"), "{}", code);
+    }
+}
@@ -0,0 +1,176 @@
+//! Graphviz dumps of the code of a program: the control-flow graph of each
+//! function, with `--emit=cfg-dot`, and the call graph, with
+//! `--emit=callgraph-dot`. Either is rendered with `dot`:
+//!
+//! ```text
+//! csub --emit=cfg-dot prog.cm | dot -Tsvg > prog.svg
+//! ```
+//!
+//! In the control-flow graphs, each function is a box of its own, and each
+//! basic block a node with its instructions, as `--emit=ir` shows them. The
+//! edges out of a `branch` say which one is taken when the condition holds.
+//! In the call graph, each function is a node, the builtins dashed, with an
+//! edge to each function it calls, saying how many times if more than once.
+
+use crate::{
+    cfg::Cfg,
+    ir::{dump_inst, Callee, Inst, Program},
+    sema::Builtin,
+};
+use std::fmt::Write;
+
+/// The control-flow graphs of the functions of `program`, in a graph.
+pub(crate) fn cfg_dot(program: &Program) -> String {
+    let mut out = String::from("digraph cfg {\n");
+    out.push_str("  node [shape=box, fontname=monospace];\n");
+    for (index, fun) in program.funs.iter().enumerate() {
+        let _ = writeln!(out, "  subgraph cluster_{} {{", index);
+        let _ = writeln!(out, "    label=\"@{}\";", escape(&fun.name));
+        let cfg = Cfg::new(fun);
+        for (block_index, block) in cfg.blocks.iter().enumerate() {
+            // Each line is left-justified, with `\l` after it.
+            let mut label = String::new();
+            for inst in &fun.code[block.insts.clone()] {
+                if !matches!(inst, Inst::Label(_)) {
+                    label.push_str("  ");
+                }
+                label.push_str(&escape(&dump_inst(program, fun, inst)));
+                label.push_str("\\l");
+            }
+            let _ = writeln!(
+                out,
+                "    f{}_b{} [label=\"{}\"];",
+                index, block_index, label
+            );
+        }
+        for (block_index, block) in cfg.blocks.iter().enumerate() {
+            let is_branch =
+                matches!(fun.code[block.insts.end - 1], Inst::Branch { .. });
+            for (succ_index, succ) in block.succs.iter().enumerate() {
+                let label = match (is_branch, succ_index) {
+                    (true, 0) => " [label=\"true\"]",
+                    (true, _) => " [label=\"false\"]",
+                    (false, _) => "",
+                };
+                let _ = writeln!(
+                    out,
+                    "    f{}_b{} -> f{}_b{}{};",
+                    index, block_index, index, succ.0, label
+                );
+            }
+        }
+        out.push_str("  }\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// The call graph of `program`.
+pub(crate) fn callgraph_dot(program: &Program) -> String {
+    let mut out = String::from("digraph callgraph {\n");
+    out.push_str("  node [shape=ellipse];\n");
+    for (index, fun) in program.funs.iter().enumerate() {
+        let _ =
+            writeln!(out, "  f{} [label=\"{}\"];", index, escape(&fun.name));
+    }
+    for builtin in Builtin::ALL {
+        let _ = writeln!(
+            out,
+            "  {} [label=\"{}\", style=dashed];",
+            builtin.name(),
+            builtin.name()
+        );
+    }
+
+    for (index, fun) in program.funs.iter().enumerate() {
+        // The callees in the order they're first called, with how many
+        // times each is.
+        let mut calls: Vec<(Callee, usize)> = Vec::new();
+        for inst in &fun.code {
+            let Inst::Call { callee, .. } = inst else {
+                continue;
+            };
+            match calls.iter_mut().find(|(called, _)| called == callee) {
+                Some((_, count)) => *count += 1,
+                None => calls.push((*callee, 1)),
+            }
+        }
+        for (callee, count) in calls {
+            let node = match callee {
+                Callee::Fun(callee) => format!("f{}", callee),
+                Callee::Builtin(builtin) => builtin.name().to_string(),
+            };
+            let label = if count > 1 {
+                format!(" [label=\"{}\"]", count)
+            } else {
+                String::new()
+            };
+            let _ = writeln!(out, "  f{} -> {}{};", index, node, label);
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// `text`, to go between the quotes of a string in Graphviz.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{callgraph_dot, cfg_dot};
+    use crate::test_support::lower_source;
+
+    #[test]
+    fn blocks_are_nodes_with_their_code() {
+        let program = lower_source(
+            "int f(int x) { if (x) return 1; return 2; }\n\
+             void main(void) { output(f(input())); }",
+        );
+
+        assert_eq!(
+            cfg_dot(&program),
+            "digraph cfg {\n\
+             \x20 node [shape=box, fontname=monospace];\n\
+             \x20 subgraph cluster_0 {\n\
+             \x20   label=\"@f\";\n\
+             \x20   f0_b0 [label=\"  store i32 $0, %0\\l  %1: i32 = load i32 $0\\l  branch %1, L0, L1\\l\"];\n\
+             \x20   f0_b1 [label=\"L0:\\l  ret 1\\l\"];\n\
+             \x20   f0_b2 [label=\"L1:\\l  ret 2\\l\"];\n\
+             \x20   f0_b0 -> f0_b1 [label=\"true\"];\n\
+             \x20   f0_b0 -> f0_b2 [label=\"false\"];\n\
+             \x20 }\n\
+             \x20 subgraph cluster_1 {\n\
+             \x20   label=\"@main\";\n\
+             \x20   f1_b0 [label=\"  %0: i32 = call @input()\\l  %1: i32 = call @f(%0)\\l  call @output(%1)\\l  ret\\l\"];\n\
+             \x20 }\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn calls_are_edges_counted_by_callee() {
+        let program = lower_source(
+            "int fib(int n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }\n\
+             void unused(void) { }\n\
+             void main(void) { output(fib(input())); }",
+        );
+
+        assert_eq!(
+            callgraph_dot(&program),
+            "digraph callgraph {\n\
+             \x20 node [shape=ellipse];\n\
+             \x20 f0 [label=\"fib\"];\n\
+             \x20 f1 [label=\"unused\"];\n\
+             \x20 f2 [label=\"main\"];\n\
+             \x20 input [label=\"input\", style=dashed];\n\
+             \x20 output [label=\"output\", style=dashed];\n\
+             \x20 f0 -> f0 [label=\"2\"];\n\
+             \x20 f2 -> input;\n\
+             \x20 f2 -> f0;\n\
+             \x20 f2 -> output;\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,1448 @@
+use crate::{
+    assembler::assemble,
+    ast::Program,
+    bytecode,
+    catalog::{self, Language},
+    dot::{callgraph_dot, cfg_dot},
+    dump::dump_program,
+    elf::write_object,
+    emitter::{new_emitter, ColorChoice, ErrorFormat, PhaseFilter},
+    errors::{Diag, DiagBag, Diagnostic, Phase, DEFAULT_ERROR_LIMIT},
+    extensions::{Extension, Extensions},
+    fix::{fix_files, was_fixed},
+    frame::dump_frames,
+    ice::{catch_ice, in_phase},
+    interp,
+    ir::{
+        self, dump_ir, lower_program_with_checks, lower_program_with_lines,
+        Checks,
+    },
+    lints::{Lint, LintLevel, WarningConfig},
+    llvm::emit_llvm_ir,
+    parser::{parse_program, parse_program_traced, ParseResult},
+    passes::{OptLevel, Pass, PassManager, PassOptions},
+    printer::{print_program, Indent},
+    regalloc::RegAlloc,
+    scanner::{scan_words, scan_words_with_error_limit},
+    sema::{check_entry_point, check_program, Builtin},
+    source_map::{SourceFile, SourceMap},
+    symbols::{dump_symbols, symbol_table},
+    x86_64::emit_asm,
+};
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    path::Path,
+    process,
+    rc::Rc,
+};
+
+/// Options given in the command line.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Options {
+    pub(crate) input_path: String,
+    pub(crate) error_format: ErrorFormat,
+    pub(crate) color: ColorChoice,
+    /// Language diagnostics are reported in.
+    pub(crate) language: Language,
+    /// Maximum number of errors to report before stopping, if any.
+    pub(crate) error_limit: Option<usize>,
+    pub(crate) warning_config: WarningConfig,
+    /// Whether to rewrite the input with the suggested fixes.
+    pub(crate) fix: bool,
+    /// The only phase whose diagnostics are shown, if any.
+    pub(crate) only_errors_from: Option<Phase>,
+    /// What to write to stdout besides the diagnostics, if anything.
+    pub(crate) emit: Option<Emit>,
+    /// How `--emit=source` indents the program.
+    pub(crate) indent: Indent,
+    /// The language extensions the program may use.
+    pub(crate) extensions: Extensions,
+    /// Whether to print the rules the parser goes through.
+    pub(crate) parse_trace: bool,
+    /// How much the code is optimized, unless `passes` says what to run.
+    pub(crate) opt_level: OptLevel,
+    /// The passes to run on the code, in order, instead of the ones of
+    /// `opt_level`.
+    pub(crate) passes: Option<Vec<Pass>>,
+    /// The passes to print the code to stderr after.
+    pub(crate) print_ir_after: Vec<Pass>,
+    /// The passes to print the remarks of to stderr.
+    pub(crate) remarks: Vec<Pass>,
+    /// What the passes are tuned by.
+    pub(crate) pass_options: PassOptions,
+    /// What the compiled code checks as it runs.
+    pub(crate) checks: Checks,
+    /// Whether the compiled code says which line of the source each of its
+    /// instructions is of, for a debugger.
+    pub(crate) debug_info: bool,
+    /// How the x86-64 backend allocates temporaries to registers.
+    pub(crate) regalloc: RegAlloc,
+    /// What to do with the program besides checking it.
+    pub(crate) command: Command,
+    /// What `csub build` writes.
+    pub(crate) output: Output,
+    /// Where `csub build` writes it, if not next to the input.
+    pub(crate) output_path: Option<String>,
+}
+
+/// What the compiler is asked to do, by the first argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Command {
+    /// Only check the program, and write what `--emit` says.
+    #[default]
+    Check,
+    /// `csub run`: run the program after compiling it, or the bytecode in a
+    /// `.cbc` file without compiling anything.
+    Run,
+    /// `csub build`: compile the program to bytecode (see `bytecode`), or
+    /// to what `Output` says.
+    Build,
+}
+
+/// What `csub build` compiles the program to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Output {
+    /// Bytecode, in a `.cbc` file.
+    #[default]
+    Bytecode,
+    /// `--obj`: an x86-64 object file for Linux (see `elf`), in a `.o`
+    /// file, to link with libc.
+    Object,
+    /// `--bin`: an executable for Linux, the object linked with libc, in a
+    /// file without an extension.
+    Executable,
+}
+
+impl Output {
+    /// The extension of the file it's written to by default.
+    fn extension(self) -> &'static str {
+        match self {
+            Output::Bytecode => "cbc",
+            Output::Object => "o",
+            Output::Executable => "",
+        }
+    }
+}
+
+/// Something the compiler can write out for debugging, selected with
+/// `--emit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Emit {
+    /// A dump of the syntax tree (see `dump`).
+    Ast,
+    /// The program printed back as source, in a canonical layout (see
+    /// `printer`), if it has no syntax errors.
+    Source,
+    /// The syntax tree as JSON, for tools outside the compiler.
+    #[cfg(feature = "serde")]
+    AstJson,
+    /// Every scope with the names declared in it (see `symbols`).
+    Symbols,
+    /// The three-address code of the program (see `ir`), if it has no
+    /// errors.
+    Ir,
+    /// x86-64 assembly for the program (see `x86_64`), if it has no errors.
+    Asm,
+    /// Textual LLVM IR for the program (see `llvm`), if it has no errors.
+    LlvmIr,
+    /// The control-flow graph of each function, for Graphviz (see `dot`),
+    /// if the program has no errors.
+    CfgDot,
+    /// The call graph of the program, for Graphviz, if it has no errors.
+    CallgraphDot,
+    /// The layout of the frame of each function on x86-64 (see `frame`),
+    /// if the program has no errors.
+    Frame,
+}
+
+impl Emit {
+    pub(crate) fn from_name(name: &str) -> Option<Emit> {
+        match name {
+            "ast" => Some(Emit::Ast),
+            "source" => Some(Emit::Source),
+            #[cfg(feature = "serde")]
+            "ast-json" => Some(Emit::AstJson),
+            "symbols" => Some(Emit::Symbols),
+            "ir" => Some(Emit::Ir),
+            "asm" => Some(Emit::Asm),
+            "llvm-ir" => Some(Emit::LlvmIr),
+            "cfg-dot" => Some(Emit::CfgDot),
+            "callgraph-dot" => Some(Emit::CallgraphDot),
+            "frame" => Some(Emit::Frame),
+            _ => None,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the command line arguments, excluding the program name.
+    pub(crate) fn parse(
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<Options, String> {
+        let mut input_path = None;
+        let mut error_format = ErrorFormat::Human;
+        let mut color = ColorChoice::Auto;
+        let mut language = Language::English;
+        let mut error_limit = Some(DEFAULT_ERROR_LIMIT);
+        let mut warning_config = WarningConfig::new();
+        let mut fix = false;
+        let mut only_errors_from = None;
+        let mut emit = None;
+        let mut indent = Indent::default();
+        let mut extensions = Extensions::none();
+        let mut parse_trace = false;
+        let mut opt_level = OptLevel::default();
+        let mut passes = None;
+        let mut print_ir_after = Vec::new();
+        let mut remarks = Vec::new();
+        let mut pass_options = PassOptions::default();
+        // Divisions are always checked, as one by 0 would otherwise be a
+        // fault of the machine, not saying where it is.
+        let mut checks = Checks {
+            division: true,
+            ..Checks::default()
+        };
+        let mut debug_info = false;
+        let mut regalloc = RegAlloc::default();
+        let mut output = Output::default();
+        let mut output_path = None;
+
+        let mut args = args.into_iter().peekable();
+        let command = if args.next_if_eq("run").is_some() {
+            Command::Run
+        } else if args.next_if_eq("build").is_some() {
+            Command::Build
+        } else {
+            Command::Check
+        };
+        while let Some(arg) = args.next() {
+            if let Some(level) = lint_level_flag(&arg) {
+                // Both `-W name` and `-Wname` are accepted.
+                let name = match &arg[2..] {
+                    "" => args.next().ok_or_else(|| {
+                        format!("missing warning name after `{}`", arg)
+                    })?,
+                    name => name.to_owned(),
+                };
+
+                if name == "error" && level == LintLevel::Warn {
+                    warning_config.set_warnings_as_errors(true);
+                } else {
+                    let lint = Lint::from_name(&name)
+                        .ok_or_else(|| format!("unknown warning `{}`", name))?;
+                    warning_config.set_level(lint, level);
+                }
+            } else if let Some(name) = arg.strip_prefix("--error-format=") {
+                error_format =
+                    ErrorFormat::from_name(name).ok_or_else(|| {
+                        format!("unknown error format `{}`", name)
+                    })?;
+            } else if arg == "--fix" {
+                fix = true;
+            } else if arg == "--debug-parse-trace" {
+                parse_trace = true;
+            } else if let Some(name) = arg.strip_prefix("--only-errors-from=") {
+                let phase = Phase::from_name(name)
+                    .ok_or_else(|| format!("unknown phase `{}`", name))?;
+                only_errors_from = Some(phase);
+            } else if let Some(name) = arg.strip_prefix("--emit=") {
+                let what = Emit::from_name(name)
+                    .ok_or_else(|| format!("unknown emit kind `{}`", name))?;
+                emit = Some(what);
+            } else if let Some(name) = arg.strip_prefix("--indent=") {
+                indent = Indent::from_name(name)
+                    .ok_or_else(|| format!("invalid indent `{}`", name))?;
+            } else if let Some(name) = arg.strip_prefix("--extension=") {
+                if name == "all" {
+                    extensions = Extensions::all();
+                } else {
+                    let ext = Extension::from_name(name).ok_or_else(|| {
+                        format!("unknown extension `{}`", name)
+                    })?;
+                    extensions.enable(ext);
+                }
+            } else if let Some(name) = arg.strip_prefix("--color=") {
+                color = ColorChoice::from_name(name).ok_or_else(|| {
+                    format!("unknown color choice `{}`", name)
+                })?;
+            } else if let Some(name) = arg.strip_prefix("--lang=") {
+                language = Language::from_name(name)
+                    .ok_or_else(|| format!("unknown language `{}`", name))?;
+            } else if let Some(limit) = arg.strip_prefix("--error-limit=") {
+                let limit: usize = limit
+                    .parse()
+                    .map_err(|_| format!("invalid error limit `{}`", limit))?;
+                // Zero means there's no limit at all.
+                error_limit = Some(limit).filter(|&limit| limit > 0);
+            } else if let Some(level) = arg.strip_prefix("-O") {
+                opt_level = OptLevel::from_name(level).ok_or_else(|| {
+                    format!("unknown optimization level `{}`", level)
+                })?;
+            } else if let Some(names) = arg.strip_prefix("--passes=") {
+                passes = Some(pass_names(names)?);
+            } else if let Some(names) = arg.strip_prefix("--print-ir-after=") {
+                print_ir_after.extend(pass_names(names)?);
+            } else if let Some(names) = arg.strip_prefix("--remarks=") {
+                remarks.extend(pass_names(names)?);
+            } else if let Some(threshold) =
+                arg.strip_prefix("--inline-threshold=")
+            {
+                pass_options.inline_threshold =
+                    threshold.parse().map_err(|_| {
+                        format!("invalid inline threshold `{}`", threshold)
+                    })?;
+            } else if arg == "--bounds-checks" {
+                checks.bounds = true;
+            } else if arg == "-g" {
+                debug_info = true;
+            } else if let Some(name) = arg.strip_prefix("--regalloc=") {
+                regalloc = RegAlloc::from_name(name).ok_or_else(|| {
+                    format!("unknown register allocator `{}`", name)
+                })?;
+            } else if arg == "--obj" {
+                if command != Command::Build {
+                    return Err("`--obj` is only for `csub build`".into());
+                }
+                output = Output::Object;
+            } else if arg == "--bin" {
+                if command != Command::Build {
+                    return Err("`--bin` is only for `csub build`".into());
+                }
+                output = Output::Executable;
+            } else if arg == "-o" {
+                if command != Command::Build {
+                    return Err("`-o` is only for `csub build`".into());
+                }
+                let path = args.next().ok_or("missing path after `-o`")?;
+                output_path = Some(path);
+            } else if arg.starts_with('-') {
+                return Err(format!("unknown option `{}`", arg));
+            } else if input_path.is_some() {
+                return Err(format!("unexpected argument `{}`", arg));
+            } else {
+                input_path = Some(arg);
+            }
+        }
+
+        let input_path = input_path.ok_or("no input file")?;
+
+        Ok(Options {
+            input_path,
+            error_format,
+            color,
+            language,
+            error_limit,
+            warning_config,
+            fix,
+            only_errors_from,
+            emit,
+            indent,
+            extensions,
+            parse_trace,
+            opt_level,
+            passes,
+            print_ir_after,
+            remarks,
+            pass_options,
+            checks,
+            debug_info,
+            regalloc,
+            command,
+            output,
+            output_path,
+        })
+    }
+}
+
+/// The passes named in `names`, separated by commas.
+fn pass_names(names: &str) -> Result<Vec<Pass>, String> {
+    names
+        .split(',')
+        .map(|name| {
+            Pass::from_name(name)
+                .ok_or_else(|| format!("unknown pass `{}`", name))
+        })
+        .collect()
+}
+
+/// The lint level set by a `-W`, `-A` or `-D` flag.
+fn lint_level_flag(arg: &str) -> Option<LintLevel> {
+    if arg.starts_with("-W") {
+        Some(LintLevel::Warn)
+    } else if arg.starts_with("-A") {
+        Some(LintLevel::Allow)
+    } else if arg.starts_with("-D") {
+        Some(LintLevel::Deny)
+    } else {
+        None
+    }
+}
+
+const USAGE: &str =
+    "usage: csub [run|build] [--obj|--bin] [-o <file>] [--error-format=human|short|json|sarif] \
+                     [--color=auto|always|never] [--lang=en|pt] \
+                     [--error-limit=N] \
+                     [-W|-A|-D <warning>] [-W error] [--fix] \
+                     [--only-errors-from=lex|parse|sema|codegen|run] \
+                     [--emit=ast|ast-json|source|symbols|ir|asm|llvm-ir|cfg-dot|callgraph-dot|frame] \
+                     [--indent=N|tab] \
+                     [-O0|-O1|-O2] [--passes=<pass>,...] \
+                     [--print-ir-after=<pass>,...] [--remarks=<pass>,...] \
+                     [--inline-threshold=N] [--bounds-checks] [-g] \
+                     [--regalloc=linear|graph] \
+                     [--extension=<name>|all] \
+                     [--debug-parse-trace] <file>";
+
+/// Runs the compiler with the command line arguments `args`, returning the
+/// process exit code.
+pub fn main(args: impl IntoIterator<Item = String>) -> i32 {
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            eprintln!("{}", USAGE);
+            return 1;
+        }
+    };
+
+    catalog::set_language(options.language);
+
+    let mut source_map = SourceMap::new();
+    let mut diag_bag = DiagBag::with_error_limit(options.error_limit)
+        .with_warning_config(options.warning_config.clone());
+    let result = catch_ice(|| {
+        if options.command == Command::Run
+            && options.input_path.ends_with(".cbc")
+        {
+            run_bytecode(&options.input_path, &mut diag_bag);
+            return None;
+        }
+        if options.fix {
+            return compile_and_fix(&options, &mut source_map, &mut diag_bag);
+        }
+        compile(
+            &options.input_path,
+            options.extensions,
+            &mut source_map,
+            &mut diag_bag,
+        )
+    });
+    let (program, mut ice) = match result {
+        Ok(program) => (program, None),
+        Err(ice) => (None, Some(ice)),
+    };
+    if let (true, Some(source_file)) =
+        (options.parse_trace, source_map.files().first())
+    {
+        // Parsed again, as the pipeline doesn't keep what it's not asked for.
+        let (words, _) = scan_words(source_file, options.extensions);
+        let (_, trace) =
+            parse_program_traced(source_file, &words, options.extensions);
+        print!("{}", trace);
+    }
+    match (options.emit, &program, source_map.files().first()) {
+        (Some(Emit::Ast), Some(program), Some(source_file)) => {
+            print!("{}", dump_program(program, source_file))
+        }
+        #[cfg(feature = "serde")]
+        (Some(Emit::AstJson), Some(program), _) => {
+            println!("{}", crate::dump::dump_program_json(program))
+        }
+        // Printed without what the parser skipped, it would be another
+        // program.
+        (Some(Emit::Source), Some(program), _)
+            if !diag_bag.iter().any(|diagnostic| {
+                diagnostic.is_error()
+                    && matches!(
+                        diagnostic.diag.phase(),
+                        Some(Phase::Lex | Phase::Parse)
+                    )
+            }) =>
+        {
+            print!("{}", print_program(program, options.indent))
+        }
+        (Some(Emit::Symbols), Some(program), Some(source_file)) => {
+            print!("{}", dump_symbols(&symbol_table(program), source_file))
+        }
+        (
+            Some(
+                emit @ (Emit::Ir
+                | Emit::Asm
+                | Emit::LlvmIr
+                | Emit::CfgDot
+                | Emit::CallgraphDot
+                | Emit::Frame),
+            ),
+            Some(program),
+            source_file,
+        ) if !diag_bag.has_errors() => {
+            match catch_ice(|| {
+                in_phase(Phase::Codegen, || {
+                    codegen(program, source_file, emit, &options)
+                })
+            }) {
+                Ok(code) => print!("{}", code),
+                Err(codegen_ice) => ice = Some(codegen_ice),
+            }
+        }
+        _ => {}
+    }
+    if let (Some(program), None) = (&program, &ice) {
+        if !diag_bag.has_errors() {
+            let result = match options.command {
+                Command::Check => Ok(Ok(())),
+                Command::Run => catch_ice(|| run_program(program)),
+                Command::Build => catch_ice(|| {
+                    in_phase(Phase::Codegen, || {
+                        build(program, source_map.files().first(), &options)
+                    })
+                }),
+            };
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(diag)) => diag_bag.push_unlimited(diag),
+                Err(command_ice) => ice = Some(command_ice),
+            }
+        }
+    }
+    if let Some(ice) = &ice {
+        diag_bag.push_unlimited(ice.diagnostic());
+    }
+    diag_bag.sort_by_position();
+
+    let stderr = io::stderr();
+    let no_color =
+        env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let color = options.color.should_color(stderr.is_terminal(), no_color);
+    let mut emitter =
+        new_emitter(options.error_format, &source_map, Box::new(stderr), color);
+    if let Some(phase) = options.only_errors_from {
+        emitter = Box::new(PhaseFilter {
+            inner: emitter,
+            phase,
+        });
+    }
+    for diagnostic in diag_bag.iter() {
+        // There's nowhere left to report a failure to write to stderr.
+        let _ = emitter.emit(diagnostic);
+    }
+    let _ = emitter.emit_summary(&diag_bag.summary());
+    let _ = emitter.finish();
+
+    if ice.is_some() {
+        ICE_EXIT_CODE
+    } else if diag_bag.has_errors() {
+        1
+    } else {
+        0
+    }
+}
+
+/// Lowers `program`, which has no errors, with the checks `options` says,
+/// at lines of `source_file`, its source, and runs the passes `options`
+/// says on it. The dumps of the code after passes and the remarks go to
+/// stderr.
+fn lower_and_optimize(
+    program: &Program,
+    source_file: Option<&Rc<SourceFile>>,
+    options: &Options,
+) -> ir::Program {
+    // Checked again, as the pipeline doesn't keep what it's not asked for.
+    let hir = check_program(program, &Builtin::ALL).hir;
+    let mut ir = match source_file {
+        Some(source_file) if options.debug_info => {
+            lower_program_with_lines(&hir, options.checks, source_file)
+        }
+        _ => lower_program_with_checks(
+            &hir,
+            options.checks,
+            source_file.map(|file| &**file),
+        ),
+    };
+    let manager = match &options.passes {
+        Some(passes) => PassManager::new(passes.clone()),
+        None => PassManager::for_level(options.opt_level),
+    };
+    let mut dumps = String::new();
+    manager
+        .options(options.pass_options)
+        .print_ir_after(options.print_ir_after.clone())
+        .remarks(options.remarks.clone())
+        .run(&mut ir, &mut dumps);
+    eprint!("{}", dumps);
+    ir
+}
+
+/// Lowers and optimizes `program`, which has no errors, and writes it as
+/// `emit` says, one of the emits of code.
+fn codegen(
+    program: &Program,
+    source_file: Option<&Rc<SourceFile>>,
+    emit: Emit,
+    options: &Options,
+) -> String {
+    let ir = lower_and_optimize(program, source_file, options);
+    match emit {
+        Emit::Ir => dump_ir(&ir),
+        Emit::Asm => emit_asm(&ir, options.regalloc),
+        Emit::LlvmIr => emit_llvm_ir(&ir),
+        Emit::CfgDot => cfg_dot(&ir),
+        Emit::CallgraphDot => callgraph_dot(&ir),
+        Emit::Frame => dump_frames(&ir, options.regalloc),
+        Emit::Ast | Emit::Source | Emit::Symbols => {
+            unreachable!("{:?} isn't code", emit)
+        }
+        #[cfg(feature = "serde")]
+        Emit::AstJson => unreachable!("{:?} isn't code", emit),
+    }
+}
+
+/// Runs `program`, which has no errors, on the standard input and output,
+/// returning what stopped it, if anything did.
+fn run_program(program: &Program) -> Result<(), Diag> {
+    // Checked again, as the pipeline doesn't keep what it's not asked for.
+    let hir = check_program(program, &Builtin::ALL).hir;
+    in_phase(Phase::Run, || {
+        interp::run(
+            &hir,
+            &mut io::BufReader::new(io::stdin()),
+            &mut io::BufWriter::new(io::stdout()),
+        )
+    })
+}
+
+/// Compiles `program`, which has no errors, to bytecode, or to what the
+/// output of `options` says, and writes it to the output path of `options`,
+/// or next to the input with the extension of the output.
+fn build(
+    program: &Program,
+    source_file: Option<&Rc<SourceFile>>,
+    options: &Options,
+) -> Result<(), Diag> {
+    let ir = lower_and_optimize(program, source_file, options);
+    let bytes = match options.output {
+        Output::Bytecode => bytecode::encode(&bytecode::compile(&ir)),
+        Output::Object | Output::Executable => {
+            write_object(&assemble(&emit_asm(&ir, options.regalloc)))
+        }
+    };
+    let path = match &options.output_path {
+        Some(path) => path.clone(),
+        None => Path::new(&options.input_path)
+            .with_extension(options.output.extension())
+            .to_string_lossy()
+            .into_owned(),
+    };
+    if options.output == Output::Executable {
+        return link(&bytes, &path);
+    }
+    fs::write(&path, bytes).map_err(|err| Diag::CannotWriteFile {
+        path,
+        reason: err.to_string(),
+    })
+}
+
+/// Links `object`, an object file, with libc into an executable at `path`,
+/// with `cc`, or the compiler the `CC` environment variable names, which
+/// finds the linker and libc where the system has them. The object is
+/// written to a temporary file for it, removed afterwards. If it fails,
+/// what it wrote to stderr is the reason.
+fn link(object: &[u8], path: &str) -> Result<(), Diag> {
+    let failed = |reason| Diag::LinkFailed {
+        path: path.to_owned(),
+        reason,
+    };
+    let object_path =
+        env::temp_dir().join(format!("csub-link-{}.o", process::id()));
+    fs::write(&object_path, object).map_err(|err| Diag::CannotWriteFile {
+        path: object_path.display().to_string(),
+        reason: err.to_string(),
+    })?;
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    let output = process::Command::new(&cc)
+        .arg(&object_path)
+        .arg("-o")
+        .arg(path)
+        .output();
+    let _ = fs::remove_file(&object_path);
+    let output = output
+        .map_err(|err| failed(format!("couldn't run `{}`: {}", cc, err)))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(failed(match stderr.trim() {
+        "" => format!("`{}` failed with {}", cc, output.status),
+        stderr => {
+            format!("`{}` failed with {}:\n{}", cc, output.status, stderr)
+        }
+    }))
+}
+
+/// Runs the bytecode in the file at `path` on the standard input and
+/// output, reporting to `diag_bag` why it can't be run, or what stopped it.
+fn run_bytecode(path: &str, diag_bag: &mut DiagBag) {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            diag_bag.push(Diag::CannotReadFile {
+                path: path.to_owned(),
+                reason: err.to_string(),
+            });
+            return;
+        }
+    };
+    let bytecode = match bytecode::decode(&bytes) {
+        Ok(bytecode) => bytecode,
+        Err(reason) => {
+            diag_bag.push(Diag::InvalidBytecode {
+                path: path.to_owned(),
+                reason,
+            });
+            return;
+        }
+    };
+    let result = in_phase(Phase::Run, || {
+        bytecode::run(
+            &bytecode,
+            &mut io::BufReader::new(io::stdin()),
+            &mut io::BufWriter::new(io::stdout()),
+        )
+    });
+    if let Err(diag) = result {
+        diag_bag.push_unlimited(diag);
+    }
+}
+
+/// The exit code after an internal compiler error, the same as a Rust panic.
+const ICE_EXIT_CODE: i32 = 101;
+
+/// The most times `--fix` rewrites a file before it stops compiling it again.
+/// A fix only ever makes way for other fixes by no longer overlapping them,
+/// so this is only reached by fixes that keep undoing each other.
+const MAX_FIX_ROUNDS: usize = 8;
+
+/// Compiles the file at `options.input_path` like [`compile`], rewriting it
+/// with the machine-applicable suggestions and compiling what they leave
+/// again, until none are left. `source_map` and `diag_bag` end up with the
+/// last compilation, along with a note telling how many fixes were applied
+/// to the file in all.
+///
+/// Returns the program of the last compilation.
+fn compile_and_fix(
+    options: &Options,
+    source_map: &mut SourceMap,
+    diag_bag: &mut DiagBag,
+) -> Option<Program> {
+    let compile_again = |source_map: &mut SourceMap, diag_bag: &mut DiagBag| {
+        *source_map = SourceMap::new();
+        *diag_bag = DiagBag::with_error_limit(options.error_limit)
+            .with_warning_config(options.warning_config.clone());
+        compile(
+            &options.input_path,
+            options.extensions,
+            source_map,
+            diag_bag,
+        )
+    };
+    let mut program = compile_again(source_map, diag_bag);
+    let mut applied = 0;
+
+    for _ in 0..MAX_FIX_ROUNDS {
+        let applied_now = apply_fixes(source_map, diag_bag);
+        if applied_now == 0 {
+            break;
+        }
+        applied += applied_now;
+        program = compile_again(source_map, diag_bag);
+    }
+
+    if applied > 0 {
+        let mut builder = Diagnostic::builder(Diag::FixesApplied {
+            path: options.input_path.clone(),
+            count: applied,
+        });
+        let left = fix_files(source_map, diag_bag.iter())
+            .iter()
+            .map(|fixed_file| {
+                fixed_file.applied.len() + fixed_file.skipped.len()
+            })
+            .sum::<usize>();
+        if left > 0 {
+            builder = builder.with_help(
+                None,
+                catalog::message("fixes-skipped", &[("count", &left)]),
+            );
+        }
+        builder.emit(diag_bag);
+    }
+    program
+}
+
+/// Rewrites files with the machine-applicable suggestions in `diag_bag`. The
+/// diagnostics fixed this way are dropped.
+///
+/// Returns how many fixes were written.
+fn apply_fixes(source_map: &SourceMap, diag_bag: &mut DiagBag) -> usize {
+    let mut written_files = Vec::new();
+
+    for fixed_file in fix_files(source_map, diag_bag.iter()) {
+        let path = fixed_file.source_file.name.clone();
+        match fs::write(&path, &fixed_file.fixed_source) {
+            Ok(()) => written_files.push(fixed_file),
+            Err(err) => diag_bag.push(Diag::CannotWriteFile {
+                path,
+                reason: err.to_string(),
+            }),
+        }
+    }
+
+    diag_bag.retain(|diagnostic| !was_fixed(diagnostic, &written_files));
+    written_files
+        .iter()
+        .map(|fixed_file| fixed_file.applied.len())
+        .sum()
+}
+
+/// Runs the compilation pipeline over the file at `input_path`, written in C-
+/// with `extensions`, adding it to `source_map` and reporting to `diag_bag`.
+/// The pipeline stops as soon as a phase ends with a fatal error or with too
+/// many errors.
+///
+/// Returns the program, if the file could be parsed.
+pub(crate) fn compile(
+    input_path: &str,
+    extensions: Extensions,
+    source_map: &mut SourceMap,
+    diag_bag: &mut DiagBag,
+) -> Option<Program> {
+    let source_file = match fs::read_to_string(input_path) {
+        Ok(source_content) => {
+            source_map.add_file(input_path.to_owned(), source_content)
+        }
+        Err(err) => {
+            diag_bag.push(Diag::CannotReadFile {
+                path: input_path.to_owned(),
+                reason: err.to_string(),
+            });
+            return None;
+        }
+    };
+
+    compile_file(&source_file, extensions, diag_bag)
+}
+
+/// Runs the compiler phases on `source_file`, written in C- with
+/// `extensions`, reporting to `diag_bag`. Diagnostics deferred during a phase
+/// are dealt with when it ends. The program isn't checked if the syntax
+/// errors reached the error limit.
+///
+/// Returns the program, with whatever syntax errors left out, if the file
+/// could be parsed.
+pub(crate) fn compile_file(
+    source_file: &SourceFile,
+    extensions: Extensions,
+    diag_bag: &mut DiagBag,
+) -> Option<Program> {
+    let errors_left = diag_bag.errors_left();
+    let (words, scan_diags) = in_phase(Phase::Lex, || {
+        scan_words_with_error_limit(source_file, extensions, errors_left)
+    });
+    diag_bag.extend(scan_diags);
+    diag_bag.end_phase();
+
+    // The scanner leaves out the words it couldn't make sense of, so parsing
+    // what's left would only report errors that aren't really there.
+    if diag_bag.has_errors() {
+        return None;
+    }
+
+    let parse_result = in_phase(Phase::Parse, || {
+        parse_program(source_file, &words, extensions)
+    });
+    let is_complete = parse_result.is_complete();
+    let ParseResult {
+        program,
+        diag_bag: parse_diags,
+        ..
+    } = parse_result;
+    diag_bag.extend(parse_diags);
+    diag_bag.end_phase();
+
+    // Past the error limit the bag takes nothing more, so sema would be for
+    // nothing, and after a fatal error there's nothing worth checking.
+    if diag_bag.should_abort() {
+        return Some(program);
+    }
+
+    let sema_diags = in_phase(Phase::Sema, || {
+        let mut sema_diags = check_program(&program, &Builtin::ALL).diag_bag;
+        if is_complete {
+            sema_diags.extend(check_entry_point(&program));
+        }
+        sema_diags
+    });
+    diag_bag.extend(sema_diags);
+    diag_bag.end_phase();
+
+    Some(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_fixes, compile, compile_file, link, main, Command, Emit, Options,
+        Output,
+    };
+    use crate::{
+        catalog::Language,
+        emitter::{ColorChoice, ErrorFormat},
+        errors::{
+            Diag, DiagBag, Diagnostic, Phase, Severity, Suggestion,
+            DEFAULT_ERROR_LIMIT,
+        },
+        extensions::{Extension, Extensions},
+        ir::Checks,
+        lints::{Lint, LintLevel, WarningConfig},
+        passes::{OptLevel, Pass, PassOptions},
+        printer::Indent,
+        regalloc::RegAlloc,
+        source_map::{BytePos, SourceFile, SourceMap, Span},
+    };
+    use std::{env, fs};
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_input_path_with_default_options() {
+        assert_eq!(
+            parse(&["main.cm"]),
+            Ok(Options {
+                input_path: "main.cm".into(),
+                error_format: ErrorFormat::Human,
+                color: ColorChoice::Auto,
+                language: Language::English,
+                error_limit: Some(DEFAULT_ERROR_LIMIT),
+                warning_config: WarningConfig::new(),
+                fix: false,
+                only_errors_from: None,
+                emit: None,
+                indent: Indent::Spaces(4),
+                extensions: Extensions::none(),
+                parse_trace: false,
+                opt_level: OptLevel::O0,
+                passes: None,
+                print_ir_after: Vec::new(),
+                remarks: Vec::new(),
+                pass_options: PassOptions::default(),
+                checks: Checks {
+                    bounds: false,
+                    division: true,
+                },
+                debug_info: false,
+                regalloc: RegAlloc::Linear,
+                command: Command::Check,
+                output: Output::Bytecode,
+                output_path: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_error_format() {
+        assert_eq!(
+            parse(&["--error-format=json", "main.cm"]),
+            Ok(Options {
+                input_path: "main.cm".into(),
+                error_format: ErrorFormat::Json,
+                color: ColorChoice::Auto,
+                language: Language::English,
+                error_limit: Some(DEFAULT_ERROR_LIMIT),
+                warning_config: WarningConfig::new(),
+                fix: false,
+                only_errors_from: None,
+                emit: None,
+                indent: Indent::Spaces(4),
+                extensions: Extensions::none(),
+                parse_trace: false,
+                opt_level: OptLevel::O0,
+                passes: None,
+                print_ir_after: Vec::new(),
+                remarks: Vec::new(),
+                pass_options: PassOptions::default(),
+                checks: Checks {
+                    bounds: false,
+                    division: true,
+                },
+                debug_info: false,
+                regalloc: RegAlloc::Linear,
+                command: Command::Check,
+                output: Output::Bytecode,
+                output_path: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_unknown_error_format() {
+        assert_eq!(
+            parse(&["--error-format=xml", "main.cm"]),
+            Err("unknown error format `xml`".into())
+        );
+    }
+
+    #[test]
+    fn parse_color_choice() {
+        let color = |arg: &str| parse(&[arg, "main.cm"]).map(|o| o.color);
+
+        assert_eq!(color("--color=always"), Ok(ColorChoice::Always));
+        assert_eq!(color("--color=never"), Ok(ColorChoice::Never));
+        assert_eq!(color("--color=auto"), Ok(ColorChoice::Auto));
+        assert_eq!(
+            color("--color=rainbow"),
+            Err("unknown color choice `rainbow`".into())
+        );
+    }
+
+    #[test]
+    fn parse_language() {
+        let language = |arg: &str| parse(&[arg, "main.cm"]).map(|o| o.language);
+
+        assert_eq!(language("--lang=pt"), Ok(Language::Portuguese));
+        assert_eq!(language("--lang=en"), Ok(Language::English));
+        assert_eq!(language("--lang=fr"), Err("unknown language `fr`".into()));
+    }
+
+    #[test]
+    fn parse_only_errors_from() {
+        let phase =
+            |arg: &str| parse(&[arg, "main.cm"]).map(|o| o.only_errors_from);
+
+        assert_eq!(phase("--only-errors-from=sema"), Ok(Some(Phase::Sema)));
+        assert_eq!(parse(&["main.cm"]).map(|o| o.only_errors_from), Ok(None));
+        assert_eq!(
+            phase("--only-errors-from=linking"),
+            Err("unknown phase `linking`".into())
+        );
+    }
+
+    #[test]
+    fn parse_emit() {
+        let emit = |arg: &str| parse(&[arg, "main.cm"]).map(|o| o.emit);
+
+        assert_eq!(emit("--emit=ast"), Ok(Some(Emit::Ast)));
+        assert_eq!(emit("--emit=source"), Ok(Some(Emit::Source)));
+        assert_eq!(emit("--emit=symbols"), Ok(Some(Emit::Symbols)));
+        assert_eq!(emit("--emit=ir"), Ok(Some(Emit::Ir)));
+        assert_eq!(emit("--emit=asm"), Ok(Some(Emit::Asm)));
+        assert_eq!(emit("--emit=llvm-ir"), Ok(Some(Emit::LlvmIr)));
+        assert_eq!(emit("--emit=cfg-dot"), Ok(Some(Emit::CfgDot)));
+        assert_eq!(emit("--emit=callgraph-dot"), Ok(Some(Emit::CallgraphDot)));
+        assert_eq!(emit("--emit=frame"), Ok(Some(Emit::Frame)));
+        assert_eq!(parse(&["main.cm"]).map(|o| o.emit), Ok(None));
+        assert_eq!(emit("--emit=exe"), Err("unknown emit kind `exe`".into()));
+        #[cfg(feature = "serde")]
+        assert_eq!(emit("--emit=ast-json"), Ok(Some(Emit::AstJson)));
+    }
+
+    #[test]
+    fn parse_indent() {
+        let indent = |arg: &str| parse(&[arg, "main.cm"]).map(|o| o.indent);
+
+        assert_eq!(indent("--indent=2"), Ok(Indent::Spaces(2)));
+        assert_eq!(indent("--indent=tab"), Ok(Indent::Tab));
+        assert_eq!(
+            indent("--indent=wide"),
+            Err("invalid indent `wide`".into())
+        );
+    }
+
+    #[test]
+    fn parse_extensions() {
+        let extensions =
+            |args: &[&str]| parse(args).map(|options| options.extensions);
+        let mut unary_minus = Extensions::none();
+        unary_minus.enable(Extension::UnaryMinus);
+
+        assert_eq!(
+            extensions(&["--extension=unary-minus", "main.cm"]),
+            Ok(unary_minus)
+        );
+        assert_eq!(
+            extensions(&["--extension=all", "main.cm"]),
+            Ok(Extensions::all())
+        );
+        assert_eq!(
+            extensions(&["--extension=goto", "main.cm"]),
+            Err("unknown extension `goto`".into())
+        );
+    }
+
+    #[test]
+    fn parse_fix_flag() {
+        assert_eq!(parse(&["main.cm"]).map(|o| o.fix), Ok(false));
+        assert_eq!(parse(&["--fix", "main.cm"]).map(|o| o.fix), Ok(true));
+    }
+
+    #[test]
+    fn parse_run_command() {
+        let run =
+            |args: &[&str]| parse(args).map(|o| (o.command, o.input_path));
+
+        assert_eq!(
+            run(&["run", "main.cm"]),
+            Ok((Command::Run, "main.cm".into()))
+        );
+        assert_eq!(run(&["main.cm"]), Ok((Command::Check, "main.cm".into())));
+        assert_eq!(
+            run(&["--fix", "run", "main.cm"]),
+            Err("unexpected argument `main.cm`".into())
+        );
+    }
+
+    #[test]
+    fn parse_build_command() {
+        let build =
+            |args: &[&str]| parse(args).map(|o| (o.command, o.output_path));
+
+        assert_eq!(build(&["build", "main.cm"]), Ok((Command::Build, None)));
+        assert_eq!(
+            build(&["build", "main.cm", "-o", "prog.cbc"]),
+            Ok((Command::Build, Some("prog.cbc".into())))
+        );
+        assert_eq!(
+            build(&["build", "main.cm", "-o"]),
+            Err("missing path after `-o`".into())
+        );
+        assert_eq!(
+            build(&["run", "-o", "prog.cbc", "main.cm"]),
+            Err("`-o` is only for `csub build`".into())
+        );
+        assert_eq!(
+            parse(&["build", "main.cm"]).map(|o| o.output),
+            Ok(Output::Bytecode)
+        );
+        assert_eq!(
+            parse(&["build", "--obj", "main.cm"]).map(|o| o.output),
+            Ok(Output::Object)
+        );
+        assert_eq!(
+            parse(&["--obj", "main.cm"]).map(|o| o.output),
+            Err("`--obj` is only for `csub build`".into())
+        );
+        assert_eq!(
+            parse(&["build", "--bin", "main.cm"]).map(|o| o.output),
+            Ok(Output::Executable)
+        );
+        assert_eq!(
+            parse(&["run", "--bin", "main.cm"]).map(|o| o.output),
+            Err("`--bin` is only for `csub build`".into())
+        );
+    }
+
+    #[test]
+    fn parse_debug_parse_trace_flag() {
+        let parse_trace = |args: &[&str]| parse(args).map(|o| o.parse_trace);
+
+        assert_eq!(parse_trace(&["main.cm"]), Ok(false));
+        assert_eq!(parse_trace(&["--debug-parse-trace", "main.cm"]), Ok(true));
+    }
+
+    #[test]
+    fn parse_optimization_options() {
+        let options = parse(&[
+            "-O2",
+            "--passes=dce,fold",
+            "--print-ir-after=fold",
+            "--print-ir-after=dce",
+            "--remarks=inline",
+            "--inline-threshold=5",
+            "main.cm",
+        ])
+        .unwrap();
+        assert_eq!(options.opt_level, OptLevel::O2);
+        assert_eq!(options.passes, Some(vec![Pass::Dce, Pass::Fold]));
+        assert_eq!(options.print_ir_after, [Pass::Fold, Pass::Dce]);
+        assert_eq!(options.remarks, [Pass::Inline]);
+        assert_eq!(options.pass_options.inline_threshold, 5);
+        assert!(!options.checks.bounds);
+        assert!(options.checks.division);
+        assert!(
+            parse(&["--bounds-checks", "main.cm"])
+                .unwrap()
+                .checks
+                .bounds
+        );
+        assert!(!options.debug_info);
+        assert!(parse(&["-g", "main.cm"]).unwrap().debug_info);
+        assert_eq!(options.regalloc, RegAlloc::Linear);
+        assert_eq!(
+            parse(&["--regalloc=graph", "main.cm"]).unwrap().regalloc,
+            RegAlloc::Graph
+        );
+
+        assert_eq!(
+            parse(&["-O3", "main.cm"]),
+            Err("unknown optimization level `3`".into())
+        );
+        assert_eq!(
+            parse(&["--passes=fold,vectorize", "main.cm"]),
+            Err("unknown pass `vectorize`".into())
+        );
+        assert_eq!(
+            parse(&["--regalloc=greedy", "main.cm"]),
+            Err("unknown register allocator `greedy`".into())
+        );
+        assert_eq!(
+            parse(&["--inline-threshold=many", "main.cm"]),
+            Err("invalid inline threshold `many`".into())
+        );
+    }
+
+    #[test]
+    fn parse_unknown_option() {
+        assert_eq!(
+            parse(&["--frobnicate", "main.cm"]),
+            Err("unknown option `--frobnicate`".into())
+        );
+    }
+
+    #[test]
+    fn parse_missing_input_path() {
+        assert_eq!(parse(&[]), Err("no input file".into()));
+    }
+
+    #[test]
+    fn parse_more_than_one_input_path() {
+        assert_eq!(
+            parse(&["a.cm", "b.cm"]),
+            Err("unexpected argument `b.cm`".into())
+        );
+    }
+
+    #[test]
+    fn parse_error_limit() {
+        assert_eq!(
+            parse(&["--error-limit=3", "main.cm"]).map(|opts| opts.error_limit),
+            Ok(Some(3))
+        );
+    }
+
+    #[test]
+    fn parse_zero_error_limit_as_no_limit() {
+        assert_eq!(
+            parse(&["--error-limit=0", "main.cm"]).map(|opts| opts.error_limit),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn parse_invalid_error_limit() {
+        assert_eq!(
+            parse(&["--error-limit=many", "main.cm"]),
+            Err("invalid error limit `many`".into())
+        );
+    }
+
+    #[test]
+    fn parse_warning_flags() {
+        let options = parse(&[
+            "-W",
+            "shadowing",
+            "-Aunused-variable",
+            "-W",
+            "error",
+            "main.cm",
+        ])
+        .unwrap();
+
+        let mut expected = WarningConfig::new();
+        expected.set_level(Lint::Shadowing, LintLevel::Warn);
+        expected.set_level(Lint::UnusedVariable, LintLevel::Allow);
+        expected.set_warnings_as_errors(true);
+
+        assert_eq!(options.warning_config, expected);
+    }
+
+    #[test]
+    fn parse_deny_warning_flag() {
+        let options = parse(&["-D", "unused-variable", "main.cm"]).unwrap();
+
+        assert_eq!(
+            options.warning_config.level(Lint::UnusedVariable),
+            LintLevel::Deny
+        );
+    }
+
+    #[test]
+    fn parse_unknown_warning() {
+        assert_eq!(
+            parse(&["-A", "everything", "main.cm"]),
+            Err("unknown warning `everything`".into())
+        );
+    }
+
+    #[test]
+    fn parse_warning_flag_without_name() {
+        assert_eq!(
+            parse(&["-W"]),
+            Err("missing warning name after `-W`".into())
+        );
+    }
+
+    #[test]
+    fn missing_input_file_is_a_fatal_error() {
+        let mut source_map = SourceMap::new();
+        let mut diag_bag = DiagBag::new();
+
+        compile(
+            "this/file/does/not/exist.cm",
+            Extensions::none(),
+            &mut source_map,
+            &mut diag_bag,
+        );
+        let diags: Vec<_> = diag_bag.iter().collect();
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Fatal);
+        assert!(matches!(diags[0].diag, Diag::CannotReadFile { .. }));
+        assert!(diag_bag.should_abort());
+        assert!(source_map.files().is_empty());
+    }
+
+    #[test]
+    fn programs_arent_checked_past_the_error_limit() {
+        let source_file = SourceFile::new(
+            "main.cm".into(),
+            "int x void main(void) { output(y); }".into(),
+        );
+        let mut diag_bag = DiagBag::with_error_limit(Some(1));
+
+        let program =
+            compile_file(&source_file, Extensions::none(), &mut diag_bag);
+
+        assert!(program.is_some());
+        assert!(diag_bag.should_abort());
+        let diags: Vec<_> = diag_bag.iter().map(|d| &d.diag).collect();
+        assert_eq!(diags.len(), 2, "{:?}", diags);
+        assert_eq!(diags[0].phase(), Some(Phase::Parse));
+        assert_eq!(diags[1], &Diag::TooManyErrors { limit: 1 });
+    }
+
+    #[test]
+    fn fixes_are_written_and_fixed_diagnostics_dropped() {
+        let path =
+            env::temp_dir().join(format!("csub-fix-{}.cm", std::process::id()));
+        let path_name = path.display().to_string();
+        fs::write(&path, "x @\n").unwrap();
+        let mut source_map = SourceMap::new();
+        source_map.add_file(path_name.clone(), "x @\n".into());
+        let unknown_char = |pos| Diag::UnknownCharacter {
+            pos: BytePos(pos),
+            ch: '@',
+        };
+        let mut diag_bag = DiagBag::new();
+        Diagnostic::builder(unknown_char(2))
+            .with_suggestion(Suggestion::removal(Span::with_usizes(1, 3), ""))
+            .emit(&mut diag_bag);
+        diag_bag.push(unknown_char(0));
+
+        let applied = apply_fixes(&source_map, &mut diag_bag);
+
+        let fixed_source = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(applied, 1);
+        assert_eq!(fixed_source, "x\n");
+        assert_eq!(
+            diag_bag.iter().map(|d| d.diag.clone()).collect::<Vec<_>>(),
+            vec![unknown_char(0)]
+        );
+    }
+
+    #[test]
+    fn what_fixes_leave_is_compiled_again() {
+        let dir = env::temp_dir()
+            .join(format!("csub-fix-again-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("prog.cm");
+        let obj = dir.join("prog.o");
+        let build_fixed = |source: &str| {
+            fs::write(&input, source).unwrap();
+            let _ = fs::remove_file(&obj);
+            let code = main([
+                "build".to_string(),
+                "--fix".to_string(),
+                "-o".to_string(),
+                obj.display().to_string(),
+                input.display().to_string(),
+            ]);
+            (code, fs::read_to_string(&input).unwrap(), obj.exists())
+        };
+
+        let fixed = build_fixed("void main() { output(42); }\n");
+        let still_wrong = build_fixed("void main() { output(x); }\n");
+
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            fixed,
+            (0, "void main(void) { output(42); }\n".to_string(), true)
+        );
+        assert_eq!(
+            still_wrong,
+            (1, "void main(void) { output(x); }\n".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn build_bin_links_an_executable_next_to_the_input() {
+        let dir = env::temp_dir()
+            .join(format!("csub-build-bin-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("prog.cm");
+        fs::write(&input, "void main(void) { output(6 * 7); }\n").unwrap();
+        let exe = dir.join("prog");
+
+        let code = main([
+            "build".to_string(),
+            "--bin".to_string(),
+            input.display().to_string(),
+        ]);
+
+        // Where there's no `cc`, it can only say so.
+        if code == 0 {
+            let output = std::process::Command::new(&exe).output().unwrap();
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), "42\n");
+        } else {
+            assert!(!exe.exists());
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn what_the_linker_says_is_the_reason_it_failed() {
+        let path = env::temp_dir()
+            .join(format!("csub-link-failed-{}", std::process::id()))
+            .display()
+            .to_string();
+
+        let Err(Diag::LinkFailed {
+            path: failed,
+            reason,
+        }) = link(b"not an object", &path)
+        else {
+            panic!("`{}` was linked", path);
+        };
+
+        assert_eq!(failed, path);
+        assert!(
+            reason.contains("` failed with exit status: 1:\n")
+                || reason.starts_with("couldn't run `"),
+            "{}",
+            reason
+        );
+        assert!(!std::path::Path::new(&path).exists());
+    }
+}
@@ -0,0 +1,391 @@
+//! A dump of the abstract syntax tree for debugging, shown with `--emit=ast`.
+//!
+//! Every node goes on a line of its own, indented under its parent, with its
+//! kind, its literal values and where it is in the source:
+//!
+//! ```text
+//! FunDecl int main @ 1:1-3:2
+//!   Block @ 1:16-3:2
+//!     Return @ 2:5-2:14
+//!       Number 0 @ 2:12-2:13
+//! ```
+//!
+//! Children that play different parts in their parent, e.g. the condition
+//! and the statement of a `while`, are prefixed with the part they play.
+//!
+//! With the `serde` feature, the tree can also be dumped as JSON for tools
+//! outside the compiler. That's the tree as it's stored: the declarations,
+//! and the arenas of nodes they refer to by index, with spans as byte
+//! positions.
+
+use crate::{
+    ast::{
+        BlockId, Decl, ExprId, ExprKind, Nodes, Param, Program, StmtId,
+        StmtKind, StructDecl, VarDecl,
+    },
+    emitter::SpanLocs,
+    printer::{const_prefix, print_expr},
+    scanner::quote_literal,
+    source_map::{SourceFile, Span},
+};
+use std::fmt::Write;
+
+/// Dumps the tree of `program`, parsed from `source_file`.
+pub(crate) fn dump_program(
+    program: &Program,
+    source_file: &SourceFile,
+) -> String {
+    let mut dumper = Dumper {
+        source_file,
+        nodes: &program.nodes,
+        out: String::new(),
+        depth: 0,
+    };
+    for decl in &program.decls {
+        dumper.dump_decl(decl);
+    }
+    dumper.out
+}
+
+/// Dumps `program` as JSON.
+#[cfg(feature = "serde")]
+pub(crate) fn dump_program_json(program: &Program) -> String {
+    serde_json::to_string_pretty(program)
+        .expect("the syntax tree has nothing JSON can't represent")
+}
+
+struct Dumper<'src> {
+    source_file: &'src SourceFile,
+    nodes: &'src Nodes,
+    out: String,
+    depth: usize,
+}
+
+impl Dumper<'_> {
+    /// Writes the line of a node, with the `role` it plays in its parent.
+    fn node(&mut self, role: Option<&str>, text: &str, span: Span) {
+        let locs = SpanLocs::resolve(self.source_file, span);
+        let _ = writeln!(
+            self.out,
+            "{:indent$}{}{} @ {}:{}-{}:{}",
+            "",
+            role.map(|role| format!("{}: ", role)).unwrap_or_default(),
+            text,
+            locs.line_start,
+            locs.column_start,
+            locs.line_end,
+            locs.column_end,
+            indent = self.depth * 2,
+        );
+    }
+
+    /// Dumps the children `dump` writes one level deeper.
+    fn children(&mut self, dump: impl FnOnce(&mut Self)) {
+        self.depth += 1;
+        dump(self);
+        self.depth -= 1;
+    }
+
+    fn dump_decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Var(var_decl) => self.dump_var_decl(var_decl),
+            Decl::Fun(fun_decl) => {
+                let text = format!(
+                    "FunDecl {}{} {}{}",
+                    const_prefix(fun_decl.return_is_const),
+                    fun_decl.return_ty,
+                    "*".repeat(fun_decl.return_pointers as usize),
+                    fun_decl.name.name
+                );
+                self.node(None, &text, fun_decl.span);
+                self.children(|dumper| {
+                    for param in &fun_decl.params {
+                        dumper.dump_param(param);
+                    }
+                    dumper.dump_block(None, fun_decl.body);
+                });
+            }
+            Decl::Struct(struct_decl) => self.dump_struct_decl(struct_decl),
+        }
+    }
+
+    fn dump_struct_decl(&mut self, struct_decl: &StructDecl) {
+        let text = format!("StructDecl {}", struct_decl.name.name);
+        self.node(None, &text, struct_decl.span);
+        self.children(|dumper| {
+            for field in &struct_decl.fields {
+                dumper.dump_var_decl(field);
+            }
+        });
+    }
+
+    fn dump_var_decl(&mut self, var_decl: &VarDecl) {
+        let mut text = format!(
+            "VarDecl {}{} {}{}",
+            const_prefix(var_decl.is_const),
+            var_decl.ty,
+            "*".repeat(var_decl.pointers as usize),
+            var_decl.name.name
+        );
+        for &size in &var_decl.sizes {
+            let _ = write!(text, "[{}]", print_expr(self.nodes, size));
+        }
+        self.node(None, &text, var_decl.span);
+        if let Some(init) = var_decl.init {
+            self.children(|dumper| dumper.dump_expr(Some("init"), init));
+        }
+    }
+
+    fn dump_param(&mut self, param: &Param) {
+        let mut text = format!(
+            "Param {}{} {}{}{}",
+            const_prefix(param.is_const),
+            param.ty,
+            "*".repeat(param.pointers as usize),
+            param.name.name,
+            if param.is_array { "[]" } else { "" }
+        );
+        for &size in &param.sizes {
+            let _ = write!(text, "[{}]", print_expr(self.nodes, size));
+        }
+        self.node(None, &text, param.span);
+    }
+
+    fn dump_block(&mut self, role: Option<&str>, block: BlockId) {
+        let block = &self.nodes[block];
+        self.node(role, "Block", block.span);
+        self.children(|dumper| {
+            for var_decl in &block.decls {
+                dumper.dump_var_decl(var_decl);
+            }
+            for &stmt in &block.stmts {
+                dumper.dump_stmt(None, stmt);
+            }
+        });
+    }
+
+    fn dump_stmt(&mut self, role: Option<&str>, stmt: StmtId) {
+        let stmt = &self.nodes[stmt];
+        match stmt.kind {
+            StmtKind::Expr(expr) => {
+                self.node(role, "ExprStmt", stmt.span);
+                self.children(|dumper| dumper.dump_expr(None, expr));
+            }
+            StmtKind::Empty => self.node(role, "Empty", stmt.span),
+            StmtKind::Switch { cond, ref cases } => {
+                self.node(role, "Switch", stmt.span);
+                self.children(|dumper| {
+                    dumper.dump_expr(Some("cond"), cond);
+                    for case in cases {
+                        let text = match case.value {
+                            Some(_) => "Case",
+                            None => "Default",
+                        };
+                        dumper.node(None, text, case.label_span);
+                        dumper.children(|dumper| {
+                            if let Some(value) = case.value {
+                                dumper.dump_expr(Some("value"), value);
+                            }
+                            for &stmt in &case.stmts {
+                                dumper.dump_stmt(None, stmt);
+                            }
+                        });
+                    }
+                });
+            }
+            StmtKind::Break => self.node(role, "Break", stmt.span),
+            StmtKind::Continue => self.node(role, "Continue", stmt.span),
+            StmtKind::Block(block) => self.dump_block(role, block),
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.node(role, "If", stmt.span);
+                self.children(|dumper| {
+                    dumper.dump_expr(Some("cond"), cond);
+                    dumper.dump_stmt(Some("then"), then_branch);
+                    if let Some(else_branch) = else_branch {
+                        dumper.dump_stmt(Some("else"), else_branch);
+                    }
+                });
+            }
+            StmtKind::While { cond, body } => {
+                self.node(role, "While", stmt.span);
+                self.children(|dumper| {
+                    dumper.dump_expr(Some("cond"), cond);
+                    dumper.dump_stmt(Some("body"), body);
+                });
+            }
+            StmtKind::DoWhile { body, cond } => {
+                self.node(role, "DoWhile", stmt.span);
+                self.children(|dumper| {
+                    dumper.dump_stmt(Some("body"), body);
+                    dumper.dump_expr(Some("cond"), cond);
+                });
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                self.node(role, "For", stmt.span);
+                self.children(|dumper| {
+                    for (role, expr) in
+                        [("init", init), ("cond", cond), ("step", step)]
+                    {
+                        if let Some(expr) = expr {
+                            dumper.dump_expr(Some(role), expr);
+                        }
+                    }
+                    dumper.dump_stmt(Some("body"), body);
+                });
+            }
+            StmtKind::Return(value) => {
+                self.node(role, "Return", stmt.span);
+                if let Some(value) = value {
+                    self.children(|dumper| dumper.dump_expr(None, value));
+                }
+            }
+        }
+    }
+
+    fn dump_expr(&mut self, role: Option<&str>, expr: ExprId) {
+        let expr = &self.nodes[expr];
+        match &expr.kind {
+            ExprKind::Number(value) => {
+                self.node(role, &format!("Number {}", value), expr.span)
+            }
+            ExprKind::Char(value) => {
+                let literal =
+                    quote_literal(&char::from(*value).to_string(), '\'');
+                self.node(role, &format!("Char {}", literal), expr.span)
+            }
+            ExprKind::Str(text) => {
+                let literal = quote_literal(text, '"');
+                self.node(role, &format!("String {}", literal), expr.span)
+            }
+            ExprKind::Var(ident) => {
+                self.node(role, &format!("Var {}", ident.name), expr.span)
+            }
+            ExprKind::Index { array, index } => {
+                self.node(role, "Index", expr.span);
+                self.children(|dumper| {
+                    dumper.dump_expr(Some("array"), *array);
+                    dumper.dump_expr(Some("index"), *index);
+                });
+            }
+            ExprKind::Member { base, field } => {
+                self.node(role, &format!("Member {}", field.name), expr.span);
+                self.children(|dumper| dumper.dump_expr(None, *base));
+            }
+            ExprKind::Call { callee, args } => {
+                self.node(role, &format!("Call {}", callee.name), expr.span);
+                self.children(|dumper| {
+                    for &arg in args {
+                        dumper.dump_expr(None, arg);
+                    }
+                });
+            }
+            ExprKind::Assign { target, value } => {
+                self.node(role, "Assign", expr.span);
+                self.children(|dumper| {
+                    dumper.dump_expr(Some("target"), *target);
+                    dumper.dump_expr(Some("value"), *value);
+                });
+            }
+            ExprKind::Unary { op, operand } => {
+                self.node(role, &format!("Unary {}", op.as_str()), expr.span);
+                self.children(|dumper| dumper.dump_expr(None, *operand));
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                self.node(role, &format!("Binary {}", op.as_str()), expr.span);
+                self.children(|dumper| {
+                    dumper.dump_expr(Some("lhs"), *lhs);
+                    dumper.dump_expr(Some("rhs"), *rhs);
+                });
+            }
+            ExprKind::Conditional {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                self.node(role, "Conditional", expr.span);
+                self.children(|dumper| {
+                    dumper.dump_expr(Some("cond"), *cond);
+                    dumper.dump_expr(Some("then"), *then_expr);
+                    dumper.dump_expr(Some("else"), *else_expr);
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dump_program;
+    use crate::{
+        extensions::Extensions, parser::ParseResult, test_support::parse_source,
+    };
+
+    #[test]
+    fn dump_shows_kinds_values_and_locations() {
+        let source = "int a[4];\n\
+                      int f(int b[]) {\n\
+                      \x20   while (b[0] < 2) b[0] = f(b) + 1;\n\
+                      \x20   if (a[1]) return; else ;\n\
+                      }\n";
+        let (source_file, ParseResult { program, .. }) =
+            parse_source(source, Extensions::none());
+
+        assert_eq!(
+            dump_program(&program, &source_file),
+            "VarDecl int a[4] @ 1:1-1:10\n\
+             FunDecl int f @ 2:1-5:2\n\
+             \x20 Param int b[] @ 2:7-2:14\n\
+             \x20 Block @ 2:16-5:2\n\
+             \x20   While @ 3:5-3:38\n\
+             \x20     cond: Binary < @ 3:12-3:20\n\
+             \x20       lhs: Index @ 3:12-3:16\n\
+             \x20         array: Var b @ 3:12-3:13\n\
+             \x20         index: Number 0 @ 3:14-3:15\n\
+             \x20       rhs: Number 2 @ 3:19-3:20\n\
+             \x20     body: ExprStmt @ 3:22-3:38\n\
+             \x20       Assign @ 3:22-3:37\n\
+             \x20         target: Index @ 3:22-3:26\n\
+             \x20           array: Var b @ 3:22-3:23\n\
+             \x20           index: Number 0 @ 3:24-3:25\n\
+             \x20         value: Binary + @ 3:29-3:37\n\
+             \x20           lhs: Call f @ 3:29-3:33\n\
+             \x20             Var b @ 3:31-3:32\n\
+             \x20           rhs: Number 1 @ 3:36-3:37\n\
+             \x20   If @ 4:5-4:29\n\
+             \x20     cond: Index @ 4:9-4:13\n\
+             \x20       array: Var a @ 4:9-4:10\n\
+             \x20       index: Number 1 @ 4:11-4:12\n\
+             \x20     then: Return @ 4:15-4:22\n\
+             \x20     else: Empty @ 4:28-4:29\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_dump_round_trips() {
+        use super::dump_program_json;
+        use crate::ast::Program;
+
+        let source = "int a[4];\n\
+                      int f(int b[]) {\n\
+                      \x20   while (b[0] < -2) { b[0] = f(b) + 1; }\n\
+                      \x20   if (a[1]) return; else ;\n\
+                      }\n";
+        let (_, ParseResult { program, .. }) =
+            parse_source(source, Extensions::all());
+
+        let json = dump_program_json(&program);
+        let parsed: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, program);
+        assert!(json.contains("\"Fun\""), "{}", json);
+    }
+}
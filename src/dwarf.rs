@@ -0,0 +1,333 @@
+//! The DWARF debug info of an object, of version 4, for `assembler` to add
+//! what the `.loc` directives of the code say to what it assembles, so a
+//! debugger knows which line of the source each instruction is of.
+//!
+//! It's only what's needed for a line table: a compile unit, in
+//! `.debug_info`, of the abbreviation in `.debug_abbrev`, which says the
+//! code of `.text` is of the source file, and its line program, in
+//! `.debug_line`, which goes through the rows of the table in order. The
+//! addresses and the offsets into the other sections are left to the
+//! linker, as relocations.
+
+use crate::elf::{Object, RelocKind, Relocation, Section, Target};
+
+const DW_TAG_COMPILE_UNIT: u64 = 0x11;
+const DW_CHILDREN_NO: u8 = 0;
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_STMT_LIST: u64 = 0x10;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_LANGUAGE: u64 = 0x13;
+const DW_AT_PRODUCER: u64 = 0x25;
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_SEC_OFFSET: u64 = 0x17;
+const DW_LANG_C89: u16 = 0x0001;
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+
+/// The operands of each standard opcode, from 1, as version 4 has them.
+const OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+/// Adds to `object` the debug info of its `.text`, of the source `file`,
+/// where each row is an offset into it and the line the code from there is
+/// of, in order.
+pub(crate) fn add_debug_info(
+    object: &mut Object,
+    file: &str,
+    rows: &[(u64, usize)],
+) {
+    let text_size = object.bytes(Section::Text).len() as u64;
+
+    let mut abbrev = Vec::new();
+    uleb(&mut abbrev, 1);
+    uleb(&mut abbrev, DW_TAG_COMPILE_UNIT);
+    abbrev.push(DW_CHILDREN_NO);
+    for (attribute, form) in [
+        (DW_AT_NAME, DW_FORM_STRING),
+        (DW_AT_PRODUCER, DW_FORM_STRING),
+        (DW_AT_LANGUAGE, DW_FORM_DATA2),
+        (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET),
+        (DW_AT_LOW_PC, DW_FORM_ADDR),
+        (DW_AT_HIGH_PC, DW_FORM_DATA8),
+    ] {
+        uleb(&mut abbrev, attribute);
+        uleb(&mut abbrev, form);
+    }
+    abbrev.extend_from_slice(&[0, 0, 0]);
+
+    let mut relocations = Vec::new();
+    let mut info = Vec::new();
+    // Its length, after the 4 bytes of it, is filled in at the end.
+    info.extend_from_slice(&0u32.to_le_bytes());
+    info.extend_from_slice(&4u16.to_le_bytes());
+    relocations.push(absolute(
+        Section::DebugInfo,
+        &mut info,
+        Section::DebugAbbrev,
+        4,
+    ));
+    info.push(8);
+    uleb(&mut info, 1);
+    info.extend_from_slice(file.as_bytes());
+    info.push(0);
+    info.extend_from_slice(b"csub\0");
+    info.extend_from_slice(&DW_LANG_C89.to_le_bytes());
+    relocations.push(absolute(
+        Section::DebugInfo,
+        &mut info,
+        Section::DebugLine,
+        4,
+    ));
+    relocations.push(absolute(Section::DebugInfo, &mut info, Section::Text, 8));
+    // The high address is its distance from the low one.
+    info.extend_from_slice(&text_size.to_le_bytes());
+    let length = info.len() as u32 - 4;
+    info[..4].copy_from_slice(&length.to_le_bytes());
+
+    let mut line = Vec::new();
+    line.extend_from_slice(&0u32.to_le_bytes());
+    line.extend_from_slice(&4u16.to_le_bytes());
+    // The length of the header, after the 4 bytes of it, filled in once
+    // it's written.
+    line.extend_from_slice(&0u32.to_le_bytes());
+    let header_start = line.len();
+    // Instructions of at least 1 byte, each an operation, every row a
+    // statement, and special opcodes, which aren't used, of lines from -5
+    // to 8, after the standard ones.
+    line.extend_from_slice(&[1, 1, 1, -5i8 as u8, 14]);
+    line.push(OPCODE_LENGTHS.len() as u8 + 1);
+    line.extend_from_slice(&OPCODE_LENGTHS);
+    // No include directories, and the only file, in the directory it's
+    // compiled in, of no known time nor size.
+    line.push(0);
+    line.extend_from_slice(file.as_bytes());
+    line.extend_from_slice(&[0, 0, 0, 0, 0]);
+    let header_length = (line.len() - header_start) as u32;
+    line[6..10].copy_from_slice(&header_length.to_le_bytes());
+
+    line.extend_from_slice(&[0, 9, DW_LNE_SET_ADDRESS]);
+    relocations.push(absolute(Section::DebugLine, &mut line, Section::Text, 8));
+    let (mut address, mut current) = (0, 1);
+    for &(offset, row) in rows {
+        if row != current {
+            line.push(DW_LNS_ADVANCE_LINE);
+            sleb(&mut line, row as i64 - current as i64);
+            current = row;
+        }
+        if offset != address {
+            line.push(DW_LNS_ADVANCE_PC);
+            uleb(&mut line, offset - address);
+            address = offset;
+        }
+        line.push(DW_LNS_COPY);
+    }
+    if text_size != address {
+        line.push(DW_LNS_ADVANCE_PC);
+        uleb(&mut line, text_size - address);
+    }
+    line.extend_from_slice(&[0, 1, DW_LNE_END_SEQUENCE]);
+    let length = line.len() as u32 - 4;
+    line[..4].copy_from_slice(&length.to_le_bytes());
+
+    object.sections.insert(Section::DebugInfo, (info, 1));
+    object.sections.insert(Section::DebugAbbrev, (abbrev, 1));
+    object.sections.insert(Section::DebugLine, (line, 1));
+    object.relocations.extend(relocations);
+}
+
+/// Writes `size` bytes to `bytes`, of `section`, for the linker to fill in
+/// with the address of the start of `target`, returning the relocation.
+fn absolute(
+    section: Section,
+    bytes: &mut Vec<u8>,
+    target: Section,
+    size: usize,
+) -> Relocation {
+    let offset = bytes.len() as u64;
+    bytes.resize(bytes.len() + size, 0);
+    Relocation {
+        section,
+        offset,
+        target: Target::Section(target),
+        kind: if size == 8 {
+            RelocKind::Abs64
+        } else {
+            RelocKind::Abs32
+        },
+        addend: 0,
+    }
+}
+
+/// Writes `value` as an unsigned LEB128, 7 bits a byte, the high bit set
+/// on all but the last.
+fn uleb(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Writes `value` as a signed LEB128, ending once what's left is only the
+/// sign, which the last byte has in its bit 6.
+fn sleb(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0)
+        {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_debug_info, sleb, uleb};
+    use crate::elf::{Object, RelocKind, Section, Target};
+
+    /// The rows of the line program of `line`, as offsets into `.text`
+    /// and lines, with where its sequence ends.
+    fn decode_rows(line: &[u8]) -> (Vec<(u64, i64)>, u64) {
+        fn leb(bytes: &[u8], at: &mut usize, signed: bool) -> i64 {
+            let (mut value, mut shift) = (0i64, 0);
+            loop {
+                let byte = bytes[*at];
+                *at += 1;
+                value |= i64::from(byte & 0x7f) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    if signed && byte & 0x40 != 0 {
+                        value |= -1 << shift;
+                    }
+                    return value;
+                }
+            }
+        }
+
+        let header_length =
+            u32::from_le_bytes(line[6..10].try_into().unwrap()) as usize;
+        let mut at = 10 + header_length;
+        let (mut address, mut row) = (0, 1);
+        let mut rows = Vec::new();
+        loop {
+            let opcode = line[at];
+            at += 1;
+            match opcode {
+                0 => {
+                    let length = leb(line, &mut at, false) as usize;
+                    match line[at] {
+                        // The address, which the linker fills in.
+                        2 => {}
+                        1 => return (rows, address),
+                        other => panic!("extended opcode {}", other),
+                    }
+                    at += length;
+                }
+                1 => rows.push((address, row)),
+                2 => address += leb(line, &mut at, false) as u64,
+                3 => row += leb(line, &mut at, true),
+                other => panic!("opcode {}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn numbers_are_written_in_leb128() {
+        let mut bytes = Vec::new();
+        uleb(&mut bytes, 2);
+        uleb(&mut bytes, 624485);
+        sleb(&mut bytes, -1);
+        sleb(&mut bytes, 63);
+        sleb(&mut bytes, 64);
+        sleb(&mut bytes, -123456);
+
+        assert_eq!(
+            bytes,
+            [
+                0x02, 0xe5, 0x8e, 0x26, 0x7f, 0x3f, 0xc0, 0x00, 0xc0, 0xbb,
+                0x78
+            ]
+        );
+    }
+
+    #[test]
+    fn the_line_program_goes_through_the_rows() {
+        let mut object = Object::default();
+        object.sections.insert(Section::Text, (vec![0x90; 40], 16));
+        add_debug_info(
+            &mut object,
+            "main.cm",
+            &[(0, 3), (4, 3), (10, 5), (10, 4), (30, 12)],
+        );
+
+        let line = object.bytes(Section::DebugLine);
+        assert_eq!(
+            decode_rows(line),
+            (vec![(0, 3), (4, 3), (10, 5), (10, 4), (30, 12)], 40)
+        );
+        // The name of the file, after the opcode lengths and the empty
+        // list of directories.
+        assert_eq!(&line[28..37], b"\0main.cm\0");
+
+        let info = object.bytes(Section::DebugInfo);
+        assert_eq!(
+            u32::from_le_bytes(info[..4].try_into().unwrap()) as usize,
+            info.len() - 4
+        );
+        let relocations: Vec<_> = object
+            .relocations
+            .iter()
+            .map(|relocation| {
+                (
+                    relocation.section,
+                    relocation.offset,
+                    relocation.target,
+                    relocation.kind,
+                )
+            })
+            .collect();
+        assert_eq!(
+            relocations,
+            [
+                (
+                    Section::DebugInfo,
+                    6,
+                    Target::Section(Section::DebugAbbrev),
+                    RelocKind::Abs32
+                ),
+                (
+                    Section::DebugInfo,
+                    27,
+                    Target::Section(Section::DebugLine),
+                    RelocKind::Abs32
+                ),
+                (
+                    Section::DebugInfo,
+                    31,
+                    Target::Section(Section::Text),
+                    RelocKind::Abs64
+                ),
+                (
+                    Section::DebugLine,
+                    44,
+                    Target::Section(Section::Text),
+                    RelocKind::Abs64
+                ),
+            ]
+        );
+    }
+}
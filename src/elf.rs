@@ -0,0 +1,561 @@
+//! A writer of ELF relocatable object files for x86-64 Linux, the `.o`
+//! files a linker takes, for `csub build --obj` to write what `assembler`
+//! makes of the code of the x86-64 backend without running an assembler.
+//!
+//! An object has three sections of contents, `.text`, `.data` and
+//! `.rodata`, the symbols defined in them or used from elsewhere, e.g. from
+//! libc, and the relocations the linker fills in, of where the code refers
+//! to what isn't in its own section or isn't in the object at all. Each is
+//! written as the System V ABI for x86-64 says, after the header, with the
+//! symbol and string tables, and the table of the sections at the end. An
+//! empty `.note.GNU-stack` says the stack needn't be executable.
+//!
+//! What starts out as 0 is in `.bss`, which only says how large it is, as
+//! its bytes take no room in the file. It and the DWARF sections of debug
+//! info, which `dwarf` makes, are only written if they have contents, so
+//! the index of a section depends on which are.
+
+use std::collections::HashMap;
+
+/// A section of an object with contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Section {
+    Text,
+    Data,
+    Rodata,
+    /// What starts out as 0, whose bytes are never written.
+    Bss,
+    DebugInfo,
+    DebugAbbrev,
+    DebugLine,
+}
+
+impl Section {
+    pub(crate) const ALL: [Section; 7] = [
+        Section::Text,
+        Section::Data,
+        Section::Rodata,
+        Section::Bss,
+        Section::DebugInfo,
+        Section::DebugAbbrev,
+        Section::DebugLine,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Section::Text => ".text",
+            Section::Data => ".data",
+            Section::Rodata => ".rodata",
+            Section::Bss => ".bss",
+            Section::DebugInfo => ".debug_info",
+            Section::DebugAbbrev => ".debug_abbrev",
+            Section::DebugLine => ".debug_line",
+        }
+    }
+
+    fn flags(self) -> u64 {
+        match self {
+            Section::Text => SHF_ALLOC | SHF_EXECINSTR,
+            Section::Data | Section::Bss => SHF_ALLOC | SHF_WRITE,
+            Section::Rodata => SHF_ALLOC,
+            Section::DebugInfo | Section::DebugAbbrev | Section::DebugLine => 0,
+        }
+    }
+
+    /// Whether it's written even if it's empty.
+    fn always_written(self) -> bool {
+        match self {
+            Section::Text | Section::Data | Section::Rodata => true,
+            Section::Bss
+            | Section::DebugInfo
+            | Section::DebugAbbrev
+            | Section::DebugLine => false,
+        }
+    }
+}
+
+/// What a symbol names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SymbolKind {
+    Func,
+    Object,
+    NoType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Symbol {
+    pub(crate) name: String,
+    /// The section it's in, and where, unless it's defined elsewhere.
+    pub(crate) def: Option<(Section, u64)>,
+    /// Whether other objects see it.
+    pub(crate) global: bool,
+    pub(crate) kind: SymbolKind,
+}
+
+/// What a relocation refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Target {
+    /// The symbol at this index of `Object::symbols`.
+    Symbol(usize),
+    /// The start of a section, for what's local to the object but in
+    /// another section, e.g. a string.
+    Section(Section),
+}
+
+/// How the linker fills in a relocation, each 32 bits relative to where
+/// it is, but for the absolute ones of the debug info.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RelocKind {
+    /// `R_X86_64_PC32`: the address of the target.
+    Pc32,
+    /// `R_X86_64_PLT32`: the address of the target, or of its entry in the
+    /// PLT if it's in a shared library, for calls.
+    Plt32,
+    /// `R_X86_64_GOTPCREL`: the address of the entry of the target in the
+    /// GOT, which holds its address.
+    GotPcRel,
+    /// `R_X86_64_64`: the address of the target, in 64 bits.
+    Abs64,
+    /// `R_X86_64_32`: the address of the target, in 32 bits, for offsets
+    /// into the sections of the debug info.
+    Abs32,
+}
+
+impl RelocKind {
+    fn number(self) -> u64 {
+        match self {
+            RelocKind::Pc32 => 2,
+            RelocKind::Plt32 => 4,
+            RelocKind::GotPcRel => 9,
+            RelocKind::Abs64 => 1,
+            RelocKind::Abs32 => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Relocation {
+    /// The section the bits to fill in are in, and where.
+    pub(crate) section: Section,
+    pub(crate) offset: u64,
+    pub(crate) target: Target,
+    pub(crate) kind: RelocKind,
+    /// What's added to the address, which is relative to `offset` if the
+    /// kind is.
+    pub(crate) addend: i64,
+}
+
+/// The contents of an object file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct Object {
+    /// The bytes of each section, and what they're aligned to.
+    pub(crate) sections: HashMap<Section, (Vec<u8>, u64)>,
+    pub(crate) symbols: Vec<Symbol>,
+    pub(crate) relocations: Vec<Relocation>,
+}
+
+impl Object {
+    /// The bytes of `section`.
+    pub(crate) fn bytes(&self, section: Section) -> &[u8] {
+        self.sections
+            .get(&section)
+            .map_or(&[], |(bytes, _)| bytes.as_slice())
+    }
+
+    fn align(&self, section: Section) -> u64 {
+        self.sections.get(&section).map_or(1, |&(_, align)| align)
+    }
+
+    /// The sections written, in the order they are, from index 1.
+    fn written(&self) -> Vec<Section> {
+        Section::ALL
+            .into_iter()
+            .filter(|&section| {
+                section.always_written() || !self.bytes(section).is_empty()
+            })
+            .collect()
+    }
+}
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u64 = 1;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+const SHF_INFO_LINK: u64 = 0x40;
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+
+/// A string table, of names each ended by a NUL, the first empty.
+struct Strings {
+    bytes: Vec<u8>,
+}
+
+impl Strings {
+    fn new() -> Strings {
+        Strings { bytes: vec![0] }
+    }
+
+    /// Adds `name`, returning where it is.
+    fn add(&mut self, name: &str) -> u32 {
+        if name.is_empty() {
+            return 0;
+        }
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.bytes.push(0);
+        offset
+    }
+}
+
+/// A header of the table of sections.
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entry_size: u64,
+}
+
+/// Writes `object` as an ELF relocatable object file.
+pub(crate) fn write_object(object: &Object) -> Vec<u8> {
+    let mut out = vec![0; 64];
+    let mut names = Strings::new();
+    let mut headers = vec![SectionHeader {
+        name: 0,
+        kind: 0,
+        flags: 0,
+        offset: 0,
+        size: 0,
+        link: 0,
+        info: 0,
+        align: 0,
+        entry_size: 0,
+    }];
+    let put = |out: &mut Vec<u8>, bytes: &[u8], align: u64| {
+        out.resize(out.len().next_multiple_of(align as usize), 0);
+        let offset = out.len() as u64;
+        out.extend_from_slice(bytes);
+        offset
+    };
+
+    let written = object.written();
+    let section_index = |section: Section| {
+        let position = written.iter().position(|&written| written == section);
+        position.expect("a section that's written") as u16 + 1
+    };
+    for &section in &written {
+        let bytes = object.bytes(section);
+        let align = object.align(section);
+        let (kind, offset) = match section {
+            Section::Bss => (SHT_NOBITS, out.len() as u64),
+            _ => (SHT_PROGBITS, put(&mut out, bytes, align)),
+        };
+        headers.push(SectionHeader {
+            name: names.add(section.name()),
+            kind,
+            flags: section.flags(),
+            offset,
+            size: bytes.len() as u64,
+            link: 0,
+            info: 0,
+            align,
+            entry_size: 0,
+        });
+    }
+    headers.push(SectionHeader {
+        name: names.add(".note.GNU-stack"),
+        kind: SHT_PROGBITS,
+        flags: 0,
+        offset: out.len() as u64,
+        size: 0,
+        link: 0,
+        info: 0,
+        align: 1,
+        entry_size: 0,
+    });
+
+    // The symbols, the local ones first, as the ABI asks, with a symbol
+    // of each section for the relocations against it.
+    let symtab_index = headers.len() as u32;
+    let mut strings = Strings::new();
+    let mut symtab = vec![0; 24];
+    for &section in &written {
+        push_symbol(&mut symtab, 0, STT_SECTION, section_index(section), 0);
+    }
+    let mut order: Vec<usize> = (0..object.symbols.len()).collect();
+    order.sort_by_key(|&index| object.symbols[index].global);
+    let mut symbol_index = vec![0; object.symbols.len()];
+    let mut first_global = None;
+    for (position, &index) in order.iter().enumerate() {
+        let symbol = &object.symbols[index];
+        let elf_index = 1 + written.len() + position;
+        symbol_index[index] = elf_index as u64;
+        if symbol.global && first_global.is_none() {
+            first_global = Some(elf_index);
+        }
+        let binding = if symbol.global { STB_GLOBAL } else { STB_LOCAL };
+        let kind = match symbol.kind {
+            SymbolKind::Func => STT_FUNC,
+            SymbolKind::Object => STT_OBJECT,
+            SymbolKind::NoType => STT_NOTYPE,
+        };
+        let (section, value) = match symbol.def {
+            Some((section, value)) => (section_index(section), value),
+            None => (0, 0),
+        };
+        let name = strings.add(&symbol.name);
+        push_symbol(&mut symtab, name, binding << 4 | kind, section, value);
+    }
+    let symbols = symtab.len() as u32 / 24;
+    headers.push(SectionHeader {
+        name: names.add(".symtab"),
+        kind: SHT_SYMTAB,
+        flags: 0,
+        offset: put(&mut out, &symtab, 8),
+        size: symtab.len() as u64,
+        link: symtab_index + 1,
+        info: first_global.map_or(symbols, |index| index as u32),
+        align: 8,
+        entry_size: 24,
+    });
+    headers.push(SectionHeader {
+        name: names.add(".strtab"),
+        kind: SHT_STRTAB,
+        flags: 0,
+        offset: put(&mut out, &strings.bytes, 1),
+        size: strings.bytes.len() as u64,
+        link: 0,
+        info: 0,
+        align: 1,
+        entry_size: 0,
+    });
+
+    for &section in &written {
+        let mut rela = Vec::new();
+        for relocation in &object.relocations {
+            if relocation.section != section {
+                continue;
+            }
+            let symbol = match relocation.target {
+                Target::Symbol(index) => symbol_index[index],
+                Target::Section(section) => u64::from(section_index(section)),
+            };
+            rela.extend_from_slice(&relocation.offset.to_le_bytes());
+            rela.extend_from_slice(
+                &(symbol << 32 | relocation.kind.number()).to_le_bytes(),
+            );
+            rela.extend_from_slice(&relocation.addend.to_le_bytes());
+        }
+        if rela.is_empty() {
+            continue;
+        }
+        headers.push(SectionHeader {
+            name: names.add(&format!(".rela{}", section.name())),
+            kind: SHT_RELA,
+            flags: SHF_INFO_LINK,
+            offset: put(&mut out, &rela, 8),
+            size: rela.len() as u64,
+            link: symtab_index,
+            info: u32::from(section_index(section)),
+            align: 8,
+            entry_size: 24,
+        });
+    }
+
+    let shstrtab_index = headers.len() as u16;
+    let name = names.add(".shstrtab");
+    headers.push(SectionHeader {
+        name,
+        kind: SHT_STRTAB,
+        flags: 0,
+        offset: put(&mut out, &names.bytes, 1),
+        size: names.bytes.len() as u64,
+        link: 0,
+        info: 0,
+        align: 1,
+        entry_size: 0,
+    });
+
+    let headers_offset = put(&mut out, &[], 8);
+    for header in &headers {
+        out.extend_from_slice(&header.name.to_le_bytes());
+        out.extend_from_slice(&header.kind.to_le_bytes());
+        out.extend_from_slice(&header.flags.to_le_bytes());
+        // Where it's loaded, which only an executable says.
+        out.extend_from_slice(&0u64.to_le_bytes());
+        out.extend_from_slice(&header.offset.to_le_bytes());
+        out.extend_from_slice(&header.size.to_le_bytes());
+        out.extend_from_slice(&header.link.to_le_bytes());
+        out.extend_from_slice(&header.info.to_le_bytes());
+        out.extend_from_slice(&header.align.to_le_bytes());
+        out.extend_from_slice(&header.entry_size.to_le_bytes());
+    }
+
+    let mut header = Vec::with_capacity(64);
+    // The magic number, 64 bits, little-endian, version 1, System V.
+    header.extend_from_slice(b"\x7fELF\x02\x01\x01\x00");
+    header.extend_from_slice(&[0; 8]);
+    // A relocatable file, for x86-64, version 1.
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&62u16.to_le_bytes());
+    header.extend_from_slice(&1u32.to_le_bytes());
+    // No entry point, nor program headers.
+    header.extend_from_slice(&0u64.to_le_bytes());
+    header.extend_from_slice(&0u64.to_le_bytes());
+    header.extend_from_slice(&headers_offset.to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&64u16.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(&64u16.to_le_bytes());
+    header.extend_from_slice(&(headers.len() as u16).to_le_bytes());
+    header.extend_from_slice(&shstrtab_index.to_le_bytes());
+    out[..64].copy_from_slice(&header);
+    out
+}
+
+/// Writes an entry of a symbol table to `symtab`.
+fn push_symbol(
+    symtab: &mut Vec<u8>,
+    name: u32,
+    info: u8,
+    section: u16,
+    value: u64,
+) {
+    symtab.extend_from_slice(&name.to_le_bytes());
+    symtab.push(info);
+    symtab.push(0);
+    symtab.extend_from_slice(&section.to_le_bytes());
+    symtab.extend_from_slice(&value.to_le_bytes());
+    // Its size, which is left unsaid.
+    symtab.extend_from_slice(&0u64.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        write_object, Object, RelocKind, Relocation, Section, Symbol,
+        SymbolKind, Target,
+    };
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn local_symbols_come_before_global_ones() {
+        let mut object = Object::default();
+        object
+            .sections
+            .insert(Section::Text, (vec![0xe8, 0, 0, 0, 0], 16));
+        object.symbols = vec![
+            Symbol {
+                name: "main".into(),
+                def: Some((Section::Text, 0)),
+                global: true,
+                kind: SymbolKind::Func,
+            },
+            Symbol {
+                name: "helper".into(),
+                def: Some((Section::Text, 5)),
+                global: false,
+                kind: SymbolKind::Func,
+            },
+        ];
+        object.relocations.push(Relocation {
+            section: Section::Text,
+            offset: 1,
+            target: Target::Symbol(0),
+            kind: RelocKind::Plt32,
+            addend: -4,
+        });
+        let bytes = write_object(&object);
+
+        assert_eq!(bytes[..4], *b"\x7fELF");
+        // A relocatable file for x86-64.
+        assert_eq!((u16_at(&bytes, 16), u16_at(&bytes, 18)), (1, 62));
+        let headers = u64_at(&bytes, 40) as usize;
+        let count = u16_at(&bytes, 60) as usize;
+        let header = |index: usize| &bytes[headers + index * 64..][..64];
+        let names = header(u16_at(&bytes, 62) as usize);
+        let name = |header: &[u8]| {
+            let at = u64_at(names, 24) as usize + u32_at(header, 0) as usize;
+            let end = at + bytes[at..].iter().position(|&b| b == 0).unwrap();
+            std::str::from_utf8(&bytes[at..end]).unwrap()
+        };
+        let sections: Vec<_> =
+            (0..count).map(|index| name(header(index))).collect();
+        assert_eq!(
+            sections,
+            [
+                "",
+                ".text",
+                ".data",
+                ".rodata",
+                ".note.GNU-stack",
+                ".symtab",
+                ".strtab",
+                ".rela.text",
+                ".shstrtab",
+            ]
+        );
+        assert_eq!(u64_at(header(1), 48), 16);
+
+        // The null symbol, the ones of the sections, `helper` and `main`,
+        // the first global one.
+        let symtab = header(5);
+        assert_eq!(u64_at(symtab, 32) / 24, 6);
+        assert_eq!(u32_at(symtab, 44), 5);
+        let main = u64_at(symtab, 24) as usize + 5 * 24;
+        assert_eq!(bytes[main + 4], 1 << 4 | 2);
+        // The call in `.text` is to `main`.
+        let rela = u64_at(header(7), 24) as usize;
+        assert_eq!(u64_at(&bytes, rela), 1);
+        assert_eq!(u64_at(&bytes, rela + 8), 5 << 32 | 4);
+        assert_eq!(u64_at(&bytes, rela + 16) as i64, -4);
+    }
+
+    #[test]
+    fn what_starts_out_as_zero_takes_no_room_in_the_file() {
+        let mut object = Object::default();
+        object.sections.insert(Section::Text, (vec![0xc3], 16));
+        object.sections.insert(Section::Bss, (vec![0; 1 << 20], 8));
+        object.symbols.push(Symbol {
+            name: "big".into(),
+            def: Some((Section::Bss, 0)),
+            global: true,
+            kind: SymbolKind::Object,
+        });
+        let bytes = write_object(&object);
+
+        assert!(bytes.len() < 1 << 12);
+        let headers = u64_at(&bytes, 40) as usize;
+        // After `.text`, `.data` and `.rodata`: `SHT_NOBITS`, writable and
+        // allocated, of the size of its zeros, and as aligned as they are.
+        let bss = &bytes[headers + 4 * 64..][..64];
+        assert_eq!(u32_at(bss, 4), 8);
+        assert_eq!(u64_at(bss, 8), 3);
+        assert_eq!(u64_at(bss, 32), 1 << 20);
+        assert_eq!(u64_at(bss, 48), 8);
+    }
+}
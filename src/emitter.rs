@@ -0,0 +1,1713 @@
+use crate::{
+    errors::{Diagnostic, Label, Phase, Severity, Suggestion, Summary},
+    json::JsonValue,
+    source_map::{BytePos, Loc, Pos, SourceFile, SourceMap, Span},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+    rc::Rc,
+};
+
+/// The format in which diagnostics are presented, selected with
+/// `--error-format`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ErrorFormat {
+    /// Rendered for humans, with source snippets.
+    Human,
+    /// One line per diagnostic, `file:line:column: level: message`, for
+    /// editors that match compiler output with regular expressions.
+    Short,
+    /// One JSON object per line, for editors and other tools.
+    Json,
+    /// A single SARIF 2.1.0 log, for code review and code scanning tools.
+    Sarif,
+}
+
+impl ErrorFormat {
+    pub(crate) fn from_name(name: &str) -> Option<ErrorFormat> {
+        match name {
+            "human" => Some(ErrorFormat::Human),
+            "short" => Some(ErrorFormat::Short),
+            "json" => Some(ErrorFormat::Json),
+            "sarif" => Some(ErrorFormat::Sarif),
+            _ => None,
+        }
+    }
+}
+
+/// Whether to color the output, selected with `--color`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ColorChoice {
+    /// Color only when writing to a terminal, and `NO_COLOR` isn't set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub(crate) fn from_name(name: &str) -> Option<ColorChoice> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    /// Whether output should be colored, given whether it goes to a terminal
+    /// and whether the `NO_COLOR` environment variable is set (see
+    /// <https://no-color.org>).
+    pub(crate) fn should_color(
+        self,
+        is_terminal: bool,
+        no_color: bool,
+    ) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal && !no_color,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// An `Emitter` writes diagnostics out in some format.
+pub(crate) trait Emitter {
+    fn emit(&mut self, diagnostic: &Diagnostic) -> io::Result<()>;
+
+    /// Writes the line summing up the compilation, if the format has one.
+    fn emit_summary(&mut self, _summary: &Summary) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once every diagnostic was emitted. Formats that write a single
+    /// document for the whole compilation write it here.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates the emitter for `format`, writing to `out`. Spans are resolved to
+/// files, lines and columns with `source_map`. Only human-readable output is
+/// ever colored.
+pub(crate) fn new_emitter<'a>(
+    format: ErrorFormat,
+    source_map: &'a SourceMap,
+    out: Box<dyn Write + 'a>,
+    color: bool,
+) -> Box<dyn Emitter + 'a> {
+    match format {
+        ErrorFormat::Human => Box::new(HumanEmitter {
+            source_map,
+            out,
+            color,
+        }),
+        ErrorFormat::Short => Box::new(ShortEmitter {
+            source_map,
+            out,
+            color,
+        }),
+        ErrorFormat::Json => Box::new(JsonEmitter { source_map, out }),
+        ErrorFormat::Sarif => Box::new(SarifEmitter {
+            source_map,
+            out,
+            results: Vec::new(),
+            rule_ids: BTreeSet::new(),
+        }),
+    }
+}
+
+/// Passes on to `inner` only the diagnostics from `phase`, along with those
+/// not tagged with any phase, for `--only-errors-from`.
+pub(crate) struct PhaseFilter<'a> {
+    pub(crate) inner: Box<dyn Emitter + 'a>,
+    pub(crate) phase: Phase,
+}
+
+impl Emitter for PhaseFilter<'_> {
+    fn emit(&mut self, diagnostic: &Diagnostic) -> io::Result<()> {
+        match diagnostic.diag.phase() {
+            Some(phase) if phase != self.phase => Ok(()),
+            _ => self.inner.emit(diagnostic),
+        }
+    }
+
+    fn emit_summary(&mut self, summary: &Summary) -> io::Result<()> {
+        self.inner.emit_summary(summary)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.inner.finish()
+    }
+}
+
+/// The severity of a diagnostic followed by its code, if any, e.g.
+/// `error[E0001]`.
+fn diagnostic_level(diagnostic: &Diagnostic) -> String {
+    match diagnostic.diag.code() {
+        Some(code) => format!("{}[{}]", diagnostic.severity, code),
+        None => diagnostic.severity.to_string(),
+    }
+}
+
+/// Line and column numbers (both starting at 1) of a span's start and end,
+/// where the end column points one past the last character.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SpanLocs {
+    pub(crate) line_start: usize,
+    pub(crate) column_start: usize,
+    pub(crate) line_end: usize,
+    pub(crate) column_end: usize,
+}
+
+impl SpanLocs {
+    pub(crate) fn resolve(source_file: &SourceFile, span: Span) -> SpanLocs {
+        let start = lookup_loc(source_file, span.start);
+        let end = if span.end.to_usize() > span.start.to_usize() {
+            let last = lookup_loc(source_file, span.end - BytePos(1));
+            Loc {
+                line: last.line,
+                col: last.col + BytePos(1),
+            }
+        } else {
+            start
+        };
+
+        SpanLocs {
+            line_start: start.line,
+            column_start: start.col.to_usize() + 1,
+            line_end: end.line,
+            column_end: end.col.to_usize() + 1,
+        }
+    }
+}
+
+/// Looks up the location of `pos`, mapping positions at the end of the input
+/// to just after its last character.
+fn lookup_loc(source_file: &SourceFile, pos: BytePos) -> Loc {
+    source_file.lookup_source_location(pos).unwrap_or_else(|| {
+        if source_file.src.is_empty() {
+            Loc {
+                line: 1,
+                col: BytePos(0),
+            }
+        } else {
+            let last =
+                lookup_loc(source_file, source_file.end_pos() - BytePos(1));
+            Loc {
+                line: last.line,
+                col: last.col + BytePos(1),
+            }
+        }
+    })
+}
+
+/// Renders diagnostics with the offending source lines, underlining every
+/// label: `^` for the primary span and `-` for secondary ones.
+///
+/// ```text
+/// error[E0002]: unterminated block comment
+///  --> main.cm:1:1
+///   |
+/// 1 | /* a comment
+///   | ^^ comment started here
+/// ...
+/// 3 | int x;
+///   |       - `*/` expected here
+/// ```
+///
+/// Labels in other files than the primary label's get a section of their own,
+/// introduced by `:::` instead of `-->`.
+///
+/// When coloring, the level and underlines of a diagnostic take the color of
+/// its severity, while the gutter and secondary underlines are blue.
+struct HumanEmitter<'a> {
+    source_map: &'a SourceMap,
+    out: Box<dyn Write + 'a>,
+    color: bool,
+}
+
+/// How a piece of human-readable output is colored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    /// Diagnostic levels and primary underlines.
+    Level(Severity),
+    /// Gutter, arrows and secondary underlines.
+    Accent,
+    /// Diagnostic messages.
+    Emphasis,
+}
+
+/// Wraps `text` in the escape codes of `style`, if coloring.
+fn paint(color: bool, style: Style, text: &str) -> String {
+    if color && !text.is_empty() {
+        format!("\x1b[{}m{}\x1b[0m", style.ansi_code(), text)
+    } else {
+        text.to_owned()
+    }
+}
+
+impl Style {
+    /// The ANSI SGR parameters of the style.
+    fn ansi_code(self) -> &'static str {
+        match self {
+            Style::Level(Severity::Fatal | Severity::Error) => "1;31",
+            Style::Level(Severity::Warning) => "1;33",
+            Style::Level(Severity::Note) => "1;32",
+            Style::Level(Severity::Help) => "1;36",
+            Style::Accent => "1;34",
+            Style::Emphasis => "1",
+        }
+    }
+
+    fn of_label(is_primary: bool, severity: Severity) -> Style {
+        if is_primary {
+            Style::Level(severity)
+        } else {
+            Style::Accent
+        }
+    }
+}
+
+fn line_text(source_file: &SourceFile, line: usize) -> &str {
+    source_file.line_text(line - 1).unwrap_or_default()
+}
+
+/// Number of columns a tab is drawn as.
+const TAB_WIDTH: usize = 4;
+
+/// Number of columns `ch` takes up on a terminal: two for wide characters
+/// (e.g. CJK ideographs and emoji), none for combining marks and other
+/// zero-width characters, and `TAB_WIDTH` for tabs, which are drawn as spaces.
+fn char_width(ch: char) -> usize {
+    match ch as u32 {
+        0x09 => TAB_WIDTH,
+        0x0300..=0x036F
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200B..=0x200F
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x2FFFD
+        | 0x30000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// The column (starting at 0) at which the byte at `byte_col` of `text` is
+/// drawn. Columns past the end of the text take one column each.
+fn display_column(text: &str, byte_col: usize) -> usize {
+    let width: usize = text
+        .char_indices()
+        .take_while(|&(i, _)| i < byte_col)
+        .map(|(_, ch)| char_width(ch))
+        .sum();
+
+    width + byte_col.saturating_sub(text.len())
+}
+
+/// `text` as it's drawn in a snippet, with tabs expanded to spaces.
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+/// A label resolved to the line it's drawn on and the display columns
+/// (starting at 0) it underlines. Labels spanning multiple lines are
+/// underlined up to the end of their first line.
+struct LineLabel {
+    line: usize,
+    /// Column (starting at 1) of the label's start, as shown in locations.
+    column: usize,
+    col_start: usize,
+    col_end: usize,
+    message: Option<String>,
+    is_primary: bool,
+}
+
+impl HumanEmitter<'_> {
+    fn paint(&self, style: Style, text: &str) -> String {
+        paint(self.color, style, text)
+    }
+
+    fn resolve_line_label(source_file: &SourceFile, label: Label) -> LineLabel {
+        let locs = SpanLocs::resolve(source_file, label.span);
+        let text = line_text(source_file, locs.line_start);
+        let byte_col_end = if locs.line_end == locs.line_start {
+            locs.column_end - 1
+        } else {
+            text.len()
+        };
+        let col_start = display_column(text, locs.column_start - 1);
+        let col_end = display_column(text, byte_col_end);
+
+        LineLabel {
+            line: locs.line_start,
+            column: locs.column_start,
+            col_start,
+            col_end: col_end.max(col_start + 1),
+            message: label.message,
+            is_primary: label.is_primary,
+        }
+    }
+
+    fn write_gutter_line(
+        &mut self,
+        gutter: &str,
+        content: &str,
+    ) -> io::Result<()> {
+        let content = content.trim_end();
+        let gutter = self.paint(Style::Accent, &format!("{} |", gutter));
+        if content.is_empty() {
+            writeln!(self.out, "{}", gutter)
+        } else {
+            writeln!(self.out, "{} {}", gutter, content)
+        }
+    }
+
+    /// Writes the underlines of the labels on a single line, followed by
+    /// their messages. The rightmost message goes right after the underlines,
+    /// the others hang below them:
+    ///
+    /// ```text
+    ///   | --------  ^ expected here
+    ///   | |
+    ///   | comment started here
+    /// ```
+    fn write_underlines(
+        &mut self,
+        gutter: &str,
+        labels: &mut [LineLabel],
+        severity: Severity,
+    ) -> io::Result<()> {
+        labels.sort_by_key(|label| label.col_start);
+
+        let width = labels.iter().map(|label| label.col_end).max().unwrap_or(0);
+        let mut cells: Vec<Option<bool>> = vec![None; width];
+        for label in labels.iter() {
+            for cell in &mut cells[label.col_start..label.col_end] {
+                *cell = Some(label.is_primary);
+            }
+        }
+
+        // Consecutive cells of the same kind are painted as a single run.
+        let mut underline = String::new();
+        let mut run_start = 0;
+        for i in 1..=cells.len() {
+            if i < cells.len() && cells[i] == cells[run_start] {
+                continue;
+            }
+            let run_len = i - run_start;
+            match cells[run_start] {
+                Some(is_primary) => {
+                    let mark = if is_primary { "^" } else { "-" };
+                    underline.push_str(&self.paint(
+                        Style::of_label(is_primary, severity),
+                        &mark.repeat(run_len),
+                    ));
+                }
+                None => underline.push_str(&" ".repeat(run_len)),
+            }
+            run_start = i;
+        }
+
+        let mut hanging = Vec::new();
+        for (i, label) in labels.iter().enumerate().rev() {
+            if let Some(message) = &label.message {
+                let style = Style::of_label(label.is_primary, severity);
+                if i + 1 == labels.len() {
+                    underline =
+                        format!("{} {}", underline, self.paint(style, message));
+                } else {
+                    hanging.push((label.col_start, message.as_str(), style));
+                }
+            }
+        }
+        self.write_gutter_line(gutter, &underline)?;
+
+        // `hanging` goes from right to left, so the ones yet to be written are
+        // always to the left of the current one.
+        for (i, &(col, message, style)) in hanging.iter().enumerate() {
+            let mut connectors = vec![None; col + 1];
+            for &(pending_col, _, pending_style) in &hanging[i..] {
+                connectors[pending_col] = Some(pending_style);
+            }
+            let connectors: Vec<String> = connectors
+                .into_iter()
+                .map(|connector| match connector {
+                    Some(style) => self.paint(style, "|"),
+                    None => " ".to_owned(),
+                })
+                .collect();
+            self.write_gutter_line(gutter, &connectors.concat())?;
+
+            let message_line = format!(
+                "{}{}",
+                connectors[..col].concat(),
+                self.paint(style, message)
+            );
+            self.write_gutter_line(gutter, &message_line)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl HumanEmitter<'_> {
+    /// Resolves labels to the lines they are drawn on, grouping them by file
+    /// in order of appearance. Labels outside of any file are left out.
+    fn group_labels_by_file(
+        &self,
+        labels: Vec<Label>,
+    ) -> Vec<(Rc<SourceFile>, Vec<LineLabel>)> {
+        let mut sections: Vec<(Rc<SourceFile>, Vec<LineLabel>)> = Vec::new();
+        for label in labels {
+            let source_file =
+                match self.source_map.lookup_file(label.span.start) {
+                    Some(source_file) => source_file,
+                    None => continue,
+                };
+            let line_label =
+                HumanEmitter::resolve_line_label(source_file, label);
+
+            match sections
+                .iter_mut()
+                .find(|(section_file, _)| Rc::ptr_eq(section_file, source_file))
+            {
+                Some((_, line_labels)) => line_labels.push(line_label),
+                None => {
+                    sections.push((Rc::clone(source_file), vec![line_label]))
+                }
+            }
+        }
+
+        sections
+    }
+
+    /// Writes the source lines pointed at by `labels`, a section per file.
+    /// The first section is introduced by `-->` and the others by `:::`.
+    fn write_snippet(
+        &mut self,
+        sections: Vec<(Rc<SourceFile>, Vec<LineLabel>)>,
+        gutter_width: usize,
+        severity: Severity,
+    ) -> io::Result<()> {
+        let empty_gutter = " ".repeat(gutter_width);
+
+        for (i, (source_file, line_labels)) in sections.into_iter().enumerate()
+        {
+            let arrow = if i == 0 { "-->" } else { ":::" };
+            let arrow = self.paint(Style::Accent, arrow);
+            writeln!(
+                self.out,
+                "{}{} {}:{}:{}",
+                empty_gutter,
+                arrow,
+                source_file.name,
+                line_labels[0].line,
+                line_labels[0].column,
+            )?;
+            self.write_gutter_line(&empty_gutter, "")?;
+
+            let mut labels_by_line: BTreeMap<usize, Vec<LineLabel>> =
+                BTreeMap::new();
+            for label in line_labels {
+                labels_by_line.entry(label.line).or_default().push(label);
+            }
+
+            let mut previous_line = None;
+            for (line, mut line_labels) in labels_by_line {
+                if let Some(previous_line) = previous_line {
+                    if line > previous_line + 1 {
+                        let ellipsis = self.paint(Style::Accent, "...");
+                        writeln!(self.out, "{}", ellipsis)?;
+                    }
+                }
+                previous_line = Some(line);
+
+                let line_number =
+                    format!("{:>width$}", line, width = gutter_width);
+                self.write_gutter_line(
+                    &line_number,
+                    &expand_tabs(line_text(&source_file, line)),
+                )?;
+                self.write_underlines(
+                    &empty_gutter,
+                    &mut line_labels,
+                    severity,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn max_line_number(sections: &[(Rc<SourceFile>, Vec<LineLabel>)]) -> usize {
+    sections
+        .iter()
+        .flat_map(|(_, line_labels)| line_labels.iter())
+        .map(|label| label.line)
+        .max()
+        .unwrap_or(1)
+}
+
+impl Emitter for HumanEmitter<'_> {
+    fn emit(&mut self, diagnostic: &Diagnostic) -> io::Result<()> {
+        let severity = diagnostic.severity;
+        let level =
+            self.paint(Style::Level(severity), &diagnostic_level(diagnostic));
+        let message = self.paint(
+            Style::Emphasis,
+            &format!(": {}", diagnostic.diag.message()),
+        );
+        writeln!(self.out, "{}{}", level, message)?;
+
+        let sections = self.group_labels_by_file(diagnostic.labels());
+        let child_sections: Vec<_> = diagnostic
+            .children
+            .iter()
+            .map(|child| self.group_labels_by_file(child.labels()))
+            .collect();
+
+        // Children share the parent's gutter, so that everything lines up.
+        let max_line = child_sections
+            .iter()
+            .map(|sections| max_line_number(sections))
+            .chain(Some(max_line_number(&sections)))
+            .max()
+            .unwrap_or(1);
+        let gutter_width = max_line.to_string().len();
+        let empty_gutter = " ".repeat(gutter_width);
+        let equals = self.paint(Style::Accent, "=");
+
+        self.write_snippet(sections, gutter_width, severity)?;
+
+        for (child, sections) in diagnostic.children.iter().zip(child_sections)
+        {
+            let child_level =
+                self.paint(Style::Emphasis, &child.severity.to_string());
+            writeln!(
+                self.out,
+                "{} {} {}: {}",
+                empty_gutter, equals, child_level, child.message
+            )?;
+            self.write_snippet(sections, gutter_width, severity)?;
+        }
+
+        for suggestion in &diagnostic.suggestions {
+            let help = self.paint(Style::Emphasis, "help");
+            writeln!(
+                self.out,
+                "{} {} {}: {}",
+                empty_gutter, equals, help, suggestion.message
+            )?;
+        }
+
+        writeln!(self.out)
+    }
+
+    fn emit_summary(&mut self, summary: &Summary) -> io::Result<()> {
+        match summary.line() {
+            Some((severity, line)) => {
+                let level =
+                    self.paint(Style::Level(severity), &severity.to_string());
+                let line = self.paint(Style::Emphasis, &format!(": {}", line));
+                writeln!(self.out, "{}{}", level, line)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes a line per diagnostic, prefixed by the location of its primary
+/// span, and a line per note or help message attached to it, e.g.:
+///
+/// ```text
+/// main.cm:3:5: error[E0001]: unknown character '@'
+/// main.cm:1:5: note: x declared here
+/// ```
+///
+/// Diagnostics and notes without a span have no location.
+struct ShortEmitter<'a> {
+    source_map: &'a SourceMap,
+    out: Box<dyn Write + 'a>,
+    color: bool,
+}
+
+impl ShortEmitter<'_> {
+    /// The `file:line:column: ` prefix of a line about `span`.
+    fn location(&self, span: Option<Span>) -> String {
+        span.and_then(|span| {
+            let source_file = self.source_map.lookup_file(span.start)?;
+            let locs = SpanLocs::resolve(source_file, span);
+            Some(format!(
+                "{}:{}:{}: ",
+                source_file.name, locs.line_start, locs.column_start
+            ))
+        })
+        .unwrap_or_default()
+    }
+
+    fn write_line(
+        &mut self,
+        span: Option<Span>,
+        severity: Severity,
+        level: &str,
+        message: &str,
+    ) -> io::Result<()> {
+        let location = self.location(span);
+        let level = paint(self.color, Style::Level(severity), level);
+        writeln!(self.out, "{}{}: {}", location, level, message)
+    }
+}
+
+impl Emitter for ShortEmitter<'_> {
+    fn emit(&mut self, diagnostic: &Diagnostic) -> io::Result<()> {
+        self.write_line(
+            diagnostic.diag.span(),
+            diagnostic.severity,
+            &diagnostic_level(diagnostic),
+            &diagnostic.diag.message(),
+        )?;
+
+        for child in &diagnostic.children {
+            self.write_line(
+                child.span,
+                child.severity,
+                &child.severity.to_string(),
+                &child.message,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn emit_summary(&mut self, summary: &Summary) -> io::Result<()> {
+        match summary.line() {
+            Some((severity, line)) => {
+                self.write_line(None, severity, &severity.to_string(), &line)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes one JSON object per line for every diagnostic, e.g.:
+///
+/// ```text
+/// {"code":"E0001","severity":"error","phase":"lex",
+///  "message":"unknown character '@'","file":"main.cm","spans":[{"file":"main.cm","byte_start":8,
+///  "byte_end":9,"line_start":1,"column_start":9,"line_end":1,
+///  "column_end":10,"is_primary":true,"label":null}],"children":[],
+///  "suggestions":[]}
+/// ```
+///
+/// Byte offsets are relative to the start of the span's file, and `phase` is
+/// the phase of the compiler the diagnostic comes from.
+struct JsonEmitter<'a> {
+    source_map: &'a SourceMap,
+    out: Box<dyn Write + 'a>,
+}
+
+impl JsonEmitter<'_> {
+    /// The fields locating `span` in its file.
+    fn span_fields(
+        &self,
+        span: Span,
+    ) -> Option<Vec<(&'static str, JsonValue)>> {
+        let source_file = self.source_map.lookup_file(span.start)?;
+        let locs = SpanLocs::resolve(source_file, span);
+
+        Some(vec![
+            ("file", source_file.name.as_str().into()),
+            (
+                "byte_start",
+                (span.start - source_file.start_pos).to_usize().into(),
+            ),
+            (
+                "byte_end",
+                (span.end - source_file.start_pos).to_usize().into(),
+            ),
+            ("line_start", locs.line_start.into()),
+            ("column_start", locs.column_start.into()),
+            ("line_end", locs.line_end.into()),
+            ("column_end", locs.column_end.into()),
+        ])
+    }
+
+    fn label_to_json(&self, label: Label) -> Option<JsonValue> {
+        let mut fields = self.span_fields(label.span)?;
+        fields.push(("is_primary", label.is_primary.into()));
+        fields.push(("label", label.message.into()));
+
+        Some(JsonValue::object(fields))
+    }
+
+    fn suggestion_to_json(&self, suggestion: &Suggestion) -> Option<JsonValue> {
+        let mut fields = vec![
+            ("message", suggestion.message.as_str().into()),
+            ("applicability", suggestion.applicability.name().into()),
+        ];
+        fields.extend(self.span_fields(suggestion.span)?);
+        fields.push(("replacement", suggestion.replacement.as_str().into()));
+
+        Some(JsonValue::object(fields))
+    }
+}
+
+impl Emitter for JsonEmitter<'_> {
+    fn emit(&mut self, diagnostic: &Diagnostic) -> io::Result<()> {
+        let diag = &diagnostic.diag;
+        let spans = diagnostic
+            .labels()
+            .into_iter()
+            .filter_map(|label| self.label_to_json(label))
+            .collect();
+        let file = diag
+            .span()
+            .and_then(|span| self.source_map.lookup_file(span.start))
+            .map(|source_file| source_file.name.as_str());
+        let children = diagnostic
+            .children
+            .iter()
+            .map(|child| {
+                let spans = child
+                    .labels()
+                    .into_iter()
+                    .filter_map(|label| self.label_to_json(label))
+                    .collect();
+                JsonValue::object(vec![
+                    ("severity", child.severity.to_string().into()),
+                    ("message", child.message.as_str().into()),
+                    ("spans", JsonValue::Array(spans)),
+                ])
+            })
+            .collect();
+        let suggestions = diagnostic
+            .suggestions
+            .iter()
+            .filter_map(|suggestion| self.suggestion_to_json(suggestion))
+            .collect();
+        let value = JsonValue::object(vec![
+            ("code", diag.code().into()),
+            ("severity", diagnostic.severity.to_string().into()),
+            ("phase", diag.phase().map(Phase::name).into()),
+            ("message", diag.message().into()),
+            ("file", file.into()),
+            ("spans", JsonValue::Array(spans)),
+            ("children", JsonValue::Array(children)),
+            ("suggestions", JsonValue::Array(suggestions)),
+        ]);
+
+        writeln!(self.out, "{}", value)
+    }
+}
+
+/// Writes a SARIF 2.1.0 log with every diagnostic once the compilation is
+/// over, e.g.:
+///
+/// ```text
+/// {"$schema":"https://json.schemastore.org/sarif-2.1.0.json",
+///  "version":"2.1.0","runs":[{"tool":{"driver":{"name":"csub",
+///  "version":"0.1.0","rules":[{"id":"E0001"}]}},"results":[{
+///  "ruleId":"E0001","level":"error","message":{"text":"unknown character
+///  '@'"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":
+///  "main.cm"},"region":{"startLine":1,"startColumn":9,"endLine":1,
+///  "endColumn":10}}}],"relatedLocations":[],"fixes":[]}]}]}
+/// ```
+///
+/// Secondary labels and notes pointing at code become related locations,
+/// notes without a span are appended to the message, and suggestions become
+/// fixes.
+struct SarifEmitter<'a> {
+    source_map: &'a SourceMap,
+    out: Box<dyn Write + 'a>,
+    results: Vec<JsonValue>,
+    /// Codes of the emitted diagnostics, listed as the run's rules.
+    rule_ids: BTreeSet<&'static str>,
+}
+
+impl SarifEmitter<'_> {
+    fn level(severity: Severity) -> &'static str {
+        match severity {
+            Severity::Fatal | Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note | Severity::Help => "note",
+        }
+    }
+
+    fn message(text: impl Into<String>) -> JsonValue {
+        JsonValue::object(vec![("text", JsonValue::String(text.into()))])
+    }
+
+    fn artifact_location(source_file: &SourceFile) -> JsonValue {
+        JsonValue::object(vec![("uri", source_file.name.as_str().into())])
+    }
+
+    fn region(source_file: &SourceFile, span: Span) -> JsonValue {
+        let locs = SpanLocs::resolve(source_file, span);
+
+        JsonValue::object(vec![
+            ("startLine", locs.line_start.into()),
+            ("startColumn", locs.column_start.into()),
+            ("endLine", locs.line_end.into()),
+            ("endColumn", locs.column_end.into()),
+        ])
+    }
+
+    /// The `physicalLocation` object of `span`.
+    fn physical_location(&self, span: Span) -> Option<JsonValue> {
+        let source_file = self.source_map.lookup_file(span.start)?;
+
+        Some(JsonValue::object(vec![
+            (
+                "artifactLocation",
+                SarifEmitter::artifact_location(source_file),
+            ),
+            ("region", SarifEmitter::region(source_file, span)),
+        ]))
+    }
+
+    fn location(&self, span: Span, message: Option<&str>) -> Option<JsonValue> {
+        let mut fields =
+            vec![("physicalLocation", self.physical_location(span)?)];
+        if let Some(message) = message {
+            fields.push(("message", SarifEmitter::message(message)));
+        }
+
+        Some(JsonValue::object(fields))
+    }
+
+    fn fix(&self, suggestion: &Suggestion) -> Option<JsonValue> {
+        let span = suggestion.span;
+        let source_file = self.source_map.lookup_file(span.start)?;
+        let replacement = JsonValue::object(vec![
+            ("deletedRegion", SarifEmitter::region(source_file, span)),
+            (
+                "insertedContent",
+                SarifEmitter::message(&suggestion.replacement),
+            ),
+        ]);
+
+        Some(JsonValue::object(vec![
+            ("description", SarifEmitter::message(&suggestion.message)),
+            (
+                "artifactChanges",
+                JsonValue::Array(vec![JsonValue::object(vec![
+                    (
+                        "artifactLocation",
+                        SarifEmitter::artifact_location(source_file),
+                    ),
+                    ("replacements", JsonValue::Array(vec![replacement])),
+                ])]),
+            ),
+        ]))
+    }
+}
+
+impl Emitter for SarifEmitter<'_> {
+    fn emit(&mut self, diagnostic: &Diagnostic) -> io::Result<()> {
+        let diag = &diagnostic.diag;
+        let mut text = diag.message();
+        let mut locations = Vec::new();
+        let mut related_locations = Vec::new();
+
+        for label in diagnostic.labels() {
+            let location = self.location(label.span, label.message.as_deref());
+            if label.is_primary {
+                locations.extend(location);
+            } else {
+                related_locations.extend(location);
+            }
+        }
+
+        for child in &diagnostic.children {
+            let location = child
+                .span
+                .and_then(|span| self.location(span, Some(&child.message)));
+            match location {
+                Some(location) => related_locations.push(location),
+                None => {
+                    text.push_str(&format!(
+                        "\n{}: {}",
+                        child.severity, child.message
+                    ));
+                }
+            }
+        }
+
+        let fixes = diagnostic
+            .suggestions
+            .iter()
+            .filter_map(|suggestion| self.fix(suggestion))
+            .collect();
+
+        let mut fields = Vec::new();
+        if let Some(code) = diag.code() {
+            self.rule_ids.insert(code);
+            fields.push(("ruleId", code.into()));
+        }
+        fields.extend(vec![
+            ("level", SarifEmitter::level(diagnostic.severity).into()),
+            ("message", SarifEmitter::message(text)),
+            ("locations", JsonValue::Array(locations)),
+            ("relatedLocations", JsonValue::Array(related_locations)),
+            ("fixes", JsonValue::Array(fixes)),
+        ]);
+        self.results.push(JsonValue::object(fields));
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let rules = self
+            .rule_ids
+            .iter()
+            .map(|&id| JsonValue::object(vec![("id", id.into())]))
+            .collect();
+        let tool = JsonValue::object(vec![(
+            "driver",
+            JsonValue::object(vec![
+                ("name", "csub".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+                ("rules", JsonValue::Array(rules)),
+            ]),
+        )]);
+        let run = JsonValue::object(vec![
+            ("tool", tool),
+            (
+                "results",
+                JsonValue::Array(std::mem::take(&mut self.results)),
+            ),
+        ]);
+        let log = JsonValue::object(vec![
+            (
+                "$schema",
+                "https://json.schemastore.org/sarif-2.1.0.json".into(),
+            ),
+            ("version", "2.1.0".into()),
+            ("runs", JsonValue::Array(vec![run])),
+        ]);
+
+        writeln!(self.out, "{}", log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        char_width, display_column, new_emitter, ColorChoice, Emitter,
+        ErrorFormat, PhaseFilter, SpanLocs,
+    };
+    use crate::{
+        errors::{Diag, Diagnostic, Phase, Severity, Suggestion, Summary},
+        source_map::{BytePos, SourceFile, SourceMap, Span},
+    };
+
+    fn source_map(source_text: &str) -> SourceMap {
+        let mut source_map = SourceMap::new();
+        source_map.add_file("main.cm".into(), source_text.into());
+        source_map
+    }
+
+    fn emit_to_string(
+        format: ErrorFormat,
+        source_map: &SourceMap,
+        diagnostics: &[Diagnostic],
+    ) -> String {
+        emit_to_string_with_color(format, source_map, diagnostics, false)
+    }
+
+    fn emit_to_string_with_color(
+        format: ErrorFormat,
+        source_map: &SourceMap,
+        diagnostics: &[Diagnostic],
+        color: bool,
+    ) -> String {
+        let mut out = Vec::new();
+        {
+            let mut emitter =
+                new_emitter(format, source_map, Box::new(&mut out), color);
+            for diagnostic in diagnostics {
+                emitter.emit(diagnostic).unwrap();
+            }
+            emitter.finish().unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    fn unknown_char(pos: usize, ch: char) -> Diag {
+        Diag::UnknownCharacter {
+            pos: BytePos(pos),
+            ch,
+        }
+    }
+
+    #[test]
+    fn error_format_from_name() {
+        assert_eq!(ErrorFormat::from_name("human"), Some(ErrorFormat::Human));
+        assert_eq!(ErrorFormat::from_name("short"), Some(ErrorFormat::Short));
+        assert_eq!(ErrorFormat::from_name("json"), Some(ErrorFormat::Json));
+        assert_eq!(ErrorFormat::from_name("sarif"), Some(ErrorFormat::Sarif));
+        assert_eq!(ErrorFormat::from_name("xml"), None);
+    }
+
+    #[test]
+    fn color_choice_from_name_and_environment() {
+        assert_eq!(ColorChoice::from_name("never"), Some(ColorChoice::Never));
+        assert_eq!(ColorChoice::from_name("yes"), None);
+
+        assert!(ColorChoice::Auto.should_color(true, false));
+        assert!(!ColorChoice::Auto.should_color(false, false));
+        assert!(!ColorChoice::Auto.should_color(true, true));
+        assert!(ColorChoice::Always.should_color(false, true));
+        assert!(!ColorChoice::Never.should_color(true, false));
+    }
+
+    #[test]
+    fn resolve_span_locs_on_a_single_line() {
+        let source_file = SourceFile::new("a.cm".into(), "ab\ncdef\n".into());
+
+        assert_eq!(
+            SpanLocs::resolve(&source_file, Span::with_usizes(4, 6)),
+            SpanLocs {
+                line_start: 2,
+                column_start: 2,
+                line_end: 2,
+                column_end: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn resolve_empty_span_at_end_of_input() {
+        let source_file = SourceFile::new("a.cm".into(), "ab\ncd".into());
+
+        assert_eq!(
+            SpanLocs::resolve(&source_file, Span::with_usizes(5, 5)),
+            SpanLocs {
+                line_start: 2,
+                column_start: 3,
+                line_end: 2,
+                column_end: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn human_emitter_renders_snippet_with_caret() {
+        let source_map = source_map("int x;\nx = @;\n");
+
+        let output = emit_to_string(
+            ErrorFormat::Human,
+            &source_map,
+            &[unknown_char(11, '@').into()],
+        );
+
+        assert_eq!(
+            output,
+            "error[E0001]: unknown character '@'\n \
+             --> main.cm:2:5\n  \
+             |\n\
+             2 | x = @;\n  \
+             |     ^\n\n"
+        );
+    }
+
+    #[test]
+    fn human_emitter_renders_labels_on_different_lines() {
+        let source_map = source_map("int x;\nint y;\nint z;\nx = @;\n");
+        let diagnostic = Diagnostic::builder(unknown_char(25, '@'))
+            .with_label(Span::with_usizes(4, 5), "declared here")
+            .with_label(Span::with_usizes(7, 10), "and this")
+            .build();
+
+        let output =
+            emit_to_string(ErrorFormat::Human, &source_map, &[diagnostic]);
+
+        assert_eq!(
+            output,
+            "error[E0001]: unknown character '@'\n \
+             --> main.cm:4:5\n  \
+             |\n\
+             1 | int x;\n  \
+             |     - declared here\n\
+             2 | int y;\n  \
+             | --- and this\n\
+             ...\n\
+             4 | x = @;\n  \
+             |     ^\n\n"
+        );
+    }
+
+    #[test]
+    fn human_emitter_hangs_messages_of_labels_on_the_same_line() {
+        let source_map = source_map("/* abc */ x @\n");
+        let diagnostic = Diagnostic::builder(unknown_char(12, '@'))
+            .with_label(Span::with_usizes(0, 2), "comment started here")
+            .with_label(Span::with_usizes(10, 11), "after this")
+            .build();
+
+        let output =
+            emit_to_string(ErrorFormat::Human, &source_map, &[diagnostic]);
+
+        assert_eq!(
+            output,
+            "error[E0001]: unknown character '@'\n \
+             --> main.cm:1:13\n  \
+             |\n\
+             1 | /* abc */ x @\n  \
+             | --        - ^\n  \
+             | |         |\n  \
+             | |         after this\n  \
+             | |\n  \
+             | comment started here\n\n"
+        );
+    }
+
+    #[test]
+    fn human_emitter_widens_gutter_for_large_line_numbers() {
+        let source_text = "\n".repeat(9) + "@\n";
+        let source_map = source_map(&source_text);
+
+        let output = emit_to_string(
+            ErrorFormat::Human,
+            &source_map,
+            &[unknown_char(9, '@').into()],
+        );
+
+        assert_eq!(
+            output,
+            "error[E0001]: unknown character '@'\n  \
+             --> main.cm:10:1\n   \
+             |\n\
+             10 | @\n   \
+             | ^\n\n"
+        );
+    }
+
+    #[test]
+    fn json_emitter_includes_secondary_labels() {
+        let source_map = source_map("x @\n");
+        let diagnostic = Diagnostic::builder(unknown_char(2, '@'))
+            .with_label(Span::with_usizes(0, 1), "here")
+            .build();
+
+        let output =
+            emit_to_string(ErrorFormat::Json, &source_map, &[diagnostic]);
+
+        assert!(output.contains(
+            r#""spans":[{"file":"main.cm","byte_start":2,"byte_end":3,"line_start":1,"column_start":3,"line_end":1,"column_end":4,"is_primary":true,"label":null},{"file":"main.cm","byte_start":0,"byte_end":1,"line_start":1,"column_start":1,"line_end":1,"column_end":2,"is_primary":false,"label":"here"}]"#
+        ));
+    }
+
+    #[test]
+    fn phase_filter_drops_diagnostics_from_other_phases() {
+        let source_map = source_map("x @\n");
+        let diagnostics: Vec<Diagnostic> = vec![
+            unknown_char(2, '@').into(),
+            Diag::InternalCompilerError {
+                message: "oops".into(),
+                span: None,
+                phase: Some(Phase::Sema),
+            }
+            .into(),
+            Diag::TooManyErrors { limit: 1 }.into(),
+        ];
+
+        let mut out = Vec::new();
+        {
+            let mut emitter = PhaseFilter {
+                inner: new_emitter(
+                    ErrorFormat::Short,
+                    &source_map,
+                    Box::new(&mut out),
+                    false,
+                ),
+                phase: Phase::Sema,
+            };
+            for diagnostic in &diagnostics {
+                emitter.emit(diagnostic).unwrap();
+            }
+        }
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "fatal error: internal compiler error: oops\n\
+             note: too many errors emitted, stopping now (the limit is 1)\n"
+        );
+    }
+
+    #[test]
+    fn json_emitter_writes_one_object_per_line() {
+        let source_map = source_map("int x;\nx = @;\n");
+
+        let output = emit_to_string(
+            ErrorFormat::Json,
+            &source_map,
+            &[unknown_char(11, '@').into(), unknown_char(0, '"').into()],
+        );
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"code":"E0001","severity":"error","phase":"lex","message":"unknown character '@'","file":"main.cm","spans":[{"file":"main.cm","byte_start":11,"byte_end":12,"line_start":2,"column_start":5,"line_end":2,"column_end":6,"is_primary":true,"label":null}],"children":[],"suggestions":[]}"#,
+                r#"{"code":"E0001","severity":"error","phase":"lex","message":"unknown character '\"'","file":"main.cm","spans":[{"file":"main.cm","byte_start":0,"byte_end":1,"line_start":1,"column_start":1,"line_end":1,"column_end":2,"is_primary":true,"label":null}],"children":[],"suggestions":[]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn human_emitter_renders_diagnostics_without_span() {
+        let source_map = SourceMap::new();
+        let diagnostic: Diagnostic = Diag::CannotReadFile {
+            path: "main.cm".into(),
+            reason: "No such file or directory".into(),
+        }
+        .into();
+
+        let output =
+            emit_to_string(ErrorFormat::Human, &source_map, &[diagnostic]);
+
+        assert_eq!(
+            output,
+            "fatal error[E0007]: couldn't read `main.cm`: No such file or \
+             directory\n\n"
+        );
+    }
+
+    #[test]
+    fn human_emitter_renders_diagnostics_without_code() {
+        let source_map = SourceMap::new();
+        let diagnostic: Diagnostic = Diag::TooManyErrors { limit: 3 }.into();
+
+        let output =
+            emit_to_string(ErrorFormat::Human, &source_map, &[diagnostic]);
+
+        assert_eq!(
+            output,
+            "note: too many errors emitted, stopping now (the limit is 3)\n\n"
+        );
+    }
+
+    #[test]
+    fn human_emitter_renders_labels_of_other_files_in_own_section() {
+        let mut source_map = SourceMap::new();
+        source_map.add_file("a.cm".into(), "int x;\n".into());
+        source_map.add_file("b.cm".into(), "x @\n".into());
+        let diagnostic = Diagnostic::builder(unknown_char(10, '@'))
+            .with_label(Span::with_usizes(4, 5), "x")
+            .build();
+
+        let output =
+            emit_to_string(ErrorFormat::Human, &source_map, &[diagnostic]);
+
+        assert_eq!(
+            output,
+            "error[E0001]: unknown character '@'\n \
+             --> b.cm:1:3\n  \
+             |\n\
+             1 | x @\n  \
+             |   ^\n \
+             ::: a.cm:1:5\n  \
+             |\n\
+             1 | int x;\n  \
+             |     - x\n\n"
+        );
+    }
+
+    #[test]
+    fn json_emitter_uses_offsets_relative_to_the_file() {
+        let mut source_map = SourceMap::new();
+        source_map.add_file("a.cm".into(), "int x;\n".into());
+        source_map.add_file("b.cm".into(), "x @\n".into());
+
+        let output = emit_to_string(
+            ErrorFormat::Json,
+            &source_map,
+            &[unknown_char(10, '@').into()],
+        );
+
+        assert!(output.contains(r#""file":"b.cm","spans":[{"file":"b.cm","byte_start":2,"byte_end":3,"#));
+    }
+
+    #[test]
+    fn json_emitter_writes_null_file_for_diagnostics_without_span() {
+        let source_map = SourceMap::new();
+        let diagnostic: Diagnostic = Diag::TooManyErrors { limit: 3 }.into();
+
+        let output =
+            emit_to_string(ErrorFormat::Json, &source_map, &[diagnostic]);
+
+        assert_eq!(
+            output,
+            "{\"code\":null,\"severity\":\"note\",\"phase\":null,\
+             \"message\":\"too many \
+             errors emitted, stopping now (the limit is 3)\",\"file\":null,\
+             \"spans\":[],\"children\":[],\"suggestions\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn human_emitter_renders_children_under_the_parent() {
+        let source_map = source_map("int x;\nint y;\nx @\n");
+        let diagnostic = Diagnostic::builder(unknown_char(16, '@'))
+            .with_note(Some(Span::with_usizes(4, 5)), "x declared here")
+            .with_help(None, "remove the character")
+            .build();
+
+        let output =
+            emit_to_string(ErrorFormat::Human, &source_map, &[diagnostic]);
+
+        assert_eq!(
+            output,
+            "error[E0001]: unknown character '@'\n \
+             --> main.cm:3:3\n  \
+             |\n\
+             3 | x @\n  \
+             |   ^\n  \
+             = note: x declared here\n \
+             --> main.cm:1:5\n  \
+             |\n\
+             1 | int x;\n  \
+             |     -\n  \
+             = help: remove the character\n\n"
+        );
+    }
+
+    #[test]
+    fn json_emitter_includes_children() {
+        let source_map = source_map("x @\n");
+        let diagnostic = Diagnostic::builder(unknown_char(2, '@'))
+            .with_note(Some(Span::with_usizes(0, 1)), "here")
+            .with_help(None, "remove it")
+            .build();
+
+        let output =
+            emit_to_string(ErrorFormat::Json, &source_map, &[diagnostic]);
+
+        assert!(output.contains(
+            r#""children":[{"severity":"note","message":"here","spans":[{"file":"main.cm","byte_start":0,"byte_end":1,"line_start":1,"column_start":1,"line_end":1,"column_end":2,"is_primary":false,"label":null}]},{"severity":"help","message":"remove it","spans":[]}]"#
+        ));
+    }
+
+    #[test]
+    fn human_emitter_renders_suggestions_as_help() {
+        let source_map = source_map("x @\n");
+        let diagnostic = Diagnostic::builder(unknown_char(2, '@'))
+            .with_suggestion(Suggestion::removal(
+                Span::with_usizes(2, 3),
+                "remove the character",
+            ))
+            .build();
+
+        let output =
+            emit_to_string(ErrorFormat::Human, &source_map, &[diagnostic]);
+
+        assert_eq!(
+            output,
+            "error[E0001]: unknown character '@'\n \
+             --> main.cm:1:3\n  \
+             |\n\
+             1 | x @\n  \
+             |   ^\n  \
+             = help: remove the character\n\n"
+        );
+    }
+
+    #[test]
+    fn json_emitter_includes_suggestions() {
+        let source_map = source_map("x @\n");
+        let diagnostic = Diagnostic::builder(unknown_char(2, '@'))
+            .with_suggestion(Suggestion::insertion(BytePos(3), ";", "add `;`"))
+            .build();
+
+        let output =
+            emit_to_string(ErrorFormat::Json, &source_map, &[diagnostic]);
+
+        assert!(output.contains(
+            r#""suggestions":[{"message":"add `;`","applicability":"machine-applicable","file":"main.cm","byte_start":3,"byte_end":3,"line_start":1,"column_start":4,"line_end":1,"column_end":4,"replacement":";"}]"#
+        ));
+    }
+
+    #[test]
+    fn sarif_emitter_writes_a_single_log() {
+        let source_map = source_map("x @\n#\n");
+
+        let output = emit_to_string(
+            ErrorFormat::Sarif,
+            &source_map,
+            &[unknown_char(2, '@').into(), unknown_char(4, '#').into()],
+        );
+
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.starts_with(
+            r#"{"$schema":"https://json.schemastore.org/sarif-2.1.0.json","version":"2.1.0","runs":[{"tool":{"driver":{"name":"csub","version":""#
+        ));
+        assert!(output.contains(r#""rules":[{"id":"E0001"}]"#));
+        assert!(output.contains(
+            r#"{"ruleId":"E0001","level":"error","message":{"text":"unknown character '#'"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":"main.cm"},"region":{"startLine":2,"startColumn":1,"endLine":2,"endColumn":2}}}],"relatedLocations":[],"fixes":[]}"#
+        ));
+    }
+
+    #[test]
+    fn sarif_emitter_maps_labels_children_and_suggestions() {
+        let source_map = source_map("x @\n");
+        let diagnostic = Diagnostic::builder(unknown_char(2, '@'))
+            .with_severity(Severity::Warning)
+            .with_label(Span::with_usizes(0, 1), "here")
+            .with_help(None, "remove it")
+            .with_suggestion(Suggestion::removal(
+                Span::with_usizes(2, 3),
+                "remove the character",
+            ))
+            .build();
+
+        let output =
+            emit_to_string(ErrorFormat::Sarif, &source_map, &[diagnostic]);
+
+        assert!(output.contains(
+            r#""level":"warning","message":{"text":"unknown character '@'\nhelp: remove it"}"#
+        ));
+        assert!(output.contains(
+            r#""relatedLocations":[{"physicalLocation":{"artifactLocation":{"uri":"main.cm"},"region":{"startLine":1,"startColumn":1,"endLine":1,"endColumn":2}},"message":{"text":"here"}}]"#
+        ));
+        assert!(output.contains(
+            r#""fixes":[{"description":{"text":"remove the character"},"artifactChanges":[{"artifactLocation":{"uri":"main.cm"},"replacements":[{"deletedRegion":{"startLine":1,"startColumn":3,"endLine":1,"endColumn":4},"insertedContent":{"text":""}}]}]}]"#
+        ));
+    }
+
+    #[test]
+    fn sarif_emitter_omits_rule_id_of_diagnostics_without_code() {
+        let source_map = source_map("");
+
+        let output = emit_to_string(
+            ErrorFormat::Sarif,
+            &source_map,
+            &[Diag::TooManyErrors { limit: 3 }.into()],
+        );
+
+        assert!(output.contains(r#""rules":[]"#));
+        assert!(output.contains(
+            r#""results":[{"level":"note","message":{"text":"too many errors emitted, stopping now (the limit is 3)"},"locations":[]"#
+        ));
+    }
+
+    #[test]
+    fn human_emitter_colors_output() {
+        let source_map = source_map("x @\n");
+        let diagnostic = Diagnostic::builder(unknown_char(2, '@'))
+            .with_label(Span::with_usizes(0, 1), "here")
+            .build();
+
+        let output = emit_to_string_with_color(
+            ErrorFormat::Human,
+            &source_map,
+            &[diagnostic],
+            true,
+        );
+
+        assert_eq!(
+            output,
+            "\x1b[1;31merror[E0001]\x1b[0m\x1b[1m: unknown character '@'\x1b[0m\n \
+             \x1b[1;34m-->\x1b[0m main.cm:1:3\n\
+             \x1b[1;34m  |\x1b[0m\n\
+             \x1b[1;34m1 |\x1b[0m x @\n\
+             \x1b[1;34m  |\x1b[0m \x1b[1;34m-\x1b[0m \x1b[1;31m^\x1b[0m\n\
+             \x1b[1;34m  |\x1b[0m \x1b[1;34m|\x1b[0m\n\
+             \x1b[1;34m  |\x1b[0m \x1b[1;34mhere\x1b[0m\n\n"
+        );
+    }
+
+    #[test]
+    fn json_emitter_is_never_colored() {
+        let source_map = source_map("@");
+
+        let output = emit_to_string_with_color(
+            ErrorFormat::Json,
+            &source_map,
+            &[unknown_char(0, '@').into()],
+            true,
+        );
+
+        assert!(!output.contains('\x1b'));
+    }
+
+    #[test]
+    fn width_of_characters() {
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('\t'), 4);
+        assert_eq!(char_width('中'), 2);
+        assert_eq!(char_width('🎉'), 2);
+        assert_eq!(char_width('\u{301}'), 0);
+    }
+
+    #[test]
+    fn display_column_of_byte_columns() {
+        assert_eq!(display_column("ab", 1), 1);
+        assert_eq!(display_column("\tx", 1), 4);
+        assert_eq!(display_column("中x", 3), 2);
+        assert_eq!(display_column("ab", 3), 3);
+    }
+
+    #[test]
+    fn human_emitter_aligns_carets_after_tabs() {
+        let source_map = source_map("\tx @;\n");
+
+        let output = emit_to_string(
+            ErrorFormat::Human,
+            &source_map,
+            &[unknown_char(3, '@').into()],
+        );
+
+        assert_eq!(
+            output,
+            "error[E0001]: unknown character '@'\n \
+             --> main.cm:1:4\n  \
+             |\n\
+             1 |     x @;\n  \
+             |       ^\n\n"
+        );
+    }
+
+    #[test]
+    fn human_emitter_aligns_carets_after_wide_characters() {
+        // The comment holds two CJK ideographs, 3 bytes and 2 columns each.
+        let source_map = source_map("/*中文*/ @\n");
+
+        let output = emit_to_string(
+            ErrorFormat::Human,
+            &source_map,
+            &[unknown_char(11, '@').into()],
+        );
+
+        assert_eq!(
+            output,
+            "error[E0001]: unknown character '@'\n \
+             --> main.cm:1:12\n  \
+             |\n\
+             1 | /*中文*/ @\n  \
+             |          ^\n\n"
+        );
+    }
+
+    #[test]
+    fn human_emitter_underlines_wide_characters_fully() {
+        let source_map = source_map("x 中\n");
+        let diagnostic: Diagnostic = Diag::NonAsciiCharacter {
+            pos: BytePos(2),
+            ch: '中',
+        }
+        .into();
+
+        let output =
+            emit_to_string(ErrorFormat::Human, &source_map, &[diagnostic]);
+
+        assert!(output.contains(
+            "1 | x 中\n  |   ^^ only ASCII characters are allowed outside comments\n"
+        ));
+    }
+
+    #[test]
+    fn human_emitter_writes_summary_line() {
+        let source_map = source_map("");
+        let summary = Summary {
+            errors: 3,
+            warnings: 2,
+            ..Summary::default()
+        };
+        let mut out = Vec::new();
+        {
+            let mut emitter = new_emitter(
+                ErrorFormat::Human,
+                &source_map,
+                Box::new(&mut out),
+                false,
+            );
+            emitter.emit_summary(&summary).unwrap();
+            emitter.emit_summary(&Summary::default()).unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "error: aborting due to 3 previous errors; 2 warnings emitted\n"
+        );
+    }
+
+    #[test]
+    fn json_emitter_writes_no_summary_line() {
+        let source_map = source_map("");
+        let mut out = Vec::new();
+        {
+            let mut emitter = new_emitter(
+                ErrorFormat::Json,
+                &source_map,
+                Box::new(&mut out),
+                false,
+            );
+            emitter
+                .emit_summary(&Summary {
+                    errors: 1,
+                    ..Summary::default()
+                })
+                .unwrap();
+        }
+
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn short_emitter_writes_one_line_per_diagnostic() {
+        let source_map = source_map("int x;\nx = @;\n");
+        let diagnostics = [
+            Diagnostic::builder(unknown_char(11, '@'))
+                .with_note(Some(Span::with_usizes(4, 5)), "x declared here")
+                .with_help(None, "remove it")
+                .build(),
+            Diag::TooManyErrors { limit: 1 }.into(),
+        ];
+
+        let output =
+            emit_to_string(ErrorFormat::Short, &source_map, &diagnostics);
+
+        assert_eq!(
+            output,
+            "main.cm:2:5: error[E0001]: unknown character '@'\n\
+             main.cm:1:5: note: x declared here\n\
+             help: remove it\n\
+             note: too many errors emitted, stopping now (the limit is 1)\n"
+        );
+    }
+
+    #[test]
+    fn short_emitter_colors_the_level() {
+        let source_map = source_map("@");
+
+        let output = emit_to_string_with_color(
+            ErrorFormat::Short,
+            &source_map,
+            &[unknown_char(0, '@').into()],
+            true,
+        );
+
+        assert_eq!(
+            output,
+            "main.cm:1:1: \x1b[1;31merror[E0001]\x1b[0m: unknown character '@'\n"
+        );
+    }
+}
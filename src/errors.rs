@@ -1,37 +1,2143 @@
-use crate::source_map::BytePos;
+use crate::{
+    ast::BinOp,
+    catalog::message,
+    extensions::Extension,
+    interp::{Trap, MAX_CALL_DEPTH, MAX_MEMORY},
+    lints::{Lint, WarningConfig},
+    parser::Expected,
+    scanner::{Category, Keyword},
+    source_map::{BytePos, Pos, Span},
+};
+use std::{collections::HashSet, fmt};
 
 /// A `Diag` value gathers enough information about some error in the parsing
 /// process. It is used by the diagnostics system to report good quality error
 /// messages.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Diag {
     /// Unknown character in the source code.
-    UnknownCharacter { pos: BytePos },
+    UnknownCharacter { pos: BytePos, ch: char },
+    /// `count` unknown characters in a row, e.g. `@$#`, reported at once.
+    UnknownCharacterRun { span: Span, count: usize },
+    /// A block comment starting at `start` that is still open when the input
+    /// ends at `end`.
+    UnterminatedBlockComment { start: BytePos, end: BytePos },
+    /// A number literal followed by letters, e.g. `123abc`, where `suffix` is
+    /// the span of the letters (and any digits following them).
+    InvalidNumberLiteral { number: Span, suffix: Span },
+    /// A non-ASCII character outside of comments.
+    NonAsciiCharacter { pos: BytePos, ch: char },
+    /// `count` non-ASCII characters in a row outside of comments, e.g. a
+    /// pasted emoji or “smart quotes”, reported at once.
+    NonAsciiCharacterRun { span: Span, count: usize },
+    /// An identifier with more than `MAX_IDENTIFIER_LENGTH` characters.
+    IdentifierTooLong { span: Span },
+    /// A string literal starting at `start` that is still open when its line
+    /// (or the input) ends at `end`.
+    UnterminatedString { start: BytePos, end: BytePos },
+    /// The same for a character literal.
+    UnterminatedCharLiteral { start: BytePos, end: BytePos },
+    /// An escape sequence in a literal that isn't one, e.g. `\q`, where `ch`
+    /// is the character after the backslash.
+    UnknownEscape { span: Span, ch: char },
+    /// A character literal holding `len` characters instead of one, e.g.
+    /// `''` or `'ab'`.
+    InvalidCharLiteral { span: Span, len: usize },
+    /// A word that can't go where it was found, e.g. the `)` in `x = );`.
+    UnexpectedToken {
+        expected: Expected,
+        found: Category,
+        span: Span,
+    },
+    /// A `token`, e.g. a `;`, missing right after the word ending at `pos`,
+    /// `found` coming there instead. `at_line_end` is whether that word is the
+    /// last of its line, in which case the missing token surely goes after it.
+    MissingToken {
+        token: Category,
+        found: Category,
+        pos: BytePos,
+        at_line_end: bool,
+    },
+    /// An array variable declared without a size, e.g. `int a[];`, where
+    /// `span` covers the brackets.
+    MissingArraySize { name: String, span: Span },
+    /// Words between the declaration of the variable `name` and its `;`,
+    /// e.g. the `= 1` in `int x = 1;`. `found` is the first of them, `span`
+    /// covers them all, and `after` is where the declaration should end.
+    StrayTokens {
+        name: String,
+        found: Category,
+        after: BytePos,
+        span: Span,
+    },
+    /// A parameter list with nothing in it, `()`, which in C- has to be
+    /// `(void)`. `pos` is where the `void` goes.
+    EmptyParamList { pos: BytePos },
+    /// A `void` next to other parameters, e.g. `int f(int a, void)`.
+    /// `removal` is the text to remove it along with its comma.
+    VoidParamNotAlone { span: Span, removal: Span },
+    /// A local declaration after the first statement of its block, e.g.
+    /// `x = 1; int y;`, which has to come before it. `first_stmt` is the
+    /// first word of that statement.
+    DeclarationAfterStatement { span: Span, first_stmt: Span },
+    /// An `else` that doesn't follow the statement of an `if`.
+    ElseWithoutIf { span: Span },
+    /// The condition of an `if` or a `while` written without parentheses,
+    /// e.g. `while x < 10`. `text` is the source of the condition.
+    ConditionWithoutParens {
+        keyword: Keyword,
+        span: Span,
+        text: String,
+    },
+    /// An assignment to something other than a variable or an element of an
+    /// array, e.g. `f(x) = 1`. `equal` is the span of the `=`.
+    InvalidAssignTarget { span: Span, equal: Span },
+    /// An assignment as the condition of an `if` or a loop, e.g.
+    /// `if (x = 1)`, where `==` was likely meant. `equal` is the span of the
+    /// `=`, and `text` the source of the assignment. One in parentheses of
+    /// its own, e.g. `if ((x = 1))`, is taken to be meant.
+    AssignInCondition {
+        span: Span,
+        equal: Span,
+        text: String,
+    },
+    /// A comparison operator written the wrong way around, e.g. `=<` for
+    /// `op`, `<=`. It's parsed as `op`, to go on parsing.
+    ReversedComparison { span: Span, op: BinOp },
+    /// The operand of an `&` that has no address, e.g. `&1`.
+    InvalidAddressOf { span: Span },
+    /// A construct from `extension`, e.g. the `-` of `-x`, in a program
+    /// compiled without it.
+    ExtensionNotEnabled { extension: Extension, span: Span },
+    /// A `break` or `continue` that isn't in a loop.
+    LoopControlOutsideLoop { keyword: Keyword, span: Span },
+    /// A `case` of a `switch` with the same value as one before it, or a
+    /// second `default` if `value` is `None`. `first` is the label of the
+    /// one before it.
+    DuplicateCase {
+        value: Option<i64>,
+        span: Span,
+        first: Span,
+    },
+    /// A `case` whose value isn't a constant expression, e.g. `case x:`.
+    NonConstantCase { span: Span },
+    /// The initializer of the global variable `name` isn't a constant
+    /// expression, e.g. `int y = x;`.
+    NonConstantInitializer { name: String, span: Span },
+    /// The array `name` has more elements than fit in 64 bits. `span` is
+    /// the span of its sizes.
+    ArrayTooLarge { name: String, span: Span },
+    /// A `struct name` used before any `struct name { ... };` defines it.
+    UnknownStruct { name: String, span: Span },
+    /// An assignment to the `const` variable `name`, or to one of its
+    /// elements or fields. `decl` is the name in its declaration.
+    AssignToConst {
+        name: String,
+        span: Span,
+        decl: Span,
+    },
+    /// A second field called `name` in the same `struct`. `first` is the
+    /// name of the first one.
+    DuplicateField {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    /// A second parameter called `name` of the same function. `first` is
+    /// the name of the first one.
+    DuplicateParam {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    /// A use of the name `name`, which isn't declared in any scope it's in.
+    UndeclaredName { name: String, span: Span },
+    /// A second declaration of `name` in the same scope. `previous` is the
+    /// name in the first one.
+    Redeclaration {
+        name: String,
+        span: Span,
+        previous: Span,
+    },
+    /// An argument of a call of `callee` that's an array where its parameter
+    /// isn't, or the other way around. `param` is the parameter.
+    ArrayArgumentMismatch {
+        callee: String,
+        param_is_array: bool,
+        span: Span,
+        param: Span,
+    },
+    /// A variable or parameter called `name` of type `void`, or an array of
+    /// `void` if `is_array`.
+    VoidVariable {
+        name: String,
+        is_array: bool,
+        span: Span,
+    },
+    /// The value of a call of `callee`, which returns `void`, used in an
+    /// expression.
+    VoidValueUsed { callee: String, span: Span },
+    /// Statements at `span` that never run. They're after the `return`,
+    /// `break` or `continue` at `cause`, or in a branch or loop that the
+    /// condition at `cause`, which is always `condition`, keeps from running.
+    UnreachableCode {
+        span: Span,
+        cause: Span,
+        condition: Option<bool>,
+    },
+    /// An assignment at `span` to the local variable `name`, whose value is
+    /// never read, as the variable is assigned again or the function
+    /// returns first.
+    DeadStore { name: String, span: Span },
+    /// A size of the array `name` that isn't a constant expression, e.g.
+    /// `int a[n];`.
+    NonConstantArraySize { name: String, span: Span },
+    /// A size of the array `name` that's `value`, zero or negative.
+    ArraySizeNotPositive {
+        name: String,
+        value: i64,
+        span: Span,
+    },
+    /// An assignment to a function, or to an array if `is_array`, as a
+    /// whole, e.g. `f = 1`. `name` is the variable or function the target
+    /// is of, and `decl` its name in its declaration, if it has one.
+    UnassignableTarget {
+        name: String,
+        is_array: bool,
+        span: Span,
+        decl: Option<Span>,
+    },
+    /// An operation in a constant expression whose result doesn't fit in an
+    /// `int`, e.g. `2147483647 + 1`.
+    ConstOverflow { span: Span },
+    /// A division in a constant expression whose divisor, `divisor`, is
+    /// zero.
+    ConstDivisionByZero { span: Span, divisor: Span },
+    /// A value of type `found` assigned, passed or returned where a value of
+    /// type `expected` is, which it can't become. `discards_const` if it
+    /// could, but for losing a `const` of what it points to. `because` is
+    /// what expects the type, e.g. the parameter it's passed as.
+    TypeMismatch {
+        expected: String,
+        found: String,
+        discards_const: bool,
+        span: Span,
+        because: Option<Span>,
+    },
+    /// A use of the function `name` as a value, e.g. `x = f;`, where only
+    /// a call of it is. `decl` is its name in its declaration, if it has
+    /// one.
+    NotAVariable {
+        name: String,
+        span: Span,
+        decl: Option<Span>,
+    },
+    /// A call of the variable `name`, e.g. `x(2)`, as only functions are
+    /// called. `decl` is its name in its declaration, if it has one.
+    NotAFunction {
+        name: String,
+        span: Span,
+        decl: Option<Span>,
+    },
+    /// A call of `callee` with `found` arguments where it has `expected`
+    /// parameters. `params` is its parameter list, if it's declared in the
+    /// program.
+    ArgumentCountMismatch {
+        callee: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+        params: Option<Span>,
+    },
+    /// A local variable, or a parameter if `is_param`, called `name` that's
+    /// never used after it's declared at `span`.
+    UnusedVariable {
+        name: String,
+        is_param: bool,
+        span: Span,
+    },
+    /// A declaration of `name` that hides the one at `previous`, of an
+    /// enclosing scope.
+    Shadowing {
+        name: String,
+        span: Span,
+        previous: Span,
+    },
+    /// An index into a value of type `ty`, which is neither an array nor a
+    /// pointer.
+    NotAnArray { ty: String, span: Span },
+    /// A use of the field `field` of a value of type `ty`, which isn't a
+    /// struct with one.
+    UnknownField {
+        field: String,
+        ty: String,
+        span: Span,
+    },
+    /// A `*` applied to a value of type `ty`, which isn't a pointer.
+    NotAPointer { ty: String, span: Span },
+    /// What stopped a program running with `csub run` at `span`, e.g. a
+    /// division by zero.
+    RuntimeError { trap: Trap, span: Span },
+    /// What stopped bytecode running with `csub run` in the function `fun`,
+    /// where there's no source to point at, but the line a check is at.
+    BytecodeRuntimeError {
+        trap: Trap,
+        fun: String,
+        line: Option<usize>,
+    },
+    /// A program without a `main` function to start running from.
+    MissingMain,
+    /// A `main` function declared other than as `void main(void)`, where
+    /// `span` is from its return type to its parameter list.
+    MainSignature { span: Span },
+    /// A declaration after `main`, which the reference grammar asks to be the
+    /// last one. `span` is the name of `main`, and `after` the name in the
+    /// first declaration after it.
+    MainNotLast { span: Span, after: Span },
+    /// A `do`-`while` without the `;` after its condition, which should be
+    /// at `pos`. `do_span` is the span of the `do`.
+    DoWhileWithoutSemicolon {
+        do_span: Span,
+        pos: BytePos,
+        at_line_end: bool,
+    },
+    /// A comma after the last argument of a call, e.g. `f(a, b,)`.
+    TrailingComma { span: Span },
+    /// A comparison whose operand is another comparison, e.g. `a < b < c`,
+    /// where `first` and `second` are the spans of the two operators.
+    ChainedComparison { first: Span, second: Span },
+    /// Statements or expressions nested more than `MAX_NESTING_DEPTH` levels
+    /// deep, where `span` is the word that went over the limit.
+    NestingTooDeep { span: Span },
+    /// An input file couldn't be read, e.g. because it doesn't exist.
+    CannotReadFile { path: String, reason: String },
+    /// A file given as bytecode to run isn't, or can't be run, e.g. as it
+    /// was built by another version of the compiler.
+    InvalidBytecode { path: String, reason: String },
+    /// A file couldn't be written, e.g. when applying fixes to it.
+    CannotWriteFile { path: String, reason: String },
+    /// The object of `csub build --bin` couldn't be linked into the
+    /// executable at `path`, e.g. as there's no `cc`, with what the linker
+    /// said in `reason`.
+    LinkFailed { path: String, reason: String },
+    /// `count` suggested edits were applied to the file at `path`.
+    FixesApplied { path: String, count: usize },
+    /// Compilation stopped because `limit` errors were emitted.
+    TooManyErrors { limit: usize },
+    /// The compiler panicked, which is a bug in the compiler rather than in
+    /// the program. `span` is what the compiler was working on and `phase`
+    /// the phase it was in, if known.
+    InternalCompilerError {
+        message: String,
+        span: Option<Span>,
+        phase: Option<Phase>,
+    },
 }
 
+/// The maximum number of characters in an identifier, the same limit C99
+/// guarantees to be significant for internal identifiers.
+pub(crate) const MAX_IDENTIFIER_LENGTH: usize = 63;
+
+/// How deep statements and expressions can be nested, where each operator of
+/// a chain, e.g. `a + b + c`, is a level. Well beyond anything written by
+/// hand, and low enough for the recursion of the parser and of the passes
+/// after it to fit the stack.
+pub(crate) const MAX_NESTING_DEPTH: usize = 256;
+
+impl Diag {
+    /// A stable code identifying the kind of diagnostic, so that tools can
+    /// match on it without parsing messages.
+    /// Purely informative diagnostics have no code.
+    pub(crate) fn code(&self) -> Option<&'static str> {
+        let code = match self {
+            Diag::UnknownCharacter { .. }
+            | Diag::UnknownCharacterRun { .. } => "E0001",
+            Diag::UnterminatedBlockComment { .. } => "E0002",
+            Diag::InvalidNumberLiteral { .. } => "E0003",
+            Diag::NonAsciiCharacter { .. }
+            | Diag::NonAsciiCharacterRun { .. } => "E0004",
+            Diag::IdentifierTooLong { .. } => "E0005",
+            Diag::UnterminatedString { .. } => "E0006",
+            Diag::UnterminatedCharLiteral { .. } => "E0029",
+            Diag::UnknownEscape { .. } => "E0030",
+            Diag::InvalidCharLiteral { .. } => "E0031",
+            Diag::UnexpectedToken { .. } => "E0009",
+            Diag::MissingToken { .. } => "E0010",
+            Diag::NestingTooDeep { .. } => "E0011",
+            Diag::ChainedComparison { .. } => "E0012",
+            Diag::MissingArraySize { .. } => "E0013",
+            Diag::StrayTokens { .. } => "E0014",
+            Diag::EmptyParamList { .. } => "E0015",
+            Diag::VoidParamNotAlone { .. } => "E0016",
+            Diag::DeclarationAfterStatement { .. } => "E0017",
+            Diag::ElseWithoutIf { .. } => "E0018",
+            Diag::ConditionWithoutParens { .. } => "E0019",
+            Diag::TrailingComma { .. } => "E0020",
+            Diag::InvalidAssignTarget { .. } => "E0021",
+            Diag::InvalidAddressOf { .. } => "E0032",
+            Diag::ExtensionNotEnabled { .. } => "E0022",
+            Diag::LoopControlOutsideLoop { .. } => "E0023",
+            Diag::DoWhileWithoutSemicolon { .. } => "E0024",
+            Diag::DuplicateCase { .. } => "E0025",
+            Diag::NonConstantCase { .. } => "E0026",
+            Diag::NonConstantInitializer { .. } => "E0027",
+            Diag::ArrayTooLarge { .. } => "E0028",
+            Diag::UnknownStruct { .. } => "E0033",
+            Diag::DuplicateField { .. } => "E0034",
+            Diag::DuplicateParam { .. } => "E0050",
+            Diag::AssignToConst { .. } => "E0035",
+            Diag::AssignInCondition { .. } => "E0036",
+            Diag::ReversedComparison { .. } => "E0037",
+            Diag::UndeclaredName { .. } => "E0038",
+            Diag::Redeclaration { .. } => "E0039",
+            Diag::MissingMain => "E0040",
+            Diag::MainSignature { .. } => "E0041",
+            Diag::MainNotLast { .. } => "E0042",
+            Diag::ArrayArgumentMismatch { .. } => "E0043",
+            Diag::VoidVariable { .. } => "E0044",
+            Diag::VoidValueUsed { .. } => "E0045",
+            Diag::UnreachableCode { .. } => "E0046",
+            Diag::DeadStore { .. } => "E0054",
+            Diag::NonConstantArraySize { .. } => "E0047",
+            Diag::ArraySizeNotPositive { .. } => "E0048",
+            Diag::UnassignableTarget { .. } => "E0049",
+            Diag::ConstOverflow { .. } => "E0051",
+            Diag::ConstDivisionByZero { .. } => "E0052",
+            Diag::TypeMismatch { .. } => "E0053",
+            Diag::RuntimeError { .. } | Diag::BytecodeRuntimeError { .. } => {
+                "E0055"
+            }
+            Diag::InvalidBytecode { .. } => "E0056",
+            Diag::CannotReadFile { .. } => "E0007",
+            Diag::CannotWriteFile { .. } => "E0008",
+            Diag::LinkFailed { .. } => "E0057",
+            Diag::NotAVariable { .. } => "E0058",
+            Diag::NotAFunction { .. } => "E0059",
+            Diag::ArgumentCountMismatch { .. } => "E0060",
+            Diag::UnusedVariable { .. } => "E0061",
+            Diag::Shadowing { .. } => "E0062",
+            Diag::NotAnArray { .. } => "E0063",
+            Diag::UnknownField { .. } => "E0064",
+            Diag::NotAPointer { .. } => "E0065",
+            Diag::FixesApplied { .. }
+            | Diag::TooManyErrors { .. }
+            | Diag::InternalCompilerError { .. } => return None,
+        };
+
+        Some(code)
+    }
+
+    /// The main message of the diagnostic, shown next to its severity, in
+    /// the current language of the catalog.
+    pub(crate) fn message(&self) -> String {
+        match self {
+            Diag::UnknownCharacter { ch, .. } => {
+                message("E0001", &[("ch", &format!("{:?}", ch))])
+            }
+            Diag::UnknownCharacterRun { count, .. } => {
+                message("E0001.run", &[("count", count)])
+            }
+            Diag::UnterminatedBlockComment { .. } => message("E0002", &[]),
+            Diag::InvalidNumberLiteral { .. } => message("E0003", &[]),
+            Diag::NonAsciiCharacter { ch, .. } => {
+                message("E0004", &[("ch", &format!("{:?}", ch))])
+            }
+            Diag::NonAsciiCharacterRun { count, .. } => {
+                message("E0004.run", &[("count", count)])
+            }
+            Diag::IdentifierTooLong { .. } => message("E0005", &[]),
+            Diag::UnterminatedString { .. } => message("E0006", &[]),
+            Diag::UnterminatedCharLiteral { .. } => message("E0029", &[]),
+            Diag::UnknownEscape { ch, .. } => {
+                message("E0030", &[("escape", &format!("\\{}", ch))])
+            }
+            Diag::InvalidCharLiteral { len: 0, .. } => {
+                message("E0031.empty", &[])
+            }
+            Diag::InvalidCharLiteral { len, .. } => {
+                message("E0031", &[("len", &len)])
+            }
+            Diag::UnexpectedToken {
+                expected, found, ..
+            } => message(
+                "E0009",
+                &[
+                    ("expected", &expected.describe()),
+                    ("found", &found.describe()),
+                ],
+            ),
+            Diag::MissingToken { token, found, .. } => message(
+                "E0010",
+                &[("token", &token.describe()), ("found", &found.describe())],
+            ),
+            Diag::NestingTooDeep { .. } => message("E0011", &[]),
+            Diag::ChainedComparison { .. } => message("E0012", &[]),
+            Diag::MissingArraySize { name, .. } => {
+                message("E0013", &[("name", name)])
+            }
+            Diag::StrayTokens { name, found, .. } => message(
+                "E0014",
+                &[("name", name), ("found", &found.describe())],
+            ),
+            Diag::EmptyParamList { .. } => message("E0015", &[]),
+            Diag::VoidParamNotAlone { .. } => message("E0016", &[]),
+            Diag::DeclarationAfterStatement { .. } => message("E0017", &[]),
+            Diag::ElseWithoutIf { .. } => message("E0018", &[]),
+            Diag::TrailingComma { .. } => message("E0020", &[]),
+            Diag::InvalidAssignTarget { .. } => message("E0021", &[]),
+            Diag::AssignInCondition { .. } => message("E0036", &[]),
+            Diag::ReversedComparison { op, .. } => {
+                let written: String = op.as_str().chars().rev().collect();
+                message("E0037", &[("written", &written)])
+            }
+            Diag::InvalidAddressOf { .. } => message("E0032", &[]),
+            Diag::ExtensionNotEnabled { extension, .. } => {
+                message("E0022", &[("feature", &extension.describe())])
+            }
+            Diag::LoopControlOutsideLoop {
+                keyword: Keyword::Break,
+                ..
+            } => message("E0023.break", &[]),
+            Diag::LoopControlOutsideLoop { keyword, .. } => {
+                message("E0023", &[("keyword", &keyword.as_str())])
+            }
+            Diag::DoWhileWithoutSemicolon { .. } => message("E0024", &[]),
+            Diag::DuplicateCase {
+                value: Some(value), ..
+            } => message("E0025", &[("value", &value)]),
+            Diag::DuplicateCase { value: None, .. } => {
+                message("E0025.default", &[])
+            }
+            Diag::NonConstantCase { .. } => message("E0026", &[]),
+            Diag::NonConstantInitializer { name, .. } => {
+                message("E0027", &[("name", name)])
+            }
+            Diag::ArrayTooLarge { name, .. } => {
+                message("E0028", &[("name", name)])
+            }
+            Diag::UnknownStruct { name, .. } => {
+                message("E0033", &[("name", name)])
+            }
+            Diag::DuplicateField { name, .. } => {
+                message("E0034", &[("name", name)])
+            }
+            Diag::DuplicateParam { name, .. } => {
+                message("E0050", &[("name", name)])
+            }
+            Diag::AssignToConst { name, .. } => {
+                message("E0035", &[("name", name)])
+            }
+            Diag::UndeclaredName { name, .. } => {
+                message("E0038", &[("name", name)])
+            }
+            Diag::Redeclaration { name, .. } => {
+                message("E0039", &[("name", name)])
+            }
+            Diag::MissingMain => message("E0040", &[]),
+            Diag::MainSignature { .. } => message("E0041", &[]),
+            Diag::MainNotLast { .. } => message("E0042", &[]),
+            Diag::VoidVariable { name, is_array, .. } => message(
+                if *is_array { "E0044.array" } else { "E0044" },
+                &[("name", name)],
+            ),
+            Diag::VoidValueUsed { callee, .. } => {
+                message("E0045", &[("callee", callee)])
+            }
+            Diag::UnreachableCode { .. } => message("E0046", &[]),
+            Diag::DeadStore { name, .. } => message("E0054", &[("name", name)]),
+            Diag::NonConstantArraySize { name, .. } => {
+                message("E0047", &[("name", name)])
+            }
+            Diag::ArraySizeNotPositive { name, value, .. } => {
+                message("E0048", &[("name", name), ("value", value)])
+            }
+            Diag::UnassignableTarget {
+                is_array: false, ..
+            } => message("E0049", &[]),
+            Diag::UnassignableTarget { is_array: true, .. } => {
+                message("E0049.array", &[])
+            }
+            Diag::NotAVariable { name, .. } => {
+                message("E0058", &[("name", name)])
+            }
+            Diag::NotAFunction { name, .. } => {
+                message("E0059", &[("name", name)])
+            }
+            Diag::ArgumentCountMismatch {
+                callee,
+                expected,
+                found,
+                ..
+            } => message(
+                "E0060",
+                &[("callee", callee), ("expected", expected), ("found", found)],
+            ),
+            Diag::UnusedVariable {
+                name,
+                is_param: false,
+                ..
+            } => message("E0061", &[("name", name)]),
+            Diag::UnusedVariable {
+                name,
+                is_param: true,
+                ..
+            } => message("E0061.param", &[("name", name)]),
+            Diag::Shadowing { name, .. } => message("E0062", &[("name", name)]),
+            Diag::NotAnArray { .. } => message("E0063", &[]),
+            Diag::UnknownField { field, ty, .. } => {
+                message("E0064", &[("field", field), ("ty", ty)])
+            }
+            Diag::NotAPointer { .. } => message("E0065", &[]),
+            Diag::ConstOverflow { .. } => message("E0051", &[]),
+            Diag::ConstDivisionByZero { .. } => message("E0052", &[]),
+            Diag::RuntimeError { trap, .. } => trap_message(*trap),
+            Diag::BytecodeRuntimeError {
+                trap,
+                fun,
+                line: None,
+            } => message(
+                "E0055.fun",
+                &[("error", &trap_message(*trap)), ("fun", fun)],
+            ),
+            Diag::BytecodeRuntimeError {
+                trap,
+                fun,
+                line: Some(line),
+            } => message(
+                "E0055.line",
+                &[
+                    ("error", &trap_message(*trap)),
+                    ("line", line),
+                    ("fun", fun),
+                ],
+            ),
+            Diag::TypeMismatch {
+                expected,
+                found,
+                discards_const,
+                ..
+            } => message(
+                if *discards_const {
+                    "E0053.const"
+                } else {
+                    "E0053"
+                },
+                &[("expected", expected), ("found", found)],
+            ),
+            Diag::ArrayArgumentMismatch {
+                callee,
+                param_is_array,
+                ..
+            } => message(
+                if *param_is_array {
+                    "E0043.array"
+                } else {
+                    "E0043.scalar"
+                },
+                &[("callee", callee)],
+            ),
+            Diag::ConditionWithoutParens { keyword, .. } => {
+                message("E0019", &[("keyword", &keyword.as_str())])
+            }
+            Diag::CannotReadFile { path, reason } => {
+                message("E0007", &[("path", path), ("reason", reason)])
+            }
+            Diag::CannotWriteFile { path, reason } => {
+                message("E0008", &[("path", path), ("reason", reason)])
+            }
+            Diag::InvalidBytecode { path, reason } => {
+                message("E0056", &[("path", path), ("reason", reason)])
+            }
+            Diag::LinkFailed { path, reason } => {
+                message("E0057", &[("path", path), ("reason", reason)])
+            }
+            Diag::FixesApplied { path, count } => message(
+                if *count == 1 {
+                    "fixes-applied"
+                } else {
+                    "fixes-applied.plural"
+                },
+                &[("count", count), ("path", path)],
+            ),
+            Diag::TooManyErrors { limit } => {
+                message("too-many-errors", &[("limit", limit)])
+            }
+            Diag::InternalCompilerError { message: text, .. } => {
+                message("ice", &[("message", text)])
+            }
+        }
+    }
+
+    /// The region of source code the diagnostic is about, if it's about any
+    /// (e.g. a file that can't be read has no source code to point at).
+    pub(crate) fn span(&self) -> Option<Span> {
+        let span = match *self {
+            Diag::UnknownCharacter { pos, ch }
+            | Diag::NonAsciiCharacter { pos, ch } => Span {
+                start: pos,
+                end: pos + Pos::from_usize(ch.len_utf8()),
+            },
+            Diag::UnterminatedBlockComment { start, .. } => Span {
+                start,
+                end: start + BytePos(2),
+            },
+            Diag::UnknownCharacterRun { span, .. }
+            | Diag::NonAsciiCharacterRun { span, .. } => span,
+            Diag::InvalidNumberLiteral { suffix, .. } => suffix,
+            Diag::IdentifierTooLong { span } => span,
+            Diag::UnterminatedString { start, .. }
+            | Diag::UnterminatedCharLiteral { start, .. } => Span {
+                start,
+                end: start + BytePos(1),
+            },
+            Diag::UnknownEscape { span, .. }
+            | Diag::InvalidCharLiteral { span, .. } => span,
+            Diag::UnexpectedToken { span, .. }
+            | Diag::NestingTooDeep { span } => span,
+            Diag::ChainedComparison { second, .. } => second,
+            Diag::MissingArraySize { span, .. }
+            | Diag::StrayTokens { span, .. }
+            | Diag::VoidParamNotAlone { span, .. }
+            | Diag::DeclarationAfterStatement { span, .. }
+            | Diag::ElseWithoutIf { span }
+            | Diag::TrailingComma { span }
+            | Diag::InvalidAssignTarget { span, .. }
+            | Diag::ReversedComparison { span, .. }
+            | Diag::AssignInCondition { span, .. }
+            | Diag::InvalidAddressOf { span }
+            | Diag::ExtensionNotEnabled { span, .. }
+            | Diag::LoopControlOutsideLoop { span, .. }
+            | Diag::DuplicateCase { span, .. }
+            | Diag::NonConstantCase { span }
+            | Diag::NonConstantInitializer { span, .. }
+            | Diag::ArrayTooLarge { span, .. }
+            | Diag::UnknownStruct { span, .. }
+            | Diag::DuplicateField { span, .. }
+            | Diag::DuplicateParam { span, .. }
+            | Diag::AssignToConst { span, .. }
+            | Diag::UndeclaredName { span, .. }
+            | Diag::Redeclaration { span, .. }
+            | Diag::MainSignature { span }
+            | Diag::MainNotLast { span, .. }
+            | Diag::ArrayArgumentMismatch { span, .. }
+            | Diag::VoidVariable { span, .. }
+            | Diag::VoidValueUsed { span, .. }
+            | Diag::UnreachableCode { span, .. }
+            | Diag::DeadStore { span, .. }
+            | Diag::NonConstantArraySize { span, .. }
+            | Diag::ArraySizeNotPositive { span, .. }
+            | Diag::UnassignableTarget { span, .. }
+            | Diag::ConstOverflow { span }
+            | Diag::ConstDivisionByZero { span, .. }
+            | Diag::TypeMismatch { span, .. }
+            | Diag::NotAPointer { span, .. }
+            | Diag::UnknownField { span, .. }
+            | Diag::NotAnArray { span, .. }
+            | Diag::Shadowing { span, .. }
+            | Diag::UnusedVariable { span, .. }
+            | Diag::ArgumentCountMismatch { span, .. }
+            | Diag::NotAFunction { span, .. }
+            | Diag::NotAVariable { span, .. }
+            | Diag::RuntimeError { span, .. }
+            | Diag::ConditionWithoutParens { span, .. } => span,
+            Diag::EmptyParamList { pos }
+            | Diag::DoWhileWithoutSemicolon { pos, .. } => Span {
+                start: pos,
+                end: pos,
+            },
+            Diag::MissingToken { pos, .. } => Span {
+                start: pos,
+                end: pos,
+            },
+            Diag::MissingMain
+            | Diag::BytecodeRuntimeError { .. }
+            | Diag::CannotReadFile { .. }
+            | Diag::InvalidBytecode { .. }
+            | Diag::CannotWriteFile { .. }
+            | Diag::LinkFailed { .. }
+            | Diag::FixesApplied { .. }
+            | Diag::TooManyErrors { .. } => return None,
+            Diag::InternalCompilerError { span, .. } => return span,
+        };
+
+        Some(span)
+    }
+
+    /// The labeled spans of the diagnostic, the primary one coming first.
+    /// Diagnostics without a span have no labels.
+    pub(crate) fn labels(&self) -> Vec<Label> {
+        let primary = match self.span() {
+            Some(span) => Label::primary(span),
+            None => return Vec::new(),
+        };
+
+        match *self {
+            Diag::UnknownCharacter { .. }
+            | Diag::UnknownCharacterRun { .. } => vec![primary],
+            Diag::UnterminatedBlockComment { end, .. } => vec![
+                primary.with_message(message("E0002.start", &[])),
+                Label::secondary(
+                    Span { start: end, end },
+                    message("E0002.end", &[]),
+                ),
+            ],
+            Diag::InvalidNumberLiteral { .. } => {
+                vec![primary.with_message(message("E0003.suffix", &[]))]
+            }
+            Diag::NonAsciiCharacter { .. }
+            | Diag::NonAsciiCharacterRun { .. } => {
+                vec![primary.with_message(message("E0004.char", &[]))]
+            }
+            Diag::IdentifierTooLong { span } => {
+                let len = span.end.to_usize() - span.start.to_usize();
+                vec![primary.with_message(message(
+                    "E0005.len",
+                    &[("len", &len), ("max", &MAX_IDENTIFIER_LENGTH)],
+                ))]
+            }
+            Diag::UnterminatedString { end, .. } => vec![
+                primary.with_message(message("E0006.start", &[])),
+                Label::secondary(
+                    Span { start: end, end },
+                    message("E0006.end", &[]),
+                ),
+            ],
+            Diag::UnterminatedCharLiteral { end, .. } => vec![
+                primary.with_message(message("E0029.start", &[])),
+                Label::secondary(
+                    Span { start: end, end },
+                    message("E0029.end", &[]),
+                ),
+            ],
+            Diag::UnknownEscape { .. } => {
+                vec![primary.with_message(message("E0030.label", &[]))]
+            }
+            Diag::InvalidCharLiteral { .. } => {
+                vec![primary.with_message(message("E0031.label", &[]))]
+            }
+            Diag::UnexpectedToken { expected, .. } => {
+                vec![primary.with_message(message(
+                    "E0009.expected",
+                    &[("expected", &expected.describe())],
+                ))]
+            }
+            Diag::MissingToken { token, .. } => {
+                vec![primary.with_message(message(
+                    "E0010.here",
+                    &[("token", &token.describe())],
+                ))]
+            }
+            Diag::NestingTooDeep { .. } => {
+                vec![primary.with_message(message(
+                    "E0011.limit",
+                    &[("max", &MAX_NESTING_DEPTH)],
+                ))]
+            }
+            Diag::ChainedComparison { first, .. } => vec![
+                primary.with_message(message("E0012.second", &[])),
+                Label::secondary(first, message("E0012.first", &[])),
+            ],
+            Diag::MissingArraySize { .. } => {
+                vec![primary.with_message(message("E0013.size", &[]))]
+            }
+            Diag::EmptyParamList { .. } => {
+                vec![primary.with_message(message("E0015.void", &[]))]
+            }
+            Diag::VoidParamNotAlone { .. } => {
+                vec![primary.with_message(message("E0016.void", &[]))]
+            }
+            Diag::DeclarationAfterStatement { first_stmt, .. } => vec![
+                primary,
+                Label::secondary(first_stmt, message("E0017.first", &[])),
+            ],
+            Diag::ElseWithoutIf { .. } => {
+                vec![primary.with_message(message("E0018.else", &[]))]
+            }
+            Diag::ConditionWithoutParens { .. } => vec![primary],
+            Diag::TrailingComma { .. } => {
+                vec![primary.with_message(message("E0020.comma", &[]))]
+            }
+            Diag::InvalidAddressOf { .. } => {
+                vec![primary.with_message(message("E0032.label", &[]))]
+            }
+            Diag::InvalidAssignTarget { equal, .. } => vec![
+                primary.with_message(message("E0021.target", &[])),
+                Label::secondary(equal, message("E0021.equal", &[])),
+            ],
+            Diag::AssignInCondition { equal, .. } => vec![
+                primary,
+                Label::secondary(equal, message("E0036.equal", &[])),
+            ],
+            Diag::ReversedComparison { .. } => vec![primary],
+            Diag::ExtensionNotEnabled { extension, .. } => {
+                vec![primary.with_message(message(
+                    "E0022.flag",
+                    &[("name", &extension.name())],
+                ))]
+            }
+            Diag::LoopControlOutsideLoop {
+                keyword: Keyword::Break,
+                ..
+            } => {
+                vec![primary.with_message(message("E0023.break-label", &[]))]
+            }
+            Diag::LoopControlOutsideLoop { keyword, .. } => {
+                vec![primary.with_message(message(
+                    "E0023.label",
+                    &[("keyword", &keyword.as_str())],
+                ))]
+            }
+            Diag::DuplicateCase { first, .. } => vec![
+                primary,
+                Label::secondary(first, message("E0025.first", &[])),
+            ],
+            Diag::NonConstantCase { .. } => {
+                vec![primary.with_message(message("E0026.label", &[]))]
+            }
+            Diag::NonConstantInitializer { .. } => {
+                vec![primary.with_message(message("E0027.label", &[]))]
+            }
+            Diag::ArrayTooLarge { .. } => {
+                vec![primary.with_message(message("E0028.label", &[]))]
+            }
+            Diag::UnknownStruct { ref name, .. } => {
+                vec![primary
+                    .with_message(message("E0033.label", &[("name", name)]))]
+            }
+            Diag::DuplicateField { first, .. } => vec![
+                primary,
+                Label::secondary(first, message("E0034.first", &[])),
+            ],
+            Diag::DuplicateParam { first, .. } => vec![
+                primary,
+                Label::secondary(first, message("E0050.first", &[])),
+            ],
+            Diag::UndeclaredName { .. } => {
+                vec![primary.with_message(message("E0038.label", &[]))]
+            }
+            Diag::Redeclaration { previous, .. } => vec![
+                primary,
+                Label::secondary(previous, message("E0039.previous", &[])),
+            ],
+            Diag::MainSignature { .. } => {
+                vec![primary.with_message(message("E0041.label", &[]))]
+            }
+            Diag::VoidVariable { .. } => {
+                vec![primary.with_message(message("E0044.label", &[]))]
+            }
+            Diag::VoidValueUsed { .. } => {
+                vec![primary.with_message(message("E0045.label", &[]))]
+            }
+            Diag::NonConstantArraySize { .. } => {
+                vec![primary.with_message(message("E0047.label", &[]))]
+            }
+            Diag::ArraySizeNotPositive { .. } => {
+                vec![primary.with_message(message("E0048.label", &[]))]
+            }
+            Diag::UnassignableTarget {
+                ref name,
+                is_array,
+                decl,
+                ..
+            } => {
+                let primary = if is_array {
+                    primary.with_message(message("E0049.label", &[]))
+                } else {
+                    primary
+                };
+                let decl = decl.map(|decl| {
+                    Label::secondary(
+                        decl,
+                        message("E0049.decl", &[("name", name)]),
+                    )
+                });
+                std::iter::once(primary).chain(decl).collect()
+            }
+            Diag::ConstOverflow { .. } => {
+                vec![primary.with_message(message("E0051.label", &[]))]
+            }
+            Diag::ConstDivisionByZero { divisor, .. } => vec![
+                primary,
+                Label::secondary(divisor, message("E0052.divisor", &[])),
+            ],
+            Diag::NotAVariable { ref name, decl, .. } => {
+                let decl = decl.map(|decl| {
+                    Label::secondary(
+                        decl,
+                        message("E0058.decl", &[("name", name)]),
+                    )
+                });
+                std::iter::once(
+                    primary.with_message(message("E0058.label", &[])),
+                )
+                .chain(decl)
+                .collect()
+            }
+            Diag::NotAFunction { ref name, decl, .. } => {
+                let decl = decl.map(|decl| {
+                    Label::secondary(
+                        decl,
+                        message("E0059.decl", &[("name", name)]),
+                    )
+                });
+                std::iter::once(
+                    primary.with_message(message("E0059.label", &[])),
+                )
+                .chain(decl)
+                .collect()
+            }
+            Diag::ArgumentCountMismatch { params, .. } => {
+                let params = params.map(|params| {
+                    Label::secondary(params, message("E0060.params", &[]))
+                });
+                std::iter::once(primary).chain(params).collect()
+            }
+            Diag::UnusedVariable { .. } => {
+                vec![primary.with_message(message("E0061.label", &[]))]
+            }
+            Diag::Shadowing {
+                ref name, previous, ..
+            } => vec![
+                primary,
+                Label::secondary(
+                    previous,
+                    message("E0062.previous", &[("name", name)]),
+                ),
+            ],
+            Diag::NotAnArray { ref ty, .. } => {
+                vec![primary.with_message(message("E0063.label", &[("ty", ty)]))]
+            }
+            Diag::UnknownField { .. } => vec![primary],
+            Diag::NotAPointer { ref ty, .. } => {
+                vec![primary.with_message(message("E0065.label", &[("ty", ty)]))]
+            }
+            Diag::RuntimeError { .. } => vec![primary],
+            Diag::TypeMismatch { because, .. } => {
+                let because = because.map(|because| {
+                    Label::secondary(because, message("E0053.because", &[]))
+                });
+                std::iter::once(primary).chain(because).collect()
+            }
+            Diag::DeadStore { ref name, .. } => {
+                vec![primary
+                    .with_message(message("E0054.label", &[("name", name)]))]
+            }
+            Diag::UnreachableCode {
+                cause, condition, ..
+            } => vec![
+                primary,
+                Label::secondary(
+                    cause,
+                    message(
+                        match condition {
+                            None => "E0046.jump",
+                            Some(false) => "E0046.false",
+                            Some(true) => "E0046.true",
+                        },
+                        &[],
+                    ),
+                ),
+            ],
+            Diag::ArrayArgumentMismatch { param, .. } => vec![
+                primary,
+                Label::secondary(param, message("E0043.param", &[])),
+            ],
+            Diag::MainNotLast { after, .. } => vec![
+                primary,
+                Label::secondary(after, message("E0042.after", &[])),
+            ],
+            Diag::AssignToConst { ref name, decl, .. } => vec![
+                primary,
+                Label::secondary(
+                    decl,
+                    message("E0035.decl", &[("name", name)]),
+                ),
+            ],
+            Diag::DoWhileWithoutSemicolon { do_span, .. } => vec![
+                primary.with_message(message("E0024.here", &[])),
+                Label::secondary(do_span, message("E0024.do", &[])),
+            ],
+            Diag::StrayTokens { found, .. } => {
+                vec![primary.with_message(if found == Category::Equal {
+                    message("E0014.init", &[])
+                } else {
+                    message("E0014.stray", &[])
+                })]
+            }
+            Diag::MissingMain
+            | Diag::BytecodeRuntimeError { .. }
+            | Diag::CannotReadFile { .. }
+            | Diag::InvalidBytecode { .. }
+            | Diag::CannotWriteFile { .. }
+            | Diag::LinkFailed { .. }
+            | Diag::FixesApplied { .. }
+            | Diag::TooManyErrors { .. } => Vec::new(),
+            Diag::InternalCompilerError { .. } => {
+                vec![primary.with_message(message("ice.span", &[]))]
+            }
+        }
+    }
+
+    /// The fixes the diagnostic comes with, e.g. inserting the `*/` missing
+    /// from a block comment.
+    pub(crate) fn suggestions(&self) -> Vec<Suggestion> {
+        match *self {
+            // The scanner already takes the comment to go on until the end
+            // of the input, so closing it there doesn't change the program.
+            Diag::UnterminatedBlockComment { end, .. } => {
+                vec![Suggestion::insertion(
+                    end,
+                    "*/",
+                    message("E0002.fix", &[]),
+                )]
+            }
+            // The words may be meant to be there, e.g. an initializer,
+            // which then has to be moved into a statement.
+            Diag::StrayTokens { after, span, .. } => vec![Suggestion::new(
+                Span {
+                    start: after,
+                    end: span.end,
+                },
+                "",
+                message("E0014.fix", &[]),
+                Applicability::MaybeIncorrect,
+            )],
+            Diag::EmptyParamList { pos } => vec![Suggestion::insertion(
+                pos,
+                "void",
+                message("E0015.fix", &[]),
+            )],
+            Diag::VoidParamNotAlone { removal, .. } => {
+                vec![Suggestion::removal(removal, message("E0016.fix", &[]))]
+            }
+            Diag::TrailingComma { span } => {
+                vec![Suggestion::removal(span, message("E0020.fix", &[]))]
+            }
+            Diag::ConditionWithoutParens { span, ref text, .. } => {
+                vec![Suggestion::new(
+                    span,
+                    format!("({})", text),
+                    message("E0019.fix", &[]),
+                    Applicability::MachineApplicable,
+                )]
+            }
+            // Either could be what was meant.
+            Diag::AssignInCondition {
+                span,
+                equal,
+                ref text,
+            } => vec![
+                Suggestion::new(
+                    equal,
+                    "==",
+                    message("E0036.compare", &[]),
+                    Applicability::MaybeIncorrect,
+                ),
+                Suggestion::new(
+                    span,
+                    format!("({})", text),
+                    message("E0036.parens", &[]),
+                    Applicability::MaybeIncorrect,
+                ),
+            ],
+            // The body may not fit the new signature, e.g. if it returns a
+            // value.
+            Diag::MainSignature { span } => vec![Suggestion::new(
+                span,
+                "void main(void)",
+                message("E0041.fix", &[]),
+                Applicability::MaybeIncorrect,
+            )],
+            Diag::ReversedComparison { span, op } => vec![Suggestion::new(
+                span,
+                op.as_str(),
+                message("E0037.fix", &[("op", &op.as_str())]),
+                Applicability::MachineApplicable,
+            )],
+            // Only a token missing at the end of a line is surely missing
+            // right there; otherwise the words around it may be what's wrong.
+            Diag::MissingToken {
+                token,
+                pos,
+                at_line_end,
+                ..
+            } => {
+                let applicability = if at_line_end {
+                    Applicability::MachineApplicable
+                } else {
+                    Applicability::MaybeIncorrect
+                };
+                vec![Suggestion::new(
+                    Span {
+                        start: pos,
+                        end: pos,
+                    },
+                    token.symbol().unwrap_or_default(),
+                    message("E0010.fix", &[("token", &token.describe())]),
+                    applicability,
+                )]
+            }
+            Diag::DoWhileWithoutSemicolon {
+                pos, at_line_end, ..
+            } => {
+                let applicability = if at_line_end {
+                    Applicability::MachineApplicable
+                } else {
+                    Applicability::MaybeIncorrect
+                };
+                vec![Suggestion::new(
+                    Span {
+                        start: pos,
+                        end: pos,
+                    },
+                    ";",
+                    message("E0024.fix", &[]),
+                    applicability,
+                )]
+            }
+            Diag::UnknownCharacter { .. }
+            | Diag::UnknownCharacterRun { .. }
+            | Diag::InvalidNumberLiteral { .. }
+            | Diag::NonAsciiCharacter { .. }
+            | Diag::NonAsciiCharacterRun { .. }
+            | Diag::IdentifierTooLong { .. }
+            | Diag::UnterminatedString { .. }
+            | Diag::UnterminatedCharLiteral { .. }
+            | Diag::UnknownEscape { .. }
+            | Diag::InvalidCharLiteral { .. }
+            | Diag::UnexpectedToken { .. }
+            | Diag::NestingTooDeep { .. }
+            | Diag::ChainedComparison { .. }
+            | Diag::MissingArraySize { .. }
+            | Diag::DeclarationAfterStatement { .. }
+            | Diag::ElseWithoutIf { .. }
+            | Diag::InvalidAssignTarget { .. }
+            | Diag::InvalidAddressOf { .. }
+            | Diag::ExtensionNotEnabled { .. }
+            | Diag::LoopControlOutsideLoop { .. }
+            | Diag::DuplicateCase { .. }
+            | Diag::NonConstantCase { .. }
+            | Diag::NonConstantInitializer { .. }
+            | Diag::ArrayTooLarge { .. }
+            | Diag::UnknownStruct { .. }
+            | Diag::DuplicateField { .. }
+            | Diag::DuplicateParam { .. }
+            | Diag::AssignToConst { .. }
+            | Diag::UndeclaredName { .. }
+            | Diag::Redeclaration { .. }
+            | Diag::MissingMain
+            | Diag::MainNotLast { .. }
+            | Diag::ArrayArgumentMismatch { .. }
+            | Diag::VoidVariable { .. }
+            | Diag::VoidValueUsed { .. }
+            | Diag::UnreachableCode { .. }
+            | Diag::DeadStore { .. }
+            | Diag::NonConstantArraySize { .. }
+            | Diag::ArraySizeNotPositive { .. }
+            | Diag::UnassignableTarget { .. }
+            | Diag::ConstOverflow { .. }
+            | Diag::ConstDivisionByZero { .. }
+            | Diag::TypeMismatch { .. }
+            | Diag::NotAPointer { .. }
+            | Diag::UnknownField { .. }
+            | Diag::NotAnArray { .. }
+            | Diag::Shadowing { .. }
+            | Diag::UnusedVariable { .. }
+            | Diag::ArgumentCountMismatch { .. }
+            | Diag::NotAFunction { .. }
+            | Diag::NotAVariable { .. }
+            | Diag::RuntimeError { .. }
+            | Diag::BytecodeRuntimeError { .. }
+            | Diag::CannotReadFile { .. }
+            | Diag::InvalidBytecode { .. }
+            | Diag::CannotWriteFile { .. }
+            | Diag::LinkFailed { .. }
+            | Diag::FixesApplied { .. }
+            | Diag::TooManyErrors { .. }
+            | Diag::InternalCompilerError { .. } => Vec::new(),
+        }
+    }
+
+    /// The phase of the compiler the diagnostic comes from. Diagnostics
+    /// about the compilation as a whole (e.g. an unreadable file) have none.
+    pub(crate) fn phase(&self) -> Option<Phase> {
+        match *self {
+            Diag::UnknownCharacter { .. }
+            | Diag::UnknownCharacterRun { .. }
+            | Diag::UnterminatedBlockComment { .. }
+            | Diag::InvalidNumberLiteral { .. }
+            | Diag::NonAsciiCharacter { .. }
+            | Diag::NonAsciiCharacterRun { .. }
+            | Diag::IdentifierTooLong { .. }
+            | Diag::UnterminatedString { .. }
+            | Diag::UnterminatedCharLiteral { .. }
+            | Diag::UnknownEscape { .. }
+            | Diag::InvalidCharLiteral { .. } => Some(Phase::Lex),
+            Diag::UnexpectedToken { .. }
+            | Diag::MissingToken { .. }
+            | Diag::NestingTooDeep { .. }
+            | Diag::ChainedComparison { .. }
+            | Diag::MissingArraySize { .. }
+            | Diag::StrayTokens { .. }
+            | Diag::EmptyParamList { .. }
+            | Diag::VoidParamNotAlone { .. }
+            | Diag::DeclarationAfterStatement { .. }
+            | Diag::ElseWithoutIf { .. }
+            | Diag::ConditionWithoutParens { .. }
+            | Diag::TrailingComma { .. }
+            | Diag::InvalidAssignTarget { .. }
+            | Diag::AssignInCondition { .. }
+            | Diag::ReversedComparison { .. }
+            | Diag::InvalidAddressOf { .. }
+            | Diag::ExtensionNotEnabled { .. }
+            | Diag::DoWhileWithoutSemicolon { .. } => Some(Phase::Parse),
+            Diag::LoopControlOutsideLoop { .. }
+            | Diag::DuplicateCase { .. }
+            | Diag::NonConstantCase { .. }
+            | Diag::NonConstantInitializer { .. }
+            | Diag::ArrayTooLarge { .. }
+            | Diag::UnknownStruct { .. }
+            | Diag::DuplicateField { .. }
+            | Diag::DuplicateParam { .. }
+            | Diag::AssignToConst { .. }
+            | Diag::UndeclaredName { .. }
+            | Diag::Redeclaration { .. }
+            | Diag::MissingMain
+            | Diag::MainSignature { .. }
+            | Diag::MainNotLast { .. }
+            | Diag::ArrayArgumentMismatch { .. }
+            | Diag::VoidVariable { .. }
+            | Diag::VoidValueUsed { .. }
+            | Diag::UnreachableCode { .. }
+            | Diag::DeadStore { .. }
+            | Diag::NonConstantArraySize { .. }
+            | Diag::ArraySizeNotPositive { .. }
+            | Diag::UnassignableTarget { .. }
+            | Diag::ConstOverflow { .. }
+            | Diag::ConstDivisionByZero { .. }
+            | Diag::TypeMismatch { .. }
+            | Diag::NotAPointer { .. }
+            | Diag::UnknownField { .. }
+            | Diag::NotAnArray { .. }
+            | Diag::Shadowing { .. }
+            | Diag::UnusedVariable { .. }
+            | Diag::ArgumentCountMismatch { .. }
+            | Diag::NotAFunction { .. }
+            | Diag::NotAVariable { .. } => Some(Phase::Sema),
+            Diag::RuntimeError { .. } | Diag::BytecodeRuntimeError { .. } => {
+                Some(Phase::Run)
+            }
+            Diag::CannotReadFile { .. }
+            | Diag::InvalidBytecode { .. }
+            | Diag::CannotWriteFile { .. }
+            | Diag::LinkFailed { .. }
+            | Diag::FixesApplied { .. }
+            | Diag::TooManyErrors { .. } => None,
+            Diag::InternalCompilerError { phase, .. } => phase,
+        }
+    }
+
+    /// The lint the diagnostic belongs to, if it's a warning that can be
+    /// switched on or off.
+    pub(crate) fn lint(&self) -> Option<Lint> {
+        match self {
+            Diag::UnknownCharacter { .. }
+            | Diag::UnknownCharacterRun { .. }
+            | Diag::UnterminatedBlockComment { .. }
+            | Diag::InvalidNumberLiteral { .. }
+            | Diag::NonAsciiCharacter { .. }
+            | Diag::NonAsciiCharacterRun { .. }
+            | Diag::IdentifierTooLong { .. }
+            | Diag::UnterminatedString { .. }
+            | Diag::UnterminatedCharLiteral { .. }
+            | Diag::UnknownEscape { .. }
+            | Diag::InvalidCharLiteral { .. }
+            | Diag::UnexpectedToken { .. }
+            | Diag::MissingToken { .. }
+            | Diag::NestingTooDeep { .. }
+            | Diag::ChainedComparison { .. }
+            | Diag::MissingArraySize { .. }
+            | Diag::StrayTokens { .. }
+            | Diag::EmptyParamList { .. }
+            | Diag::VoidParamNotAlone { .. }
+            | Diag::DeclarationAfterStatement { .. }
+            | Diag::ElseWithoutIf { .. }
+            | Diag::ConditionWithoutParens { .. }
+            | Diag::TrailingComma { .. }
+            | Diag::InvalidAssignTarget { .. }
+            | Diag::ReversedComparison { .. }
+            | Diag::InvalidAddressOf { .. }
+            | Diag::ExtensionNotEnabled { .. }
+            | Diag::LoopControlOutsideLoop { .. }
+            | Diag::DoWhileWithoutSemicolon { .. }
+            | Diag::DuplicateCase { .. }
+            | Diag::NonConstantCase { .. }
+            | Diag::NonConstantInitializer { .. }
+            | Diag::ArrayTooLarge { .. }
+            | Diag::UnknownStruct { .. }
+            | Diag::DuplicateField { .. }
+            | Diag::DuplicateParam { .. }
+            | Diag::AssignToConst { .. }
+            | Diag::UndeclaredName { .. }
+            | Diag::Redeclaration { .. }
+            | Diag::MissingMain
+            | Diag::MainSignature { .. }
+            | Diag::ArrayArgumentMismatch { .. }
+            | Diag::VoidVariable { .. }
+            | Diag::VoidValueUsed { .. }
+            | Diag::NonConstantArraySize { .. }
+            | Diag::ArraySizeNotPositive { .. }
+            | Diag::UnassignableTarget { .. }
+            | Diag::ConstOverflow { .. }
+            | Diag::ConstDivisionByZero { .. }
+            | Diag::TypeMismatch { .. }
+            | Diag::NotAPointer { .. }
+            | Diag::UnknownField { .. }
+            | Diag::NotAnArray { .. }
+            | Diag::ArgumentCountMismatch { .. }
+            | Diag::NotAFunction { .. }
+            | Diag::NotAVariable { .. }
+            | Diag::RuntimeError { .. }
+            | Diag::BytecodeRuntimeError { .. }
+            | Diag::CannotReadFile { .. }
+            | Diag::InvalidBytecode { .. }
+            | Diag::CannotWriteFile { .. }
+            | Diag::LinkFailed { .. }
+            | Diag::FixesApplied { .. }
+            | Diag::TooManyErrors { .. }
+            | Diag::InternalCompilerError { .. } => None,
+            Diag::AssignInCondition { .. } => Some(Lint::AssignInCondition),
+            Diag::MainNotLast { .. } => Some(Lint::MainNotLast),
+            Diag::UnreachableCode { .. } => Some(Lint::UnreachableCode),
+            Diag::DeadStore { .. } => Some(Lint::DeadStore),
+            Diag::UnusedVariable { .. } => Some(Lint::UnusedVariable),
+            Diag::Shadowing { .. } => Some(Lint::Shadowing),
+        }
+    }
+
+    /// The severity a diagnostic of this kind is reported with, unless
+    /// something (e.g. a command line flag) says otherwise.
+    pub(crate) fn default_severity(&self) -> Severity {
+        match self {
+            Diag::UnknownCharacter { .. }
+            | Diag::UnknownCharacterRun { .. }
+            | Diag::UnterminatedBlockComment { .. }
+            | Diag::InvalidNumberLiteral { .. }
+            | Diag::NonAsciiCharacter { .. }
+            | Diag::NonAsciiCharacterRun { .. }
+            | Diag::IdentifierTooLong { .. }
+            | Diag::UnterminatedString { .. }
+            | Diag::UnterminatedCharLiteral { .. }
+            | Diag::UnknownEscape { .. }
+            | Diag::InvalidCharLiteral { .. }
+            | Diag::UnexpectedToken { .. }
+            | Diag::MissingToken { .. }
+            | Diag::NestingTooDeep { .. }
+            | Diag::ChainedComparison { .. }
+            | Diag::MissingArraySize { .. }
+            | Diag::StrayTokens { .. }
+            | Diag::EmptyParamList { .. }
+            | Diag::VoidParamNotAlone { .. }
+            | Diag::DeclarationAfterStatement { .. }
+            | Diag::ElseWithoutIf { .. }
+            | Diag::ConditionWithoutParens { .. }
+            | Diag::TrailingComma { .. }
+            | Diag::InvalidAssignTarget { .. }
+            | Diag::ReversedComparison { .. }
+            | Diag::InvalidAddressOf { .. }
+            | Diag::ExtensionNotEnabled { .. }
+            | Diag::LoopControlOutsideLoop { .. }
+            | Diag::DoWhileWithoutSemicolon { .. }
+            | Diag::DuplicateCase { .. }
+            | Diag::NonConstantCase { .. }
+            | Diag::NonConstantInitializer { .. }
+            | Diag::ArrayTooLarge { .. }
+            | Diag::UnknownStruct { .. }
+            | Diag::DuplicateField { .. }
+            | Diag::DuplicateParam { .. }
+            | Diag::AssignToConst { .. }
+            | Diag::UndeclaredName { .. }
+            | Diag::Redeclaration { .. }
+            | Diag::MissingMain
+            | Diag::MainSignature { .. }
+            | Diag::ArrayArgumentMismatch { .. }
+            | Diag::VoidVariable { .. }
+            | Diag::VoidValueUsed { .. }
+            | Diag::NonConstantArraySize { .. }
+            | Diag::ArraySizeNotPositive { .. }
+            | Diag::UnassignableTarget { .. }
+            | Diag::ConstOverflow { .. }
+            | Diag::ConstDivisionByZero { .. }
+            | Diag::TypeMismatch { .. }
+            | Diag::NotAPointer { .. }
+            | Diag::UnknownField { .. }
+            | Diag::NotAnArray { .. }
+            | Diag::ArgumentCountMismatch { .. }
+            | Diag::NotAFunction { .. }
+            | Diag::NotAVariable { .. }
+            | Diag::RuntimeError { .. }
+            | Diag::BytecodeRuntimeError { .. }
+            | Diag::CannotWriteFile { .. }
+            | Diag::LinkFailed { .. } => Severity::Error,
+            Diag::CannotReadFile { .. }
+            | Diag::InvalidBytecode { .. }
+            | Diag::InternalCompilerError { .. } => Severity::Fatal,
+            Diag::AssignInCondition { .. }
+            | Diag::MainNotLast { .. }
+            | Diag::UnreachableCode { .. }
+            | Diag::DeadStore { .. }
+            | Diag::UnusedVariable { .. }
+            | Diag::Shadowing { .. } => Severity::Warning,
+            Diag::FixesApplied { .. } | Diag::TooManyErrors { .. } => {
+                Severity::Note
+            }
+        }
+    }
+}
+
+/// What `trap` stopped a program with.
+fn trap_message(trap: Trap) -> String {
+    match trap {
+        Trap::DivisionByZero => message("E0055", &[]),
+        Trap::InvalidAddress => message("E0055.address", &[]),
+        Trap::StackOverflow => {
+            message("E0055.stack", &[("max", &MAX_CALL_DEPTH)])
+        }
+        Trap::OutOfMemory => {
+            message("E0055.memory", &[("max", &(MAX_MEMORY >> 20))])
+        }
+        Trap::InvalidInput => message("E0055.input", &[]),
+        Trap::IndexOutOfBounds => message("E0055.bounds", &[]),
+    }
+}
+
+/// How serious a diagnostic is. Only `Fatal` and `Error` diagnostics make the
+/// compilation fail; the other levels are advisory and exist so that lints and
+/// warnings can be reported alongside hard errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// An error after which the compilation can't go on at all.
+    Fatal,
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Fatal => "fatal error",
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// A phase of the compiler, which diagnostics are tagged with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Lex,
+    Parse,
+    Sema,
+    Codegen,
+    /// Running the program, with `csub run`.
+    Run,
+}
+
+impl Phase {
+    pub(crate) fn from_name(name: &str) -> Option<Phase> {
+        match name {
+            "lex" => Some(Phase::Lex),
+            "parse" => Some(Phase::Parse),
+            "sema" => Some(Phase::Sema),
+            "codegen" => Some(Phase::Codegen),
+            "run" => Some(Phase::Run),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Phase::Lex => "lex",
+            Phase::Parse => "parse",
+            Phase::Sema => "sema",
+            Phase::Codegen => "codegen",
+            Phase::Run => "run",
+        }
+    }
+}
+
+/// A span of source code pointed at by a diagnostic, optionally explaining
+/// its role (e.g. "comment started here"). Every diagnostic has exactly one
+/// primary label, the place the error is reported at, and any number of
+/// secondary ones giving context.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Label {
+    pub(crate) span: Span,
+    pub(crate) message: Option<String>,
+    pub(crate) is_primary: bool,
+}
+
+impl Label {
+    pub(crate) fn primary(span: Span) -> Label {
+        Label {
+            span,
+            message: None,
+            is_primary: true,
+        }
+    }
+
+    pub(crate) fn secondary(span: Span, message: impl Into<String>) -> Label {
+        Label {
+            span,
+            message: Some(message.into()),
+            is_primary: false,
+        }
+    }
+
+    pub(crate) fn with_message(self, message: impl Into<String>) -> Label {
+        Label {
+            message: Some(message.into()),
+            ..self
+        }
+    }
+}
+
+/// A note or help message attached to a diagnostic, e.g. "previous
+/// declaration was here". It's rendered under its parent rather than as a
+/// separate diagnostic.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SubDiagnostic {
+    pub(crate) severity: Severity,
+    pub(crate) message: String,
+    pub(crate) span: Option<Span>,
+}
+
+impl SubDiagnostic {
+    /// The labels of the sub-diagnostic: its span, if it has one, as a
+    /// secondary label.
+    pub(crate) fn labels(&self) -> Vec<Label> {
+        self.span
+            .map(|span| Label {
+                span,
+                message: None,
+                is_primary: false,
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+/// A `Diag` together with the severity it's going to be reported with.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Diagnostic {
+    pub(crate) severity: Severity,
+    pub(crate) diag: Diag,
+    /// Secondary labels attached on top of the ones the `Diag` provides.
+    pub(crate) extra_labels: Vec<Label>,
+    /// Notes and help messages rendered under the diagnostic.
+    pub(crate) children: Vec<SubDiagnostic>,
+    /// Edits to the source that may fix the problem.
+    pub(crate) suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    /// A diagnostic for `diag`, with the suggestions `diag` comes with.
+    pub(crate) fn new(severity: Severity, diag: Diag) -> Diagnostic {
+        Diagnostic {
+            severity,
+            suggestions: diag.suggestions(),
+            diag,
+            extra_labels: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Starts building a diagnostic for `diag`, with its default severity.
+    pub(crate) fn builder(diag: Diag) -> DiagBuilder {
+        DiagBuilder {
+            diagnostic: diag.into(),
+        }
+    }
+
+    /// All labels of the diagnostic, the primary one coming first.
+    pub(crate) fn labels(&self) -> Vec<Label> {
+        let mut labels = self.diag.labels();
+        labels.extend(self.extra_labels.iter().cloned());
+        labels
+    }
+
+    /// Whether the diagnostic makes the compilation fail.
+    pub(crate) fn is_error(&self) -> bool {
+        matches!(self.severity, Severity::Fatal | Severity::Error)
+    }
+}
+
+impl From<Diag> for Diagnostic {
+    fn from(diag: Diag) -> Diagnostic {
+        Diagnostic::new(diag.default_severity(), diag)
+    }
+}
+
+/// Builds a `Diagnostic` piece by piece and then hands it over to a
+/// `DiagBag`, e.g.:
+///
+/// ```ignore
+/// Diagnostic::builder(Diag::UnknownCharacter { pos, ch })
+///     .with_note(None, "the character isn't part of C-")
+///     .emit(&mut diag_bag);
+/// ```
+///
+/// The labels and suggestions of a diagnostic come with its `Diag`, so only
+/// tests, which make up diagnostics no `Diag` has, add more or change its
+/// severity.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DiagBuilder {
+    diagnostic: Diagnostic,
+}
+
+impl DiagBuilder {
+    /// Overrides the default severity of the diagnostic's kind.
+    #[cfg(test)]
+    pub(crate) fn with_severity(mut self, severity: Severity) -> DiagBuilder {
+        self.diagnostic.severity = severity;
+        self
+    }
+
+    /// Attaches a secondary label to the diagnostic.
+    #[cfg(test)]
+    pub(crate) fn with_label(
+        mut self,
+        span: Span,
+        message: impl Into<String>,
+    ) -> DiagBuilder {
+        self.diagnostic
+            .extra_labels
+            .push(Label::secondary(span, message));
+        self
+    }
+
+    /// Attaches a note, optionally pointing at some `span`.
+    pub(crate) fn with_note(
+        self,
+        span: Option<Span>,
+        message: impl Into<String>,
+    ) -> DiagBuilder {
+        self.with_child(Severity::Note, span, message)
+    }
+
+    /// Attaches a help message, optionally pointing at some `span`.
+    pub(crate) fn with_help(
+        self,
+        span: Option<Span>,
+        message: impl Into<String>,
+    ) -> DiagBuilder {
+        self.with_child(Severity::Help, span, message)
+    }
+
+    fn with_child(
+        mut self,
+        severity: Severity,
+        span: Option<Span>,
+        message: impl Into<String>,
+    ) -> DiagBuilder {
+        self.diagnostic.children.push(SubDiagnostic {
+            severity,
+            message: message.into(),
+            span,
+        });
+        self
+    }
+
+    /// Attaches a suggested edit to the source.
+    #[cfg(test)]
+    pub(crate) fn with_suggestion(
+        mut self,
+        suggestion: Suggestion,
+    ) -> DiagBuilder {
+        self.diagnostic.suggestions.push(suggestion);
+        self
+    }
+
+    pub(crate) fn build(self) -> Diagnostic {
+        self.diagnostic
+    }
+
+    /// Pushes the diagnostic to `diag_bag`.
+    pub(crate) fn emit(self, diag_bag: &mut DiagBag) {
+        diag_bag.push(self.diagnostic);
+    }
+}
+
+impl From<DiagBuilder> for Diagnostic {
+    fn from(builder: DiagBuilder) -> Diagnostic {
+        builder.build()
+    }
+}
+
+/// How confident we are that applying a suggestion fixes the problem without
+/// changing the meaning of the program in unintended ways.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Applicability {
+    /// The suggestion is definitely what the user meant, so tools may apply
+    /// it automatically.
+    MachineApplicable,
+    /// The suggestion may or may not be what the user meant.
+    MaybeIncorrect,
+}
+
+impl Applicability {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Applicability::MachineApplicable => "machine-applicable",
+            Applicability::MaybeIncorrect => "maybe-incorrect",
+        }
+    }
+}
+
+/// An edit to the source that may fix the problem a diagnostic is about:
+/// replace the text in `span` by `replacement`. Empty spans insert text, and
+/// empty replacements remove it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Suggestion {
+    pub(crate) message: String,
+    pub(crate) span: Span,
+    pub(crate) replacement: String,
+    pub(crate) applicability: Applicability,
+}
+
+impl Suggestion {
+    pub(crate) fn new(
+        span: Span,
+        replacement: impl Into<String>,
+        message: impl Into<String>,
+        applicability: Applicability,
+    ) -> Suggestion {
+        Suggestion {
+            message: message.into(),
+            span,
+            replacement: replacement.into(),
+            applicability,
+        }
+    }
+
+    /// Suggests inserting `text` at `pos`.
+    pub(crate) fn insertion(
+        pos: BytePos,
+        text: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Suggestion {
+        let span = Span {
+            start: pos,
+            end: pos,
+        };
+        Suggestion::new(span, text, message, Applicability::MachineApplicable)
+    }
+
+    /// Suggests removing the text in `span`.
+    pub(crate) fn removal(
+        span: Span,
+        message: impl Into<String>,
+    ) -> Suggestion {
+        Suggestion::new(span, "", message, Applicability::MachineApplicable)
+    }
+}
+
+/// How many diagnostics of each severity a compilation reported.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Summary {
+    pub(crate) fatal: usize,
+    pub(crate) errors: usize,
+    pub(crate) warnings: usize,
+    pub(crate) notes: usize,
+    pub(crate) helps: usize,
+    /// Diagnostics dropped for being duplicates of reported ones.
+    pub(crate) suppressed: usize,
+}
+
+impl Summary {
+    /// The line closing the output of a compilation, with its severity, e.g.
+    /// `aborting due to 3 previous errors; 2 warnings emitted; 1 duplicate
+    /// diagnostic suppressed`. There's no such line when there were neither
+    /// errors nor warnings.
+    pub(crate) fn line(&self) -> Option<(Severity, String)> {
+        let errors = self.fatal + self.errors;
+        let warnings = message(
+            if self.warnings == 1 {
+                "summary.warning"
+            } else {
+                "summary.warnings"
+            },
+            &[("count", &self.warnings)],
+        );
+
+        let (severity, mut line) = if errors == 0 && self.warnings == 0 {
+            return None;
+        } else if errors == 0 {
+            (Severity::Warning, warnings)
+        } else if self.warnings == 0 {
+            (Severity::Error, Self::errors_line(errors))
+        } else {
+            let errors = Self::errors_line(errors);
+            (Severity::Error, format!("{}; {}", errors, warnings))
+        };
+        if self.suppressed > 0 {
+            let suppressed = message(
+                if self.suppressed == 1 {
+                    "summary.duplicate"
+                } else {
+                    "summary.duplicates"
+                },
+                &[("count", &self.suppressed)],
+            );
+            line = format!("{}; {}", line, suppressed);
+        }
+
+        Some((severity, line))
+    }
+
+    fn errors_line(errors: usize) -> String {
+        if errors == 1 {
+            message("summary.error", &[])
+        } else {
+            message("summary.errors", &[("count", &errors)])
+        }
+    }
+}
+
+/// The default maximum number of errors reported before compilation stops.
+pub(crate) const DEFAULT_ERROR_LIMIT: usize = 20;
+
 #[derive(Debug)]
 pub(crate) struct DiagBag {
-    diags: Vec<Diag>,
+    diags: Vec<Diagnostic>,
+    /// Maximum number of errors the bag takes before it stops taking any
+    /// diagnostics at all, if there's a maximum.
+    error_limit: Option<usize>,
+    reached_error_limit: bool,
+    warning_config: WarningConfig,
+    /// The code and primary span of each diagnostic taken, so that the same
+    /// report isn't taken twice.
+    taken: HashSet<(&'static str, Span)>,
+    /// Number of diagnostics dropped for being duplicates.
+    suppressed_count: usize,
+    /// Diagnostics held back until the end of the current phase.
+    deferred: Vec<Diagnostic>,
 }
 
 impl DiagBag {
-    fn new() -> DiagBag {
-        DiagBag { diags: Vec::new() }
+    pub(crate) fn new() -> DiagBag {
+        DiagBag {
+            diags: Vec::new(),
+            error_limit: None,
+            reached_error_limit: false,
+            warning_config: WarningConfig::new(),
+            taken: HashSet::new(),
+            suppressed_count: 0,
+            deferred: Vec::new(),
+        }
     }
 
-    fn push(&mut self, diag: Diag) {
-        self.diags.push(diag)
+    /// Constructs a bag that takes at most `error_limit` errors. Once the limit
+    /// is reached, a note saying so is added and everything else is dropped.
+    pub(crate) fn with_error_limit(error_limit: Option<usize>) -> DiagBag {
+        DiagBag {
+            error_limit,
+            ..DiagBag::new()
+        }
     }
 
-    fn extend(&mut self, diag_bag: DiagBag) {
-        self.diags.extend(diag_bag.diags);
+    /// Makes the bag adjust the severity of pushed diagnostics (or drop them)
+    /// according to `warning_config`.
+    pub(crate) fn with_warning_config(
+        self,
+        warning_config: WarningConfig,
+    ) -> DiagBag {
+        DiagBag {
+            warning_config,
+            ..self
+        }
+    }
+
+    pub(crate) fn push(&mut self, diag: impl Into<Diagnostic>) {
+        if self.reached_error_limit {
+            return;
+        }
+
+        let mut diagnostic = diag.into();
+        match self
+            .warning_config
+            .adjust_severity(diagnostic.diag.lint(), diagnostic.severity)
+        {
+            Some(severity) => diagnostic.severity = severity,
+            None => return,
+        }
+
+        // Error recovery may report the same problem more than once (e.g. a
+        // cascade of parse errors at the same token), so only the first report
+        // of a code at some span is taken.
+        if let Some(key) = Self::key(&diagnostic) {
+            if !self.taken.insert(key) {
+                self.suppressed_count += 1;
+                return;
+            }
+        }
+
+        let is_error = diagnostic.is_error();
+        self.diags.push(diagnostic);
+
+        if let Some(limit) = self.error_limit {
+            if is_error && self.error_count() >= limit {
+                self.diags.push(Diag::TooManyErrors { limit }.into());
+                self.reached_error_limit = true;
+            }
+        }
+    }
+
+    /// Pushes a diagnostic even past the error limit, for the ones that must
+    /// always be seen, such as internal compiler errors.
+    pub(crate) fn push_unlimited(&mut self, diag: impl Into<Diagnostic>) {
+        let diagnostic = diag.into();
+        self.taken.extend(Self::key(&diagnostic));
+        self.diags.push(diagnostic);
+    }
+
+    /// The code and primary span a diagnostic is a duplicate of another by.
+    /// Diagnostics without a code or span are never duplicates.
+    fn key(diagnostic: &Diagnostic) -> Option<(&'static str, Span)> {
+        Some((diagnostic.diag.code()?, diagnostic.diag.span()?))
+    }
+
+    /// Keeps only the diagnostics for which `keep` returns true.
+    pub(crate) fn retain(&mut self, keep: impl FnMut(&Diagnostic) -> bool) {
+        self.diags.retain(keep);
+        self.taken = self.diags.iter().filter_map(Self::key).collect();
+    }
+
+    pub(crate) fn extend(&mut self, diag_bag: DiagBag) {
+        for diagnostic in diag_bag.diags {
+            self.push(diagnostic);
+        }
+        self.deferred.extend(diag_bag.deferred);
+    }
+
+    /// Holds `diag` back until the current phase ends (see `end_phase`). This
+    /// is for diagnostics only worth reporting when the phase goes well, e.g.
+    /// an "unused function" warning is just noise next to errors.
+    pub(crate) fn defer(&mut self, diag: impl Into<Diagnostic>) {
+        self.deferred.push(diag.into());
+    }
+
+    /// Pushes every diagnostic held back so far.
+    pub(crate) fn flush_deferred(&mut self) {
+        for diagnostic in std::mem::take(&mut self.deferred) {
+            self.push(diagnostic);
+        }
+    }
+
+    /// Drops every diagnostic held back so far.
+    pub(crate) fn discard_deferred(&mut self) {
+        self.deferred.clear();
+    }
+
+    /// Ends a phase of the compilation: the diagnostics held back during it
+    /// are pushed if there were no errors, and dropped otherwise.
+    pub(crate) fn end_phase(&mut self) {
+        if self.has_errors() {
+            self.discard_deferred();
+        } else {
+            self.flush_deferred();
+        }
+    }
+
+    /// Sorts the diagnostics so that they follow the source top to bottom:
+    /// by file, then by position in the file, then by severity. The sort is
+    /// stable, so diagnostics at the same place keep the order they were
+    /// found in. Diagnostics without a span go last, as they are mostly
+    /// about the compilation as a whole (e.g. "too many errors").
+    pub(crate) fn sort_by_position(&mut self) {
+        // Positions are global to the `SourceMap`, so ordering by them orders
+        // by file as well.
+        self.diags.sort_by_key(|diagnostic| {
+            let start = diagnostic.diag.span().map(|span| span.start);
+            (start.is_none(), start, diagnostic.severity)
+        });
+    }
+
+    /// Counts the diagnostics taken so far by severity.
+    pub(crate) fn summary(&self) -> Summary {
+        let mut summary = Summary {
+            suppressed: self.suppressed_count,
+            ..Summary::default()
+        };
+        for diagnostic in &self.diags {
+            let count = match diagnostic.severity {
+                Severity::Fatal => &mut summary.fatal,
+                Severity::Error => &mut summary.errors,
+                Severity::Warning => &mut summary.warnings,
+                Severity::Note => &mut summary.notes,
+                Severity::Help => &mut summary.helps,
+            };
+            *count += 1;
+        }
+
+        summary
+    }
+
+    pub(crate) fn error_count(&self) -> usize {
+        self.diags.iter().filter(|diag| diag.is_error()).count()
+    }
+
+    /// How many more errors the bag takes, if there's a limit.
+    pub(crate) fn errors_left(&self) -> Option<usize> {
+        self.error_limit
+            .map(|limit| limit.saturating_sub(self.error_count()))
+    }
+
+    /// Whether the bag took as many errors as it takes, and takes no more
+    /// diagnostics.
+    pub(crate) fn reached_error_limit(&self) -> bool {
+        self.reached_error_limit
+    }
+
+    /// Whether compilation must stop right away, either because there was a
+    /// fatal error or because the error limit was reached.
+    pub(crate) fn should_abort(&self) -> bool {
+        self.reached_error_limit
+            || self
+                .diags
+                .iter()
+                .any(|diag| diag.severity == Severity::Fatal)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diags.iter()
+    }
+
+    /// Whether any of the diagnostics is an error, i.e. whether compilation
+    /// has to stop. Warnings and other advisory diagnostics don't count.
+    pub(crate) fn has_errors(&self) -> bool {
+        self.diags.iter().any(Diagnostic::is_error)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Diag, DiagBag};
-    use crate::errors::BytePos;
+    use super::{
+        Applicability, Diag, DiagBag, Diagnostic, Label, Severity,
+        SubDiagnostic, Suggestion, Summary,
+    };
+    use crate::{
+        catalog::{set_language, Language},
+        errors::BytePos,
+        lints::WarningConfig,
+        source_map::Span,
+    };
 
     #[test]
     fn new_diag_bag_is_empty() {
@@ -44,36 +2150,763 @@ mod tests {
         let mut bag = DiagBag::new();
         assert!(bag.diags.is_empty());
 
-        let diag1 = Diag::UnknownCharacter { pos: BytePos(0) };
-        let diag2 = Diag::UnknownCharacter { pos: BytePos(1) };
+        let diag1 = Diag::UnknownCharacter {
+            pos: BytePos(0),
+            ch: '@',
+        };
+        let diag2 = Diag::UnknownCharacter {
+            pos: BytePos(1),
+            ch: '@',
+        };
 
-        bag.push(diag1);
-        bag.push(diag2);
+        bag.push(diag1.clone());
+        bag.push(diag2.clone());
 
         assert!(!bag.diags.is_empty());
-        assert_eq!(bag.diags, vec![diag1, diag2]);
+        assert_eq!(bag.diags, vec![diag1.into(), diag2.into()]);
     }
 
     #[test]
     fn extending_from_diag_bag() {
-        let diag1 = Diag::UnknownCharacter { pos: BytePos(0) };
-        let diag2 = Diag::UnknownCharacter { pos: BytePos(1) };
-        let diag3 = Diag::UnknownCharacter { pos: BytePos(2) };
-        let diag4 = Diag::UnknownCharacter { pos: BytePos(3) };
+        let diag1: Diagnostic = Diag::UnknownCharacter {
+            pos: BytePos(0),
+            ch: '@',
+        }
+        .into();
+        let diag2: Diagnostic = Diag::UnknownCharacter {
+            pos: BytePos(1),
+            ch: '@',
+        }
+        .into();
+        let diag3: Diagnostic = Diag::UnknownCharacter {
+            pos: BytePos(2),
+            ch: '@',
+        }
+        .into();
+        let diag4: Diagnostic = Diag::UnknownCharacter {
+            pos: BytePos(3),
+            ch: '@',
+        }
+        .into();
 
         let mut bag1 = DiagBag {
-            diags: vec![diag1, diag2],
+            diags: vec![diag1.clone(), diag2.clone()],
+            ..DiagBag::new()
         };
 
         let bag2 = DiagBag {
-            diags: vec![diag3, diag4],
+            diags: vec![diag3.clone(), diag4.clone()],
+            ..DiagBag::new()
         };
 
-        assert_eq!(bag1.diags, vec![diag1, diag2]);
-        assert_eq!(bag2.diags, vec![diag3, diag4]);
+        assert_eq!(bag1.diags, vec![diag1.clone(), diag2.clone()]);
+        assert_eq!(bag2.diags, vec![diag3.clone(), diag4.clone()]);
 
         bag1.extend(bag2);
 
         assert_eq!(bag1.diags, vec![diag1, diag2, diag3, diag4]);
     }
+
+    #[test]
+    fn diag_converts_to_diagnostic_with_its_default_severity() {
+        let diag = Diag::UnknownCharacter {
+            pos: BytePos(0),
+            ch: '@',
+        };
+        let diagnostic: Diagnostic = diag.clone().into();
+
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.diag, diag);
+    }
+
+    #[test]
+    fn empty_diag_bag_has_no_errors() {
+        let bag = DiagBag::new();
+        assert!(!bag.has_errors());
+    }
+
+    #[test]
+    fn diag_bag_with_only_advisory_diagnostics_has_no_errors() {
+        let diag = Diag::UnknownCharacter {
+            pos: BytePos(0),
+            ch: '@',
+        };
+        let mut bag = DiagBag::new();
+
+        bag.push(Diagnostic::new(Severity::Warning, diag.clone()));
+        bag.push(Diagnostic::new(Severity::Note, diag.clone()));
+        bag.push(Diagnostic::new(Severity::Help, diag));
+
+        assert!(!bag.has_errors());
+    }
+
+    #[test]
+    fn diag_bag_with_an_error_has_errors() {
+        let mut bag = DiagBag::new();
+
+        bag.push(Diagnostic::new(Severity::Warning, unknown_char_error(0)));
+        bag.push(Diagnostic::new(Severity::Error, unknown_char_error(1)));
+
+        assert!(bag.has_errors());
+    }
+
+    #[test]
+    fn unknown_character_spans_the_whole_utf8_char() {
+        let diag = Diag::UnknownCharacter {
+            pos: BytePos(3),
+            ch: 'ã',
+        };
+
+        assert_eq!(diag.span(), Some(Span::with_usizes(3, 5)));
+    }
+
+    #[test]
+    fn unknown_character_message_quotes_the_char() {
+        let diag = Diag::UnknownCharacter {
+            pos: BytePos(0),
+            ch: '@',
+        };
+
+        assert_eq!(diag.message(), "unknown character '@'");
+        assert_eq!(diag.code(), Some("E0001"));
+    }
+
+    #[test]
+    fn display_severities() {
+        assert_eq!(Severity::Error.to_string(), "error");
+        assert_eq!(Severity::Warning.to_string(), "warning");
+        assert_eq!(Severity::Note.to_string(), "note");
+        assert_eq!(Severity::Help.to_string(), "help");
+    }
+
+    #[test]
+    fn diagnostic_labels_start_with_the_primary_one() {
+        let diag = Diag::UnknownCharacter {
+            pos: BytePos(4),
+            ch: '@',
+        };
+        let diagnostic = Diagnostic::builder(diag)
+            .with_label(Span::with_usizes(0, 2), "first")
+            .with_label(Span::with_usizes(8, 9), "second")
+            .build();
+
+        assert_eq!(
+            diagnostic.labels(),
+            vec![
+                Label::primary(Span::with_usizes(4, 5)),
+                Label::secondary(Span::with_usizes(0, 2), "first"),
+                Label::secondary(Span::with_usizes(8, 9), "second"),
+            ]
+        );
+    }
+
+    #[test]
+    fn diagnostic_children_keep_their_order() {
+        let diagnostic = Diagnostic::builder(unknown_char_error(4))
+            .with_note(Some(Span::with_usizes(0, 1)), "first")
+            .with_help(None, "second")
+            .build();
+
+        assert_eq!(
+            diagnostic.children,
+            vec![
+                SubDiagnostic {
+                    severity: Severity::Note,
+                    message: "first".into(),
+                    span: Some(Span::with_usizes(0, 1)),
+                },
+                SubDiagnostic {
+                    severity: Severity::Help,
+                    message: "second".into(),
+                    span: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sub_diagnostic_labels() {
+        let diagnostic = Diagnostic::builder(unknown_char_error(4))
+            .with_note(Some(Span::with_usizes(0, 1)), "here")
+            .with_note(None, "nowhere")
+            .build();
+
+        assert_eq!(
+            diagnostic.children[0].labels(),
+            vec![Label {
+                span: Span::with_usizes(0, 1),
+                message: None,
+                is_primary: false,
+            }]
+        );
+        assert!(diagnostic.children[1].labels().is_empty());
+    }
+
+    #[test]
+    fn label_with_message() {
+        let label =
+            Label::primary(Span::with_usizes(0, 1)).with_message("here");
+
+        assert_eq!(label.message.as_deref(), Some("here"));
+        assert!(label.is_primary);
+    }
+
+    #[test]
+    fn unterminated_block_comment_points_at_start_and_end() {
+        let diag = Diag::UnterminatedBlockComment {
+            start: BytePos(4),
+            end: BytePos(20),
+        };
+
+        assert_eq!(diag.span(), Some(Span::with_usizes(4, 6)));
+        assert_eq!(
+            diag.labels(),
+            vec![
+                Label::primary(Span::with_usizes(4, 6))
+                    .with_message("comment started here"),
+                Label::secondary(
+                    Span::with_usizes(20, 20),
+                    "`*/` expected here"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn messages_and_labels_follow_the_catalog_language() {
+        let diag = Diag::UnterminatedBlockComment {
+            start: BytePos(4),
+            end: BytePos(20),
+        };
+
+        set_language(Language::Portuguese);
+        let message = diag.message();
+        let labels = diag.labels();
+        set_language(Language::English);
+
+        assert_eq!(message, "comentário de bloco não terminado");
+        assert_eq!(
+            labels[0].message.as_deref(),
+            Some("o comentário começa aqui")
+        );
+        assert_eq!(diag.message(), "unterminated block comment");
+    }
+
+    #[test]
+    fn unterminated_block_comment_suggests_closing_it() {
+        let diagnostic: Diagnostic = Diag::UnterminatedBlockComment {
+            start: BytePos(4),
+            end: BytePos(20),
+        }
+        .into();
+
+        assert_eq!(
+            diagnostic.suggestions,
+            vec![Suggestion::insertion(
+                BytePos(20),
+                "*/",
+                "close the comment"
+            )]
+        );
+    }
+
+    #[test]
+    fn identifier_too_long_reports_its_length() {
+        let diag = Diag::IdentifierTooLong {
+            span: Span::with_usizes(10, 110),
+        };
+
+        assert_eq!(
+            diag.labels()[0].message.as_deref(),
+            Some("this identifier has 100 characters, the maximum is 63")
+        );
+    }
+
+    #[test]
+    fn every_lexical_diag_has_a_distinct_code() {
+        let diags = [
+            Diag::UnknownCharacter {
+                pos: BytePos(0),
+                ch: '@',
+            },
+            Diag::UnterminatedBlockComment {
+                start: BytePos(0),
+                end: BytePos(2),
+            },
+            Diag::InvalidNumberLiteral {
+                number: Span::with_usizes(0, 2),
+                suffix: Span::with_usizes(1, 2),
+            },
+            Diag::NonAsciiCharacter {
+                pos: BytePos(0),
+                ch: 'ç',
+            },
+            Diag::IdentifierTooLong {
+                span: Span::with_usizes(0, 64),
+            },
+            Diag::UnterminatedString {
+                start: BytePos(0),
+                end: BytePos(3),
+            },
+            Diag::UnterminatedCharLiteral {
+                start: BytePos(0),
+                end: BytePos(2),
+            },
+            Diag::UnknownEscape {
+                span: Span::with_usizes(1, 3),
+                ch: 'q',
+            },
+            Diag::InvalidCharLiteral {
+                span: Span::with_usizes(0, 2),
+                len: 0,
+            },
+        ];
+
+        let mut codes: Vec<&str> =
+            diags.iter().filter_map(Diag::code).collect();
+        codes.sort_unstable();
+        codes.dedup();
+
+        assert_eq!(codes.len(), diags.len());
+    }
+
+    fn unknown_char_error(pos: usize) -> Diag {
+        Diag::UnknownCharacter {
+            pos: BytePos(pos),
+            ch: '@',
+        }
+    }
+
+    fn non_ascii_error(pos: usize) -> Diag {
+        Diag::NonAsciiCharacter {
+            pos: BytePos(pos),
+            ch: 'é',
+        }
+    }
+
+    #[test]
+    fn fatal_diagnostics_are_errors_and_abort() {
+        let mut bag = DiagBag::new();
+        bag.push(unknown_char_error(0));
+
+        assert!(bag.has_errors());
+        assert!(!bag.should_abort());
+
+        bag.push(Diag::CannotReadFile {
+            path: "main.cm".into(),
+            reason: "No such file or directory".into(),
+        });
+
+        assert_eq!(bag.error_count(), 2);
+        assert!(bag.should_abort());
+    }
+
+    #[test]
+    fn diag_bag_without_error_limit_takes_every_error() {
+        let mut bag = DiagBag::with_error_limit(None);
+        for pos in 0..100 {
+            bag.push(unknown_char_error(pos));
+        }
+
+        assert_eq!(bag.error_count(), 100);
+        assert!(!bag.should_abort());
+    }
+
+    #[test]
+    fn diag_bag_stops_taking_diagnostics_after_error_limit() {
+        let mut bag = DiagBag::with_error_limit(Some(2));
+
+        bag.push(unknown_char_error(0));
+        bag.push(Diagnostic::new(Severity::Warning, unknown_char_error(1)));
+        assert!(!bag.should_abort());
+
+        bag.push(unknown_char_error(2));
+        assert!(bag.should_abort());
+
+        bag.push(unknown_char_error(3));
+        bag.push(Diagnostic::new(Severity::Warning, unknown_char_error(4)));
+
+        assert_eq!(
+            bag.diags,
+            vec![
+                unknown_char_error(0).into(),
+                Diagnostic::new(Severity::Warning, unknown_char_error(1)),
+                unknown_char_error(2).into(),
+                Diag::TooManyErrors { limit: 2 }.into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn extending_diag_bag_respects_error_limit() {
+        let mut bag = DiagBag::with_error_limit(Some(3));
+        let mut other_bag = DiagBag::new();
+        for pos in 0..5 {
+            other_bag.push(unknown_char_error(pos));
+        }
+
+        bag.extend(other_bag);
+
+        assert_eq!(bag.error_count(), 3);
+        assert!(bag.should_abort());
+    }
+
+    #[test]
+    fn diag_bag_applies_warning_config() {
+        let mut warning_config = WarningConfig::new();
+        warning_config.set_warnings_as_errors(true);
+        let mut bag = DiagBag::new().with_warning_config(warning_config);
+
+        bag.push(Diagnostic::new(Severity::Warning, unknown_char_error(0)));
+        bag.push(Diagnostic::new(Severity::Note, unknown_char_error(1)));
+
+        assert_eq!(
+            bag.diags,
+            vec![
+                Diagnostic::new(Severity::Error, unknown_char_error(0)),
+                Diagnostic::new(Severity::Note, unknown_char_error(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn warnings_promoted_to_errors_count_towards_error_limit() {
+        let mut warning_config = WarningConfig::new();
+        warning_config.set_warnings_as_errors(true);
+        let mut bag = DiagBag::with_error_limit(Some(1))
+            .with_warning_config(warning_config);
+
+        bag.push(Diagnostic::new(Severity::Warning, unknown_char_error(0)));
+
+        assert!(bag.should_abort());
+    }
+
+    #[test]
+    fn sort_diagnostics_by_position_then_severity() {
+        let mut bag = DiagBag::new();
+        bag.push(Diag::TooManyErrors { limit: 20 });
+        bag.push(unknown_char_error(7));
+        bag.push(Diagnostic::new(Severity::Warning, non_ascii_error(2)));
+        bag.push(unknown_char_error(2));
+        bag.push(Diagnostic::new(Severity::Note, unknown_char_error(0)));
+
+        bag.sort_by_position();
+
+        assert_eq!(
+            bag.diags,
+            vec![
+                Diagnostic::new(Severity::Note, unknown_char_error(0)),
+                unknown_char_error(2).into(),
+                Diagnostic::new(Severity::Warning, non_ascii_error(2)),
+                unknown_char_error(7).into(),
+                Diag::TooManyErrors { limit: 20 }.into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn sorting_diagnostics_is_stable() {
+        let first = Diagnostic::builder(unknown_char_error(3))
+            .with_label(Span::with_usizes(0, 1), "first")
+            .build();
+        let second = Diagnostic::builder(non_ascii_error(3))
+            .with_label(Span::with_usizes(0, 1), "second")
+            .build();
+        let mut bag = DiagBag::new();
+        bag.push(unknown_char_error(5));
+        bag.push(first.clone());
+        bag.push(second.clone());
+
+        bag.sort_by_position();
+
+        assert_eq!(
+            bag.diags,
+            vec![first, second, unknown_char_error(5).into()]
+        );
+    }
+
+    #[test]
+    fn too_many_errors_is_a_note_without_code_or_span() {
+        let diagnostic: Diagnostic = Diag::TooManyErrors { limit: 20 }.into();
+
+        assert_eq!(diagnostic.severity, Severity::Note);
+        assert_eq!(diagnostic.diag.code(), None);
+        assert_eq!(diagnostic.diag.span(), None);
+        assert!(diagnostic.labels().is_empty());
+    }
+
+    #[test]
+    fn diag_builder_emits_to_a_bag() {
+        let mut bag = DiagBag::new();
+
+        Diagnostic::builder(unknown_char_error(4))
+            .with_severity(Severity::Warning)
+            .with_label(Span::with_usizes(0, 1), "here")
+            .with_help(None, "remove it")
+            .with_suggestion(Suggestion::removal(
+                Span::with_usizes(4, 5),
+                "remove the character",
+            ))
+            .emit(&mut bag);
+
+        assert_eq!(
+            bag.diags,
+            vec![Diagnostic {
+                severity: Severity::Warning,
+                diag: unknown_char_error(4),
+                extra_labels: vec![Label::secondary(
+                    Span::with_usizes(0, 1),
+                    "here"
+                )],
+                children: vec![SubDiagnostic {
+                    severity: Severity::Help,
+                    message: "remove it".into(),
+                    span: None,
+                }],
+                suggestions: vec![Suggestion {
+                    message: "remove the character".into(),
+                    span: Span::with_usizes(4, 5),
+                    replacement: "".into(),
+                    applicability: Applicability::MachineApplicable,
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn diag_builder_keeps_the_default_severity() {
+        let diagnostic = Diagnostic::builder(unknown_char_error(4)).build();
+
+        assert_eq!(diagnostic, unknown_char_error(4).into());
+    }
+
+    #[test]
+    fn insertion_suggestions_have_an_empty_span() {
+        let suggestion = Suggestion::insertion(BytePos(3), ";", "add `;`");
+
+        assert_eq!(suggestion.span, Span::with_usizes(3, 3));
+        assert_eq!(suggestion.replacement, ";");
+        assert_eq!(suggestion.applicability.name(), "machine-applicable");
+    }
+
+    #[test]
+    fn duplicate_diagnostics_are_suppressed() {
+        let mut bag = DiagBag::new();
+
+        bag.push(unknown_char_error(3));
+        bag.push(unknown_char_error(5));
+        bag.push(unknown_char_error(3));
+        bag.push(Diagnostic::new(Severity::Warning, unknown_char_error(3)));
+
+        assert_eq!(
+            bag.diags,
+            vec![unknown_char_error(3).into(), unknown_char_error(5).into()]
+        );
+        assert_eq!(bag.summary().suppressed, 2);
+    }
+
+    #[test]
+    fn diagnostics_left_out_are_no_longer_duplicated() {
+        let mut bag = DiagBag::new();
+
+        bag.push(unknown_char_error(3));
+        bag.push(unknown_char_error(5));
+        bag.retain(|diagnostic| diagnostic.diag != unknown_char_error(3));
+        bag.push(unknown_char_error(3));
+        bag.push(unknown_char_error(5));
+
+        assert_eq!(
+            bag.diags,
+            vec![unknown_char_error(5).into(), unknown_char_error(3).into()]
+        );
+        assert_eq!(bag.summary().suppressed, 1);
+    }
+
+    #[test]
+    fn diagnostics_without_span_are_never_duplicates() {
+        let cannot_read = |path: &str| Diag::CannotReadFile {
+            path: path.into(),
+            reason: "not found".into(),
+        };
+        let mut bag = DiagBag::new();
+
+        bag.push(cannot_read("a.cm"));
+        bag.push(cannot_read("a.cm"));
+
+        assert_eq!(bag.diags.len(), 2);
+        assert_eq!(bag.summary().suppressed, 0);
+    }
+
+    #[test]
+    fn suppressed_duplicates_dont_count_towards_the_error_limit() {
+        let mut bag = DiagBag::with_error_limit(Some(2));
+
+        bag.push(unknown_char_error(3));
+        bag.push(unknown_char_error(3));
+        bag.push(unknown_char_error(3));
+
+        assert_eq!(bag.error_count(), 1);
+        assert!(!bag.should_abort());
+    }
+
+    #[test]
+    fn deferred_diagnostics_are_flushed_at_the_end_of_a_good_phase() {
+        let mut bag = DiagBag::new();
+
+        bag.defer(Diagnostic::new(Severity::Warning, unknown_char_error(1)));
+        bag.push(Diagnostic::new(Severity::Warning, unknown_char_error(5)));
+        assert_eq!(bag.iter().count(), 1);
+
+        bag.end_phase();
+
+        assert_eq!(
+            bag.diags,
+            vec![
+                Diagnostic::new(Severity::Warning, unknown_char_error(5)),
+                Diagnostic::new(Severity::Warning, unknown_char_error(1)),
+            ]
+        );
+        assert!(bag.deferred.is_empty());
+    }
+
+    #[test]
+    fn deferred_diagnostics_are_dropped_at_the_end_of_a_phase_with_errors() {
+        let mut bag = DiagBag::new();
+
+        bag.defer(Diagnostic::new(Severity::Warning, unknown_char_error(1)));
+        bag.push(unknown_char_error(5));
+        bag.end_phase();
+
+        assert_eq!(bag.diags, vec![unknown_char_error(5).into()]);
+        assert!(bag.deferred.is_empty());
+    }
+
+    #[test]
+    fn deferred_diagnostics_can_be_flushed_or_discarded_explicitly() {
+        let mut bag = DiagBag::new();
+
+        bag.defer(unknown_char_error(1));
+        bag.discard_deferred();
+        bag.defer(unknown_char_error(2));
+        bag.flush_deferred();
+
+        assert_eq!(bag.diags, vec![unknown_char_error(2).into()]);
+    }
+
+    #[test]
+    fn extending_keeps_deferred_diagnostics_deferred() {
+        let mut phase_bag = DiagBag::new();
+        phase_bag.defer(unknown_char_error(1));
+        let mut bag = DiagBag::new();
+
+        bag.extend(phase_bag);
+
+        assert!(bag.diags.is_empty());
+        assert_eq!(bag.deferred, vec![unknown_char_error(1).into()]);
+    }
+
+    #[test]
+    fn summary_counts_diagnostics_by_severity() {
+        let mut bag = DiagBag::new();
+        bag.push(unknown_char_error(0));
+        bag.push(unknown_char_error(1));
+        bag.push(unknown_char_error(1));
+        bag.push(Diagnostic::new(Severity::Warning, unknown_char_error(2)));
+        bag.push(Diagnostic::new(Severity::Help, unknown_char_error(3)));
+        bag.push(Diag::CannotReadFile {
+            path: "a.cm".into(),
+            reason: "not found".into(),
+        });
+
+        assert_eq!(
+            bag.summary(),
+            Summary {
+                fatal: 1,
+                errors: 2,
+                warnings: 1,
+                notes: 0,
+                helps: 1,
+                suppressed: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn summary_line() {
+        let line = |errors, warnings| {
+            Summary {
+                errors,
+                warnings,
+                ..Summary::default()
+            }
+            .line()
+        };
+
+        assert_eq!(line(0, 0), None);
+        assert_eq!(
+            line(1, 0),
+            Some((Severity::Error, "aborting due to previous error".into()))
+        );
+        assert_eq!(
+            line(3, 2),
+            Some((
+                Severity::Error,
+                "aborting due to 3 previous errors; 2 warnings emitted".into()
+            ))
+        );
+        assert_eq!(
+            line(0, 1),
+            Some((Severity::Warning, "1 warning emitted".into()))
+        );
+    }
+
+    #[test]
+    fn summary_line_counts_suppressed_duplicates() {
+        let line = |errors, suppressed| {
+            Summary {
+                errors,
+                suppressed,
+                ..Summary::default()
+            }
+            .line()
+        };
+
+        assert_eq!(line(0, 3), None);
+        assert_eq!(
+            line(1, 1),
+            Some((
+                Severity::Error,
+                "aborting due to previous error; 1 duplicate diagnostic \
+                 suppressed"
+                    .into()
+            ))
+        );
+        assert_eq!(
+            line(2, 3),
+            Some((
+                Severity::Error,
+                "aborting due to 2 previous errors; 3 duplicate diagnostics \
+                 suppressed"
+                    .into()
+            ))
+        );
+    }
+
+    #[test]
+    fn summary_line_counts_fatal_errors_as_errors() {
+        let summary = Summary {
+            fatal: 1,
+            errors: 1,
+            warnings: 1,
+            ..Summary::default()
+        };
+
+        assert_eq!(
+            summary.line(),
+            Some((
+                Severity::Error,
+                "aborting due to 2 previous errors; 1 warning emitted".into()
+            ))
+        );
+    }
 }
@@ -0,0 +1,164 @@
+//! Extensions to the C- language, which programs opt into with
+//! `--extension=<name>` flags (or `--extension=all`). Without them, only the
+//! reference grammar is accepted, and a program using an extension is told
+//! which flag enables it.
+
+use crate::catalog::message;
+
+/// A construct beyond the reference grammar of C-.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Extension {
+    /// Negation, `-expr`. The reference grammar only has the binary `-`, and
+    /// it's unclear whether `-1` is meant to be a literal there.
+    UnaryMinus,
+    /// The `for` loop, which C- leaves out in favor of `while`.
+    For,
+    /// The `do`-`while` loop, which checks its condition after each
+    /// iteration.
+    DoWhile,
+    /// The `switch` statement, with `case` and `default` labels.
+    Switch,
+    /// `break;`, leaving the innermost loop or `switch`.
+    Break,
+    /// `continue;`, going on to the next iteration of the innermost loop.
+    Continue,
+    /// The conditional operator, `cond ? a : b`.
+    Ternary,
+    /// An initial value for a variable at file scope, `int x = 10;`, which
+    /// must be a constant.
+    GlobalInit,
+    /// Arrays of more than one dimension, `int m[3][4];`, indexed as
+    /// `m[i][j]`, and parameters of them, `int m[][4]`.
+    MultiDimArrays,
+    /// The `char` type, with character literals, `'a'`, and string
+    /// literals, `"abc"`.
+    Char,
+    /// Pointers: `int *p;` declarations, the address of a variable, `&x`,
+    /// and dereferences, `*p`.
+    Pointers,
+    /// `struct` types, defined at file scope with their fields, and member
+    /// access, `s.x`.
+    Structs,
+    /// The `const` qualifier, `const int x = 5;`, for variables that can't
+    /// be assigned to.
+    Const,
+    /// More than one variable in a declaration, `int a, b[10];`.
+    DeclaratorLists,
+    /// An array size that's a constant expression rather than a number,
+    /// e.g. `int a[2 * 8];`.
+    SizeExpressions,
+}
+
+impl Extension {
+    pub(crate) const ALL: &'static [Extension] = &[
+        Extension::UnaryMinus,
+        Extension::For,
+        Extension::DoWhile,
+        Extension::Switch,
+        Extension::Break,
+        Extension::Continue,
+        Extension::Ternary,
+        Extension::GlobalInit,
+        Extension::MultiDimArrays,
+        Extension::Char,
+        Extension::Pointers,
+        Extension::Structs,
+        Extension::Const,
+        Extension::DeclaratorLists,
+        Extension::SizeExpressions,
+    ];
+
+    /// The name used to refer to the extension in the command line.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Extension::UnaryMinus => "unary-minus",
+            Extension::For => "for",
+            Extension::DoWhile => "do-while",
+            Extension::Switch => "switch",
+            Extension::Break => "break",
+            Extension::Continue => "continue",
+            Extension::Ternary => "ternary",
+            Extension::GlobalInit => "global-init",
+            Extension::MultiDimArrays => "multi-dim-arrays",
+            Extension::Char => "char",
+            Extension::Pointers => "pointers",
+            Extension::Structs => "structs",
+            Extension::Const => "const",
+            Extension::DeclaratorLists => "declarator-lists",
+            Extension::SizeExpressions => "size-expressions",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Extension> {
+        Extension::ALL
+            .iter()
+            .copied()
+            .find(|ext| ext.name() == name)
+    }
+
+    /// What the extension adds, as a noun phrase for messages, e.g. "unary
+    /// minus".
+    pub(crate) fn describe(self) -> String {
+        message(&format!("extension.{}", self.name()), &[])
+    }
+}
+
+/// The set of extensions enabled for a compilation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Extensions {
+    /// A bit per extension, in the order of `Extension::ALL`.
+    bits: u32,
+}
+
+impl Extensions {
+    /// No extensions, i.e. the reference C- language.
+    pub(crate) fn none() -> Extensions {
+        Extensions::default()
+    }
+
+    pub(crate) fn all() -> Extensions {
+        let mut extensions = Extensions::none();
+        for &ext in Extension::ALL {
+            extensions.enable(ext);
+        }
+        extensions
+    }
+
+    pub(crate) fn enable(&mut self, ext: Extension) {
+        self.bits |= bit(ext);
+    }
+
+    pub(crate) fn is_enabled(self, ext: Extension) -> bool {
+        self.bits & bit(ext) != 0
+    }
+}
+
+fn bit(ext: Extension) -> u32 {
+    1 << ext as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Extension, Extensions};
+
+    #[test]
+    fn extension_names_round_trip() {
+        for &ext in Extension::ALL {
+            assert_eq!(Extension::from_name(ext.name()), Some(ext));
+        }
+
+        assert_eq!(Extension::from_name("no-such-extension"), None);
+    }
+
+    #[test]
+    fn only_enabled_extensions_are_enabled() {
+        let mut extensions = Extensions::none();
+        assert!(!extensions.is_enabled(Extension::UnaryMinus));
+
+        extensions.enable(Extension::UnaryMinus);
+        assert!(extensions.is_enabled(Extension::UnaryMinus));
+        assert!(Extension::ALL
+            .iter()
+            .all(|&ext| Extensions::all().is_enabled(ext)));
+    }
+}
@@ -0,0 +1,292 @@
+//! Rewriting source files with the machine-applicable suggestions of a
+//! compilation, for `--fix`.
+
+use crate::{
+    errors::{Applicability, Diagnostic, Suggestion},
+    source_map::{Pos, SourceFile, SourceMap},
+};
+use std::rc::Rc;
+
+/// A source file with suggestions applied to it.
+pub(crate) struct FixedFile {
+    pub(crate) source_file: Rc<SourceFile>,
+    /// The file's text after applying the suggestions.
+    pub(crate) fixed_source: String,
+    pub(crate) applied: Vec<Suggestion>,
+    /// Suggestions left out for overlapping with applied ones.
+    pub(crate) skipped: Vec<Suggestion>,
+}
+
+impl FixedFile {
+    /// Applies `suggestions` to `source_file`. Suggestions are applied from
+    /// the start of the file to its end, and one overlapping a suggestion
+    /// applied before is skipped, as its span no longer means the same text.
+    /// Identical suggestions are applied once.
+    fn new(
+        source_file: Rc<SourceFile>,
+        mut suggestions: Vec<Suggestion>,
+    ) -> FixedFile {
+        suggestions.sort_by_key(|suggestion| {
+            (suggestion.span.start, suggestion.span.end)
+        });
+
+        let mut applied: Vec<Suggestion> = Vec::new();
+        let mut skipped = Vec::new();
+        for suggestion in suggestions {
+            match applied.last() {
+                Some(last) if *last == suggestion => {}
+                Some(last) if overlaps(last, &suggestion) => {
+                    skipped.push(suggestion)
+                }
+                _ => applied.push(suggestion),
+            }
+        }
+
+        let src = source_file.src.as_str();
+        let mut fixed_source = String::with_capacity(src.len());
+        let mut copied_up_to = 0;
+        for suggestion in &applied {
+            let start =
+                (suggestion.span.start - source_file.start_pos).to_usize();
+            let end = (suggestion.span.end - source_file.start_pos).to_usize();
+            fixed_source.push_str(&src[copied_up_to..start]);
+            fixed_source.push_str(&suggestion.replacement);
+            copied_up_to = end;
+        }
+        fixed_source.push_str(&src[copied_up_to..]);
+
+        FixedFile {
+            source_file,
+            fixed_source,
+            applied,
+            skipped,
+        }
+    }
+}
+
+/// Whether `next`, which doesn't start before `prev`, edits text `prev` edits
+/// too. Two insertions at the same position overlap, as the order of the
+/// inserted texts would be arbitrary.
+fn overlaps(prev: &Suggestion, next: &Suggestion) -> bool {
+    let is_insertion =
+        |suggestion: &Suggestion| suggestion.span.start == suggestion.span.end;
+
+    next.span.start < prev.span.end
+        || (is_insertion(prev) && next.span == prev.span)
+}
+
+/// Applies the machine-applicable suggestions of `diagnostics` to the files
+/// they point into, returning the files that had any.
+pub(crate) fn fix_files<'a>(
+    source_map: &SourceMap,
+    diagnostics: impl IntoIterator<Item = &'a Diagnostic>,
+) -> Vec<FixedFile> {
+    let mut suggestions_by_file: Vec<(Rc<SourceFile>, Vec<Suggestion>)> =
+        Vec::new();
+
+    for suggestion in diagnostics
+        .into_iter()
+        .flat_map(|diagnostic| diagnostic.suggestions.iter())
+        .filter(|suggestion| {
+            suggestion.applicability == Applicability::MachineApplicable
+        })
+    {
+        let source_file = match source_map.lookup_file(suggestion.span.start) {
+            Some(source_file) if source_file.contains(suggestion.span.end) => {
+                source_file
+            }
+            _ => continue,
+        };
+
+        match suggestions_by_file
+            .iter_mut()
+            .find(|(file, _)| Rc::ptr_eq(file, source_file))
+        {
+            Some((_, suggestions)) => suggestions.push(suggestion.clone()),
+            None => suggestions_by_file
+                .push((Rc::clone(source_file), vec![suggestion.clone()])),
+        }
+    }
+
+    suggestions_by_file
+        .into_iter()
+        .map(|(source_file, suggestions)| {
+            FixedFile::new(source_file, suggestions)
+        })
+        .collect()
+}
+
+/// Whether `diagnostic` was fixed, that is, it has suggestions and all of its
+/// machine-applicable ones were applied to some file in `fixed_files`.
+pub(crate) fn was_fixed(
+    diagnostic: &Diagnostic,
+    fixed_files: &[FixedFile],
+) -> bool {
+    let mut suggestions = diagnostic
+        .suggestions
+        .iter()
+        .filter(|suggestion| {
+            suggestion.applicability == Applicability::MachineApplicable
+        })
+        .peekable();
+
+    suggestions.peek().is_some()
+        && suggestions.all(|suggestion| {
+            fixed_files
+                .iter()
+                .any(|fixed_file| fixed_file.applied.contains(suggestion))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fix_files, was_fixed};
+    use crate::{
+        errors::{Applicability, Diag, Diagnostic, Suggestion},
+        source_map::{BytePos, SourceMap, Span},
+    };
+
+    fn diagnostic_with(suggestions: Vec<Suggestion>) -> Diagnostic {
+        suggestions
+            .into_iter()
+            .fold(
+                Diagnostic::builder(Diag::UnknownCharacter {
+                    pos: BytePos(0),
+                    ch: '@',
+                }),
+                |builder, suggestion| builder.with_suggestion(suggestion),
+            )
+            .build()
+    }
+
+    fn source_map(text: &str) -> SourceMap {
+        let mut source_map = SourceMap::new();
+        source_map.add_file("main.cm".into(), text.into());
+        source_map
+    }
+
+    #[test]
+    fn apply_suggestions_in_source_order() {
+        let source_map = source_map("int x @\nx = 1\n");
+        let diagnostics = vec![
+            diagnostic_with(vec![Suggestion::insertion(BytePos(13), ";", "")]),
+            diagnostic_with(vec![Suggestion::removal(
+                Span::with_usizes(5, 7),
+                "",
+            )]),
+            diagnostic_with(vec![Suggestion::insertion(BytePos(5), ";", "")]),
+        ];
+
+        let fixed_files = fix_files(&source_map, &diagnostics);
+
+        assert_eq!(fixed_files.len(), 1);
+        assert_eq!(fixed_files[0].fixed_source, "int x;\nx = 1;\n");
+        assert_eq!(fixed_files[0].applied.len(), 3);
+        assert!(fixed_files[0].skipped.is_empty());
+    }
+
+    #[test]
+    fn overlapping_suggestions_are_skipped() {
+        let source_map = source_map("abcdef");
+        let insertion =
+            |pos, text| Suggestion::insertion(BytePos(pos), text, "");
+        let removal = Suggestion::removal(Span::with_usizes(1, 4), "");
+        let overlapping = Suggestion::removal(Span::with_usizes(3, 5), "");
+        let diagnostics = vec![diagnostic_with(vec![
+            overlapping.clone(),
+            insertion(2, "z"),
+            removal.clone(),
+            insertion(0, "x"),
+            insertion(0, "y"),
+        ])];
+
+        let fixed_files = fix_files(&source_map, &diagnostics);
+
+        assert_eq!(fixed_files[0].fixed_source, "xaef");
+        assert_eq!(fixed_files[0].applied, vec![insertion(0, "x"), removal]);
+        assert_eq!(
+            fixed_files[0].skipped,
+            vec![insertion(0, "y"), insertion(2, "z"), overlapping]
+        );
+    }
+
+    #[test]
+    fn insertion_before_a_replacement_is_applied() {
+        let source_map = source_map("abc");
+        let diagnostics = vec![diagnostic_with(vec![
+            Suggestion::new(
+                Span::with_usizes(1, 2),
+                "B",
+                "",
+                Applicability::MachineApplicable,
+            ),
+            Suggestion::insertion(BytePos(1), "_", ""),
+        ])];
+
+        let fixed_files = fix_files(&source_map, &diagnostics);
+
+        assert_eq!(fixed_files[0].fixed_source, "a_Bc");
+        assert!(fixed_files[0].skipped.is_empty());
+    }
+
+    #[test]
+    fn identical_suggestions_are_applied_once() {
+        let source_map = source_map("x");
+        let suggestion = Suggestion::insertion(BytePos(1), ";", "");
+        let diagnostics = vec![
+            diagnostic_with(vec![suggestion.clone()]),
+            diagnostic_with(vec![suggestion]),
+        ];
+
+        let fixed_files = fix_files(&source_map, &diagnostics);
+
+        assert_eq!(fixed_files[0].fixed_source, "x;");
+        assert!(fixed_files[0].skipped.is_empty());
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| was_fixed(diagnostic, &fixed_files)));
+    }
+
+    #[test]
+    fn only_machine_applicable_suggestions_are_applied() {
+        let source_map = source_map("x");
+        let diagnostic = diagnostic_with(vec![Suggestion::new(
+            Span::with_usizes(0, 1),
+            "y",
+            "",
+            Applicability::MaybeIncorrect,
+        )]);
+
+        let fixed_files = fix_files(&source_map, [&diagnostic]);
+
+        assert!(fixed_files.is_empty());
+        assert!(!was_fixed(&diagnostic, &fixed_files));
+    }
+
+    #[test]
+    fn suggestions_are_applied_to_their_own_file() {
+        let mut source_map = SourceMap::new();
+        source_map.add_file("a.cm".into(), "ab".into());
+        source_map.add_file("b.cm".into(), "cd".into());
+        let diagnostics = vec![
+            diagnostic_with(vec![Suggestion::removal(
+                Span::with_usizes(4, 5),
+                "",
+            )]),
+            diagnostic_with(vec![Suggestion::insertion(BytePos(0), "_", "")]),
+        ];
+
+        let fixed_files = fix_files(&source_map, &diagnostics);
+
+        let fixed: Vec<_> = fixed_files
+            .iter()
+            .map(|fixed_file| {
+                (
+                    fixed_file.source_file.name.as_str(),
+                    fixed_file.fixed_source.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(fixed, vec![("b.cm", "c"), ("a.cm", "_ab")]);
+    }
+}
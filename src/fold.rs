@@ -0,0 +1,279 @@
+//! Constant folding: working out at compile time what's only made of
+//! constants, e.g. the `mul 1, 4` an index into an `int` array is scaled
+//! with, and what an identity makes of an operand, e.g. `add %0, 0`.
+//!
+//! A temporary assigned a constant is replaced by it where it's read later
+//! in the same block, so what's worked out from it is folded too. Nothing
+//! is known at the start of a block, as a temporary may be assigned more
+//! than once, on different paths into it. A branch on a constant becomes a
+//! jump to where it'd go, and a check of a constant that isn't 0 is dropped,
+//! as it always passes.
+//!
+//! `int` arithmetic wraps, as in the interpreter. A division by zero is
+//! left for the program to do, as folding it would drop its error.
+
+use crate::{
+    ast::BinOp,
+    ir::{Fun, Inst, Operand, Program, Ty},
+};
+use std::collections::HashMap;
+
+/// Folds the constants in the functions of `program`, returning whether
+/// anything changed.
+pub(crate) fn fold_program(program: &mut Program) -> bool {
+    let mut changed = false;
+    for fun in &mut program.funs {
+        changed |= fold_fun(fun);
+    }
+    changed
+}
+
+/// Folds the constants in `fun`, returning whether anything changed.
+pub(crate) fn fold_fun(fun: &mut Fun) -> bool {
+    let mut changed = false;
+    // The constant each `i32` temporary was last assigned in the block.
+    let mut known = HashMap::new();
+    for inst in &mut fun.code {
+        if let Inst::Label(_) = inst {
+            known.clear();
+            continue;
+        }
+        for operand in inst.operands_mut() {
+            if let Operand::Temp(temp) = *operand {
+                if let Some(&value) = known.get(&temp) {
+                    *operand = Operand::Const(value);
+                    changed = true;
+                }
+            }
+        }
+        if let Some(folded) = fold_inst(inst) {
+            *inst = folded;
+            changed = true;
+        }
+        match *inst {
+            Inst::Copy {
+                dst,
+                src: Operand::Const(value),
+            } if fun.temps[dst.0] == Ty::I32 => {
+                known.insert(dst, value);
+            }
+            _ => {
+                if let Some(dst) = inst.dst() {
+                    known.remove(&dst);
+                }
+            }
+        }
+        if inst.is_terminator() {
+            known.clear();
+        }
+    }
+    let len = fun.code.len();
+    fun.code.retain(|inst| {
+        !matches!(*inst, Inst::Check { cond: Operand::Const(value), .. }
+            if value != 0)
+    });
+    changed || fun.code.len() != len
+}
+
+/// What `inst` simplifies to, if it does.
+pub(crate) fn fold_inst(inst: &Inst) -> Option<Inst> {
+    use Operand::Const;
+
+    match *inst {
+        Inst::Neg {
+            dst,
+            src: Const(value),
+        } => Some(Inst::Copy {
+            dst,
+            src: Const(i64::from((value as i32).wrapping_neg())),
+        }),
+        Inst::Binary {
+            dst,
+            op,
+            lhs: Const(lhs),
+            rhs: Const(rhs),
+        } => fold_binary(op, lhs as i32, rhs as i32).map(|value| Inst::Copy {
+            dst,
+            src: Const(i64::from(value)),
+        }),
+        Inst::Binary { dst, op, lhs, rhs } => {
+            let src = match (op, lhs, rhs) {
+                (BinOp::Add, Const(0), other)
+                | (BinOp::Add | BinOp::Sub, other, Const(0))
+                | (BinOp::Mul, Const(1), other)
+                | (BinOp::Mul | BinOp::Div, other, Const(1)) => other,
+                (BinOp::Mul, Const(0), _) | (BinOp::Mul, _, Const(0)) => {
+                    Const(0)
+                }
+                _ => return None,
+            };
+            Some(Inst::Copy { dst, src })
+        }
+        Inst::Offset {
+            dst,
+            base,
+            bytes: Const(0),
+        } => Some(Inst::Copy { dst, src: base }),
+        Inst::Branch {
+            cond: Const(value),
+            then_label,
+            else_label,
+        } => Some(Inst::Jump(if value != 0 { then_label } else { else_label })),
+        _ => None,
+    }
+}
+
+/// `lhs op rhs`, unless it's a division by zero.
+fn fold_binary(op: BinOp, lhs: i32, rhs: i32) -> Option<i32> {
+    let value = match op {
+        BinOp::Add => lhs.wrapping_add(rhs),
+        BinOp::Sub => lhs.wrapping_sub(rhs),
+        BinOp::Mul => lhs.wrapping_mul(rhs),
+        BinOp::Div if rhs == 0 => return None,
+        BinOp::Div => lhs.wrapping_div(rhs),
+        BinOp::Less => i32::from(lhs < rhs),
+        BinOp::LessEqual => i32::from(lhs <= rhs),
+        BinOp::Greater => i32::from(lhs > rhs),
+        BinOp::GreaterEqual => i32::from(lhs >= rhs),
+        BinOp::Equal => i32::from(lhs == rhs),
+        BinOp::NotEqual => i32::from(lhs != rhs),
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        extensions::Extensions,
+        ir::{dump_ir, lower_program_with_checks, Checks},
+        parser::ParseResult,
+        passes::{run_passes, Pass},
+        sema::{check_program, Builtin},
+        test_support::{ir_after, parse_source},
+    };
+
+    /// The code of `source` before and after folding.
+    fn fold(source: &str) -> (String, String) {
+        (ir_after(source, &[]), ir_after(source, &[Pass::Fold]))
+    }
+
+    #[test]
+    fn constants_identities_and_branches_are_folded() {
+        let source = "int v[4];\n\
+                      void main(void) {\n\
+                      \x20   int i;\n\
+                      \x20   i = 2 * 3 - 6;\n\
+                      \x20   v[1] = i + 0;\n\
+                      \x20   if (1 < 2) output(v[1] * 1); else output(-(4 / 0));\n\
+                      }\n";
+
+        let (before, after) = fold(source);
+        assert_eq!(
+            before,
+            "global @v: 16, align 4\n\
+             \n\
+             fun @main() {\n\
+             \x20 slot $0 i: 4, align 4\n\
+             \x20 %0: i32 = mul 2, 3\n\
+             \x20 %1: i32 = sub %0, 6\n\
+             \x20 store i32 $0, %1\n\
+             \x20 %2: i32 = mul 1, 4\n\
+             \x20 %3: ptr = offset @v, %2\n\
+             \x20 %4: i32 = load i32 $0\n\
+             \x20 %5: i32 = add %4, 0\n\
+             \x20 store i32 %3, %5\n\
+             \x20 %6: i32 = lt 1, 2\n\
+             \x20 branch %6, L0, L1\n\
+             L0:\n\
+             \x20 %7: i32 = mul 1, 4\n\
+             \x20 %8: ptr = offset @v, %7\n\
+             \x20 %9: i32 = load i32 %8\n\
+             \x20 %10: i32 = mul %9, 1\n\
+             \x20 call @output(%10)\n\
+             \x20 jump L2\n\
+             L1:\n\
+             \x20 %11: i32 = div 4, 0\n\
+             \x20 %12: i32 = neg %11\n\
+             \x20 call @output(%12)\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 ret\n\
+             }\n"
+        );
+        assert_eq!(
+            after,
+            "global @v: 16, align 4\n\
+             \n\
+             fun @main() {\n\
+             \x20 slot $0 i: 4, align 4\n\
+             \x20 %0: i32 = copy 6\n\
+             \x20 %1: i32 = copy 0\n\
+             \x20 store i32 $0, 0\n\
+             \x20 %2: i32 = copy 4\n\
+             \x20 %3: ptr = offset @v, 4\n\
+             \x20 %4: i32 = load i32 $0\n\
+             \x20 %5: i32 = copy %4\n\
+             \x20 store i32 %3, %5\n\
+             \x20 %6: i32 = copy 1\n\
+             \x20 jump L0\n\
+             L0:\n\
+             \x20 %7: i32 = copy 4\n\
+             \x20 %8: ptr = offset @v, 4\n\
+             \x20 %9: i32 = load i32 %8\n\
+             \x20 %10: i32 = copy %9\n\
+             \x20 call @output(%10)\n\
+             \x20 jump L2\n\
+             L1:\n\
+             \x20 %11: i32 = div 4, 0\n\
+             \x20 %12: i32 = neg %11\n\
+             \x20 call @output(%12)\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn int_arithmetic_wraps() {
+        let (_, after) = fold(
+            "void main(void) { output(2147483647 + 1); output(-2147483647 - 1 - 1); }",
+        );
+        assert!(after.contains("call @output(-2147483648)"), "{}", after);
+        assert!(after.contains("call @output(2147483647)"), "{}", after);
+    }
+
+    #[test]
+    fn checks_of_constants_that_pass_are_dropped() {
+        let (source_file, ParseResult { program, .. }) = parse_source(
+            "void main(void) { int a[4]; a[4] = 1; }",
+            Extensions::all(),
+        );
+        let analysis = check_program(&program, &Builtin::ALL);
+        let checks = Checks {
+            bounds: true,
+            ..Checks::default()
+        };
+        let mut program = lower_program_with_checks(
+            &analysis.hir,
+            checks,
+            Some(&source_file),
+        );
+        run_passes(&mut program, &[Pass::Fold]);
+
+        // `ge 4, 0` holds, and `lt 4, 4` doesn't.
+        assert_eq!(
+            dump_ir(&program),
+            "fun @main() {\n\
+             \x20 slot $0 a: 16, align 4\n\
+             \x20 %0: i32 = copy 1\n\
+             \x20 %1: i32 = copy 0\n\
+             \x20 check bounds 0, line 1\n\
+             \x20 %2: i32 = copy 16\n\
+             \x20 %3: ptr = offset $0, 16\n\
+             \x20 store i32 %3, 1\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! The layout of the frame of a function: where its arguments come in, and
+//! where the registers it saves, its spill slots and its slots are, as
+//! offsets from its frame pointer. Every backend that keeps a function's
+//! variables in memory lays them out here, for the target it's for, rather
+//! than working the offsets out on its own. Shown with `--emit=frame`, for
+//! x86-64.
+//!
+//! The saved registers come first, then the spill slots, each a word, and
+//! then the slots, each aligned as its layout says. The frame takes a
+//! multiple of the alignment the target keeps the stack at, so a call from
+//! it finds the stack as aligned as it should be.
+
+use crate::{ir::Program, regalloc::RegAlloc, sema::Layout};
+use std::fmt::Write;
+
+/// What a frame is laid out for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Target {
+    /// In bytes, of a saved register, of a spill slot and of an argument on
+    /// the stack.
+    pub(crate) word: u64,
+    /// What the stack is kept aligned to at a call.
+    pub(crate) stack_align: u64,
+    /// Whether the frame is below the frame pointer, with its variables at
+    /// negative offsets, rather than above it.
+    pub(crate) grows_down: bool,
+    /// The registers a function saves before it allocates temporaries to
+    /// them, in the order they're allocated.
+    pub(crate) saved_regs: &'static [&'static str],
+    pub(crate) args: Args,
+}
+
+/// How a target passes the arguments of a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Args {
+    /// Straight into the temporaries of the callee.
+    InTemps,
+    /// The first ones in `regs` and the rest on the stack, the first of
+    /// those at `stack_offset` from the frame pointer of the callee.
+    Regs {
+        regs: &'static [&'static str],
+        stack_offset: i64,
+    },
+}
+
+/// The System V calling convention, below `%rbp`. The arguments on the
+/// stack are above the return address and the saved `%rbp`.
+pub(crate) const X86_64: Target = Target {
+    word: 8,
+    stack_align: 16,
+    grows_down: true,
+    saved_regs: &["%rbx", "%r12", "%r13", "%r14", "%r15"],
+    args: Args::Regs {
+        regs: &["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"],
+        stack_offset: 16,
+    },
+};
+
+/// The bytecode VM (see `bytecode`), whose frames are in its memory, from
+/// the address each starts at upwards.
+pub(crate) const VM: Target = Target {
+    word: 8,
+    stack_align: 1,
+    grows_down: false,
+    saved_regs: &[],
+    args: Args::InTemps,
+};
+
+impl Target {
+    /// How many of `args` arguments a call passes on the stack, and how
+    /// many bytes of padding go before them for the stack to stay aligned.
+    pub(crate) fn stack_args(&self, args: usize) -> (usize, u64) {
+        match self.args {
+            Args::InTemps => (0, 0),
+            Args::Regs { regs, .. } => {
+                let on_stack = args.saturating_sub(regs.len());
+                let size = self.word * on_stack as u64;
+                (on_stack, size.next_multiple_of(self.stack_align) - size)
+            }
+        }
+    }
+}
+
+/// Where an argument of a function comes in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ParamPlace {
+    /// In its temporary.
+    Temp,
+    Reg(&'static str),
+    /// On the stack, at this offset from the frame pointer.
+    Stack(i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FrameLayout {
+    pub(crate) params: Vec<ParamPlace>,
+    /// Where each register the function saves is, as its offset from the
+    /// frame pointer, by its index in `Target::saved_regs`.
+    pub(crate) saved_regs: Vec<i64>,
+    /// Where each spill slot is, as its offset from the frame pointer.
+    pub(crate) spill_slots: Vec<i64>,
+    /// Where each slot is, as its offset from the frame pointer.
+    pub(crate) slots: Vec<i64>,
+    /// How many bytes the frame takes, a multiple of `Target::stack_align`.
+    pub(crate) size: u64,
+    /// What the frame pointer must be aligned to.
+    pub(crate) align: u64,
+}
+
+impl FrameLayout {
+    /// Lays out, for `target`, the frame of a function with `params`
+    /// arguments and `slots`, which saves the first `saved_regs` of the
+    /// registers of the target and has `spill_slots` spill slots.
+    pub(crate) fn new(
+        target: &Target,
+        params: usize,
+        slots: impl IntoIterator<Item = Layout>,
+        saved_regs: usize,
+        spill_slots: usize,
+    ) -> Self {
+        let mut size = 0;
+        let mut align = target.stack_align;
+        let mut place = |layout: Layout| {
+            let layout_align = layout.align.max(1);
+            align = align.max(layout_align);
+            if target.grows_down {
+                size = (size + layout.size).next_multiple_of(layout_align);
+                -(size as i64)
+            } else {
+                let offset = size.next_multiple_of(layout_align);
+                size = offset + layout.size;
+                offset as i64
+            }
+        };
+        let word = Layout {
+            size: target.word,
+            align: target.word,
+        };
+        let saved_regs = (0..saved_regs).map(|_| place(word)).collect();
+        let spill_slots = (0..spill_slots).map(|_| place(word)).collect();
+        let slots = slots.into_iter().map(&mut place).collect();
+
+        let params = (0..params)
+            .map(|index| match target.args {
+                Args::InTemps => ParamPlace::Temp,
+                Args::Regs { regs, stack_offset } => match regs.get(index) {
+                    Some(reg) => ParamPlace::Reg(reg),
+                    None => ParamPlace::Stack(
+                        stack_offset
+                            + (target.word * (index - regs.len()) as u64)
+                                as i64,
+                    ),
+                },
+            })
+            .collect();
+        FrameLayout {
+            params,
+            saved_regs,
+            spill_slots,
+            slots,
+            size: size.next_multiple_of(target.stack_align),
+            align,
+        }
+    }
+}
+
+/// The layout of the frame of each function of `program` on x86-64, with
+/// its temporaries allocated as the backend allocates them, as `regalloc`
+/// says.
+pub(crate) fn dump_frames(program: &Program, regalloc: RegAlloc) -> String {
+    let mut out = String::new();
+    for fun in &program.funs {
+        let allocation = regalloc.allocate(fun, X86_64.saved_regs.len());
+        let layout = FrameLayout::new(
+            &X86_64,
+            fun.params,
+            fun.slots.iter().map(|slot| slot.layout),
+            allocation.registers_used,
+            allocation.spill_slots,
+        );
+        let _ = writeln!(
+            out,
+            "@{}: {} bytes, aligned to {}",
+            fun.name, layout.size, layout.align
+        );
+        for (index, &place) in layout.params.iter().enumerate() {
+            let place = match place {
+                ParamPlace::Temp => format!("%{}", index),
+                ParamPlace::Reg(reg) => reg.to_string(),
+                ParamPlace::Stack(offset) => offset_from_fp(offset),
+            };
+            let _ = writeln!(out, "  param {:<10} {}", index, place);
+        }
+        for (reg, &offset) in X86_64.saved_regs.iter().zip(&layout.saved_regs) {
+            let _ =
+                writeln!(out, "  saved {:<10} {}", reg, offset_from_fp(offset));
+        }
+        for (index, &offset) in layout.spill_slots.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "  spill {:<10} {}",
+                index,
+                offset_from_fp(offset)
+            );
+        }
+        for (index, (slot, &offset)) in
+            fun.slots.iter().zip(&layout.slots).enumerate()
+        {
+            let name = match &slot.name {
+                Some(name) => format!("${} {}", index, name),
+                None => format!("${}", index),
+            };
+            let _ = writeln!(
+                out,
+                "  slot  {:<10} {}, {} bytes",
+                name,
+                offset_from_fp(offset),
+                slot.layout.size
+            );
+        }
+    }
+    out
+}
+
+/// `offset` from the frame pointer, e.g. `fp-8`.
+fn offset_from_fp(offset: i64) -> String {
+    if offset < 0 {
+        format!("fp{}", offset)
+    } else {
+        format!("fp+{}", offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dump_frames, FrameLayout, ParamPlace, VM, X86_64};
+    use crate::{regalloc::RegAlloc, sema::Layout, test_support::lower_source};
+
+    const INT: Layout = Layout { size: 4, align: 4 };
+    const CHARS: Layout = Layout { size: 5, align: 1 };
+    const POINTER: Layout = Layout { size: 8, align: 8 };
+
+    #[test]
+    fn frames_below_the_frame_pointer_are_aligned_for_calls() {
+        let layout = FrameLayout::new(&X86_64, 8, [INT, CHARS, POINTER], 2, 1);
+
+        assert_eq!(layout.saved_regs, [-8, -16]);
+        assert_eq!(layout.spill_slots, [-24]);
+        assert_eq!(layout.slots, [-28, -33, -48]);
+        assert_eq!(layout.size, 48);
+        assert_eq!(layout.align, 16);
+        assert_eq!(layout.params[0], ParamPlace::Reg("%rdi"));
+        assert_eq!(layout.params[5], ParamPlace::Reg("%r9"));
+        assert_eq!(
+            layout.params[6..],
+            [ParamPlace::Stack(16), ParamPlace::Stack(24)]
+        );
+
+        assert_eq!(X86_64.stack_args(6), (0, 0));
+        assert_eq!(X86_64.stack_args(7), (1, 8));
+        assert_eq!(X86_64.stack_args(8), (2, 0));
+    }
+
+    #[test]
+    fn frames_above_the_frame_pointer_are_as_small_as_they_can_be() {
+        let layout = FrameLayout::new(&VM, 2, [CHARS, INT, CHARS], 0, 0);
+
+        assert_eq!(layout.slots, [0, 8, 12]);
+        assert_eq!(layout.size, 17);
+        assert_eq!(layout.align, 4);
+        assert_eq!(layout.params, [ParamPlace::Temp, ParamPlace::Temp]);
+        assert_eq!(VM.stack_args(9), (0, 0));
+    }
+
+    #[test]
+    fn every_function_has_its_frame_dumped() {
+        let program = lower_source(
+            "int f(int a, int b, int c, int d, int e, int f, int g) {\n\
+             \x20 int v[3]; v[0] = g; return v[0] + a;\n\
+             }\n\
+             void main(void) { output(f(1, 2, 3, 4, 5, 6, 7)); }",
+        );
+
+        assert_eq!(
+            dump_frames(&program, RegAlloc::Linear),
+            "@f: 96 bytes, aligned to 16\n\
+             \x20 param 0          %rdi\n\
+             \x20 param 1          %rsi\n\
+             \x20 param 2          %rdx\n\
+             \x20 param 3          %rcx\n\
+             \x20 param 4          %r8\n\
+             \x20 param 5          %r9\n\
+             \x20 param 6          fp+16\n\
+             \x20 saved %rbx       fp-8\n\
+             \x20 saved %r12       fp-16\n\
+             \x20 saved %r13       fp-24\n\
+             \x20 saved %r14       fp-32\n\
+             \x20 saved %r15       fp-40\n\
+             \x20 spill 0          fp-48\n\
+             \x20 spill 1          fp-56\n\
+             \x20 slot  $0 a       fp-60, 4 bytes\n\
+             \x20 slot  $1 b       fp-64, 4 bytes\n\
+             \x20 slot  $2 c       fp-68, 4 bytes\n\
+             \x20 slot  $3 d       fp-72, 4 bytes\n\
+             \x20 slot  $4 e       fp-76, 4 bytes\n\
+             \x20 slot  $5 f       fp-80, 4 bytes\n\
+             \x20 slot  $6 g       fp-84, 4 bytes\n\
+             \x20 slot  $7 v       fp-96, 12 bytes\n\
+             @main: 16 bytes, aligned to 16\n\
+             \x20 saved %rbx       fp-8\n\
+             "
+        );
+    }
+}
@@ -0,0 +1,748 @@
+//! The high-level intermediate representation of a program: the program as
+//! sema understood it, which the phases after sema work on.
+//!
+//! It's the syntax tree without the names: a use of a name is a `Def`, the
+//! definition it refers to, and every expression has its `Type`. The
+//! variables of a function, its parameters first, are in one list, wherever
+//! in the function they're declared, and constant expressions the program
+//! needs to know before running, e.g. the value of a `case`, are evaluated.
+//!
+//! Sema lowers every program it checks, even one with errors, so that tools
+//! can look at the parts that are right. Whatever sema couldn't make sense
+//! of, e.g. an undeclared name, is an `ExprKind::Error` or has a
+//! `Type::Error`.
+
+use crate::{
+    ast::{self, BinOp, BlockId, ExprId, Nodes, UnOp},
+    errors::{Diag, DiagBag},
+    sema::{const_value, Binding, Builtin, Resolutions},
+    source_map::Span,
+    types::Type,
+};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Program {
+    pub(crate) structs: Vec<Struct>,
+    pub(crate) globals: Vec<Global>,
+    pub(crate) funs: Vec<Fun>,
+}
+
+/// What a name refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Def {
+    /// The variable at this index of `Program::globals`.
+    Global(usize),
+    /// The function at this index of `Program::funs`.
+    Fun(usize),
+    /// The parameter or local variable at this index of the `locals` of the
+    /// function it's used in.
+    Local(usize),
+    Builtin(Builtin),
+}
+
+/// `struct name { ... };`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Struct {
+    pub(crate) name: String,
+    pub(crate) fields: Vec<Var>,
+    /// The name in its definition.
+    pub(crate) span: Span,
+}
+
+/// A variable at file scope.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Global {
+    pub(crate) name: String,
+    pub(crate) ty: Type,
+    /// The value of its initializer, from `Extension::GlobalInit`. One that
+    /// isn't a constant, which sema reports, counts as 0.
+    pub(crate) init: Option<i64>,
+    /// The name in its declaration.
+    pub(crate) span: Span,
+}
+
+/// A parameter, a local variable or a field of a struct.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Var {
+    pub(crate) name: String,
+    pub(crate) ty: Type,
+    /// The name in its declaration.
+    pub(crate) span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Fun {
+    pub(crate) name: String,
+    pub(crate) return_ty: Type,
+    /// How many of the first `locals` are parameters.
+    pub(crate) params: usize,
+    /// The parameters, and then the variables declared in the body, in the
+    /// order they're declared.
+    pub(crate) locals: Vec<Var>,
+    pub(crate) body: Vec<Stmt>,
+    /// The name in its declaration.
+    pub(crate) span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Stmt {
+    pub(crate) kind: StmtKind,
+    pub(crate) span: Span,
+}
+
+/// As in the syntax tree, but for blocks, whose variables are the
+/// function's.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum StmtKind {
+    Expr(Expr),
+    Empty,
+    Block(Vec<Stmt>),
+    If {
+        cond: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    While {
+        cond: Expr,
+        body: Box<Stmt>,
+    },
+    DoWhile {
+        body: Box<Stmt>,
+        cond: Expr,
+    },
+    For {
+        init: Option<Expr>,
+        cond: Option<Expr>,
+        step: Option<Expr>,
+        body: Box<Stmt>,
+    },
+    Switch {
+        cond: Expr,
+        cases: Vec<SwitchCase>,
+    },
+    Break,
+    Continue,
+    Return(Option<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SwitchCase {
+    /// The value of a `case`, or `None` for the `default`. One that isn't a
+    /// constant, which sema reports, counts as 0.
+    pub(crate) value: Option<i64>,
+    pub(crate) label_span: Span,
+    pub(crate) stmts: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Expr {
+    pub(crate) kind: ExprKind,
+    pub(crate) ty: Type,
+    pub(crate) span: Span,
+}
+
+/// As in the syntax tree, but for names, which are what they refer to.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ExprKind {
+    Number(u64),
+    Char(u8),
+    Str(String),
+    Var(Def),
+    Index {
+        array: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// The field at index `field` of the struct `base` is.
+    Member {
+        base: Box<Expr>,
+        field: usize,
+    },
+    Call {
+        callee: Def,
+        args: Vec<Expr>,
+    },
+    Assign {
+        target: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Unary {
+        op: UnOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Conditional {
+        cond: Box<Expr>,
+        then_expr: Box<Expr>,
+        else_expr: Box<Expr>,
+    },
+    /// An expression sema couldn't make sense of, e.g. an undeclared name, or
+    /// a field a struct doesn't have, which has a `Type::Error`.
+    Error,
+}
+
+/// Lowers `program`, whose names refer to what `resolutions` says, and
+/// reports in `diag_bag` the expressions its types don't allow, e.g. an index
+/// into an `int`.
+pub(crate) fn lower_program(
+    program: &ast::Program,
+    resolutions: &Resolutions,
+    diag_bag: &mut DiagBag,
+) -> Program {
+    let mut lowerer = Lowerer {
+        nodes: &program.nodes,
+        resolutions,
+        diag_bag,
+        ids: Vec::with_capacity(program.decls.len()),
+        return_types: Vec::new(),
+        program: Program::default(),
+        locals: Vec::new(),
+        local_ids: HashMap::new(),
+    };
+    let (mut globals, mut funs, mut structs) = (0, 0, 0);
+    for decl in &program.decls {
+        let counter = match decl {
+            ast::Decl::Var(_) => &mut globals,
+            ast::Decl::Fun(fun_decl) => {
+                lowerer.return_types.push(Type::of_return(fun_decl));
+                &mut funs
+            }
+            ast::Decl::Struct(_) => &mut structs,
+        };
+        lowerer.ids.push(*counter);
+        *counter += 1;
+    }
+
+    for (index, decl) in program.decls.iter().enumerate() {
+        match decl {
+            ast::Decl::Var(var_decl) => {
+                let global = lowerer.lower_global(var_decl);
+                lowerer.program.globals.push(global);
+            }
+            ast::Decl::Fun(fun_decl) => {
+                let fun = lowerer.lower_fun(index, fun_decl);
+                lowerer.program.funs.push(fun);
+            }
+            ast::Decl::Struct(struct_decl) => {
+                let fields = struct_decl
+                    .fields
+                    .iter()
+                    .map(|field| lowerer.var(field))
+                    .collect();
+                lowerer.program.structs.push(Struct {
+                    name: struct_decl.name.name.clone(),
+                    fields,
+                    span: struct_decl.name.span,
+                });
+            }
+        }
+    }
+    lowerer.program
+}
+
+struct Lowerer<'a> {
+    nodes: &'a Nodes,
+    resolutions: &'a Resolutions,
+    diag_bag: &'a mut DiagBag,
+    /// The index of each declaration of the program in the list of its
+    /// kind, e.g. in `Program::funs` for a function.
+    ids: Vec<usize>,
+    /// The return type of each function, by its index in `Program::funs`.
+    return_types: Vec<Type>,
+    /// The program lowered so far.
+    program: Program,
+    /// The variables of the function being lowered, declared so far.
+    locals: Vec<Var>,
+    /// The index in `locals` of each parameter and local variable.
+    local_ids: HashMap<Binding, usize>,
+}
+
+impl Lowerer<'_> {
+    fn var(&self, var_decl: &ast::VarDecl) -> Var {
+        Var {
+            name: var_decl.name.name.clone(),
+            ty: Type::of_var_decl(self.nodes, var_decl),
+            span: var_decl.name.span,
+        }
+    }
+
+    fn lower_global(&self, var_decl: &ast::VarDecl) -> Global {
+        let Var { name, ty, span } = self.var(var_decl);
+        let init = var_decl
+            .init
+            .map(|init| const_value(self.nodes, init).unwrap_or(0));
+        Global {
+            name,
+            ty,
+            init,
+            span,
+        }
+    }
+
+    fn lower_fun(&mut self, index: usize, fun_decl: &ast::FunDecl) -> Fun {
+        self.locals.clear();
+        self.local_ids.clear();
+        for (param_index, param) in fun_decl.params.iter().enumerate() {
+            let binding = Binding::Param {
+                fun: index,
+                index: param_index,
+            };
+            self.local_ids.insert(binding, self.locals.len());
+            self.locals.push(Var {
+                name: param.name.name.clone(),
+                ty: Type::of_param(self.nodes, param),
+                span: param.name.span,
+            });
+        }
+        let body = self.lower_block(fun_decl.body);
+
+        Fun {
+            name: fun_decl.name.name.clone(),
+            return_ty: Type::of_return(fun_decl),
+            params: fun_decl.params.len(),
+            locals: std::mem::take(&mut self.locals),
+            body,
+            span: fun_decl.name.span,
+        }
+    }
+
+    fn lower_block(&mut self, block: BlockId) -> Vec<Stmt> {
+        let nodes = self.nodes;
+        for (index, var_decl) in nodes[block].decls.iter().enumerate() {
+            let binding = Binding::Local { block, index };
+            self.local_ids.insert(binding, self.locals.len());
+            let var = self.var(var_decl);
+            self.locals.push(var);
+        }
+        self.lower_stmts(&nodes[block].stmts)
+    }
+
+    fn lower_stmts(&mut self, stmts: &[ast::StmtId]) -> Vec<Stmt> {
+        stmts.iter().map(|&stmt| self.lower_stmt(stmt)).collect()
+    }
+
+    fn lower_stmt(&mut self, stmt: ast::StmtId) -> Stmt {
+        let nodes = self.nodes;
+        let kind = match nodes[stmt].kind {
+            ast::StmtKind::Expr(expr) => StmtKind::Expr(self.lower_expr(expr)),
+            ast::StmtKind::Empty => StmtKind::Empty,
+            ast::StmtKind::Block(block) => {
+                StmtKind::Block(self.lower_block(block))
+            }
+            ast::StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => StmtKind::If {
+                cond: self.lower_expr(cond),
+                then_branch: self.boxed_stmt(then_branch),
+                else_branch: else_branch.map(|stmt| self.boxed_stmt(stmt)),
+            },
+            ast::StmtKind::While { cond, body } => StmtKind::While {
+                cond: self.lower_expr(cond),
+                body: self.boxed_stmt(body),
+            },
+            ast::StmtKind::DoWhile { body, cond } => StmtKind::DoWhile {
+                body: self.boxed_stmt(body),
+                cond: self.lower_expr(cond),
+            },
+            ast::StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => StmtKind::For {
+                init: init.map(|expr| self.lower_expr(expr)),
+                cond: cond.map(|expr| self.lower_expr(expr)),
+                step: step.map(|expr| self.lower_expr(expr)),
+                body: self.boxed_stmt(body),
+            },
+            ast::StmtKind::Switch { cond, ref cases } => StmtKind::Switch {
+                cond: self.lower_expr(cond),
+                cases: cases
+                    .iter()
+                    .map(|case| SwitchCase {
+                        value: case.value.map(|value| {
+                            const_value(nodes, value).unwrap_or(0)
+                        }),
+                        label_span: case.label_span,
+                        stmts: self.lower_stmts(&case.stmts),
+                    })
+                    .collect(),
+            },
+            ast::StmtKind::Break => StmtKind::Break,
+            ast::StmtKind::Continue => StmtKind::Continue,
+            ast::StmtKind::Return(value) => {
+                StmtKind::Return(value.map(|expr| self.lower_expr(expr)))
+            }
+        };
+        Stmt {
+            kind,
+            span: nodes[stmt].span,
+        }
+    }
+
+    fn boxed_stmt(&mut self, stmt: ast::StmtId) -> Box<Stmt> {
+        Box::new(self.lower_stmt(stmt))
+    }
+
+    fn boxed_expr(&mut self, expr: ExprId) -> Box<Expr> {
+        Box::new(self.lower_expr(expr))
+    }
+
+    /// What the name `expr` uses refers to, if sema found out.
+    fn def(&self, expr: ExprId) -> Option<Def> {
+        let def = match self.resolutions.get(expr)? {
+            Binding::Global(index) => Def::Global(self.ids[index]),
+            Binding::Fun(index) => Def::Fun(self.ids[index]),
+            binding @ (Binding::Param { .. } | Binding::Local { .. }) => {
+                Def::Local(*self.local_ids.get(&binding)?)
+            }
+            Binding::Builtin(builtin) => Def::Builtin(builtin),
+        };
+        Some(def)
+    }
+
+    /// The type of the variable `def` refers to. A function isn't one.
+    fn var_type(&self, def: Def) -> Type {
+        match def {
+            Def::Global(id) => self.program.globals[id].ty.clone(),
+            Def::Local(id) => self.locals[id].ty.clone(),
+            Def::Fun(_) | Def::Builtin(_) => Type::Error,
+        }
+    }
+
+    /// The type of the values a call of `def` returns, if it's a function.
+    fn return_type(&self, def: Def) -> Type {
+        match def {
+            Def::Fun(id) => self.return_types[id].clone(),
+            Def::Builtin(builtin) => builtin.return_type(),
+            Def::Global(_) | Def::Local(_) => Type::Error,
+        }
+    }
+
+    /// The index and the type of the field `name` of a value of type `ty`,
+    /// if it's a struct with one.
+    fn field(&self, ty: &Type, name: &str) -> Option<(usize, Type)> {
+        let Type::Struct(struct_name) = ty.unqualified() else {
+            return None;
+        };
+        let fields = &self
+            .program
+            .structs
+            .iter()
+            .find(|s| s.name == *struct_name)?
+            .fields;
+        let index = fields.iter().position(|field| field.name == name)?;
+        Some((index, fields[index].ty.clone()))
+    }
+
+    fn lower_expr(&mut self, expr: ExprId) -> Expr {
+        let nodes = self.nodes;
+        let (kind, ty) = match nodes[expr].kind {
+            ast::ExprKind::Number(value) => {
+                (ExprKind::Number(value), Type::Int)
+            }
+            ast::ExprKind::Char(value) => (ExprKind::Char(value), Type::Char),
+            ast::ExprKind::Str(ref text) => {
+                // With the `\0` at the end, as in C.
+                let ty = Type::Array {
+                    elem: Box::new(Type::Char),
+                    len: text.len() as u64 + 1,
+                };
+                (ExprKind::Str(text.clone()), ty)
+            }
+            ast::ExprKind::Var(_) => match self.def(expr) {
+                Some(def) => (ExprKind::Var(def), self.var_type(def)),
+                None => (ExprKind::Error, Type::Error),
+            },
+            ast::ExprKind::Index { array, index } => {
+                let (array, index) =
+                    (self.boxed_expr(array), self.boxed_expr(index));
+                let ty = match array.ty.unqualified() {
+                    Type::Array { elem, .. } | Type::Pointer(elem) => {
+                        (**elem).clone()
+                    }
+                    Type::Error => Type::Error,
+                    ty => {
+                        self.diag_bag.push(Diag::NotAnArray {
+                            ty: ty.to_string(),
+                            span: array.span,
+                        });
+                        Type::Error
+                    }
+                };
+                (ExprKind::Index { array, index }, ty)
+            }
+            ast::ExprKind::Member { base, ref field } => {
+                let base = self.boxed_expr(base);
+                match self.field(&base.ty, &field.name) {
+                    Some((field, ty)) => (ExprKind::Member { base, field }, ty),
+                    None => {
+                        if base.ty != Type::Error {
+                            self.diag_bag.push(Diag::UnknownField {
+                                field: field.name.clone(),
+                                ty: base.ty.to_string(),
+                                span: field.span,
+                            });
+                        }
+                        (ExprKind::Error, Type::Error)
+                    }
+                }
+            }
+            ast::ExprKind::Call { ref args, .. } => {
+                let args =
+                    args.iter().map(|&arg| self.lower_expr(arg)).collect();
+                match self.def(expr) {
+                    Some(callee) => (
+                        ExprKind::Call { callee, args },
+                        self.return_type(callee),
+                    ),
+                    None => (ExprKind::Error, Type::Error),
+                }
+            }
+            ast::ExprKind::Assign { target, value } => {
+                let (target, value) =
+                    (self.boxed_expr(target), self.boxed_expr(value));
+                let ty = target.ty.clone();
+                (ExprKind::Assign { target, value }, ty)
+            }
+            ast::ExprKind::Unary { op, operand } => {
+                let operand = self.boxed_expr(operand);
+                let ty = match (op, operand.ty.decay()) {
+                    (_, Type::Error) => Type::Error,
+                    (UnOp::Neg, _) => Type::Int,
+                    (UnOp::Deref, Type::Pointer(pointee)) => *pointee,
+                    (UnOp::Deref, ty) => {
+                        self.diag_bag.push(Diag::NotAPointer {
+                            ty: ty.to_string(),
+                            span: operand.span,
+                        });
+                        Type::Error
+                    }
+                    (UnOp::AddrOf, _) => operand.ty.clone().pointer_to(),
+                };
+                (ExprKind::Unary { op, operand }, ty)
+            }
+            ast::ExprKind::Binary { op, lhs, rhs } => {
+                let (lhs, rhs) = (self.boxed_expr(lhs), self.boxed_expr(rhs));
+                let ty = binary_type(op, &lhs.ty.decay(), &rhs.ty.decay());
+                (ExprKind::Binary { op, lhs, rhs }, ty)
+            }
+            ast::ExprKind::Conditional {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                let (cond, then_expr, else_expr) = (
+                    self.boxed_expr(cond),
+                    self.boxed_expr(then_expr),
+                    self.boxed_expr(else_expr),
+                );
+                let ty = then_expr.ty.decay();
+                let kind = ExprKind::Conditional {
+                    cond,
+                    then_expr,
+                    else_expr,
+                };
+                (kind, ty)
+            }
+        };
+        Expr {
+            kind,
+            ty,
+            span: nodes[expr].span,
+        }
+    }
+}
+
+/// The type of `lhs op rhs`, given the types of its operands: a pointer
+/// plus or minus an `int` is a pointer, as in C, and anything else an `int`.
+fn binary_type(op: BinOp, lhs: &Type, rhs: &Type) -> Type {
+    match op {
+        _ if *lhs == Type::Error || *rhs == Type::Error => Type::Error,
+        BinOp::Add | BinOp::Sub if lhs.is_pointer() && !rhs.is_pointer() => {
+            lhs.clone()
+        }
+        BinOp::Add if rhs.is_pointer() && !lhs.is_pointer() => rhs.clone(),
+        _ => Type::Int,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Def, Expr, ExprKind, Program, StmtKind};
+    use crate::{
+        extensions::Extensions,
+        parser::ParseResult,
+        sema::{check_program, Builtin},
+        source_map::SourceFile,
+        test_support::parse_source,
+    };
+
+    fn lower(source: &str) -> (SourceFile, Program) {
+        let (source_file, ParseResult { program, .. }) =
+            parse_source(source, Extensions::all());
+        (source_file, check_program(&program, &Builtin::ALL).hir)
+    }
+
+    /// Each expression of `expr`, outermost first, with its type.
+    fn types(source_file: &SourceFile, expr: &Expr, out: &mut Vec<String>) {
+        out.push(format!(
+            "{}: {}",
+            source_file.span_to_snippet(expr.span),
+            expr.ty
+        ));
+        match &expr.kind {
+            ExprKind::Index { array: a, index: b }
+            | ExprKind::Assign {
+                target: a,
+                value: b,
+            }
+            | ExprKind::Binary { lhs: a, rhs: b, .. } => {
+                types(source_file, a, out);
+                types(source_file, b, out);
+            }
+            ExprKind::Member { base: a, .. }
+            | ExprKind::Unary { operand: a, .. } => types(source_file, a, out),
+            ExprKind::Call { args, .. } => {
+                for arg in args {
+                    types(source_file, arg, out);
+                }
+            }
+            ExprKind::Conditional {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                for expr in [cond, then_expr, else_expr] {
+                    types(source_file, expr, out);
+                }
+            }
+            ExprKind::Number(_)
+            | ExprKind::Char(_)
+            | ExprKind::Str(_)
+            | ExprKind::Var(_)
+            | ExprKind::Error => {}
+        }
+    }
+
+    #[test]
+    fn names_are_replaced_by_what_they_refer_to() {
+        let source = "int g;\n\
+                      int f(int a) { int b; { int c; c = a + b + g; } \
+                      return f(input()); }\n";
+        let (_source_file, program) = lower(source);
+
+        assert_eq!(program.globals[0].name, "g");
+        let f = &program.funs[0];
+        let locals: Vec<_> =
+            f.locals.iter().map(|local| local.name.as_str()).collect();
+        assert_eq!((f.params, locals), (1, vec!["a", "b", "c"]));
+
+        let StmtKind::Block(inner) = &f.body[0].kind else {
+            panic!("expected a block, found {:?}", f.body[0]);
+        };
+        let StmtKind::Expr(assign) = &inner[0].kind else {
+            panic!("expected an expression, found {:?}", inner[0]);
+        };
+        let ExprKind::Assign { target, value } = &assign.kind else {
+            unreachable!()
+        };
+        assert_eq!(target.kind, ExprKind::Var(Def::Local(2)));
+        let ExprKind::Binary { lhs, rhs, .. } = &value.kind else {
+            unreachable!()
+        };
+        assert_eq!(rhs.kind, ExprKind::Var(Def::Global(0)));
+        let ExprKind::Binary { lhs: a, rhs: b, .. } = &lhs.kind else {
+            unreachable!()
+        };
+        assert_eq!(a.kind, ExprKind::Var(Def::Local(0)));
+        assert_eq!(b.kind, ExprKind::Var(Def::Local(1)));
+
+        let StmtKind::Return(Some(call)) = &f.body[1].kind else {
+            panic!("expected a return, found {:?}", f.body[1]);
+        };
+        let ExprKind::Call { callee, args } = &call.kind else {
+            unreachable!()
+        };
+        assert_eq!(*callee, Def::Fun(0));
+        assert!(matches!(
+            args[0].kind,
+            ExprKind::Call {
+                callee: Def::Builtin(Builtin::Input),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn expressions_have_types() {
+        let source = "struct p { int x; char s[4]; };\n\
+                      void f(int a[], int *q) {\n\
+                      \x20   struct p v; char c;\n\
+                      \x20   a[1] = *q + v.s[0];\n\
+                      \x20   q = &v.x;\n\
+                      \x20   q = a + 1;\n\
+                      \x20   c = v.s[q - a] > 'a' ? 'b' : c;\n\
+                      \x20   q = v.y + undeclared;\n\
+                      }\n";
+        let (source_file, program) = lower(source);
+
+        let mut out = Vec::new();
+        for stmt in &program.funs[0].body {
+            if let StmtKind::Expr(expr) = &stmt.kind {
+                types(&source_file, expr, &mut out);
+            }
+        }
+
+        assert_eq!(
+            out,
+            [
+                "a[1] = *q + v.s[0]: int",
+                "a[1]: int",
+                "a: int *",
+                "1: int",
+                "*q + v.s[0]: int",
+                "*q: int",
+                "q: int *",
+                "v.s[0]: char",
+                "v.s: char [4]",
+                "v: struct p",
+                "0: int",
+                "q = &v.x: int *",
+                "q: int *",
+                "&v.x: int *",
+                "v.x: int",
+                "v: struct p",
+                "q = a + 1: int *",
+                "q: int *",
+                "a + 1: int *",
+                "a: int *",
+                "1: int",
+                "c = v.s[q - a] > 'a' ? 'b' : c: char",
+                "c: char",
+                "v.s[q - a] > 'a' ? 'b' : c: char",
+                "v.s[q - a] > 'a': int",
+                "v.s[q - a]: char",
+                "v.s: char [4]",
+                "v: struct p",
+                "q - a: int",
+                "q: int *",
+                "a: int *",
+                "'a': char",
+                "'b': char",
+                "c: char",
+                "q = v.y + undeclared: int *",
+                "q: int *",
+                "v.y + undeclared: {error}",
+                "v.y: {error}",
+                "undeclared: {error}",
+            ]
+        );
+    }
+}
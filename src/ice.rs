@@ -0,0 +1,274 @@
+//! Reporting internal compiler errors (ICEs): panics caught at the driver are
+//! turned into diagnostics telling which phase was running and what part of
+//! the source it was working on, instead of a bare Rust backtrace.
+
+use crate::{
+    catalog::message,
+    errors::{Diag, Diagnostic, Phase},
+    source_map::Span,
+};
+use std::{
+    cell::{Cell, RefCell},
+    panic::{self, AssertUnwindSafe},
+    sync::Once,
+    thread,
+};
+
+const BUG_REPORT_URL: &str = "https://github.com/feroldi/csub/issues";
+
+thread_local! {
+    /// The phase running on this thread, if any.
+    static CURRENT_PHASE: Cell<Option<Phase>> = const { Cell::new(None) };
+    /// The span the current phase is working on, if known.
+    static CURRENT_SPAN: Cell<Option<Span>> = const { Cell::new(None) };
+    /// Whether panics on this thread are being caught by `catch_ice`.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// Message and location of the last panic caught on this thread.
+    static CAUGHT_PANIC: RefCell<Option<(String, String)>> =
+        const { RefCell::new(None) };
+}
+
+/// A panic caught while compiling.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Ice {
+    pub(crate) message: String,
+    /// Where in the compiler's code the panic happened, e.g. `src/x.rs:3:5`.
+    pub(crate) location: String,
+    pub(crate) phase: Option<Phase>,
+    pub(crate) span: Option<Span>,
+}
+
+impl Ice {
+    pub(crate) fn diagnostic(&self) -> Diagnostic {
+        let mut builder = Diagnostic::builder(Diag::InternalCompilerError {
+            message: self.message.clone(),
+            span: self.span,
+            phase: self.phase,
+        });
+        builder = match self.phase {
+            Some(phase) => builder.with_note(
+                None,
+                message(
+                    "ice.panicked-in-phase",
+                    &[("location", &self.location), ("phase", &phase.name())],
+                ),
+            ),
+            None => builder.with_note(
+                None,
+                message("ice.panicked", &[("location", &self.location)]),
+            ),
+        };
+
+        builder
+            .with_help(None, message("ice.report", &[("url", &BUG_REPORT_URL)]))
+            .build()
+    }
+}
+
+/// Runs `f` as `phase`, so that a panic in it is reported as happening during
+/// that phase.
+pub(crate) fn in_phase<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let outer_phase =
+        CURRENT_PHASE.with(|current| current.replace(Some(phase)));
+    let outer_span = CURRENT_SPAN.with(|span| span.take());
+    let result = f();
+    CURRENT_PHASE.with(|phase| phase.set(outer_phase));
+    CURRENT_SPAN.with(|span| span.set(outer_span));
+    result
+}
+
+/// Records the span the current phase is working on.
+pub(crate) fn set_current_span(span: Span) {
+    CURRENT_SPAN.with(|current| current.set(Some(span)));
+}
+
+/// Installs, once, a panic hook that records the panics `catch_ice` is about
+/// to catch instead of printing them. Other panics (e.g. in tests) are left
+/// to the previous hook.
+fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !CATCHING.with(Cell::get) {
+                previous_hook(info);
+                return;
+            }
+
+            let payload = info.payload();
+            let message = match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => match payload.downcast_ref::<String>() {
+                    Some(message) => message.clone(),
+                    None => "unknown panic payload".to_owned(),
+                },
+            };
+            let location = info
+                .location()
+                .map(|location| location.to_string())
+                .unwrap_or_else(|| "an unknown location".to_owned());
+
+            CAUGHT_PANIC.with(|caught| {
+                *caught.borrow_mut() = Some((message, location));
+            });
+        }));
+    });
+}
+
+/// Runs `f` on a thread of its own with `stack_size` bytes of stack, for
+/// work that recurses deeper than the stack of the calling thread allows. A
+/// panic in it is a panic on the calling thread, as if it ran there.
+pub(crate) fn with_stack<T: Send>(
+    stack_size: usize,
+    f: impl FnOnce() -> T + Send,
+) -> T {
+    let phase = CURRENT_PHASE.with(Cell::get);
+    let result = thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn_scoped(scope, || {
+                catch_ice(|| match phase {
+                    Some(phase) => in_phase(phase, f),
+                    None => f(),
+                })
+            })
+            .expect("couldn't start a thread")
+            .join()
+            .expect("a panic got past catch_ice")
+    });
+
+    result.unwrap_or_else(|ice| {
+        if !CATCHING.with(Cell::get) {
+            panic!("{} at {}", ice.message, ice.location);
+        }
+        // Passed on to the `catch_ice` on this thread as if it had caught
+        // the panic itself, without going through the hook again.
+        CAUGHT_PANIC.with(|caught| {
+            *caught.borrow_mut() = Some((ice.message, ice.location));
+        });
+        CURRENT_PHASE.with(|current| current.set(ice.phase));
+        CURRENT_SPAN.with(|current| current.set(ice.span));
+        panic::resume_unwind(Box::new(()))
+    })
+}
+
+/// Runs `f`, turning a panic in it into an `Ice`.
+pub(crate) fn catch_ice<T>(f: impl FnOnce() -> T) -> Result<T, Ice> {
+    install_panic_hook();
+
+    let was_catching = CATCHING.with(|catching| catching.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(was_catching));
+
+    result.map_err(|_| {
+        let (message, location) = CAUGHT_PANIC
+            .with(|caught| caught.borrow_mut().take())
+            .unwrap_or_else(|| {
+                ("unknown panic".to_owned(), "an unknown location".to_owned())
+            });
+
+        Ice {
+            message,
+            location,
+            phase: CURRENT_PHASE.with(|phase| phase.take()),
+            span: CURRENT_SPAN.with(|span| span.take()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{catch_ice, in_phase, set_current_span, with_stack};
+    use crate::{
+        errors::{Diag, Phase, Severity},
+        source_map::Span,
+    };
+
+    #[test]
+    fn catch_ice_passes_results_through() {
+        assert_eq!(catch_ice(|| 42), Ok(42));
+    }
+
+    #[test]
+    fn catch_ice_reports_phase_and_span() {
+        let ice = catch_ice(|| {
+            in_phase(Phase::Lex, || {
+                set_current_span(Span::with_usizes(3, 4));
+                panic!("oops");
+            })
+        })
+        .unwrap_err();
+
+        assert_eq!(ice.message, "oops");
+        assert!(ice.location.starts_with("src/ice.rs:"));
+        assert_eq!(ice.phase, Some(Phase::Lex));
+        assert_eq!(ice.span, Some(Span::with_usizes(3, 4)));
+    }
+
+    #[test]
+    fn catch_ice_reports_formatted_panic_messages() {
+        let ice =
+            catch_ice(|| -> () { panic!("{} went wrong", 2 + 2) }).unwrap_err();
+
+        assert_eq!(ice.message, "4 went wrong");
+        assert_eq!(ice.phase, None);
+        assert_eq!(ice.span, None);
+    }
+
+    #[test]
+    fn panics_on_a_bigger_stack_are_caught_here() {
+        assert_eq!(catch_ice(|| with_stack(1 << 20, || 42)), Ok(42));
+
+        let ice = catch_ice(|| {
+            in_phase(Phase::Run, || {
+                with_stack(1 << 20, || {
+                    set_current_span(Span::with_usizes(1, 2));
+                    panic!("too deep");
+                })
+            })
+        })
+        .unwrap_err();
+
+        assert_eq!(ice.message, "too deep");
+        assert_eq!(ice.phase, Some(Phase::Run));
+        assert_eq!(ice.span, Some(Span::with_usizes(1, 2)));
+    }
+
+    #[test]
+    fn phase_context_is_restored_after_a_phase() {
+        let ice = catch_ice(|| {
+            in_phase(Phase::Lex, || set_current_span(Span::with_usizes(0, 1)));
+            panic!("between phases");
+        })
+        .unwrap_err();
+
+        assert_eq!(ice.phase, None);
+        assert_eq!(ice.span, None);
+    }
+
+    #[test]
+    fn ice_diagnostic_is_fatal_and_points_at_the_span() {
+        let ice = catch_ice(|| {
+            in_phase(Phase::Lex, || {
+                set_current_span(Span::with_usizes(3, 4));
+                panic!("oops");
+            })
+        })
+        .unwrap_err();
+
+        let diagnostic = ice.diagnostic();
+
+        assert_eq!(diagnostic.severity, Severity::Fatal);
+        assert_eq!(
+            diagnostic.diag,
+            Diag::InternalCompilerError {
+                message: "oops".into(),
+                span: Some(Span::with_usizes(3, 4)),
+                phase: Some(Phase::Lex),
+            }
+        );
+        assert_eq!(diagnostic.children.len(), 2);
+        assert!(diagnostic.children[0].message.ends_with("in the lex phase"));
+    }
+}
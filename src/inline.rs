@@ -0,0 +1,349 @@
+//! Inlining: replacing a call to a small function that calls no other
+//! function of the program, a leaf, with the code of the function, so the
+//! call costs nothing and the other passes see what it does with what it's
+//! given, e.g. the constants it's called with.
+//!
+//! A leaf is small if it has at most as many instructions as the threshold
+//! says, which `--inline-threshold` sets. Its temporaries, slots and labels
+//! are renamed to new ones of the caller, its arguments are copied into the
+//! temporaries they're passed in, and each `ret` copies what it returns to
+//! where the call assigned it and jumps to after the call. The functions
+//! are gone through callees first, so a function that's a leaf once what it
+//! calls is inlined into it is then inlined too.
+//!
+//! What's decided at each call is reported as a remark, with
+//! `--remarks=inline`.
+
+use crate::ir::{Callee, Fun, Inst, Label, Operand, Program, Temp};
+
+/// Inlines the calls to small leaves in the functions of `program`, with
+/// at most `threshold` instructions, returning whether anything changed.
+/// What's decided at each call is written to `remarks`.
+pub(crate) fn inline_program(
+    program: &mut Program,
+    threshold: usize,
+    remarks: &mut Vec<String>,
+) -> bool {
+    let mut changed = false;
+    for caller in callees_first(program) {
+        let mut index = 0;
+        while index < program.funs[caller].code.len() {
+            let Inst::Call {
+                callee: Callee::Fun(callee),
+                ..
+            } = program.funs[caller].code[index]
+            else {
+                index += 1;
+                continue;
+            };
+            let callee = &program.funs[callee];
+            let size = size(callee);
+            let names =
+                format!("@{} into @{}", callee.name, program.funs[caller].name);
+            if let Some(called) = first_call(callee) {
+                remarks.push(format!(
+                    "not inlining {}: it calls @{}",
+                    names, program.funs[called].name
+                ));
+            } else if size > threshold {
+                remarks.push(format!(
+                    "not inlining {}: {} instructions, over the threshold \
+                     of {}",
+                    names, size, threshold
+                ));
+            } else {
+                remarks
+                    .push(format!("inlined {} ({} instructions)", names, size));
+                let callee = callee.clone();
+                index = inline_call(&mut program.funs[caller], index, &callee);
+                changed = true;
+                continue;
+            }
+            index += 1;
+        }
+    }
+    changed
+}
+
+/// The indices of the functions of `program`, each after the ones it calls,
+/// but for those it calls back.
+fn callees_first(program: &Program) -> Vec<usize> {
+    fn visit(
+        program: &Program,
+        fun: usize,
+        visited: &mut [bool],
+        order: &mut Vec<usize>,
+    ) {
+        if visited[fun] {
+            return;
+        }
+        visited[fun] = true;
+        for inst in &program.funs[fun].code {
+            if let Inst::Call {
+                callee: Callee::Fun(callee),
+                ..
+            } = *inst
+            {
+                visit(program, callee, visited, order);
+            }
+        }
+        order.push(fun);
+    }
+
+    let mut visited = vec![false; program.funs.len()];
+    let mut order = Vec::new();
+    for fun in 0..program.funs.len() {
+        visit(program, fun, &mut visited, &mut order);
+    }
+    order
+}
+
+/// The first function of the program `fun` calls, if it isn't a leaf,
+/// calling builtins only, if any.
+fn first_call(fun: &Fun) -> Option<usize> {
+    fun.code.iter().find_map(|inst| match *inst {
+        Inst::Call {
+            callee: Callee::Fun(callee),
+            ..
+        } => Some(callee),
+        _ => None,
+    })
+}
+
+/// How many instructions `fun` has, not counting its labels, nor the lines
+/// they're of.
+fn size(fun: &Fun) -> usize {
+    fun.code
+        .iter()
+        .filter(|inst| !matches!(inst, Inst::Label(_) | Inst::Line(_)))
+        .count()
+}
+
+/// Replaces the call at `index` of the code of `caller` with the code of
+/// `callee`, returning the index of what's after it.
+fn inline_call(caller: &mut Fun, index: usize, callee: &Fun) -> usize {
+    let Inst::Call { dst, ref args, .. } = caller.code[index] else {
+        unreachable!("{:?} isn't a call", caller.code[index]);
+    };
+    let args = args.clone();
+    let temps = caller.temps.len();
+    let slots = caller.slots.len();
+    let labels = caller.labels;
+    caller.temps.extend_from_slice(&callee.temps);
+    caller.slots.extend_from_slice(&callee.slots);
+    caller.labels += callee.labels;
+
+    // A `ret` at the end, if it's the only one, goes on to after the call
+    // without jumping there.
+    let returns = callee
+        .code
+        .iter()
+        .filter(|inst| matches!(inst, Inst::Return(_)))
+        .count();
+    let falls_through =
+        returns == 1 && matches!(callee.code.last(), Some(Inst::Return(_)));
+    let after = Label(caller.labels);
+    if !falls_through {
+        caller.labels += 1;
+    }
+
+    let mut code: Vec<Inst> = args
+        .into_iter()
+        .enumerate()
+        .map(|(param, arg)| Inst::Copy {
+            dst: Temp(temps + param),
+            src: arg,
+        })
+        .collect();
+    for inst in &callee.code {
+        let mut inst = inst.clone();
+        rename(&mut inst, temps, slots, labels);
+        let Inst::Return(value) = inst else {
+            code.push(inst);
+            continue;
+        };
+        if let (Some(dst), Some(value)) = (dst, value) {
+            code.push(Inst::Copy { dst, src: value });
+        }
+        if !falls_through {
+            code.push(Inst::Jump(after));
+        }
+    }
+    if !falls_through {
+        code.push(Inst::Label(after));
+    }
+    // What's after the call is of the line of the call, not of the last
+    // line of the callee.
+    let line = caller.code[..index]
+        .iter()
+        .rev()
+        .find_map(|inst| match inst {
+            Inst::Line(line) => Some(*line),
+            _ => None,
+        });
+    let callee_lines =
+        callee.code.iter().any(|inst| matches!(inst, Inst::Line(_)));
+    if let (Some(line), true) = (line, callee_lines) {
+        code.push(Inst::Line(line));
+    }
+
+    let end = index + code.len();
+    caller.code.splice(index..index + 1, code);
+    end
+}
+
+/// Renames the temporaries, slots and labels of `inst`, of a function being
+/// inlined, to those after the first `temps`, `slots` and `labels` of the
+/// function it's inlined into.
+fn rename(inst: &mut Inst, temps: usize, slots: usize, labels: usize) {
+    for operand in inst.operands_mut() {
+        match operand {
+            Operand::Temp(temp) => temp.0 += temps,
+            Operand::Slot(slot) => *slot += slots,
+            Operand::Const(_) | Operand::Global(_) | Operand::Str(_) => {}
+        }
+    }
+    match inst {
+        Inst::Copy { dst, .. }
+        | Inst::Neg { dst, .. }
+        | Inst::Binary { dst, .. }
+        | Inst::Offset { dst, .. }
+        | Inst::Diff { dst, .. }
+        | Inst::Load { dst, .. } => dst.0 += temps,
+        Inst::Call { dst, .. } => {
+            if let Some(dst) = dst {
+                dst.0 += temps;
+            }
+        }
+        Inst::Label(label) | Inst::Jump(label) => label.0 += labels,
+        Inst::Branch {
+            then_label,
+            else_label,
+            ..
+        } => {
+            then_label.0 += labels;
+            else_label.0 += labels;
+        }
+        Inst::Store { .. }
+        | Inst::MemCopy { .. }
+        | Inst::Return(_)
+        | Inst::Check { .. }
+        | Inst::Line(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ir::dump_ir,
+        passes::{Pass, PassManager, PassOptions},
+        test_support::{fun_code, lower_source},
+    };
+
+    /// The code of `source` after inlining under `threshold`, with the
+    /// remarks.
+    fn inline(source: &str, threshold: usize) -> (String, String) {
+        let mut program = lower_source(source);
+        let mut remarks = String::new();
+        PassManager::new(vec![Pass::Inline])
+            .options(PassOptions {
+                inline_threshold: threshold,
+            })
+            .remarks(vec![Pass::Inline])
+            .run(&mut program, &mut remarks);
+        (dump_ir(&program), remarks)
+    }
+
+    const SOURCE: &str = "int sq(int x) { return x * x; }\n\
+                          int abs(int x) { if (x < 0) return 0 - x; return x; }\n\
+                          int sumsq(int a, int b) { return sq(a) + sq(b); }\n\
+                          int fact(int n) { if (n < 2) return 1; return n * fact(n - 1); }\n\
+                          void main(void) {\n\
+                          \x20   output(sumsq(input(), 3));\n\
+                          \x20   output(abs(input()));\n\
+                          \x20   output(fact(5));\n\
+                          }";
+
+    #[test]
+    fn small_leaves_are_inlined_with_their_names_renamed() {
+        let (code, remarks) = inline(SOURCE, 20);
+
+        assert_eq!(
+            remarks,
+            "remark: inlined @sq into @sumsq (5 instructions)\n\
+             remark: inlined @sq into @sumsq (5 instructions)\n\
+             remark: not inlining @fact into @fact: it calls @fact\n\
+             remark: inlined @sumsq into @main (18 instructions)\n\
+             remark: inlined @abs into @main (9 instructions)\n\
+             remark: not inlining @fact into @main: it calls @fact\n"
+        );
+        assert_eq!(
+            fun_code(&code, "main"),
+            "fun @main() {\n\
+             \x20 slot $0 a: 4, align 4\n\
+             \x20 slot $1 b: 4, align 4\n\
+             \x20 slot $2 x: 4, align 4\n\
+             \x20 slot $3 x: 4, align 4\n\
+             \x20 slot $4 x: 4, align 4\n\
+             \x20 %0: i32 = call @input()\n\
+             \x20 %5: i32 = copy %0\n\
+             \x20 %6: i32 = copy 3\n\
+             \x20 store i32 $0, %5\n\
+             \x20 store i32 $1, %6\n\
+             \x20 %7: i32 = load i32 $0\n\
+             \x20 %12: i32 = copy %7\n\
+             \x20 store i32 $2, %12\n\
+             \x20 %13: i32 = load i32 $2\n\
+             \x20 %14: i32 = load i32 $2\n\
+             \x20 %15: i32 = mul %13, %14\n\
+             \x20 %8: i32 = copy %15\n\
+             \x20 %9: i32 = load i32 $1\n\
+             \x20 %16: i32 = copy %9\n\
+             \x20 store i32 $3, %16\n\
+             \x20 %17: i32 = load i32 $3\n\
+             \x20 %18: i32 = load i32 $3\n\
+             \x20 %19: i32 = mul %17, %18\n\
+             \x20 %10: i32 = copy %19\n\
+             \x20 %11: i32 = add %8, %10\n\
+             \x20 %1: i32 = copy %11\n\
+             \x20 call @output(%1)\n\
+             \x20 %2: i32 = call @input()\n\
+             \x20 %20: i32 = copy %2\n\
+             \x20 store i32 $4, %20\n\
+             \x20 %21: i32 = load i32 $4\n\
+             \x20 %22: i32 = lt %21, 0\n\
+             \x20 branch %22, L0, L1\n\
+             L0:\n\
+             \x20 %23: i32 = load i32 $4\n\
+             \x20 %24: i32 = sub 0, %23\n\
+             \x20 %3: i32 = copy %24\n\
+             \x20 jump L2\n\
+             L1:\n\
+             \x20 %25: i32 = load i32 $4\n\
+             \x20 %3: i32 = copy %25\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 call @output(%3)\n\
+             \x20 %4: i32 = call @fact(5)\n\
+             \x20 call @output(%4)\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn calls_to_large_functions_are_kept() {
+        let (code, remarks) = inline(SOURCE, 8);
+
+        assert_eq!(
+            remarks,
+            "remark: inlined @sq into @sumsq (5 instructions)\n\
+             remark: inlined @sq into @sumsq (5 instructions)\n\
+             remark: not inlining @fact into @fact: it calls @fact\n\
+             remark: not inlining @sumsq into @main: 18 instructions, over the threshold of 8\n\
+             remark: not inlining @abs into @main: 9 instructions, over the threshold of 8\n\
+             remark: not inlining @fact into @main: it calls @fact\n"
+        );
+        assert!(fun_code(&code, "main").contains("call @abs("));
+    }
+}
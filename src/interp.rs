@@ -0,0 +1,806 @@
+//! Running a program without compiling it to anything, with `csub run`, by
+//! walking its HIR: `input()` reads an integer from the standard input and
+//! `output(x)` writes `x` to the standard output, a line each.
+//!
+//! Memory is an array of bytes laid out as in C on a 64-bit target (see
+//! `Layout`): the globals and the strings first, and then the frame of each
+//! function being called, with its variables, above the one of its caller.
+//! Variables start out as 0. The first few bytes aren't anything's, so that
+//! a null pointer points nowhere.
+//!
+//! What would be undefined in C stops the program with a `Trap` instead,
+//! e.g. a division by zero, an index out of the bounds of its array, even
+//! through a pointer or a parameter, or a pointer to memory no variable is
+//! in, and an `int` that overflows wraps around.
+
+use crate::{
+    ast::{BinOp, UnOp},
+    errors::Diag,
+    hir::{self, Def, ExprKind, StmtKind},
+    ice::{set_current_span, with_stack},
+    runtime::call_native,
+    sema::{Builtin, Layout, StructLayout},
+    source_map::Span,
+    types::Type,
+};
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+/// What stops a program before the end of `main`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Trap {
+    DivisionByZero,
+    /// A load or a store through a pointer to memory no variable is in,
+    /// e.g. a null one.
+    InvalidAddress,
+    /// More than `MAX_CALL_DEPTH` calls nested in each other.
+    StackOverflow,
+    /// More memory for variables than `MAX_MEMORY`, e.g. for a large
+    /// array.
+    OutOfMemory,
+    /// `input()` read something that isn't an integer, or nothing at all.
+    InvalidInput,
+    /// An index out of the range of the elements of its array, or, through
+    /// a pointer, out of the variable it points into.
+    IndexOutOfBounds,
+}
+
+/// How many calls can be nested in each other, e.g. by a recursive
+/// function.
+pub(crate) const MAX_CALL_DEPTH: usize = 10_000;
+
+/// How many bytes the variables of a program can take, in all.
+pub(crate) const MAX_MEMORY: usize = 64 << 20;
+
+/// How many bytes of stack the interpreter runs with, enough for
+/// `MAX_CALL_DEPTH` calls.
+const STACK_SIZE: usize = 512 << 20;
+
+/// How many bytes at the start of memory aren't anything's.
+pub(crate) const NULL_GUARD: usize = 8;
+
+/// A value: an `int`, a `char`, or the address of a pointer, an array or a
+/// struct.
+type Value = i64;
+
+/// Runs the function `main` of `program`, in which sema found no errors,
+/// with `input` as its standard input and `output` as its standard output.
+pub(crate) fn run(
+    program: &hir::Program,
+    input: &mut (dyn BufRead + Send),
+    output: &mut (dyn Write + Send),
+) -> Result<(), Diag> {
+    with_stack(STACK_SIZE, || {
+        let mut interp = Interp::new(program, input, output)?;
+        let main = program
+            .funs
+            .iter()
+            .position(|fun| fun.name == "main")
+            .expect("running a program without a main function");
+        let result = interp.call(main, Vec::new(), program.funs[main].span);
+        // There's nowhere left to report a failure to write the output.
+        let _ = interp.output.flush();
+        result.map(|_| ())
+    })
+}
+
+/// How a statement ends.
+enum Flow {
+    /// Going on to the next one.
+    Next,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+struct Interp<'a> {
+    program: &'a hir::Program,
+    structs: HashMap<String, StructLayout>,
+    memory: Vec<u8>,
+    /// Where each variable, string and struct returned by a call is in
+    /// `memory`, from its start to its end, in the order they were taken.
+    allocs: Vec<(usize, usize)>,
+    /// The address of each global, by its index in `Program::globals`.
+    globals: Vec<usize>,
+    /// The address of each string literal, by its span.
+    strings: HashMap<Span, usize>,
+    /// The address of each variable of the function being called, and
+    /// those of the functions it was called from before it.
+    frames: Vec<Vec<usize>>,
+    input: &'a mut (dyn BufRead + Send),
+    output: &'a mut (dyn Write + Send),
+}
+
+impl<'a> Interp<'a> {
+    fn new(
+        program: &'a hir::Program,
+        input: &'a mut (dyn BufRead + Send),
+        output: &'a mut (dyn Write + Send),
+    ) -> Result<Interp<'a>, Diag> {
+        let mut structs = HashMap::new();
+        for s in &program.structs {
+            let fields =
+                s.fields.iter().map(|f| (f.name.clone(), f.ty.clone()));
+            if let Some(layout) = StructLayout::of_fields(fields, &structs) {
+                structs.insert(s.name.clone(), layout);
+            }
+        }
+
+        let mut interp = Interp {
+            program,
+            structs,
+            memory: vec![0; NULL_GUARD],
+            allocs: Vec::new(),
+            globals: Vec::with_capacity(program.globals.len()),
+            strings: HashMap::new(),
+            frames: Vec::new(),
+            input,
+            output,
+        };
+        for global in &program.globals {
+            let layout = interp.layout(&global.ty);
+            let addr = interp.alloc(layout).ok_or(Diag::RuntimeError {
+                trap: Trap::OutOfMemory,
+                span: global.span,
+            })?;
+            if let Some(init) = global.init {
+                interp.write(&global.ty, addr, init);
+            }
+            interp.globals.push(addr);
+        }
+        Ok(interp)
+    }
+
+    fn layout(&self, ty: &Type) -> Layout {
+        Layout::of(ty, &self.structs)
+            .unwrap_or_else(|| panic!("ran a value of type {}", ty))
+    }
+
+    /// Takes memory for a value with `layout`, after everything else, or
+    /// `None` if there's no memory left.
+    fn alloc(&mut self, layout: Layout) -> Option<usize> {
+        let addr = self.memory.len().next_multiple_of(layout.align as usize);
+        let end = addr.checked_add(layout.size as usize)?;
+        if end > MAX_MEMORY {
+            return None;
+        }
+        self.memory.resize(end, 0);
+        self.allocs.push((addr, end));
+        Some(addr)
+    }
+
+    /// Gives back the memory from `addr` on.
+    fn free(&mut self, addr: usize) {
+        self.memory.truncate(addr);
+        let kept = self.allocs.partition_point(|&(start, _)| start < addr);
+        self.allocs.truncate(kept);
+    }
+
+    /// Whether the `size` bytes at `addr` are in what `base` points into,
+    /// if it points into anything: a pointer may point to the end of it.
+    fn in_alloc_of(&self, base: Value, addr: Value, size: Value) -> bool {
+        let before = self
+            .allocs
+            .partition_point(|&(start, _)| start as Value <= base);
+        let mut allocs = self.allocs[..before]
+            .iter()
+            .rev()
+            .take(2)
+            .map(|&(start, end)| (start as Value, end as Value))
+            .filter(|&(_, end)| base <= end)
+            .peekable();
+        allocs.peek().is_none()
+            || allocs.any(|(start, end)| {
+                start <= addr && addr.saturating_add(size) <= end
+            })
+    }
+
+    /// The `size` bytes at `addr`, if they're some variable's.
+    fn bytes(&mut self, addr: Value, size: usize) -> Option<&mut [u8]> {
+        let addr = usize::try_from(addr).ok().filter(|&a| a >= NULL_GUARD)?;
+        self.memory.get_mut(addr..addr.checked_add(size)?)
+    }
+
+    /// Reads the value of type `ty` at `addr`: the address itself for a
+    /// struct or an array.
+    fn load(
+        &mut self,
+        ty: &Type,
+        addr: Value,
+        span: Span,
+    ) -> Result<Value, Diag> {
+        let size = match ty.unqualified() {
+            Type::Struct(_) | Type::Array { .. } => return Ok(addr),
+            Type::Char => 1,
+            Type::Int => 4,
+            _ => 8,
+        };
+        let bytes = self.bytes(addr, size).ok_or(Diag::RuntimeError {
+            trap: Trap::InvalidAddress,
+            span,
+        })?;
+        let value = match bytes {
+            &mut [byte] => Value::from(byte),
+            &mut [a, b, c, d] => Value::from(i32::from_le_bytes([a, b, c, d])),
+            bytes => {
+                let mut word = [0; 8];
+                word.copy_from_slice(bytes);
+                i64::from_le_bytes(word)
+            }
+        };
+        Ok(value)
+    }
+
+    /// Writes `value`, of type `ty`, to `addr`: copies the struct at
+    /// `value` for a struct.
+    fn store(
+        &mut self,
+        ty: &Type,
+        addr: Value,
+        value: Value,
+        span: Span,
+    ) -> Result<(), Diag> {
+        let trap = Diag::RuntimeError {
+            trap: Trap::InvalidAddress,
+            span,
+        };
+        let size = self.layout(ty).size as usize;
+        if self.bytes(addr, size).is_none() {
+            return Err(trap);
+        }
+        if let Type::Struct(_) = ty.unqualified() {
+            let src = self.bytes(value, size).ok_or(trap)?.to_vec();
+            let addr = addr as usize;
+            self.memory[addr..addr + size].copy_from_slice(&src);
+        } else {
+            self.write(ty, addr as usize, value);
+        }
+        Ok(())
+    }
+
+    /// Writes `value`, of type `ty`, which isn't a struct, to the memory at
+    /// `addr`, which is there.
+    fn write(&mut self, ty: &Type, addr: usize, value: Value) {
+        match ty.unqualified() {
+            Type::Char => self.memory[addr] = value as u8,
+            Type::Int => self.memory[addr..addr + 4]
+                .copy_from_slice(&(value as i32).to_le_bytes()),
+            _ => self.memory[addr..addr + 8]
+                .copy_from_slice(&value.to_le_bytes()),
+        }
+    }
+
+    /// Calls the function at index `fun` of `Program::funs` with `args`,
+    /// from the call at `span`.
+    fn call(
+        &mut self,
+        fun: usize,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, Diag> {
+        if self.frames.len() == MAX_CALL_DEPTH {
+            return Err(Diag::RuntimeError {
+                trap: Trap::StackOverflow,
+                span,
+            });
+        }
+        let out_of_memory = Diag::RuntimeError {
+            trap: Trap::OutOfMemory,
+            span,
+        };
+        let fun = &self.program.funs[fun];
+        let base = self.memory.len();
+        let mut frame = Vec::with_capacity(fun.locals.len());
+        for local in &fun.locals {
+            let layout = self.layout(&local.ty);
+            let addr = self.alloc(layout).ok_or(out_of_memory.clone())?;
+            frame.push(addr);
+        }
+        for ((param, &addr), arg) in fun.locals.iter().zip(&frame).zip(args) {
+            self.store(&param.ty, addr as Value, arg, span)?;
+        }
+
+        self.frames.push(frame);
+        let flow = self.stmts(&fun.body);
+        self.frames.pop();
+        let value = match flow? {
+            Flow::Return(value) => value,
+            // A function that returns a value but gets to its end without
+            // one returns 0.
+            _ => 0,
+        };
+
+        // A struct is copied out of the frame before it goes away, to just
+        // after the frame of the caller.
+        let value = if let Type::Struct(_) = fun.return_ty.unqualified() {
+            let layout = self.layout(&fun.return_ty);
+            let start = value as usize;
+            let bytes =
+                self.memory[start..start + layout.size as usize].to_vec();
+            self.free(base);
+            let addr = self.alloc(layout).ok_or(out_of_memory)?;
+            self.memory[addr..addr + bytes.len()].copy_from_slice(&bytes);
+            addr as Value
+        } else {
+            self.free(base);
+            value
+        };
+        Ok(value)
+    }
+
+    fn builtin(
+        &mut self,
+        builtin: Builtin,
+        args: &[Value],
+        span: Span,
+    ) -> Result<Value, Diag> {
+        call_native(builtin, args, self.input, self.output).ok_or(
+            Diag::RuntimeError {
+                trap: Trap::InvalidInput,
+                span,
+            },
+        )
+    }
+
+    fn stmts(&mut self, stmts: &[hir::Stmt]) -> Result<Flow, Diag> {
+        for stmt in stmts {
+            match self.stmt(stmt)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Runs the body of a loop, telling whether the loop goes on, or the
+    /// flow out of it if it doesn't.
+    fn loop_body(&mut self, body: &hir::Stmt) -> Result<Option<Flow>, Diag> {
+        match self.stmt(body)? {
+            Flow::Next | Flow::Continue => Ok(None),
+            Flow::Break => Ok(Some(Flow::Next)),
+            flow @ Flow::Return(_) => Ok(Some(flow)),
+        }
+    }
+
+    fn is_true(&mut self, cond: &hir::Expr) -> Result<bool, Diag> {
+        Ok(self.value(cond)? != 0)
+    }
+
+    fn stmt(&mut self, stmt: &hir::Stmt) -> Result<Flow, Diag> {
+        set_current_span(stmt.span);
+        match &stmt.kind {
+            StmtKind::Expr(expr) => {
+                self.value(expr)?;
+            }
+            StmtKind::Empty => {}
+            StmtKind::Block(stmts) => return self.stmts(stmts),
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                if self.is_true(cond)? {
+                    return self.stmt(then_branch);
+                } else if let Some(else_branch) = else_branch {
+                    return self.stmt(else_branch);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                while self.is_true(cond)? {
+                    if let Some(flow) = self.loop_body(body)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            StmtKind::DoWhile { body, cond } => loop {
+                if let Some(flow) = self.loop_body(body)? {
+                    return Ok(flow);
+                }
+                if !self.is_true(cond)? {
+                    break;
+                }
+            },
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.value(init)?;
+                }
+                loop {
+                    if let Some(cond) = cond {
+                        if !self.is_true(cond)? {
+                            break;
+                        }
+                    }
+                    if let Some(flow) = self.loop_body(body)? {
+                        return Ok(flow);
+                    }
+                    if let Some(step) = step {
+                        self.value(step)?;
+                    }
+                }
+            }
+            StmtKind::Switch { cond, cases } => {
+                let value = self.value(cond)? as i32;
+                let start = cases
+                    .iter()
+                    .position(|case| case.value == Some(i64::from(value)))
+                    .or_else(|| {
+                        cases.iter().position(|case| case.value.is_none())
+                    });
+                // Each case goes on to the next one, until a `break`.
+                for case in cases.iter().skip(start.unwrap_or(cases.len())) {
+                    match self.stmts(&case.stmts)? {
+                        Flow::Next => {}
+                        Flow::Break => break,
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            StmtKind::Break => return Ok(Flow::Break),
+            StmtKind::Continue => return Ok(Flow::Continue),
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.value(value)?,
+                    None => 0,
+                };
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// The address of the value `expr` is, which is somewhere in memory.
+    fn address(&mut self, expr: &hir::Expr) -> Result<Value, Diag> {
+        let addr = match &expr.kind {
+            ExprKind::Var(Def::Global(index)) => self.globals[*index] as Value,
+            ExprKind::Var(Def::Local(index)) => {
+                let frame = self.frames.last().expect("no function running");
+                frame[*index] as Value
+            }
+            ExprKind::Index { array, index } => {
+                // The value of an array is its address, so this is the same
+                // for pointers and arrays, but for the bounds: an array's
+                // are its own, and a pointer's those of what it points into.
+                let base = self.value(array)?;
+                let index = self.value(index)?;
+                let size = self.layout(&expr.ty).size as Value;
+                let addr = base.wrapping_add(index.wrapping_mul(size));
+                let in_bounds = match array.ty.unqualified() {
+                    Type::Array { len, .. } => {
+                        (0..*len as Value).contains(&index)
+                    }
+                    _ => self.in_alloc_of(base, addr, size),
+                };
+                if !in_bounds {
+                    return Err(Diag::RuntimeError {
+                        trap: Trap::IndexOutOfBounds,
+                        span: expr.span,
+                    });
+                }
+                addr
+            }
+            ExprKind::Member { base, field } => {
+                let Type::Struct(name) = base.ty.unqualified() else {
+                    panic!("a member of a {}", base.ty);
+                };
+                let offset = self.structs[name].fields[*field].1 as Value;
+                self.value(base)?.wrapping_add(offset)
+            }
+            ExprKind::Unary {
+                op: UnOp::Deref,
+                operand,
+            } => self.value(operand)?,
+            // A struct a function returns, or one assigned or chosen by a
+            // conditional, whose value is already its address.
+            _ => self.value(expr)?,
+        };
+        Ok(addr)
+    }
+
+    /// The value of `expr`, after evaluating it.
+    fn value(&mut self, expr: &hir::Expr) -> Result<Value, Diag> {
+        let value = match &expr.kind {
+            ExprKind::Number(value) => *value as i32 as Value,
+            ExprKind::Char(value) => Value::from(*value),
+            ExprKind::Str(text) => {
+                let addr = match self.strings.get(&expr.span) {
+                    Some(&addr) => addr,
+                    None => {
+                        let layout = self.layout(&expr.ty);
+                        let addr =
+                            self.alloc(layout).ok_or(Diag::RuntimeError {
+                                trap: Trap::OutOfMemory,
+                                span: expr.span,
+                            })?;
+                        self.memory[addr..addr + text.len()]
+                            .copy_from_slice(text.as_bytes());
+                        self.strings.insert(expr.span, addr);
+                        addr
+                    }
+                };
+                addr as Value
+            }
+            ExprKind::Var(_)
+            | ExprKind::Index { .. }
+            | ExprKind::Member { .. }
+            | ExprKind::Unary {
+                op: UnOp::Deref, ..
+            } => {
+                let addr = self.address(expr)?;
+                self.load(&expr.ty, addr, expr.span)?
+            }
+            ExprKind::Unary {
+                op: UnOp::AddrOf,
+                operand,
+            } => self.address(operand)?,
+            ExprKind::Unary {
+                op: UnOp::Neg,
+                operand,
+            } => Value::from((self.value(operand)? as i32).wrapping_neg()),
+            ExprKind::Call { callee, args } => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.value(arg)?);
+                }
+                match *callee {
+                    Def::Fun(index) => self.call(index, values, expr.span)?,
+                    Def::Builtin(builtin) => {
+                        self.builtin(builtin, &values, expr.span)?
+                    }
+                    Def::Global(_) | Def::Local(_) => {
+                        panic!("called a variable")
+                    }
+                }
+            }
+            ExprKind::Assign { target, value } => {
+                let addr = self.address(target)?;
+                let value = self.value(value)?;
+                self.store(&target.ty, addr, value, expr.span)?;
+                self.load(&target.ty, addr, expr.span)?
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                let (lhs_ty, rhs_ty) = (lhs.ty.decay(), rhs.ty.decay());
+                let (lhs, rhs) = (self.value(lhs)?, self.value(rhs)?);
+                self.binary(*op, (lhs, &lhs_ty), (rhs, &rhs_ty), expr.span)?
+            }
+            ExprKind::Conditional {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                if self.is_true(cond)? {
+                    self.value(then_expr)?
+                } else {
+                    self.value(else_expr)?
+                }
+            }
+            ExprKind::Error => {
+                panic!("ran an expression sema couldn't make sense of")
+            }
+        };
+        Ok(value)
+    }
+
+    /// `lhs op rhs`, given the values of its operands and their types.
+    fn binary(
+        &self,
+        op: BinOp,
+        (lhs, lhs_ty): (Value, &Type),
+        (rhs, rhs_ty): (Value, &Type),
+        span: Span,
+    ) -> Result<Value, Diag> {
+        let pointee_size = |ty: &Type| {
+            self.layout(ty.pointee().expect("not a pointer")).size as Value
+        };
+        let (a, b) = (lhs as i32, rhs as i32);
+        let value = match op {
+            BinOp::Sub if lhs_ty.is_pointer() && rhs_ty.is_pointer() => {
+                Value::from(
+                    (lhs.wrapping_sub(rhs) / pointee_size(lhs_ty)) as i32,
+                )
+            }
+            BinOp::Add | BinOp::Sub if lhs_ty.is_pointer() => {
+                let bytes = Value::from(b).wrapping_mul(pointee_size(lhs_ty));
+                match op {
+                    BinOp::Add => lhs.wrapping_add(bytes),
+                    _ => lhs.wrapping_sub(bytes),
+                }
+            }
+            BinOp::Add if rhs_ty.is_pointer() => rhs.wrapping_add(
+                Value::from(a).wrapping_mul(pointee_size(rhs_ty)),
+            ),
+            BinOp::Add => Value::from(a.wrapping_add(b)),
+            BinOp::Sub => Value::from(a.wrapping_sub(b)),
+            BinOp::Mul => Value::from(a.wrapping_mul(b)),
+            BinOp::Div if b == 0 => {
+                return Err(Diag::RuntimeError {
+                    trap: Trap::DivisionByZero,
+                    span,
+                })
+            }
+            BinOp::Div => Value::from(a.wrapping_div(b)),
+            // Addresses are compared as they are, and `int`s and `char`s
+            // as they are, too.
+            BinOp::Less => Value::from(lhs < rhs),
+            BinOp::LessEqual => Value::from(lhs <= rhs),
+            BinOp::Greater => Value::from(lhs > rhs),
+            BinOp::GreaterEqual => Value::from(lhs >= rhs),
+            BinOp::Equal => Value::from(lhs == rhs),
+            BinOp::NotEqual => Value::from(lhs != rhs),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, Trap, MAX_CALL_DEPTH};
+    use crate::{errors::Diag, test_support::analyze_source};
+
+    /// Runs `source` with `input`, returning what it wrote, and the snippet
+    /// of what stopped it, if anything did.
+    fn run_source(
+        source: &str,
+        input: &str,
+    ) -> (String, Option<(Trap, String)>) {
+        let (source_file, analysis) = analyze_source(source);
+
+        let mut output = Vec::new();
+        let result = run(&analysis.hir, &mut input.as_bytes(), &mut output);
+        let trap = result.err().map(|diag| match diag {
+            Diag::RuntimeError { trap, span } => {
+                (trap, source_file.span_to_snippet(span).to_string())
+            }
+            diag => panic!("expected a runtime error, found {:?}", diag),
+        });
+        (String::from_utf8(output).unwrap(), trap)
+    }
+
+    #[test]
+    fn programs_read_input_and_write_output() {
+        let source = "int gcd(int u, int v) {\n\
+                      \x20   if (v == 0) return u; else return gcd(v, u - u / v * v);\n\
+                      }\n\
+                      void sort(int a[], int n) {\n\
+                      \x20   int i; int j; int t;\n\
+                      \x20   for (i = 0; i < n; i = i + 1)\n\
+                      \x20       for (j = i + 1; j < n; j = j + 1)\n\
+                      \x20           if (a[j] < a[i]) { t = a[i]; a[i] = a[j]; a[j] = t; }\n\
+                      }\n\
+                      void main(void) {\n\
+                      \x20   int x[4]; int i;\n\
+                      \x20   output(gcd(input(), input()));\n\
+                      \x20   i = 0;\n\
+                      \x20   while (i < 4) { x[i] = input(); i = i + 1; }\n\
+                      \x20   sort(x, 4);\n\
+                      \x20   i = 0;\n\
+                      \x20   do { output(x[i]); i = i + 1; } while (i < 4);\n\
+                      }\n";
+
+        assert_eq!(
+            run_source(source, "36 24\n3 -1\n 2\t0"),
+            ("12\n-1\n0\n2\n3\n".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn pointers_structs_and_strings_point_into_memory() {
+        let source = "struct p { char c; int x; };\n\
+                      int g = 7;\n\
+                      struct p twice(struct p q) { q.x = q.x * 2; return q; }\n\
+                      int len(char *s) { int n; n = 0; while (s[n] != 0) n = n + 1; return n; }\n\
+                      void main(void) {\n\
+                      \x20   struct p a; struct p b; int *r; int i;\n\
+                      \x20   a.x = 21; b = twice(a);\n\
+                      \x20   output(a.x); output(b.x);\n\
+                      \x20   r = &g; *r = *r + 1; output(g);\n\
+                      \x20   output(len(\"hello\"));\n\
+                      \x20   for (i = 0; i < 5; i = i + 1) {\n\
+                      \x20       switch (i) { case 1: continue; case 2: output(20); default: output(i); break; }\n\
+                      \x20   }\n\
+                      \x20   output(2147483647 + i);\n\
+                      }\n";
+
+        assert_eq!(
+            run_source(source, ""),
+            (
+                "21\n42\n8\n5\n0\n20\n2\n3\n4\n-2147483644\n".to_string(),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn rows_of_arrays_are_passed_by_address() {
+        let source = "int sum(int m[][3], int rows) {\n\
+                      \x20   int i; int j; int s;\n\
+                      \x20   s = 0;\n\
+                      \x20   for (i = 0; i < rows; i = i + 1)\n\
+                      \x20       for (j = 0; j < 3; j = j + 1) s = s + m[i][j] * (j + 1);\n\
+                      \x20   return s;\n\
+                      }\n\
+                      void clear(int row[]) { row[0] = 0; }\n\
+                      void main(void) {\n\
+                      \x20   int m[2][3]; int i; int j;\n\
+                      \x20   for (i = 0; i < 2; i = i + 1)\n\
+                      \x20       for (j = 0; j < 3; j = j + 1) m[i][j] = input();\n\
+                      \x20   output(sum(m, 2));\n\
+                      \x20   clear(m[1]);\n\
+                      \x20   output(sum(m, 2));\n\
+                      }\n";
+
+        assert_eq!(
+            run_source(source, "1 2 3 4 5 6"),
+            ("46\n42\n".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn undefined_behavior_stops_the_program() {
+        let trap = |source: &str, input: &str| run_source(source, input).1;
+
+        assert_eq!(
+            trap("void main(void) { output(10 / input()); }", "0"),
+            Some((Trap::DivisionByZero, "10 / input()".to_string()))
+        );
+        assert_eq!(
+            trap("void main(void) { int *p; p = 0; output(*p); }", ""),
+            Some((Trap::InvalidAddress, "*p".to_string()))
+        );
+        assert_eq!(
+            trap("void main(void) { output(input()); }", "x"),
+            Some((Trap::InvalidInput, "input()".to_string()))
+        );
+        assert_eq!(
+            trap("void main(void) { int m[2][3]; m[1][input()] = 1; }", "3"),
+            Some((Trap::IndexOutOfBounds, "m[1][input()]".to_string()))
+        );
+        assert_eq!(
+            trap("void main(void) { int a[4]; output(a[input()]); }", "-1"),
+            Some((Trap::IndexOutOfBounds, "a[input()]".to_string()))
+        );
+        assert_eq!(
+            trap("void main(void) { output(input()); }", ""),
+            Some((Trap::InvalidInput, "input()".to_string()))
+        );
+        assert_eq!(
+            trap(
+                "void main(void) { int a[4]; int *p; p = &a[3]; \
+                 output(p[-3]); output(p[1]); }",
+                ""
+            ),
+            Some((Trap::IndexOutOfBounds, "p[1]".to_string()))
+        );
+        assert_eq!(
+            trap("int big[30000000];\nvoid main(void) { big[0] = 1; }", ""),
+            Some((Trap::OutOfMemory, "big".to_string()))
+        );
+        assert_eq!(
+            trap("void main(void) { int a[20000000]; a[0] = 1; }", ""),
+            Some((Trap::OutOfMemory, "main".to_string()))
+        );
+        let (output, trap) = run_source(
+            "void f(int b[]) { b[input()] = 7; }\n\
+             void main(void) { int i; int a[4]; i = 1; f(a); output(i); }",
+            "4",
+        );
+        assert_eq!(output, "");
+        assert_eq!(
+            trap,
+            Some((Trap::IndexOutOfBounds, "b[input()]".to_string()))
+        );
+        let (output, trap) = run_source(
+            "int f(int n) { output(n); return f(n + 1); }\n\
+             void main(void) { f(1); }",
+            "",
+        );
+        assert_eq!(trap, Some((Trap::StackOverflow, "f(n + 1)".to_string())));
+        assert_eq!(output.lines().count(), MAX_CALL_DEPTH - 1);
+    }
+}
@@ -0,0 +1,1506 @@
+//! The three-address code of a program, which the backends and the passes
+//! that optimize a program work on. Shown with `--emit=ir`:
+//!
+//! ```text
+//! global @n: 4, align 4 = 3
+//!
+//! fun @main() {
+//!   slot $0 i: 4, align 4
+//!   store i32 $0, 0
+//!   jump L0
+//! L0:
+//!   %0: i32 = load i32 $0
+//!   %1: i32 = load i32 @n
+//!   %2: i32 = lt %0, %1
+//!   branch %2, L1, L2
+//! L1:
+//!   %3: i32 = load i32 $0
+//!   call @output(%3)
+//!   ...
+//! ```
+//!
+//! A function is a list of instructions, each doing one thing to at most two
+//! operands: a constant, a temporary, or the address of a variable or a
+//! string. Temporaries hold an `i32` or a `ptr`, and may be assigned more
+//! than once. Every variable is in memory, a global or a slot in the frame
+//! of its function, and is read and written with loads and stores. Code
+//! never falls through to a label: it jumps there.
+//!
+//! A value of a struct or an array is its address. A struct is copied where
+//! it's assigned, into a function that takes it as an argument and out of
+//! one that returns it, which takes where to copy it to as its first
+//! argument. Memory is laid out as in C on a 64-bit target (see `Layout`).
+//!
+//! Lowered with `Checks`, the code checks what the interpreter does as it
+//! runs, e.g. with `--bounds-checks` that each index into an array is in
+//! its bounds, and stops the program where a check fails, reporting the line
+//! of the source it's on.
+//!
+//! Lowered with lines, with `-g`, the code says which line of the source
+//! each statement of it is of, for the backends to write as debug info, so
+//! a debugger can step through the program line by line.
+
+use crate::{
+    ast::{BinOp, UnOp},
+    hir::{self, Def, ExprKind, StmtKind},
+    interp::Trap,
+    sema::{Builtin, Layout, StructLayout},
+    source_map::{SourceFile, Span},
+    types::Type,
+    verify::assert_valid,
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Program {
+    pub(crate) globals: Vec<Global>,
+    /// The string literals of the program, without the `\0` at the end.
+    pub(crate) strings: Vec<String>,
+    pub(crate) funs: Vec<Fun>,
+    /// The name of the source file, if it was lowered with lines.
+    pub(crate) file: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Global {
+    pub(crate) name: String,
+    pub(crate) layout: Layout,
+    /// Its initial value, if it isn't 0.
+    pub(crate) init: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Fun {
+    pub(crate) name: String,
+    /// How many of the first `temps` the arguments are passed in.
+    pub(crate) params: usize,
+    /// The type of the values it returns, if any. A function returning a
+    /// struct doesn't, but copies it where its first argument says.
+    pub(crate) ret: Option<Ty>,
+    /// Its variables, the parameters first, and then the space for the
+    /// structs the functions it calls return.
+    pub(crate) slots: Vec<Slot>,
+    /// The type of each temporary.
+    pub(crate) temps: Vec<Ty>,
+    /// How many labels there are, `L0` to the one before this.
+    pub(crate) labels: usize,
+    pub(crate) code: Vec<Inst>,
+}
+
+/// Memory in the frame of a function.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Slot {
+    /// The variable it's of, if it's one.
+    pub(crate) name: Option<String>,
+    pub(crate) layout: Layout,
+}
+
+/// `%n`, the temporary at index `n` of `Fun::temps`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Temp(pub(crate) usize);
+
+/// `Ln`, a place in the code of a function that can be jumped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Label(pub(crate) usize);
+
+/// What a temporary holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Ty {
+    /// An `int`, or a `char`, which is widened to one when loaded.
+    I32,
+    /// An address.
+    Ptr,
+}
+
+/// How much memory a load or a store reads or writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mem {
+    /// A `char`. Loads fill the rest of the `i32` with zeros, and stores
+    /// drop it.
+    I8,
+    I32,
+    Ptr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operand {
+    Temp(Temp),
+    Const(i64),
+    /// `$n`, the address of the slot at index `n` of `Fun::slots`.
+    Slot(usize),
+    /// `@name`, the address of the global at this index of
+    /// `Program::globals`.
+    Global(usize),
+    /// `@.strN`, the address of the string at this index of
+    /// `Program::strings`.
+    Str(usize),
+}
+
+/// What an `Inst::Check` checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Check {
+    /// That an index into an array is in the range of its elements.
+    Bounds,
+    /// That what an `int` is divided by isn't 0.
+    Division,
+}
+
+impl Check {
+    pub(crate) const ALL: [Check; 2] = [Check::Bounds, Check::Division];
+
+    /// What stops the program when the check fails.
+    pub(crate) fn trap(self) -> Trap {
+        match self {
+            Check::Bounds => Trap::IndexOutOfBounds,
+            Check::Division => Trap::DivisionByZero,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Check::Bounds => "bounds",
+            Check::Division => "division",
+        }
+    }
+}
+
+/// The checks lowering puts in the code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Checks {
+    /// Whether each index into an array is checked to be in its bounds.
+    pub(crate) bounds: bool,
+    /// Whether each division is checked not to be by 0, but those by a
+    /// constant.
+    pub(crate) division: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Callee {
+    /// The function at this index of `Program::funs`.
+    Fun(usize),
+    Builtin(Builtin),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Inst {
+    /// `dst = src`.
+    Copy {
+        dst: Temp,
+        src: Operand,
+    },
+    /// `dst = -src`.
+    Neg {
+        dst: Temp,
+        src: Operand,
+    },
+    /// `dst = lhs op rhs`, on `i32`s, but for comparisons, which may be of
+    /// addresses. A comparison is 1 if it holds and 0 if it doesn't.
+    Binary {
+        dst: Temp,
+        op: BinOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    /// `dst = base + bytes`, an address `bytes` after `base`.
+    Offset {
+        dst: Temp,
+        base: Operand,
+        bytes: Operand,
+    },
+    /// `dst = lhs - rhs`, how many bytes after `rhs` the address `lhs` is.
+    Diff {
+        dst: Temp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    /// `dst = *addr`.
+    Load {
+        dst: Temp,
+        mem: Mem,
+        addr: Operand,
+    },
+    /// `*addr = value`.
+    Store {
+        mem: Mem,
+        addr: Operand,
+        value: Operand,
+    },
+    /// Copies the `size` bytes at `src` to `dst`.
+    MemCopy {
+        dst: Operand,
+        src: Operand,
+        size: u64,
+    },
+    /// `dst = callee(args)`, or just the call if it returns nothing.
+    Call {
+        dst: Option<Temp>,
+        callee: Callee,
+        args: Vec<Operand>,
+    },
+    Label(Label),
+    Jump(Label),
+    /// Jumps to `then_label` if `cond` isn't 0, and to `else_label` if it
+    /// is.
+    Branch {
+        cond: Operand,
+        then_label: Label,
+        else_label: Label,
+    },
+    Return(Option<Operand>),
+    /// Goes on if `cond` isn't 0, and stops the program as `check` says if
+    /// it is, at `line` of the source.
+    Check {
+        cond: Operand,
+        check: Check,
+        line: usize,
+    },
+    /// Does nothing, but says the code after it, up to the next one, is of
+    /// `line` of the source.
+    Line(usize),
+}
+
+impl Inst {
+    /// Whether the code after it runs only if it's jumped to.
+    pub(crate) fn is_terminator(&self) -> bool {
+        matches!(self, Inst::Jump(_) | Inst::Branch { .. } | Inst::Return(_))
+    }
+
+    /// The temporary it assigns, if any.
+    pub(crate) fn dst(&self) -> Option<Temp> {
+        match *self {
+            Inst::Copy { dst, .. }
+            | Inst::Neg { dst, .. }
+            | Inst::Binary { dst, .. }
+            | Inst::Offset { dst, .. }
+            | Inst::Diff { dst, .. }
+            | Inst::Load { dst, .. } => Some(dst),
+            Inst::Call { dst, .. } => dst,
+            Inst::Store { .. }
+            | Inst::MemCopy { .. }
+            | Inst::Label(_)
+            | Inst::Jump(_)
+            | Inst::Branch { .. }
+            | Inst::Return(_)
+            | Inst::Check { .. }
+            | Inst::Line(_) => None,
+        }
+    }
+
+    /// The operands it reads, in order.
+    pub(crate) fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Inst::Copy { src, .. } | Inst::Neg { src, .. } => vec![src],
+            Inst::Binary { lhs, rhs, .. } | Inst::Diff { lhs, rhs, .. } => {
+                vec![lhs, rhs]
+            }
+            Inst::Offset { base, bytes, .. } => vec![base, bytes],
+            Inst::Load { addr, .. } => vec![addr],
+            Inst::Store { addr, value, .. } => vec![addr, value],
+            Inst::MemCopy { dst, src, .. } => vec![dst, src],
+            Inst::Call { args, .. } => args.iter().collect(),
+            Inst::Branch { cond, .. } | Inst::Check { cond, .. } => {
+                vec![cond]
+            }
+            Inst::Return(value) => value.iter().collect(),
+            Inst::Label(_) | Inst::Jump(_) | Inst::Line(_) => Vec::new(),
+        }
+    }
+
+    /// The operands it reads, in order, to be replaced.
+    pub(crate) fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Inst::Copy { src, .. } | Inst::Neg { src, .. } => vec![src],
+            Inst::Binary { lhs, rhs, .. } | Inst::Diff { lhs, rhs, .. } => {
+                vec![lhs, rhs]
+            }
+            Inst::Offset { base, bytes, .. } => vec![base, bytes],
+            Inst::Load { addr, .. } => vec![addr],
+            Inst::Store { addr, value, .. } => vec![addr, value],
+            Inst::MemCopy { dst, src, .. } => vec![dst, src],
+            Inst::Call { args, .. } => args.iter_mut().collect(),
+            Inst::Branch { cond, .. } | Inst::Check { cond, .. } => {
+                vec![cond]
+            }
+            Inst::Return(value) => value.iter_mut().collect(),
+            Inst::Label(_) | Inst::Jump(_) | Inst::Line(_) => Vec::new(),
+        }
+    }
+}
+
+impl Ty {
+    /// What a value of type `ty` is held in, or `None` for `void`.
+    fn of(ty: &Type) -> Option<Ty> {
+        match ty.unqualified() {
+            Type::Int | Type::Char => Some(Ty::I32),
+            Type::Pointer(_) | Type::Array { .. } | Type::Struct(_) => {
+                Some(Ty::Ptr)
+            }
+            Type::Void => None,
+            Type::Const(_) | Type::Error => {
+                panic!("lowered a value of type {}", ty)
+            }
+        }
+    }
+}
+
+impl Mem {
+    /// How a value of type `ty`, which isn't a struct or an array, is in
+    /// memory.
+    fn of(ty: &Type) -> Mem {
+        match ty.unqualified() {
+            Type::Char => Mem::I8,
+            Type::Int => Mem::I32,
+            Type::Pointer(_) => Mem::Ptr,
+            _ => panic!("loaded or stored a value of type {}", ty),
+        }
+    }
+}
+
+/// Lowers `program`, in which sema found no errors.
+#[cfg(test)]
+pub(crate) fn lower_program(program: &hir::Program) -> Program {
+    lower_program_with_checks(program, Checks::default(), None)
+}
+
+/// Lowers `program`, in which sema found no errors, with the checks
+/// `checks` says, which report lines of `source_file`, the source it's of.
+pub(crate) fn lower_program_with_checks(
+    program: &hir::Program,
+    checks: Checks,
+    source_file: Option<&SourceFile>,
+) -> Program {
+    lower(program, checks, source_file, false)
+}
+
+/// Lowers `program` as `lower_program_with_checks` does, saying which line
+/// of `source_file` the code of each statement is of (see `Inst::Line`).
+pub(crate) fn lower_program_with_lines(
+    program: &hir::Program,
+    checks: Checks,
+    source_file: &SourceFile,
+) -> Program {
+    lower(program, checks, Some(source_file), true)
+}
+
+fn lower(
+    program: &hir::Program,
+    checks: Checks,
+    source_file: Option<&SourceFile>,
+    lines: bool,
+) -> Program {
+    let mut structs = HashMap::new();
+    for s in &program.structs {
+        let fields = s.fields.iter().map(|f| (f.name.clone(), f.ty.clone()));
+        if let Some(layout) = StructLayout::of_fields(fields, &structs) {
+            structs.insert(s.name.clone(), layout);
+        }
+    }
+
+    let mut ir = Program {
+        globals: program
+            .globals
+            .iter()
+            .map(|global| Global {
+                name: global.name.clone(),
+                layout: layout_of(&global.ty, &structs),
+                init: global.init.filter(|&init| init != 0),
+            })
+            .collect(),
+        strings: Vec::new(),
+        funs: Vec::with_capacity(program.funs.len()),
+        file: source_file
+            .filter(|_| lines)
+            .map(|source_file| source_file.name.clone()),
+    };
+    for fun in &program.funs {
+        let lowerer = Lowerer {
+            structs: &structs,
+            strings: &mut ir.strings,
+            fun: Fun {
+                name: fun.name.clone(),
+                params: 0,
+                ret: None,
+                slots: Vec::new(),
+                temps: Vec::new(),
+                labels: 0,
+                code: Vec::new(),
+            },
+            breaks: Vec::new(),
+            continues: Vec::new(),
+            checks,
+            source_file,
+            lines,
+            line: None,
+        };
+        ir.funs.push(lowerer.lower_fun(fun));
+    }
+    assert_valid(&ir, "lowering");
+    ir
+}
+
+fn layout_of(ty: &Type, structs: &HashMap<String, StructLayout>) -> Layout {
+    Layout::of(ty, structs)
+        .unwrap_or_else(|| panic!("lowered a value of type {}", ty))
+}
+
+fn is_struct(ty: &Type) -> bool {
+    matches!(ty.unqualified(), Type::Struct(_))
+}
+
+struct Lowerer<'a> {
+    structs: &'a HashMap<String, StructLayout>,
+    strings: &'a mut Vec<String>,
+    /// The function lowered so far.
+    fun: Fun,
+    /// Where a `break` goes, innermost last.
+    breaks: Vec<Label>,
+    /// Where a `continue` goes, innermost last.
+    continues: Vec<Label>,
+    checks: Checks,
+    /// The source of the program, for the lines of the checks.
+    source_file: Option<&'a SourceFile>,
+    /// Whether the code says which line it's of.
+    lines: bool,
+    /// The line the code emitted last is of, if it says so.
+    line: Option<usize>,
+}
+
+impl Lowerer<'_> {
+    fn lower_fun(mut self, fun: &hir::Fun) -> Fun {
+        let returns_struct = is_struct(&fun.return_ty);
+        if returns_struct {
+            self.temp(Ty::Ptr);
+        } else {
+            self.fun.ret = Ty::of(&fun.return_ty);
+        }
+        let params = &fun.locals[..fun.params];
+        let args: Vec<_> = params
+            .iter()
+            .map(|param| {
+                let ty = Ty::of(&param.ty).expect("a parameter of type void");
+                self.temp(ty)
+            })
+            .collect();
+        self.fun.params = self.fun.temps.len();
+        self.mark_line(fun.span);
+        for local in &fun.locals {
+            let layout = self.layout(&local.ty);
+            self.fun.slots.push(Slot {
+                name: Some(local.name.clone()),
+                layout,
+            });
+        }
+        // Arguments are copied to their slots, so that they're variables
+        // like any other.
+        for (index, (param, arg)) in params.iter().zip(args).enumerate() {
+            self.store(&param.ty, Operand::Slot(index), Operand::Temp(arg));
+        }
+
+        self.stmts(&fun.body);
+        if !self.is_terminated() {
+            // A function that returns a value but gets to its end without
+            // one returns 0.
+            let value = self.fun.ret.map(|_| Operand::Const(0));
+            self.emit(Inst::Return(value));
+        }
+        self.fun
+    }
+
+    fn layout(&self, ty: &Type) -> Layout {
+        layout_of(ty, self.structs)
+    }
+
+    fn temp(&mut self, ty: Ty) -> Temp {
+        self.fun.temps.push(ty);
+        Temp(self.fun.temps.len() - 1)
+    }
+
+    fn label(&mut self) -> Label {
+        self.fun.labels += 1;
+        Label(self.fun.labels - 1)
+    }
+
+    fn emit(&mut self, inst: Inst) {
+        self.fun.code.push(inst);
+    }
+
+    /// The line of the source `span` starts at.
+    fn line_of(&self, span: Span) -> usize {
+        let source_file = self.source_file.expect("lines without a source");
+        source_file
+            .lookup_source_location(span.start)
+            .map_or(0, |loc| loc.line)
+    }
+
+    /// Says the code emitted next is of the line `span` starts at, if the
+    /// code says which line it's of, unless it can't be got to.
+    fn mark_line(&mut self, span: Span) {
+        if !self.lines || self.is_terminated() {
+            return;
+        }
+        let line = self.line_of(span);
+        if self.line != Some(line) {
+            self.line = Some(line);
+            self.emit(Inst::Line(line));
+        }
+    }
+
+    /// Whether the code emitted last can't go on to what's emitted next.
+    fn is_terminated(&self) -> bool {
+        self.fun.code.last().is_some_and(Inst::is_terminator)
+    }
+
+    /// Jumps to `label`, unless the code before can't get here.
+    fn jump(&mut self, label: Label) {
+        if !self.is_terminated() {
+            self.emit(Inst::Jump(label));
+        }
+    }
+
+    /// Puts `label` here, jumping to it from the code before.
+    fn place(&mut self, label: Label) {
+        if !self.fun.code.is_empty() {
+            self.jump(label);
+        }
+        self.emit(Inst::Label(label));
+    }
+
+    fn branch(
+        &mut self,
+        cond: &hir::Expr,
+        then_label: Label,
+        else_label: Label,
+    ) {
+        let cond = self.value(cond);
+        self.emit(Inst::Branch {
+            cond,
+            then_label,
+            else_label,
+        });
+    }
+
+    fn stmts(&mut self, stmts: &[hir::Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    /// Lowers the body of a loop, which `break` leaves for `end` and
+    /// `continue` leaves for `next`.
+    fn loop_body(&mut self, body: &hir::Stmt, end: Label, next: Label) {
+        self.breaks.push(end);
+        self.continues.push(next);
+        self.stmt(body);
+        self.continues.pop();
+        self.breaks.pop();
+    }
+
+    fn stmt(&mut self, stmt: &hir::Stmt) {
+        if !matches!(stmt.kind, StmtKind::Block(_) | StmtKind::Empty) {
+            self.mark_line(stmt.span);
+        }
+        match &stmt.kind {
+            StmtKind::Expr(expr) => {
+                self.value(expr);
+            }
+            StmtKind::Empty => {}
+            StmtKind::Block(stmts) => self.stmts(stmts),
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let then_label = self.label();
+                let else_label = else_branch.as_ref().map(|_| self.label());
+                let end = self.label();
+                self.branch(cond, then_label, else_label.unwrap_or(end));
+                self.place(then_label);
+                self.stmt(then_branch);
+                if let (Some(else_branch), Some(else_label)) =
+                    (else_branch, else_label)
+                {
+                    self.jump(end);
+                    self.emit(Inst::Label(else_label));
+                    self.stmt(else_branch);
+                }
+                self.place(end);
+            }
+            StmtKind::While { cond, body } => {
+                let (next, body_label, end) =
+                    (self.label(), self.label(), self.label());
+                self.place(next);
+                self.mark_line(cond.span);
+                self.branch(cond, body_label, end);
+                self.place(body_label);
+                self.loop_body(body, end, next);
+                self.jump(next);
+                self.place(end);
+            }
+            StmtKind::DoWhile { body, cond } => {
+                let (body_label, next, end) =
+                    (self.label(), self.label(), self.label());
+                self.place(body_label);
+                self.loop_body(body, end, next);
+                self.place(next);
+                self.mark_line(cond.span);
+                self.branch(cond, body_label, end);
+                self.place(end);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.value(init);
+                }
+                let (start, body_label, next, end) =
+                    (self.label(), self.label(), self.label(), self.label());
+                self.place(start);
+                if let Some(cond) = cond {
+                    self.mark_line(cond.span);
+                    self.branch(cond, body_label, end);
+                }
+                self.place(body_label);
+                self.loop_body(body, end, next);
+                self.place(next);
+                if let Some(step) = step {
+                    self.mark_line(step.span);
+                    self.value(step);
+                }
+                self.jump(start);
+                self.place(end);
+            }
+            StmtKind::Switch { cond, cases } => {
+                let value = self.value(cond);
+                let labels: Vec<_> =
+                    cases.iter().map(|_| self.label()).collect();
+                let end = self.label();
+                let mut default = end;
+                for (case, &label) in cases.iter().zip(&labels) {
+                    let Some(case_value) = case.value else {
+                        default = label;
+                        continue;
+                    };
+                    let (is_case, next) = (self.temp(Ty::I32), self.label());
+                    self.emit(Inst::Binary {
+                        dst: is_case,
+                        op: BinOp::Equal,
+                        lhs: value,
+                        rhs: Operand::Const(case_value),
+                    });
+                    self.emit(Inst::Branch {
+                        cond: Operand::Temp(is_case),
+                        then_label: label,
+                        else_label: next,
+                    });
+                    self.place(next);
+                }
+                self.jump(default);
+
+                self.breaks.push(end);
+                for (case, &label) in cases.iter().zip(&labels) {
+                    self.place(label);
+                    self.stmts(&case.stmts);
+                }
+                self.breaks.pop();
+                self.place(end);
+            }
+            StmtKind::Break => {
+                let end = *self.breaks.last().expect("a break outside loops");
+                self.jump(end);
+            }
+            StmtKind::Continue => {
+                let next =
+                    *self.continues.last().expect("a continue outside loops");
+                self.jump(next);
+            }
+            StmtKind::Return(value) => {
+                let value =
+                    value.as_ref().map(|value| (value, self.value(value)));
+                match value {
+                    Some((expr, value)) if is_struct(&expr.ty) => {
+                        let size = self.layout(&expr.ty).size;
+                        self.emit(Inst::MemCopy {
+                            dst: Operand::Temp(Temp(0)),
+                            src: value,
+                            size,
+                        });
+                        self.emit(Inst::Return(None));
+                    }
+                    value => {
+                        self.emit(Inst::Return(value.map(|(_, value)| value)))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the value of type `ty` at `addr`: the address itself for a
+    /// struct or an array.
+    fn load(&mut self, ty: &Type, addr: Operand) -> Operand {
+        if matches!(ty.unqualified(), Type::Struct(_) | Type::Array { .. }) {
+            return addr;
+        }
+        let dst = self.temp(Ty::of(ty).expect("loaded a void"));
+        self.emit(Inst::Load {
+            dst,
+            mem: Mem::of(ty),
+            addr,
+        });
+        Operand::Temp(dst)
+    }
+
+    /// Writes `value`, of type `ty`, to `addr`.
+    fn store(&mut self, ty: &Type, addr: Operand, value: Operand) {
+        let inst = if is_struct(ty) {
+            Inst::MemCopy {
+                dst: addr,
+                src: value,
+                size: self.layout(ty).size,
+            }
+        } else {
+            Inst::Store {
+                mem: Mem::of(ty),
+                addr,
+                value,
+            }
+        };
+        self.emit(inst);
+    }
+
+    /// `base + bytes`, skipping the addition of a constant 0.
+    fn offset(&mut self, base: Operand, bytes: Operand) -> Operand {
+        if bytes == Operand::Const(0) {
+            return base;
+        }
+        let dst = self.temp(Ty::Ptr);
+        self.emit(Inst::Offset { dst, base, bytes });
+        Operand::Temp(dst)
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: Operand, rhs: Operand) -> Operand {
+        let dst = self.temp(Ty::I32);
+        self.emit(Inst::Binary { dst, op, lhs, rhs });
+        Operand::Temp(dst)
+    }
+
+    /// Checks that `cond` isn't 0, as `check` says, for the expression at
+    /// `span`.
+    fn check(&mut self, cond: Operand, check: Check, span: Span) {
+        let line = self.line_of(span);
+        self.emit(Inst::Check { cond, check, line });
+    }
+
+    /// Checks that `index`, into an array of `len` elements at `span`, is
+    /// in its bounds, unless it's a constant that is.
+    fn check_bounds(&mut self, index: Operand, len: i64, span: Span) {
+        if let Operand::Const(index) = index {
+            if (0..len).contains(&index) {
+                return;
+            }
+        }
+        let at_least_0 =
+            self.binary(BinOp::GreaterEqual, index, Operand::Const(0));
+        self.check(at_least_0, Check::Bounds, span);
+        let under_len = self.binary(BinOp::Less, index, Operand::Const(len));
+        self.check(under_len, Check::Bounds, span);
+    }
+
+    /// Checks that `divisor`, of a division at `span`, isn't 0, unless it's
+    /// a constant.
+    fn check_divisor(&mut self, divisor: Operand, span: Span) {
+        if let Operand::Const(divisor) = divisor {
+            if divisor != 0 {
+                return;
+            }
+        }
+        let not_0 = self.binary(BinOp::NotEqual, divisor, Operand::Const(0));
+        self.check(not_0, Check::Division, span);
+    }
+
+    /// The size of what a value of type `ty`, a pointer, points to.
+    fn pointee_size(&self, ty: &Type) -> i64 {
+        let pointee = ty.pointee().expect("not a pointer");
+        self.layout(pointee).size as i64
+    }
+
+    /// The address of the value `expr` is, which is somewhere in memory.
+    fn address(&mut self, expr: &hir::Expr) -> Operand {
+        match &expr.kind {
+            ExprKind::Var(Def::Global(index)) => Operand::Global(*index),
+            ExprKind::Var(Def::Local(index)) => Operand::Slot(*index),
+            ExprKind::Index { array, index } => {
+                // The value of an array is its address, so this is the same
+                // for pointers and arrays.
+                let base = self.value(array);
+                let index = self.value(index);
+                if let (true, Type::Array { len, .. }) =
+                    (self.checks.bounds, array.ty.unqualified())
+                {
+                    self.check_bounds(index, *len as i64, expr.span);
+                }
+                let size = self.layout(&expr.ty).size as i64;
+                let bytes =
+                    self.binary(BinOp::Mul, index, Operand::Const(size));
+                self.offset(base, bytes)
+            }
+            ExprKind::Member { base, field } => {
+                let Type::Struct(name) = base.ty.unqualified() else {
+                    panic!("a member of a {}", base.ty);
+                };
+                let offset = self.structs[name].fields[*field].1 as i64;
+                let base = self.value(base);
+                self.offset(base, Operand::Const(offset))
+            }
+            ExprKind::Unary {
+                op: UnOp::Deref,
+                operand,
+            } => self.value(operand),
+            // A struct a function returns, or one assigned or chosen by a
+            // conditional, whose value is already its address.
+            _ => self.value(expr),
+        }
+    }
+
+    /// The value of `expr`, after evaluating it.
+    fn value(&mut self, expr: &hir::Expr) -> Operand {
+        match &expr.kind {
+            ExprKind::Number(value) => Operand::Const(*value as i64),
+            ExprKind::Char(value) => Operand::Const(i64::from(*value)),
+            ExprKind::Str(text) => {
+                self.strings.push(text.clone());
+                Operand::Str(self.strings.len() - 1)
+            }
+            ExprKind::Var(_)
+            | ExprKind::Index { .. }
+            | ExprKind::Member { .. }
+            | ExprKind::Unary {
+                op: UnOp::Deref, ..
+            } => {
+                let addr = self.address(expr);
+                self.load(&expr.ty, addr)
+            }
+            ExprKind::Unary {
+                op: UnOp::AddrOf,
+                operand,
+            } => self.address(operand),
+            ExprKind::Unary {
+                op: UnOp::Neg,
+                operand,
+            } => {
+                let src = self.value(operand);
+                let dst = self.temp(Ty::I32);
+                self.emit(Inst::Neg { dst, src });
+                Operand::Temp(dst)
+            }
+            ExprKind::Call { callee, args } => {
+                self.call(&expr.ty, *callee, args)
+            }
+            ExprKind::Assign { target, value } => {
+                let addr = self.address(target);
+                let value = self.value(value);
+                self.store(&target.ty, addr, value);
+                if is_struct(&target.ty) {
+                    addr
+                } else {
+                    value
+                }
+            }
+            ExprKind::Binary { op, lhs, rhs } => {
+                let (lhs_ty, rhs_ty) = (lhs.ty.decay(), rhs.ty.decay());
+                let (lhs, rhs) = (self.value(lhs), self.value(rhs));
+                match op {
+                    BinOp::Add | BinOp::Sub
+                        if lhs_ty.is_pointer() && rhs_ty.is_pointer() =>
+                    {
+                        // Only a subtraction, how many elements apart.
+                        let bytes = self.temp(Ty::I32);
+                        self.emit(Inst::Diff {
+                            dst: bytes,
+                            lhs,
+                            rhs,
+                        });
+                        let size = self.pointee_size(&lhs_ty);
+                        self.binary(
+                            BinOp::Div,
+                            Operand::Temp(bytes),
+                            Operand::Const(size),
+                        )
+                    }
+                    BinOp::Add | BinOp::Sub if lhs_ty.is_pointer() => {
+                        let mut size = self.pointee_size(&lhs_ty);
+                        if *op == BinOp::Sub {
+                            size = -size;
+                        }
+                        let bytes =
+                            self.binary(BinOp::Mul, rhs, Operand::Const(size));
+                        self.offset(lhs, bytes)
+                    }
+                    BinOp::Add if rhs_ty.is_pointer() => {
+                        let size = self.pointee_size(&rhs_ty);
+                        let bytes =
+                            self.binary(BinOp::Mul, lhs, Operand::Const(size));
+                        self.offset(rhs, bytes)
+                    }
+                    BinOp::Div if self.checks.division => {
+                        self.check_divisor(rhs, expr.span);
+                        self.binary(BinOp::Div, lhs, rhs)
+                    }
+                    _ => self.binary(*op, lhs, rhs),
+                }
+            }
+            ExprKind::Conditional {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                let (then_label, else_label, end) =
+                    (self.label(), self.label(), self.label());
+                let dst = Ty::of(&expr.ty).map(|ty| self.temp(ty));
+                self.branch(cond, then_label, else_label);
+                for (label, branch) in
+                    [(then_label, then_expr), (else_label, else_expr)]
+                {
+                    self.place(label);
+                    let src = self.value(branch);
+                    if let Some(dst) = dst {
+                        self.emit(Inst::Copy { dst, src });
+                    }
+                    self.jump(end);
+                }
+                self.place(end);
+                dst.map_or(Operand::Const(0), Operand::Temp)
+            }
+            ExprKind::Error => {
+                panic!("lowered an expression sema couldn't make sense of")
+            }
+        }
+    }
+
+    /// Calls `callee`, whose calls are values of type `ty`, with `args`.
+    fn call(&mut self, ty: &Type, callee: Def, args: &[hir::Expr]) -> Operand {
+        let callee = match callee {
+            Def::Fun(index) => Callee::Fun(index),
+            Def::Builtin(builtin) => Callee::Builtin(builtin),
+            Def::Global(_) | Def::Local(_) => panic!("called a variable"),
+        };
+        let mut operands = Vec::with_capacity(args.len() + 1);
+        let result = if is_struct(ty) {
+            let layout = self.layout(ty);
+            self.fun.slots.push(Slot { name: None, layout });
+            let slot = Operand::Slot(self.fun.slots.len() - 1);
+            operands.push(slot);
+            Some(slot)
+        } else {
+            None
+        };
+        for arg in args {
+            let arg = self.value(arg);
+            operands.push(arg);
+        }
+        let dst = match result {
+            Some(_) => None,
+            None => Ty::of(ty).map(|ty| self.temp(ty)),
+        };
+        self.emit(Inst::Call {
+            dst,
+            callee,
+            args: operands,
+        });
+        // The value of a call to a function returning `void` is never used.
+        result
+            .or(dst.map(Operand::Temp))
+            .unwrap_or(Operand::Const(0))
+    }
+}
+
+impl fmt::Display for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Ty::I32 => "i32",
+            Ty::Ptr => "ptr",
+        })
+    }
+}
+
+impl fmt::Display for Mem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mem::I8 => "i8",
+            Mem::I32 => "i32",
+            Mem::Ptr => "ptr",
+        })
+    }
+}
+
+/// The name of the instruction for `op`.
+fn binary_name(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "add",
+        BinOp::Sub => "sub",
+        BinOp::Mul => "mul",
+        BinOp::Div => "div",
+        BinOp::Less => "lt",
+        BinOp::LessEqual => "le",
+        BinOp::Greater => "gt",
+        BinOp::GreaterEqual => "ge",
+        BinOp::Equal => "eq",
+        BinOp::NotEqual => "ne",
+    }
+}
+
+/// Dumps `program` as `--emit=ir` shows it.
+pub(crate) fn dump_ir(program: &Program) -> String {
+    let mut out = String::new();
+    for global in &program.globals {
+        let _ = write!(
+            out,
+            "global @{}: {}, align {}",
+            global.name, global.layout.size, global.layout.align
+        );
+        if let Some(init) = global.init {
+            let _ = write!(out, " = {}", init);
+        }
+        out.push('\n');
+    }
+    for (index, string) in program.strings.iter().enumerate() {
+        let _ = writeln!(out, "string @.str{} = {:?}", index, string);
+    }
+
+    for fun in &program.funs {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let params: Vec<_> = fun.temps[..fun.params]
+            .iter()
+            .enumerate()
+            .map(|(index, ty)| format!("{}: {}", Temp(index), ty))
+            .collect();
+        let _ = write!(out, "fun @{}({})", fun.name, params.join(", "));
+        if let Some(ret) = fun.ret {
+            let _ = write!(out, " -> {}", ret);
+        }
+        out.push_str(" {\n");
+        for (index, slot) in fun.slots.iter().enumerate() {
+            let name = slot
+                .name
+                .as_ref()
+                .map_or(String::new(), |name| format!(" {}", name));
+            let _ = writeln!(
+                out,
+                "  slot ${}{}: {}, align {}",
+                index, name, slot.layout.size, slot.layout.align
+            );
+        }
+        for inst in &fun.code {
+            let indent = if let Inst::Label(_) = inst { "" } else { "  " };
+            let _ =
+                writeln!(out, "{}{}", indent, dump_inst(program, fun, inst));
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+/// Dumps `inst`, an instruction of `fun`, as `--emit=ir` shows it, a label
+/// with the `:` after it.
+pub(crate) fn dump_inst(program: &Program, fun: &Fun, inst: &Inst) -> String {
+    let operand = |operand: &Operand| match *operand {
+        Operand::Temp(temp) => temp.to_string(),
+        Operand::Const(value) => value.to_string(),
+        Operand::Slot(index) => format!("${}", index),
+        Operand::Global(index) => format!("@{}", program.globals[index].name),
+        Operand::Str(index) => format!("@.str{}", index),
+    };
+    let operands = |operands: &[Operand]| {
+        operands.iter().map(operand).collect::<Vec<_>>().join(", ")
+    };
+    let def = |dst: Temp| format!("{}: {}", dst, fun.temps[dst.0]);
+    match inst {
+        Inst::Label(label) => format!("{}:", label),
+        Inst::Copy { dst, src } => {
+            format!("{} = copy {}", def(*dst), operand(src))
+        }
+        Inst::Neg { dst, src } => {
+            format!("{} = neg {}", def(*dst), operand(src))
+        }
+        Inst::Binary { dst, op, lhs, rhs } => format!(
+            "{} = {} {}, {}",
+            def(*dst),
+            binary_name(*op),
+            operand(lhs),
+            operand(rhs)
+        ),
+        Inst::Offset { dst, base, bytes } => format!(
+            "{} = offset {}, {}",
+            def(*dst),
+            operand(base),
+            operand(bytes)
+        ),
+        Inst::Diff { dst, lhs, rhs } => {
+            format!("{} = diff {}, {}", def(*dst), operand(lhs), operand(rhs))
+        }
+        Inst::Load { dst, mem, addr } => {
+            format!("{} = load {} {}", def(*dst), mem, operand(addr))
+        }
+        Inst::Store { mem, addr, value } => {
+            format!("store {} {}, {}", mem, operand(addr), operand(value))
+        }
+        Inst::MemCopy { dst, src, size } => {
+            format!("memcopy {}, {}, {}", operand(dst), operand(src), size)
+        }
+        Inst::Call { dst, callee, args } => {
+            let callee = match *callee {
+                Callee::Fun(index) => &program.funs[index].name,
+                Callee::Builtin(builtin) => builtin.name(),
+            };
+            let call = format!("call @{}({})", callee, operands(args));
+            match dst {
+                Some(dst) => format!("{} = {}", def(*dst), call),
+                None => call,
+            }
+        }
+        Inst::Jump(label) => format!("jump {}", label),
+        Inst::Branch {
+            cond,
+            then_label,
+            else_label,
+        } => {
+            format!("branch {}, {}, {}", operand(cond), then_label, else_label)
+        }
+        Inst::Return(None) => "ret".to_string(),
+        Inst::Return(Some(value)) => format!("ret {}", operand(value)),
+        Inst::Check { cond, check, line } => {
+            format!("check {} {}, line {}", check.name(), operand(cond), line)
+        }
+        Inst::Line(line) => format!("line {}", line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        dump_ir, lower_program_with_checks, lower_program_with_lines, Checks,
+    };
+    use crate::test_support::analyze_source;
+
+    fn ir(source: &str) -> String {
+        ir_with_checks(source, Checks::default())
+    }
+
+    fn ir_with_checks(source: &str, checks: Checks) -> String {
+        let (source_file, analysis) = analyze_source(source);
+        dump_ir(&lower_program_with_checks(
+            &analysis.hir,
+            checks,
+            Some(&source_file),
+        ))
+    }
+
+    fn ir_with_lines(source: &str) -> String {
+        let (source_file, analysis) = analyze_source(source);
+        dump_ir(&lower_program_with_lines(
+            &analysis.hir,
+            Checks::default(),
+            &source_file,
+        ))
+    }
+
+    #[test]
+    fn loops_and_arrays_are_lowered_to_jumps_and_addresses() {
+        let source = "int v[4];\n\
+                      int sum(int a[], int n) {\n\
+                      \x20   int i; int s;\n\
+                      \x20   i = 0; s = 0;\n\
+                      \x20   while (i < n) { s = s + a[i]; i = i + 1; }\n\
+                      \x20   return s;\n\
+                      }\n\
+                      void main(void) { output(sum(v, 4)); }\n";
+
+        assert_eq!(
+            ir(source),
+            "global @v: 16, align 4\n\
+             \n\
+             fun @sum(%0: ptr, %1: i32) -> i32 {\n\
+             \x20 slot $0 a: 8, align 8\n\
+             \x20 slot $1 n: 4, align 4\n\
+             \x20 slot $2 i: 4, align 4\n\
+             \x20 slot $3 s: 4, align 4\n\
+             \x20 store ptr $0, %0\n\
+             \x20 store i32 $1, %1\n\
+             \x20 store i32 $2, 0\n\
+             \x20 store i32 $3, 0\n\
+             \x20 jump L0\n\
+             L0:\n\
+             \x20 %2: i32 = load i32 $2\n\
+             \x20 %3: i32 = load i32 $1\n\
+             \x20 %4: i32 = lt %2, %3\n\
+             \x20 branch %4, L1, L2\n\
+             L1:\n\
+             \x20 %5: i32 = load i32 $3\n\
+             \x20 %6: ptr = load ptr $0\n\
+             \x20 %7: i32 = load i32 $2\n\
+             \x20 %8: i32 = mul %7, 4\n\
+             \x20 %9: ptr = offset %6, %8\n\
+             \x20 %10: i32 = load i32 %9\n\
+             \x20 %11: i32 = add %5, %10\n\
+             \x20 store i32 $3, %11\n\
+             \x20 %12: i32 = load i32 $2\n\
+             \x20 %13: i32 = add %12, 1\n\
+             \x20 store i32 $2, %13\n\
+             \x20 jump L0\n\
+             L2:\n\
+             \x20 %14: i32 = load i32 $3\n\
+             \x20 ret %14\n\
+             }\n\
+             \n\
+             fun @main() {\n\
+             \x20 %0: i32 = call @sum(@v, 4)\n\
+             \x20 call @output(%0)\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn structs_are_copied_and_pointers_scaled() {
+        let source = "struct p { char c; int x; };\n\
+                      struct p f(struct p q, int *r) {\n\
+                      \x20   q.x = r[1] - *(r + 2);\n\
+                      \x20   return q;\n\
+                      }\n\
+                      void main(void) {\n\
+                      \x20   struct p s; char t[3];\n\
+                      \x20   s = f(s, &s.x);\n\
+                      \x20   output(t[1] ? 1 : -1);\n\
+                      }\n";
+
+        assert_eq!(
+            ir(source),
+            "fun @f(%0: ptr, %1: ptr, %2: ptr) {\n\
+             \x20 slot $0 q: 8, align 4\n\
+             \x20 slot $1 r: 8, align 8\n\
+             \x20 memcopy $0, %1, 8\n\
+             \x20 store ptr $1, %2\n\
+             \x20 %3: ptr = offset $0, 4\n\
+             \x20 %4: ptr = load ptr $1\n\
+             \x20 %5: i32 = mul 1, 4\n\
+             \x20 %6: ptr = offset %4, %5\n\
+             \x20 %7: i32 = load i32 %6\n\
+             \x20 %8: ptr = load ptr $1\n\
+             \x20 %9: i32 = mul 2, 4\n\
+             \x20 %10: ptr = offset %8, %9\n\
+             \x20 %11: i32 = load i32 %10\n\
+             \x20 %12: i32 = sub %7, %11\n\
+             \x20 store i32 %3, %12\n\
+             \x20 memcopy %0, $0, 8\n\
+             \x20 ret\n\
+             }\n\
+             \n\
+             fun @main() {\n\
+             \x20 slot $0 s: 8, align 4\n\
+             \x20 slot $1 t: 3, align 1\n\
+             \x20 slot $2: 8, align 4\n\
+             \x20 %0: ptr = offset $0, 4\n\
+             \x20 call @f($2, $0, %0)\n\
+             \x20 memcopy $0, $2, 8\n\
+             \x20 %2: i32 = mul 1, 1\n\
+             \x20 %3: ptr = offset $1, %2\n\
+             \x20 %4: i32 = load i8 %3\n\
+             \x20 branch %4, L0, L1\n\
+             L0:\n\
+             \x20 %1: i32 = copy 1\n\
+             \x20 jump L2\n\
+             L1:\n\
+             \x20 %5: i32 = neg 1\n\
+             \x20 %1: i32 = copy %5\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 call @output(%1)\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn indices_into_arrays_are_checked_with_bounds_checks() {
+        let source = "int m[2][3];\n\
+                      int get(int a[], int i) { return a[i]; }\n\
+                      void main(void) {\n\
+                      \x20   int i;\n\
+                      \x20   i = input();\n\
+                      \x20   m[1][i] = get(m[0], m[i][2]);\n\
+                      }\n";
+        let checks = Checks {
+            bounds: true,
+            ..Checks::default()
+        };
+
+        // Constant indices in the bounds aren't checked, and neither are
+        // those through pointers, as in `get`.
+        assert_eq!(
+            ir_with_checks(source, checks),
+            "global @m: 24, align 4\n\
+             \n\
+             fun @get(%0: ptr, %1: i32) -> i32 {\n\
+             \x20 slot $0 a: 8, align 8\n\
+             \x20 slot $1 i: 4, align 4\n\
+             \x20 store ptr $0, %0\n\
+             \x20 store i32 $1, %1\n\
+             \x20 %2: ptr = load ptr $0\n\
+             \x20 %3: i32 = load i32 $1\n\
+             \x20 %4: i32 = mul %3, 4\n\
+             \x20 %5: ptr = offset %2, %4\n\
+             \x20 %6: i32 = load i32 %5\n\
+             \x20 ret %6\n\
+             }\n\
+             \n\
+             fun @main() {\n\
+             \x20 slot $0 i: 4, align 4\n\
+             \x20 %0: i32 = call @input()\n\
+             \x20 store i32 $0, %0\n\
+             \x20 %1: i32 = mul 1, 12\n\
+             \x20 %2: ptr = offset @m, %1\n\
+             \x20 %3: i32 = load i32 $0\n\
+             \x20 %4: i32 = ge %3, 0\n\
+             \x20 check bounds %4, line 6\n\
+             \x20 %5: i32 = lt %3, 3\n\
+             \x20 check bounds %5, line 6\n\
+             \x20 %6: i32 = mul %3, 4\n\
+             \x20 %7: ptr = offset %2, %6\n\
+             \x20 %8: i32 = mul 0, 12\n\
+             \x20 %9: ptr = offset @m, %8\n\
+             \x20 %10: i32 = load i32 $0\n\
+             \x20 %11: i32 = ge %10, 0\n\
+             \x20 check bounds %11, line 6\n\
+             \x20 %12: i32 = lt %10, 2\n\
+             \x20 check bounds %12, line 6\n\
+             \x20 %13: i32 = mul %10, 12\n\
+             \x20 %14: ptr = offset @m, %13\n\
+             \x20 %15: i32 = mul 2, 4\n\
+             \x20 %16: ptr = offset %14, %15\n\
+             \x20 %17: i32 = load i32 %16\n\
+             \x20 %18: i32 = call @get(%9, %17)\n\
+             \x20 store i32 %7, %18\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn divisors_are_checked_with_division_checks() {
+        let source = "void main(void) {\n\
+                      \x20   int a[4]; int *p; int n;\n\
+                      \x20   p = a + 3; n = input();\n\
+                      \x20   output(n / 2 + 2 / n + (p - a));\n\
+                      }\n";
+        let checks = Checks {
+            division: true,
+            ..Checks::default()
+        };
+
+        // Constant divisors aren't checked, and neither are the sizes the
+        // difference of two pointers is divided by.
+        assert_eq!(
+            ir_with_checks(source, checks),
+            "fun @main() {\n\
+             \x20 slot $0 a: 16, align 4\n\
+             \x20 slot $1 p: 8, align 8\n\
+             \x20 slot $2 n: 4, align 4\n\
+             \x20 %0: i32 = mul 3, 4\n\
+             \x20 %1: ptr = offset $0, %0\n\
+             \x20 store ptr $1, %1\n\
+             \x20 %2: i32 = call @input()\n\
+             \x20 store i32 $2, %2\n\
+             \x20 %3: i32 = load i32 $2\n\
+             \x20 %4: i32 = div %3, 2\n\
+             \x20 %5: i32 = load i32 $2\n\
+             \x20 %6: i32 = ne %5, 0\n\
+             \x20 check division %6, line 4\n\
+             \x20 %7: i32 = div 2, %5\n\
+             \x20 %8: i32 = add %4, %7\n\
+             \x20 %9: ptr = load ptr $1\n\
+             \x20 %10: i32 = diff %9, $0\n\
+             \x20 %11: i32 = div %10, 4\n\
+             \x20 %12: i32 = add %8, %11\n\
+             \x20 call @output(%12)\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn lines_are_said_where_the_code_of_each_starts() {
+        // The condition and the step of a loop, which are jumped to from
+        // its body, say their lines of their own.
+        let source = "int sq(int x) {\n\
+                      \x20   return x * x;\n\
+                      }\n\
+                      void main(void) {\n\
+                      \x20   int i;\n\
+                      \x20   for (i = 0;\n\
+                      \x20        i < 3;\n\
+                      \x20        i = i + 1) {\n\
+                      \x20       output(sq(i));\n\
+                      \x20   }\n\
+                      }\n";
+
+        assert_eq!(
+            ir_with_lines(source),
+            "fun @sq(%0: i32) -> i32 {\n\
+             \x20 slot $0 x: 4, align 4\n\
+             \x20 line 1\n\
+             \x20 store i32 $0, %0\n\
+             \x20 line 2\n\
+             \x20 %1: i32 = load i32 $0\n\
+             \x20 %2: i32 = load i32 $0\n\
+             \x20 %3: i32 = mul %1, %2\n\
+             \x20 ret %3\n\
+             }\n\
+             \n\
+             fun @main() {\n\
+             \x20 slot $0 i: 4, align 4\n\
+             \x20 line 4\n\
+             \x20 line 6\n\
+             \x20 store i32 $0, 0\n\
+             \x20 jump L0\n\
+             L0:\n\
+             \x20 line 7\n\
+             \x20 %0: i32 = load i32 $0\n\
+             \x20 %1: i32 = lt %0, 3\n\
+             \x20 branch %1, L1, L3\n\
+             L1:\n\
+             \x20 line 9\n\
+             \x20 %2: i32 = load i32 $0\n\
+             \x20 %3: i32 = call @sq(%2)\n\
+             \x20 call @output(%3)\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 line 8\n\
+             \x20 %4: i32 = load i32 $0\n\
+             \x20 %5: i32 = add %4, 1\n\
+             \x20 store i32 $0, %5\n\
+             \x20 jump L0\n\
+             L3:\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+}
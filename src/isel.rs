@@ -0,0 +1,489 @@
+//! Instruction selection by tables: a backend says, in a table of rules,
+//! which instructions of the target each instruction of the three-address
+//! code is written as, and this goes through the code with it, so a new
+//! target is mostly a new table, not a new walk over the code.
+//!
+//! A rule covers an instruction by what kind it is, and by the classes its
+//! operands are in, e.g. a `Binary` adding an `i32` to a constant, and its
+//! template is the lines the instruction is selected as. The first rule of
+//! the table that covers an instruction is the one selected, so the more
+//! specific rules go first, and the last rule of a kind is usually the one
+//! for any operands.
+//!
+//! A template is written in the syntax of the target, with placeholders,
+//! `{name}` or `{name:form}`, of what the target writes for an operand,
+//! e.g. `{lhs:32}` for the 32-bit register `lhs` is in, or for anything
+//! else of the instruction it knows how to write, e.g. `{label}`. Writing
+//! a placeholder may emit lines of its own first, e.g. to load an operand
+//! into a register, and a placeholder is written once for an instruction,
+//! however many times its template has it. A line starting with `@` is an
+//! action of the target, e.g. `@load lhs %rax`, for what's more than a
+//! line, with the rest of the line, its placeholders written, as what it's
+//! done with.
+
+use crate::{
+    ast::BinOp,
+    ir::{Fun, Inst, Mem, Operand, Temp, Ty},
+};
+use std::collections::HashMap;
+
+/// What an instruction is, as the variants of `Inst` are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Copy,
+    Neg,
+    Binary,
+    Offset,
+    Diff,
+    Load,
+    Store,
+    MemCopy,
+    Call,
+    Label,
+    Jump,
+    Branch,
+    Return,
+    Check,
+    Line,
+}
+
+impl Kind {
+    pub(crate) fn of(inst: &Inst) -> Kind {
+        match inst {
+            Inst::Copy { .. } => Kind::Copy,
+            Inst::Neg { .. } => Kind::Neg,
+            Inst::Binary { .. } => Kind::Binary,
+            Inst::Offset { .. } => Kind::Offset,
+            Inst::Diff { .. } => Kind::Diff,
+            Inst::Load { .. } => Kind::Load,
+            Inst::Store { .. } => Kind::Store,
+            Inst::MemCopy { .. } => Kind::MemCopy,
+            Inst::Call { .. } => Kind::Call,
+            Inst::Label(_) => Kind::Label,
+            Inst::Jump(_) => Kind::Jump,
+            Inst::Branch { .. } => Kind::Branch,
+            Inst::Return(_) => Kind::Return,
+            Inst::Check { .. } => Kind::Check,
+            Inst::Line(_) => Kind::Line,
+        }
+    }
+}
+
+/// An operand of an instruction, by the name of its field in `Inst`, which
+/// is its name in templates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Field {
+    Dst,
+    Src,
+    Lhs,
+    Rhs,
+    Base,
+    Bytes,
+    Addr,
+    Value,
+    Cond,
+}
+
+impl Field {
+    const ALL: [Field; 9] = [
+        Field::Dst,
+        Field::Src,
+        Field::Lhs,
+        Field::Rhs,
+        Field::Base,
+        Field::Bytes,
+        Field::Addr,
+        Field::Value,
+        Field::Cond,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Field::Dst => "dst",
+            Field::Src => "src",
+            Field::Lhs => "lhs",
+            Field::Rhs => "rhs",
+            Field::Base => "base",
+            Field::Bytes => "bytes",
+            Field::Addr => "addr",
+            Field::Value => "value",
+            Field::Cond => "cond",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Field> {
+        Field::ALL.into_iter().find(|field| field.name() == name)
+    }
+
+    /// The operand of `inst` this is, if it has it. The temporary an
+    /// instruction assigns is its `dst`.
+    pub(crate) fn of(self, inst: &Inst) -> Option<Operand> {
+        let operand = match (self, inst) {
+            (Field::Dst, Inst::MemCopy { dst, .. }) => *dst,
+            (Field::Dst, _) => Operand::Temp(inst.dst()?),
+            (
+                Field::Src,
+                Inst::Copy { src, .. }
+                | Inst::Neg { src, .. }
+                | Inst::MemCopy { src, .. },
+            ) => *src,
+            (Field::Lhs, Inst::Binary { lhs, .. } | Inst::Diff { lhs, .. }) => {
+                *lhs
+            }
+            (Field::Rhs, Inst::Binary { rhs, .. } | Inst::Diff { rhs, .. }) => {
+                *rhs
+            }
+            (Field::Base, Inst::Offset { base, .. }) => *base,
+            (Field::Bytes, Inst::Offset { bytes, .. }) => *bytes,
+            (
+                Field::Addr,
+                Inst::Load { addr, .. } | Inst::Store { addr, .. },
+            ) => *addr,
+            (Field::Value, Inst::Store { value, .. }) => *value,
+            (Field::Value, Inst::Return(value)) => (*value)?,
+            (
+                Field::Cond,
+                Inst::Branch { cond, .. } | Inst::Check { cond, .. },
+            ) => *cond,
+            _ => return None,
+        };
+        Some(operand)
+    }
+}
+
+/// What a rule asks of an operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Class {
+    /// It's not there, as the value of a `ret` of nothing, or the `dst` of
+    /// a call whose value isn't used.
+    Absent,
+    /// An `i32`: a temporary of that type, or a constant.
+    I32,
+    /// An address: a temporary of that type, a slot, a global or a string.
+    Ptr,
+    Const,
+    /// A constant the target takes as an operand of an instruction.
+    Imm,
+    /// A temporary kept in a register.
+    Reg,
+}
+
+/// A rule of a table: the instructions it covers, and the template of what
+/// they're selected as.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rule {
+    pub(crate) kind: Kind,
+    /// The operators of the `Binary`s it covers, or any, if empty.
+    pub(crate) ops: &'static [BinOp],
+    /// What the `Load`s or `Store`s it covers load or store, or any.
+    pub(crate) mem: Option<Mem>,
+    /// The classes each operand is in.
+    pub(crate) when: &'static [(Field, Class)],
+    pub(crate) template: &'static [&'static str],
+}
+
+impl Rule {
+    /// A rule covering every instruction of `kind`.
+    pub(crate) const fn new(
+        kind: Kind,
+        template: &'static [&'static str],
+    ) -> Rule {
+        Rule {
+            kind,
+            ops: &[],
+            mem: None,
+            when: &[],
+            template,
+        }
+    }
+
+    pub(crate) const fn ops(self, ops: &'static [BinOp]) -> Rule {
+        Rule { ops, ..self }
+    }
+
+    pub(crate) const fn mem(self, mem: Mem) -> Rule {
+        Rule {
+            mem: Some(mem),
+            ..self
+        }
+    }
+
+    pub(crate) const fn when(self, when: &'static [(Field, Class)]) -> Rule {
+        Rule { when, ..self }
+    }
+
+    fn covers(&self, selector: &impl Selector, inst: &Inst) -> bool {
+        if self.kind != Kind::of(inst) {
+            return false;
+        }
+        match inst {
+            Inst::Binary { op, .. }
+                if !self.ops.is_empty() && !self.ops.contains(op) =>
+            {
+                return false
+            }
+            Inst::Load { mem, .. } | Inst::Store { mem, .. }
+                if self.mem.is_some_and(|covered| covered != *mem) =>
+            {
+                return false
+            }
+            _ => {}
+        }
+        self.when.iter().all(|&(field, class)| {
+            is_in(selector, field.of(inst).as_ref(), class)
+        })
+    }
+}
+
+/// Whether `operand` is in `class`, as `selector` keeps it.
+fn is_in(
+    selector: &impl Selector,
+    operand: Option<&Operand>,
+    class: Class,
+) -> bool {
+    let Some(operand) = operand else {
+        return class == Class::Absent;
+    };
+    let temp_ty = |temp: Temp| selector.fun().temps[temp.0];
+    match (class, *operand) {
+        (Class::Absent, _) => false,
+        (Class::I32, Operand::Temp(temp)) => temp_ty(temp) == Ty::I32,
+        (Class::I32, operand) => matches!(operand, Operand::Const(_)),
+        (Class::Ptr, Operand::Temp(temp)) => temp_ty(temp) == Ty::Ptr,
+        (Class::Ptr, operand) => !matches!(operand, Operand::Const(_)),
+        (Class::Const, operand) => matches!(operand, Operand::Const(_)),
+        (Class::Imm, Operand::Const(value)) => selector.is_immediate(value),
+        (Class::Imm, _) => false,
+        (Class::Reg, Operand::Temp(temp)) => selector.in_register(temp),
+        (Class::Reg, _) => false,
+    }
+}
+
+/// What a backend writes the templates of its table with.
+pub(crate) trait Selector {
+    /// The function being selected for.
+    fn fun(&self) -> &Fun;
+
+    /// Whether `temp` is kept in a register.
+    fn in_register(&self, _temp: Temp) -> bool {
+        false
+    }
+
+    /// Whether `value` is taken as an operand of an instruction.
+    fn is_immediate(&self, _value: i64) -> bool {
+        true
+    }
+
+    /// What the placeholder `{name:form}` of the template of `inst` is, or
+    /// `{name}`, with an empty `form`.
+    fn placeholder(&mut self, inst: &Inst, name: &str, form: &str) -> String;
+
+    /// Does the action `@name` of the template of `inst`, with `args`.
+    fn action(&mut self, inst: &Inst, name: &str, args: &str);
+
+    /// Writes `line`, of a template.
+    fn emit_line(&mut self, line: String);
+}
+
+/// Writes `inst` as the template of the first rule of `rules` covering it.
+pub(crate) fn select(
+    selector: &mut impl Selector,
+    rules: &[Rule],
+    inst: &Inst,
+) {
+    let rule = rules
+        .iter()
+        .find(|rule| rule.covers(selector, inst))
+        .unwrap_or_else(|| panic!("no rule selects {:?}", inst));
+    let mut written = HashMap::new();
+    for line in rule.template {
+        match line.strip_prefix('@') {
+            Some(action) => {
+                let (name, args) =
+                    action.split_once(' ').unwrap_or((action, ""));
+                let args = expand(selector, inst, args, &mut written);
+                selector.action(inst, name, &args);
+            }
+            None => {
+                let line = expand(selector, inst, line, &mut written);
+                selector.emit_line(line);
+            }
+        }
+    }
+}
+
+/// `text` with its placeholders written, from left to right, unless they
+/// were already, as `written` remembers.
+fn expand(
+    selector: &mut impl Selector,
+    inst: &Inst,
+    text: &str,
+    written: &mut HashMap<String, String>,
+) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let end = start
+            + rest[start..].find('}').unwrap_or_else(|| {
+                panic!("unclosed placeholder in `{}`", text)
+            });
+        out.push_str(&rest[..start]);
+        let placeholder = &rest[start + 1..end];
+        let value = match written.get(placeholder) {
+            Some(value) => value.clone(),
+            None => {
+                let (name, form) =
+                    placeholder.split_once(':').unwrap_or((placeholder, ""));
+                let value = selector.placeholder(inst, name, form);
+                written.insert(placeholder.to_string(), value.clone());
+                value
+            }
+        };
+        out.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{select, Class, Field, Kind, Rule, Selector};
+    use crate::{
+        ast::BinOp,
+        ir::{Fun, Inst, Label, Operand, Temp, Ty},
+    };
+
+    /// A target of a machine with an accumulator, and registers for the
+    /// temporaries below `registers`.
+    struct Accumulator {
+        fun: Fun,
+        registers: usize,
+        lines: Vec<String>,
+    }
+
+    const RULES: &[Rule] = &[
+        Rule::new(Kind::Binary, &["add {lhs}, {rhs}", "@move dst"])
+            .ops(&[BinOp::Add])
+            .when(&[(Field::Lhs, Class::Reg), (Field::Rhs, Class::Imm)]),
+        Rule::new(Kind::Binary, &["@load lhs", "{op} {rhs}", "@move dst"]),
+        Rule::new(Kind::Return, &["ret"])
+            .when(&[(Field::Value, Class::Absent)]),
+        Rule::new(Kind::Return, &["@load value", "ret"]),
+        Rule::new(Kind::Jump, &["jump {label}", "; to {label}"]),
+    ];
+
+    fn fun(temps: usize) -> Fun {
+        Fun {
+            name: "f".into(),
+            params: 0,
+            ret: None,
+            slots: Vec::new(),
+            temps: vec![Ty::I32; temps],
+            labels: 4,
+            code: Vec::new(),
+        }
+    }
+
+    impl Selector for Accumulator {
+        fn fun(&self) -> &Fun {
+            &self.fun
+        }
+
+        fn in_register(&self, temp: Temp) -> bool {
+            temp.0 < self.registers
+        }
+
+        fn is_immediate(&self, value: i64) -> bool {
+            (-128..128).contains(&value)
+        }
+
+        fn placeholder(&mut self, inst: &Inst, name: &str, _: &str) -> String {
+            match (name, inst) {
+                ("op", Inst::Binary { op, .. }) => {
+                    format!("{:?}", op).to_lowercase()
+                }
+                ("label", Inst::Jump(label)) => {
+                    self.lines.push("; a label".into());
+                    format!("L{}", label.0)
+                }
+                _ => match Field::from_name(name).and_then(|f| f.of(inst)) {
+                    Some(Operand::Temp(temp)) => format!("t{}", temp.0),
+                    Some(Operand::Const(value)) => format!("#{}", value),
+                    operand => panic!("{:?} for `{}`", operand, name),
+                },
+            }
+        }
+
+        fn action(&mut self, inst: &Inst, name: &str, args: &str) {
+            let operand = self.placeholder(inst, args, "");
+            match name {
+                "load" => self.lines.push(format!("lda {}", operand)),
+                "move" => self.lines.push(format!("sta {}", operand)),
+                _ => panic!("unknown action `{}`", name),
+            }
+        }
+
+        fn emit_line(&mut self, line: String) {
+            self.lines.push(line);
+        }
+    }
+
+    #[test]
+    fn the_first_rule_covering_an_instruction_is_selected() {
+        let mut target = Accumulator {
+            fun: fun(3),
+            registers: 2,
+            lines: Vec::new(),
+        };
+        let add = |lhs, rhs| Inst::Binary {
+            dst: Temp(2),
+            op: BinOp::Add,
+            lhs,
+            rhs,
+        };
+        for inst in [
+            add(Operand::Temp(Temp(0)), Operand::Const(1)),
+            add(Operand::Temp(Temp(0)), Operand::Const(1000)),
+            add(Operand::Temp(Temp(2)), Operand::Const(1)),
+            Inst::Return(None),
+            Inst::Return(Some(Operand::Temp(Temp(1)))),
+            Inst::Jump(Label(3)),
+        ] {
+            select(&mut target, RULES, &inst);
+        }
+
+        assert_eq!(
+            target.lines,
+            [
+                "add t0, #1",
+                "sta t2",
+                "lda t0",
+                "add #1000",
+                "sta t2",
+                "lda t2",
+                "add #1",
+                "sta t2",
+                "ret",
+                "lda t1",
+                "ret",
+                // A placeholder is written once, however many times it's
+                // in the template.
+                "; a label",
+                "jump L3",
+                "; to L3",
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no rule selects Label(Label(0))")]
+    fn instructions_no_rule_covers_are_bugs_of_the_table() {
+        let mut target = Accumulator {
+            fun: fun(0),
+            registers: 0,
+            lines: Vec::new(),
+        };
+        select(&mut target, RULES, &Inst::Label(Label(0)));
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+
+/// A minimal JSON value, just enough for the machine-readable diagnostic
+/// emitters. Objects keep their keys in insertion order so that the output is
+/// stable and easy to diff.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Constructs an object from a list of key-value pairs.
+    pub(crate) fn object<K: Into<String>>(
+        fields: impl IntoIterator<Item = (K, JsonValue)>,
+    ) -> JsonValue {
+        JsonValue::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.into(), value))
+                .collect(),
+        )
+    }
+}
+
+impl From<bool> for JsonValue {
+    fn from(value: bool) -> JsonValue {
+        JsonValue::Bool(value)
+    }
+}
+
+impl From<usize> for JsonValue {
+    fn from(value: usize) -> JsonValue {
+        JsonValue::Number(value as i64)
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(value: &str) -> JsonValue {
+        JsonValue::String(value.to_owned())
+    }
+}
+
+impl From<String> for JsonValue {
+    fn from(value: String) -> JsonValue {
+        JsonValue::String(value)
+    }
+}
+
+impl<T: Into<JsonValue>> From<Option<T>> for JsonValue {
+    fn from(value: Option<T>) -> JsonValue {
+        value.map_or(JsonValue::Null, Into::into)
+    }
+}
+
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonValue::Null => write!(f, "null"),
+            JsonValue::Bool(value) => write!(f, "{}", value),
+            JsonValue::Number(value) => write!(f, "{}", value),
+            JsonValue::String(value) => write_json_string(f, value),
+            JsonValue::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            JsonValue::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_json_string(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in text.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonValue;
+
+    #[test]
+    fn display_scalars() {
+        assert_eq!(JsonValue::Null.to_string(), "null");
+        assert_eq!(JsonValue::Bool(true).to_string(), "true");
+        assert_eq!(JsonValue::Number(-42).to_string(), "-42");
+        assert_eq!(JsonValue::from("abc").to_string(), "\"abc\"");
+    }
+
+    #[test]
+    fn display_escapes_special_chars_in_strings() {
+        let value = JsonValue::from("a \"quoted\"\\path\n\t\u{1}");
+
+        assert_eq!(value.to_string(), r#""a \"quoted\"\\path\n\t\u0001""#);
+    }
+
+    #[test]
+    fn display_keeps_non_ascii_chars_as_is() {
+        assert_eq!(JsonValue::from("ação").to_string(), "\"ação\"");
+    }
+
+    #[test]
+    fn display_arrays_and_objects() {
+        let value = JsonValue::object(vec![
+            ("a", JsonValue::Array(vec![1usize.into(), 2usize.into()])),
+            ("b", JsonValue::object(Vec::<(&str, JsonValue)>::new())),
+            ("c", None::<bool>.into()),
+        ]);
+
+        assert_eq!(value.to_string(), r#"{"a":[1,2],"b":{},"c":null}"#);
+    }
+
+    #[test]
+    fn object_keeps_insertion_order() {
+        let value = JsonValue::object(vec![
+            ("z", JsonValue::Null),
+            ("a", JsonValue::Null),
+        ]);
+
+        assert_eq!(value.to_string(), r#"{"z":null,"a":null}"#);
+    }
+}
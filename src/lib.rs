@@ -0,0 +1,51 @@
+pub mod api;
+pub mod assembler;
+pub mod ast;
+pub mod bytecode;
+pub mod catalog;
+pub mod ccp;
+pub mod cfg;
+pub mod dce;
+pub mod dot;
+pub mod driver;
+pub mod dump;
+pub mod dwarf;
+pub mod elf;
+pub mod emitter;
+pub mod errors;
+pub mod extensions;
+pub mod fix;
+pub mod fold;
+pub mod frame;
+pub mod hir;
+pub mod ice;
+pub mod inline;
+pub mod interp;
+pub mod ir;
+pub mod isel;
+pub mod json;
+pub mod licm;
+pub mod lints;
+pub mod liveness;
+pub mod llvm;
+pub mod node_map;
+pub mod parser;
+pub mod passes;
+pub mod peephole;
+pub mod printer;
+pub mod regalloc;
+pub mod reparse;
+pub mod runtime;
+pub mod scanner;
+pub mod sema;
+pub mod semantic_tokens;
+pub mod source_map;
+pub mod strength;
+pub mod symbols;
+pub mod tailcall;
+#[cfg(test)]
+mod test_support;
+pub mod types;
+pub mod verify;
+pub mod visit;
+pub mod x86_64;
@@ -0,0 +1,480 @@
+//! Loop-invariant code motion: moving the instructions of a loop that
+//! compute the same value in every iteration to before the loop, so it's
+//! computed once. Most of what it moves is the address math of the arrays
+//! indexed in a loop, e.g. loading the pointer an array parameter is.
+//!
+//! An instruction is invariant in a natural loop (see `cfg`) if it does
+//! nothing but assign its result (see `dce`), and what it reads is assigned
+//! before the loop or by other invariant instructions. A load is only
+//! invariant if it's of a slot the loop doesn't store to and whose address
+//! is only ever loaded from and stored to, as anything else might store to
+//! it through a pointer. The temporary it assigns must be assigned nowhere
+//! else, and not be read before it's assigned in the loop.
+//!
+//! The instructions are moved to the end of the block before the loop, if
+//! the loop's entered from there only, or else to a new block the loop's
+//! entered through. Such an instruction may then be run when the loop's
+//! body isn't, which is fine, as it can't stop the program. Inner loops are
+//! gone through first, so what's invariant in an outer loop too is moved
+//! out of it next.
+
+use crate::{
+    cfg::{BlockId, Cfg, Loop},
+    dce::is_pure,
+    ir::{Fun, Inst, Label, Operand, Program},
+    liveness::temp_liveness,
+};
+
+/// Moves the loop-invariant code of the functions of `program` out of its
+/// loops, returning whether anything changed.
+pub(crate) fn licm_program(program: &mut Program) -> bool {
+    let mut changed = false;
+    for fun in &mut program.funs {
+        changed |= licm_fun(fun);
+    }
+    changed
+}
+
+/// Moves the loop-invariant code of `fun` out of its loops, returning
+/// whether anything changed.
+pub(crate) fn licm_fun(fun: &mut Fun) -> bool {
+    let mut changed = false;
+    // A loop at a time, as moving code out of one changes the graph.
+    while hoist_from_a_loop(fun) {
+        changed = true;
+    }
+    changed
+}
+
+/// Moves the invariant code of the innermost loop of `fun` that has some,
+/// returning whether there was one.
+fn hoist_from_a_loop(fun: &mut Fun) -> bool {
+    let cfg = Cfg::new(fun);
+    let mut loops = cfg.loops();
+    loops.sort_by_key(|lp| lp.blocks.len());
+    let live_in = temp_liveness(fun, &cfg).blocks.live_in;
+
+    let escapes = escaping_slots(fun);
+    let mut defs = vec![0; fun.temps.len()];
+    for inst in &fun.code {
+        if let Some(dst) = inst.dst() {
+            defs[dst.0] += 1;
+        }
+    }
+
+    for lp in &loops {
+        let insts: Vec<usize> = lp
+            .blocks
+            .iter()
+            .flat_map(|block| cfg.blocks[block.0].insts.clone())
+            .collect();
+        let mut stored = vec![false; fun.slots.len()];
+        let mut in_loop = vec![false; fun.temps.len()];
+        for &index in &insts {
+            if let Inst::Store {
+                addr: Operand::Slot(slot),
+                ..
+            } = fun.code[index]
+            {
+                stored[slot] = true;
+            }
+            if let Some(dst) = fun.code[index].dst() {
+                in_loop[dst.0] = true;
+            }
+        }
+
+        // The invariant instructions, each after those whose results it
+        // reads, until no more are found.
+        let mut invariant = Vec::new();
+        let mut is_invariant = vec![false; fun.code.len()];
+        let mut found = true;
+        while found {
+            found = false;
+            for &index in &insts {
+                let inst = &fun.code[index];
+                if is_invariant[index] || !is_pure(inst) {
+                    continue;
+                }
+                let Some(dst) = inst.dst() else {
+                    continue;
+                };
+                if defs[dst.0] != 1 || live_in[lp.header.0].contains(&dst.0) {
+                    continue;
+                }
+                if let Inst::Load { addr, .. } = inst {
+                    match *addr {
+                        Operand::Slot(slot)
+                            if !escapes[slot] && !stored[slot] => {}
+                        _ => continue,
+                    }
+                }
+                // A temporary assigned in the loop is assigned by only one
+                // instruction there, which must be invariant.
+                let reads_invariant =
+                    inst.operands().into_iter().all(|operand| match *operand {
+                        Operand::Temp(temp) => {
+                            !in_loop[temp.0]
+                                || insts.iter().any(|&other| {
+                                    is_invariant[other]
+                                        && fun.code[other].dst() == Some(temp)
+                                })
+                        }
+                        _ => true,
+                    });
+                if reads_invariant {
+                    is_invariant[index] = true;
+                    invariant.push(index);
+                    found = true;
+                }
+            }
+        }
+        if invariant.is_empty() {
+            continue;
+        }
+        let hoisted = invariant
+            .iter()
+            .map(|&index| fun.code[index].clone())
+            .collect();
+        if put_before_loop(fun, &cfg, lp, hoisted, &invariant) {
+            return true;
+        }
+    }
+    false
+}
+
+/// The slots of `fun` whose address is used other than to load from them
+/// and to store to them, e.g. taken into a pointer, which may be stored to
+/// through it anywhere.
+pub(crate) fn escaping_slots(fun: &Fun) -> Vec<bool> {
+    let mut escapes = vec![false; fun.slots.len()];
+    for inst in &fun.code {
+        // Everything but the address of a load or a store.
+        let operands = match inst {
+            Inst::Load { .. } => Vec::new(),
+            Inst::Store { value, .. } => vec![value],
+            _ => inst.operands(),
+        };
+        for operand in operands {
+            if let Operand::Slot(slot) = *operand {
+                escapes[slot] = true;
+            }
+        }
+    }
+    escapes
+}
+
+/// Puts `code` where `lp` is entered, in `fun`, whose control-flow graph is
+/// `cfg`, dropping the instructions at `dropped`, returning whether it
+/// could: a loop that's never entered is left as it is.
+pub(crate) fn put_before_loop(
+    fun: &mut Fun,
+    cfg: &Cfg,
+    lp: &Loop,
+    code: Vec<Inst>,
+    dropped: &[usize],
+) -> bool {
+    let header = &cfg.blocks[lp.header.0];
+    let header_label = header.label.expect("a loop's header is jumped to");
+    let entries: Vec<BlockId> = header
+        .preds
+        .iter()
+        .filter(|pred| !lp.blocks.contains(pred))
+        .copied()
+        .collect();
+
+    // Where the instructions go, with a block of their own if they need.
+    let (at, mut moved) = match entries[..] {
+        [] => return false,
+        [entry] if cfg.blocks[entry.0].succs == [lp.header] => {
+            (cfg.blocks[entry.0].insts.end - 1, code)
+        }
+        _ => {
+            let label = Label(fun.labels);
+            fun.labels += 1;
+            for entry in entries {
+                let last = cfg.blocks[entry.0].insts.end - 1;
+                retarget(&mut fun.code[last], header_label, label);
+            }
+            let mut block = vec![Inst::Label(label)];
+            block.extend(code);
+            block.push(Inst::Jump(header_label));
+            (header.insts.start, block)
+        }
+    };
+
+    let mut is_dropped = vec![false; fun.code.len()];
+    for &index in dropped {
+        is_dropped[index] = true;
+    }
+    let code = std::mem::take(&mut fun.code);
+    for (index, inst) in code.into_iter().enumerate() {
+        if index == at {
+            fun.code.append(&mut moved);
+        }
+        if !is_dropped[index] {
+            fun.code.push(inst);
+        }
+    }
+    true
+}
+
+/// Makes the terminator `inst` go to `to` wherever it goes to `from`.
+fn retarget(inst: &mut Inst, from: Label, to: Label) {
+    let swap = |label: &mut Label| {
+        if *label == from {
+            *label = to;
+        }
+    };
+    match inst {
+        Inst::Jump(label) => swap(label),
+        Inst::Branch {
+            then_label,
+            else_label,
+            ..
+        } => {
+            swap(then_label);
+            swap(else_label);
+        }
+        Inst::Return(_) => {}
+        inst => unreachable!("{:?} isn't a terminator", inst),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::licm_fun;
+    use crate::{
+        ast::BinOp,
+        ir::{
+            dump_ir, Fun, Inst, Label, Mem, Operand, Program, Slot, Temp, Ty,
+        },
+        passes::Pass,
+        sema::Layout,
+        test_support::{accept_source, fun_code, ir_after},
+    };
+
+    #[test]
+    fn array_addresses_are_worked_out_before_loops() {
+        let code = ir_after(&accept_source("sort.cm"), &[Pass::Licm]);
+
+        // The pointer `a` is, and so is `high`, as they aren't assigned in
+        // the loop.
+        assert_eq!(
+            fun_code(&code, "minloc"),
+            "fun @minloc(%0: ptr, %1: i32, %2: i32) -> i32 {\n\
+             \x20 slot $0 a: 8, align 8\n\
+             \x20 slot $1 low: 4, align 4\n\
+             \x20 slot $2 high: 4, align 4\n\
+             \x20 slot $3 i: 4, align 4\n\
+             \x20 slot $4 x: 4, align 4\n\
+             \x20 slot $5 k: 4, align 4\n\
+             \x20 store ptr $0, %0\n\
+             \x20 store i32 $1, %1\n\
+             \x20 store i32 $2, %2\n\
+             \x20 %3: i32 = load i32 $1\n\
+             \x20 store i32 $5, %3\n\
+             \x20 %4: ptr = load ptr $0\n\
+             \x20 %5: i32 = load i32 $1\n\
+             \x20 %6: i32 = mul %5, 4\n\
+             \x20 %7: ptr = offset %4, %6\n\
+             \x20 %8: i32 = load i32 %7\n\
+             \x20 store i32 $4, %8\n\
+             \x20 %9: i32 = load i32 $1\n\
+             \x20 %10: i32 = add %9, 1\n\
+             \x20 store i32 $3, %10\n\
+             \x20 %12: i32 = load i32 $2\n\
+             \x20 %14: ptr = load ptr $0\n\
+             \x20 %21: ptr = load ptr $0\n\
+             \x20 jump L0\n\
+             L0:\n\
+             \x20 %11: i32 = load i32 $3\n\
+             \x20 %13: i32 = lt %11, %12\n\
+             \x20 branch %13, L1, L2\n\
+             L1:\n\
+             \x20 %15: i32 = load i32 $3\n\
+             \x20 %16: i32 = mul %15, 4\n\
+             \x20 %17: ptr = offset %14, %16\n\
+             \x20 %18: i32 = load i32 %17\n\
+             \x20 %19: i32 = load i32 $4\n\
+             \x20 %20: i32 = lt %18, %19\n\
+             \x20 branch %20, L3, L4\n\
+             L3:\n\
+             \x20 %22: i32 = load i32 $3\n\
+             \x20 %23: i32 = mul %22, 4\n\
+             \x20 %24: ptr = offset %21, %23\n\
+             \x20 %25: i32 = load i32 %24\n\
+             \x20 store i32 $4, %25\n\
+             \x20 %26: i32 = load i32 $3\n\
+             \x20 store i32 $5, %26\n\
+             \x20 jump L4\n\
+             L4:\n\
+             \x20 %27: i32 = load i32 $3\n\
+             \x20 %28: i32 = add %27, 1\n\
+             \x20 store i32 $3, %28\n\
+             \x20 jump L0\n\
+             L2:\n\
+             \x20 %29: i32 = load i32 $5\n\
+             \x20 ret %29\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn code_without_loops_is_left_as_it_is() {
+        let source = accept_source("gcd.cm");
+        assert_eq!(ir_after(&source, &[Pass::Licm]), ir_after(&source, &[]));
+    }
+
+    #[test]
+    fn only_what_the_loop_never_changes_is_moved() {
+        // `s` may be assigned through `p`, and `n / d` may be a division by
+        // zero. `n * 4` is moved out of both loops.
+        let code = ir_after(
+            "int f(int n, int d) {\n\
+             \x20   int i; int j; int s; int *p;\n\
+             \x20   s = 0; p = &s;\n\
+             \x20   i = 0;\n\
+             \x20   while (i < n) {\n\
+             \x20       j = 0;\n\
+             \x20       do { s = s + n / d + n * 4; j = j + 1; } while (j < n * 2);\n\
+             \x20       i = i + *p;\n\
+             \x20   }\n\
+             \x20   return s;\n\
+             }",
+            &[Pass::Licm],
+        );
+
+        assert_eq!(
+            code,
+            "fun @f(%0: i32, %1: i32) -> i32 {\n\
+             \x20 slot $0 n: 4, align 4\n\
+             \x20 slot $1 d: 4, align 4\n\
+             \x20 slot $2 i: 4, align 4\n\
+             \x20 slot $3 j: 4, align 4\n\
+             \x20 slot $4 s: 4, align 4\n\
+             \x20 slot $5 p: 8, align 8\n\
+             \x20 store i32 $0, %0\n\
+             \x20 store i32 $1, %1\n\
+             \x20 store i32 $4, 0\n\
+             \x20 store ptr $5, $4\n\
+             \x20 store i32 $2, 0\n\
+             \x20 %3: i32 = load i32 $0\n\
+             \x20 %6: i32 = load i32 $0\n\
+             \x20 %7: i32 = load i32 $1\n\
+             \x20 %10: i32 = load i32 $0\n\
+             \x20 %11: i32 = mul %10, 4\n\
+             \x20 %16: i32 = load i32 $0\n\
+             \x20 %17: i32 = mul %16, 2\n\
+             \x20 %20: ptr = load ptr $5\n\
+             \x20 jump L0\n\
+             L0:\n\
+             \x20 %2: i32 = load i32 $2\n\
+             \x20 %4: i32 = lt %2, %3\n\
+             \x20 branch %4, L1, L2\n\
+             L1:\n\
+             \x20 store i32 $3, 0\n\
+             \x20 jump L3\n\
+             L3:\n\
+             \x20 %5: i32 = load i32 $4\n\
+             \x20 %8: i32 = div %6, %7\n\
+             \x20 %9: i32 = add %5, %8\n\
+             \x20 %12: i32 = add %9, %11\n\
+             \x20 store i32 $4, %12\n\
+             \x20 %13: i32 = load i32 $3\n\
+             \x20 %14: i32 = add %13, 1\n\
+             \x20 store i32 $3, %14\n\
+             \x20 jump L4\n\
+             L4:\n\
+             \x20 %15: i32 = load i32 $3\n\
+             \x20 %18: i32 = lt %15, %17\n\
+             \x20 branch %18, L3, L5\n\
+             L5:\n\
+             \x20 %19: i32 = load i32 $2\n\
+             \x20 %21: i32 = load i32 %20\n\
+             \x20 %22: i32 = add %19, %21\n\
+             \x20 store i32 $2, %22\n\
+             \x20 jump L0\n\
+             L2:\n\
+             \x20 %23: i32 = load i32 $4\n\
+             \x20 ret %23\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn loops_entered_from_several_blocks_get_one_to_be_entered_through() {
+        let (t0, t1, t2) = (Temp(0), Temp(1), Temp(2));
+        let mut fun = Fun {
+            name: "f".into(),
+            params: 1,
+            ret: None,
+            slots: vec![Slot {
+                name: None,
+                layout: Layout { size: 4, align: 4 },
+            }],
+            temps: vec![Ty::I32; 3],
+            labels: 3,
+            code: vec![
+                Inst::Branch {
+                    cond: Operand::Temp(t0),
+                    then_label: Label(0),
+                    else_label: Label(1),
+                },
+                Inst::Label(Label(1)),
+                Inst::Jump(Label(0)),
+                Inst::Label(Label(0)),
+                Inst::Binary {
+                    dst: t1,
+                    op: BinOp::Mul,
+                    lhs: Operand::Temp(t0),
+                    rhs: Operand::Const(4),
+                },
+                Inst::Store {
+                    mem: Mem::I32,
+                    addr: Operand::Slot(0),
+                    value: Operand::Temp(t1),
+                },
+                Inst::Load {
+                    dst: t2,
+                    mem: Mem::I32,
+                    addr: Operand::Slot(0),
+                },
+                Inst::Branch {
+                    cond: Operand::Temp(t2),
+                    then_label: Label(0),
+                    else_label: Label(2),
+                },
+                Inst::Label(Label(2)),
+                Inst::Return(None),
+            ],
+        };
+
+        assert!(licm_fun(&mut fun));
+        assert!(!licm_fun(&mut fun));
+
+        let program = Program {
+            globals: Vec::new(),
+            strings: Vec::new(),
+            funs: vec![fun],
+            file: None,
+        };
+        assert_eq!(
+            dump_ir(&program),
+            "fun @f(%0: i32) {\n\
+             \x20 slot $0: 4, align 4\n\
+             \x20 branch %0, L3, L1\n\
+             L1:\n\
+             \x20 jump L3\n\
+             L3:\n\
+             \x20 %1: i32 = mul %0, 4\n\
+             \x20 jump L0\n\
+             L0:\n\
+             \x20 store i32 $0, %1\n\
+             \x20 %2: i32 = load i32 $0\n\
+             \x20 branch %2, L0, L2\n\
+             L2:\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use crate::errors::Severity;
+use std::collections::HashMap;
+
+/// A lint is a named kind of warning that users can switch on or off with
+/// command line flags (e.g. `-A shadowing`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Lint {
+    /// A local variable or parameter that is never used.
+    UnusedVariable,
+    /// A declaration that hides another one from an enclosing scope.
+    Shadowing,
+    /// An assignment as the condition of an `if` or a loop, e.g.
+    /// `if (x = 1)`, where `==` was likely meant.
+    AssignInCondition,
+    /// A declaration after `main`, which the reference grammar of C- asks to
+    /// be the last one.
+    MainNotLast,
+    /// Statements that never run, e.g. after a `return`.
+    UnreachableCode,
+    /// A value assigned to a local variable that's never read, e.g. the
+    /// first one in `x = f(); x = 0;`.
+    DeadStore,
+}
+
+impl Lint {
+    pub(crate) const ALL: &'static [Lint] = &[
+        Lint::UnusedVariable,
+        Lint::Shadowing,
+        Lint::AssignInCondition,
+        Lint::MainNotLast,
+        Lint::UnreachableCode,
+        Lint::DeadStore,
+    ];
+
+    /// The name used to refer to the lint in the command line.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::Shadowing => "shadowing",
+            Lint::AssignInCondition => "assign-in-condition",
+            Lint::MainNotLast => "main-not-last",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::DeadStore => "dead-store",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.iter().copied().find(|lint| lint.name() == name)
+    }
+
+    /// The level the lint has unless a flag says otherwise.
+    pub(crate) fn default_level(self) -> LintLevel {
+        match self {
+            Lint::UnusedVariable
+            | Lint::AssignInCondition
+            | Lint::UnreachableCode => LintLevel::Warn,
+            // Shadowing is legitimate C, so it's only reported on request.
+            Lint::Shadowing => LintLevel::Allow,
+            // Only the reference grammar asks for it; C doesn't.
+            Lint::MainNotLast => LintLevel::Allow,
+            // Assigning a value just in case, e.g. `x = 0;` before a loop
+            // that assigns `x` anyway, is common and harmless.
+            Lint::DeadStore => LintLevel::Allow,
+        }
+    }
+}
+
+/// What to do with the diagnostics of a lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LintLevel {
+    /// Drop them.
+    Allow,
+    /// Report them as warnings.
+    Warn,
+    /// Report them as errors.
+    Deny,
+}
+
+/// The warning settings of a compilation, as given by `-W <lint>`,
+/// `-A <lint>`, `-D <lint>` and `-W error` flags.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct WarningConfig {
+    levels: HashMap<Lint, LintLevel>,
+    /// Whether every warning is reported as an error instead.
+    warnings_as_errors: bool,
+}
+
+impl WarningConfig {
+    pub(crate) fn new() -> WarningConfig {
+        WarningConfig::default()
+    }
+
+    pub(crate) fn set_level(&mut self, lint: Lint, level: LintLevel) {
+        self.levels.insert(lint, level);
+    }
+
+    pub(crate) fn set_warnings_as_errors(&mut self, warnings_as_errors: bool) {
+        self.warnings_as_errors = warnings_as_errors;
+    }
+
+    pub(crate) fn level(&self, lint: Lint) -> LintLevel {
+        self.levels
+            .get(&lint)
+            .copied()
+            .unwrap_or_else(|| lint.default_level())
+    }
+
+    /// Applies the settings to a diagnostic of `severity`, which belongs to
+    /// `lint` if it has one. Returns the severity the diagnostic should be
+    /// reported with, or `None` if it shouldn't be reported at all.
+    pub(crate) fn adjust_severity(
+        &self,
+        lint: Option<Lint>,
+        severity: Severity,
+    ) -> Option<Severity> {
+        let severity = match lint.map(|lint| self.level(lint)) {
+            Some(LintLevel::Allow) => return None,
+            Some(LintLevel::Warn) => Severity::Warning,
+            Some(LintLevel::Deny) => Severity::Error,
+            None => severity,
+        };
+
+        if self.warnings_as_errors && severity == Severity::Warning {
+            Some(Severity::Error)
+        } else {
+            Some(severity)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lint, LintLevel, WarningConfig};
+    use crate::errors::Severity;
+
+    #[test]
+    fn lint_names_round_trip() {
+        for &lint in Lint::ALL {
+            assert_eq!(Lint::from_name(lint.name()), Some(lint));
+        }
+
+        assert_eq!(
+            Lint::from_name("unused-variable"),
+            Some(Lint::UnusedVariable)
+        );
+        assert_eq!(Lint::from_name("no-such-lint"), None);
+    }
+
+    #[test]
+    fn default_config_uses_default_levels() {
+        let config = WarningConfig::new();
+
+        assert_eq!(config.level(Lint::UnusedVariable), LintLevel::Warn);
+        assert_eq!(config.level(Lint::Shadowing), LintLevel::Allow);
+    }
+
+    #[test]
+    fn set_level_overrides_default_level() {
+        let mut config = WarningConfig::new();
+
+        config.set_level(Lint::UnusedVariable, LintLevel::Allow);
+        config.set_level(Lint::Shadowing, LintLevel::Warn);
+
+        assert_eq!(config.level(Lint::UnusedVariable), LintLevel::Allow);
+        assert_eq!(config.level(Lint::Shadowing), LintLevel::Warn);
+    }
+
+    #[test]
+    fn allowed_lints_are_dropped() {
+        let config = WarningConfig::new();
+
+        assert_eq!(
+            config.adjust_severity(Some(Lint::Shadowing), Severity::Warning),
+            None
+        );
+    }
+
+    #[test]
+    fn lint_level_decides_severity() {
+        let mut config = WarningConfig::new();
+        config.set_level(Lint::Shadowing, LintLevel::Deny);
+
+        assert_eq!(
+            config
+                .adjust_severity(Some(Lint::UnusedVariable), Severity::Warning),
+            Some(Severity::Warning)
+        );
+        assert_eq!(
+            config.adjust_severity(Some(Lint::Shadowing), Severity::Warning),
+            Some(Severity::Error)
+        );
+    }
+
+    #[test]
+    fn warnings_as_errors_promotes_every_warning() {
+        let mut config = WarningConfig::new();
+        config.set_warnings_as_errors(true);
+
+        assert_eq!(
+            config
+                .adjust_severity(Some(Lint::UnusedVariable), Severity::Warning),
+            Some(Severity::Error)
+        );
+        assert_eq!(
+            config.adjust_severity(None, Severity::Warning),
+            Some(Severity::Error)
+        );
+        assert_eq!(
+            config.adjust_severity(None, Severity::Note),
+            Some(Severity::Note)
+        );
+        assert_eq!(
+            config.adjust_severity(Some(Lint::Shadowing), Severity::Warning),
+            None
+        );
+    }
+
+    #[test]
+    fn non_lint_diagnostics_keep_their_severity() {
+        let config = WarningConfig::new();
+
+        for severity in [
+            Severity::Fatal,
+            Severity::Error,
+            Severity::Warning,
+            Severity::Note,
+            Severity::Help,
+        ] {
+            assert_eq!(config.adjust_severity(None, severity), Some(severity));
+        }
+    }
+}
@@ -0,0 +1,861 @@
+//! Liveness: at each point of a function, the variables whose values may
+//! still be read before they're assigned again.
+//!
+//! It's solved backwards over a graph of blocks of code, each with the
+//! variables it reads before it assigns them and those it assigns, from
+//! what's live at the end of each block, the union of what's live at the
+//! start of the blocks after it, to what's live at its start. Blocks are
+//! gone through again until nothing changes, as what's read in an iteration
+//! of a loop is live at the end of the one before.
+//!
+//! Two analyses are solved this way:
+//!
+//! - The temporaries of a function of the IR, over its control-flow graph
+//!   (see `cfg`), for the interval each is live in, which register
+//!   allocation works from, and for dead code elimination.
+//! - The local variables of a function of the HIR, over a graph of the
+//!   reads and the assignments of the variables in it, for the dead-store
+//!   lint: an assignment to a variable that isn't live right after it is a
+//!   dead store, as its value is never read. Only the variables that are
+//!   single values and whose address is never taken are tracked. An element
+//!   of an array or a field of a struct may be read through another name,
+//!   e.g. a pointer, so their stores are never taken for dead.
+
+use crate::{
+    ast::UnOp,
+    cfg::Cfg,
+    hir::{Def, Expr, ExprKind, Fun, Stmt, StmtKind},
+    ir::{self, Inst, Operand, Temp},
+    source_map::Span,
+    types::Type,
+};
+use std::collections::BTreeSet;
+
+/// The indices of the variables that are live, e.g. of the temporaries of
+/// a function.
+pub(crate) type Live = BTreeSet<usize>;
+
+/// A block of a graph to solve liveness over.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct FlowBlock {
+    /// The blocks control may go to from it.
+    pub(crate) succs: Vec<usize>,
+    /// The variables it reads before assigning them.
+    pub(crate) uses: Live,
+    /// The variables it assigns.
+    pub(crate) defs: Live,
+}
+
+/// What's live at the start and at the end of each block of a graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BlockLiveness {
+    pub(crate) live_in: Vec<Live>,
+    pub(crate) live_out: Vec<Live>,
+}
+
+/// Solves liveness over `blocks`, where nothing's live after the blocks
+/// without successors.
+pub(crate) fn solve(blocks: &[FlowBlock]) -> BlockLiveness {
+    let mut preds = vec![Vec::new(); blocks.len()];
+    for (index, block) in blocks.iter().enumerate() {
+        for &succ in &block.succs {
+            preds[succ].push(index);
+        }
+    }
+    let mut live_in = vec![Live::new(); blocks.len()];
+    let mut live_out = vec![Live::new(); blocks.len()];
+    // Last first, as code mostly goes forwards, and a block is gone
+    // through again whenever what's live at the start of a successor
+    // changes.
+    let mut worklist: Vec<usize> = (0..blocks.len()).collect();
+    let mut queued = vec![true; blocks.len()];
+    while let Some(index) = worklist.pop() {
+        queued[index] = false;
+        let block = &blocks[index];
+        let out: Live = block
+            .succs
+            .iter()
+            .flat_map(|&succ| live_in[succ].iter().copied())
+            .collect();
+        let mut live: Live = out.difference(&block.defs).copied().collect();
+        live.extend(block.uses.iter().copied());
+        live_out[index] = out;
+        if live != live_in[index] {
+            live_in[index] = live;
+            for &pred in &preds[index] {
+                if !queued[pred] {
+                    queued[pred] = true;
+                    worklist.push(pred);
+                }
+            }
+        }
+    }
+    BlockLiveness { live_in, live_out }
+}
+
+/// The liveness of the temporaries of a function of the IR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TempLiveness {
+    /// By the blocks of its control-flow graph.
+    pub(crate) blocks: BlockLiveness,
+    /// The temporaries live before each instruction.
+    pub(crate) insts: Vec<Live>,
+}
+
+/// Solves the liveness of the temporaries of `fun`, whose control-flow
+/// graph is `cfg`.
+pub(crate) fn temp_liveness(fun: &ir::Fun, cfg: &Cfg) -> TempLiveness {
+    let flow: Vec<FlowBlock> = cfg
+        .blocks
+        .iter()
+        .map(|block| {
+            let mut flow = FlowBlock {
+                succs: block.succs.iter().map(|succ| succ.0).collect(),
+                ..FlowBlock::default()
+            };
+            for inst in fun.code[block.insts.clone()].iter().rev() {
+                let (uses, def) = uses_and_def(inst);
+                if let Some(def) = def {
+                    flow.uses.remove(&def.0);
+                    flow.defs.insert(def.0);
+                }
+                flow.uses.extend(uses.iter().map(|temp| temp.0));
+            }
+            flow
+        })
+        .collect();
+    let blocks = solve(&flow);
+
+    let mut insts = vec![Live::new(); fun.code.len()];
+    for (block, live_out) in cfg.blocks.iter().zip(&blocks.live_out) {
+        let mut live = live_out.clone();
+        for index in block.insts.clone().rev() {
+            let (uses, def) = uses_and_def(&fun.code[index]);
+            if let Some(def) = def {
+                live.remove(&def.0);
+            }
+            live.extend(uses.iter().map(|temp| temp.0));
+            insts[index] = live.clone();
+        }
+    }
+    TempLiveness { blocks, insts }
+}
+
+/// The indices of the temporaries live before each instruction of `fun`,
+/// whose control-flow graph is `cfg`.
+pub(crate) fn live_in(fun: &ir::Fun, cfg: &Cfg) -> Vec<Live> {
+    temp_liveness(fun, cfg).insts
+}
+
+/// The instructions from `start` to `end`, both included, in which a
+/// temporary is live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Interval {
+    pub(crate) temp: Temp,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+/// The interval of each temporary of `fun` that's ever live or assigned,
+/// in no particular order: the smallest range of instructions covering
+/// where it's assigned and where it's live, so a temporary whose value goes
+/// round a loop is live for the whole loop.
+pub(crate) fn live_intervals(fun: &ir::Fun) -> Vec<Interval> {
+    let live_in = live_in(fun, &Cfg::new(fun));
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; fun.temps.len()];
+    let mut extend = |temp: usize, index: usize| {
+        let range = ranges[temp].get_or_insert((index, index));
+        range.0 = range.0.min(index);
+        range.1 = range.1.max(index);
+    };
+    for (index, inst) in fun.code.iter().enumerate() {
+        for &temp in &live_in[index] {
+            extend(temp, index);
+        }
+        if let (_, Some(def)) = uses_and_def(inst) {
+            extend(def.0, index);
+        }
+    }
+    ranges
+        .into_iter()
+        .enumerate()
+        .filter_map(|(temp, range)| {
+            range.map(|(start, end)| Interval {
+                temp: Temp(temp),
+                start,
+                end,
+            })
+        })
+        .collect()
+}
+
+/// The temporaries `inst` reads, and the one it assigns, if any.
+pub(crate) fn uses_and_def(inst: &Inst) -> (Vec<Temp>, Option<Temp>) {
+    let uses = inst
+        .operands()
+        .into_iter()
+        .filter_map(|operand| match *operand {
+            Operand::Temp(temp) => Some(temp),
+            _ => None,
+        })
+        .collect();
+    (uses, inst.dst())
+}
+
+/// An assignment whose value is never read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DeadStore {
+    /// The index of the variable in the `locals` of the function.
+    pub(crate) local: usize,
+    /// The assignment.
+    pub(crate) span: Span,
+}
+
+/// The assignments in `fun` whose values are never read, in no particular
+/// order.
+pub(crate) fn dead_stores(fun: &Fun) -> Vec<DeadStore> {
+    let mut tracked: Vec<_> = fun
+        .locals
+        .iter()
+        .map(|local| {
+            matches!(
+                local.ty.unqualified(),
+                Type::Int | Type::Char | Type::Pointer(_)
+            )
+        })
+        .collect();
+    for stmt in &fun.body {
+        untrack_addressed_in_stmt(stmt, &mut tracked);
+    }
+
+    let mut graph = FlowGraph {
+        tracked,
+        blocks: vec![GraphBlock::default()],
+        current: 0,
+        breaks: Vec::new(),
+        continues: Vec::new(),
+    };
+    graph.stmts(&fun.body);
+
+    let flow: Vec<FlowBlock> = graph
+        .blocks
+        .iter()
+        .map(|block| {
+            let mut flow = FlowBlock {
+                succs: block.succs.clone(),
+                ..FlowBlock::default()
+            };
+            for event in block.events.iter().rev() {
+                match *event {
+                    Event::Read(local) => {
+                        flow.uses.insert(local);
+                    }
+                    Event::Write { local, .. } => {
+                        flow.uses.remove(&local);
+                        flow.defs.insert(local);
+                    }
+                }
+            }
+            flow
+        })
+        .collect();
+    let liveness = solve(&flow);
+
+    let mut dead_stores = Vec::new();
+    for (block, live_out) in graph.blocks.iter().zip(liveness.live_out) {
+        let mut live = live_out;
+        for event in block.events.iter().rev() {
+            match *event {
+                Event::Read(local) => {
+                    live.insert(local);
+                }
+                Event::Write { local, span } => {
+                    if !live.remove(&local) {
+                        dead_stores.push(DeadStore { local, span });
+                    }
+                }
+            }
+        }
+    }
+    dead_stores
+}
+
+/// What the code of a function does to a local variable, in the order it's
+/// run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Read(usize),
+    /// An assignment to a tracked variable.
+    Write {
+        local: usize,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+struct GraphBlock {
+    events: Vec<Event>,
+    succs: Vec<usize>,
+}
+
+/// Builds the graph of the reads and the assignments of the local variables
+/// of a function, going through its body forwards.
+struct FlowGraph {
+    /// Whether each variable is tracked.
+    tracked: Vec<bool>,
+    /// The blocks, the one the function starts at first.
+    blocks: Vec<GraphBlock>,
+    /// The block what's gone through next goes at the end of.
+    current: usize,
+    /// Where a `break` in each loop or `switch` the statement being gone
+    /// through is in goes to, innermost last.
+    breaks: Vec<usize>,
+    /// Where a `continue` in each loop the statement being gone through is
+    /// in goes to, innermost last.
+    continues: Vec<usize>,
+}
+
+impl FlowGraph {
+    fn new_block(&mut self) -> usize {
+        self.blocks.push(GraphBlock::default());
+        self.blocks.len() - 1
+    }
+
+    fn edge(&mut self, from: usize, to: usize) {
+        self.blocks[from].succs.push(to);
+    }
+
+    /// Goes on at the start of `block`, which the current one goes to.
+    fn go_to(&mut self, block: usize) {
+        let current = self.current;
+        self.edge(current, block);
+        self.current = block;
+    }
+
+    /// Goes on at a new block, which nothing goes to yet, after control
+    /// leaves the current one, e.g. at a `return`.
+    fn leave(&mut self) {
+        self.current = self.new_block();
+    }
+
+    fn event(&mut self, event: Event) {
+        self.blocks[self.current].events.push(event);
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::Empty => {}
+            StmtKind::Block(stmts) => self.stmts(stmts),
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond);
+                let at_cond = self.current;
+                let join = self.new_block();
+                let then_block = self.new_block();
+                self.go_to(then_block);
+                self.stmt(then_branch);
+                self.go_to(join);
+                match else_branch {
+                    Some(else_branch) => {
+                        let else_block = self.new_block();
+                        self.current = at_cond;
+                        self.go_to(else_block);
+                        self.stmt(else_branch);
+                        self.go_to(join);
+                    }
+                    None => {
+                        self.edge(at_cond, join);
+                        self.current = join;
+                    }
+                }
+            }
+            StmtKind::While { cond, body } => {
+                let head = self.new_block();
+                let exit = self.new_block();
+                let body_block = self.new_block();
+                self.go_to(head);
+                self.expr(cond);
+                let at_cond = self.current;
+                self.edge(at_cond, exit);
+                self.go_to(body_block);
+                self.in_loop(exit, head, |this| this.stmt(body));
+                self.go_to(head);
+                self.current = exit;
+            }
+            StmtKind::DoWhile { body, cond } => {
+                let body_block = self.new_block();
+                let at_cond = self.new_block();
+                let exit = self.new_block();
+                self.go_to(body_block);
+                self.in_loop(exit, at_cond, |this| this.stmt(body));
+                self.go_to(at_cond);
+                self.expr(cond);
+                self.edge(self.current, body_block);
+                self.go_to(exit);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                self.opt_expr(init);
+                let head = self.new_block();
+                let at_step = self.new_block();
+                let exit = self.new_block();
+                let body_block = self.new_block();
+                self.go_to(head);
+                // Without a condition, the loop is only left by a `break`.
+                if let Some(cond) = cond {
+                    self.expr(cond);
+                    self.edge(self.current, exit);
+                }
+                self.go_to(body_block);
+                self.in_loop(exit, at_step, |this| this.stmt(body));
+                self.go_to(at_step);
+                self.opt_expr(step);
+                self.go_to(head);
+                self.current = exit;
+            }
+            StmtKind::Switch { cond, cases } => {
+                self.expr(cond);
+                let at_cond = self.current;
+                let exit = self.new_block();
+                // Without a `default`, none of the cases may run.
+                if cases.iter().all(|case| case.value.is_some()) {
+                    self.edge(at_cond, exit);
+                }
+                // A case goes on to the next one, the last one to what's
+                // after the `switch`.
+                self.breaks.push(exit);
+                for case in cases {
+                    let case_block = self.new_block();
+                    self.go_to(case_block);
+                    self.edge(at_cond, case_block);
+                    self.stmts(&case.stmts);
+                }
+                self.breaks.pop();
+                self.go_to(exit);
+            }
+            StmtKind::Break => {
+                if let Some(&exit) = self.breaks.last() {
+                    self.edge(self.current, exit);
+                }
+                self.leave();
+            }
+            StmtKind::Continue => {
+                if let Some(&next) = self.continues.last() {
+                    self.edge(self.current, next);
+                }
+                self.leave();
+            }
+            StmtKind::Return(value) => {
+                self.opt_expr(value);
+                self.leave();
+            }
+        }
+    }
+
+    /// Goes through the body of a loop, with `exit` where a `break` goes
+    /// to and `next` where a `continue` goes to.
+    fn in_loop(
+        &mut self,
+        exit: usize,
+        next: usize,
+        body: impl FnOnce(&mut Self),
+    ) {
+        self.breaks.push(exit);
+        self.continues.push(next);
+        body(self);
+        self.breaks.pop();
+        self.continues.pop();
+    }
+
+    fn opt_expr(&mut self, expr: &Option<Expr>) {
+        if let Some(expr) = expr {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Number(_)
+            | ExprKind::Char(_)
+            | ExprKind::Str(_)
+            | ExprKind::Error => {}
+            ExprKind::Var(def) => {
+                if let Def::Local(local) = *def {
+                    self.event(Event::Read(local));
+                }
+            }
+            ExprKind::Index { array, index } => {
+                self.expr(array);
+                self.expr(index);
+            }
+            ExprKind::Member { base, .. } => self.expr(base),
+            ExprKind::Call { args, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Assign { target, value } => {
+                // The value is worked out before it's stored.
+                self.expr(value);
+                match target.kind {
+                    ExprKind::Var(Def::Local(local)) => {
+                        if self.tracked[local] {
+                            self.event(Event::Write {
+                                local,
+                                span: expr.span,
+                            });
+                        }
+                    }
+                    ExprKind::Var(_) => {}
+                    _ => self.expr(target),
+                }
+            }
+            ExprKind::Unary { operand, .. } => self.expr(operand),
+            ExprKind::Binary { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::Conditional {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                self.expr(cond);
+                let at_cond = self.current;
+                let join = self.new_block();
+                for branch in [then_expr, else_expr] {
+                    let block = self.new_block();
+                    self.current = at_cond;
+                    self.go_to(block);
+                    self.expr(branch);
+                    self.go_to(join);
+                }
+            }
+        }
+    }
+}
+
+/// Stops tracking the variables whose address is taken in `stmt`.
+fn untrack_addressed_in_stmt(stmt: &Stmt, tracked: &mut [bool]) {
+    let mut exprs = Vec::new();
+    let mut stmts = Vec::new();
+    match &stmt.kind {
+        StmtKind::Expr(expr) => exprs.push(expr),
+        StmtKind::Empty | StmtKind::Break | StmtKind::Continue => {}
+        StmtKind::Block(block) => stmts.extend(block),
+        StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            exprs.push(cond);
+            stmts.push(then_branch);
+            stmts.extend(else_branch.as_deref());
+        }
+        StmtKind::While { cond, body } | StmtKind::DoWhile { body, cond } => {
+            exprs.push(cond);
+            stmts.push(body);
+        }
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            exprs.extend([init, cond, step].into_iter().flatten());
+            stmts.push(body);
+        }
+        StmtKind::Switch { cond, cases } => {
+            exprs.push(cond);
+            stmts.extend(cases.iter().flat_map(|case| &case.stmts));
+        }
+        StmtKind::Return(value) => exprs.extend(value),
+    }
+    for expr in exprs {
+        untrack_addressed_in_expr(expr, tracked);
+    }
+    for stmt in stmts {
+        untrack_addressed_in_stmt(stmt, tracked);
+    }
+}
+
+fn untrack_addressed_in_expr(expr: &Expr, tracked: &mut [bool]) {
+    match &expr.kind {
+        ExprKind::Number(_)
+        | ExprKind::Char(_)
+        | ExprKind::Str(_)
+        | ExprKind::Var(_)
+        | ExprKind::Error => {}
+        ExprKind::Unary {
+            op: UnOp::AddrOf,
+            operand,
+        } => {
+            if let ExprKind::Var(Def::Local(local)) = operand.kind {
+                tracked[local] = false;
+            }
+            untrack_addressed_in_expr(operand, tracked);
+        }
+        ExprKind::Unary { operand, .. }
+        | ExprKind::Member { base: operand, .. } => {
+            untrack_addressed_in_expr(operand, tracked)
+        }
+        ExprKind::Index { array, index } => {
+            untrack_addressed_in_expr(array, tracked);
+            untrack_addressed_in_expr(index, tracked);
+        }
+        ExprKind::Call { args, .. } => {
+            for arg in args {
+                untrack_addressed_in_expr(arg, tracked);
+            }
+        }
+        ExprKind::Assign {
+            target: lhs,
+            value: rhs,
+        }
+        | ExprKind::Binary { lhs, rhs, .. } => {
+            untrack_addressed_in_expr(lhs, tracked);
+            untrack_addressed_in_expr(rhs, tracked);
+        }
+        ExprKind::Conditional {
+            cond,
+            then_expr,
+            else_expr,
+        } => {
+            untrack_addressed_in_expr(cond, tracked);
+            untrack_addressed_in_expr(then_expr, tracked);
+            untrack_addressed_in_expr(else_expr, tracked);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dead_stores, solve, temp_liveness, FlowBlock, Live};
+    use crate::{
+        cfg::Cfg,
+        extensions::Extensions,
+        ir::{Fun, Inst, Label, Operand, Temp, Ty},
+        parser::ParseResult,
+        sema::{check_program, Builtin},
+        test_support::parse_source,
+    };
+
+    /// The dead stores in the functions of `source`, as their text.
+    fn dead(source: &str) -> Vec<String> {
+        let (
+            source_file,
+            ParseResult {
+                program, diag_bag, ..
+            },
+        ) = parse_source(source, Extensions::all());
+        assert!(!diag_bag.has_errors(), "syntax errors in {:?}", source);
+        let hir = check_program(&program, &Builtin::ALL).hir;
+
+        let mut stores: Vec<_> = hir
+            .funs
+            .iter()
+            .flat_map(dead_stores)
+            .map(|store| store.span)
+            .collect();
+        stores.sort_by_key(|span| span.start);
+        stores
+            .into_iter()
+            .map(|span| source_file.span_to_snippet(span).to_string())
+            .collect()
+    }
+
+    fn live<const N: usize>(vars: [usize; N]) -> Live {
+        vars.into_iter().collect()
+    }
+
+    #[test]
+    fn what_is_read_in_a_loop_is_live_all_round_it() {
+        // 0: v0 = ...
+        // 1: loop head, reads v0, goes to 2 or 3
+        // 2: reads v0 and v1, assigns v0, goes back to 1
+        // 3: exit
+        let blocks = [
+            FlowBlock {
+                succs: vec![1],
+                uses: live([]),
+                defs: live([0]),
+            },
+            FlowBlock {
+                succs: vec![2, 3],
+                uses: live([0]),
+                defs: live([]),
+            },
+            FlowBlock {
+                succs: vec![1],
+                uses: live([0, 1]),
+                defs: live([0]),
+            },
+            FlowBlock::default(),
+        ];
+
+        let liveness = solve(&blocks);
+
+        assert_eq!(
+            liveness.live_in,
+            [live([1]), live([0, 1]), live([0, 1]), live([])]
+        );
+        assert_eq!(
+            liveness.live_out,
+            [live([0, 1]), live([0, 1]), live([0, 1]), live([])]
+        );
+    }
+
+    #[test]
+    fn temps_are_live_by_block_and_by_instruction() {
+        let (t0, t1) = (Temp(0), Temp(1));
+        let fun = Fun {
+            name: "f".into(),
+            params: 0,
+            ret: Some(Ty::I32),
+            slots: Vec::new(),
+            temps: vec![Ty::I32, Ty::I32],
+            labels: 3,
+            code: vec![
+                Inst::Copy {
+                    dst: t0,
+                    src: Operand::Const(1),
+                },
+                Inst::Branch {
+                    cond: Operand::Temp(t0),
+                    then_label: Label(0),
+                    else_label: Label(1),
+                },
+                Inst::Label(Label(0)),
+                Inst::Copy {
+                    dst: t1,
+                    src: Operand::Const(2),
+                },
+                Inst::Jump(Label(2)),
+                Inst::Label(Label(1)),
+                Inst::Copy {
+                    dst: t1,
+                    src: Operand::Temp(t0),
+                },
+                Inst::Jump(Label(2)),
+                Inst::Label(Label(2)),
+                Inst::Return(Some(Operand::Temp(t1))),
+            ],
+        };
+
+        let liveness = temp_liveness(&fun, &Cfg::new(&fun));
+
+        assert_eq!(
+            liveness.blocks.live_in,
+            [live([]), live([]), live([0]), live([1])]
+        );
+        assert_eq!(
+            liveness.blocks.live_out,
+            [live([0]), live([1]), live([1]), live([])]
+        );
+        assert_eq!(
+            liveness.insts,
+            [
+                live([]),
+                live([0]),
+                live([]),
+                live([]),
+                live([1]),
+                live([0]),
+                live([0]),
+                live([1]),
+                live([1]),
+                live([1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn stores_overwritten_or_never_read_are_dead() {
+        assert_eq!(
+            dead("void f(int a) { int x; x = input(); x = 0; output(x); }"),
+            ["x = input()"]
+        );
+        assert_eq!(
+            dead("int f(int a) { int x; a = 1; x = a; return 0; }"),
+            ["x = a"]
+        );
+        assert_eq!(
+            dead("int f(int a) { a = a + 1; return a; }"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn stores_read_in_a_later_iteration_are_live() {
+        assert_eq!(
+            dead(
+                "void f(void) { int i; int s; int t; i = 0; s = 0; t = 0;\n\
+                 while (i < 10) { s = s + i; i = i + 1; t = i; }\n\
+                 output(s); }"
+            ),
+            ["t = 0", "t = i"]
+        );
+        assert_eq!(
+            dead(
+                "void f(void) { int i; int x; x = 1;\n\
+                 for (i = 0; i < 10; i = i + 1) { if (i) continue; x = 2; }\n\
+                 output(x); }\n\
+                 void g(int i) { int x; do { x = i; i = i - 1; } while (i); }"
+            ),
+            ["x = i"]
+        );
+    }
+
+    #[test]
+    fn stores_read_on_some_path_are_live() {
+        assert_eq!(
+            dead(
+                "int f(int a) { int x; x = 1; if (a) x = 2; return x; }\n\
+                 int g(int a) { int x; x = 1;\n\
+                 switch (a) { case 0: x = 2; case 1: return x; default: break; }\n\
+                 return 0; }"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            dead(
+                "int f(int a) { int x; x = 1;\n\
+                 switch (a) { case 0: x = 2; break; default: x = 3; }\n\
+                 return x; }"
+            ),
+            ["x = 1"]
+        );
+        assert_eq!(
+            dead(
+                "int f(int a) { int x; x = 1; return a ? x : 0; }\n\
+                 int g(void) { int x; x = 1; return x; x = 2; }"
+            ),
+            ["x = 2"]
+        );
+    }
+
+    #[test]
+    fn variables_that_may_be_read_through_other_names_are_not_tracked() {
+        assert_eq!(
+            dead(
+                "int f(void) { int x; int *p; int v[2]; p = &x; x = 1; \
+                 v[0] = 1; p = v; return *p; }"
+            ),
+            ["p = &x"]
+        );
+    }
+}
@@ -0,0 +1,865 @@
+//! A printer writing the three-address code of a program as textual LLVM
+//! IR, shown with `--emit=llvm-ir`, for comparing what csub does with what
+//! an industrial compiler does to the same program. Nothing of LLVM is
+//! linked in: the output is piped through its tools, e.g.
+//!
+//! ```text
+//! csub --emit=llvm-ir prog.cm > prog.ll && clang prog.ll -o prog
+//! ```
+//!
+//! Temporaries may be assigned more than once, so each is a variable of its
+//! own, an `alloca` read with a `load` at every use and written with a
+//! `store` at every assignment, as clang does before `mem2reg` turns them
+//! into SSA values. Slots are `alloca`s of bytes, and globals and strings
+//! are globals of the module. Pointers are opaque, `ptr`, as in LLVM 15 and
+//! later. Instructions are printed as the rules of `RULES` say, as `isel`
+//! selects them.
+//!
+//! Only `main` is visible outside the module. The builtins are the
+//! functions of the runtime, which are written after the program's, as for
+//! the x86-64 backend, and so are the ones the checks call when they fail,
+//! from a block of their own ending in `unreachable`.
+//!
+//! Lowered with lines, each instruction has the location of the line it's
+//! of as `!dbg`, in a `DISubprogram` of its function, so LLVM writes a line
+//! table of the source for a debugger.
+
+use crate::{
+    ast::BinOp,
+    ir::{Callee, Fun, Inst, Label, Mem, Operand, Program, Temp, Ty},
+    isel::{select, Class, Field, Kind, Rule, Selector},
+    runtime,
+    sema::Builtin,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+/// Writes `program` as LLVM IR.
+pub(crate) fn emit_llvm_ir(program: &Program) -> String {
+    let mut out = String::new();
+    let mut debug_info = program.file.as_deref().map(DebugInfo::new);
+
+    for global in &program.globals {
+        let ty = match global.layout.size {
+            1 => "i8".to_string(),
+            4 => "i32".to_string(),
+            8 => "i64".to_string(),
+            size => format!("[{} x i8]", size),
+        };
+        let init = match global.init {
+            Some(init) if !ty.starts_with('[') => init.to_string(),
+            _ => "zeroinitializer".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "@{} = internal global {} {}, align {}",
+            global.name, ty, init, global.layout.align
+        );
+    }
+    for (index, string) in program.strings.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "@.str{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"",
+            index,
+            string.len() + 1,
+            escape(string)
+        );
+    }
+
+    for fun in &program.funs {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        FunPrinter::new(program, fun, &mut out, debug_info.as_mut()).print();
+    }
+
+    let runtime = runtime::used_builtins(program)
+        .into_iter()
+        .map(|builtin| runtime::llvm_ir(builtin).to_string())
+        .chain(
+            runtime::used_checks(program)
+                .into_iter()
+                .map(runtime::llvm_ir_check),
+        )
+        .collect::<Vec<_>>();
+    // What libc has is declared once, though several functions of the
+    // runtime may call it.
+    let mut declared = HashSet::new();
+    for code in &runtime {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        for line in code.lines() {
+            let is_declaration =
+                line.starts_with("declare ") || line.contains(" = external ");
+            if !is_declaration || declared.insert(line) {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+    let mut code = program.funs.iter().flat_map(|fun| &fun.code);
+    if code.any(|inst| matches!(inst, Inst::MemCopy { .. })) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(
+            "declare void @llvm.memcpy.p0.p0.i64(ptr, ptr, i64, i1)\n",
+        );
+    }
+    if let Some(debug_info) = debug_info {
+        out.push('\n');
+        out.push_str(&debug_info.metadata());
+    }
+    out
+}
+
+/// The metadata of the debug info of a program lowered with lines, each
+/// node `!N` the one at index `N`.
+struct DebugInfo {
+    nodes: Vec<String>,
+    /// The node of the location of each line, in each scope.
+    locations: HashMap<(usize, usize), usize>,
+}
+
+impl DebugInfo {
+    /// The compile unit, its file, the type of every function, which is
+    /// left unsaid, and what the module says of its debug info.
+    const UNIT: usize = 0;
+    const FILE: usize = 1;
+    const FUN_TYPE: usize = 2;
+
+    fn new(file: &str) -> DebugInfo {
+        let nodes = vec![
+            format!(
+                "distinct !DICompileUnit(language: DW_LANG_C89, file: !{}, \
+                 producer: \"csub\", isOptimized: false, runtimeVersion: 0, \
+                 emissionKind: LineTablesOnly)",
+                DebugInfo::FILE
+            ),
+            format!(
+                "!DIFile(filename: \"{}\", directory: \"\")",
+                file.replace('\\', "\\5C").replace('"', "\\22")
+            ),
+            "!DISubroutineType(types: !{})".to_string(),
+            "!{i32 2, !\"Dwarf Version\", i32 4}".to_string(),
+            "!{i32 2, !\"Debug Info Version\", i32 3}".to_string(),
+        ];
+        DebugInfo {
+            nodes,
+            locations: HashMap::new(),
+        }
+    }
+
+    fn node(&mut self, node: String) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// A new scope, of the function `name` at `line`.
+    fn subprogram(&mut self, name: &str, line: usize) -> usize {
+        self.node(format!(
+            "distinct !DISubprogram(name: \"{}\", scope: !{}, file: !{}, \
+             line: {}, type: !{}, scopeLine: {}, spFlags: DISPFlagDefinition, \
+             unit: !{})",
+            name,
+            DebugInfo::FILE,
+            DebugInfo::FILE,
+            line,
+            DebugInfo::FUN_TYPE,
+            line,
+            DebugInfo::UNIT
+        ))
+    }
+
+    /// The location of `line` in `scope`.
+    fn location(&mut self, line: usize, scope: usize) -> usize {
+        if let Some(&location) = self.locations.get(&(line, scope)) {
+            return location;
+        }
+        let location = self
+            .node(format!("!DILocation(line: {}, scope: !{})", line, scope));
+        self.locations.insert((line, scope), location);
+        location
+    }
+
+    /// The metadata, written after the functions.
+    fn metadata(&self) -> String {
+        let mut out = format!(
+            "!llvm.dbg.cu = !{{!{}}}\n!llvm.module.flags = !{{!3, !4}}\n",
+            DebugInfo::UNIT
+        );
+        for (index, node) in self.nodes.iter().enumerate() {
+            let _ = writeln!(out, "!{} = {}", index, node);
+        }
+        out
+    }
+}
+
+/// `string` as the contents of a `c"..."` constant.
+fn escape(string: &str) -> String {
+    let mut escaped = String::new();
+    for byte in string.bytes() {
+        match byte {
+            b' '..=b'~' if byte != b'"' && byte != b'\\' => {
+                escaped.push(byte as char)
+            }
+            _ => {
+                let _ = write!(escaped, "\\{:02X}", byte);
+            }
+        }
+    }
+    escaped
+}
+
+fn ty_name(ty: Ty) -> &'static str {
+    match ty {
+        Ty::I32 => "i32",
+        Ty::Ptr => "ptr",
+    }
+}
+
+/// The type `fun` returns in LLVM, if any. `main` returns the exit status
+/// of the program, even if it's `void`.
+fn ret_ty(fun: &Fun) -> Option<Ty> {
+    match fun.ret {
+        None if fun.name == "main" => Some(Ty::I32),
+        ret => ret,
+    }
+}
+
+fn ret_name(ret: Option<Ty>) -> &'static str {
+    ret.map_or("void", ty_name)
+}
+
+struct FunPrinter<'a> {
+    program: &'a Program,
+    fun: &'a Fun,
+    out: &'a mut String,
+    /// How many values, `%vN`, have been defined.
+    values: usize,
+    /// How many blocks of unreachable code, `%deadN`, have been started.
+    dead_blocks: usize,
+    /// How many checks, each going on to `%checkN` if it passes and to
+    /// `%checkN.fail` if it doesn't, there have been.
+    checks: usize,
+    /// Whether the block being printed has ended.
+    terminated: bool,
+    /// The debug info of the program, if it was lowered with lines.
+    debug_info: Option<&'a mut DebugInfo>,
+    /// The scope of the function, and the location of what's printed, if
+    /// there's debug info.
+    scope: Option<usize>,
+    location: Option<usize>,
+    /// The values the instruction being printed has defined, by the names
+    /// its template gives them.
+    named: HashMap<String, String>,
+}
+
+impl<'a> FunPrinter<'a> {
+    fn new(
+        program: &'a Program,
+        fun: &'a Fun,
+        out: &'a mut String,
+        debug_info: Option<&'a mut DebugInfo>,
+    ) -> Self {
+        FunPrinter {
+            program,
+            fun,
+            out,
+            values: 0,
+            dead_blocks: 0,
+            checks: 0,
+            terminated: false,
+            debug_info,
+            scope: None,
+            location: None,
+            named: HashMap::new(),
+        }
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        self.out.push_str("  ");
+        self.out.push_str(line.as_ref());
+        if let Some(location) = self.location {
+            let _ = write!(self.out, ", !dbg !{}", location);
+        }
+        self.out.push('\n');
+    }
+
+    /// Has what's printed next be of `line`, if there's debug info.
+    fn set_line(&mut self, line: usize) {
+        if let (Some(debug_info), Some(scope)) =
+            (self.debug_info.as_mut(), self.scope)
+        {
+            self.location = Some(debug_info.location(line, scope));
+        }
+    }
+
+    /// Defines a new value as `rhs`, returning its name.
+    fn define(&mut self, rhs: impl AsRef<str>) -> String {
+        let name = format!("%v{}", self.values);
+        self.values += 1;
+        self.line(format!("{} = {}", name, rhs.as_ref()));
+        name
+    }
+
+    fn print(mut self) {
+        let fun = self.fun;
+        let params: Vec<_> = fun.temps[..fun.params]
+            .iter()
+            .enumerate()
+            .map(|(index, ty)| format!("{} %p{}", ty_name(*ty), index))
+            .collect();
+        let linkage = if fun.name == "main" { "" } else { "internal " };
+        // The allocas and the stores of the arguments are of the line of
+        // the function.
+        let first_line = fun.code.iter().find_map(|inst| match inst {
+            Inst::Line(line) => Some(*line),
+            _ => None,
+        });
+        let mut dbg = String::new();
+        if let (Some(debug_info), Some(line)) =
+            (self.debug_info.as_mut(), first_line)
+        {
+            let scope = debug_info.subprogram(&fun.name, line);
+            self.scope = Some(scope);
+            dbg = format!(" !dbg !{}", scope);
+            self.set_line(line);
+        }
+        let _ = writeln!(
+            self.out,
+            "define {}{} @{}({}){} {{",
+            linkage,
+            ret_name(ret_ty(fun)),
+            fun.name,
+            params.join(", "),
+            dbg
+        );
+        for (index, ty) in fun.temps.iter().enumerate() {
+            self.line(format!("%t{} = alloca {}", index, ty_name(*ty)));
+        }
+        for (index, slot) in fun.slots.iter().enumerate() {
+            self.line(format!(
+                "%s{} = alloca [{} x i8], align {}",
+                index, slot.layout.size, slot.layout.align
+            ));
+        }
+        for (index, ty) in fun.temps[..fun.params].iter().enumerate() {
+            self.line(format!(
+                "store {} %p{}, ptr %t{}",
+                ty_name(*ty),
+                index,
+                index
+            ));
+        }
+        for inst in &fun.code {
+            self.inst(inst);
+        }
+        self.out.push_str("}\n");
+    }
+
+    fn label(label: Label) -> String {
+        format!("%L{}", label.0)
+    }
+
+    fn ty(&self, operand: &Operand) -> Ty {
+        match *operand {
+            Operand::Temp(temp) => self.fun.temps[temp.0],
+            Operand::Const(_) => Ty::I32,
+            Operand::Slot(_) | Operand::Global(_) | Operand::Str(_) => Ty::Ptr,
+        }
+    }
+
+    /// `operand` as a value of type `ty`.
+    fn value(&mut self, operand: &Operand, ty: Ty) -> String {
+        let value = match *operand {
+            Operand::Const(0) if ty == Ty::Ptr => return "null".into(),
+            Operand::Const(value) => value.to_string(),
+            Operand::Temp(temp) => {
+                let temp_ty = ty_name(self.fun.temps[temp.0]);
+                self.define(format!("load {}, ptr %t{}", temp_ty, temp.0))
+            }
+            Operand::Slot(index) => format!("%s{}", index),
+            Operand::Global(index) => {
+                format!("@{}", self.program.globals[index].name)
+            }
+            Operand::Str(index) => format!("@.str{}", index),
+        };
+        match (self.ty(operand), ty) {
+            (Ty::I32, Ty::Ptr) => {
+                self.define(format!("inttoptr i32 {} to ptr", value))
+            }
+            (Ty::Ptr, Ty::I32) => {
+                self.define(format!("ptrtoint ptr {} to i32", value))
+            }
+            _ => value,
+        }
+    }
+
+    /// Stores `value`, of type `ty`, into `dst`.
+    fn store(&mut self, dst: Temp, value: String, ty: Ty) {
+        let dst_ty = self.fun.temps[dst.0];
+        let value = match (ty, dst_ty) {
+            (Ty::I32, Ty::Ptr) => {
+                self.define(format!("inttoptr i32 {} to ptr", value))
+            }
+            (Ty::Ptr, Ty::I32) => {
+                self.define(format!("ptrtoint ptr {} to i32", value))
+            }
+            _ => value,
+        };
+        self.line(format!(
+            "store {} {}, ptr %t{}",
+            ty_name(dst_ty),
+            value,
+            dst.0
+        ));
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Label(_) => self.terminated = false,
+            Inst::Line(_) => {}
+            _ => {
+                if self.terminated {
+                    // LLVM wants every instruction in a block, even
+                    // unreachable ones.
+                    let _ = writeln!(self.out, "dead{}:", self.dead_blocks);
+                    self.dead_blocks += 1;
+                }
+                self.terminated = inst.is_terminator();
+            }
+        }
+        self.named.clear();
+        select(self, RULES, inst);
+    }
+
+    /// The operand of `inst` by the name of its field.
+    fn operand(inst: &Inst, name: &str) -> Operand {
+        Field::from_name(name)
+            .and_then(|field| field.of(inst))
+            .unwrap_or_else(|| panic!("{:?} has no `{}`", inst, name))
+    }
+}
+
+/// How each instruction is printed. Operands are loaded from their
+/// `alloca`s, or converted, as values of the type the template says, and
+/// the values an instruction defines are named in its template for the
+/// lines after, e.g. `@define sum add i32 ...` for `{sum}`.
+const RULES: &[Rule] = &[
+    Rule::new(Kind::Copy, &["@store dst i32 {src:i32}"]).when(&[(Field::Dst, Class::I32)]),
+    Rule::new(Kind::Copy, &["@store dst ptr {src:ptr}"]).when(&[(Field::Dst, Class::Ptr)]),
+    Rule::new(Kind::Neg, &["@define result sub i32 0, {src:i32}", "@store dst i32 {result}"]),
+    // Addresses are compared as unsigned numbers.
+    Rule::new(Kind::Binary, COMPARE_ADDRESSES).when(&[(Field::Lhs, Class::Ptr)]),
+    Rule::new(Kind::Binary, COMPARE_ADDRESSES).when(&[(Field::Rhs, Class::Ptr)]),
+    Rule::new(
+        Kind::Binary,
+        &["@define result {op:int} i32 {lhs:i32}, {rhs:i32}", "@store dst i32 {result}"],
+    )
+    .ops(&[BinOp::Add, BinOp::Sub, BinOp::Mul, BinOp::Div]),
+    Rule::new(
+        Kind::Binary,
+        &[
+            "@define cmp {op:signed} i32 {lhs:i32}, {rhs:i32}",
+            "@define result zext i1 {cmp} to i32",
+            "@store dst i32 {result}",
+        ],
+    ),
+    Rule::new(
+        Kind::Offset,
+        &[
+            "@define result getelementptr i8, ptr {base:ptr}, i64 {bytes:i64}",
+            "@store dst ptr {result}",
+        ],
+    ),
+    Rule::new(
+        Kind::Diff,
+        &[
+            "@define diff sub i64 {lhs:i64}, {rhs:i64}",
+            "@define result trunc i64 {diff} to i32",
+            "@store dst i32 {result}",
+        ],
+    ),
+    Rule::new(
+        Kind::Load,
+        &[
+            "@define byte load i8, ptr {addr:ptr}",
+            "@define result zext i8 {byte} to i32",
+            "@store dst i32 {result}",
+        ],
+    )
+    .mem(Mem::I8),
+    Rule::new(Kind::Load, &["@define result load i32, ptr {addr:ptr}", "@store dst i32 {result}"])
+        .mem(Mem::I32),
+    Rule::new(Kind::Load, &["@define result load ptr, ptr {addr:ptr}", "@store dst ptr {result}"])
+        .mem(Mem::Ptr),
+    Rule::new(
+        Kind::Store,
+        &["@define byte trunc i32 {value:i32} to i8", "store i8 {byte}, ptr {addr:ptr}"],
+    )
+    .mem(Mem::I8),
+    Rule::new(Kind::Store, &["store i32 {value:i32}, ptr {addr:ptr}"]).mem(Mem::I32),
+    Rule::new(Kind::Store, &["store ptr {value:ptr}, ptr {addr:ptr}"]).mem(Mem::Ptr),
+    Rule::new(
+        Kind::MemCopy,
+        &["call void @llvm.memcpy.p0.p0.i64(ptr {dst:ptr}, ptr {src:ptr}, i64 {size}, i1 false)"],
+    ),
+    Rule::new(Kind::Call, &["@call {ret} @{callee}({args})"]).when(&[(Field::Dst, Class::Absent)]),
+    Rule::new(Kind::Call, &["@call {ret} @{callee}({args})", "@store dst {ret} {result}"]),
+    Rule::new(Kind::Label, &["{label:block}:"]),
+    Rule::new(Kind::Jump, &["br label {label}"]),
+    Rule::new(
+        Kind::Branch,
+        &["@define test icmp ne i32 {cond:i32}, 0", "br i1 {test}, label {then}, label {else}"],
+    )
+    .when(&[(Field::Cond, Class::I32)]),
+    Rule::new(
+        Kind::Branch,
+        &["@define test icmp ne ptr {cond:ptr}, null", "br i1 {test}, label {then}, label {else}"],
+    ),
+    // What `main` returns is the exit status of the program.
+    Rule::new(Kind::Return, &["ret {zero}"]).when(&[(Field::Value, Class::Absent)]),
+    Rule::new(Kind::Return, &["ret {value:ret}"]),
+    Rule::new(
+        Kind::Check,
+        &[
+            "@define test icmp ne i32 {cond:i32}, 0",
+            "br i1 {test}, label %{check}, label %{check}.fail",
+            "{check}.fail:",
+            "call void @{check:fn}(i32 {line})",
+            "unreachable",
+            "{check}:",
+        ],
+    ),
+    Rule::new(Kind::Line, &["@line {line}"]),
+];
+
+const COMPARE_ADDRESSES: &[&str] = &[
+    "@define cmp {op:unsigned} ptr {lhs:ptr}, {rhs:ptr}",
+    "@define result zext i1 {cmp} to i32",
+    "@store dst i32 {result}",
+];
+
+fn parse_ty(name: &str) -> Ty {
+    match name {
+        "i32" => Ty::I32,
+        "ptr" => Ty::Ptr,
+        _ => panic!("no type `{}`", name),
+    }
+}
+
+impl Selector for FunPrinter<'_> {
+    fn fun(&self) -> &Fun {
+        self.fun
+    }
+
+    /// Writes a value the instruction defined by its name, or an operand
+    /// as a value of type `i32`, `ptr` or `i64`, or as the type `ret` the
+    /// function returns, with it.
+    fn placeholder(&mut self, inst: &Inst, name: &str, form: &str) -> String {
+        if let Some(value) = self.named.get(name) {
+            return value.clone();
+        }
+        match (name, inst) {
+            ("op", Inst::Binary { op, .. }) => {
+                return match (form, op) {
+                    ("int", BinOp::Add) => "add",
+                    ("int", BinOp::Sub) => "sub",
+                    ("int", BinOp::Mul) => "mul",
+                    ("int", BinOp::Div) => "sdiv",
+                    ("signed", BinOp::Less) => "icmp slt",
+                    ("signed", BinOp::LessEqual) => "icmp sle",
+                    ("signed", BinOp::Greater) => "icmp sgt",
+                    ("signed", BinOp::GreaterEqual) => "icmp sge",
+                    ("unsigned", BinOp::Less) => "icmp ult",
+                    ("unsigned", BinOp::LessEqual) => "icmp ule",
+                    ("unsigned", BinOp::Greater) => "icmp ugt",
+                    ("unsigned", BinOp::GreaterEqual) => "icmp uge",
+                    ("signed" | "unsigned", BinOp::Equal) => "icmp eq",
+                    ("signed" | "unsigned", BinOp::NotEqual) => "icmp ne",
+                    _ => panic!("{:?} has no `{}` form", op, form),
+                }
+                .into()
+            }
+            ("label", Inst::Label(label)) if form == "block" => {
+                return format!("L{}", label.0)
+            }
+            ("label", Inst::Jump(label))
+            | (
+                "then",
+                Inst::Branch {
+                    then_label: label, ..
+                },
+            )
+            | (
+                "else",
+                Inst::Branch {
+                    else_label: label, ..
+                },
+            ) => return Self::label(*label),
+            ("size", Inst::MemCopy { size, .. }) => return size.to_string(),
+            ("line", Inst::Line(line) | Inst::Check { line, .. }) => {
+                return line.to_string()
+            }
+            ("check", Inst::Check { check, .. }) if form == "fn" => {
+                return runtime::check_symbol(*check).into()
+            }
+            ("check", Inst::Check { .. }) => {
+                let block = format!("check{}", self.checks);
+                self.checks += 1;
+                return block;
+            }
+            ("zero", Inst::Return(None)) => {
+                return match ret_ty(self.fun) {
+                    Some(ty) => {
+                        let zero = self.value(&Operand::Const(0), ty);
+                        format!("{} {}", ty_name(ty), zero)
+                    }
+                    None => "void".into(),
+                }
+            }
+            ("ret" | "callee" | "args", Inst::Call { callee, args, .. }) => {
+                let (callee, param_tys, ret) = match *callee {
+                    Callee::Fun(index) => {
+                        let fun = &self.program.funs[index];
+                        (
+                            fun.name.as_str(),
+                            fun.temps[..fun.params].to_vec(),
+                            ret_ty(fun),
+                        )
+                    }
+                    Callee::Builtin(Builtin::Input) => (
+                        runtime::symbol(Builtin::Input),
+                        Vec::new(),
+                        Some(Ty::I32),
+                    ),
+                    Callee::Builtin(Builtin::Output) => {
+                        (runtime::symbol(Builtin::Output), vec![Ty::I32], None)
+                    }
+                };
+                return match name {
+                    "ret" => ret_name(ret).into(),
+                    "callee" => callee.into(),
+                    _ => args
+                        .iter()
+                        .zip(param_tys)
+                        .map(|(arg, ty)| {
+                            format!("{} {}", ty_name(ty), self.value(arg, ty))
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+            }
+            _ => {}
+        }
+        let operand = Self::operand(inst, name);
+        match form {
+            "i32" => self.value(&operand, Ty::I32),
+            "ptr" => self.value(&operand, Ty::Ptr),
+            "i64" => match (self.ty(&operand), operand) {
+                (Ty::I32, Operand::Const(value)) => value.to_string(),
+                (Ty::I32, _) => {
+                    let value = self.value(&operand, Ty::I32);
+                    self.define(format!("sext i32 {} to i64", value))
+                }
+                (Ty::Ptr, _) => {
+                    let value = self.value(&operand, Ty::Ptr);
+                    self.define(format!("ptrtoint ptr {} to i64", value))
+                }
+            },
+            "ret" => {
+                let ty = self.fun.ret.unwrap_or(Ty::I32);
+                let value = self.value(&operand, ty);
+                format!("{} {}", ty_name(ty), value)
+            }
+            _ => panic!("`{}` has no `{}` form for {:?}", name, form, inst),
+        }
+    }
+
+    /// `@define`s a value by a name, `@store`s a value of a type into a
+    /// temporary, `@call`s, defining `result` unless the call is `void`,
+    /// and has the code after it be of a `@line`.
+    fn action(&mut self, inst: &Inst, name: &str, args: &str) {
+        match name {
+            "define" => {
+                let (name, rhs) = args.split_once(' ').unwrap();
+                let value = self.define(rhs);
+                self.named.insert(name.into(), value);
+            }
+            "store" => {
+                let mut args = args.splitn(3, ' ');
+                let Operand::Temp(dst) =
+                    Self::operand(inst, args.next().unwrap())
+                else {
+                    panic!("{:?} stores into no temporary", inst)
+                };
+                let ty = parse_ty(args.next().unwrap());
+                self.store(dst, args.next().unwrap().into(), ty);
+            }
+            "call" if args.starts_with("void ") => {
+                self.line(format!("call {}", args))
+            }
+            "call" => {
+                let value = self.define(format!("call {}", args));
+                self.named.insert("result".into(), value);
+            }
+            "line" => self.set_line(args.parse().unwrap()),
+            _ => panic!("no action `@{}`", name),
+        }
+    }
+
+    fn emit_line(&mut self, line: String) {
+        match line.strip_suffix(':') {
+            Some(label) if !label.contains(' ') => {
+                let _ = writeln!(self.out, "{}", line);
+            }
+            _ => self.line(line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::emit_llvm_ir;
+    use crate::{
+        extensions::Extensions,
+        ir::{lower_program_with_lines, Checks},
+        parser::ParseResult,
+        sema::{check_program, Builtin},
+        test_support::{lower_source, parse_source},
+    };
+
+    fn llvm_ir(source: &str) -> String {
+        emit_llvm_ir(&lower_source(source))
+    }
+
+    #[test]
+    fn temporaries_are_allocas_and_main_returns_zero() {
+        let source = "char *s;\n\
+                      int at(char *t, int i) { return t[i] < 'b'; }\n\
+                      void main(void) { s = \"a\\\"\\n\"; output(at(s, 1)); }\n";
+
+        assert_eq!(
+            llvm_ir(source),
+            "@s = internal global i64 zeroinitializer, align 8\n\
+             @.str0 = private unnamed_addr constant [4 x i8] c\"a\\22\\0A\\00\"\n\
+             \n\
+             define internal i32 @at(ptr %p0, i32 %p1) {\n\
+             \x20 %t0 = alloca ptr\n\
+             \x20 %t1 = alloca i32\n\
+             \x20 %t2 = alloca ptr\n\
+             \x20 %t3 = alloca i32\n\
+             \x20 %t4 = alloca i32\n\
+             \x20 %t5 = alloca ptr\n\
+             \x20 %t6 = alloca i32\n\
+             \x20 %t7 = alloca i32\n\
+             \x20 %s0 = alloca [8 x i8], align 8\n\
+             \x20 %s1 = alloca [4 x i8], align 4\n\
+             \x20 store ptr %p0, ptr %t0\n\
+             \x20 store i32 %p1, ptr %t1\n\
+             \x20 %v0 = load ptr, ptr %t0\n\
+             \x20 store ptr %v0, ptr %s0\n\
+             \x20 %v1 = load i32, ptr %t1\n\
+             \x20 store i32 %v1, ptr %s1\n\
+             \x20 %v2 = load ptr, ptr %s0\n\
+             \x20 store ptr %v2, ptr %t2\n\
+             \x20 %v3 = load i32, ptr %s1\n\
+             \x20 store i32 %v3, ptr %t3\n\
+             \x20 %v4 = load i32, ptr %t3\n\
+             \x20 %v5 = mul i32 %v4, 1\n\
+             \x20 store i32 %v5, ptr %t4\n\
+             \x20 %v6 = load ptr, ptr %t2\n\
+             \x20 %v7 = load i32, ptr %t4\n\
+             \x20 %v8 = sext i32 %v7 to i64\n\
+             \x20 %v9 = getelementptr i8, ptr %v6, i64 %v8\n\
+             \x20 store ptr %v9, ptr %t5\n\
+             \x20 %v10 = load ptr, ptr %t5\n\
+             \x20 %v11 = load i8, ptr %v10\n\
+             \x20 %v12 = zext i8 %v11 to i32\n\
+             \x20 store i32 %v12, ptr %t6\n\
+             \x20 %v13 = load i32, ptr %t6\n\
+             \x20 %v14 = icmp slt i32 %v13, 98\n\
+             \x20 %v15 = zext i1 %v14 to i32\n\
+             \x20 store i32 %v15, ptr %t7\n\
+             \x20 %v16 = load i32, ptr %t7\n\
+             \x20 ret i32 %v16\n\
+             }\n\
+             \n\
+             define i32 @main() {\n\
+             \x20 %t0 = alloca ptr\n\
+             \x20 %t1 = alloca i32\n\
+             \x20 store ptr @.str0, ptr @s\n\
+             \x20 %v0 = load ptr, ptr @s\n\
+             \x20 store ptr %v0, ptr %t0\n\
+             \x20 %v1 = load ptr, ptr %t0\n\
+             \x20 %v2 = call i32 @at(ptr %v1, i32 1)\n\
+             \x20 store i32 %v2, ptr %t1\n\
+             \x20 %v3 = load i32, ptr %t1\n\
+             \x20 call void @csub_output(i32 %v3)\n\
+             \x20 ret i32 0\n\
+             }\n\
+             \n\
+             @csub_output.format = private unnamed_addr constant [4 x i8] c\"%d\\0A\\00\"\n\
+             \n\
+             define internal void @csub_output(i32 %value) {\n\
+             \x20 call i32 (ptr, ...) @printf(ptr @csub_output.format, i32 %value)\n\
+             \x20 ret void\n\
+             }\n\
+             \n\
+             declare i32 @printf(ptr, ...)\n"
+        );
+    }
+
+    #[test]
+    fn lines_are_locations_in_the_scope_of_their_function() {
+        let source = "void main(void) {\n\
+                      \x20   int n;\n\
+                      \x20   n = input();\n\
+                      \x20   output(n);\n\
+                      }\n";
+        let (source_file, ParseResult { program, .. }) =
+            parse_source(source, Extensions::all());
+        let analysis = check_program(&program, &Builtin::ALL);
+        let program = lower_program_with_lines(
+            &analysis.hir,
+            Checks::default(),
+            &source_file,
+        );
+
+        let ir = emit_llvm_ir(&program);
+
+        assert!(ir.starts_with(
+            "define i32 @main() !dbg !5 {\n\
+             \x20 %t0 = alloca i32, !dbg !6\n\
+             \x20 %t1 = alloca i32, !dbg !6\n\
+             \x20 %s0 = alloca [4 x i8], align 4, !dbg !6\n\
+             \x20 %v0 = call i32 @csub_input(), !dbg !7\n\
+             \x20 store i32 %v0, ptr %t0, !dbg !7\n\
+             \x20 %v1 = load i32, ptr %t0, !dbg !7\n\
+             \x20 store i32 %v1, ptr %s0, !dbg !7\n\
+             \x20 %v2 = load i32, ptr %s0, !dbg !8\n\
+             \x20 store i32 %v2, ptr %t1, !dbg !8\n\
+             \x20 %v3 = load i32, ptr %t1, !dbg !8\n\
+             \x20 call void @csub_output(i32 %v3), !dbg !8\n\
+             \x20 ret i32 0, !dbg !8\n\
+             }\n"
+        ));
+        assert!(ir.ends_with(
+            "!llvm.dbg.cu = !{!0}\n\
+             !llvm.module.flags = !{!3, !4}\n\
+             !0 = distinct !DICompileUnit(language: DW_LANG_C89, file: !1, producer: \"csub\", isOptimized: false, runtimeVersion: 0, emissionKind: LineTablesOnly)\n\
+             !1 = !DIFile(filename: \"main.cm\", directory: \"\")\n\
+             !2 = !DISubroutineType(types: !{})\n\
+             !3 = !{i32 2, !\"Dwarf Version\", i32 4}\n\
+             !4 = !{i32 2, !\"Debug Info Version\", i32 3}\n\
+             !5 = distinct !DISubprogram(name: \"main\", scope: !1, file: !1, line: 1, type: !2, scopeLine: 1, spFlags: DISPFlagDefinition, unit: !0)\n\
+             !6 = !DILocation(line: 1, scope: !5)\n\
+             !7 = !DILocation(line: 3, scope: !5)\n\
+             !8 = !DILocation(line: 4, scope: !5)\n"
+        ));
+    }
+}
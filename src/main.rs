@@ -1,7 +1,4 @@
-pub mod scanner;
-pub mod source_map;
-pub mod errors;
-
 fn main() {
-    println!("Hello, world!");
+    let exit_code = csub::driver::main(std::env::args().skip(1));
+    std::process::exit(exit_code);
 }
@@ -0,0 +1,266 @@
+//! Side tables of where every node of the tree is, for finding nodes by
+//! position and going up the tree without walking it.
+//!
+//! The tree only points down, from a node to its children. A `NodeMap` is
+//! built by walking it once, and then tells the span and the parent of any
+//! node in it, and which node is at a position, e.g. under an editor's
+//! cursor.
+
+use crate::{
+    ast::{BlockId, ExprId, Nodes, Program, StmtId},
+    source_map::{BytePos, Span},
+    visit::{walk_block, walk_decl, walk_expr, walk_stmt, Visitor},
+};
+
+/// A node of the tree: a top-level declaration by its index in the program,
+/// or a node in the arenas by its id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum NodeRef {
+    Decl(usize),
+    Block(BlockId),
+    Stmt(StmtId),
+    Expr(ExprId),
+}
+
+/// What the map knows of a node.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    span: Span,
+    /// `None` for a top-level declaration.
+    parent: Option<NodeRef>,
+}
+
+/// The spans and parents of the nodes of a program, indexed by their ids.
+///
+/// Nodes in the arenas that aren't part of the tree, e.g. the ones parsed
+/// before a syntax error in the statement they were in, aren't in the map.
+#[derive(Debug, Default)]
+pub(crate) struct NodeMap {
+    decls: Vec<Entry>,
+    blocks: Vec<Option<Entry>>,
+    stmts: Vec<Option<Entry>>,
+    exprs: Vec<Option<Entry>>,
+    /// Every node in the map, parents before their children, and children
+    /// in source order.
+    preorder: Vec<NodeRef>,
+}
+
+impl NodeMap {
+    pub(crate) fn of(program: &Program) -> NodeMap {
+        let mut builder = Builder {
+            map: NodeMap {
+                decls: Vec::with_capacity(program.decls.len()),
+                blocks: vec![None; program.nodes.blocks.len()],
+                stmts: vec![None; program.nodes.stmts.len()],
+                exprs: vec![None; program.nodes.exprs.len()],
+                preorder: Vec::new(),
+            },
+            parents: Vec::new(),
+        };
+        builder.visit_program(program);
+        builder.map
+    }
+
+    fn entry(&self, node: NodeRef) -> Option<&Entry> {
+        match node {
+            NodeRef::Decl(index) => self.decls.get(index),
+            NodeRef::Block(block) => self.blocks.get(block.index())?.as_ref(),
+            NodeRef::Stmt(stmt) => self.stmts.get(stmt.index())?.as_ref(),
+            NodeRef::Expr(expr) => self.exprs.get(expr.index())?.as_ref(),
+        }
+    }
+
+    /// The span of `node`, if it's part of the tree.
+    pub(crate) fn span(&self, node: NodeRef) -> Option<Span> {
+        self.entry(node).map(|entry| entry.span)
+    }
+
+    /// The node `node` is a child of, or `None` for a top-level declaration
+    /// and for a node that isn't part of the tree.
+    pub(crate) fn parent(&self, node: NodeRef) -> Option<NodeRef> {
+        self.entry(node)?.parent
+    }
+
+    /// `node` and the nodes it's in, from `node` up to its top-level
+    /// declaration.
+    pub(crate) fn ancestors(
+        &self,
+        node: NodeRef,
+    ) -> impl Iterator<Item = NodeRef> + '_ {
+        std::iter::successors(Some(node), move |&node| self.parent(node))
+    }
+
+    /// The innermost node whose span has `pos` in it, not counting its end.
+    pub(crate) fn node_at(&self, pos: BytePos) -> Option<NodeRef> {
+        self.preorder.iter().rev().copied().find(|&node| {
+            let span = self.span(node).unwrap();
+            span.start <= pos && pos < span.end
+        })
+    }
+}
+
+/// Walks the tree, keeping the nodes it's in.
+struct Builder {
+    map: NodeMap,
+    parents: Vec<NodeRef>,
+}
+
+impl Builder {
+    /// Adds `node` to the map, and walks its children with `walk`.
+    fn add(&mut self, node: NodeRef, span: Span, walk: impl FnOnce(&mut Self)) {
+        let entry = Entry {
+            span,
+            parent: self.parents.last().copied(),
+        };
+        match node {
+            NodeRef::Decl(_) => self.map.decls.push(entry),
+            NodeRef::Block(block) => {
+                self.map.blocks[block.index()] = Some(entry)
+            }
+            NodeRef::Stmt(stmt) => self.map.stmts[stmt.index()] = Some(entry),
+            NodeRef::Expr(expr) => self.map.exprs[expr.index()] = Some(entry),
+        }
+        self.map.preorder.push(node);
+
+        self.parents.push(node);
+        walk(self);
+        self.parents.pop();
+    }
+}
+
+impl Visitor for Builder {
+    fn visit_program(&mut self, program: &Program) {
+        for (index, decl) in program.decls.iter().enumerate() {
+            self.add(NodeRef::Decl(index), decl.span(), |builder| {
+                walk_decl(builder, &program.nodes, decl)
+            });
+        }
+    }
+
+    fn visit_block(&mut self, nodes: &Nodes, block: BlockId) {
+        self.add(NodeRef::Block(block), nodes[block].span, |builder| {
+            walk_block(builder, nodes, block)
+        });
+    }
+
+    fn visit_stmt(&mut self, nodes: &Nodes, stmt: StmtId) {
+        self.add(NodeRef::Stmt(stmt), nodes[stmt].span, |builder| {
+            walk_stmt(builder, nodes, stmt)
+        });
+    }
+
+    fn visit_expr(&mut self, nodes: &Nodes, expr: ExprId) {
+        self.add(NodeRef::Expr(expr), nodes[expr].span, |builder| {
+            walk_expr(builder, nodes, expr)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NodeMap, NodeRef};
+    use crate::{
+        ast::{ExprKind, Program, StmtKind},
+        extensions::Extensions,
+        parser::ParseResult,
+        source_map::{BytePos, SourceFile},
+        test_support::parse_source,
+    };
+
+    fn parse(source: &str) -> (SourceFile, Program) {
+        let (source_file, ParseResult { program, .. }) =
+            parse_source(source, Extensions::none());
+        (source_file, program)
+    }
+
+    #[test]
+    fn the_node_at_a_position_is_the_innermost_one() {
+        let source = "int g;\nint f(int a) {\n    return a + g * 2;\n}\n";
+        let (source_file, program) = parse(source);
+        let map = NodeMap::of(&program);
+        let node_at = |text: &str| {
+            let pos = BytePos(source.find(text).unwrap());
+            let node = map.node_at(pos)?;
+            Some(source_file.span_to_snippet(map.span(node)?).to_string())
+        };
+
+        assert_eq!(node_at("g;").as_deref(), Some("int g;"));
+        assert_eq!(node_at("a +").as_deref(), Some("a"));
+        assert_eq!(node_at("+ g").as_deref(), Some("a + g * 2"));
+        assert_eq!(node_at("* 2").as_deref(), Some("g * 2"));
+        assert_eq!(node_at("2;").as_deref(), Some("2"));
+        assert_eq!(node_at("return").as_deref(), Some("return a + g * 2;"));
+        assert_eq!(
+            node_at("\n}").as_deref(),
+            Some("{\n    return a + g * 2;\n}")
+        );
+        assert_eq!(node_at("\nint f"), None);
+    }
+
+    #[test]
+    fn parents_lead_up_to_the_declaration() {
+        let source = "int f(int a) { while (a) a = a - 1; }";
+        let (source_file, program) = parse(source);
+        let map = NodeMap::of(&program);
+
+        let one = program
+            .nodes
+            .exprs
+            .iter()
+            .find(|(_, expr)| expr.kind == ExprKind::Number(1))
+            .map(|(id, _)| id)
+            .unwrap();
+        let kinds: Vec<_> = map
+            .ancestors(NodeRef::Expr(one))
+            .map(|node| match node {
+                NodeRef::Decl(index) => format!("decl {}", index),
+                NodeRef::Block(_) => "block".to_string(),
+                NodeRef::Stmt(stmt) => match program.nodes[stmt].kind {
+                    StmtKind::While { .. } => "while".to_string(),
+                    _ => "stmt".to_string(),
+                },
+                NodeRef::Expr(expr) => {
+                    source_file.span_to_snippet(program.nodes[expr].span).into()
+                }
+            })
+            .collect();
+
+        assert_eq!(
+            kinds,
+            [
+                "1",
+                "a - 1",
+                "a = a - 1",
+                "stmt",
+                "while",
+                "block",
+                "decl 0"
+            ]
+        );
+        assert_eq!(map.parent(NodeRef::Decl(0)), None);
+    }
+
+    #[test]
+    fn nodes_left_out_of_the_tree_arent_in_the_map() {
+        let source = "int f(void) { g(1 + ; return 0; }";
+        let (source_file, program) = parse(source);
+        let map = NodeMap::of(&program);
+
+        let (one, _) = program
+            .nodes
+            .exprs
+            .iter()
+            .find(|(_, expr)| expr.kind == ExprKind::Number(1))
+            .unwrap();
+        assert_eq!(map.span(NodeRef::Expr(one)), None);
+        assert_eq!(map.parent(NodeRef::Expr(one)), None);
+        assert_eq!(
+            map.node_at(BytePos(source.find('1').unwrap()))
+                .map(|node| source_file
+                    .span_to_snippet(map.span(node).unwrap())
+                    .to_string())
+                .as_deref(),
+            Some("{ g(1 + ; return 0; }")
+        );
+    }
+}
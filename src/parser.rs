@@ -0,0 +1,3201 @@
+//! The parser: builds the abstract syntax tree of a program out of the words
+//! the scanner found in it, following the C- grammar.
+//!
+//! The parser is a recursive descent one, with a function for each rule of
+//! the grammar. A syntax error is reported as soon as it's found, and the
+//! parser then skips to where it can make sense of the program again (the end
+//! of the statement or of the declaration), so that the errors after it are
+//! reported too. What's skipped is left out of the tree, and the rest of the
+//! program is kept for the phases after parsing.
+
+use crate::{
+    ast::{
+        BinOp, Block, BlockId, Decl, Expr, ExprId, ExprKind, FunDecl, Ident,
+        Nodes, Number, Param, Program, Stmt, StmtId, StmtKind, StructDecl,
+        SwitchCase, TypeSpec, UnOp, VarDecl,
+    },
+    catalog::message,
+    emitter::SpanLocs,
+    errors::{Diag, DiagBag, MAX_NESTING_DEPTH},
+    extensions::{Extension, Extensions},
+    ice::set_current_span,
+    scanner::{literal_text, Category, Keyword, Word},
+    source_map::{BytePos, SourceFile, Span},
+};
+
+/// What the parser expected to find where a syntax error is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Expected {
+    Token(Category),
+    Expression,
+    Declaration,
+    TypeSpecifier,
+    /// A `case` or `default` label, e.g. at the start of a `switch`.
+    CaseLabel,
+}
+
+impl Expected {
+    /// How the expected thing is called in messages, e.g. "an expression".
+    pub(crate) fn describe(self) -> String {
+        match self {
+            Expected::Token(category) => category.describe(),
+            Expected::Expression => message("expected.expression", &[]),
+            Expected::Declaration => message("expected.declaration", &[]),
+            Expected::TypeSpecifier => message("expected.type", &[]),
+            Expected::CaseLabel => message("expected.case", &[]),
+        }
+    }
+}
+
+/// What the parser made of a program.
+#[derive(Debug)]
+pub(crate) struct ParseResult {
+    /// The program, without the parts the parser had to skip.
+    pub(crate) program: Program,
+    /// The source the parser skipped to recover from syntax errors, in
+    /// order, e.g. a statement with an error in it.
+    pub(crate) skipped: Vec<Span>,
+    pub(crate) diag_bag: DiagBag,
+}
+
+impl ParseResult {
+    /// Whether nothing in the source was left out of the program. Even then
+    /// there may be errors the parser could make sense of, e.g. a `;` missing
+    /// at the end of a line.
+    pub(crate) fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+    }
+}
+
+/// A syntax error was found and reported, and the parser has to recover from
+/// it.
+struct SyntaxError;
+
+type PResult<T> = Result<T, SyntaxError>;
+
+struct Parser<'src> {
+    source_file: &'src SourceFile,
+    words: &'src [Word],
+    /// The extensions the program may use.
+    extensions: Extensions,
+    /// The word standing for the end of the input, past the last one.
+    eof: Word,
+    /// Index of the current word.
+    pos: usize,
+    /// How deep the statement or expression being parsed is nested.
+    depth: usize,
+    /// Whether nesting too deep was reported in the current declaration. What
+    /// comes after it is likely as deep, and isn't reported again.
+    too_deep_reported: bool,
+    /// Where the nodes parsed so far are stored.
+    nodes: Nodes,
+    /// The source skipped so far to recover from syntax errors.
+    skipped: Vec<Span>,
+    diag_bag: DiagBag,
+    /// The rules entered and left so far, if they're being traced.
+    trace: Option<String>,
+    /// How many traced rules the parser is in.
+    trace_depth: usize,
+}
+
+impl<'src> Parser<'src> {
+    fn new(
+        source_file: &'src SourceFile,
+        words: &'src [Word],
+        extensions: Extensions,
+    ) -> Parser<'src> {
+        let end_pos = source_file.end_pos();
+
+        Parser {
+            source_file,
+            words,
+            extensions,
+            eof: Word {
+                category: Category::Eof,
+                lexeme: Span {
+                    start: end_pos,
+                    end: end_pos,
+                },
+            },
+            pos: 0,
+            depth: 0,
+            too_deep_reported: false,
+            nodes: Nodes::default(),
+            skipped: Vec::new(),
+            diag_bag: DiagBag::new(),
+            trace: None,
+            trace_depth: 0,
+        }
+    }
+
+    fn peek(&self) -> Word {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, n: usize) -> Word {
+        self.words.get(self.pos + n).copied().unwrap_or(self.eof)
+    }
+
+    fn at(&self, category: Category) -> bool {
+        self.peek().category == category
+    }
+
+    fn bump(&mut self) -> Word {
+        let word = self.peek();
+        if self.pos < self.words.len() {
+            set_current_span(word.lexeme);
+            self.pos += 1;
+        }
+        word
+    }
+
+    /// Bumps the current word if it's a `category` one.
+    fn eat(&mut self, category: Category) -> bool {
+        let is_there = self.at(category);
+        if is_there {
+            self.bump();
+        }
+        is_there
+    }
+
+    /// Where the last word bumped ends.
+    fn prev_end(&self) -> BytePos {
+        match self.pos.checked_sub(1) {
+            Some(prev) => self.words[prev].lexeme.end,
+            None => self.source_file.start_pos,
+        }
+    }
+
+    fn span_from(&self, start: BytePos) -> Span {
+        Span {
+            start,
+            end: self.prev_end(),
+        }
+    }
+
+    fn text(&self, word: Word) -> &'src str {
+        self.source_file.span_to_snippet(word.lexeme)
+    }
+
+    fn unexpected<T>(&mut self, expected: Expected) -> PResult<T> {
+        let found = self.peek();
+        self.diag_bag.push(Diag::UnexpectedToken {
+            expected,
+            found: found.category,
+            span: found.lexeme,
+        });
+        Err(SyntaxError)
+    }
+
+    /// Bumps a `category` word, reporting an error if it isn't there.
+    ///
+    /// A missing `;`, `)` or `]` is reported as such, with a fix inserting
+    /// it. If the word before it ends a line, the token most likely belongs
+    /// there and parsing goes on as if it had been written.
+    fn expect(&mut self, category: Category) -> PResult<()> {
+        if self.eat(category) {
+            return Ok(());
+        }
+
+        if !matches!(
+            category,
+            Category::Semicolon | Category::CloseParen | Category::CloseBracket
+        ) {
+            return self.unexpected(Expected::Token(category));
+        }
+
+        if self.report_missing(category) {
+            Ok(())
+        } else {
+            Err(SyntaxError)
+        }
+    }
+
+    /// Reports a `category` word missing after the last word bumped, and
+    /// returns whether that word ends its line.
+    fn report_missing(&mut self, category: Category) -> bool {
+        let found = self.peek();
+        let pos = self.prev_end();
+        let at_line_end = self.at_line_end();
+        self.diag_bag.push(Diag::MissingToken {
+            token: category,
+            found: found.category,
+            pos,
+            at_line_end,
+        });
+        at_line_end
+    }
+
+    /// Whether the last word bumped ends its line.
+    fn at_line_end(&self) -> bool {
+        let found = self.peek();
+        found.category == Category::Eof
+            || self
+                .source_file
+                .span_to_snippet(Span {
+                    start: self.prev_end(),
+                    end: found.lexeme.start,
+                })
+                .contains('\n')
+    }
+
+    /// The index of the `;` ending the current line, if there are words
+    /// before it, e.g. the `= 1` in `int x = 1;`. Nothing is returned if a
+    /// word on the way could start something else, e.g. a `{`.
+    fn stray_words_before_semicolon(&self) -> Option<usize> {
+        if self.at(Category::Semicolon) {
+            return None;
+        }
+
+        let mut prev_end = self.prev_end();
+        for (index, word) in self.words.iter().enumerate().skip(self.pos) {
+            let gap = Span {
+                start: prev_end,
+                end: word.lexeme.start,
+            };
+            if self.source_file.span_to_snippet(gap).contains('\n') {
+                return None;
+            }
+
+            match word.category {
+                Category::Semicolon => return Some(index),
+                Category::OpenCurly | Category::CloseCurly => return None,
+                category if starts_type(category) => return None,
+                _ => prev_end = word.lexeme.end,
+            }
+        }
+
+        None
+    }
+
+    /// Reports the construct at `span` if it needs `extension` and the
+    /// program wasn't compiled with it. It's parsed all the same.
+    fn require(&mut self, extension: Extension, span: Span) {
+        if !self.extensions.is_enabled(extension) {
+            self.diag_bag
+                .push(Diag::ExtensionNotEnabled { extension, span });
+        }
+    }
+
+    fn alloc_expr(&mut self, kind: ExprKind, span: Span) -> ExprId {
+        self.nodes.exprs.alloc(Expr { kind, span })
+    }
+
+    /// Runs `parse` one level of nesting deeper, so that absurdly nested
+    /// programs are reported instead of overflowing the stack.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Parser<'src>) -> PResult<T>,
+    ) -> PResult<T> {
+        self.deeper()?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Runs `parse`, which parses a chain of operators, e.g. `a + b + c`,
+    /// going one level of nesting deeper with `deeper` for each of them, as
+    /// the tree of a chain is as deep as it's long. The levels are left
+    /// where the chain ends.
+    fn chained<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Parser<'src>) -> PResult<T>,
+    ) -> PResult<T> {
+        let depth = self.depth;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+
+    /// Goes one level of nesting deeper, unless it's one too many.
+    fn deeper(&mut self) -> PResult<()> {
+        if self.depth == MAX_NESTING_DEPTH {
+            if !self.too_deep_reported {
+                let span = self.peek().lexeme;
+                self.diag_bag.push(Diag::NestingTooDeep { span });
+                self.too_deep_reported = true;
+            }
+            return Err(SyntaxError);
+        }
+
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Runs `parse`, the function of the grammar rule `rule`, adding to the
+    /// trace, if there's one, when it's entered and when it's left.
+    fn traced<T>(
+        &mut self,
+        rule: &str,
+        parse: impl FnOnce(&mut Parser<'src>) -> PResult<T>,
+    ) -> PResult<T> {
+        if self.trace.is_none() {
+            return parse(self);
+        }
+
+        self.trace_line(&format!("-> {}", rule));
+        self.trace_depth += 1;
+        let result = parse(self);
+        self.trace_depth -= 1;
+        let outcome = if result.is_ok() { "" } else { " (error)" };
+        self.trace_line(&format!("<- {}{}", rule, outcome));
+        result
+    }
+
+    /// Adds `event` to the trace, indented by how deep it happened, with the
+    /// word the parser is looking at and where it is.
+    fn trace_line(&mut self, event: &str) {
+        let word = self.peek();
+        let lookahead = match word.category {
+            Category::Eof => "end of file".to_string(),
+            _ => format!("`{}`", self.text(word)),
+        };
+        let locs = SpanLocs::resolve(self.source_file, word.lexeme);
+        if let Some(trace) = &mut self.trace {
+            trace.push_str(&format!(
+                "{:indent$}{}, at {} {}:{}\n",
+                "",
+                event,
+                lookahead,
+                locs.line_start,
+                locs.column_start,
+                indent = 2 * self.trace_depth,
+            ));
+        }
+    }
+
+    /// Skips to the next declaration at file scope, i.e. a type specifier
+    /// outside of braces. The declaration being skipped starts at `start`.
+    fn recover_decl(&mut self, start: BytePos) {
+        let mut depth = 0usize;
+        loop {
+            match self.peek().category {
+                Category::Eof => break,
+                category if depth == 0 && starts_type(category) => break,
+                Category::OpenCurly => depth += 1,
+                Category::CloseCurly => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.bump();
+        }
+        self.record_skipped(start);
+    }
+
+    /// Skips past the end of the current statement, or to the start of the
+    /// next one, or to the end of the block it's in. Blocks opened in the
+    /// statement are skipped whole. The statement being skipped starts at
+    /// `start`.
+    fn recover_stmt(&mut self, start: BytePos) {
+        let mut depth = 0usize;
+        loop {
+            match self.peek().category {
+                Category::Eof => break,
+                Category::CloseCurly
+                | Category::Kw(
+                    Keyword::If
+                    | Keyword::While
+                    | Keyword::For
+                    | Keyword::Do
+                    | Keyword::Switch
+                    | Keyword::Break
+                    | Keyword::Continue
+                    | Keyword::Return,
+                ) if depth == 0 => break,
+                Category::Semicolon if depth == 0 => {
+                    self.bump();
+                    break;
+                }
+                Category::OpenCurly => depth += 1,
+                Category::CloseCurly => depth -= 1,
+                _ => {}
+            }
+            self.bump();
+        }
+        self.record_skipped(start);
+    }
+
+    /// Records the words from `start` up to the last one bumped as skipped,
+    /// if there are any.
+    fn record_skipped(&mut self, start: BytePos) {
+        let span = self.span_from(start);
+        if span.end > span.start {
+            self.skipped.push(span);
+        }
+    }
+
+    /// program → declaration-list
+    fn parse_program(&mut self) -> Program {
+        let mut decls = Vec::new();
+        while !self.at(Category::Eof) {
+            let start = self.peek().lexeme.start;
+            self.too_deep_reported = false;
+            match self.parse_decl() {
+                Ok(decl) => decls.extend(decl),
+                Err(SyntaxError) => self.recover_decl(start),
+            }
+        }
+
+        Program {
+            decls,
+            nodes: std::mem::take(&mut self.nodes),
+        }
+    }
+
+    /// declaration → var-declaration | fun-declaration
+    ///             | struct-declaration  (with `Extension::Structs`)
+    ///
+    /// A var-declaration of more than one variable is a declaration of each.
+    fn parse_decl(&mut self) -> PResult<Vec<Decl>> {
+        self.traced("declaration", |parser| {
+            if !parser.at_type_spec() {
+                return parser.unexpected(Expected::Declaration);
+            }
+            // `struct s {` defines the struct, while `struct s x;` only uses it.
+            if parser.at(Category::Kw(Keyword::Struct))
+                && parser.peek_nth(2).category == Category::OpenCurly
+            {
+                return parser
+                    .parse_struct_decl()
+                    .map(|struct_decl| vec![Decl::Struct(struct_decl)]);
+            }
+
+            let start = parser.peek().lexeme.start;
+            let is_const = parser.parse_const();
+            let ty = parser.parse_type_spec()?;
+            let pointers = parser.parse_pointers();
+            let name = parser.parse_ident()?;
+
+            if parser.at(Category::OpenParen) {
+                parser
+                    .parse_fun_decl_rest(start, is_const, ty, pointers, name)
+                    .map(|fun_decl| vec![Decl::Fun(fun_decl)])
+            } else {
+                let var_decls = parser.parse_var_decl_rest(
+                    start, is_const, ty, pointers, name, true,
+                )?;
+                Ok(var_decls.into_iter().map(Decl::Var).collect())
+            }
+        })
+    }
+
+    /// type-specifier → `int` | `void`
+    ///                | `char`  (with `Extension::Char`)
+    ///                | `struct` ID  (with `Extension::Structs`)
+    fn parse_type_spec(&mut self) -> PResult<TypeSpec> {
+        self.traced("type-specifier", |parser| match parser.peek().category {
+            Category::Kw(Keyword::Struct) => {
+                let struct_kw = parser.bump().lexeme;
+                parser.require(Extension::Structs, struct_kw);
+                parser.parse_ident().map(TypeSpec::Struct)
+            }
+            Category::Kw(Keyword::Char) => {
+                let char_kw = parser.bump().lexeme;
+                parser.require(Extension::Char, char_kw);
+                Ok(TypeSpec::Char)
+            }
+            Category::Kw(Keyword::Int) => {
+                parser.bump();
+                Ok(TypeSpec::Int)
+            }
+            Category::Kw(Keyword::Void) => {
+                parser.bump();
+                Ok(TypeSpec::Void)
+            }
+            _ => parser.unexpected(Expected::TypeSpecifier),
+        })
+    }
+
+    /// struct-declaration → `struct` ID `{` field-declarations `}` `;`
+    /// field-declarations → field-declarations var-declaration | empty
+    ///
+    /// The fields are declared like the local variables of a block.
+    fn parse_struct_decl(&mut self) -> PResult<StructDecl> {
+        self.traced("struct-declaration", |parser| {
+            let start = parser.peek().lexeme.start;
+            let struct_kw = parser.bump().lexeme;
+            parser.require(Extension::Structs, struct_kw);
+            let name = parser.parse_ident()?;
+            parser.expect(Category::OpenCurly)?;
+
+            let mut fields = Vec::new();
+            while parser.at_type_spec() {
+                let start = parser.peek().lexeme.start;
+                match parser.parse_local_var_decl() {
+                    Ok(field) => fields.extend(field),
+                    Err(SyntaxError) => parser.recover_stmt(start),
+                }
+            }
+            parser.expect(Category::CloseCurly)?;
+            parser.expect(Category::Semicolon)?;
+
+            Ok(StructDecl {
+                name,
+                fields,
+                span: parser.span_from(start),
+            })
+        })
+    }
+
+    /// The `const` before a type specifier, e.g. `const int x = 5;`, with
+    /// `Extension::Const`. Returns whether there's one.
+    fn parse_const(&mut self) -> bool {
+        if !self.at(Category::Kw(Keyword::Const)) {
+            return false;
+        }
+        let const_kw = self.bump().lexeme;
+        self.require(Extension::Const, const_kw);
+        true
+    }
+
+    /// The `*`s of a pointer declarator, e.g. `int **p`, between the type
+    /// specifier and the name. Returns how many there are.
+    fn parse_pointers(&mut self) -> u32 {
+        let mut pointers = 0;
+        while self.at(Category::Star) {
+            let star = self.bump().lexeme;
+            self.require(Extension::Pointers, star);
+            pointers += 1;
+        }
+        pointers
+    }
+
+    fn parse_ident(&mut self) -> PResult<Ident> {
+        if !self.at(Category::Ident) {
+            return self.unexpected(Expected::Token(Category::Ident));
+        }
+
+        let word = self.bump();
+        Ok(Ident {
+            name: self.text(word).to_owned(),
+            span: word.lexeme,
+        })
+    }
+
+    fn parse_number(&mut self) -> PResult<Number> {
+        if !self.at(Category::Number) {
+            return self.unexpected(Expected::Token(Category::Number));
+        }
+
+        let word = self.bump();
+        Ok(Number {
+            value: self.text(word).parse().unwrap_or(u64::MAX),
+            span: word.lexeme,
+        })
+    }
+
+    /// var-declaration → type-specifier ID `;`
+    ///                 | type-specifier ID `[` NUM `]` `;`
+    ///                 | type-specifier ID `[` NUM `]` `[` NUM `]`... `;`
+    ///                   (with `Extension::MultiDimArrays`)
+    ///                 | type-specifier ID `=` expression `;`
+    ///                   (at file scope, with `Extension::GlobalInit`)
+    ///                 | type-specifier declarator `,` declarator... `;`
+    ///                   (with `Extension::DeclaratorLists`)
+    ///
+    /// A declarator is what follows the type specifier in the others, e.g.
+    /// `a[10]`, and the list declares a variable for each, e.g. `int a, b;`
+    /// declares `a` and `b`. The span of each variable goes from where its
+    /// declaration starts, the type specifier for the first and the
+    /// declarator for the others, to the end of its declarator, or to the
+    /// `;` for the last.
+    ///
+    /// The type specifier may have a `const` before it, with
+    /// `Extension::Const`, and the name `*`s, with `Extension::Pointers`.
+    /// An array size may be any conditional-expression instead of a NUM,
+    /// with `Extension::SizeExpressions`. The `*`s are of one declarator, as
+    /// in C, so `int *p, x;` declares a pointer and an `int`. Everything up
+    /// to the first name was already parsed. Anywhere but at file scope, an
+    /// `=` is a stray token like any other.
+    fn parse_var_decl_rest(
+        &mut self,
+        start: BytePos,
+        is_const: bool,
+        ty: TypeSpec,
+        pointers: u32,
+        name: Ident,
+        at_file_scope: bool,
+    ) -> PResult<Vec<VarDecl>> {
+        self.traced("var-declaration", |parser| {
+            let (mut start, mut pointers, mut name) = (start, pointers, name);
+            let mut var_decls = Vec::new();
+            loop {
+                let (sizes, init) =
+                    parser.parse_declarator_rest(&name, at_file_scope)?;
+                var_decls.push(VarDecl {
+                    is_const,
+                    ty: ty.clone(),
+                    pointers,
+                    name,
+                    sizes,
+                    init,
+                    span: parser.span_from(start),
+                });
+
+                if !parser.at(Category::Comma) {
+                    break;
+                }
+                let comma = parser.bump().lexeme;
+                parser.require(Extension::DeclaratorLists, comma);
+                start = parser.peek().lexeme.start;
+                pointers = parser.parse_pointers();
+                name = parser.parse_ident()?;
+            }
+
+            let last = var_decls.len() - 1;
+            if let Some(semicolon) = parser.stray_words_before_semicolon() {
+                let first = parser.peek();
+                let after = parser.prev_end();
+                parser.pos = semicolon;
+                parser.diag_bag.push(Diag::StrayTokens {
+                    name: var_decls[last].name.name.clone(),
+                    found: first.category,
+                    after,
+                    span: parser.span_from(first.lexeme.start),
+                });
+            }
+            parser.expect(Category::Semicolon)?;
+
+            var_decls[last].span = parser.span_from(var_decls[last].span.start);
+            Ok(var_decls)
+        })
+    }
+
+    /// The array sizes and the initializer after the name of a declarator.
+    fn parse_declarator_rest(
+        &mut self,
+        name: &Ident,
+        at_file_scope: bool,
+    ) -> PResult<(Vec<ExprId>, Option<ExprId>)> {
+        let mut sizes = Vec::new();
+        let mut is_array = false;
+        while self.at(Category::OpenBracket) {
+            let open = self.bump().lexeme;
+            if is_array {
+                self.require(Extension::MultiDimArrays, open);
+            }
+            is_array = true;
+            sizes.extend(self.parse_array_size(name, open)?);
+        }
+
+        let init = if at_file_scope && !is_array && self.at(Category::Equal) {
+            let equal = self.bump().lexeme;
+            self.require(Extension::GlobalInit, equal);
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+
+        Ok((sizes, init))
+    }
+
+    /// The size between the `[`, `open`, and the `]` of a dimension of the
+    /// array `name`, if there's one, which is reported if there isn't.
+    fn parse_array_size(
+        &mut self,
+        name: &Ident,
+        open: Span,
+    ) -> PResult<Option<ExprId>> {
+        if self.at(Category::CloseBracket) {
+            let close = self.bump().lexeme;
+            self.diag_bag.push(Diag::MissingArraySize {
+                name: name.name.clone(),
+                span: Span {
+                    start: open.start,
+                    end: close.end,
+                },
+            });
+            return Ok(None);
+        }
+
+        let size = self.parse_conditional_expr()?;
+        if !matches!(self.nodes[size].kind, ExprKind::Number(_)) {
+            self.require(Extension::SizeExpressions, self.nodes[size].span);
+        }
+        self.expect(Category::CloseBracket)?;
+        Ok(Some(size))
+    }
+
+    /// fun-declaration → type-specifier ID `(` params `)` compound-stmt
+    ///
+    /// The type specifier and the name were already parsed.
+    fn parse_fun_decl_rest(
+        &mut self,
+        start: BytePos,
+        return_is_const: bool,
+        return_ty: TypeSpec,
+        return_pointers: u32,
+        name: Ident,
+    ) -> PResult<FunDecl> {
+        self.traced("fun-declaration", |parser| {
+            let params_start = parser.peek().lexeme.start;
+            parser.expect(Category::OpenParen)?;
+            let params = parser.parse_params()?;
+            parser.expect(Category::CloseParen)?;
+            let params_span = parser.span_from(params_start);
+            let body = parser.parse_block()?;
+
+            Ok(FunDecl {
+                return_is_const,
+                return_ty,
+                return_pointers,
+                name,
+                params,
+                params_span,
+                body,
+                span: parser.span_from(start),
+            })
+        })
+    }
+
+    /// params → param-list | `void`
+    ///
+    /// An empty list, which C- doesn't allow, and a `void` next to other
+    /// parameters are reported, and taken as if they weren't there.
+    fn parse_params(&mut self) -> PResult<Vec<Param>> {
+        self.traced("params", |parser| {
+            if parser.at(Category::CloseParen) {
+                let pos = parser.peek().lexeme.start;
+                parser.diag_bag.push(Diag::EmptyParamList { pos });
+                return Ok(Vec::new());
+            }
+
+            if parser.at(Category::Kw(Keyword::Void))
+                && parser.peek_nth(1).category == Category::CloseParen
+            {
+                parser.bump();
+                return Ok(Vec::new());
+            }
+
+            let mut params = Vec::new();
+            loop {
+                if parser.at(Category::Kw(Keyword::Void))
+                    && matches!(
+                        parser.peek_nth(1).category,
+                        Category::Comma | Category::CloseParen
+                    )
+                {
+                    let void = parser.bump().lexeme;
+                    // Removing the `void` along with the comma separating it from
+                    // the other parameters.
+                    let removal = match params.last() {
+                        Some(Param { span, .. }) => Span {
+                            start: span.end,
+                            end: void.end,
+                        },
+                        None => Span {
+                            start: void.start,
+                            end: parser.peek_nth(1).lexeme.start,
+                        },
+                    };
+                    parser.diag_bag.push(Diag::VoidParamNotAlone {
+                        span: void,
+                        removal,
+                    });
+                } else {
+                    params.push(parser.parse_param()?);
+                }
+
+                if !parser.eat(Category::Comma) {
+                    return Ok(params);
+                }
+            }
+        })
+    }
+
+    /// param → type-specifier ID | type-specifier ID `[` `]`
+    ///       | type-specifier ID `[` `]` `[` NUM `]`...
+    ///         (with `Extension::MultiDimArrays`)
+    ///
+    /// There may be a `const` and `*`s, as in a variable declaration.
+    fn parse_param(&mut self) -> PResult<Param> {
+        self.traced("param", |parser| {
+            let start = parser.peek().lexeme.start;
+            let is_const = parser.parse_const();
+            let ty = parser.parse_type_spec()?;
+            let pointers = parser.parse_pointers();
+            let name = parser.parse_ident()?;
+            let is_array = parser.eat(Category::OpenBracket);
+            let mut sizes = Vec::new();
+            if is_array {
+                parser.expect(Category::CloseBracket)?;
+                while parser.at(Category::OpenBracket) {
+                    let open = parser.bump().lexeme;
+                    parser.require(Extension::MultiDimArrays, open);
+                    sizes.extend(parser.parse_array_size(&name, open)?);
+                }
+            }
+
+            Ok(Param {
+                is_const,
+                ty,
+                pointers,
+                name,
+                is_array,
+                sizes,
+                span: parser.span_from(start),
+            })
+        })
+    }
+
+    /// compound-stmt → `{` local-declarations statement-list `}`
+    fn parse_block(&mut self) -> PResult<BlockId> {
+        self.traced("compound-stmt", |parser| {
+            let start = parser.peek().lexeme.start;
+            parser.expect(Category::OpenCurly)?;
+
+            let mut decls = Vec::new();
+            while parser.at_type_spec() {
+                let start = parser.peek().lexeme.start;
+                match parser.parse_local_var_decl() {
+                    Ok(var_decls) => decls.extend(var_decls),
+                    Err(SyntaxError) => parser.recover_stmt(start),
+                }
+            }
+
+            // Declarations after statements are reported, but kept with the
+            // others, to go on parsing.
+            let stmts_start = parser.peek().lexeme;
+            let mut stmts = Vec::new();
+            while !parser.at(Category::CloseCurly) && !parser.at(Category::Eof)
+            {
+                let start = parser.peek().lexeme.start;
+                if parser.at_type_spec() {
+                    match parser.parse_local_var_decl() {
+                        Ok(var_decls) => {
+                            let span = Span {
+                                start: var_decls[0].span.start,
+                                end: var_decls[var_decls.len() - 1].span.end,
+                            };
+                            parser.diag_bag.push(
+                                Diag::DeclarationAfterStatement {
+                                    span,
+                                    first_stmt: stmts_start,
+                                },
+                            );
+                            decls.extend(var_decls);
+                        }
+                        Err(SyntaxError) => parser.recover_stmt(start),
+                    }
+                    continue;
+                }
+
+                match parser.parse_stmt() {
+                    Ok(stmt) => stmts.push(stmt),
+                    Err(SyntaxError) => parser.recover_stmt(start),
+                }
+            }
+            // A block cut short by the end of the input is kept, with what's in
+            // it, rather than losing the whole function it's in.
+            let _ = parser.expect(Category::CloseCurly);
+
+            let span = parser.span_from(start);
+            Ok(parser.nodes.blocks.alloc(Block { decls, stmts, span }))
+        })
+    }
+
+    fn at_type_spec(&self) -> bool {
+        starts_type(self.peek().category)
+    }
+
+    /// local-declarations → local-declarations var-declaration | empty
+    fn parse_local_var_decl(&mut self) -> PResult<Vec<VarDecl>> {
+        let start = self.peek().lexeme.start;
+        let is_const = self.parse_const();
+        let ty = self.parse_type_spec()?;
+        let pointers = self.parse_pointers();
+        let name = self.parse_ident()?;
+        self.parse_var_decl_rest(start, is_const, ty, pointers, name, false)
+    }
+
+    /// statement → expression-stmt | compound-stmt | selection-stmt
+    ///           | iteration-stmt | return-stmt
+    fn parse_stmt(&mut self) -> PResult<StmtId> {
+        self.traced("statement", |parser| {
+            parser.nested(|parser| {
+                let start = parser.peek().lexeme.start;
+                let kind = parser.parse_stmt_kind()?;
+                let span = parser.span_from(start);
+
+                Ok(parser.nodes.stmts.alloc(Stmt { kind, span }))
+            })
+        })
+    }
+
+    fn parse_stmt_kind(&mut self) -> PResult<StmtKind> {
+        match self.peek().category {
+            Category::OpenCurly => self.parse_block().map(StmtKind::Block),
+            // selection-stmt → `if` `(` expression `)` statement
+            //                | `if` `(` expression `)` statement `else` statement
+            //
+            // An `else` goes with the closest `if` without one.
+            Category::Kw(Keyword::If) => {
+                self.bump();
+                let cond = self.parse_condition(Keyword::If)?;
+                let then_branch = self.parse_stmt()?;
+                let else_branch = if self.eat(Category::Kw(Keyword::Else)) {
+                    Some(self.parse_stmt()?)
+                } else {
+                    None
+                };
+
+                Ok(StmtKind::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                })
+            }
+            // iteration-stmt → `while` `(` expression `)` statement
+            Category::Kw(Keyword::While) => {
+                self.bump();
+                let cond = self.parse_condition(Keyword::While)?;
+                let body = self.parse_stmt()?;
+
+                Ok(StmtKind::While { cond, body })
+            }
+            // do-while-stmt → `do` statement `while` `(` expression `)` `;`
+            //
+            // The `;` is easy to forget, as a `while` doesn't have it, so
+            // it's reported on its own.
+            Category::Kw(Keyword::Do) => {
+                let do_span = self.bump().lexeme;
+                self.require(Extension::DoWhile, do_span);
+                let body = self.parse_stmt()?;
+                self.expect(Category::Kw(Keyword::While))?;
+                let cond = self.parse_condition(Keyword::While)?;
+                if !self.eat(Category::Semicolon) {
+                    let at_line_end = self.at_line_end();
+                    self.diag_bag.push(Diag::DoWhileWithoutSemicolon {
+                        do_span,
+                        pos: self.prev_end(),
+                        at_line_end,
+                    });
+                    if !at_line_end {
+                        return Err(SyntaxError);
+                    }
+                }
+
+                Ok(StmtKind::DoWhile { body, cond })
+            }
+            // for-stmt → `for` `(` [expression] `;` [expression] `;`
+            //            [expression] `)` statement
+            Category::Kw(Keyword::For) => {
+                let keyword = self.bump().lexeme;
+                self.require(Extension::For, keyword);
+                self.expect(Category::OpenParen)?;
+                let init = self.parse_opt_expr(Category::Semicolon)?;
+                self.expect(Category::Semicolon)?;
+                let cond = self.parse_opt_expr(Category::Semicolon)?;
+                self.expect(Category::Semicolon)?;
+                let step = self.parse_opt_expr(Category::CloseParen)?;
+                self.expect(Category::CloseParen)?;
+                let body = self.parse_stmt()?;
+
+                Ok(StmtKind::For {
+                    init,
+                    cond,
+                    step,
+                    body,
+                })
+            }
+            // switch-stmt → `switch` `(` expression `)` `{` switch-case* `}`
+            Category::Kw(Keyword::Switch) => {
+                let keyword = self.bump().lexeme;
+                self.require(Extension::Switch, keyword);
+                let cond = self.parse_condition(Keyword::Switch)?;
+                let cases = self.parse_switch_body()?;
+
+                Ok(StmtKind::Switch { cond, cases })
+            }
+            // break-stmt → `break` `;`
+            Category::Kw(Keyword::Break) => {
+                let keyword = self.bump().lexeme;
+                self.require(Extension::Break, keyword);
+                self.expect(Category::Semicolon)?;
+                Ok(StmtKind::Break)
+            }
+            // continue-stmt → `continue` `;`
+            Category::Kw(Keyword::Continue) => {
+                let keyword = self.bump().lexeme;
+                self.require(Extension::Continue, keyword);
+                self.expect(Category::Semicolon)?;
+                Ok(StmtKind::Continue)
+            }
+            // return-stmt → `return` `;` | `return` expression `;`
+            Category::Kw(Keyword::Return) => {
+                self.bump();
+                let value = if self.at(Category::Semicolon) {
+                    None
+                } else {
+                    Some(self.parse_expr()?)
+                };
+                self.expect(Category::Semicolon)?;
+
+                Ok(StmtKind::Return(value))
+            }
+            // The `if` of this `else` already ended, e.g. because of a `;`
+            // after its statement that should've been inside braces. The
+            // statement of the `else` is kept in place of it.
+            Category::Kw(Keyword::Else) => {
+                let span = self.bump().lexeme;
+                self.diag_bag.push(Diag::ElseWithoutIf { span });
+                self.nested(|parser| parser.parse_stmt_kind())
+            }
+            // expression-stmt → expression `;` | `;`
+            Category::Semicolon => {
+                self.bump();
+                Ok(StmtKind::Empty)
+            }
+            _ => {
+                let expr = self.parse_expr()?;
+                self.expect(Category::Semicolon)?;
+
+                Ok(StmtKind::Expr(expr))
+            }
+        }
+    }
+
+    /// The condition of an `if` or a `while`, which goes in parentheses.
+    /// One without them is reported, and taken as if they were there.
+    fn parse_condition(&mut self, keyword: Keyword) -> PResult<ExprId> {
+        if self.at(Category::OpenParen) {
+            let open = self.peek().lexeme;
+            let cond = self.parse_paren_expr()?;
+            if keyword != Keyword::Switch {
+                self.check_assign_in_condition(open, cond);
+            }
+            return Ok(cond);
+        }
+
+        let cond = self.parse_expr()?;
+        let span = self.nodes[cond].span;
+        self.diag_bag.push(Diag::ConditionWithoutParens {
+            keyword,
+            span,
+            text: self.source_file.span_to_snippet(span).to_owned(),
+        });
+        Ok(cond)
+    }
+
+    /// Warns about `cond` if it's an assignment right inside the parentheses
+    /// opened at `open`, e.g. `if (x = 1)`. In parentheses of its own, e.g.
+    /// `if ((x = 1))`, the assignment is taken to be meant.
+    fn check_assign_in_condition(&mut self, open: Span, cond: ExprId) {
+        let Expr { kind, span } = &self.nodes[cond];
+        let target = match *kind {
+            ExprKind::Assign { target, .. } => target,
+            _ => return,
+        };
+        let span = *span;
+        let before = Span {
+            start: open.end,
+            end: span.start,
+        };
+        if !self.source_file.span_to_snippet(before).trim().is_empty() {
+            return;
+        }
+
+        // The `=` is the first one after the target.
+        let target_end = self.nodes[target].span.end;
+        let after_target = self.source_file.span_to_snippet(Span {
+            start: target_end,
+            end: span.end,
+        });
+        let equal_start = target_end + BytePos(after_target.find('=').unwrap());
+        self.diag_bag.push(Diag::AssignInCondition {
+            span,
+            equal: Span {
+                start: equal_start,
+                end: equal_start + BytePos(1),
+            },
+            text: self.source_file.span_to_snippet(span).to_owned(),
+        });
+    }
+
+    /// The braces of a `switch`, with the cases in them.
+    ///
+    /// switch-case → `case` expression `:` statement*
+    ///             | `default` `:` statement*
+    fn parse_switch_body(&mut self) -> PResult<Vec<SwitchCase>> {
+        self.expect(Category::OpenCurly)?;
+        let mut cases: Vec<SwitchCase> = Vec::new();
+        while !self.at(Category::CloseCurly) && !self.at(Category::Eof) {
+            let start = self.peek().lexeme.start;
+            let result = if self.at(Category::Kw(Keyword::Case))
+                || self.at(Category::Kw(Keyword::Default))
+            {
+                self.parse_switch_label().map(|case| cases.push(case))
+            } else {
+                // A statement before the first label can never run. It's
+                // reported, and parsed for the errors in it.
+                if cases.is_empty() {
+                    let _ = self.unexpected::<()>(Expected::CaseLabel);
+                }
+                self.parse_stmt().map(|stmt| {
+                    if let Some(case) = cases.last_mut() {
+                        case.stmts.push(stmt);
+                    }
+                })
+            };
+            if let Err(SyntaxError) = result {
+                self.recover_stmt(start);
+            }
+        }
+        self.expect(Category::CloseCurly)?;
+        Ok(cases)
+    }
+
+    /// `case` expression `:` | `default` `:`
+    fn parse_switch_label(&mut self) -> PResult<SwitchCase> {
+        let start = self.peek().lexeme.start;
+        let value = if self.eat(Category::Kw(Keyword::Default)) {
+            None
+        } else {
+            self.bump();
+            Some(self.parse_expr()?)
+        };
+        self.expect(Category::Colon)?;
+
+        Ok(SwitchCase {
+            value,
+            label_span: self.span_from(start),
+            stmts: Vec::new(),
+        })
+    }
+
+    /// An expression, unless the next word is `end`, e.g. in the parentheses
+    /// of a `for`.
+    fn parse_opt_expr(&mut self, end: Category) -> PResult<Option<ExprId>> {
+        if self.at(end) {
+            Ok(None)
+        } else {
+            self.parse_expr().map(Some)
+        }
+    }
+
+    /// `(` expression `)`
+    fn parse_paren_expr(&mut self) -> PResult<ExprId> {
+        self.expect(Category::OpenParen)?;
+        let expr = self.parse_expr()?;
+        self.expect(Category::CloseParen)?;
+        Ok(expr)
+    }
+
+    /// expression → var `=` expression | conditional-expression
+    ///
+    /// Anything is parsed on the left of the `=`, and then reported if it
+    /// isn't a var, i.e. a variable or an element of an array.
+    fn parse_expr(&mut self) -> PResult<ExprId> {
+        self.traced("expression", |parser| {
+            parser.nested(|parser| {
+                let target = parser.parse_conditional_expr()?;
+                if !parser.at(Category::Equal) {
+                    return Ok(target);
+                }
+
+                let equal = parser.bump().lexeme;
+                // `=<` and `=>` are `<=` and `>=` the wrong way around.
+                let next = parser.peek();
+                let reversed = match next.category {
+                    Category::Less => Some(BinOp::LessEqual),
+                    Category::Greater => Some(BinOp::GreaterEqual),
+                    _ => None,
+                };
+                if let (Some(op), true) =
+                    (reversed, next.lexeme.start == equal.end)
+                {
+                    parser.bump();
+                    let span = Span {
+                        start: equal.start,
+                        end: next.lexeme.end,
+                    };
+                    parser.diag_bag.push(Diag::ReversedComparison { span, op });
+                    let rhs = parser.parse_additive_expr()?;
+                    return Ok(parser.binary(op, target, rhs));
+                }
+
+                if !is_lvalue(&parser.nodes, target) {
+                    let span = parser.nodes[target].span;
+                    parser
+                        .diag_bag
+                        .push(Diag::InvalidAssignTarget { span, equal });
+                }
+
+                let value = parser.parse_expr()?;
+                let span = parser.join(target, value);
+                Ok(parser.alloc_expr(ExprKind::Assign { target, value }, span))
+            })
+        })
+    }
+
+    /// conditional-expression → simple-expression `?` expression `:`
+    ///                            conditional-expression
+    ///                          | simple-expression
+    ///
+    /// With `Extension::Ternary`. It groups to the right, so
+    /// `a ? b : c ? d : e` is `a ? b : (c ? d : e)`.
+    fn parse_conditional_expr(&mut self) -> PResult<ExprId> {
+        self.traced("conditional-expression", |parser| {
+            let cond = parser.parse_simple_expr()?;
+            if !parser.at(Category::Question) {
+                return Ok(cond);
+            }
+
+            parser.nested(|parser| {
+                let question = parser.bump().lexeme;
+                parser.require(Extension::Ternary, question);
+                let then_expr = parser.parse_expr()?;
+                parser.expect(Category::Colon)?;
+                let else_expr = parser.parse_conditional_expr()?;
+                let span = parser.join(cond, else_expr);
+                let kind = ExprKind::Conditional {
+                    cond,
+                    then_expr,
+                    else_expr,
+                };
+                Ok(parser.alloc_expr(kind, span))
+            })
+        })
+    }
+
+    /// simple-expression → additive-expression relop additive-expression
+    ///                   | additive-expression
+    ///
+    /// Comparisons don't chain, so `a < b < c` is an error. It's parsed as
+    /// `(a < b) < c`, as C would, to go on parsing.
+    fn parse_simple_expr(&mut self) -> PResult<ExprId> {
+        self.traced("simple-expression", |parser| {
+            parser.chained(|parser| {
+                let mut lhs = parser.parse_additive_expr()?;
+                let mut prev_op_span = None;
+                while let Some(op) = relational_op(parser.peek().category) {
+                    parser.deeper()?;
+                    let op_span = parser.bump().lexeme;
+                    if let Some(first) = prev_op_span {
+                        parser.diag_bag.push(Diag::ChainedComparison {
+                            first,
+                            second: op_span,
+                        });
+                    }
+                    prev_op_span = Some(op_span);
+
+                    let rhs = parser.parse_additive_expr()?;
+                    lhs = parser.binary(op, lhs, rhs);
+                }
+
+                Ok(lhs)
+            })
+        })
+    }
+
+    /// additive-expression → additive-expression addop term | term
+    fn parse_additive_expr(&mut self) -> PResult<ExprId> {
+        self.traced("additive-expression", |parser| {
+            parser.chained(|parser| {
+                let mut lhs = parser.parse_term()?;
+                loop {
+                    let op = match parser.peek().category {
+                        Category::Plus => BinOp::Add,
+                        Category::Minus => BinOp::Sub,
+                        _ => return Ok(lhs),
+                    };
+                    parser.deeper()?;
+                    parser.bump();
+                    let rhs = parser.parse_term()?;
+                    lhs = parser.binary(op, lhs, rhs);
+                }
+            })
+        })
+    }
+
+    /// term → term mulop factor | factor
+    fn parse_term(&mut self) -> PResult<ExprId> {
+        self.traced("term", |parser| {
+            parser.chained(|parser| {
+                let mut lhs = parser.parse_factor()?;
+                loop {
+                    let op = match parser.peek().category {
+                        Category::Star => BinOp::Mul,
+                        Category::Slash => BinOp::Div,
+                        _ => return Ok(lhs),
+                    };
+                    parser.deeper()?;
+                    parser.bump();
+                    let rhs = parser.parse_factor()?;
+                    lhs = parser.binary(op, lhs, rhs);
+                }
+            })
+        })
+    }
+
+    /// factor → `(` expression `)` | var | call | NUM
+    ///        | `-` factor  (with `Extension::UnaryMinus`)
+    ///        | `*` factor | `&` factor  (with `Extension::Pointers`)
+    ///        | CHAR | STRING  (with `Extension::Char`)
+    ///
+    /// A unary operator binds tighter than any binary operator, as in C, so
+    /// `-a * b` is `(-a) * b`, and `*p + 1` is `(*p) + 1`. Only what has an
+    /// address can be the operand of `&`.
+    fn parse_factor(&mut self) -> PResult<ExprId> {
+        self.traced("factor", |parser| {
+            let (op, extension) = match parser.peek().category {
+                Category::Minus => (UnOp::Neg, Extension::UnaryMinus),
+                Category::Star => (UnOp::Deref, Extension::Pointers),
+                Category::Ampersand => (UnOp::AddrOf, Extension::Pointers),
+                _ => return parser.parse_primary(),
+            };
+
+            parser.nested(|parser| {
+                let op_span = parser.bump().lexeme;
+                parser.require(extension, op_span);
+                let operand = parser.parse_factor()?;
+                let operand_span = parser.nodes[operand].span;
+                if op == UnOp::AddrOf && !is_lvalue(&parser.nodes, operand) {
+                    parser
+                        .diag_bag
+                        .push(Diag::InvalidAddressOf { span: operand_span });
+                }
+
+                let span = Span {
+                    start: op_span.start,
+                    end: operand_span.end,
+                };
+                Ok(parser.alloc_expr(ExprKind::Unary { op, operand }, span))
+            })
+        })
+    }
+
+    /// The factors without a unary operator.
+    fn parse_primary(&mut self) -> PResult<ExprId> {
+        match self.peek().category {
+            Category::OpenParen => {
+                let start = self.peek().lexeme.start;
+                let expr = self.parse_paren_expr()?;
+                self.parse_postfix(expr, start)
+            }
+            Category::Number => {
+                let number = self.parse_number()?;
+                Ok(self.alloc_expr(ExprKind::Number(number.value), number.span))
+            }
+            Category::CharLiteral | Category::StringLiteral => {
+                let word = self.bump();
+                self.require(Extension::Char, word.lexeme);
+                let text = literal_text(self.text(word));
+                let kind = match word.category {
+                    // The scanner made sure it holds one ASCII character.
+                    Category::CharLiteral => ExprKind::Char(text.as_bytes()[0]),
+                    _ => ExprKind::Str(text),
+                };
+                Ok(self.alloc_expr(kind, word.lexeme))
+            }
+            Category::Ident => self.parse_var_or_call(),
+            _ => self.unexpected(Expected::Expression),
+        }
+    }
+
+    /// args → arg-list | empty
+    /// arg-list → arg-list `,` expression | expression
+    ///
+    /// A comma after the last argument, or missing between two of them, is
+    /// reported, and the arguments are taken as if it were right.
+    fn parse_args(&mut self) -> PResult<Vec<ExprId>> {
+        self.traced("args", |parser| {
+            let mut args = Vec::new();
+            while !parser.at(Category::CloseParen) {
+                args.push(parser.parse_expr()?);
+
+                if parser.at(Category::Comma) {
+                    let comma = parser.bump().lexeme;
+                    if parser.at(Category::CloseParen) {
+                        parser
+                            .diag_bag
+                            .push(Diag::TrailingComma { span: comma });
+                    }
+                } else if starts_expr(parser.peek().category) {
+                    parser.report_missing(Category::Comma);
+                } else {
+                    break;
+                }
+            }
+
+            Ok(args)
+        })
+    }
+
+    /// var → ID | ID `[` expression `]`
+    ///     | ID `[` expression `]` `[` expression `]`...
+    ///       (with `Extension::MultiDimArrays`)
+    ///     | var `.` ID  (with `Extension::Structs`)
+    /// call → ID `(` args `)`
+    ///
+    /// A field of a struct may be an array itself, e.g. `s.a[i]`, and an
+    /// element of an array may be a struct, e.g. `a[i].x`. So may what a call
+    /// returns be, e.g. `f().x` (see `parse_postfix`).
+    fn parse_var_or_call(&mut self) -> PResult<ExprId> {
+        self.traced("var", |parser| {
+            let name = parser.parse_ident()?;
+            let start = name.span.start;
+
+            let kind = if parser.eat(Category::OpenParen) {
+                let args = parser.parse_args()?;
+                parser.expect(Category::CloseParen)?;
+                ExprKind::Call { callee: name, args }
+            } else {
+                ExprKind::Var(name)
+            };
+
+            let span = parser.span_from(start);
+            let base = parser.alloc_expr(kind, span);
+            parser.parse_postfix(base, start)
+        })
+    }
+
+    /// The indices and fields after `base`, which starts at `start`, e.g. the
+    /// `[i].x` of `a[i].x`, or of `(*p)[i].x`. Each is one level of nesting
+    /// deeper than the one before it.
+    fn parse_postfix(
+        &mut self,
+        base: ExprId,
+        start: BytePos,
+    ) -> PResult<ExprId> {
+        self.chained(|parser| {
+            let mut base = base;
+            loop {
+                let kind = match parser.peek().category {
+                    Category::OpenBracket => {
+                        parser.deeper()?;
+                        let open = parser.bump().lexeme;
+                        // Indexing an element of an array picks an element of
+                        // a row.
+                        if let ExprKind::Index { .. } = parser.nodes[base].kind
+                        {
+                            parser.require(Extension::MultiDimArrays, open);
+                        }
+
+                        let index = parser.parse_expr()?;
+                        parser.expect(Category::CloseBracket)?;
+                        ExprKind::Index { array: base, index }
+                    }
+                    Category::Dot => {
+                        parser.deeper()?;
+                        let dot = parser.bump().lexeme;
+                        parser.require(Extension::Structs, dot);
+
+                        let field = parser.parse_ident()?;
+                        ExprKind::Member { base, field }
+                    }
+                    _ => return Ok(base),
+                };
+                let span = parser.span_from(start);
+                base = parser.alloc_expr(kind, span);
+            }
+        })
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: ExprId, rhs: ExprId) -> ExprId {
+        let span = self.join(lhs, rhs);
+        self.alloc_expr(ExprKind::Binary { op, lhs, rhs }, span)
+    }
+
+    /// The span from the start of `first` to the end of `last`.
+    fn join(&self, first: ExprId, last: ExprId) -> Span {
+        Span {
+            start: self.nodes[first].span.start,
+            end: self.nodes[last].span.end,
+        }
+    }
+}
+
+/// Whether an expression has an address, so that it can be assigned to, or be
+/// the operand of `&`. A field has one only if its struct has one, e.g. not
+/// the field of what a call returns.
+fn is_lvalue(nodes: &Nodes, expr: ExprId) -> bool {
+    match nodes[expr].kind {
+        ExprKind::Var(_)
+        | ExprKind::Index { .. }
+        | ExprKind::Unary {
+            op: UnOp::Deref, ..
+        } => true,
+        ExprKind::Member { base, .. } => is_lvalue(nodes, base),
+        _ => false,
+    }
+}
+
+/// Whether a word of this category can start a type, and so a declaration.
+fn starts_type(category: Category) -> bool {
+    matches!(
+        category,
+        Category::Kw(
+            Keyword::Int
+                | Keyword::Void
+                | Keyword::Char
+                | Keyword::Struct
+                | Keyword::Const
+        )
+    )
+}
+
+/// Whether a word of this category can start an expression.
+fn starts_expr(category: Category) -> bool {
+    matches!(
+        category,
+        Category::Ident
+            | Category::Number
+            | Category::Minus
+            | Category::Star
+            | Category::Ampersand
+            | Category::CharLiteral
+            | Category::StringLiteral
+            | Category::OpenParen
+    )
+}
+
+fn relational_op(category: Category) -> Option<BinOp> {
+    let op = match category {
+        Category::Less => BinOp::Less,
+        Category::LessEqual => BinOp::LessEqual,
+        Category::Greater => BinOp::Greater,
+        Category::GreaterEqual => BinOp::GreaterEqual,
+        Category::EqualEqual => BinOp::Equal,
+        Category::ExclamaEqual => BinOp::NotEqual,
+        _ => return None,
+    };
+
+    Some(op)
+}
+
+/// Parses the `words` scanned from `source_file` into a program, which may use
+/// `extensions`. Syntax errors are reported in the diagnostics of the result,
+/// and the program is kept without the parts that had them.
+pub(crate) fn parse_program(
+    source_file: &SourceFile,
+    words: &[Word],
+    extensions: Extensions,
+) -> ParseResult {
+    parse_program_into(source_file, words, extensions, Nodes::default())
+}
+
+/// Like `parse_program`, but with the nodes of the program stored after the
+/// ones already in `nodes`, whose ids stay valid. That's for parsing a part
+/// of a program again, e.g. a declaration that was edited, to put it back in
+/// the tree.
+pub(crate) fn parse_program_into(
+    source_file: &SourceFile,
+    words: &[Word],
+    extensions: Extensions,
+    nodes: Nodes,
+) -> ParseResult {
+    let mut parser = Parser::new(source_file, words, extensions);
+    parser.nodes = nodes;
+    let program = parser.parse_program();
+
+    ParseResult {
+        program,
+        skipped: parser.skipped,
+        diag_bag: parser.diag_bag,
+    }
+}
+
+/// Like `parse_program`, but also returns a trace of the rules of the
+/// grammar the parser entered and left on the way, for `--debug-parse-trace`.
+/// Every rule is on a line of its own, indented under the rule it's in, with
+/// the word the parser was looking at:
+///
+/// ```text
+/// -> declaration, at `int` 1:1
+///   -> type-specifier, at `int` 1:1
+///   <- type-specifier, at `x` 1:5
+///   -> var-declaration, at `;` 1:6
+///   <- var-declaration, at end of file 1:7
+/// <- declaration, at end of file 1:7
+/// ```
+pub(crate) fn parse_program_traced(
+    source_file: &SourceFile,
+    words: &[Word],
+    extensions: Extensions,
+) -> (ParseResult, String) {
+    let mut parser = Parser::new(source_file, words, extensions);
+    parser.trace = Some(String::new());
+    let program = parser.parse_program();
+    let trace = parser.trace.take().unwrap_or_default();
+
+    let result = ParseResult {
+        program,
+        skipped: parser.skipped,
+        diag_bag: parser.diag_bag,
+    };
+    (result, trace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_program_traced, Expected, ParseResult};
+    use crate::{
+        ast::{
+            BinOp, Decl, ExprId, ExprKind, FunDecl, Nodes, Param, Program,
+            Stmt, StmtKind, TypeSpec,
+        },
+        errors::{Applicability, Diag, MAX_NESTING_DEPTH},
+        extensions::{Extension, Extensions},
+        scanner::{quote_literal, scan_words, Category, Keyword},
+        source_map::{BytePos, SourceFile, Span},
+        test_support::parse_source,
+    };
+
+    fn parse(source: &str) -> (Program, Vec<Diag>) {
+        parse_with(source, Extensions::none())
+    }
+
+    fn parse_with(
+        source: &str,
+        extensions: Extensions,
+    ) -> (Program, Vec<Diag>) {
+        let (
+            _,
+            ParseResult {
+                program, diag_bag, ..
+            },
+        ) = parse_source(source, extensions);
+        let diags = diag_bag.iter().map(|diag| diag.diag.clone()).collect();
+        (program, diags)
+    }
+
+    /// The `n`th declaration of `program`, which must be a function.
+    fn fun(program: &Program, n: usize) -> &FunDecl {
+        let Decl::Fun(f) = &program.decls[n] else {
+            panic!("expected a function, found {:?}", program.decls[n]);
+        };
+        f
+    }
+
+    /// The statements in the body of `f`.
+    fn body<'a>(program: &'a Program, f: &FunDecl) -> Vec<&'a Stmt> {
+        let nodes = &program.nodes;
+        nodes[f.body]
+            .stmts
+            .iter()
+            .map(|&stmt| &nodes[stmt])
+            .collect()
+    }
+
+    /// `expr` as an S-expression, e.g. `(+ a (* b c))` for `a + b * c`.
+    fn sexpr(nodes: &Nodes, expr: ExprId) -> String {
+        match &nodes[expr].kind {
+            ExprKind::Number(value) => value.to_string(),
+            ExprKind::Char(value) => {
+                quote_literal(&char::from(*value).to_string(), '\'')
+            }
+            ExprKind::Str(text) => quote_literal(text, '"'),
+            ExprKind::Var(ident) => ident.name.clone(),
+            ExprKind::Index { array, index } => format!(
+                "([] {} {})",
+                sexpr(nodes, *array),
+                sexpr(nodes, *index)
+            ),
+            ExprKind::Member { base, field } => {
+                format!("(. {} {})", sexpr(nodes, *base), field.name)
+            }
+            ExprKind::Call { callee, args } => {
+                let args: Vec<_> =
+                    args.iter().map(|&arg| sexpr(nodes, arg)).collect();
+                format!("(call {} [{}])", callee.name, args.join(" "))
+            }
+            ExprKind::Assign { target, value } => format!(
+                "(= {} {})",
+                sexpr(nodes, *target),
+                sexpr(nodes, *value)
+            ),
+            ExprKind::Unary { op, operand } => {
+                format!("({} {})", op.as_str(), sexpr(nodes, *operand))
+            }
+            ExprKind::Binary { op, lhs, rhs } => format!(
+                "({} {} {})",
+                op.as_str(),
+                sexpr(nodes, *lhs),
+                sexpr(nodes, *rhs)
+            ),
+            ExprKind::Conditional {
+                cond,
+                then_expr,
+                else_expr,
+            } => format!(
+                "(? {} {} {})",
+                sexpr(nodes, *cond),
+                sexpr(nodes, *then_expr),
+                sexpr(nodes, *else_expr)
+            ),
+        }
+    }
+
+    /// The shape of the tree of the expression statement `source`.
+    fn expr_shape(source: &str) -> (String, Vec<Diag>) {
+        expr_shape_with(source, Extensions::none())
+    }
+
+    fn expr_shape_with(
+        source: &str,
+        extensions: Extensions,
+    ) -> (String, Vec<Diag>) {
+        let (program, diags) =
+            parse_with(&format!("void f(void) {{ {}; }}", source), extensions);
+        let stmt = body(&program, fun(&program, 0))[0];
+        let StmtKind::Expr(expr) = stmt.kind else {
+            panic!("expected an expression, found {:?}", stmt);
+        };
+        (sexpr(&program.nodes, expr), diags)
+    }
+
+    #[test]
+    fn expressions_respect_precedence() {
+        let cases = [
+            ("a = b < c + d * e", "(= a (< b (+ c (* d e))))"),
+            ("a * b + c", "(+ (* a b) c)"),
+            ("a + b == c * d", "(== (+ a b) (* c d))"),
+            ("(a + b) * c", "(* (+ a b) c)"),
+            (
+                "a[i + 1] = f(x, y * 2)",
+                "(= ([] a (+ i 1)) (call f [x (* y 2)]))",
+            ),
+            ("a - f() / b[0]", "(- a (/ (call f []) ([] b 0)))"),
+        ];
+
+        for (source, shape) in cases {
+            assert_eq!(expr_shape(source), (shape.to_owned(), vec![]));
+        }
+    }
+
+    #[test]
+    fn arithmetic_is_left_associative_and_assignment_right_associative() {
+        let cases = [
+            ("a - b - c", "(- (- a b) c)"),
+            ("a / b * c", "(* (/ a b) c)"),
+            ("a + b - c + d", "(+ (- (+ a b) c) d)"),
+            ("a = b = c", "(= a (= b c))"),
+        ];
+
+        for (source, shape) in cases {
+            assert_eq!(expr_shape(source), (shape.to_owned(), vec![]));
+        }
+    }
+
+    #[test]
+    fn comparisons_dont_chain() {
+        assert_eq!(
+            expr_shape("a < b <= c"),
+            (
+                "(<= (< a b) c)".to_owned(),
+                vec![Diag::ChainedComparison {
+                    first: Span::with_usizes(17, 18),
+                    second: Span::with_usizes(21, 23),
+                }]
+            )
+        );
+    }
+
+    #[test]
+    fn parse_a_whole_program() {
+        let (program, diags) = parse(
+            "int a[10];\n\
+             int gcd(int u, int v) {\n\
+                 if (v == 0) return u;\n\
+                 else return gcd(v, u - u / v * v);\n\
+             }\n\
+             void main(void) {\n\
+                 int x;\n\
+                 while (x < 10) { a[x] = x = input(); }\n\
+                 ;\n\
+             }\n",
+        );
+
+        assert_eq!(diags, Vec::new());
+        assert_eq!(program.decls.len(), 3);
+
+        let Decl::Var(array) = &program.decls[0] else {
+            panic!("expected a variable, found {:?}", program.decls[0]);
+        };
+        assert_eq!(array.ty, TypeSpec::Int);
+        assert_eq!(array.name.name, "a");
+        assert_eq!(
+            array
+                .sizes
+                .iter()
+                .map(|&size| program.nodes[size].kind.clone())
+                .collect::<Vec<_>>(),
+            [ExprKind::Number(10)]
+        );
+
+        let gcd = fun(&program, 1);
+        assert_eq!(gcd.name.name, "gcd");
+        assert_eq!(gcd.params.len(), 2);
+        assert!(matches!(
+            body(&program, gcd)[0].kind,
+            StmtKind::If {
+                else_branch: Some(_),
+                ..
+            }
+        ));
+
+        let main = fun(&program, 2);
+        assert_eq!(main.return_ty, TypeSpec::Void);
+        assert_eq!(main.params, Vec::new());
+        assert_eq!(program.nodes[main.body].decls.len(), 1);
+        let stmts = body(&program, main);
+        assert!(matches!(stmts[0].kind, StmtKind::While { .. }));
+        assert_eq!(stmts[1].kind, StmtKind::Empty);
+    }
+
+    #[test]
+    fn spans_cover_the_source_of_nodes() {
+        let source = "int f(void) { return 1 + 2; }";
+        let (program, _) = parse(source);
+
+        let f = fun(&program, 0);
+        assert_eq!(f.span.end.0, source.len());
+        let stmt = body(&program, f)[0];
+        let StmtKind::Return(Some(value)) = stmt.kind else {
+            panic!("expected a return, found {:?}", stmt);
+        };
+        let value = &program.nodes[value];
+        assert!(matches!(value.kind, ExprKind::Binary { .. }));
+        assert_eq!(&source[value.span.start.0..value.span.end.0], "1 + 2");
+    }
+
+    #[test]
+    fn parsing_goes_on_after_a_syntax_error() {
+        let (program, diags) = parse(
+            "int f(void) {\n\
+                 x = );\n\
+                 y = 1;\n\
+                 z = / 2;\n\
+             }\n\
+             int g(void) { }\n",
+        );
+
+        assert_eq!(diags.len(), 2);
+        assert!(diags.iter().all(|diag| matches!(
+            diag,
+            Diag::UnexpectedToken {
+                expected: Expected::Expression,
+                ..
+            }
+        )));
+        assert_eq!(program.decls.len(), 2);
+    }
+
+    #[test]
+    fn what_was_skipped_is_left_out_of_the_program() {
+        let source = "int 1 { x; }\n\
+                      int f(void) {\n\
+                      \x20   x = );\n\
+                      \x20   y = 1;\n\
+                      }\n";
+        let (_, result) = parse_source(source, Extensions::none());
+
+        let skipped: Vec<_> = result
+            .skipped
+            .iter()
+            .map(|span| &source[span.start.0..span.end.0])
+            .collect();
+        assert_eq!(skipped, ["int 1 { x; }", "x = );"]);
+        assert!(!result.is_complete());
+
+        // The function after the bad declaration is kept, without the bad
+        // statement.
+        let program = &result.program;
+        assert_eq!(program.decls.len(), 1);
+        assert_eq!(body(program, fun(program, 0)).len(), 1);
+    }
+
+    #[test]
+    fn a_function_cut_short_by_the_end_of_the_file_is_kept() {
+        let (program, diags) = parse("int f(void) {\n    return 1;\n");
+
+        assert_eq!(
+            diags,
+            vec![Diag::UnexpectedToken {
+                expected: Expected::Token(Category::CloseCurly),
+                found: Category::Eof,
+                span: crate::source_map::Span::with_usizes(28, 28),
+            }]
+        );
+        assert_eq!(body(&program, fun(&program, 0)).len(), 1);
+    }
+
+    #[test]
+    fn declarations_are_resynchronized_after_a_syntax_error() {
+        let (program, diags) = parse("int 1 { x; } int y;\n");
+
+        assert_eq!(
+            diags,
+            vec![Diag::UnexpectedToken {
+                expected: Expected::Token(Category::Ident),
+                found: Category::Number,
+                span: crate::source_map::Span::with_usizes(4, 5),
+            }]
+        );
+        assert_eq!(program.decls.len(), 1);
+    }
+
+    #[test]
+    fn missing_semicolon_at_the_end_of_a_line() {
+        let (program, diags) = parse("int f(void) {\n    x = 1\n    y = 2;\n}");
+
+        assert_eq!(
+            diags,
+            vec![Diag::MissingToken {
+                token: Category::Semicolon,
+                found: Category::Ident,
+                pos: BytePos(23),
+                at_line_end: true,
+            }]
+        );
+        let suggestions = diags[0].suggestions();
+        assert_eq!(suggestions[0].replacement, ";");
+        assert_eq!(
+            suggestions[0].applicability,
+            Applicability::MachineApplicable
+        );
+        // Parsing went on as if the `;` were there.
+        assert_eq!(body(&program, fun(&program, 0)).len(), 2);
+    }
+
+    #[test]
+    fn missing_token_in_the_middle_of_a_line_may_be_elsewhere() {
+        let (_, diags) = parse("int f(void) { if (x y) ; }");
+
+        assert_eq!(diags.len(), 1);
+        assert!(matches!(
+            diags[0],
+            Diag::MissingToken {
+                token: Category::CloseParen,
+                at_line_end: false,
+                ..
+            }
+        ));
+        assert_eq!(
+            diags[0].suggestions()[0].applicability,
+            Applicability::MaybeIncorrect
+        );
+    }
+
+    #[test]
+    fn variables_at_file_scope_and_in_blocks() {
+        let source = "int x;\nvoid f(void) { int a[10]; }";
+        let (program, diags) = parse(source);
+
+        assert_eq!(diags, Vec::new());
+        let Decl::Var(x) = &program.decls[0] else {
+            panic!("expected a variable, found {:?}", program.decls[0]);
+        };
+        assert_eq!(x.sizes, Vec::new());
+        assert_eq!(x.span, Span::with_usizes(0, 6));
+
+        let a = &program.nodes[fun(&program, 1).body].decls[0];
+        assert_eq!(a.ty, TypeSpec::Int);
+        assert_eq!(a.name.name, "a");
+        assert_eq!(a.sizes.len(), 1);
+        assert_eq!(program.nodes[a.sizes[0]].kind, ExprKind::Number(10));
+        assert_eq!(program.nodes[a.sizes[0]].span, Span::with_usizes(28, 30));
+        assert_eq!(&source[a.span.start.0..a.span.end.0], "int a[10];");
+    }
+
+    #[test]
+    fn array_without_a_size() {
+        let (program, diags) = parse("int a[];\nint b;");
+
+        assert_eq!(
+            diags,
+            vec![Diag::MissingArraySize {
+                name: "a".into(),
+                span: Span::with_usizes(5, 7),
+            }]
+        );
+        assert_eq!(program.decls.len(), 2);
+    }
+
+    #[test]
+    fn stray_words_after_a_declaration() {
+        let (program, diags) = parse("int x[1] = 1;\nint a[2] b c;");
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::StrayTokens {
+                    name: "x".into(),
+                    found: Category::Equal,
+                    after: BytePos(8),
+                    span: Span::with_usizes(9, 12),
+                },
+                Diag::StrayTokens {
+                    name: "a".into(),
+                    found: Category::Ident,
+                    after: BytePos(22),
+                    span: Span::with_usizes(23, 26),
+                },
+            ]
+        );
+        let suggestion = &diags[0].suggestions()[0];
+        assert_eq!(suggestion.span, Span::with_usizes(8, 12));
+        assert_eq!(suggestion.replacement, "");
+        assert_eq!(program.decls.len(), 2);
+    }
+
+    #[test]
+    fn global_variables_may_have_initializers() {
+        let source = "int x = 2 * -5;\nint y;\nvoid f(void) { int z = 1; }";
+        let (program, diags) = parse_with(source, Extensions::all());
+
+        let Decl::Var(x) = &program.decls[0] else {
+            panic!("expected a variable, found {:?}", program.decls[0]);
+        };
+        assert_eq!(x.span, Span::with_usizes(0, 15));
+        assert_eq!(sexpr(&program.nodes, x.init.unwrap()), "(* 2 (- 5))");
+        let Decl::Var(y) = &program.decls[1] else {
+            panic!("expected a variable, found {:?}", program.decls[1]);
+        };
+        assert_eq!(y.init, None);
+        assert!(matches!(
+            diags[..],
+            [Diag::StrayTokens {
+                found: Category::Equal,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn arrays_may_have_more_than_one_dimension() {
+        let source = "int m[3][4];\nvoid f(void) { m[i][j + 1] = m[0][1]; }";
+        let (program, diags) = parse_with(source, Extensions::all());
+
+        assert_eq!(diags, Vec::new());
+        let Decl::Var(m) = &program.decls[0] else {
+            panic!("expected a variable, found {:?}", program.decls[0]);
+        };
+        let sizes: Vec<_> = m
+            .sizes
+            .iter()
+            .map(|&size| program.nodes[size].kind.clone())
+            .collect();
+        assert_eq!(sizes, [ExprKind::Number(3), ExprKind::Number(4)]);
+        assert_eq!(m.span, Span::with_usizes(0, 12));
+
+        let stmt = body(&program, fun(&program, 1))[0];
+        let StmtKind::Expr(expr) = stmt.kind else {
+            panic!("expected an expression, found {:?}", stmt);
+        };
+        assert_eq!(
+            sexpr(&program.nodes, expr),
+            "(= ([] ([] m i) (+ j 1)) ([] ([] m 0) 1))"
+        );
+        let ExprKind::Assign { target, .. } = program.nodes[expr].kind else {
+            unreachable!()
+        };
+        assert_eq!(program.nodes[target].span, Span::with_usizes(28, 39));
+    }
+
+    #[test]
+    fn array_params_may_have_more_than_one_dimension() {
+        let source = "void f(int m[][4], int t[][2][3]) { }";
+        let (program, diags) = parse_with(source, Extensions::all());
+
+        assert_eq!(diags, Vec::new());
+        let params = &fun(&program, 0).params;
+        let sizes = |param: &Param| -> Vec<_> {
+            param
+                .sizes
+                .iter()
+                .map(|&size| program.nodes[size].kind.clone())
+                .collect()
+        };
+        assert!(params[0].is_array);
+        assert_eq!(sizes(&params[0]), [ExprKind::Number(4)]);
+        assert_eq!(params[0].span, Span::with_usizes(7, 17));
+        assert_eq!(
+            sizes(&params[1]),
+            [ExprKind::Number(2), ExprKind::Number(3)]
+        );
+        assert_eq!(params[1].span, Span::with_usizes(19, 32));
+
+        let (_, diags) = parse(source);
+        assert_eq!(
+            diags,
+            [14, 26, 29]
+                .map(|start| Diag::ExtensionNotEnabled {
+                    extension: Extension::MultiDimArrays,
+                    span: Span::with_usizes(start, start + 1),
+                })
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn more_dimensions_need_their_extension() {
+        let (_, diags) = parse("int m[3][4];\nvoid f(void) { m[1][2]; }");
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::MultiDimArrays,
+                    span: Span::with_usizes(8, 9),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::MultiDimArrays,
+                    span: Span::with_usizes(32, 33),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn size_expressions_need_their_extension() {
+        let (_, diags) = parse("int a[2 * 8];\nint b[n];\nint c[4];");
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::SizeExpressions,
+                    span: Span::with_usizes(6, 11),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::SizeExpressions,
+                    span: Span::with_usizes(20, 21),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn global_initializers_need_their_extension() {
+        let (_, diags) = parse("int x = 1;");
+
+        assert_eq!(
+            diags,
+            vec![Diag::ExtensionNotEnabled {
+                extension: Extension::GlobalInit,
+                span: Span::with_usizes(6, 7),
+            }]
+        );
+    }
+
+    #[test]
+    fn char_variables_parameters_and_literals() {
+        let source = "char c;\n\
+                      char s[8];\n\
+                      void f(char t[], char u) {\n\
+                      t[0] = 'a'; g(\"hi\\n\", '\\'');\n\
+                      }";
+        let (program, diags) = parse_with(source, Extensions::all());
+
+        assert_eq!(diags, Vec::new());
+        let Decl::Var(c) = &program.decls[0] else {
+            panic!("expected a variable, found {:?}", program.decls[0]);
+        };
+        assert_eq!(c.ty, TypeSpec::Char);
+        let Decl::Var(s) = &program.decls[1] else {
+            panic!("expected a variable, found {:?}", program.decls[1]);
+        };
+        assert_eq!((s.ty.clone(), s.sizes.len()), (TypeSpec::Char, 1));
+
+        let f = fun(&program, 2);
+        let params: Vec<_> = f
+            .params
+            .iter()
+            .map(|param| (param.ty.clone(), param.is_array))
+            .collect();
+        assert_eq!(params, [(TypeSpec::Char, true), (TypeSpec::Char, false)]);
+
+        let exprs: Vec<_> = body(&program, f)
+            .iter()
+            .map(|stmt| {
+                let StmtKind::Expr(expr) = stmt.kind else {
+                    panic!("expected an expression, found {:?}", stmt);
+                };
+                expr
+            })
+            .collect();
+        assert_eq!(sexpr(&program.nodes, exprs[0]), "(= ([] t 0) 'a')");
+        assert_eq!(
+            sexpr(&program.nodes, exprs[1]),
+            "(call g [\"hi\\n\" '\\''])"
+        );
+        let ExprKind::Call { args, .. } = &program.nodes[exprs[1]].kind else {
+            unreachable!()
+        };
+        assert_eq!(program.nodes[args[0]].kind, ExprKind::Str("hi\n".into()));
+        assert_eq!(program.nodes[args[1]].kind, ExprKind::Char(b'\''));
+    }
+
+    #[test]
+    fn pointers_are_declared_with_stars() {
+        let source = "int *p;\n\
+                      int **f(int *q, char **r, int a[]) { int ***s; }";
+        let (program, diags) = parse_with(source, Extensions::all());
+
+        assert_eq!(diags, Vec::new());
+        let Decl::Var(p) = &program.decls[0] else {
+            panic!("expected a variable, found {:?}", program.decls[0]);
+        };
+        assert_eq!((p.pointers, p.name.name.as_str()), (1, "p"));
+        assert_eq!(p.span, Span::with_usizes(0, 7));
+
+        let f = fun(&program, 1);
+        assert_eq!(f.return_pointers, 2);
+        let params: Vec<_> = f
+            .params
+            .iter()
+            .map(|param| (param.ty.clone(), param.pointers, param.is_array))
+            .collect();
+        assert_eq!(
+            params,
+            [
+                (TypeSpec::Int, 1, false),
+                (TypeSpec::Char, 2, false),
+                (TypeSpec::Int, 0, true)
+            ]
+        );
+        assert_eq!(program.nodes[f.body].decls[0].pointers, 3);
+    }
+
+    #[test]
+    fn dereferences_and_addresses() {
+        let cases = [
+            ("*p = *q + 1", "(= (* p) (+ (* q) 1))"),
+            ("a * *p", "(* a (* p))"),
+            ("**p", "(* (* p))"),
+            ("*p[i]", "(* ([] p i))"),
+            ("*p = &a[i]", "(= (* p) (& ([] a i)))"),
+            ("p = &*q", "(= p (& (* q)))"),
+            ("f(&x, -*p)", "(call f [(& x) (- (* p))])"),
+        ];
+
+        for (source, shape) in cases {
+            assert_eq!(
+                expr_shape_with(source, Extensions::all()),
+                (shape.to_owned(), vec![])
+            );
+        }
+    }
+
+    #[test]
+    fn only_what_has_an_address_can_be_its_operand() {
+        let (shape, diags) =
+            expr_shape_with("&1 + &(a + b)", Extensions::all());
+
+        assert_eq!(shape, "(+ (& 1) (& (+ a b)))");
+        assert_eq!(
+            diags,
+            vec![
+                Diag::InvalidAddressOf {
+                    span: Span::with_usizes(16, 17),
+                },
+                Diag::InvalidAddressOf {
+                    span: Span::with_usizes(22, 27),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pointers_need_their_extension() {
+        let (_, diags) = parse("int *p;\nvoid f(void) { *p = &x; }");
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Pointers,
+                    span: Span::with_usizes(4, 5),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Pointers,
+                    span: Span::with_usizes(23, 24),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Pointers,
+                    span: Span::with_usizes(28, 29),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn structs_are_declared_with_their_fields() {
+        let (program, diags) = parse_with(
+            "struct point { int x; int *y[2]; };\n\
+             struct point p;\n\
+             struct point f(struct point *q) { struct point r; }",
+            Extensions::all(),
+        );
+        assert_eq!(diags, vec![]);
+
+        let Decl::Struct(point) = &program.decls[0] else {
+            panic!("expected a struct, found {:?}", program.decls[0]);
+        };
+        let fields: Vec<_> = point
+            .fields
+            .iter()
+            .map(|field| {
+                (field.name.name.as_str(), field.pointers, field.sizes.len())
+            })
+            .collect();
+        assert_eq!(point.name.name, "point");
+        assert_eq!(fields, [("x", 0, 0), ("y", 1, 1)]);
+
+        let Decl::Var(p) = &program.decls[1] else {
+            panic!("expected a variable, found {:?}", program.decls[1]);
+        };
+        let f = fun(&program, 2);
+        let r = &program.nodes[f.body].decls[0];
+        for ty in [&p.ty, &f.return_ty, &f.params[0].ty, &r.ty] {
+            assert_eq!(ty.to_string(), "struct point");
+        }
+    }
+
+    #[test]
+    fn member_access() {
+        let cases = [
+            ("p.x", "(. p x)"),
+            ("p.a[i].b", "(. ([] (. p a) i) b)"),
+            (
+                "a[i].m[j][k] = *p.q",
+                "(= ([] ([] (. ([] a i) m) j) k) (* (. p q)))",
+            ),
+            ("&s.x.y", "(& (. (. s x) y))"),
+        ];
+
+        for (source, shape) in cases {
+            assert_eq!(
+                expr_shape_with(source, Extensions::all()),
+                (shape.to_owned(), vec![])
+            );
+        }
+    }
+
+    #[test]
+    fn parenthesised_expressions_and_calls_take_indices_and_fields() {
+        let cases = [
+            ("(*p).x = 1", "(= (. (* p) x) 1)"),
+            ("(a)[1]", "([] a 1)"),
+            ("(p + 1)[i].m[j]", "([] (. ([] (+ p 1) i) m) j)"),
+            ("f().x", "(. (call f []) x)"),
+            ("y = g(a, b)[0].z", "(= y (. ([] (call g [a b]) 0) z))"),
+        ];
+
+        for (source, shape) in cases {
+            assert_eq!(
+                expr_shape_with(source, Extensions::all()),
+                (shape.to_owned(), vec![]),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn fields_of_what_a_call_returns_have_no_address() {
+        let (shape, diags) =
+            expr_shape_with("f().x = &g().y", Extensions::all());
+
+        assert_eq!(shape, "(= (. (call f []) x) (& (. (call g []) y)))");
+        assert_eq!(
+            diags,
+            vec![
+                Diag::InvalidAssignTarget {
+                    span: Span::with_usizes(15, 20),
+                    equal: Span::with_usizes(21, 22),
+                },
+                Diag::InvalidAddressOf {
+                    span: Span::with_usizes(24, 29),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn structs_need_their_extension() {
+        let (_, diags) =
+            parse("struct s { int x; };\nvoid f(struct s a) { a.x = 1; }");
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Structs,
+                    span: Span::with_usizes(0, 6),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Structs,
+                    span: Span::with_usizes(28, 34),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Structs,
+                    span: Span::with_usizes(43, 44),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn const_qualifies_declarations() {
+        let (program, diags) = parse_with(
+            "const int x = 5; int y;\n\
+             const int *f(const int a[], char *b) { const char c; }",
+            Extensions::all(),
+        );
+        assert_eq!(diags, vec![]);
+
+        let f = fun(&program, 2);
+        let consts: Vec<_> = [&program.decls[0], &program.decls[1]]
+            .into_iter()
+            .map(|decl| match decl {
+                Decl::Var(var_decl) => var_decl.is_const,
+                _ => panic!("expected a variable, found {:?}", decl),
+            })
+            .chain([f.return_is_const])
+            .chain(f.params.iter().map(|param| param.is_const))
+            .chain([program.nodes[f.body].decls[0].is_const])
+            .collect();
+        assert_eq!(consts, [true, false, true, true, false, true]);
+    }
+
+    #[test]
+    fn const_needs_its_extension() {
+        let (_, diags) = parse("const int x;\nvoid f(const int a) { }");
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Const,
+                    span: Span::with_usizes(0, 5),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Const,
+                    span: Span::with_usizes(20, 25),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn char_needs_its_extension() {
+        let (_, diags) = parse("char c;\nvoid f(void) { c = 'a'; g(\"b\"); }");
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Char,
+                    span: Span::with_usizes(0, 4),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Char,
+                    span: Span::with_usizes(27, 30),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Char,
+                    span: Span::with_usizes(34, 37),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn stray_words_without_a_semicolon_on_the_line() {
+        let (_, diags) = parse("int x y\nint z;");
+
+        assert!(matches!(
+            diags[..],
+            [Diag::MissingToken {
+                token: Category::Semicolon,
+                at_line_end: false,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn parameters_keep_their_types_and_spans() {
+        let source = "int f(int a, void b, int c[]) { }";
+        let (program, diags) = parse(source);
+
+        assert_eq!(diags, Vec::new());
+        let f = fun(&program, 0);
+        let params: Vec<_> = f
+            .params
+            .iter()
+            .map(|param| {
+                (
+                    param.ty.clone(),
+                    param.name.name.as_str(),
+                    param.is_array,
+                    &source[param.span.start.0..param.span.end.0],
+                )
+            })
+            .collect();
+        assert_eq!(
+            params,
+            [
+                (TypeSpec::Int, "a", false, "int a"),
+                (TypeSpec::Void, "b", false, "void b"),
+                (TypeSpec::Int, "c", true, "int c[]"),
+            ]
+        );
+        assert_eq!(f.params_span, Span::with_usizes(5, 29));
+    }
+
+    #[test]
+    fn empty_parameter_list() {
+        let (program, diags) = parse("int f() { }");
+
+        assert_eq!(diags, vec![Diag::EmptyParamList { pos: BytePos(6) }]);
+        let suggestion = &diags[0].suggestions()[0];
+        assert_eq!(suggestion.replacement, "void");
+        assert_eq!(program.decls.len(), 1);
+    }
+
+    #[test]
+    fn void_next_to_other_parameters() {
+        let (program, diags) =
+            parse("int f(int a, void) { }\nint g(void, int b) { }");
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::VoidParamNotAlone {
+                    span: Span::with_usizes(13, 17),
+                    removal: Span::with_usizes(11, 17),
+                },
+                Diag::VoidParamNotAlone {
+                    span: Span::with_usizes(29, 33),
+                    removal: Span::with_usizes(29, 35),
+                },
+            ]
+        );
+        let params = |decl: &Decl| match decl {
+            Decl::Fun(f) => f.params.len(),
+            Decl::Var(_) | Decl::Struct(_) => {
+                panic!("expected a function, found {:?}", decl)
+            }
+        };
+        assert_eq!(
+            program.decls.iter().map(params).collect::<Vec<_>>(),
+            [1, 1]
+        );
+    }
+
+    #[test]
+    fn statements_and_their_spans() {
+        let source = "void f(void) {\n\
+                      while (x) { }\n\
+                      return;\n\
+                      return x + 1;\n\
+                      ;\n\
+                      x = 1;\n\
+                      }";
+        let (program, diags) = parse(source);
+
+        assert_eq!(diags, Vec::new());
+        let stmts = body(&program, fun(&program, 0));
+        let texts: Vec<_> = stmts
+            .iter()
+            .map(|stmt| &source[stmt.span.start.0..stmt.span.end.0])
+            .collect();
+        assert_eq!(
+            texts,
+            ["while (x) { }", "return;", "return x + 1;", ";", "x = 1;"]
+        );
+        assert!(matches!(stmts[1].kind, StmtKind::Return(None)));
+        assert!(matches!(stmts[2].kind, StmtKind::Return(Some(_))));
+    }
+
+    #[test]
+    fn else_goes_with_the_closest_if() {
+        let (program, diags) =
+            parse("void f(void) { if (a) if (b) x = 1; else x = 2; }");
+
+        assert_eq!(diags, Vec::new());
+        let stmt = body(&program, fun(&program, 0))[0];
+        let StmtKind::If {
+            then_branch,
+            else_branch: None,
+            ..
+        } = stmt.kind
+        else {
+            panic!("expected an if without else, found {:?}", stmt);
+        };
+        assert!(matches!(
+            program.nodes[then_branch].kind,
+            StmtKind::If {
+                else_branch: Some(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn declarations_after_statements() {
+        let (program, diags) = parse("void f(void) { int x; x = 1; int y; }");
+
+        assert_eq!(
+            diags,
+            vec![Diag::DeclarationAfterStatement {
+                span: Span::with_usizes(29, 35),
+                first_stmt: Span::with_usizes(22, 23),
+            }]
+        );
+        let block = &program.nodes[fun(&program, 0).body];
+        assert_eq!(block.decls.len(), 2);
+        assert_eq!(block.stmts.len(), 1);
+    }
+
+    #[test]
+    fn else_without_if() {
+        let (program, diags) =
+            parse("void f(void) { if (a) x = 1; y = 2; else x = 3; }");
+
+        assert_eq!(
+            diags,
+            vec![Diag::ElseWithoutIf {
+                span: Span::with_usizes(36, 40),
+            }]
+        );
+        assert_eq!(body(&program, fun(&program, 0)).len(), 3);
+    }
+
+    #[test]
+    fn conditions_without_parentheses() {
+        let (_, diags) = parse("void f(void) { while x < 10 x = x + 1; }");
+
+        assert_eq!(
+            diags,
+            vec![Diag::ConditionWithoutParens {
+                keyword: Keyword::While,
+                span: Span::with_usizes(21, 27),
+                text: "x < 10".into(),
+            }]
+        );
+        let suggestion = &diags[0].suggestions()[0];
+        assert_eq!(suggestion.span, Span::with_usizes(21, 27));
+        assert_eq!(suggestion.replacement, "(x < 10)");
+    }
+
+    #[test]
+    fn calls_hold_their_arguments() {
+        let cases = [
+            ("f()", "(call f [])"),
+            ("f(a, b+1, g(c))", "(call f [a (+ b 1) (call g [c])])"),
+            ("f(a = 1)", "(call f [(= a 1)])"),
+            ("f(g(h()))", "(call f [(call g [(call h [])])])"),
+        ];
+
+        for (source, shape) in cases {
+            assert_eq!(expr_shape(source), (shape.to_owned(), vec![]));
+        }
+    }
+
+    #[test]
+    fn trailing_comma_in_arguments() {
+        let (shape, diags) = expr_shape("f(a, b,)");
+
+        assert_eq!(shape, "(call f [a b])");
+        assert_eq!(
+            diags,
+            vec![Diag::TrailingComma {
+                span: Span::with_usizes(21, 22),
+            }]
+        );
+        assert_eq!(diags[0].suggestions()[0].replacement, "");
+    }
+
+    #[test]
+    fn missing_comma_between_arguments() {
+        let (shape, diags) = expr_shape("f(a b, 1 (c))");
+
+        assert_eq!(shape, "(call f [a b 1 c])");
+        assert_eq!(
+            diags,
+            vec![
+                Diag::MissingToken {
+                    token: Category::Comma,
+                    found: Category::Ident,
+                    pos: BytePos(18),
+                    at_line_end: false,
+                },
+                Diag::MissingToken {
+                    token: Category::Comma,
+                    found: Category::OpenParen,
+                    pos: BytePos(23),
+                    at_line_end: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn subscripts_on_both_sides_of_an_assignment() {
+        let cases = [
+            ("a[i+1] = x[j]", "(= ([] a (+ i 1)) ([] x j))"),
+            ("a[a[0]] = a[1] + 1", "(= ([] a ([] a 0)) (+ ([] a 1) 1))"),
+            ("(x) = 1", "(= x 1)"),
+        ];
+
+        for (source, shape) in cases {
+            assert_eq!(expr_shape(source), (shape.to_owned(), vec![]));
+        }
+    }
+
+    #[test]
+    fn only_vars_can_be_assigned_to() {
+        for source in ["1 = x", "f(a) = x", "a + b = x", "a < b = x"] {
+            let (shape, diags) = expr_shape(source);
+
+            let target_end = source.find(" =").unwrap();
+            assert_eq!(
+                diags,
+                vec![Diag::InvalidAssignTarget {
+                    span: Span::with_usizes(15, 15 + target_end),
+                    equal: Span::with_usizes(16 + target_end, 17 + target_end),
+                }],
+                "{}",
+                source
+            );
+            assert!(shape.starts_with("(= "), "{}", shape);
+        }
+    }
+
+    #[test]
+    fn unary_minus_binds_tighter_than_binary_operators() {
+        let cases = [
+            ("-a * b", "(* (- a) b)"),
+            ("-(a + b)", "(- (+ a b))"),
+            ("a - -b", "(- a (- b))"),
+            ("- -a", "(- (- a))"),
+            ("-a[i] / -f(x)", "(/ (- ([] a i)) (- (call f [x])))"),
+            ("x = -1 < -y", "(= x (< (- 1) (- y)))"),
+        ];
+
+        for (source, shape) in cases {
+            assert_eq!(
+                expr_shape_with(source, Extensions::all()),
+                (shape.to_owned(), vec![])
+            );
+        }
+    }
+
+    #[test]
+    fn unary_minus_needs_its_extension() {
+        let (shape, diags) = expr_shape("-a * -1");
+
+        assert_eq!(shape, "(* (- a) (- 1))");
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::UnaryMinus,
+                    span: Span::with_usizes(15, 16),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::UnaryMinus,
+                    span: Span::with_usizes(20, 21),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn conditionals_group_to_the_right() {
+        let cases = [
+            ("a ? b : c", "(? a b c)"),
+            ("a ? b : c ? d : e", "(? a b (? c d e))"),
+            ("a ? b ? c : d : e", "(? a (? b c d) e)"),
+            ("a < b ? a + 1 : b * 2", "(? (< a b) (+ a 1) (* b 2))"),
+            ("x = a ? b : c", "(= x (? a b c))"),
+            ("a ? x = 1 : y", "(? a (= x 1) y)"),
+            ("(a ? b : c) < d", "(< (? a b c) d)"),
+        ];
+
+        for (source, shape) in cases {
+            assert_eq!(
+                expr_shape_with(source, Extensions::all()),
+                (shape.to_owned(), vec![])
+            );
+        }
+    }
+
+    #[test]
+    fn conditionals_need_their_extension() {
+        let (shape, diags) = expr_shape("a ? b : c ? d : e");
+
+        assert_eq!(shape, "(? a b (? c d e))");
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Ternary,
+                    span: Span::with_usizes(17, 18),
+                },
+                Diag::ExtensionNotEnabled {
+                    extension: Extension::Ternary,
+                    span: Span::with_usizes(25, 26),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn an_assignment_to_a_conditional_is_reported() {
+        let (shape, diags) =
+            expr_shape_with("a ? b : c = 1", Extensions::all());
+
+        assert_eq!(shape, "(= (? a b c) 1)");
+        assert_eq!(
+            diags,
+            vec![Diag::InvalidAssignTarget {
+                span: Span::with_usizes(15, 24),
+                equal: Span::with_usizes(25, 26),
+            }]
+        );
+    }
+
+    #[test]
+    fn for_loops_may_leave_out_their_expressions() {
+        let source = "void f(void) {\n\
+                      for (i = 0; i < n; i = i + 1) x = x + i;\n\
+                      for (;;) ;\n\
+                      }";
+        let (program, diags) = parse_with(source, Extensions::all());
+
+        assert_eq!(diags, Vec::new());
+        let stmts = body(&program, fun(&program, 0));
+        let nodes = &program.nodes;
+        let StmtKind::For {
+            init: Some(init),
+            cond: Some(cond),
+            step: Some(step),
+            body,
+        } = stmts[0].kind
+        else {
+            panic!("expected a for, found {:?}", stmts[0]);
+        };
+        assert_eq!(sexpr(nodes, init), "(= i 0)");
+        assert_eq!(sexpr(nodes, cond), "(< i n)");
+        assert_eq!(sexpr(nodes, step), "(= i (+ i 1))");
+        assert!(matches!(nodes[body].kind, StmtKind::Expr(_)));
+        assert!(matches!(
+            stmts[1].kind,
+            StmtKind::For {
+                init: None,
+                cond: None,
+                step: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn do_while_loops_run_their_body_first() {
+        let (program, diags) = parse_with(
+            "void f(void) { do x = x + 1; while (x < 3); do { } while (x); }",
+            Extensions::all(),
+        );
+
+        assert_eq!(diags, Vec::new());
+        let stmts = body(&program, fun(&program, 0));
+        let nodes = &program.nodes;
+        let StmtKind::DoWhile { body, cond } = stmts[0].kind else {
+            panic!("expected a do-while, found {:?}", stmts[0]);
+        };
+        assert!(matches!(nodes[body].kind, StmtKind::Expr(_)));
+        assert_eq!(sexpr(nodes, cond), "(< x 3)");
+        assert!(matches!(stmts[1].kind, StmtKind::DoWhile { .. }));
+    }
+
+    #[test]
+    fn do_while_without_a_semicolon() {
+        let source = "void f(void) {\n\
+                      do { x = 1; } while (x)\n\
+                      do ; while (x) y = 2;\n\
+                      }";
+        let (program, diags) = parse_with(source, Extensions::all());
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::DoWhileWithoutSemicolon {
+                    do_span: Span::with_usizes(15, 17),
+                    pos: BytePos(38),
+                    at_line_end: true,
+                },
+                Diag::DoWhileWithoutSemicolon {
+                    do_span: Span::with_usizes(39, 41),
+                    pos: BytePos(53),
+                    at_line_end: false,
+                },
+            ]
+        );
+        assert_eq!(
+            diags[0].suggestions()[0].applicability,
+            Applicability::MachineApplicable
+        );
+        assert_eq!(
+            diags[1].suggestions()[0].applicability,
+            Applicability::MaybeIncorrect
+        );
+        // The first one is taken as if the `;` were there, and the second is
+        // skipped up to the `;` of the statement after it.
+        assert_eq!(body(&program, fun(&program, 0)).len(), 1);
+    }
+
+    #[test]
+    fn switch_cases_hold_the_statements_after_them() {
+        let source = "void f(void) {\n\
+                      switch (x) { x = 0; case 1: case 2: x = 1; break; \
+                      default: ; }\n\
+                      }";
+        let (program, diags) = parse_with(source, Extensions::all());
+
+        assert_eq!(
+            diags,
+            vec![Diag::UnexpectedToken {
+                expected: Expected::CaseLabel,
+                found: Category::Ident,
+                span: Span::with_usizes(28, 29),
+            }]
+        );
+        let stmts = body(&program, fun(&program, 0));
+        let nodes = &program.nodes;
+        let StmtKind::Switch { cond, ref cases } = stmts[0].kind else {
+            panic!("expected a switch, found {:?}", stmts[0]);
+        };
+        assert_eq!(sexpr(nodes, cond), "x");
+        let shapes: Vec<_> = cases
+            .iter()
+            .map(|case| {
+                let value = case.value.map(|value| sexpr(nodes, value));
+                let label =
+                    &source[case.label_span.start.0..case.label_span.end.0];
+                (value, label, case.stmts.len())
+            })
+            .collect();
+        assert_eq!(
+            shapes,
+            [
+                (Some("1".to_owned()), "case 1:", 0),
+                (Some("2".to_owned()), "case 2:", 2),
+                (None, "default:", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn for_needs_its_extension() {
+        let (program, diags) = parse("void f(void) { for (;;) ; }");
+
+        assert_eq!(
+            diags,
+            vec![Diag::ExtensionNotEnabled {
+                extension: Extension::For,
+                span: Span::with_usizes(15, 18),
+            }]
+        );
+        assert!(matches!(
+            body(&program, fun(&program, 0))[0].kind,
+            StmtKind::For { .. }
+        ));
+    }
+
+    #[test]
+    fn nesting_too_deep_is_reported_once() {
+        let depth = MAX_NESTING_DEPTH * 4;
+        let source = format!(
+            "int f(void) {{ x = {}1{}; }}",
+            "(".repeat(depth),
+            ")".repeat(depth)
+        );
+        let (_, diags) = parse(&source);
+
+        assert_eq!(diags.len(), 1);
+        assert!(matches!(diags[0], Diag::NestingTooDeep { .. }));
+    }
+
+    #[test]
+    fn nesting_far_too_deep_doesnt_overflow_the_stack() {
+        const DEPTH: usize = 100_000;
+        let sources = [
+            format!(
+                "int f(void) {{ x = {}1{}; }}",
+                "(".repeat(DEPTH),
+                ")".repeat(DEPTH)
+            ),
+            format!("int f(void) {{ return {}1; }}", "-".repeat(DEPTH)),
+            format!("int f(void) {}{}", "{".repeat(DEPTH), "}".repeat(DEPTH)),
+            format!("int f(void) {{ {}; }}", "if (x) ".repeat(DEPTH)),
+            format!("int f(void) {{ {}; }}", "while (x) ".repeat(DEPTH)),
+            format!("int f(void) {{ x = {}1; }}", "x = ".repeat(DEPTH)),
+            format!("int f(void) {{ x = x{}; }}", " + 1".repeat(DEPTH)),
+            format!("int f(void) {{ x = x{}; }}", " * 1".repeat(DEPTH)),
+            format!("int f(void) {{ x = a{}; }}", "[0]".repeat(DEPTH)),
+        ];
+
+        for source in &sources {
+            let (_, diags) = parse_with(source, Extensions::all());
+
+            assert_eq!(diags.len(), 1, "in {:?}", &source[..40]);
+            assert!(matches!(diags[0], Diag::NestingTooDeep { .. }));
+        }
+    }
+
+    #[test]
+    fn trace_shows_the_rules_entered_and_left() {
+        let source = "int x; int f(void) { return -; }";
+        let source_file = SourceFile::new("main.cm".into(), source.into());
+        let (words, _) = scan_words(&source_file, Extensions::none());
+
+        let (_, trace) =
+            parse_program_traced(&source_file, &words, Extensions::none());
+
+        assert_eq!(
+            trace,
+            "-> declaration, at `int` 1:1\n\
+             \x20 -> type-specifier, at `int` 1:1\n\
+             \x20 <- type-specifier, at `x` 1:5\n\
+             \x20 -> var-declaration, at `;` 1:6\n\
+             \x20 <- var-declaration, at `int` 1:8\n\
+             <- declaration, at `int` 1:8\n\
+             -> declaration, at `int` 1:8\n\
+             \x20 -> type-specifier, at `int` 1:8\n\
+             \x20 <- type-specifier, at `f` 1:12\n\
+             \x20 -> fun-declaration, at `(` 1:13\n\
+             \x20   -> params, at `void` 1:14\n\
+             \x20   <- params, at `)` 1:18\n\
+             \x20   -> compound-stmt, at `{` 1:20\n\
+             \x20     -> statement, at `return` 1:22\n\
+             \x20       -> expression, at `-` 1:29\n\
+             \x20         -> conditional-expression, at `-` 1:29\n\
+             \x20           -> simple-expression, at `-` 1:29\n\
+             \x20             -> additive-expression, at `-` 1:29\n\
+             \x20               -> term, at `-` 1:29\n\
+             \x20                 -> factor, at `-` 1:29\n\
+             \x20                   -> factor, at `;` 1:30\n\
+             \x20                   <- factor (error), at `;` 1:30\n\
+             \x20                 <- factor (error), at `;` 1:30\n\
+             \x20               <- term (error), at `;` 1:30\n\
+             \x20             <- additive-expression (error), at `;` 1:30\n\
+             \x20           <- simple-expression (error), at `;` 1:30\n\
+             \x20         <- conditional-expression (error), at `;` 1:30\n\
+             \x20       <- expression (error), at `;` 1:30\n\
+             \x20     <- statement (error), at `;` 1:30\n\
+             \x20   <- compound-stmt, at end of file 1:33\n\
+             \x20 <- fun-declaration, at end of file 1:33\n\
+             <- declaration, at end of file 1:33\n"
+        );
+    }
+
+    #[test]
+    fn assignments_as_conditions_are_warned_about() {
+        let source = "void f(int x) { if (x = 1) ; while ((x = 2)) ; \
+                      do ; while (x = 3); switch (x = 4) { } }";
+        let (_, diags) = parse_with(source, Extensions::all());
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::AssignInCondition {
+                    span: Span::with_usizes(20, 25),
+                    equal: Span::with_usizes(22, 23),
+                    text: "x = 1".into(),
+                },
+                Diag::AssignInCondition {
+                    span: Span::with_usizes(59, 64),
+                    equal: Span::with_usizes(61, 62),
+                    text: "x = 3".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reversed_comparisons_are_parsed_as_meant() {
+        let (shape, diags) = expr_shape("a =< b + 1");
+
+        assert_eq!(shape, "(<= a (+ b 1))");
+        assert_eq!(
+            diags,
+            vec![Diag::ReversedComparison {
+                span: Span::with_usizes(17, 19),
+                op: BinOp::LessEqual,
+            }]
+        );
+        assert_eq!(expr_shape("a => b").0, "(>= a b)");
+        // Apart, they're an assignment missing its value.
+        assert!(matches!(
+            parse("void f(void) { a = < b; }").1[..],
+            [Diag::UnexpectedToken { .. }]
+        ));
+    }
+
+    #[test]
+    fn a_declarator_list_declares_each_variable() {
+        let source = "int a, *p, v[10];\n\
+                      struct s { int x, y; };\n\
+                      void f(void) { char c, d; }";
+        let (program, diags) = parse_with(source, Extensions::all());
+
+        assert_eq!(diags, Vec::new());
+        let globals: Vec<_> = program.decls[..3]
+            .iter()
+            .map(|decl| match decl {
+                Decl::Var(var_decl) => (
+                    var_decl.name.name.as_str(),
+                    var_decl.pointers,
+                    var_decl.sizes.len(),
+                    var_decl.span,
+                ),
+                _ => panic!("expected a variable, found {:?}", decl),
+            })
+            .collect();
+        assert_eq!(
+            globals,
+            [
+                ("a", 0, 0, Span::with_usizes(0, 5)),
+                ("p", 1, 0, Span::with_usizes(7, 9)),
+                ("v", 0, 1, Span::with_usizes(11, 17)),
+            ]
+        );
+
+        let Decl::Struct(s) = &program.decls[3] else {
+            panic!("expected a struct, found {:?}", program.decls[3]);
+        };
+        let fields: Vec<_> = s
+            .fields
+            .iter()
+            .map(|field| field.name.name.as_str())
+            .collect();
+        assert_eq!(fields, ["x", "y"]);
+
+        let f = fun(&program, 4);
+        let locals: Vec<_> = program.nodes[f.body]
+            .decls
+            .iter()
+            .map(|var_decl| (var_decl.ty.clone(), var_decl.name.name.as_str()))
+            .collect();
+        assert_eq!(locals, [(TypeSpec::Char, "c"), (TypeSpec::Char, "d")]);
+    }
+
+    #[test]
+    fn declarator_lists_need_their_extension() {
+        let (program, diags) = parse("int a, b;");
+
+        assert_eq!(program.decls.len(), 2);
+        assert_eq!(
+            diags,
+            vec![Diag::ExtensionNotEnabled {
+                extension: Extension::DeclaratorLists,
+                span: Span::with_usizes(5, 6),
+            }]
+        );
+    }
+}
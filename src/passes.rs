@@ -0,0 +1,296 @@
+//! The passes that transform the three-address code of a program, e.g. to
+//! optimize it, and the pass manager running them one after the other.
+//!
+//! What's run is either the pipeline of an optimization level, `-O0` to
+//! `-O2`, or the passes given with `--passes`, in order. The code is
+//! verified after each pass, and dumped after the ones `--print-ir-after`
+//! names, to see what each one does. The ones `--remarks` names report
+//! what they decide, e.g. which calls are inlined and why the others
+//! aren't.
+
+use crate::{
+    ccp::ccp_program,
+    dce::dce_program,
+    fold::fold_program,
+    inline::inline_program,
+    ir::{dump_ir, Program},
+    licm::licm_program,
+    strength::strength_program,
+    tailcall::tailcall_program,
+    verify::assert_valid,
+};
+use std::fmt::Write;
+
+/// A transformation of the three-address code of a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pass {
+    /// Inlining of calls to small functions (see `inline`).
+    Inline,
+    /// Tail call optimization, of the calls a function makes to itself
+    /// (see `tailcall`).
+    TailCall,
+    /// Constant folding (see `fold`).
+    Fold,
+    /// Conditional constant propagation (see `ccp`).
+    Ccp,
+    /// Dead code elimination (see `dce`).
+    Dce,
+    /// Loop-invariant code motion (see `licm`).
+    Licm,
+    /// Strength reduction of the multiplications in loops (see
+    /// `strength`).
+    Strength,
+}
+
+impl Pass {
+    pub(crate) const ALL: [Pass; 7] = [
+        Pass::Inline,
+        Pass::TailCall,
+        Pass::Fold,
+        Pass::Ccp,
+        Pass::Dce,
+        Pass::Licm,
+        Pass::Strength,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Pass::Inline => "inline",
+            Pass::TailCall => "tailcall",
+            Pass::Fold => "fold",
+            Pass::Ccp => "ccp",
+            Pass::Dce => "dce",
+            Pass::Licm => "licm",
+            Pass::Strength => "strength",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Pass> {
+        Pass::ALL.into_iter().find(|pass| pass.name() == name)
+    }
+
+    /// Runs it on `program`, as `options` say, returning whether it changed
+    /// anything. What it decides is written to `remarks`.
+    pub(crate) fn run(
+        self,
+        program: &mut Program,
+        options: &PassOptions,
+        remarks: &mut Vec<String>,
+    ) -> bool {
+        match self {
+            Pass::Inline => {
+                inline_program(program, options.inline_threshold, remarks)
+            }
+            Pass::TailCall => tailcall_program(program),
+            Pass::Fold => fold_program(program),
+            Pass::Ccp => ccp_program(program),
+            Pass::Dce => dce_program(program),
+            Pass::Licm => licm_program(program),
+            Pass::Strength => strength_program(program),
+        }
+    }
+}
+
+/// What the passes that can be tuned are tuned by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PassOptions {
+    /// How many instructions a function may have at most to be inlined,
+    /// set with `--inline-threshold`.
+    pub(crate) inline_threshold: usize,
+}
+
+/// The threshold functions are inlined under if `--inline-threshold`
+/// doesn't say.
+pub(crate) const DEFAULT_INLINE_THRESHOLD: usize = 20;
+
+impl Default for PassOptions {
+    fn default() -> Self {
+        PassOptions {
+            inline_threshold: DEFAULT_INLINE_THRESHOLD,
+        }
+    }
+}
+
+/// How much a program is optimized, selected with `-O`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum OptLevel {
+    /// Nothing is, so the code is as it's lowered.
+    #[default]
+    O0,
+    /// Each pass is run once.
+    O1,
+    /// Inlining, tail call optimization, loop-invariant code motion and
+    /// strength reduction are run too, with sparse conditional constant
+    /// propagation in place of folding, and
+    /// the passes are run again until they change nothing, as what one does
+    /// may leave more for another.
+    O2,
+}
+
+impl OptLevel {
+    pub(crate) fn from_name(name: &str) -> Option<OptLevel> {
+        match name {
+            "0" => Some(OptLevel::O0),
+            "1" => Some(OptLevel::O1),
+            "2" => Some(OptLevel::O2),
+            _ => None,
+        }
+    }
+}
+
+/// Runs passes on a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PassManager {
+    passes: Vec<Pass>,
+    /// What they're tuned by.
+    options: PassOptions,
+    /// Whether to run the passes again until none changes anything.
+    until_unchanged: bool,
+    /// The passes to dump the code after.
+    print_ir_after: Vec<Pass>,
+    /// The passes to report the remarks of.
+    remarks: Vec<Pass>,
+}
+
+impl PassManager {
+    /// Runs `passes`, in order.
+    pub(crate) fn new(passes: Vec<Pass>) -> PassManager {
+        PassManager {
+            passes,
+            options: PassOptions::default(),
+            until_unchanged: false,
+            print_ir_after: Vec::new(),
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Runs the pipeline of `level`.
+    pub(crate) fn for_level(level: OptLevel) -> PassManager {
+        let passes = match level {
+            OptLevel::O0 => Vec::new(),
+            OptLevel::O1 => vec![Pass::Fold, Pass::Dce],
+            OptLevel::O2 => vec![
+                Pass::Inline,
+                Pass::TailCall,
+                Pass::Ccp,
+                Pass::Dce,
+                Pass::Licm,
+                Pass::Strength,
+            ],
+        };
+        PassManager {
+            until_unchanged: level == OptLevel::O2,
+            ..PassManager::new(passes)
+        }
+    }
+
+    /// Dumps the code after each of `passes` is run.
+    pub(crate) fn print_ir_after(mut self, passes: Vec<Pass>) -> PassManager {
+        self.print_ir_after = passes;
+        self
+    }
+
+    /// Tunes the passes as `options` say.
+    pub(crate) fn options(mut self, options: PassOptions) -> PassManager {
+        self.options = options;
+        self
+    }
+
+    /// Reports the remarks of `passes`.
+    pub(crate) fn remarks(mut self, passes: Vec<Pass>) -> PassManager {
+        self.remarks = passes;
+        self
+    }
+
+    /// Runs the passes on `program`, verifying it after each, and writing
+    /// the dumps and the remarks asked for to `dumps`.
+    pub(crate) fn run(&self, program: &mut Program, dumps: &mut String) {
+        // What's decided again each time the passes are run is reported
+        // once.
+        let mut reported: Vec<String> = Vec::new();
+        loop {
+            let mut changed = false;
+            for &pass in &self.passes {
+                let mut remarks = Vec::new();
+                changed |= pass.run(program, &self.options, &mut remarks);
+                assert_valid(program, pass.name());
+                if self.remarks.contains(&pass) {
+                    remarks.retain(|remark| !reported.contains(remark));
+                    for remark in &remarks {
+                        let _ = writeln!(dumps, "remark: {}", remark);
+                    }
+                    reported.extend(remarks);
+                }
+                if self.print_ir_after.contains(&pass) {
+                    let _ = writeln!(dumps, "*** IR after {} ***", pass.name());
+                    dumps.push_str(&dump_ir(program));
+                }
+            }
+            if !self.until_unchanged || !changed {
+                break;
+            }
+        }
+    }
+}
+
+/// Runs `passes` on `program`, in order, verifying it after each.
+#[cfg(test)]
+pub(crate) fn run_passes(program: &mut Program, passes: &[Pass]) {
+    PassManager::new(passes.to_vec()).run(program, &mut String::new());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OptLevel, Pass, PassManager};
+    use crate::{ir::dump_ir, test_support::lower_source};
+
+    #[test]
+    fn levels_run_their_pipelines_and_dump_after_passes() {
+        let source = "void main(void) { int x; x = 2 * 3; output(x - 6); }";
+        let lowered = lower_source(source);
+        let run = |manager: PassManager| {
+            let mut program = lowered.clone();
+            let mut dumps = String::new();
+            manager.run(&mut program, &mut dumps);
+            (dump_ir(&program), dumps)
+        };
+
+        assert_eq!(
+            run(PassManager::for_level(OptLevel::O0)),
+            (dump_ir(&lowered), String::new())
+        );
+        let (code, dumps) = run(PassManager::for_level(OptLevel::O1)
+            .print_ir_after(vec![Pass::Fold, Pass::Dce]));
+        assert_eq!(
+            code,
+            "fun @main() {\n\
+             \x20 slot $0 x: 4, align 4\n\
+             \x20 store i32 $0, 6\n\
+             \x20 %1: i32 = load i32 $0\n\
+             \x20 %2: i32 = sub %1, 6\n\
+             \x20 call @output(%2)\n\
+             \x20 ret\n\
+             }\n"
+        );
+        let headers: Vec<_> = dumps
+            .lines()
+            .filter(|line| line.starts_with("***"))
+            .collect();
+        assert_eq!(headers, ["*** IR after fold ***", "*** IR after dce ***"]);
+        assert!(dumps.ends_with(&code));
+    }
+
+    #[test]
+    fn o2_runs_the_passes_until_they_change_nothing() {
+        let lowered = lower_source("void main(void) { output(1 + 2 * 3); }");
+        let mut program = lowered.clone();
+        let mut dumps = String::new();
+        PassManager::for_level(OptLevel::O2)
+            .print_ir_after(vec![Pass::Dce])
+            .run(&mut program, &mut dumps);
+
+        assert!(dump_ir(&program).contains("call @output(7)"));
+        // Once to fold and drop, and again to find nothing's left to.
+        assert_eq!(dumps.matches("*** IR after dce ***").count(), 2);
+    }
+}
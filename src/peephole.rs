@@ -0,0 +1,121 @@
+//! A peephole optimizer over the assembly a backend emits for a function,
+//! rewriting short runs of instructions into fewer or cheaper ones, e.g. a
+//! jump to the label right after it into nothing.
+//!
+//! What can be rewritten depends on the target, so each backend has its
+//! own list of patterns, and this only goes through the lines with them: a
+//! pattern is tried at every line, and may replace the lines it starts at,
+//! until none applies anywhere.
+
+use std::fmt;
+
+/// A line of the assembly of a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Line {
+    Label(String),
+    /// An instruction, by its mnemonic and its operands, in the order the
+    /// target's syntax writes them.
+    Inst {
+        op: String,
+        args: Vec<String>,
+    },
+}
+
+impl Line {
+    /// The instruction written as `text`, e.g. `movq %rax, -8(%rbp)`.
+    /// Operands are separated by `, `.
+    pub(crate) fn inst(text: &str) -> Line {
+        let (op, args) = text.split_once(' ').unwrap_or((text, ""));
+        Line::Inst {
+            op: op.to_string(),
+            args: if args.is_empty() {
+                Vec::new()
+            } else {
+                args.split(", ").map(str::to_string).collect()
+            },
+        }
+    }
+
+    /// Its mnemonic and operands, if it's an instruction.
+    pub(crate) fn as_inst(&self) -> Option<(&str, Vec<&str>)> {
+        match self {
+            Line::Inst { op, args } => {
+                Some((op, args.iter().map(String::as_str).collect()))
+            }
+            Line::Label(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Label(label) => write!(f, "{}:", label),
+            Line::Inst { op, args } if args.is_empty() => write!(f, "\t{}", op),
+            Line::Inst { op, args } => {
+                write!(f, "\t{} {}", op, args.join(", "))
+            }
+        }
+    }
+}
+
+/// A rewrite of the lines at the start of a window: how many of them it
+/// replaces, and with what, if it applies.
+pub(crate) type Pattern = fn(&[Line]) -> Option<(usize, Vec<Line>)>;
+
+/// Rewrites `lines` with `patterns` until none applies, returning how many
+/// rewrites there were.
+pub(crate) fn optimize(lines: &mut Vec<Line>, patterns: &[Pattern]) -> usize {
+    let mut rewrites = 0;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut index = 0;
+        while index < lines.len() {
+            let rewrite =
+                patterns.iter().find_map(|pattern| pattern(&lines[index..]));
+            match rewrite {
+                Some((count, replacement)) => {
+                    lines.splice(index..index + count, replacement);
+                    rewrites += 1;
+                    changed = true;
+                }
+                None => index += 1,
+            }
+        }
+    }
+    rewrites
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{optimize, Line, Pattern};
+
+    #[test]
+    fn patterns_are_applied_until_none_applies() {
+        // Drops a `nop`, and a `jmp` to the label right after it.
+        let patterns: [Pattern; 2] = [
+            |lines| match lines.first()?.as_inst()? {
+                ("nop", _) => Some((1, Vec::new())),
+                _ => None,
+            },
+            |lines| match (lines.first()?.as_inst()?, lines.get(1)?) {
+                (("jmp", args), Line::Label(label)) if args == [label] => {
+                    Some((1, Vec::new()))
+                }
+                _ => None,
+            },
+        ];
+        let mut lines = vec![
+            Line::inst("movq %rax, %rcx"),
+            Line::inst("jmp .L0"),
+            Line::inst("nop"),
+            Line::Label(".L0".into()),
+            Line::inst("ret"),
+        ];
+
+        assert_eq!(optimize(&mut lines, &patterns), 2);
+        let text: Vec<_> = lines.iter().map(Line::to_string).collect();
+        assert_eq!(text, ["\tmovq %rax, %rcx", ".L0:", "\tret"]);
+    }
+}
@@ -0,0 +1,760 @@
+//! Printing a program back as C- source, in a canonical layout.
+//!
+//! The printed program parses back into the same tree, so it can be used to
+//! format programs and to check the parser. Comments and the original layout
+//! aren't part of the tree, so they're lost.
+
+use crate::{
+    ast::{
+        BinOp, BlockId, Decl, Expr, ExprId, ExprKind, FunDecl, Nodes, Param,
+        Program, StmtId, StmtKind, StructDecl, VarDecl,
+    },
+    scanner::quote_literal,
+};
+use std::fmt::Write;
+
+/// How the printed program is indented.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Indent {
+    /// This many spaces per level.
+    Spaces(usize),
+    /// A tab per level.
+    Tab,
+}
+
+impl Indent {
+    /// The indentation set by `--indent`: `tab`, or a number of spaces.
+    pub(crate) fn from_name(name: &str) -> Option<Indent> {
+        match name {
+            "tab" => Some(Indent::Tab),
+            _ => name.parse().ok().map(Indent::Spaces),
+        }
+    }
+}
+
+impl Default for Indent {
+    fn default() -> Indent {
+        Indent::Spaces(4)
+    }
+}
+
+/// Prints `program` as C- source.
+pub(crate) fn print_program(program: &Program, indent: Indent) -> String {
+    let mut printer = Printer {
+        nodes: &program.nodes,
+        out: String::new(),
+        indent: match indent {
+            Indent::Spaces(width) => " ".repeat(width),
+            Indent::Tab => "\t".to_owned(),
+        },
+        level: 0,
+    };
+
+    let mut prev: Option<&Decl> = None;
+    for decl in &program.decls {
+        // Functions and structs are set apart from what's around them by a
+        // blank line.
+        let stands_apart =
+            |decl: &Decl| matches!(decl, Decl::Fun(_) | Decl::Struct(_));
+        if prev.is_some_and(|prev| stands_apart(prev) || stands_apart(decl)) {
+            printer.out.push('\n');
+        }
+        printer.print_decl(decl);
+        prev = Some(decl);
+    }
+
+    printer.out
+}
+
+/// What goes before the type specifier of a declaration with a `const` or
+/// without one.
+pub(crate) fn const_prefix(is_const: bool) -> &'static str {
+    if is_const {
+        "const "
+    } else {
+        ""
+    }
+}
+
+/// Prints `expr`, one of `nodes`, as C- source, with only the parentheses
+/// it needs.
+pub(crate) fn print_expr(nodes: &Nodes, expr: ExprId) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, nodes, expr, Prec::Assign);
+    out
+}
+
+struct Printer<'ast> {
+    nodes: &'ast Nodes,
+    out: String,
+    indent: String,
+    /// How many levels the current line is indented.
+    level: usize,
+}
+
+impl Printer<'_> {
+    fn start_line(&mut self) {
+        for _ in 0..self.level {
+            self.out.push_str(&self.indent);
+        }
+    }
+
+    fn print_decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Var(var_decl) => self.print_var_decl(var_decl),
+            Decl::Fun(fun_decl) => self.print_fun_decl(fun_decl),
+            Decl::Struct(struct_decl) => self.print_struct_decl(struct_decl),
+        }
+    }
+
+    fn print_struct_decl(&mut self, struct_decl: &StructDecl) {
+        self.start_line();
+        let _ = writeln!(self.out, "struct {} {{", struct_decl.name.name);
+        self.level += 1;
+        for field in &struct_decl.fields {
+            self.print_var_decl(field);
+        }
+        self.level -= 1;
+        self.start_line();
+        self.out.push_str("};\n");
+    }
+
+    fn print_var_decl(&mut self, var_decl: &VarDecl) {
+        self.start_line();
+        let _ = write!(
+            self.out,
+            "{}{} {}{}",
+            const_prefix(var_decl.is_const),
+            var_decl.ty,
+            "*".repeat(var_decl.pointers as usize),
+            var_decl.name.name
+        );
+        for &size in &var_decl.sizes {
+            self.out.push('[');
+            write_expr(&mut self.out, self.nodes, size, Prec::Conditional);
+            self.out.push(']');
+        }
+        if let Some(init) = var_decl.init {
+            self.out.push_str(" = ");
+            write_expr(&mut self.out, self.nodes, init, Prec::Assign);
+        }
+        self.out.push_str(";\n");
+    }
+
+    fn print_fun_decl(&mut self, fun_decl: &FunDecl) {
+        self.start_line();
+        let _ = write!(
+            self.out,
+            "{}{} {}{}(",
+            const_prefix(fun_decl.return_is_const),
+            fun_decl.return_ty,
+            "*".repeat(fun_decl.return_pointers as usize),
+            fun_decl.name.name
+        );
+        if fun_decl.params.is_empty() {
+            self.out.push_str("void");
+        }
+        for (i, param) in fun_decl.params.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.print_param(param);
+        }
+        self.out.push_str(") ");
+        self.print_block(fun_decl.body);
+        self.out.push('\n');
+    }
+
+    fn print_param(&mut self, param: &Param) {
+        let _ = write!(
+            self.out,
+            "{}{} {}{}",
+            const_prefix(param.is_const),
+            param.ty,
+            "*".repeat(param.pointers as usize),
+            param.name.name
+        );
+        if param.is_array {
+            self.out.push_str("[]");
+        }
+        for &size in &param.sizes {
+            self.out.push('[');
+            write_expr(&mut self.out, self.nodes, size, Prec::Conditional);
+            self.out.push(']');
+        }
+    }
+
+    /// Prints `block` from where the current line is, ending right after
+    /// its `}`.
+    fn print_block(&mut self, block: BlockId) {
+        let block = &self.nodes[block];
+        self.out.push_str("{\n");
+        self.level += 1;
+        for var_decl in &block.decls {
+            self.print_var_decl(var_decl);
+        }
+        for &stmt in &block.stmts {
+            self.start_line();
+            self.print_stmt(stmt);
+            self.out.push('\n');
+        }
+        self.level -= 1;
+        self.start_line();
+        self.out.push('}');
+    }
+
+    /// Prints `stmt` from where the current line is, without ending it.
+    fn print_stmt(&mut self, stmt: StmtId) {
+        let nodes = self.nodes;
+        match nodes[stmt].kind {
+            StmtKind::Expr(expr) => {
+                write_expr(&mut self.out, nodes, expr, Prec::Assign);
+                self.out.push(';');
+            }
+            StmtKind::Empty => self.out.push(';'),
+            StmtKind::Block(block) => self.print_block(block),
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.out.push_str("if (");
+                write_expr(&mut self.out, nodes, cond, Prec::Assign);
+                self.out.push(')');
+                match else_branch {
+                    // The `else` would go with an `if` in the `then` branch
+                    // that has none, unless it's in braces.
+                    Some(_) if ends_in_if_without_else(nodes, then_branch) => {
+                        self.out.push(' ');
+                        self.print_block_of(then_branch);
+                    }
+                    _ => self.print_body(then_branch),
+                }
+
+                if let Some(else_branch) = else_branch {
+                    if matches!(nodes[then_branch].kind, StmtKind::Block(_))
+                        || ends_in_if_without_else(nodes, then_branch)
+                    {
+                        self.out.push(' ');
+                    } else {
+                        self.out.push('\n');
+                        self.start_line();
+                    }
+                    self.out.push_str("else");
+                    if matches!(nodes[else_branch].kind, StmtKind::If { .. }) {
+                        self.out.push(' ');
+                        self.print_stmt(else_branch);
+                    } else {
+                        self.print_body(else_branch);
+                    }
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.out.push_str("while (");
+                write_expr(&mut self.out, nodes, cond, Prec::Assign);
+                self.out.push(')');
+                self.print_body(body);
+            }
+            StmtKind::DoWhile { body, cond } => {
+                self.out.push_str("do");
+                self.print_body(body);
+                if matches!(nodes[body].kind, StmtKind::Block(_)) {
+                    self.out.push(' ');
+                } else {
+                    self.out.push('\n');
+                    self.start_line();
+                }
+                self.out.push_str("while (");
+                write_expr(&mut self.out, nodes, cond, Prec::Assign);
+                self.out.push_str(");");
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                self.out.push_str("for (");
+                if let Some(init) = init {
+                    write_expr(&mut self.out, nodes, init, Prec::Assign);
+                }
+                self.out.push(';');
+                if let Some(cond) = cond {
+                    self.out.push(' ');
+                    write_expr(&mut self.out, nodes, cond, Prec::Assign);
+                }
+                self.out.push(';');
+                if let Some(step) = step {
+                    self.out.push(' ');
+                    write_expr(&mut self.out, nodes, step, Prec::Assign);
+                }
+                self.out.push(')');
+                self.print_body(body);
+            }
+            StmtKind::Switch { cond, ref cases } => {
+                self.out.push_str("switch (");
+                write_expr(&mut self.out, nodes, cond, Prec::Assign);
+                self.out.push_str(") {\n");
+                for case in cases {
+                    self.start_line();
+                    if let Some(value) = case.value {
+                        self.out.push_str("case ");
+                        write_expr(&mut self.out, nodes, value, Prec::Assign);
+                        self.out.push_str(":\n");
+                    } else {
+                        self.out.push_str("default:\n");
+                    }
+                    self.level += 1;
+                    for &stmt in &case.stmts {
+                        self.start_line();
+                        self.print_stmt(stmt);
+                        self.out.push('\n');
+                    }
+                    self.level -= 1;
+                }
+                self.start_line();
+                self.out.push('}');
+            }
+            StmtKind::Break => self.out.push_str("break;"),
+            StmtKind::Continue => self.out.push_str("continue;"),
+            StmtKind::Return(None) => self.out.push_str("return;"),
+            StmtKind::Return(Some(value)) => {
+                self.out.push_str("return ");
+                write_expr(&mut self.out, nodes, value, Prec::Assign);
+                self.out.push(';');
+            }
+        }
+    }
+
+    /// Prints the statement of an `if`, `else` or loop: a block on the
+    /// same line, anything else indented on the next one.
+    fn print_body(&mut self, body: StmtId) {
+        if let StmtKind::Block(block) = self.nodes[body].kind {
+            self.out.push(' ');
+            self.print_block(block);
+        } else {
+            self.out.push('\n');
+            self.level += 1;
+            self.start_line();
+            self.print_stmt(body);
+            self.level -= 1;
+        }
+    }
+
+    /// Prints `stmt` in a block of its own.
+    fn print_block_of(&mut self, stmt: StmtId) {
+        self.out.push_str("{\n");
+        self.level += 1;
+        self.start_line();
+        self.print_stmt(stmt);
+        self.out.push('\n');
+        self.level -= 1;
+        self.start_line();
+        self.out.push('}');
+    }
+}
+
+/// Whether an `else` right after `stmt` would be taken as part of it.
+fn ends_in_if_without_else(nodes: &Nodes, stmt: StmtId) -> bool {
+    match nodes[stmt].kind {
+        StmtKind::If {
+            else_branch: None, ..
+        } => true,
+        StmtKind::If {
+            else_branch: Some(else_branch),
+            ..
+        } => ends_in_if_without_else(nodes, else_branch),
+        StmtKind::While { body, .. } | StmtKind::For { body, .. } => {
+            ends_in_if_without_else(nodes, body)
+        }
+        _ => false,
+    }
+}
+
+/// How tightly an expression binds, from loosest to tightest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Assign,
+    Conditional,
+    Relational,
+    Additive,
+    Multiplicative,
+    Unary,
+    Primary,
+}
+
+fn prec_of(expr: &Expr) -> Prec {
+    match &expr.kind {
+        ExprKind::Assign { .. } => Prec::Assign,
+        ExprKind::Conditional { .. } => Prec::Conditional,
+        ExprKind::Unary { .. } => Prec::Unary,
+        ExprKind::Binary { op, .. } => binary_prec(*op),
+        ExprKind::Number(_)
+        | ExprKind::Char(_)
+        | ExprKind::Str(_)
+        | ExprKind::Var(_)
+        | ExprKind::Index { .. }
+        | ExprKind::Member { .. }
+        | ExprKind::Call { .. } => Prec::Primary,
+    }
+}
+
+fn binary_prec(op: BinOp) -> Prec {
+    match op {
+        BinOp::Less
+        | BinOp::LessEqual
+        | BinOp::Greater
+        | BinOp::GreaterEqual
+        | BinOp::Equal
+        | BinOp::NotEqual => Prec::Relational,
+        BinOp::Add | BinOp::Sub => Prec::Additive,
+        BinOp::Mul | BinOp::Div => Prec::Multiplicative,
+    }
+}
+
+/// The next tighter precedence level than `prec`.
+fn tighter(prec: Prec) -> Prec {
+    match prec {
+        Prec::Assign => Prec::Conditional,
+        Prec::Conditional => Prec::Relational,
+        Prec::Relational => Prec::Additive,
+        Prec::Additive => Prec::Multiplicative,
+        Prec::Multiplicative => Prec::Unary,
+        Prec::Unary | Prec::Primary => Prec::Primary,
+    }
+}
+
+/// Writes `expr` where an expression binding at least as tightly as `min`
+/// goes, in parentheses if it binds looser.
+fn write_expr(out: &mut String, nodes: &Nodes, expr: ExprId, min: Prec) {
+    let prec = prec_of(&nodes[expr]);
+    if prec < min {
+        out.push('(');
+        write_expr(out, nodes, expr, Prec::Assign);
+        out.push(')');
+        return;
+    }
+
+    match &nodes[expr].kind {
+        ExprKind::Number(value) => {
+            let _ = write!(out, "{}", value);
+        }
+        ExprKind::Char(value) => {
+            out.push_str(&quote_literal(&char::from(*value).to_string(), '\''))
+        }
+        ExprKind::Str(text) => out.push_str(&quote_literal(text, '"')),
+        ExprKind::Var(ident) => out.push_str(&ident.name),
+        ExprKind::Index { array, index } => {
+            write_expr(out, nodes, *array, Prec::Primary);
+            out.push('[');
+            write_expr(out, nodes, *index, Prec::Assign);
+            out.push(']');
+        }
+        ExprKind::Member { base, field } => {
+            write_expr(out, nodes, *base, Prec::Primary);
+            out.push('.');
+            out.push_str(&field.name);
+        }
+        ExprKind::Call { callee, args } => {
+            out.push_str(&callee.name);
+            out.push('(');
+            for (i, &arg) in args.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, nodes, arg, Prec::Assign);
+            }
+            out.push(')');
+        }
+        // Assignment is right associative.
+        ExprKind::Assign { target, value } => {
+            write_expr(out, nodes, *target, Prec::Relational);
+            out.push_str(" = ");
+            write_expr(out, nodes, *value, Prec::Assign);
+        }
+        // The operand of a unary operator goes in parentheses if it has one
+        // of its own, so that `-(-a)` doesn't come out as `--a`.
+        ExprKind::Unary { op, operand } => {
+            out.push_str(op.as_str());
+            write_expr(out, nodes, *operand, Prec::Primary);
+        }
+        // Arithmetic is left associative, and comparisons don't chain.
+        ExprKind::Binary { op, lhs, rhs } => {
+            let lhs_min = if prec == Prec::Relational {
+                tighter(prec)
+            } else {
+                prec
+            };
+            write_expr(out, nodes, *lhs, lhs_min);
+            let _ = write!(out, " {} ", op.as_str());
+            write_expr(out, nodes, *rhs, tighter(prec));
+        }
+        // Right associative, like `=`: only the condition of a nested
+        // conditional needs parentheses.
+        ExprKind::Conditional {
+            cond,
+            then_expr,
+            else_expr,
+        } => {
+            write_expr(out, nodes, *cond, Prec::Relational);
+            out.push_str(" ? ");
+            write_expr(out, nodes, *then_expr, Prec::Assign);
+            out.push_str(" : ");
+            write_expr(out, nodes, *else_expr, Prec::Conditional);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{print_expr, print_program, Indent};
+    use crate::{
+        ast::{Decl, Program, StmtKind},
+        extensions::Extensions,
+        parser::ParseResult,
+        test_support::parse_source,
+    };
+
+    fn parse(source: &str) -> Program {
+        let (
+            _,
+            ParseResult {
+                program, diag_bag, ..
+            },
+        ) = parse_source(source, Extensions::all());
+        assert!(!diag_bag.has_errors(), "errors in {:?}", source);
+        program
+    }
+
+    /// Parses `source` and prints it back.
+    fn reprint(source: &str, indent: Indent) -> String {
+        print_program(&parse(source), indent)
+    }
+
+    #[test]
+    fn print_in_canonical_layout() {
+        let source = "int n; int a[10];\n\
+                      int gcd(int u,int v){if(v==0)return u;\n\
+                      else return gcd(v,u-u/v*v);}\n\
+                      void main(void){int x;while(x<n){a[x]=input();x=x+1;}\n\
+                      if(x)output(x);else{;}return;}";
+
+        assert_eq!(
+            reprint(source, Indent::default()),
+            "int n;\n\
+             int a[10];\n\
+             \n\
+             int gcd(int u, int v) {\n    \
+                 if (v == 0)\n        \
+                     return u;\n    \
+                 else\n        \
+                     return gcd(v, u - u / v * v);\n\
+             }\n\
+             \n\
+             void main(void) {\n    \
+                 int x;\n    \
+                 while (x < n) {\n        \
+                     a[x] = input();\n        \
+                     x = x + 1;\n    \
+                 }\n    \
+                 if (x)\n        \
+                     output(x);\n    \
+                 else {\n        \
+                     ;\n    \
+                 }\n    \
+                 return;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn indentation_is_configurable() {
+        let source = "void f(void) { while (1) { return; } }";
+
+        assert_eq!(
+            reprint(source, Indent::Spaces(2)),
+            "void f(void) {\n  while (1) {\n    return;\n  }\n}\n"
+        );
+        assert_eq!(
+            reprint(source, Indent::Tab),
+            "void f(void) {\n\twhile (1) {\n\t\treturn;\n\t}\n}\n"
+        );
+    }
+
+    #[test]
+    fn only_needed_parentheses_are_printed() {
+        let cases = [
+            ("(a + b) * c", "(a + b) * c"),
+            ("a + (b * c)", "a + b * c"),
+            ("(a - b) - c", "a - b - c"),
+            ("a - (b - c)", "a - (b - c)"),
+            ("a = (b = c)", "a = b = c"),
+            ("(a < b) < c", "(a < b) < c"),
+            ("a < (b < c)", "a < (b < c)"),
+            ("f((a), (b + 1))", "f(a, b + 1)"),
+            ("a[(i + 1)] = (x)", "a[i + 1] = x"),
+            ("(-a) * b", "-a * b"),
+            ("-(a + b)", "-(a + b)"),
+            ("-(a * b)", "-(a * b)"),
+            ("a - -b", "a - -b"),
+            ("-(-a)", "-(-a)"),
+            ("-(a[i])", "-a[i]"),
+            ("*(p)", "*p"),
+            ("*(p[i])", "*p[i]"),
+            ("&(*p)", "&(*p)"),
+            ("(*p) * (*q)", "*p * *q"),
+            ("*(s.p)", "*s.p"),
+            ("-(s.a[i])", "-s.a[i]"),
+            ("a ? b : (c ? d : e)", "a ? b : c ? d : e"),
+            ("(a ? b : c) ? d : e", "(a ? b : c) ? d : e"),
+            ("a ? (b = c) : (d < e)", "a ? b = c : d < e"),
+            ("(a ? b : c) + 1", "(a ? b : c) + 1"),
+            ("x = (a ? b : c)", "x = a ? b : c"),
+            ("a ? b : (c = d)", "a ? b : (c = d)"),
+        ];
+
+        for (source, printed) in cases {
+            let program = parse(&format!("void f(void) {{ {}; }}", source));
+            let Decl::Fun(f) = &program.decls[0] else {
+                panic!("expected a function, found {:?}", program.decls[0]);
+            };
+            let stmt = &program.nodes[program.nodes[f.body].stmts[0]];
+            let StmtKind::Expr(expr) = stmt.kind else {
+                panic!("expected an expression, found {:?}", stmt);
+            };
+            assert_eq!(print_expr(&program.nodes, expr), printed, "{}", source);
+        }
+    }
+
+    #[test]
+    fn structs_are_set_apart_with_their_fields_indented() {
+        assert_eq!(
+            reprint(
+                "int a; struct p{int x;char *s[2];};int b;",
+                Indent::Spaces(2)
+            ),
+            "int a;\n\
+             \n\
+             struct p {\n  \
+                 int x;\n  \
+                 char *s[2];\n\
+             };\n\
+             \n\
+             int b;\n"
+        );
+    }
+
+    #[test]
+    fn else_keeps_its_if() {
+        // Take the braces out of the `then` branch in the tree, as if it had
+        // been built by hand.
+        let mut program =
+            parse("void f(void) { if (a) { if (b) x = 1; } else x = 2; }");
+        let Decl::Fun(f) = &program.decls[0] else {
+            panic!("expected a function, found {:?}", program.decls[0]);
+        };
+        let nodes = &mut program.nodes;
+        let if_stmt = nodes[f.body].stmts[0];
+        let StmtKind::If { then_branch, .. } = nodes[if_stmt].kind else {
+            panic!("expected an if, found {:?}", nodes[if_stmt]);
+        };
+        let StmtKind::Block(block) = nodes[then_branch].kind else {
+            panic!("expected a block, found {:?}", nodes[then_branch]);
+        };
+        let inner_if = nodes[block].stmts[0];
+        nodes[then_branch] = nodes[inner_if].clone();
+
+        let printed = print_program(&program, Indent::default());
+
+        assert_eq!(
+            printed,
+            "void f(void) {\n    \
+                 if (a) {\n        \
+                     if (b)\n            \
+                         x = 1;\n    \
+                 } else\n        \
+                     x = 2;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn for_loops_print_their_parts() {
+        let source = "void f(void) { for(i=0;i<n;i=i+1){ } for(;;)\n; }";
+
+        assert_eq!(
+            reprint(source, Indent::default()),
+            "void f(void) {\n    \
+                 for (i = 0; i < n; i = i + 1) {\n    \
+                 }\n    \
+                 for (;;)\n        \
+                     ;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn do_while_keeps_its_semicolon() {
+        let source = "void f(void) { do { x = x + 1; } while (x < 3); \
+                      do x = 1; while (x); }";
+
+        assert_eq!(
+            reprint(source, Indent::default()),
+            "void f(void) {\n    \
+                 do {\n        \
+                     x = x + 1;\n    \
+                 } while (x < 3);\n    \
+                 do\n        \
+                     x = 1;\n    \
+                 while (x);\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn switch_labels_line_up_with_the_switch() {
+        let source = "void f(void) { switch (x) { case 1: case -2: x = 1; \
+                      break; default: { } } }";
+
+        assert_eq!(
+            reprint(source, Indent::default()),
+            "void f(void) {\n    \
+                 switch (x) {\n    \
+                 case 1:\n    \
+                 case -2:\n        \
+                     x = 1;\n        \
+                     break;\n    \
+                 default:\n        \
+                     {\n        \
+                     }\n    \
+                 }\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn printed_programs_parse_back_the_same() {
+        let sources = [
+            include_str!("../tests/ui/valid.cm"),
+            "int a[3]; void f(int b[], int c) { if (c) if (b[0]) ; else \
+             while (c < 3) { a[c] = c = c + 1; } else if (c == 1) return; \
+             else { } }",
+            "int x = -(1 + 2) * 3; int y = 1 < 2 ? x : 0;",
+            "int m[2][3]; void f(void) { m[m[0][1]][2] = 1; }",
+            "int *p; int **f(int *q, int a[]) { *p = &a[*q]; **f(&*p, a) = \
+             -*q * *p; }",
+            "char s[4]; void f(char t[]) { s[0] = '\\''; f(\"a\\t\\\"b\\\"\"); }",
+            "struct p { int x; struct p *n; int a[2]; }; struct p g; \
+             int f(struct p *q) { g.a[1] = g.x; q = &g; return *g.n == *q; }",
+            "const int x = 5; const int *f(const int a[]) { const char c; }",
+        ];
+
+        for source in sources {
+            let printed = print_program(&parse(source), Indent::default());
+            assert_eq!(
+                print_program(&parse(&printed), Indent::default()),
+                printed
+            );
+        }
+    }
+}
@@ -0,0 +1,480 @@
+//! Register allocation of the temporaries of a function, for the backends
+//! to keep them in registers rather than all in memory, by linear scan or,
+//! with `--regalloc=graph`, by coloring a graph.
+//!
+//! For linear scan, a temporary is live from where it's first assigned, or
+//! read before any assignment in a loop, to where it's last read, which
+//! `liveness` gives. Its interval is the smallest range of instructions
+//! covering all of that, so a temporary whose value goes round a loop is
+//! live for the whole loop.
+//!
+//! The intervals are gone through by where they start, each given a
+//! register no interval that overlaps it has. When there's none left, the
+//! interval that ends last, of the new one and the ones holding registers,
+//! is spilled to the stack, as it'd keep a register the longest. Spilled
+//! temporaries each have a slot of their own.
+//!
+//! Graph coloring is Chaitin's, with Briggs's optimistic coloring. Two
+//! temporaries interfere if one is assigned where the other is live after,
+//! or read by the same instruction, which the backend may write the result
+//! of before it's done reading, and the arguments all interfere, as they're
+//! assigned at once. Temporaries that interfere are nodes of a graph joined
+//! by an edge, and each is given a register, a color, none of its
+//! neighbours has. The nodes with fewer neighbours than there are registers
+//! are taken out of the graph one by one, as they'll have a register
+//! whatever their neighbours have, and when there's none, the one that's
+//! cheapest to spill for how many neighbours it has is, where a use or an
+//! assignment in a loop costs ten times one outside of it. They're then put
+//! back in the reverse order, each given the first register its neighbours
+//! put back don't have, and only spilled if there's none, as its neighbours
+//! may share registers. Since the backend works on spilled temporaries in
+//! place, in scratch registers, nothing has to be allocated again after
+//! spilling, as Chaitin's allocator does.
+//!
+//! Unlike an interval, a temporary's node knows of the holes in where it's
+//! live, e.g. when it's assigned on one path of an `if` and read in it, so
+//! it may share a register with one the interval of which overlaps.
+
+use crate::{
+    cfg::Cfg,
+    ir::{Fun, Temp},
+    liveness::{live_intervals, temp_liveness, uses_and_def, Interval},
+};
+use std::collections::BTreeSet;
+
+/// How temporaries are allocated to registers, selected with `--regalloc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum RegAlloc {
+    /// Linear scan of live intervals.
+    #[default]
+    Linear,
+    /// Chaitin-Briggs graph coloring.
+    Graph,
+}
+
+impl RegAlloc {
+    pub(crate) fn from_name(name: &str) -> Option<RegAlloc> {
+        match name {
+            "linear" => Some(RegAlloc::Linear),
+            "graph" => Some(RegAlloc::Graph),
+            _ => None,
+        }
+    }
+
+    /// Allocates the temporaries of `fun` to `registers` registers, as it
+    /// says.
+    pub(crate) fn allocate(self, fun: &Fun, registers: usize) -> Allocation {
+        match self {
+            RegAlloc::Linear => linear_scan(fun, registers),
+            RegAlloc::Graph => graph_coloring(fun, registers),
+        }
+    }
+}
+
+/// Where a temporary is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Location {
+    /// The register at this index of the ones the backend allocates.
+    Reg(usize),
+    /// The spill slot at this index.
+    Spill(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Allocation {
+    /// Where each temporary of the function is kept.
+    pub(crate) locations: Vec<Location>,
+    /// How many spill slots there are.
+    pub(crate) spill_slots: usize,
+    /// How many registers are used, the first ones of those the backend
+    /// allocates.
+    pub(crate) registers_used: usize,
+}
+
+/// Allocates the temporaries of `fun` to `registers` registers, spilling
+/// the ones that don't fit.
+pub(crate) fn linear_scan(fun: &Fun, registers: usize) -> Allocation {
+    let mut intervals = live_intervals(fun);
+    intervals.sort_by_key(|interval| (interval.start, interval.temp.0));
+
+    let mut locations = vec![None; fun.temps.len()];
+    let mut spill_slots = 0;
+    let mut spill = |locations: &mut Vec<Option<Location>>, temp: Temp| {
+        locations[temp.0] = Some(Location::Spill(spill_slots));
+        spill_slots += 1;
+    };
+    let mut free: BTreeSet<usize> = (0..registers).collect();
+    // The intervals holding registers, with their registers.
+    let mut active: Vec<(Interval, usize)> = Vec::new();
+    let mut registers_used = 0;
+
+    for interval in intervals {
+        active.retain(|&(other, reg)| {
+            let expired = other.end < interval.start;
+            if expired {
+                free.insert(reg);
+            }
+            !expired
+        });
+        if let Some(reg) = free.pop_first() {
+            locations[interval.temp.0] = Some(Location::Reg(reg));
+            registers_used = registers_used.max(reg + 1);
+            active.push((interval, reg));
+            continue;
+        }
+        let last = active
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (other, _))| other.end)
+            .map(|(index, &(other, reg))| (index, other, reg));
+        match last {
+            Some((index, other, reg)) if other.end > interval.end => {
+                spill(&mut locations, other.temp);
+                locations[interval.temp.0] = Some(Location::Reg(reg));
+                active[index] = (interval, reg);
+            }
+            _ => spill(&mut locations, interval.temp),
+        }
+    }
+
+    // A temporary that's never live, e.g. an argument that's never read,
+    // still needs a place to be put in.
+    let locations = locations
+        .into_iter()
+        .map(|location| {
+            location.unwrap_or_else(|| {
+                spill_slots += 1;
+                Location::Spill(spill_slots - 1)
+            })
+        })
+        .collect();
+    Allocation {
+        locations,
+        spill_slots,
+        registers_used,
+    }
+}
+
+/// Allocates the temporaries of `fun` to `registers` registers by coloring
+/// their interference graph, spilling the ones that don't fit.
+pub(crate) fn graph_coloring(fun: &Fun, registers: usize) -> Allocation {
+    let graph = interference(fun);
+    let costs = spill_costs(fun);
+
+    // Simplifying, taking out the nodes one by one.
+    let mut degrees: Vec<usize> =
+        graph.iter().map(|neighbours| neighbours.len()).collect();
+    let mut removed = vec![false; graph.len()];
+    let mut stack = Vec::with_capacity(graph.len());
+    for _ in 0..graph.len() {
+        let remaining = (0..graph.len()).filter(|&temp| !removed[temp]);
+        let next = remaining
+            .clone()
+            .find(|&temp| degrees[temp] < registers)
+            .unwrap_or_else(|| {
+                remaining
+                    .min_by(|&a, &b| {
+                        let a_cost = costs[a] / (degrees[a] as f64 + 1.0);
+                        let b_cost = costs[b] / (degrees[b] as f64 + 1.0);
+                        a_cost.total_cmp(&b_cost)
+                    })
+                    .expect("a node left")
+            });
+        removed[next] = true;
+        for &neighbour in &graph[next] {
+            degrees[neighbour] -= 1;
+        }
+        stack.push(next);
+    }
+
+    // Selecting, putting them back with a register each if there's one.
+    let mut locations: Vec<Option<Location>> = vec![None; graph.len()];
+    let mut spill_slots = 0;
+    let mut registers_used = 0;
+    while let Some(temp) = stack.pop() {
+        let taken: BTreeSet<usize> = graph[temp]
+            .iter()
+            .filter_map(|&neighbour| match locations[neighbour] {
+                Some(Location::Reg(reg)) => Some(reg),
+                _ => None,
+            })
+            .collect();
+        locations[temp] =
+            Some(match (0..registers).find(|reg| !taken.contains(reg)) {
+                Some(reg) => {
+                    registers_used = registers_used.max(reg + 1);
+                    Location::Reg(reg)
+                }
+                None => {
+                    spill_slots += 1;
+                    Location::Spill(spill_slots - 1)
+                }
+            });
+    }
+    Allocation {
+        locations: locations
+            .into_iter()
+            .map(|location| location.expect("a node put back"))
+            .collect(),
+        spill_slots,
+        registers_used,
+    }
+}
+
+/// The interference graph of the temporaries of `fun`: the temporaries
+/// each interferes with, by index.
+pub(crate) fn interference(fun: &Fun) -> Vec<BTreeSet<usize>> {
+    let mut graph = vec![BTreeSet::new(); fun.temps.len()];
+    let mut join = |a: usize, b: usize| {
+        if a != b {
+            graph[a].insert(b);
+            graph[b].insert(a);
+        }
+    };
+    for a in 0..fun.params {
+        for b in 0..fun.params {
+            join(a, b);
+        }
+    }
+    if fun.code.is_empty() {
+        return graph;
+    }
+    let cfg = Cfg::new(fun);
+    let live_in = temp_liveness(fun, &cfg).insts;
+    for (index, inst) in fun.code.iter().enumerate() {
+        let (uses, def) = uses_and_def(inst);
+        let Some(def) = def else {
+            continue;
+        };
+        for next in cfg.inst_succs(index) {
+            for &live in &live_in[next] {
+                join(def.0, live);
+            }
+        }
+        for used in uses {
+            join(def.0, used.0);
+        }
+    }
+    // The arguments are assigned at the start, with what's live there.
+    for &live in live_in.first().into_iter().flatten() {
+        for param in 0..fun.params {
+            join(param, live);
+        }
+    }
+    graph
+}
+
+/// What it'd cost to spill each temporary of `fun`: how many times it's
+/// assigned and read, each ten times as much for each loop it's in.
+fn spill_costs(fun: &Fun) -> Vec<f64> {
+    let mut costs = vec![0.0; fun.temps.len()];
+    if fun.code.is_empty() {
+        return costs;
+    }
+    let cfg = Cfg::new(fun);
+    let mut depths = vec![0; cfg.blocks.len()];
+    for lp in cfg.loops() {
+        for block in lp.blocks {
+            depths[block.0] += 1;
+        }
+    }
+    for (index, inst) in fun.code.iter().enumerate() {
+        let weight = 10f64.powi(depths[cfg.block_of(index).0]);
+        let (uses, def) = uses_and_def(inst);
+        for temp in uses.into_iter().chain(def) {
+            costs[temp.0] += weight;
+        }
+    }
+    costs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        graph_coloring, interference, linear_scan, live_intervals, Allocation,
+        Interval, Location,
+    };
+    use crate::{
+        ast::BinOp,
+        extensions::Extensions,
+        ir::{lower_program, Fun, Inst, Label, Operand, Temp, Ty},
+        parser::ParseResult,
+        sema::{check_program, Builtin},
+        test_support::{accept_corpus, parse_source},
+    };
+    use std::fs;
+
+    #[test]
+    fn values_going_round_a_loop_are_live_for_all_of_it() {
+        // %0 is read at the top of the loop, so it's live at the jump back
+        // there, after its last assignment.
+        let (t0, t1) = (Temp(0), Temp(1));
+        let fun = Fun {
+            name: "f".into(),
+            params: 0,
+            ret: None,
+            slots: Vec::new(),
+            temps: vec![Ty::I32, Ty::I32],
+            labels: 3,
+            code: vec![
+                Inst::Copy {
+                    dst: t0,
+                    src: Operand::Const(3),
+                },
+                Inst::Jump(Label(0)),
+                Inst::Label(Label(0)),
+                Inst::Branch {
+                    cond: Operand::Temp(t0),
+                    then_label: Label(1),
+                    else_label: Label(2),
+                },
+                Inst::Label(Label(1)),
+                Inst::Copy {
+                    dst: t1,
+                    src: Operand::Const(1),
+                },
+                Inst::Binary {
+                    dst: t0,
+                    op: BinOp::Sub,
+                    lhs: Operand::Temp(t0),
+                    rhs: Operand::Temp(t1),
+                },
+                Inst::Jump(Label(0)),
+                Inst::Label(Label(2)),
+                Inst::Return(None),
+            ],
+        };
+
+        let mut intervals = live_intervals(&fun);
+        intervals.sort_by_key(|interval| interval.temp.0);
+        assert_eq!(
+            intervals,
+            [
+                Interval {
+                    temp: t0,
+                    start: 0,
+                    end: 7
+                },
+                Interval {
+                    temp: t1,
+                    start: 5,
+                    end: 6
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn temporaries_live_at_once_never_share_a_register() {
+        let source =
+            "int f(int a, int b) { return a * b + (a - b) / (b + 1); }";
+        let (_, ParseResult { program, .. }) =
+            parse_source(source, Extensions::none());
+        let hir = check_program(&program, &Builtin::ALL).hir;
+        let fun = &lower_program(&hir).funs[0];
+        let intervals = live_intervals(fun);
+
+        for registers in 0..4 {
+            let allocation = linear_scan(fun, registers);
+            assert!(allocation.registers_used <= registers);
+            let spilled = allocation
+                .locations
+                .iter()
+                .filter(|location| matches!(location, Location::Spill(_)))
+                .count();
+            assert_eq!(spilled, allocation.spill_slots);
+            for a in &intervals {
+                for b in &intervals {
+                    let overlap = a.start <= b.end && b.start <= a.end;
+                    let (a_at, b_at) = (
+                        allocation.locations[a.temp.0],
+                        allocation.locations[b.temp.0],
+                    );
+                    assert!(a == b || !overlap || a_at != b_at);
+                }
+            }
+        }
+        assert_eq!(linear_scan(fun, 8).spill_slots, 0);
+    }
+
+    /// The functions of `source`, lowered.
+    fn funs(source: &str) -> Vec<Fun> {
+        let (_, ParseResult { program, .. }) =
+            parse_source(source, Extensions::all());
+        let hir = check_program(&program, &Builtin::ALL).hir;
+        lower_program(&hir).funs
+    }
+
+    #[test]
+    fn interfering_temporaries_never_share_a_color() {
+        for path in accept_corpus() {
+            let source = fs::read_to_string(path).unwrap();
+            for fun in funs(&source) {
+                let graph = interference(&fun);
+                for registers in [0, 1, 2, 5] {
+                    let allocation = graph_coloring(&fun, registers);
+                    assert!(allocation.registers_used <= registers);
+                    for (a, neighbours) in graph.iter().enumerate() {
+                        for &b in neighbours {
+                            let (a_at, b_at) = (
+                                allocation.locations[a],
+                                allocation.locations[b],
+                            );
+                            assert_ne!(a_at, b_at, "%{} and %{}", a, b);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn holes_in_where_temporaries_are_live_are_shared() {
+        // %0 is live from where it's assigned to the end, but not where %1
+        // is, which is between, on the other path of the branch.
+        let (t0, t1, t2) = (Temp(0), Temp(1), Temp(2));
+        let fun = Fun {
+            name: "f".into(),
+            params: 0,
+            ret: Some(Ty::I32),
+            slots: Vec::new(),
+            temps: vec![Ty::I32; 3],
+            labels: 3,
+            code: vec![
+                Inst::Copy {
+                    dst: t2,
+                    src: Operand::Const(1),
+                },
+                Inst::Branch {
+                    cond: Operand::Temp(t2),
+                    then_label: Label(0),
+                    else_label: Label(1),
+                },
+                Inst::Label(Label(0)),
+                Inst::Copy {
+                    dst: t0,
+                    src: Operand::Const(3),
+                },
+                Inst::Jump(Label(2)),
+                Inst::Label(Label(1)),
+                Inst::Copy {
+                    dst: t1,
+                    src: Operand::Const(4),
+                },
+                Inst::Return(Some(Operand::Temp(t1))),
+                Inst::Label(Label(2)),
+                Inst::Return(Some(Operand::Temp(t0))),
+            ],
+        };
+
+        assert_eq!(linear_scan(&fun, 1).spill_slots, 1);
+        assert_eq!(
+            graph_coloring(&fun, 1),
+            Allocation {
+                locations: vec![Location::Reg(0); 3],
+                spill_slots: 0,
+                registers_used: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Parsing a program again after an edit, for tools that keep the tree of a
+//! file being edited, e.g. an editor.
+//!
+//! An edit confined to one top-level declaration only needs that declaration
+//! parsed again: the new one takes the place of the old one in the tree, and
+//! the spans after the edit move by as much as the edit grew or shrank the
+//! text. Anything else, e.g. an edit across two declarations, takes parsing
+//! the whole file again.
+
+use crate::{
+    ast::{
+        BlockId, ExprId, ExprKind, FunDecl, Ident, Nodes, Param, Program,
+        StmtId, StmtKind, StructDecl, TypeSpec, VarDecl,
+    },
+    extensions::Extensions,
+    parser::parse_program_into,
+    scanner::scan_words,
+    source_map::{BytePos, Pos, SourceFile, Span},
+    visit::{
+        walk_block_mut, walk_expr_mut, walk_fun_decl_mut, walk_param_mut,
+        walk_stmt_mut, walk_struct_decl_mut, walk_var_decl_mut, VisitorMut,
+    },
+};
+use std::mem;
+
+/// The text at `span` replaced by `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Edit {
+    pub(crate) span: Span,
+    pub(crate) replacement: String,
+}
+
+/// A program after an edit was reparsed into it.
+pub(crate) struct Reparsed {
+    /// The edited file, at the same start position as the file before the
+    /// edit. The spans of the program point into it.
+    pub(crate) source_file: SourceFile,
+    /// The index of the declaration parsed again in the program.
+    pub(crate) index: usize,
+}
+
+/// Applies `edit` to `source_file`, and parses the declaration of `program`
+/// it's in again, putting it in place of the old one.
+///
+/// Returns `None`, leaving the declarations of `program` as they were, if
+/// the edit isn't inside exactly one declaration, or if the edited
+/// declaration doesn't parse into exactly one declaration without errors.
+/// Then the whole file has to be parsed again, as the edit may change how
+/// the declarations around it parse. The nodes of the reparsed declaration
+/// are stored after the ones already in the program, so the arenas then hold
+/// the nodes of the old one, which aren't part of the tree anymore.
+///
+/// Positions after the end of `source_file` aren't moved, so if the file
+/// isn't the last one of its `SourceMap` and the edit changes its length,
+/// its positions overlap with the ones of the files after it.
+pub(crate) fn reparse_edit(
+    program: &mut Program,
+    source_file: &SourceFile,
+    edit: &Edit,
+    extensions: Extensions,
+) -> Option<Reparsed> {
+    let mut containing =
+        program.decls.iter().enumerate().filter(|(_, decl)| {
+            let span = decl.span();
+            span.start <= edit.span.start && edit.span.end <= span.end
+        });
+    let (index, old_span) = match (containing.next(), containing.next()) {
+        (Some((index, decl)), None) => (index, decl.span()),
+        _ => return None,
+    };
+
+    let start_pos = source_file.start_pos;
+    let src = source_file.src.as_str();
+    let offset = |pos: BytePos| (pos - start_pos).to_usize();
+    let edited_src = format!(
+        "{}{}{}",
+        &src[..offset(edit.span.start)],
+        edit.replacement,
+        &src[offset(edit.span.end)..]
+    );
+    let old_end = old_span.end;
+    let new_end = old_end - edit.span.end
+        + edit.span.start
+        + BytePos(edit.replacement.len());
+
+    let decl_file = SourceFile::with_start_pos(
+        source_file.name.clone(),
+        edited_src[offset(old_span.start)..offset(new_end)].to_string(),
+        old_span.start,
+    );
+    let (words, scan_diags) = scan_words(&decl_file, extensions);
+    if scan_diags.has_errors() {
+        return None;
+    }
+
+    let old_nodes = mem::take(&mut program.nodes);
+    let mut result =
+        parse_program_into(&decl_file, &words, extensions, old_nodes);
+    program.nodes = mem::take(&mut result.program.nodes);
+    if result.diag_bag.has_errors()
+        || !result.is_complete()
+        || result.program.decls.len() != 1
+    {
+        return None;
+    }
+
+    let mut rebase = Rebase { old_end, new_end };
+    for decl in &mut program.decls[index + 1..] {
+        rebase.visit_decl_mut(&mut program.nodes, decl);
+    }
+
+    program.decls[index] = result.program.decls.remove(0);
+
+    Some(Reparsed {
+        source_file: SourceFile::with_start_pos(
+            source_file.name.clone(),
+            edited_src,
+            start_pos,
+        ),
+        index,
+    })
+}
+
+/// Moves the positions from the end of an edited declaration on, from where
+/// they were before the edit to where they are after it, in the nodes it
+/// visits.
+struct Rebase {
+    old_end: BytePos,
+    new_end: BytePos,
+}
+
+impl Rebase {
+    fn pos(&self, pos: &mut BytePos) {
+        if *pos >= self.old_end {
+            *pos = *pos - self.old_end + self.new_end;
+        }
+    }
+
+    fn span(&self, span: &mut Span) {
+        if span.start >= self.old_end {
+            self.pos(&mut span.start);
+            self.pos(&mut span.end);
+        }
+    }
+
+    fn type_spec(&mut self, ty: &mut TypeSpec) {
+        if let TypeSpec::Struct(name) = ty {
+            self.visit_ident_mut(name);
+        }
+    }
+}
+
+impl VisitorMut for Rebase {
+    fn visit_var_decl_mut(
+        &mut self,
+        nodes: &mut Nodes,
+        var_decl: &mut VarDecl,
+    ) {
+        self.type_spec(&mut var_decl.ty);
+        self.span(&mut var_decl.span);
+        walk_var_decl_mut(self, nodes, var_decl);
+    }
+
+    fn visit_fun_decl_mut(
+        &mut self,
+        nodes: &mut Nodes,
+        fun_decl: &mut FunDecl,
+    ) {
+        self.type_spec(&mut fun_decl.return_ty);
+        self.span(&mut fun_decl.params_span);
+        self.span(&mut fun_decl.span);
+        walk_fun_decl_mut(self, nodes, fun_decl);
+    }
+
+    fn visit_param_mut(&mut self, nodes: &mut Nodes, param: &mut Param) {
+        self.type_spec(&mut param.ty);
+        self.span(&mut param.span);
+        walk_param_mut(self, nodes, param);
+    }
+
+    fn visit_struct_decl_mut(
+        &mut self,
+        nodes: &mut Nodes,
+        struct_decl: &mut StructDecl,
+    ) {
+        self.span(&mut struct_decl.span);
+        walk_struct_decl_mut(self, nodes, struct_decl);
+    }
+
+    fn visit_block_mut(&mut self, nodes: &mut Nodes, block: BlockId) {
+        self.span(&mut nodes[block].span);
+        walk_block_mut(self, nodes, block);
+    }
+
+    fn visit_stmt_mut(&mut self, nodes: &mut Nodes, stmt: StmtId) {
+        self.span(&mut nodes[stmt].span);
+        if let StmtKind::Switch { cases, .. } = &mut nodes[stmt].kind {
+            for case in cases {
+                self.span(&mut case.label_span);
+            }
+        }
+        walk_stmt_mut(self, nodes, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, nodes: &mut Nodes, expr: ExprId) {
+        self.span(&mut nodes[expr].span);
+        // The walk leaves the field out, as it names no variable.
+        if let ExprKind::Member { field, .. } = &mut nodes[expr].kind {
+            self.visit_ident_mut(field);
+        }
+        walk_expr_mut(self, nodes, expr);
+    }
+
+    fn visit_ident_mut(&mut self, ident: &mut Ident) {
+        self.span(&mut ident.span);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reparse_edit, Edit};
+    use crate::{
+        ast::{Decl, ExprKind, Program},
+        dump::dump_program,
+        extensions::Extensions,
+        parser::ParseResult,
+        source_map::{SourceFile, Span},
+        test_support::parse_source,
+    };
+
+    fn parse(source: &str) -> (SourceFile, Program) {
+        let (
+            source_file,
+            ParseResult {
+                program, diag_bag, ..
+            },
+        ) = parse_source(source, Extensions::all());
+        assert!(!diag_bag.has_errors());
+        (source_file, program)
+    }
+
+    /// Applies the edit replacing the first `old` in `source` with `new`.
+    fn reparse(source: &str, old: &str, new: &str) -> Option<String> {
+        let (source_file, mut program) = parse(source);
+        let start = source.find(old).unwrap();
+        let edit = Edit {
+            span: Span::with_usizes(start, start + old.len()),
+            replacement: new.into(),
+        };
+
+        let reparsed =
+            reparse_edit(&mut program, &source_file, &edit, Extensions::all())?;
+        Some(dump_program(&program, &reparsed.source_file))
+    }
+
+    #[test]
+    fn reparsing_an_edit_is_like_parsing_the_edited_source() {
+        let source = "int g;\n\
+                      int f(int a) {\n\
+                      \x20   return a + 1;\n\
+                      }\n\
+                      struct p { int x; };\n\
+                      int main(void) {\n\
+                      \x20   struct p q;\n\
+                      \x20   switch (g) { case 1: q.x = f(g); }\n\
+                      }\n";
+
+        for (old, new) in [
+            ("a + 1", "a * (a - 2)"),
+            ("a + 1", "a"),
+            ("return a + 1;", "while (a) a = a - 1;\n    return a;"),
+            ("int g;", "int g[10];"),
+        ] {
+            let edited = source.replacen(old, new, 1);
+            let (edited_file, program) = parse(&edited);
+
+            assert_eq!(
+                reparse(source, old, new).as_deref(),
+                Some(dump_program(&program, &edited_file).as_str()),
+                "{:?}",
+                edited
+            );
+        }
+    }
+
+    #[test]
+    fn names_after_the_edit_move_with_it() {
+        let source = "int f(void) { return 1; }\nint g(void) { return f(); }";
+        let (source_file, mut program) = parse(source);
+        let edit = Edit {
+            span: Span::with_usizes(21, 22),
+            replacement: "100".into(),
+        };
+
+        let reparsed =
+            reparse_edit(&mut program, &source_file, &edit, Extensions::all())
+                .unwrap();
+
+        assert_eq!(reparsed.index, 0);
+        let callee = program
+            .nodes
+            .exprs
+            .iter()
+            .find_map(|(_, expr)| match &expr.kind {
+                ExprKind::Call { callee, .. } => Some(callee.span),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(reparsed.source_file.span_to_snippet(callee), "f");
+        match &program.decls[1] {
+            Decl::Fun(fun_decl) => assert_eq!(
+                reparsed.source_file.span_to_snippet(fun_decl.name.span),
+                "g"
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn edits_that_may_change_other_declarations_arent_reparsed() {
+        let source = "int x;\nint f(void) { return x; }\nint y;\n";
+
+        // Across two declarations.
+        assert_eq!(reparse(source, ";\nint f", ""), None);
+        // Between two declarations.
+        assert_eq!(reparse(source, "\nint y", " int y"), None);
+        // Breaking the braces, so the declaration would take the next one in.
+        assert_eq!(reparse(source, "}", ""), None);
+        // Splitting a declaration in two.
+        assert_eq!(reparse(source, "int x;", "int x; int z;"), None);
+        // A syntax error.
+        assert_eq!(reparse(source, "return x;", "return x"), None);
+    }
+}
@@ -0,0 +1,304 @@
+//! The runtime of C-, which is its builtins, `input()` reading an integer
+//! from the standard input and `output(x)` writing `x` to the standard
+//! output, a line each, for every target a program runs on.
+//!
+//! The interpreter calls the natives here. The backends lower a call to a
+//! builtin to one to its function, `csub_input` or `csub_output`, and write
+//! the ones the program calls into it, after its own functions, as shims
+//! over libc: assembly for x86-64, and IR for LLVM. So what they emit is
+//! all there is to the program, linked with nothing but libc.
+//!
+//! `input()` flushes the standard output before it reads, so a prompt is
+//! seen before it waits, and the program stops if what it reads isn't an
+//! integer: the interpreter reports it, and the others write an error and
+//! exit with 1. So does a check the code was lowered with that fails (see
+//! `Inst::Check`), calling the function of its check with the line it's
+//! at.
+
+use crate::{
+    ir::{Callee, Check, Inst, Program},
+    sema::Builtin,
+};
+use std::io::{BufRead, Write};
+
+/// The name of the function `builtin` is lowered to.
+pub(crate) fn symbol(builtin: Builtin) -> &'static str {
+    match builtin {
+        Builtin::Input => "csub_input",
+        Builtin::Output => "csub_output",
+    }
+}
+
+/// The builtins `program` calls, in the order of `Builtin::ALL`.
+pub(crate) fn used_builtins(program: &Program) -> Vec<Builtin> {
+    let calls = |builtin| {
+        let mut code = program.funs.iter().flat_map(|fun| &fun.code);
+        code.any(|inst| {
+            matches!(inst, Inst::Call { callee: Callee::Builtin(called), .. }
+                if *called == builtin)
+        })
+    };
+    Builtin::ALL
+        .into_iter()
+        .filter(|&builtin| calls(builtin))
+        .collect()
+}
+
+/// The name of the function a failed `check` calls, with its line.
+pub(crate) fn check_symbol(check: Check) -> &'static str {
+    match check {
+        Check::Bounds => "csub_bounds_error",
+        Check::Division => "csub_division_error",
+    }
+}
+
+/// The checks in the code of `program`, in the order of `Check::ALL`.
+pub(crate) fn used_checks(program: &Program) -> Vec<Check> {
+    let checks = |check| {
+        let mut code = program.funs.iter().flat_map(|fun| &fun.code);
+        code.any(|inst| {
+            matches!(inst, Inst::Check { check: checked, .. }
+                if *checked == check)
+        })
+    };
+    Check::ALL
+        .into_iter()
+        .filter(|&check| checks(check))
+        .collect()
+}
+
+/// Calls `builtin` with `args` in the interpreter, returning what it
+/// returns, 0 if it's `void`. `None` is for `input()` not reading an
+/// integer.
+pub(crate) fn call_native(
+    builtin: Builtin,
+    args: &[i64],
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Option<i64> {
+    match builtin {
+        Builtin::Input => {
+            // Whatever a prompt wrote has to be seen before waiting.
+            let _ = output.flush();
+            read_int(input)
+        }
+        Builtin::Output => {
+            let _ = writeln!(output, "{}", args[0] as i32);
+            Some(0)
+        }
+    }
+}
+
+/// Reads an integer, after any whitespace, up to the next whitespace.
+fn read_int(input: &mut dyn BufRead) -> Option<i64> {
+    let mut word = Vec::new();
+    loop {
+        let buf = input.fill_buf().ok()?;
+        if buf.is_empty() {
+            break;
+        }
+        let skipped = if word.is_empty() {
+            buf.iter().take_while(|b| b.is_ascii_whitespace()).count()
+        } else {
+            0
+        };
+        let len = buf[skipped..]
+            .iter()
+            .take_while(|b| !b.is_ascii_whitespace())
+            .count();
+        word.extend_from_slice(&buf[skipped..skipped + len]);
+        let at_end = skipped + len < buf.len();
+        input.consume(skipped + len);
+        if at_end && !word.is_empty() {
+            break;
+        }
+    }
+    let word = std::str::from_utf8(&word).ok()?;
+    word.parse::<i32>().ok().map(i64::from)
+}
+
+/// The function of `builtin` in x86-64 assembly, in AT&T syntax, with its
+/// strings. It's local to the file, as the functions of the program but
+/// `main` are, and calls libc through the PLT.
+pub(crate) fn x86_64(builtin: Builtin) -> &'static str {
+    match builtin {
+        Builtin::Input => {
+            "\t.section .rodata\n\
+             .Lcsub_input_format:\n\
+             \t.asciz \"%d\"\n\
+             .Lcsub_input_message:\n\
+             \t.asciz \"error: `input()` read something that isn't an integer\\n\"\n\
+             \t.text\n\
+             \t.type csub_input, @function\n\
+             csub_input:\n\
+             \tpushq %rbp\n\
+             \tmovq %rsp, %rbp\n\
+             \tsubq $16, %rsp\n\
+             \txorl %edi, %edi\n\
+             \tcall fflush@PLT\n\
+             \tleaq .Lcsub_input_format(%rip), %rdi\n\
+             \tleaq -4(%rbp), %rsi\n\
+             \txorl %eax, %eax\n\
+             \tcall scanf@PLT\n\
+             \tcmpl $1, %eax\n\
+             \tjne .Lcsub_input_error\n\
+             \tmovl -4(%rbp), %eax\n\
+             \tleave\n\
+             \tret\n\
+             .Lcsub_input_error:\n\
+             \tleaq .Lcsub_input_message(%rip), %rdi\n\
+             \tmovq stderr@GOTPCREL(%rip), %rax\n\
+             \tmovq (%rax), %rsi\n\
+             \tcall fputs@PLT\n\
+             \tmovl $1, %edi\n\
+             \tcall exit@PLT\n"
+        }
+        Builtin::Output => {
+            "\t.section .rodata\n\
+             .Lcsub_output_format:\n\
+             \t.asciz \"%d\\n\"\n\
+             \t.text\n\
+             \t.type csub_output, @function\n\
+             csub_output:\n\
+             \tpushq %rbp\n\
+             \tmovq %rsp, %rbp\n\
+             \tmovl %edi, %esi\n\
+             \tleaq .Lcsub_output_format(%rip), %rdi\n\
+             \txorl %eax, %eax\n\
+             \tcall printf@PLT\n\
+             \tleave\n\
+             \tret\n"
+        }
+    }
+}
+
+/// What the function of a failed `check` writes, before the line.
+fn check_message(check: Check) -> &'static str {
+    match check {
+        Check::Bounds => "error: index out of the bounds of its array",
+        Check::Division => "error: division by zero",
+    }
+}
+
+/// The function of a failed `check` in x86-64 assembly, as for a builtin,
+/// flushing the standard output, writing an error with the line in its
+/// first argument, and exiting with 1.
+pub(crate) fn x86_64_check(check: Check) -> String {
+    let symbol = check_symbol(check);
+    format!(
+        "\t.section .rodata\n\
+         .L{symbol}_message:\n\
+         \t.asciz \"{message}, at line %d\\n\"\n\
+         \t.text\n\
+         \t.type {symbol}, @function\n\
+         {symbol}:\n\
+         \tpushq %rbp\n\
+         \tmovq %rsp, %rbp\n\
+         \tsubq $16, %rsp\n\
+         \tmovl %edi, -4(%rbp)\n\
+         \txorl %edi, %edi\n\
+         \tcall fflush@PLT\n\
+         \tmovl -4(%rbp), %edx\n\
+         \tleaq .L{symbol}_message(%rip), %rsi\n\
+         \tmovq stderr@GOTPCREL(%rip), %rax\n\
+         \tmovq (%rax), %rdi\n\
+         \txorl %eax, %eax\n\
+         \tcall fprintf@PLT\n\
+         \tmovl $1, %edi\n\
+         \tcall exit@PLT\n",
+        symbol = symbol,
+        message = check_message(check),
+    )
+}
+
+/// The function of `builtin` in LLVM IR, internal to the module, with its
+/// strings and the declarations of the libc functions it calls, which the
+/// backend writes once however many functions of the runtime have them.
+pub(crate) fn llvm_ir(builtin: Builtin) -> &'static str {
+    match builtin {
+        Builtin::Input => {
+            "@csub_input.format = private unnamed_addr constant [3 x i8] c\"%d\\00\"\n\
+             @csub_input.message = private unnamed_addr constant [55 x i8] c\"error: `input()` read something that isn't an integer\\0A\\00\"\n\
+             @stderr = external global ptr\n\
+             \n\
+             define internal i32 @csub_input() {\n\
+             \x20 %value = alloca i32\n\
+             \x20 call i32 @fflush(ptr null)\n\
+             \x20 %read = call i32 (ptr, ...) @scanf(ptr @csub_input.format, ptr %value)\n\
+             \x20 %ok = icmp eq i32 %read, 1\n\
+             \x20 br i1 %ok, label %done, label %error\n\
+             done:\n\
+             \x20 %result = load i32, ptr %value\n\
+             \x20 ret i32 %result\n\
+             error:\n\
+             \x20 %stderr = load ptr, ptr @stderr\n\
+             \x20 call i32 @fputs(ptr @csub_input.message, ptr %stderr)\n\
+             \x20 call void @exit(i32 1)\n\
+             \x20 unreachable\n\
+             }\n\
+             \n\
+             declare i32 @fflush(ptr)\n\
+             declare i32 @scanf(ptr, ...)\n\
+             declare i32 @fputs(ptr, ptr)\n\
+             declare void @exit(i32)\n"
+        }
+        Builtin::Output => {
+            "@csub_output.format = private unnamed_addr constant [4 x i8] c\"%d\\0A\\00\"\n\
+             \n\
+             define internal void @csub_output(i32 %value) {\n\
+             \x20 call i32 (ptr, ...) @printf(ptr @csub_output.format, i32 %value)\n\
+             \x20 ret void\n\
+             }\n\
+             \n\
+             declare i32 @printf(ptr, ...)\n"
+        }
+    }
+}
+
+/// The function of a failed `check` in LLVM IR, as for a builtin.
+pub(crate) fn llvm_ir_check(check: Check) -> String {
+    let symbol = check_symbol(check);
+    let message = format!("{}, at line %d", check_message(check));
+    format!(
+        "@{symbol}.message = private unnamed_addr constant [{len} x i8] c\"{message}\\0A\\00\"\n\
+         @stderr = external global ptr\n\
+         \n\
+         define internal void @{symbol}(i32 %line) {{\n\
+         \x20 call i32 @fflush(ptr null)\n\
+         \x20 %stderr = load ptr, ptr @stderr\n\
+         \x20 call i32 (ptr, ptr, ...) @fprintf(ptr %stderr, ptr @{symbol}.message, i32 %line)\n\
+         \x20 call void @exit(i32 1)\n\
+         \x20 unreachable\n\
+         }}\n\
+         \n\
+         declare i32 @fflush(ptr)\n\
+         declare i32 @fprintf(ptr, ptr, ...)\n\
+         declare void @exit(i32)\n",
+        symbol = symbol,
+        // With the newline and the terminating NUL.
+        len = message.len() + 2,
+        message = message,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::call_native;
+    use crate::sema::Builtin;
+
+    #[test]
+    fn natives_read_and_write_integers_a_line_each() {
+        let mut input = " 12\n-3x 4".as_bytes();
+        let mut output = Vec::new();
+        let mut call = |builtin, args: &[i64]| {
+            call_native(builtin, args, &mut input, &mut output)
+        };
+
+        assert_eq!(call(Builtin::Input, &[]), Some(12));
+        assert_eq!(call(Builtin::Output, &[-7]), Some(0));
+        assert_eq!(call(Builtin::Input, &[]), None);
+        assert_eq!(call(Builtin::Input, &[]), Some(4));
+        assert_eq!(call(Builtin::Input, &[]), None);
+        assert_eq!(output, b"-7\n");
+    }
+}
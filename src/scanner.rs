@@ -1,12 +1,15 @@
-#![allow(dead_code)]
-
-use crate::errors::{Diag, DiagBag};
+use crate::{
+    catalog::message,
+    errors::{Diag, DiagBag, MAX_IDENTIFIER_LENGTH},
+    extensions::{Extension, Extensions},
+    ice::set_current_span,
+};
 use std::{iter::Peekable, str::Chars};
 
-use crate::source_map::{BytePos, Pos, Span};
+use crate::source_map::{BytePos, Pos, SourceFile, Span};
 
-#[derive(Debug, PartialEq)]
-enum Category {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Category {
     Kw(Keyword),
     Plus,
     Minus,
@@ -19,8 +22,12 @@ enum Category {
     EqualEqual,
     ExclamaEqual,
     Equal,
+    Ampersand,
+    Dot,
     Semicolon,
     Comma,
+    Colon,
+    Question,
     OpenParen,
     CloseParen,
     OpenCurly,
@@ -29,21 +36,154 @@ enum Category {
     CloseBracket,
     Ident,
     Number,
+    CharLiteral,
+    StringLiteral,
     Eof,
 }
 
-#[derive(Debug, PartialEq)]
-enum Keyword {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Keyword {
+    Break,
+    Case,
+    Char,
+    Const,
+    Continue,
+    Default,
+    Do,
     Else,
+    For,
     If,
     Int,
     Return,
+    Struct,
+    Switch,
     Void,
     While,
 }
 
-#[derive(Debug, PartialEq)]
-struct Word {
+impl Category {
+    /// The text of the words in this category, if they all have the same,
+    /// e.g. `;` or `while`.
+    pub(crate) fn symbol(self) -> Option<&'static str> {
+        let symbol = match self {
+            Category::Kw(keyword) => keyword.as_str(),
+            Category::Plus => "+",
+            Category::Minus => "-",
+            Category::Star => "*",
+            Category::Slash => "/",
+            Category::Less => "<",
+            Category::LessEqual => "<=",
+            Category::Greater => ">",
+            Category::GreaterEqual => ">=",
+            Category::EqualEqual => "==",
+            Category::ExclamaEqual => "!=",
+            Category::Equal => "=",
+            Category::Ampersand => "&",
+            Category::Dot => ".",
+            Category::Semicolon => ";",
+            Category::Comma => ",",
+            Category::Colon => ":",
+            Category::Question => "?",
+            Category::OpenParen => "(",
+            Category::CloseParen => ")",
+            Category::OpenCurly => "{",
+            Category::CloseCurly => "}",
+            Category::OpenBracket => "[",
+            Category::CloseBracket => "]",
+            Category::Ident
+            | Category::Number
+            | Category::CharLiteral
+            | Category::StringLiteral
+            | Category::Eof => return None,
+        };
+
+        Some(symbol)
+    }
+
+    /// How the category is called in diagnostics, e.g. "`;`" or "an
+    /// identifier".
+    pub(crate) fn describe(self) -> String {
+        match self {
+            Category::Ident => message("token.ident", &[]),
+            Category::Number => message("token.number", &[]),
+            Category::CharLiteral => message("token.char", &[]),
+            Category::StringLiteral => message("token.string", &[]),
+            Category::Eof => message("token.eof", &[]),
+            _ => format!("`{}`", self.symbol().unwrap_or_default()),
+        }
+    }
+}
+
+impl Keyword {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Keyword::Break => "break",
+            Keyword::Case => "case",
+            Keyword::Char => "char",
+            Keyword::Const => "const",
+            Keyword::Continue => "continue",
+            Keyword::Default => "default",
+            Keyword::Do => "do",
+            Keyword::Else => "else",
+            Keyword::For => "for",
+            Keyword::If => "if",
+            Keyword::Int => "int",
+            Keyword::Return => "return",
+            Keyword::Struct => "struct",
+            Keyword::Switch => "switch",
+            Keyword::Void => "void",
+            Keyword::While => "while",
+        }
+    }
+
+    /// The extension that makes the word a keyword, if it isn't one of the
+    /// reference grammar.
+    pub(crate) fn extension(self) -> Option<Extension> {
+        match self {
+            Keyword::Break => Some(Extension::Break),
+            Keyword::Case | Keyword::Default | Keyword::Switch => {
+                Some(Extension::Switch)
+            }
+            Keyword::Char => Some(Extension::Char),
+            Keyword::Const => Some(Extension::Const),
+            Keyword::Continue => Some(Extension::Continue),
+            Keyword::Do => Some(Extension::DoWhile),
+            Keyword::For => Some(Extension::For),
+            Keyword::Struct => Some(Extension::Structs),
+            Keyword::Else
+            | Keyword::If
+            | Keyword::Int
+            | Keyword::Return
+            | Keyword::Void
+            | Keyword::While => None,
+        }
+    }
+
+    pub(crate) fn from_str(text: &str) -> Option<Keyword> {
+        match text {
+            "break" => Some(Keyword::Break),
+            "case" => Some(Keyword::Case),
+            "char" => Some(Keyword::Char),
+            "const" => Some(Keyword::Const),
+            "continue" => Some(Keyword::Continue),
+            "default" => Some(Keyword::Default),
+            "do" => Some(Keyword::Do),
+            "else" => Some(Keyword::Else),
+            "for" => Some(Keyword::For),
+            "if" => Some(Keyword::If),
+            "int" => Some(Keyword::Int),
+            "return" => Some(Keyword::Return),
+            "struct" => Some(Keyword::Struct),
+            "switch" => Some(Keyword::Switch),
+            "void" => Some(Keyword::Void),
+            "while" => Some(Keyword::While),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Word {
     pub category: Category,
     pub lexeme: Span,
 }
@@ -63,15 +203,23 @@ struct CharBumper<'chars> {
 }
 
 impl<'chars> CharBumper<'chars> {
+    #[cfg(test)]
     fn new(chars: Chars<'chars>) -> CharBumper<'chars> {
+        CharBumper::with_start_pos(chars, BytePos(0))
+    }
+
+    fn with_start_pos(
+        chars: Chars<'chars>,
+        start_pos: BytePos,
+    ) -> CharBumper<'chars> {
         CharBumper {
             char_stream: chars.peekable(),
-            current_peek_pos: BytePos(0),
+            current_peek_pos: start_pos,
         }
     }
 
     fn peek(&mut self) -> Option<char> {
-        self.char_stream.peek().map(|&c| c)
+        self.char_stream.peek().copied()
     }
 
     fn peek_is(&mut self, ch: char) -> bool {
@@ -80,20 +228,19 @@ impl<'chars> CharBumper<'chars> {
 
     fn bump(&mut self) -> Option<char> {
         let next_char = self.char_stream.next();
-        next_char.and_then(|c| {
+        next_char.inspect(|c| {
             let num_of_bytes_in_utf8_char = Pos::from_usize(c.len_utf8());
             self.current_peek_pos =
                 self.current_peek_pos + num_of_bytes_in_utf8_char;
-            Some(c)
         })
     }
 
     fn bump_if(&mut self, ch: char) -> bool {
         if self.peek_is(ch) {
             self.bump();
-            return true;
+            true
         } else {
-            return false;
+            false
         }
     }
 }
@@ -107,13 +254,29 @@ enum ScanState {
 }
 
 struct CSubScanner<'chars> {
+    source_text: &'chars str,
+    start_pos: BytePos,
     char_stream: CharBumper<'chars>,
 }
 
 impl CSubScanner<'_> {
+    #[cfg(test)]
     fn with_chars(chars: Chars<'_>) -> CSubScanner<'_> {
+        CSubScanner::with_start_pos(chars, BytePos(0))
+    }
+
+    fn with_source_file(source_file: &SourceFile) -> CSubScanner<'_> {
+        CSubScanner::with_start_pos(
+            source_file.src.chars(),
+            source_file.start_pos,
+        )
+    }
+
+    fn with_start_pos(chars: Chars<'_>, start_pos: BytePos) -> CSubScanner<'_> {
         CSubScanner {
-            char_stream: CharBumper::new(chars),
+            source_text: chars.as_str(),
+            start_pos,
+            char_stream: CharBumper::with_start_pos(chars, start_pos),
         }
     }
 
@@ -133,36 +296,48 @@ impl CSubScanner<'_> {
         self.char_stream.bump_if(expected_char)
     }
 
-    fn scan_next_word(&mut self) -> Result<Word, DiagBag> {
+    fn scan_next_word(&mut self) -> Result<Word, Diag> {
         let lexeme_start = self.char_stream.current_peek_pos;
         let scan_state = self.analyse_category_and_bump_chars();
         match scan_state {
-            Ok(ScanState::FoundCategory(Category::Ident)) => {
-                // TODO: CharBumper should be created by a `SourceMap`, so that
-                // we can request the text for a span.
-                todo!("scan keywords!")
-            }
             Ok(ScanState::FoundCategory(category)) => {
                 let lexeme = Span {
                     start: lexeme_start,
                     end: self.char_stream.current_peek_pos,
                 };
 
+                let category = match category {
+                    Category::Ident => self.keyword_or_ident(lexeme),
+                    category => category,
+                };
+
                 Ok(Word { category, lexeme })
             }
             Ok(ScanState::Skipped) => self.scan_next_word(),
             Ok(ScanState::ReachedEndOfInput) => Ok(Word::end_of_input()),
-            Err(_) => todo!("diagnose errors!"),
+            Err(diag) => Err(diag),
+        }
+    }
+
+    fn keyword_or_ident(&self, lexeme: Span) -> Category {
+        let start = (lexeme.start - self.start_pos).to_usize();
+        let end = (lexeme.end - self.start_pos).to_usize();
+        let text = &self.source_text[start..end];
+
+        match Keyword::from_str(text) {
+            Some(keyword) => Category::Kw(keyword),
+            None => Category::Ident,
         }
     }
 
     fn analyse_category_and_bump_chars(&mut self) -> ScanResult {
+        let char_pos = self.char_stream.current_peek_pos;
         let category = match self.bump() {
             Some('+') => Category::Plus,
             Some('-') => Category::Minus,
             Some('*') => Category::Star,
             Some('/') if self.bump_if('*') => {
-                self.skip_block_comment();
+                self.skip_block_comment(char_pos)?;
                 return Ok(ScanState::Skipped);
             }
             Some('/') => Category::Slash,
@@ -173,38 +348,102 @@ impl CSubScanner<'_> {
             Some('=') if self.bump_if('=') => Category::EqualEqual,
             Some('=') => Category::Equal,
             Some('!') if self.bump_if('=') => Category::ExclamaEqual,
+            Some('&') => Category::Ampersand,
+            Some('.') => Category::Dot,
             Some(';') => Category::Semicolon,
             Some(',') => Category::Comma,
+            Some(':') => Category::Colon,
+            Some('?') => Category::Question,
             Some('(') => Category::OpenParen,
             Some(')') => Category::CloseParen,
-            Some('[') => Category::OpenCurly,
-            Some(']') => Category::CloseCurly,
-            Some('{') => Category::OpenBracket,
-            Some('}') => Category::CloseBracket,
+            Some('[') => Category::OpenBracket,
+            Some(']') => Category::CloseBracket,
+            Some('{') => Category::OpenCurly,
+            Some('}') => Category::CloseCurly,
             Some('a'..='z' | 'A'..='Z') => {
                 self.bump_ident_body();
+                self.check_ident_length(char_pos)?;
                 Category::Ident
             }
             Some('0'..='9') => {
-                self.bump_number();
+                self.bump_number(char_pos)?;
                 Category::Number
             }
+            Some('\'') => {
+                let len = self.bump_quoted(char_pos, '\'')?;
+                if len != 1 {
+                    return Err(Diag::InvalidCharLiteral {
+                        span: self.span_from(char_pos),
+                        len,
+                    });
+                }
+                Category::CharLiteral
+            }
+            Some('"') => {
+                self.bump_quoted(char_pos, '"')?;
+                Category::StringLiteral
+            }
             Some(' ' | '\n' | '\t') => return Ok(ScanState::Skipped),
             None => return Ok(ScanState::ReachedEndOfInput),
-            _ => todo!("Not tested"),
+            Some(ch) if !ch.is_ascii() => {
+                return Err(match self.bump_run(|ch| !ch.is_ascii()) {
+                    1 => Diag::NonAsciiCharacter { pos: char_pos, ch },
+                    count => Diag::NonAsciiCharacterRun {
+                        span: self.span_from(char_pos),
+                        count,
+                    },
+                })
+            }
+            Some(ch) => {
+                return Err(match self.bump_run(is_unknown_ascii) {
+                    1 => Diag::UnknownCharacter { pos: char_pos, ch },
+                    count => Diag::UnknownCharacterRun {
+                        span: self.span_from(char_pos),
+                        count,
+                    },
+                })
+            }
         };
 
         Ok(ScanState::FoundCategory(category))
     }
 
-    fn skip_block_comment(&mut self) {
+    /// Bumps the characters following an invalid one for as long as they're
+    /// invalid the same way, so that a pasted run of them is reported once.
+    /// Returns the number of characters in the run.
+    fn bump_run(&mut self, is_in_run: impl Fn(char) -> bool) -> usize {
+        let mut count = 1;
+        while self.peek().is_some_and(&is_in_run) {
+            self.bump();
+            count += 1;
+        }
+
+        count
+    }
+
+    fn span_from(&self, start: BytePos) -> Span {
+        Span {
+            start,
+            end: self.char_stream.current_peek_pos,
+        }
+    }
+
+    fn skip_block_comment(
+        &mut self,
+        comment_start: BytePos,
+    ) -> Result<(), Diag> {
         loop {
             match self.bump() {
                 Some('*') if self.peek_is('/') => {
                     self.bump();
-                    break;
+                    return Ok(());
+                }
+                None => {
+                    return Err(Diag::UnterminatedBlockComment {
+                        start: comment_start,
+                        end: self.char_stream.current_peek_pos,
+                    })
                 }
-                None => todo!("todo_missing_termination_of_comment"),
                 _ => {}
             }
         }
@@ -216,25 +455,354 @@ impl CSubScanner<'_> {
         }
     }
 
-    fn bump_number(&mut self) {
+    fn check_ident_length(&self, ident_start: BytePos) -> Result<(), Diag> {
+        let span = Span {
+            start: ident_start,
+            end: self.char_stream.current_peek_pos,
+        };
+
+        // Identifiers are ASCII only, so bytes and characters are the same.
+        if span.end.to_usize() - span.start.to_usize() > MAX_IDENTIFIER_LENGTH {
+            Err(Diag::IdentifierTooLong { span })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn bump_number(&mut self, number_start: BytePos) -> Result<(), Diag> {
         while let Some('0'..='9') = self.peek() {
             self.bump();
         }
 
         if let Some('a'..='z' | 'A'..='Z') = self.peek() {
-            todo!("todo_invalid_digit_error");
+            let suffix_start = self.char_stream.current_peek_pos;
+            self.bump_ident_body();
+            let end = self.char_stream.current_peek_pos;
+
+            return Err(Diag::InvalidNumberLiteral {
+                number: Span {
+                    start: number_start,
+                    end,
+                },
+                suffix: Span {
+                    start: suffix_start,
+                    end,
+                },
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Bumps the rest of a literal opened with `quote` at `start`, returning
+    /// how many characters it holds, an escape sequence counting as one. A
+    /// literal can't span lines, so one without its closing quote ends with
+    /// its line.
+    fn bump_quoted(
+        &mut self,
+        start: BytePos,
+        quote: char,
+    ) -> Result<usize, Diag> {
+        let mut len = 0;
+        // The first error in the literal, reported once all of it is bumped.
+        let mut error = None;
+        loop {
+            let pos = self.char_stream.current_peek_pos;
+            match self.peek() {
+                None | Some('\n') => {
+                    return Err(if quote == '"' {
+                        Diag::UnterminatedString { start, end: pos }
+                    } else {
+                        Diag::UnterminatedCharLiteral { start, end: pos }
+                    });
+                }
+                Some(ch) if ch == quote => {
+                    self.bump();
+                    break;
+                }
+                Some('\\') => {
+                    self.bump();
+                    match self.peek() {
+                        // Reported as unterminated on the next iteration.
+                        None | Some('\n') => continue,
+                        Some(ch) => {
+                            self.bump();
+                            if unescape(ch).is_none() {
+                                error.get_or_insert(Diag::UnknownEscape {
+                                    span: self.span_from(pos),
+                                    ch,
+                                });
+                            }
+                        }
+                    }
+                }
+                Some(ch) => {
+                    self.bump();
+                    if !ch.is_ascii() {
+                        error
+                            .get_or_insert(Diag::NonAsciiCharacter { pos, ch });
+                    }
+                }
+            }
+            len += 1;
+        }
+
+        match error {
+            Some(diag) => Err(diag),
+            None => Ok(len),
+        }
+    }
+}
+
+/// The character an escape sequence stands for, given the character after
+/// its backslash, e.g. a newline for `\n`.
+fn unescape(ch: char) -> Option<char> {
+    match ch {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        '0' => Some('\0'),
+        '\\' | '\'' | '"' => Some(ch),
+        _ => None,
+    }
+}
+
+/// The text a character or string literal stands for, given the literal as
+/// it's written in the source, quotes included. The scanner already made
+/// sure its escape sequences are valid.
+pub(crate) fn literal_text(literal: &str) -> String {
+    let mut chars = literal[1..literal.len() - 1].chars();
+    let mut text = String::new();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => text.extend(chars.next().and_then(unescape)),
+            ch => text.push(ch),
+        }
+    }
+    text
+}
+
+/// Writes `text` as a literal quoted with `quote`, the inverse of
+/// `literal_text`.
+pub(crate) fn quote_literal(text: &str, quote: char) -> String {
+    let mut literal = String::from(quote);
+    for ch in text.chars() {
+        match ch {
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\0' => literal.push_str("\\0"),
+            '\\' => literal.push_str("\\\\"),
+            ch if ch == quote => {
+                literal.push('\\');
+                literal.push(ch);
+            }
+            ch => literal.push(ch),
+        }
+    }
+    literal.push(quote);
+    literal
+}
+
+/// Whether `ch` is an ASCII character that can't start a word (nor be skipped
+/// as whitespace). `!` can start `!=`, so it never continues a run.
+fn is_unknown_ascii(ch: char) -> bool {
+    ch.is_ascii()
+        && !ch.is_ascii_alphanumeric()
+        && !matches!(
+            ch,
+            '+' | '-'
+                | '*'
+                | '/'
+                | '<'
+                | '>'
+                | '='
+                | '!'
+                | '&'
+                | '.'
+                | ';'
+                | ','
+                | ':'
+                | '?'
+                | '\''
+                | '"'
+                | '('
+                | ')'
+                | '['
+                | ']'
+                | '{'
+                | '}'
+                | ' '
+                | '\n'
+                | '\t'
+        )
+}
+
+/// Scans the whole source file, written in C- with `extensions`, returning
+/// every word up to (but not including) the end of input, along with the
+/// diagnostics found on the way. Scanning carries on after an error, so all
+/// of them are reported at once.
+pub(crate) fn scan_words(
+    source_file: &SourceFile,
+    extensions: Extensions,
+) -> (Vec<Word>, DiagBag) {
+    scan_words_with_error_limit(source_file, extensions, None)
+}
+
+/// Like `scan_words`, but stops at the end of the input or once `error_limit`
+/// errors were found, if there's a limit. The words after an error aren't
+/// parsed, so there's no scanning them only to drop their errors.
+pub(crate) fn scan_words_with_error_limit(
+    source_file: &SourceFile,
+    extensions: Extensions,
+    error_limit: Option<usize>,
+) -> (Vec<Word>, DiagBag) {
+    let mut scanner = CSubScanner::with_source_file(source_file);
+    let mut words = Vec::new();
+    let mut diag_bag = DiagBag::with_error_limit(error_limit);
+
+    loop {
+        match scanner.scan_next_word() {
+            Ok(Word {
+                category: Category::Eof,
+                ..
+            }) => break,
+            Ok(word) => {
+                set_current_span(word.lexeme);
+                words.push(word);
+            }
+            Err(diag) => {
+                diag_bag.push(diag);
+                if diag_bag.reached_error_limit() {
+                    break;
+                }
+            }
+        }
+    }
+    unreserve_keywords(&mut words, extensions);
+
+    (words, diag_bag)
+}
+
+/// Turns the keywords of the extensions not in `extensions` back into
+/// identifiers, so that e.g. `int for;` declares a variable. Those where no
+/// identifier could be are kept, as in `for (;;)` or `do {`, for the parser
+/// to tell which extension they need.
+fn unreserve_keywords(words: &mut [Word], extensions: Extensions) {
+    let category =
+        |index: usize| words.get(index).map_or(Category::Eof, |w| w.category);
+    // How many parentheses are open before each word, and the first `;`, `{`
+    // or `}` from each word on, which ends the statement it's in.
+    let mut open_parens = Vec::with_capacity(words.len() + 1);
+    let mut depth = 0isize;
+    for word in words.iter() {
+        open_parens.push(depth);
+        match word.category {
+            Category::OpenParen => depth += 1,
+            Category::CloseParen => depth -= 1,
+            _ => {}
         }
     }
+    open_parens.push(depth);
+    let mut stmt_end = vec![words.len(); words.len() + 1];
+    for index in (0..words.len()).rev() {
+        stmt_end[index] = match words[index].category {
+            Category::Semicolon
+            | Category::OpenCurly
+            | Category::CloseCurly => index,
+            _ => stmt_end[index + 1],
+        };
+    }
+
+    let mut unreserved = Vec::new();
+    for (index, word) in words.iter().enumerate() {
+        let Category::Kw(keyword) = word.category else {
+            continue;
+        };
+        match keyword.extension() {
+            Some(extension) if !extensions.is_enabled(extension) => {}
+            _ => continue,
+        }
+        let prev = index.checked_sub(1).map_or(Category::Eof, category);
+        let next = category(index + 1);
+        let end = stmt_end[index + 1];
+        // What comes after a type, a name or a `.` is a name.
+        let is_name = matches!(
+            prev,
+            Category::Kw(
+                Keyword::Int | Keyword::Void | Keyword::Char | Keyword::Struct
+            ) | Category::Ident
+                | Category::Star
+                | Category::Dot
+        );
+        // None of these ever comes after an identifier.
+        let is_keyword = matches!(
+            next,
+            Category::Kw(_)
+                | Category::Ident
+                | Category::Number
+                | Category::CharLiteral
+                | Category::StringLiteral
+                | Category::OpenCurly
+        ) || match keyword {
+            // `for (a;`, rather than the call `for (a);`.
+            Keyword::For => {
+                next == Category::OpenParen
+                    && category(end) == Category::Semicolon
+                    && open_parens[end] > open_parens[index + 1]
+            }
+            // `switch (a) {`, rather than the call `switch (a);`.
+            Keyword::Switch => {
+                next == Category::OpenParen
+                    && category(end) == Category::OpenCurly
+                    && category(end - 1) == Category::CloseParen
+                    && open_parens[end] == open_parens[index + 1]
+            }
+            // `default:`, rather than the operand of `c ? default : 0`.
+            Keyword::Default => {
+                next == Category::Colon && prev != Category::Question
+            }
+            // `char *p` where a declaration or a parameter starts, rather
+            // than a product.
+            Keyword::Char => {
+                next == Category::Star
+                    && matches!(
+                        prev,
+                        Category::Eof
+                            | Category::Semicolon
+                            | Category::OpenCurly
+                            | Category::CloseCurly
+                            | Category::OpenParen
+                            | Category::Comma
+                    )
+            }
+            _ => false,
+        };
+        if is_name || !is_keyword {
+            unreserved.push(index);
+        }
+    }
+
+    for index in unreserved {
+        words[index].category = Category::Ident;
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{CSubScanner, Category, CharBumper, Keyword};
+    use super::{
+        literal_text, quote_literal, scan_words, scan_words_with_error_limit,
+        CSubScanner, Category, CharBumper, Keyword,
+    };
     use crate::{
+        errors::{Diag, Diagnostic, MAX_IDENTIFIER_LENGTH},
+        extensions::Extensions,
         scanner::Word,
-        source_map::{Pos, Span},
+        source_map::{BytePos, Pos, SourceFile, SourceMap, Span},
     };
 
+    fn source_file(source_text: &str) -> SourceFile {
+        SourceFile::new("test.cm".into(), source_text.into())
+    }
+
     #[test]
     fn peek_empty_input() {
         let mut bumper = CharBumper::new("".chars());
@@ -290,6 +858,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn bumps_only_if_peek_is_expected_char() {
         let mut bumper = CharBumper::new("abc".chars());
 
@@ -377,6 +946,16 @@ mod tests {
         assert_symbol("!=", Category::ExclamaEqual, 2);
     }
 
+    #[test]
+    fn scan_ampersand_token() {
+        assert_symbol("&", Category::Ampersand, 1);
+    }
+
+    #[test]
+    fn scan_dot_token() {
+        assert_symbol(".", Category::Dot, 1);
+    }
+
     #[test]
     fn scan_equal_token() {
         assert_symbol("=", Category::Equal, 1);
@@ -392,6 +971,16 @@ mod tests {
         assert_symbol(",", Category::Comma, 1);
     }
 
+    #[test]
+    fn scan_colon_token() {
+        assert_symbol(":", Category::Colon, 1);
+    }
+
+    #[test]
+    fn scan_question_token() {
+        assert_symbol("?", Category::Question, 1);
+    }
+
     #[test]
     fn scan_open_paren_token() {
         assert_symbol("(", Category::OpenParen, 1);
@@ -403,23 +992,23 @@ mod tests {
     }
 
     #[test]
-    fn scan_open_curly_token() {
-        assert_symbol("[", Category::OpenCurly, 1);
+    fn scan_open_bracket_token() {
+        assert_symbol("[", Category::OpenBracket, 1);
     }
 
     #[test]
-    fn scan_close_curly_token() {
-        assert_symbol("]", Category::CloseCurly, 1);
+    fn scan_close_bracket_token() {
+        assert_symbol("]", Category::CloseBracket, 1);
     }
 
     #[test]
-    fn scan_open_bracket_token() {
-        assert_symbol("{", Category::OpenBracket, 1);
+    fn scan_open_curly_token() {
+        assert_symbol("{", Category::OpenCurly, 1);
     }
 
     #[test]
-    fn scan_close_bracket_token() {
-        assert_symbol("}", Category::CloseBracket, 1);
+    fn scan_close_curly_token() {
+        assert_symbol("}", Category::CloseCurly, 1);
     }
 
     #[test]
@@ -427,6 +1016,126 @@ mod tests {
         assert_symbol("else", Category::Kw(Keyword::Else), 4);
     }
 
+    #[test]
+    fn scan_break_keyword_token() {
+        assert_symbol("break", Category::Kw(Keyword::Break), 5);
+    }
+
+    #[test]
+    fn scan_continue_keyword_token() {
+        assert_symbol("continue", Category::Kw(Keyword::Continue), 8);
+    }
+
+    #[test]
+    fn scan_switch_keywords() {
+        assert_symbol("switch", Category::Kw(Keyword::Switch), 6);
+        assert_symbol("case", Category::Kw(Keyword::Case), 4);
+        assert_symbol("default", Category::Kw(Keyword::Default), 7);
+    }
+
+    #[test]
+    fn scan_do_keyword_token() {
+        assert_symbol("do", Category::Kw(Keyword::Do), 2);
+    }
+
+    #[test]
+    fn scan_for_keyword_token() {
+        assert_symbol("for", Category::Kw(Keyword::For), 3);
+    }
+
+    #[test]
+    fn scan_char_keyword_token() {
+        assert_symbol("char", Category::Kw(Keyword::Char), 4);
+    }
+
+    #[test]
+    fn scan_const_keyword_token() {
+        assert_symbol("const", Category::Kw(Keyword::Const), 5);
+    }
+
+    #[test]
+    fn scan_struct_keyword_token() {
+        assert_symbol("struct", Category::Kw(Keyword::Struct), 6);
+    }
+
+    #[test]
+    fn scan_character_and_string_literals() {
+        assert_symbol("'a'", Category::CharLiteral, 3);
+        assert_symbol("'\\n'", Category::CharLiteral, 4);
+        assert_symbol("'\"'", Category::CharLiteral, 3);
+        assert_symbol("\"\"", Category::StringLiteral, 2);
+        assert_symbol("\"a 'b' \\\"c\\\"\\n\"", Category::StringLiteral, 15);
+    }
+
+    #[test]
+    fn literals_stand_for_their_text_without_escapes() {
+        assert_eq!(literal_text("'a'"), "a");
+        assert_eq!(literal_text("'\\''"), "'");
+        assert_eq!(literal_text("\"a\\tb\\\\\\0\\\"\""), "a\tb\\\0\"");
+
+        for text in ["", "a'b\"c", "\n\t\0\\"] {
+            for quote in ['\'', '"'] {
+                assert_eq!(literal_text(&quote_literal(text, quote)), text);
+            }
+        }
+        assert_eq!(quote_literal("it's \"x\"\n", '"'), "\"it's \\\"x\\\"\\n\"");
+    }
+
+    #[test]
+    fn unterminated_literals_end_with_their_line() {
+        let (words, diags) =
+            scan_words(&source_file("\"abc\nx 'y\\\n"), Extensions::none());
+
+        assert_eq!(words.len(), 1);
+        assert_eq!(
+            diags.iter().cloned().collect::<Vec<Diagnostic>>(),
+            vec![
+                Diag::UnterminatedString {
+                    start: BytePos(0),
+                    end: BytePos(4),
+                }
+                .into(),
+                Diag::UnterminatedCharLiteral {
+                    start: BytePos(7),
+                    end: BytePos(10),
+                }
+                .into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_literals_are_diagnosed() {
+        assert_scan_error(
+            "\"a\\qb\\w\"",
+            Diag::UnknownEscape {
+                span: Span::with_usizes(2, 4),
+                ch: 'q',
+            },
+        );
+        assert_scan_error(
+            "\"só\"",
+            Diag::NonAsciiCharacter {
+                pos: BytePos(2),
+                ch: 'ó',
+            },
+        );
+        assert_scan_error(
+            "''",
+            Diag::InvalidCharLiteral {
+                span: Span::with_usizes(0, 2),
+                len: 0,
+            },
+        );
+        assert_scan_error(
+            "'ab'",
+            Diag::InvalidCharLiteral {
+                span: Span::with_usizes(0, 4),
+                len: 2,
+            },
+        );
+    }
+
     #[test]
     fn scan_ident_head_token() {
         for letter in 'a'..='z' {
@@ -439,6 +1148,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn scan_ident_letters_and_digits_mixed_token() {
         let input_string = "H3ll0W0r1d";
         assert_symbol(&input_string, Category::Ident, input_string.len());
@@ -535,12 +1245,32 @@ mod tests {
         assert_eq!(slash_word.category, Category::Slash);
     }
 
-    // TODO(feroldi): Test that this actually returns a diagnostic.
+    fn assert_scan_error(input: &str, diag: Diag) {
+        let mut scanner = CSubScanner::with_chars(input.chars());
+
+        assert_eq!(scanner.scan_next_word(), Err(diag));
+    }
+
     #[test]
-    #[should_panic = "todo_missing_termination_of_comment"]
     fn missing_end_of_block_comment() {
-        let mut scanner = CSubScanner::with_chars("/*".chars());
-        let _ = scanner.scan_next_word();
+        assert_scan_error(
+            "/*",
+            Diag::UnterminatedBlockComment {
+                start: BytePos(0),
+                end: BytePos(2),
+            },
+        );
+    }
+
+    #[test]
+    fn missing_end_of_block_comment_after_some_text() {
+        assert_scan_error(
+            "/* abc\n*",
+            Diag::UnterminatedBlockComment {
+                start: BytePos(0),
+                end: BytePos(8),
+            },
+        );
     }
 
     #[test]
@@ -560,11 +1290,222 @@ mod tests {
         assert_symbol("00000000000000000000000", Category::Number, 23);
     }
 
-    // TODO(feroldi): Test that this actually returns a diagnostic.
     #[test]
-    #[should_panic = "todo_invalid_digit_error"]
     fn given_it_found_letter_when_scanning_number_then_diagnose_error() {
-        let mut scanner = CSubScanner::with_chars("0123a".chars());
-        let _ = scanner.scan_next_word();
+        assert_scan_error(
+            "0123a",
+            Diag::InvalidNumberLiteral {
+                number: Span::with_usizes(0, 5),
+                suffix: Span::with_usizes(4, 5),
+            },
+        );
+    }
+
+    #[test]
+    fn invalid_number_suffix_includes_following_letters_and_digits() {
+        assert_scan_error(
+            "12ab3c+",
+            Diag::InvalidNumberLiteral {
+                number: Span::with_usizes(0, 6),
+                suffix: Span::with_usizes(2, 6),
+            },
+        );
+    }
+
+    #[test]
+    fn non_ascii_characters_are_diagnosed() {
+        assert_scan_error(
+            "ç",
+            Diag::NonAsciiCharacter {
+                pos: BytePos(0),
+                ch: 'ç',
+            },
+        );
+    }
+
+    #[test]
+    fn non_ascii_characters_are_allowed_in_comments() {
+        let mut scanner = CSubScanner::with_chars("/* ação 😀 */".chars());
+        let next_word = scanner.scan_next_word().unwrap();
+        assert_eq!(next_word, Word::end_of_input());
+    }
+
+    #[test]
+    fn identifier_at_maximum_length_is_accepted() {
+        let ident = "a".repeat(MAX_IDENTIFIER_LENGTH);
+        assert_symbol(&ident, Category::Ident, MAX_IDENTIFIER_LENGTH);
+    }
+
+    #[test]
+    fn identifier_longer_than_maximum_length_is_diagnosed() {
+        let ident = "a".repeat(MAX_IDENTIFIER_LENGTH + 1);
+
+        assert_scan_error(
+            &ident,
+            Diag::IdentifierTooLong {
+                span: Span::with_usizes(0, MAX_IDENTIFIER_LENGTH + 1),
+            },
+        );
+    }
+
+    #[test]
+    fn unknown_character_is_diagnosed() {
+        let mut scanner = CSubScanner::with_chars("+@".chars());
+
+        let plus_word = scanner.scan_next_word().unwrap();
+        assert_eq!(plus_word.category, Category::Plus);
+
+        assert_eq!(
+            scanner.scan_next_word().unwrap_err(),
+            Diag::UnknownCharacter {
+                pos: BytePos(1),
+                ch: '@'
+            }
+        );
+    }
+
+    #[test]
+    fn runs_of_unknown_characters_are_diagnosed_once() {
+        assert_scan_error(
+            "@$#`x",
+            Diag::UnknownCharacterRun {
+                span: Span::with_usizes(0, 4),
+                count: 4,
+            },
+        );
+        assert_scan_error(
+            "@!=",
+            Diag::UnknownCharacter {
+                pos: BytePos(0),
+                ch: '@',
+            },
+        );
+    }
+
+    #[test]
+    fn runs_of_non_ascii_characters_are_diagnosed_once() {
+        assert_scan_error(
+            "“😀”",
+            Diag::NonAsciiCharacterRun {
+                span: Span::with_usizes(0, 10),
+                count: 3,
+            },
+        );
+    }
+
+    #[test]
+    fn scan_words_continues_after_unknown_characters() {
+        let (words, diags) =
+            scan_words(&source_file("a $ b ç"), Extensions::none());
+
+        let categories: Vec<&Category> =
+            words.iter().map(|word| &word.category).collect();
+        assert_eq!(categories, vec![&Category::Ident, &Category::Ident]);
+
+        assert_eq!(
+            diags.iter().cloned().collect::<Vec<Diagnostic>>(),
+            vec![
+                Diag::UnknownCharacter {
+                    pos: BytePos(2),
+                    ch: '$'
+                }
+                .into(),
+                Diag::NonAsciiCharacter {
+                    pos: BytePos(6),
+                    ch: 'ç'
+                }
+                .into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn scanning_stops_at_the_error_limit() {
+        let (words, diags) = scan_words_with_error_limit(
+            &source_file("a $ b $ c $ d"),
+            Extensions::none(),
+            Some(2),
+        );
+
+        assert_eq!(words.len(), 2);
+        assert_eq!(diags.error_count(), 2);
+        assert!(diags.reached_error_limit());
+    }
+
+    #[test]
+    fn extension_keywords_are_identifiers_where_a_keyword_cant_be() {
+        let file = source_file(
+            "int for; int case; int char; int const, struct;\n\
+             void f(char *p, const int c) {\n\
+             for (;;) do { switch (x) { default: break; } } while (x);\n\
+             for (x); switch (x); x = c ? default : 0;\n\
+             }",
+        );
+        let keywords = |extensions| {
+            let (words, _) = scan_words(&file, extensions);
+            words
+                .into_iter()
+                .filter_map(|word| {
+                    let text = file.span_to_snippet(word.lexeme);
+                    Keyword::from_str(text)?.extension()?;
+                    Some((text, matches!(word.category, Category::Kw(_))))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            keywords(Extensions::none()),
+            [
+                ("for", false),
+                ("case", false),
+                ("char", false),
+                ("const", false),
+                ("struct", false),
+                ("char", true),
+                ("const", true),
+                ("for", true),
+                ("do", true),
+                ("switch", true),
+                ("default", true),
+                ("break", false),
+                ("for", false),
+                ("switch", false),
+                ("default", false),
+            ]
+        );
+        assert!(keywords(Extensions::all()).iter().all(|&(_, kw)| kw));
+    }
+
+    #[test]
+    fn scan_words_stops_before_end_of_input() {
+        let (words, diags) =
+            scan_words(&source_file("int x;"), Extensions::none());
+
+        assert_eq!(words.len(), 3);
+        assert_eq!(words[0].category, Category::Kw(Keyword::Int));
+        assert!(!diags.has_errors());
+    }
+
+    #[test]
+    fn scan_words_of_a_file_not_at_the_start_of_the_source_map() {
+        let mut source_map = SourceMap::new();
+        source_map.add_file("a.cm".into(), "int".into());
+        let file = source_map.add_file("b.cm".into(), "if x".into());
+
+        let (words, _) = scan_words(&file, Extensions::none());
+
+        assert_eq!(
+            words,
+            vec![
+                Word {
+                    category: Category::Kw(Keyword::If),
+                    lexeme: Span::with_usizes(4, 6),
+                },
+                Word {
+                    category: Category::Ident,
+                    lexeme: Span::with_usizes(7, 8),
+                },
+            ]
+        );
     }
 }
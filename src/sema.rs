@@ -0,0 +1,2616 @@
+//! Semantic analysis: the checks on a parsed program that its grammar can't
+//! make, e.g. that a `break` is in a loop, or that the cases of a `switch`
+//! are different from each other, and the facts about the program the
+//! backend needs, e.g. what declaration each name refers to, or how the
+//! elements of an array or the fields of a struct are laid out.
+//!
+//! The program may be missing the parts the parser had to skip, so the
+//! checks only report what's wrong with the parts that are there.
+
+use crate::{
+    ast::{
+        BinOp, BlockId, Decl, ExprId, ExprKind, FunDecl, Ident, Nodes, Param,
+        Program, StmtId, StmtKind, StructDecl, SwitchCase, TypeSpec, UnOp,
+        VarDecl,
+    },
+    catalog::message,
+    errors::{Diag, DiagBag, Diagnostic},
+    hir, liveness,
+    scanner::Keyword,
+    source_map::Span,
+    types::{coerce, Mismatch, Type},
+    visit::{
+        walk_expr, walk_param, walk_stmt, walk_struct_decl, walk_var_decl,
+        Visitor,
+    },
+};
+use std::collections::{HashMap, HashSet};
+
+/// What sema found out about a program.
+pub(crate) struct Analysis {
+    /// What's wrong with the program.
+    pub(crate) diag_bag: DiagBag,
+    pub(crate) resolutions: Resolutions,
+    /// The program lowered for the phases after sema.
+    pub(crate) hir: hir::Program,
+}
+
+/// Checks `program`, returning the diagnostics of what's wrong with it, what
+/// its names refer to, and the program lowered into the HIR.
+///
+/// `builtins` are declared in a scope around the file's, so a program can
+/// call them without declaring them, and can declare its own functions with
+/// their names. The driver declares `Builtin::ALL`.
+pub(crate) fn check_program(
+    program: &Program,
+    builtins: &[Builtin],
+) -> Analysis {
+    let builtins = builtins
+        .iter()
+        .map(|&builtin| {
+            let declared = Declared {
+                binding: Binding::Builtin(builtin),
+                span: None,
+            };
+            (builtin.name().to_string(), declared)
+        })
+        .collect();
+    let mut checker = Checker {
+        decls: &program.decls,
+        diag_bag: DiagBag::new(),
+        loop_depth: 0,
+        switch_depth: 0,
+        structs: HashSet::new(),
+        struct_layouts: HashMap::new(),
+        scopes: vec![builtins, HashMap::new()],
+        fun: 0,
+        discarded: HashSet::new(),
+        assign_targets: HashSet::new(),
+        used: HashSet::new(),
+        unreachable: None,
+        resolutions: Resolutions {
+            bindings: vec![None; program.nodes.exprs.len()],
+        },
+    };
+    checker.visit_program(program);
+    let hir = hir::lower_program(
+        program,
+        &checker.resolutions,
+        &mut checker.diag_bag,
+    );
+    let fun_decls = program
+        .decls
+        .iter()
+        .filter_map(|decl| match decl {
+            Decl::Fun(fun_decl) => Some(fun_decl),
+            _ => None,
+        })
+        .collect();
+    let mut coercions = Coercions {
+        program: &hir,
+        fun_decls,
+        fun: 0,
+        diag_bag: &mut checker.diag_bag,
+    };
+    for (index, fun) in hir.funs.iter().enumerate() {
+        coercions.fun = index;
+        coercions.check_stmts(&fun.body);
+    }
+    // A lint that's off by default, and that's only worth it for a program
+    // that makes sense.
+    for fun in &hir.funs {
+        let mut dead_stores = liveness::dead_stores(fun);
+        dead_stores.sort_by_key(|store| store.span.start);
+        for store in dead_stores {
+            checker.diag_bag.defer(Diag::DeadStore {
+                name: fun.locals[store.local].name.clone(),
+                span: store.span,
+            });
+        }
+    }
+    Analysis {
+        diag_bag: checker.diag_bag,
+        resolutions: checker.resolutions,
+        hir,
+    }
+}
+
+/// Checks that `program` has a `main` function to start running from,
+/// declared as `void main(void)`, and that it's the last declaration, as the
+/// reference grammar asks. The program must be complete, or its `main` may
+/// be in the parts the parser skipped.
+pub(crate) fn check_entry_point(program: &Program) -> DiagBag {
+    let mut diag_bag = DiagBag::new();
+    let main =
+        program
+            .decls
+            .iter()
+            .enumerate()
+            .find_map(|(index, decl)| match decl {
+                Decl::Fun(fun_decl) if fun_decl.name.name == "main" => {
+                    Some((index, fun_decl))
+                }
+                _ => None,
+            });
+    let Some((index, main)) = main else {
+        diag_bag.push(Diag::MissingMain);
+        return diag_bag;
+    };
+
+    if main.return_is_const
+        || main.return_ty != TypeSpec::Void
+        || main.return_pointers != 0
+        || !main.params.is_empty()
+    {
+        diag_bag.push(Diag::MainSignature {
+            span: Span {
+                start: main.span.start,
+                end: main.params_span.end,
+            },
+        });
+    }
+    // A lint that's off by default, which would be noise next to errors
+    // anyway.
+    if let Some(after) = program.decls.get(index + 1) {
+        diag_bag.defer(Diag::MainNotLast {
+            span: main.name.span,
+            after: after.name().span,
+        });
+    }
+
+    diag_bag
+}
+
+/// What a name refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Binding {
+    /// The variable declared at file scope by the declaration at this index
+    /// of the program.
+    Global(usize),
+    /// The function declared by the declaration at this index of the
+    /// program.
+    Fun(usize),
+    /// The `index`th parameter of the function declared by the declaration
+    /// at `fun`.
+    Param {
+        fun: usize,
+        index: usize,
+    },
+    /// The `index`th variable declared at the start of `block`.
+    Local {
+        block: BlockId,
+        index: usize,
+    },
+    Builtin(Builtin),
+}
+
+/// The functions every program can call without declaring them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Builtin {
+    /// `int input(void)`, which reads an integer from the standard input.
+    Input,
+    /// `void output(int x)`, which writes `x` to the standard output.
+    Output,
+}
+
+impl Builtin {
+    pub(crate) const ALL: [Builtin; 2] = [Builtin::Input, Builtin::Output];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Builtin::Input => "input",
+            Builtin::Output => "output",
+        }
+    }
+
+    pub(crate) fn return_type(self) -> Type {
+        match self {
+            Builtin::Input => Type::Int,
+            Builtin::Output => Type::Void,
+        }
+    }
+
+    pub(crate) fn param_types(self) -> Vec<Type> {
+        match self {
+            Builtin::Input => Vec::new(),
+            Builtin::Output => vec![Type::Int],
+        }
+    }
+}
+
+/// What the names used in a program refer to, by the id of the expression
+/// they're used in: a variable, or a call of a function.
+#[derive(Debug, Default)]
+pub(crate) struct Resolutions {
+    bindings: Vec<Option<Binding>>,
+}
+
+impl Resolutions {
+    /// What the name `expr` uses refers to, or `None` if it's undeclared or
+    /// `expr` doesn't use a name.
+    pub(crate) fn get(&self, expr: ExprId) -> Option<Binding> {
+        self.bindings.get(expr.index()).copied().flatten()
+    }
+}
+
+/// A name declared in a scope.
+#[derive(Debug, Clone)]
+struct Declared {
+    binding: Binding,
+    /// The name in its declaration, or `None` for a builtin.
+    span: Option<Span>,
+}
+
+struct Checker<'a> {
+    /// The declarations of the program being checked.
+    decls: &'a [Decl],
+    diag_bag: DiagBag,
+    /// How many loops the statement being checked is in.
+    loop_depth: usize,
+    /// How many `switch`es the statement being checked is in.
+    switch_depth: usize,
+    /// The names of the structs defined so far.
+    structs: HashSet<String>,
+    /// The layouts of the structs defined so far, but for those with fields
+    /// of unknown types.
+    struct_layouts: HashMap<String, StructLayout>,
+    /// The names declared in each scope the node being checked is in,
+    /// innermost last. The outermost one has the builtins, and the one after
+    /// it the declarations at file scope.
+    scopes: Vec<HashMap<String, Declared>>,
+    /// The index in the program of the function being checked.
+    fun: usize,
+    /// The expressions whose values are thrown away, e.g. the one of an
+    /// expression statement.
+    discarded: HashSet<ExprId>,
+    /// The targets of the assignments, which are reported on their own
+    /// when they're functions.
+    assign_targets: HashSet<ExprId>,
+    /// What the names used so far refer to, so that the variables and
+    /// parameters never used are reported when their scope ends.
+    used: HashSet<Binding>,
+    /// The last code reported unreachable, so that the code in it isn't
+    /// reported again.
+    unreachable: Option<Span>,
+    resolutions: Resolutions,
+}
+
+impl<'a> Checker<'a> {
+    /// Runs `check` in a scope of its own.
+    fn scoped(&mut self, check: impl FnOnce(&mut Checker<'a>)) {
+        self.scopes.push(HashMap::new());
+        check(self);
+        let scope = self.scopes.pop().expect("no scope to leave");
+        self.check_used(scope);
+    }
+
+    /// Reports the variables and parameters of `scope`, which just ended,
+    /// that were never used. It's only worth it for a program that makes
+    /// sense, as a use in a statement that couldn't be parsed is no use.
+    fn check_used(&mut self, scope: HashMap<String, Declared>) {
+        let mut unused: Vec<_> = scope
+            .into_iter()
+            .filter_map(|(name, declared)| {
+                let is_param = match declared.binding {
+                    Binding::Param { .. } => true,
+                    Binding::Local { .. } => false,
+                    _ => return None,
+                };
+                let span = declared.span?;
+                (!self.used.contains(&declared.binding)).then_some(
+                    Diag::UnusedVariable {
+                        name,
+                        is_param,
+                        span,
+                    },
+                )
+            })
+            .collect();
+        unused.sort_by_key(|diag| diag.span().map(|span| span.start));
+        for diag in unused {
+            self.diag_bag.defer(diag);
+        }
+    }
+
+    /// Declares `name` in the innermost scope, unless it's already declared
+    /// there.
+    fn declare(&mut self, name: &Ident, binding: Binding) {
+        let (scope, enclosing) = self
+            .scopes
+            .split_last_mut()
+            .expect("no scope to declare in");
+        if let Some(&Declared {
+            span: Some(previous),
+            ..
+        }) = scope.get(&name.name)
+        {
+            self.diag_bag.push(Diag::Redeclaration {
+                name: name.name.clone(),
+                span: name.span,
+                previous,
+            });
+            return;
+        }
+        if let Some(previous) = enclosing
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.name)?.span)
+        {
+            // Deferred, as it's off by default, and the flags that turn it on
+            // only apply once it leaves sema.
+            self.diag_bag.defer(Diag::Shadowing {
+                name: name.name.clone(),
+                span: name.span,
+                previous,
+            });
+        }
+        let declared = Declared {
+            binding,
+            span: Some(name.span),
+        };
+        scope.insert(name.name.clone(), declared);
+    }
+
+    /// The innermost declaration of `name`.
+    fn lookup(&self, name: &str) -> Option<&Declared> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Records what the name `expr` uses refers to.
+    fn resolve(&mut self, expr: ExprId, name: &Ident) {
+        match self.lookup(&name.name).map(|declared| declared.binding) {
+            Some(binding) => {
+                self.resolutions.bindings[expr.index()] = Some(binding);
+                self.used.insert(binding);
+            }
+            None => {
+                let mut builder = Diagnostic::builder(Diag::UndeclaredName {
+                    name: name.name.clone(),
+                    span: name.span,
+                });
+                // E.g. `break;` without the extension is the name `break`.
+                if let Some(extension) =
+                    Keyword::from_str(&name.name).and_then(Keyword::extension)
+                {
+                    builder = builder.with_help(
+                        None,
+                        message(
+                            "E0038.keyword",
+                            &[
+                                ("name", &name.name),
+                                ("extension", &extension.name()),
+                            ],
+                        ),
+                    );
+                }
+                builder.emit(&mut self.diag_bag);
+            }
+        }
+    }
+
+    /// Checks the declarations and statements of `block`, declaring its
+    /// variables in the innermost scope.
+    fn check_block(&mut self, nodes: &Nodes, block: BlockId) {
+        for (index, var_decl) in nodes[block].decls.iter().enumerate() {
+            self.visit_var_decl(nodes, var_decl);
+            self.declare(&var_decl.name, Binding::Local { block, index });
+        }
+        self.check_stmts(nodes, &nodes[block].stmts);
+    }
+
+    /// Checks `stmts`, which run one after the other, and that none comes
+    /// after one that always jumps elsewhere.
+    fn check_stmts(&mut self, nodes: &Nodes, stmts: &[StmtId]) {
+        for (index, &stmt) in stmts.iter().enumerate() {
+            self.visit_stmt(nodes, stmt);
+            if let (true, Some(&first), Some(&last)) =
+                (jumps(nodes, stmt), stmts.get(index + 1), stmts.last())
+            {
+                let span = Span {
+                    start: nodes[first].span.start,
+                    end: nodes[last].span.end,
+                };
+                self.report_unreachable(span, nodes[stmt].span, None);
+            }
+        }
+    }
+
+    /// Reports the code at `span` as unreachable, because of the jump or
+    /// the condition at `cause`, unless it's in code already reported.
+    fn report_unreachable(
+        &mut self,
+        span: Span,
+        cause: Span,
+        condition: Option<bool>,
+    ) {
+        if let Some(reported) = self.unreachable {
+            if reported.start <= span.start && span.end <= reported.end {
+                return;
+            }
+        }
+        self.unreachable = Some(span);
+        self.diag_bag.push(Diag::UnreachableCode {
+            span,
+            cause,
+            condition,
+        });
+    }
+
+    /// The value of `expr` if it's a constant expression, reporting the
+    /// overflows and divisions by zero that keep it from being one.
+    fn eval_const(
+        &mut self,
+        nodes: &Nodes,
+        expr: ExprId,
+    ) -> Result<i64, ConstError> {
+        let value = eval_const(nodes, expr);
+        if let Some(diag) = value.err().and_then(ConstError::diag) {
+            self.diag_bag.push(diag);
+        }
+        value
+    }
+
+    /// Checks that the condition `cond` of a statement doesn't keep `stmt`,
+    /// which runs if it's `runs_if`, from ever running.
+    fn check_condition(
+        &mut self,
+        nodes: &Nodes,
+        cond: ExprId,
+        stmt: StmtId,
+        runs_if: bool,
+    ) {
+        if let Ok(value) = self.eval_const(nodes, cond) {
+            if (value != 0) != runs_if {
+                let span = nodes[stmt].span;
+                self.report_unreachable(
+                    span,
+                    nodes[cond].span,
+                    Some(value != 0),
+                );
+            }
+        }
+    }
+
+    /// Checks that the call `expr` of the function declared at `fun` passes
+    /// it as many arguments, `args`, as it has parameters.
+    fn check_arg_count(
+        &mut self,
+        nodes: &Nodes,
+        expr: ExprId,
+        fun: usize,
+        args: &[ExprId],
+    ) {
+        let Decl::Fun(fun_decl) = &self.decls[fun] else {
+            return;
+        };
+        if args.len() != fun_decl.params.len() {
+            self.diag_bag.push(Diag::ArgumentCountMismatch {
+                callee: fun_decl.name.name.clone(),
+                expected: fun_decl.params.len(),
+                found: args.len(),
+                span: nodes[expr].span,
+                params: Some(fun_decl.params_span),
+            });
+        }
+    }
+
+    /// Checks that the name `expr` uses, `name`, is of a variable, as a
+    /// function is only ever called.
+    fn check_var(&mut self, expr: ExprId, name: &Ident) {
+        let Some(&Declared { binding, span, .. }) = self.lookup(&name.name)
+        else {
+            return;
+        };
+        if matches!(binding, Binding::Fun(_) | Binding::Builtin(_))
+            && !self.assign_targets.contains(&expr)
+        {
+            self.diag_bag.push(Diag::NotAVariable {
+                name: name.name.clone(),
+                span: name.span,
+                decl: span,
+            });
+        }
+    }
+
+    /// Checks that a variable or parameter called `name` isn't `void`, nor
+    /// an array of `void`, as `void` has no values.
+    fn check_not_void(
+        &mut self,
+        name: &Ident,
+        ty: &TypeSpec,
+        pointers: u32,
+        is_array: bool,
+    ) {
+        if *ty == TypeSpec::Void && pointers == 0 {
+            self.diag_bag.push(Diag::VoidVariable {
+                name: name.name.clone(),
+                is_array,
+                span: name.span,
+            });
+        }
+    }
+
+    /// Checks that the `sizes` of the dimensions of the array `name` are
+    /// positive constants, and that its elements fit in 64 bits.
+    fn check_array_sizes(
+        &mut self,
+        nodes: &Nodes,
+        name: &Ident,
+        sizes: &[ExprId],
+    ) {
+        let mut lens = Vec::new();
+        for &size in sizes {
+            let name = name.name.clone();
+            let span = nodes[size].span;
+            if let Some(len) = array_len(nodes, size) {
+                lens.push(len);
+                continue;
+            }
+            match self.eval_const(nodes, size) {
+                Ok(value) => self.diag_bag.push(Diag::ArraySizeNotPositive {
+                    name,
+                    value,
+                    span,
+                }),
+                Err(ConstError::NotConstant) => self
+                    .diag_bag
+                    .push(Diag::NonConstantArraySize { name, span }),
+                Err(_) => {}
+            }
+        }
+        // Only worth checking once every size is known.
+        if let (Some(&first), Some(&last)) = (sizes.first(), sizes.last()) {
+            if lens.len() == sizes.len() && ArrayLayout::of(&lens).is_none() {
+                self.diag_bag.push(Diag::ArrayTooLarge {
+                    name: name.name.clone(),
+                    span: Span {
+                        start: nodes[first].span.start,
+                        end: nodes[last].span.end,
+                    },
+                });
+            }
+        }
+    }
+
+    /// Checks that the struct `ty` names, if any, is defined. A pointer to a
+    /// struct doesn't need its fields, so the struct may be defined later, as
+    /// in C, e.g. for the `next` field of a node of a list.
+    fn check_struct_defined(&mut self, ty: &TypeSpec, pointers: u32) {
+        if let TypeSpec::Struct(name) = ty {
+            if pointers == 0 && !self.structs.contains(&name.name) {
+                self.diag_bag.push(Diag::UnknownStruct {
+                    name: name.name.clone(),
+                    span: name.span,
+                });
+            }
+        }
+    }
+
+    /// Checks that the values of `cases` are constants, and that no two are
+    /// the same.
+    fn check_cases(&mut self, nodes: &Nodes, cases: &[SwitchCase]) {
+        let mut seen: HashMap<Option<i64>, Span> = HashMap::new();
+        for case in cases {
+            let value = match case.value {
+                None => None,
+                Some(expr) => match self.eval_const(nodes, expr) {
+                    Ok(value) => Some(value),
+                    Err(ConstError::NotConstant) => {
+                        let span = nodes[expr].span;
+                        self.diag_bag.push(Diag::NonConstantCase { span });
+                        continue;
+                    }
+                    Err(_) => continue,
+                },
+            };
+            if let Some(&first) = seen.get(&value) {
+                self.diag_bag.push(Diag::DuplicateCase {
+                    value,
+                    span: case.label_span,
+                    first,
+                });
+            } else {
+                seen.insert(value, case.label_span);
+            }
+        }
+    }
+}
+
+impl Visitor for Checker<'_> {
+    fn visit_program(&mut self, program: &Program) {
+        let nodes = &program.nodes;
+        for (index, decl) in program.decls.iter().enumerate() {
+            match decl {
+                Decl::Var(var_decl) => {
+                    self.visit_var_decl(nodes, var_decl);
+                    self.declare(&var_decl.name, Binding::Global(index));
+                }
+                Decl::Fun(fun_decl) => {
+                    // Declared before its body, so that it can call itself.
+                    self.declare(&fun_decl.name, Binding::Fun(index));
+                    self.fun = index;
+                    self.visit_fun_decl(nodes, fun_decl);
+                }
+                Decl::Struct(struct_decl) => {
+                    self.visit_struct_decl(nodes, struct_decl)
+                }
+            }
+        }
+    }
+
+    fn visit_struct_decl(&mut self, nodes: &Nodes, struct_decl: &StructDecl) {
+        let mut seen: HashMap<&str, Span> = HashMap::new();
+        for field in &struct_decl.fields {
+            let name = &field.name;
+            if let Some(&first) = seen.get(name.name.as_str()) {
+                self.diag_bag.push(Diag::DuplicateField {
+                    name: name.name.clone(),
+                    span: name.span,
+                    first,
+                });
+            } else {
+                seen.insert(&name.name, name.span);
+            }
+        }
+        // The fields are checked before the struct is defined, so that it
+        // can't have a field of its own type.
+        self.scoped(|checker| walk_struct_decl(checker, nodes, struct_decl));
+
+        let name = &struct_decl.name.name;
+        self.structs.insert(name.clone());
+        if let Some(layout) =
+            StructLayout::of(nodes, struct_decl, &self.struct_layouts)
+        {
+            self.struct_layouts.insert(name.clone(), layout);
+        }
+    }
+
+    fn visit_fun_decl(&mut self, nodes: &Nodes, fun_decl: &FunDecl) {
+        self.check_struct_defined(
+            &fun_decl.return_ty,
+            fun_decl.return_pointers,
+        );
+        // The parameters are in the same scope as the variables declared at
+        // the start of the body, so neither can have the name of the other.
+        self.scoped(|checker| {
+            for (index, param) in fun_decl.params.iter().enumerate() {
+                checker.visit_param(nodes, param);
+                let name = &param.name;
+                let first = fun_decl.params[..index]
+                    .iter()
+                    .find(|other| other.name.name == name.name);
+                if let Some(first) = first {
+                    checker.diag_bag.push(Diag::DuplicateParam {
+                        name: name.name.clone(),
+                        span: name.span,
+                        first: first.name.span,
+                    });
+                    continue;
+                }
+                checker.declare(
+                    &param.name,
+                    Binding::Param {
+                        fun: checker.fun,
+                        index,
+                    },
+                );
+            }
+            checker.check_block(nodes, fun_decl.body);
+        });
+    }
+
+    fn visit_param(&mut self, nodes: &Nodes, param: &Param) {
+        self.check_struct_defined(&param.ty, param.pointers);
+        self.check_not_void(
+            &param.name,
+            &param.ty,
+            param.pointers,
+            param.is_array,
+        );
+        self.check_array_sizes(nodes, &param.name, &param.sizes);
+        walk_param(self, nodes, param);
+    }
+
+    fn visit_block(&mut self, nodes: &Nodes, block: BlockId) {
+        self.scoped(|checker| checker.check_block(nodes, block));
+    }
+
+    fn visit_expr(&mut self, nodes: &Nodes, expr: ExprId) {
+        match nodes[expr].kind {
+            ExprKind::Assign { target, .. } => {
+                self.assign_targets.insert(target);
+            }
+            ExprKind::Var(ref name) => {
+                self.resolve(expr, name);
+                self.check_var(expr, name);
+            }
+            ExprKind::Call {
+                callee: ref name,
+                ref args,
+            } => {
+                self.resolve(expr, name);
+                let returns_void = match self.resolutions.get(expr) {
+                    Some(Binding::Fun(fun)) => {
+                        self.check_arg_count(nodes, expr, fun, args);
+                        matches!(
+                            &self.decls[fun],
+                            Decl::Fun(FunDecl {
+                                return_ty: TypeSpec::Void,
+                                return_pointers: 0,
+                                ..
+                            })
+                        )
+                    }
+                    Some(Binding::Builtin(builtin)) => {
+                        let expected = builtin.param_types().len();
+                        if args.len() != expected {
+                            self.diag_bag.push(Diag::ArgumentCountMismatch {
+                                callee: name.name.clone(),
+                                expected,
+                                found: args.len(),
+                                span: nodes[expr].span,
+                                params: None,
+                            });
+                        }
+                        builtin.return_type() == Type::Void
+                    }
+                    Some(
+                        Binding::Global(_)
+                        | Binding::Param { .. }
+                        | Binding::Local { .. },
+                    ) => {
+                        let decl = self
+                            .lookup(&name.name)
+                            .and_then(|declared| declared.span);
+                        self.diag_bag.push(Diag::NotAFunction {
+                            name: name.name.clone(),
+                            span: name.span,
+                            decl,
+                        });
+                        false
+                    }
+                    None => false,
+                };
+                if returns_void && !self.discarded.contains(&expr) {
+                    self.diag_bag.push(Diag::VoidValueUsed {
+                        callee: name.name.clone(),
+                        span: nodes[expr].span,
+                    });
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, nodes, expr);
+    }
+
+    fn visit_var_decl(&mut self, nodes: &Nodes, var_decl: &VarDecl) {
+        self.check_struct_defined(&var_decl.ty, var_decl.pointers);
+        self.check_not_void(
+            &var_decl.name,
+            &var_decl.ty,
+            var_decl.pointers,
+            !var_decl.sizes.is_empty(),
+        );
+        // Only variables at file scope have initializers.
+        if let Some(init) = var_decl.init {
+            if let Err(ConstError::NotConstant) = self.eval_const(nodes, init) {
+                self.diag_bag.push(Diag::NonConstantInitializer {
+                    name: var_decl.name.name.clone(),
+                    span: nodes[init].span,
+                });
+            }
+        }
+        self.check_array_sizes(nodes, &var_decl.name, &var_decl.sizes);
+        walk_var_decl(self, nodes, var_decl);
+    }
+
+    fn visit_stmt(&mut self, nodes: &Nodes, stmt: StmtId) {
+        match nodes[stmt].kind {
+            StmtKind::Expr(expr) => {
+                self.discarded.insert(expr);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                self.discarded.extend(init.into_iter().chain(step));
+                if let Some(cond) = cond {
+                    self.check_condition(nodes, cond, body, true);
+                }
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.check_condition(nodes, cond, then_branch, true);
+                if let Some(else_branch) = else_branch {
+                    self.check_condition(nodes, cond, else_branch, false);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.check_condition(nodes, cond, body, true)
+            }
+            _ => {}
+        }
+
+        let keyword = match nodes[stmt].kind {
+            StmtKind::While { .. }
+            | StmtKind::DoWhile { .. }
+            | StmtKind::For { .. } => {
+                self.loop_depth += 1;
+                walk_stmt(self, nodes, stmt);
+                self.loop_depth -= 1;
+                return;
+            }
+            StmtKind::Switch { cond, ref cases } => {
+                self.check_cases(nodes, cases);
+                self.visit_expr(nodes, cond);
+                self.switch_depth += 1;
+                for case in cases {
+                    if let Some(value) = case.value {
+                        self.visit_expr(nodes, value);
+                    }
+                    self.check_stmts(nodes, &case.stmts);
+                }
+                self.switch_depth -= 1;
+                return;
+            }
+            StmtKind::Break if self.switch_depth == 0 => Keyword::Break,
+            StmtKind::Continue => Keyword::Continue,
+            _ => return walk_stmt(self, nodes, stmt),
+        };
+
+        if self.loop_depth == 0 {
+            self.diag_bag.push(Diag::LoopControlOutsideLoop {
+                keyword,
+                span: nodes[stmt].span,
+            });
+        }
+    }
+}
+
+/// Checks that the values a program assigns, passes as arguments and returns
+/// can become the types they're used as (see `coerce`), and that what it
+/// assigns to can be assigned to. It works on the HIR, where every expression
+/// has its type.
+struct Coercions<'a> {
+    program: &'a hir::Program,
+    /// The declaration of each function, by its index in `Program::funs`.
+    fun_decls: Vec<&'a FunDecl>,
+    /// The index of the function being checked.
+    fun: usize,
+    diag_bag: &'a mut DiagBag,
+}
+
+impl Coercions<'_> {
+    /// Checks that `value` can become a value of type `expected`, because of
+    /// what `because` is, if it's something in particular.
+    fn check(
+        &mut self,
+        value: &hir::Expr,
+        expected: &Type,
+        because: Option<Span>,
+    ) {
+        let discards_const = match coerce(&value.ty, expected) {
+            Ok(_) => return,
+            // Reported as a use of the value of a call of a `void` function.
+            Err(Mismatch::Void)
+                if matches!(value.kind, hir::ExprKind::Call { .. }) =>
+            {
+                return
+            }
+            // `0` is also the null pointer, as in C.
+            Err(Mismatch::Incompatible)
+                if value.kind == hir::ExprKind::Number(0)
+                    && expected.unqualified().is_pointer() =>
+            {
+                return
+            }
+            Err(mismatch) => mismatch == Mismatch::DiscardsConst,
+        };
+        self.diag_bag.push(Diag::TypeMismatch {
+            expected: expected.to_string(),
+            found: value.ty.decay().to_string(),
+            discards_const,
+            span: value.span,
+            because,
+        });
+    }
+
+    fn check_stmts(&mut self, stmts: &[hir::Stmt]) {
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &hir::Stmt) {
+        match &stmt.kind {
+            hir::StmtKind::Expr(expr) => self.check_expr(expr),
+            hir::StmtKind::Empty
+            | hir::StmtKind::Break
+            | hir::StmtKind::Continue => {}
+            hir::StmtKind::Block(stmts) => self.check_stmts(stmts),
+            hir::StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.check_condition(cond);
+                self.check_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.check_stmt(else_branch);
+                }
+            }
+            hir::StmtKind::While { cond, body }
+            | hir::StmtKind::DoWhile { body, cond } => {
+                self.check_condition(cond);
+                self.check_stmt(body);
+            }
+            hir::StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                for expr in [init, step].into_iter().flatten() {
+                    self.check_expr(expr);
+                }
+                if let Some(cond) = cond {
+                    self.check_condition(cond);
+                }
+                self.check_stmt(body);
+            }
+            hir::StmtKind::Switch { cond, cases } => {
+                self.check_expr(cond);
+                self.check(cond, &Type::Int, None);
+                for case in cases {
+                    self.check_stmts(&case.stmts);
+                }
+            }
+            hir::StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.check_expr(value);
+                    let fun = &self.program.funs[self.fun];
+                    self.check(value, &fun.return_ty, Some(fun.span));
+                }
+            }
+        }
+    }
+
+    fn check_expr(&mut self, expr: &hir::Expr) {
+        match &expr.kind {
+            hir::ExprKind::Number(_)
+            | hir::ExprKind::Char(_)
+            | hir::ExprKind::Str(_)
+            | hir::ExprKind::Var(_)
+            | hir::ExprKind::Error => {}
+            hir::ExprKind::Index { array, index } => {
+                self.check_expr(array);
+                self.check_expr(index);
+            }
+            hir::ExprKind::Member { base, .. } => self.check_expr(base),
+            hir::ExprKind::Call { callee, args } => {
+                for arg in args {
+                    self.check_expr(arg);
+                }
+                self.check_args(*callee, args);
+            }
+            hir::ExprKind::Assign { target, value } => {
+                self.check_expr(target);
+                self.check_expr(value);
+                // Assigning to an array as a whole is reported as such.
+                if self.check_assign_target(target) {
+                    self.check(value, &target.ty, None);
+                }
+            }
+            hir::ExprKind::Unary { op, operand } => {
+                self.check_expr(operand);
+                if *op == UnOp::Neg {
+                    self.check(operand, &Type::Int, None);
+                }
+            }
+            hir::ExprKind::Binary { op, lhs, rhs } => {
+                self.check_expr(lhs);
+                self.check_expr(rhs);
+                self.check_operands(*op, lhs, rhs);
+            }
+            hir::ExprKind::Conditional {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                self.check_condition(cond);
+                self.check_expr(then_expr);
+                self.check_expr(else_expr);
+            }
+        }
+    }
+
+    /// Checks that `cond`, the condition of an `if`, a loop or a `?:`, is a
+    /// number or a pointer, which is true unless it's `0`, the null pointer.
+    fn check_condition(&mut self, cond: &hir::Expr) {
+        self.check_expr(cond);
+        if !cond.ty.unqualified().is_pointer() {
+            self.check(cond, &Type::Int, None);
+        }
+    }
+
+    /// Checks that the operands of `lhs op rhs` are numbers, or pointers where
+    /// C has arithmetic on them: a pointer plus or minus a number, and the
+    /// difference or comparison of two pointers to the same type. An array
+    /// is the address of its first element in arithmetic, as in C, but it's
+    /// only compared with a pointer, as two arrays are never at the same
+    /// address, nor is one ever at a lower address than another.
+    fn check_operands(&mut self, op: BinOp, lhs: &hir::Expr, rhs: &hir::Expr) {
+        let (lhs_is_pointer, rhs_is_pointer) =
+            (lhs.ty.decay().is_pointer(), rhs.ty.decay().is_pointer());
+        match op {
+            BinOp::Add | BinOp::Sub if lhs_is_pointer && !rhs_is_pointer => {
+                self.check(rhs, &Type::Int, None)
+            }
+            BinOp::Add if rhs_is_pointer && !lhs_is_pointer => {
+                self.check(lhs, &Type::Int, None)
+            }
+            BinOp::Sub if lhs_is_pointer => self.check_pointers(lhs, rhs),
+            BinOp::Less
+            | BinOp::LessEqual
+            | BinOp::Greater
+            | BinOp::GreaterEqual
+            | BinOp::Equal
+            | BinOp::NotEqual
+                if lhs.ty.unqualified().is_pointer()
+                    || rhs.ty.unqualified().is_pointer() =>
+            {
+                self.check_pointers(lhs, rhs)
+            }
+            BinOp::Add
+            | BinOp::Sub
+            | BinOp::Mul
+            | BinOp::Div
+            | BinOp::Less
+            | BinOp::LessEqual
+            | BinOp::Greater
+            | BinOp::GreaterEqual
+            | BinOp::Equal
+            | BinOp::NotEqual => {
+                self.check(lhs, &Type::Int, None);
+                self.check(rhs, &Type::Int, None);
+            }
+        }
+    }
+
+    /// Checks that `lhs` and `rhs`, one of which is a pointer, are pointers
+    /// that can be subtracted or compared: to the same type, one of them a
+    /// `void *`, or one of them `0`, the null pointer.
+    fn check_pointers(&mut self, lhs: &hir::Expr, rhs: &hir::Expr) {
+        if coerce(&lhs.ty, &rhs.ty).is_ok() || coerce(&rhs.ty, &lhs.ty).is_ok()
+        {
+            return;
+        }
+        let (pointer, other) = if lhs.ty.unqualified().is_pointer() {
+            (lhs, rhs)
+        } else {
+            (rhs, lhs)
+        };
+        self.check(other, pointer.ty.unqualified(), None);
+    }
+
+    /// Whether `expr` is an array, which an array parameter is too, or a
+    /// single value, if it's known. A pointer may point to the first element
+    /// of an array, so it may be either.
+    fn is_array(&self, expr: &hir::Expr) -> Option<bool> {
+        match expr.kind {
+            hir::ExprKind::Number(_) | hir::ExprKind::Char(_) => {
+                return Some(false)
+            }
+            hir::ExprKind::Var(hir::Def::Local(id))
+                if id < self.program.funs[self.fun].params
+                    && self.fun_decls[self.fun].params[id].is_array =>
+            {
+                return Some(true)
+            }
+            hir::ExprKind::Var(_)
+            | hir::ExprKind::Index { .. }
+            | hir::ExprKind::Member { .. } => {}
+            _ => return None,
+        }
+        match expr.ty.unqualified() {
+            Type::Array { .. } => Some(true),
+            Type::Pointer(_) | Type::Error => None,
+            _ => Some(false),
+        }
+    }
+
+    /// Checks that the target of an assignment, `target`, is a variable, or
+    /// an element or a field of one, or of what a pointer points to, that
+    /// isn't an array, as an array's elements are assigned one at a time, nor
+    /// `const`. The parser already made sure it's made of names, indices,
+    /// fields and `*`s, but not what the names are. Returns whether it can
+    /// be assigned to, so that the value assigned is checked against its
+    /// type.
+    fn check_assign_target(&mut self, target: &hir::Expr) -> bool {
+        let (mut var, mut is_const) = (target, false);
+        loop {
+            is_const |= matches!(var.ty, Type::Const(_));
+            match &var.kind {
+                hir::ExprKind::Index { array: of, .. }
+                | hir::ExprKind::Member { base: of, .. } => var = of,
+                _ => break,
+            }
+        }
+        let program = self.program;
+        let (name, decl) = match var.kind {
+            hir::ExprKind::Var(hir::Def::Global(id)) => {
+                let global = &program.globals[id];
+                (global.name.as_str(), Some(global.span))
+            }
+            hir::ExprKind::Var(hir::Def::Local(id)) => {
+                let local = &program.funs[self.fun].locals[id];
+                (local.name.as_str(), Some(local.span))
+            }
+            hir::ExprKind::Var(hir::Def::Fun(id)) => {
+                let fun = &program.funs[id];
+                (fun.name.as_str(), Some(fun.span))
+            }
+            hir::ExprKind::Var(hir::Def::Builtin(builtin)) => {
+                (builtin.name(), None)
+            }
+            // What a pointer points to, which isn't declared anywhere.
+            _ => ("", None),
+        };
+
+        let is_fun = matches!(
+            var.kind,
+            hir::ExprKind::Var(hir::Def::Fun(_) | hir::Def::Builtin(_))
+        );
+        let is_array = self.is_array(target) == Some(true);
+        if (is_fun && std::ptr::eq(var, target)) || is_array {
+            self.diag_bag.push(Diag::UnassignableTarget {
+                name: name.to_string(),
+                is_array,
+                span: target.span,
+                decl,
+            });
+            return false;
+        } else if let (true, Some(decl)) = (is_const, decl) {
+            self.diag_bag.push(Diag::AssignToConst {
+                name: name.to_string(),
+                span: target.span,
+                decl,
+            });
+        }
+        true
+    }
+
+    /// Checks the arguments `args` of a call of `callee` against the types of
+    /// its parameters: that they're arrays where its parameters are, and only
+    /// there, as an array is passed by reference, as the address of its
+    /// first element, and that their values can become the types of the
+    /// others. Their number is checked along with the names, by `Checker`.
+    fn check_args(&mut self, callee: hir::Def, args: &[hir::Expr]) {
+        match callee {
+            hir::Def::Fun(id) => {
+                let (program, fun_decl) = (self.program, self.fun_decls[id]);
+                let fun = &program.funs[id];
+                let params = fun.locals[..fun.params].iter();
+                for ((param, param_decl), arg) in
+                    params.zip(&fun_decl.params).zip(args)
+                {
+                    // A pointer takes an array as the address of its first
+                    // element.
+                    let takes_either =
+                        !param_decl.is_array && param_decl.pointers > 0;
+                    match self.is_array(arg) {
+                        Some(is_array)
+                            if !takes_either
+                                && is_array != param_decl.is_array =>
+                        {
+                            self.diag_bag.push(Diag::ArrayArgumentMismatch {
+                                callee: fun.name.clone(),
+                                param_is_array: param_decl.is_array,
+                                span: arg.span,
+                                param: param_decl.span,
+                            });
+                        }
+                        _ => self.check(arg, &param.ty, Some(param.span)),
+                    }
+                }
+            }
+            hir::Def::Builtin(builtin) => {
+                for (ty, arg) in builtin.param_types().iter().zip(args) {
+                    self.check(arg, ty, None);
+                }
+            }
+            hir::Def::Global(_) | hir::Def::Local(_) => {}
+        }
+    }
+}
+
+/// How the elements of an array are laid out in memory: row by row, so that
+/// the elements of `m[i]` are next to each other in `int m[3][4];`, and
+/// `m[i][j]` is the element `i * 4 + j`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ArrayLayout {
+    /// How many elements apart the consecutive indices of each dimension
+    /// are, outermost first. The last one is always 1.
+    pub(crate) strides: Vec<u64>,
+    /// The number of elements of the whole array.
+    pub(crate) len: u64,
+}
+
+impl ArrayLayout {
+    /// The layout of an array with `sizes` elements along each of its
+    /// dimensions, or `None` if it has more elements than fit in a `u64`.
+    pub(crate) fn of(sizes: &[u64]) -> Option<ArrayLayout> {
+        let mut strides = vec![0; sizes.len()];
+        let mut len: u64 = 1;
+        for (stride, size) in strides.iter_mut().zip(sizes).rev() {
+            *stride = len;
+            len = len.checked_mul(*size)?;
+        }
+        Some(ArrayLayout { strides, len })
+    }
+}
+
+/// How much memory a value takes, and what its address must be a multiple
+/// of, as in C on a 64-bit target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Layout {
+    /// In bytes.
+    pub(crate) size: u64,
+    pub(crate) align: u64,
+}
+
+impl Layout {
+    /// The layout of the values of type `ty`: a `char` takes a byte, an
+    /// `int` 4 and a pointer 8, an array its elements one after the other,
+    /// and a struct what its layout in `structs` says. There's none for
+    /// `void`, for structs not in `structs`, and for values too large to fit
+    /// in memory.
+    pub(crate) fn of(
+        ty: &Type,
+        structs: &HashMap<String, StructLayout>,
+    ) -> Option<Layout> {
+        let (size, align) = match ty {
+            Type::Char => (1, 1),
+            Type::Int => (4, 4),
+            Type::Pointer(_) => (8, 8),
+            Type::Void | Type::Error => return None,
+            Type::Struct(name) => return structs.get(name).map(|s| s.layout),
+            Type::Const(ty) => return Layout::of(ty, structs),
+            Type::Array { elem, len } => {
+                let elem = Layout::of(elem, structs)?;
+                (elem.size.checked_mul(*len)?, elem.align)
+            }
+        };
+        Some(Layout { size, align })
+    }
+}
+
+/// Where the fields of a struct are, as in C: one after the other, in the
+/// order they're declared, each at the first offset that's a multiple of
+/// its alignment. The struct is as aligned as its most aligned field, and
+/// takes a multiple of that, so that the fields of each element of an array
+/// of them are aligned too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StructLayout {
+    /// The name of each field and its offset from the start of the struct,
+    /// in bytes, in the order they're declared.
+    pub(crate) fields: Vec<(String, u64)>,
+    pub(crate) layout: Layout,
+}
+
+impl StructLayout {
+    /// The layout of the struct `struct_decl` defines, whose fields may be
+    /// of the structs in `structs`, or `None` if a field has no layout.
+    pub(crate) fn of(
+        nodes: &Nodes,
+        struct_decl: &StructDecl,
+        structs: &HashMap<String, StructLayout>,
+    ) -> Option<StructLayout> {
+        let fields = struct_decl.fields.iter().map(|field| {
+            (field.name.name.clone(), Type::of_var_decl(nodes, field))
+        });
+        StructLayout::of_fields(fields, structs)
+    }
+
+    /// The layout of a struct with `fields`, each a name and a type, in the
+    /// order they're declared.
+    pub(crate) fn of_fields(
+        fields: impl IntoIterator<Item = (String, Type)>,
+        structs: &HashMap<String, StructLayout>,
+    ) -> Option<StructLayout> {
+        let mut offsets = Vec::new();
+        let mut size: u64 = 0;
+        let mut align = 1;
+        for (name, ty) in fields {
+            let layout = Layout::of(&ty, structs)?;
+            let offset = size.checked_next_multiple_of(layout.align)?;
+            offsets.push((name, offset));
+            size = offset.checked_add(layout.size)?;
+            align = align.max(layout.align);
+        }
+
+        Some(StructLayout {
+            fields: offsets,
+            layout: Layout {
+                size: size.checked_next_multiple_of(align)?,
+                align,
+            },
+        })
+    }
+
+    /// The offset of the field `name`, the first one if there are many.
+    #[cfg(test)]
+    pub(crate) fn offset_of(&self, name: &str) -> Option<u64> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|&(_, offset)| offset)
+    }
+}
+
+/// Whether `stmt` always jumps elsewhere instead of going on to the statement
+/// after it, by a `return`, `break` or `continue`.
+fn jumps(nodes: &Nodes, stmt: StmtId) -> bool {
+    match nodes[stmt].kind {
+        StmtKind::Return(_) | StmtKind::Break | StmtKind::Continue => true,
+        StmtKind::Block(block) => {
+            nodes[block].stmts.iter().any(|&stmt| jumps(nodes, stmt))
+        }
+        StmtKind::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => jumps(nodes, then_branch) && jumps(nodes, else_branch),
+        _ => false,
+    }
+}
+
+/// Why an expression has no constant value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConstError {
+    /// It's not made of numbers and operators only.
+    NotConstant,
+    /// The operation at `span` in it has a result that doesn't fit in an
+    /// `int`.
+    Overflow { span: Span },
+    /// The division at `span` in it has a divisor, `divisor`, of zero.
+    DivisionByZero { span: Span, divisor: Span },
+}
+
+impl ConstError {
+    /// The diagnostic of the error, or `None` if it's just not a constant,
+    /// which is only wrong where a constant is needed.
+    fn diag(self) -> Option<Diag> {
+        match self {
+            ConstError::NotConstant => None,
+            ConstError::Overflow { span } => Some(Diag::ConstOverflow { span }),
+            ConstError::DivisionByZero { span, divisor } => {
+                Some(Diag::ConstDivisionByZero { span, divisor })
+            }
+        }
+    }
+}
+
+/// The value of `expr` if it's a constant expression, i.e. made of numbers
+/// and operators only. Comparisons are 1 when true and 0 when false. The
+/// value and the result of every operation in it must fit in an `int`, so
+/// that it's the value the program would compute when running.
+///
+/// Only the branch of a conditional that's taken is evaluated, so an error
+/// in the other one doesn't count. Of the errors in both operands of an
+/// operator, the one of the left operand counts, and a non-constant operand
+/// counts only if there's no other error.
+pub(crate) fn eval_const(
+    nodes: &Nodes,
+    expr: ExprId,
+) -> Result<i64, ConstError> {
+    let span = nodes[expr].span;
+    let fits = |value: i64| {
+        if i32::try_from(value).is_ok() {
+            Ok(value)
+        } else {
+            Err(ConstError::Overflow { span })
+        }
+    };
+    match nodes[expr].kind {
+        ExprKind::Number(value) => {
+            fits(i64::try_from(value).unwrap_or(i64::MAX))
+        }
+        ExprKind::Char(value) => Ok(value as i64),
+        ExprKind::Unary {
+            op: UnOp::Neg,
+            operand,
+        } => match nodes[operand].kind {
+            // So that the smallest `int`, `-2147483648`, is a constant, even
+            // though `2147483648` isn't.
+            ExprKind::Number(value) => {
+                fits(i64::try_from(value).map_or(i64::MIN, |value| -value))
+            }
+            _ => fits(-eval_const(nodes, operand)?),
+        },
+        ExprKind::Binary { op, lhs, rhs } => {
+            let divisor = nodes[rhs].span;
+            let (lhs, rhs) =
+                match (eval_const(nodes, lhs), eval_const(nodes, rhs)) {
+                    (Ok(lhs), Ok(rhs)) => (lhs, rhs),
+                    (Err(ConstError::NotConstant), Err(err))
+                    | (Err(err), _)
+                    | (_, Err(err)) => return Err(err),
+                };
+            match op {
+                BinOp::Add => fits(lhs + rhs),
+                BinOp::Sub => fits(lhs - rhs),
+                BinOp::Mul => fits(lhs * rhs),
+                BinOp::Div if rhs == 0 => {
+                    Err(ConstError::DivisionByZero { span, divisor })
+                }
+                BinOp::Div => fits(lhs / rhs),
+                BinOp::Less => Ok((lhs < rhs) as i64),
+                BinOp::LessEqual => Ok((lhs <= rhs) as i64),
+                BinOp::Greater => Ok((lhs > rhs) as i64),
+                BinOp::GreaterEqual => Ok((lhs >= rhs) as i64),
+                BinOp::Equal => Ok((lhs == rhs) as i64),
+                BinOp::NotEqual => Ok((lhs != rhs) as i64),
+            }
+        }
+        ExprKind::Conditional {
+            cond,
+            then_expr,
+            else_expr,
+        } => {
+            if eval_const(nodes, cond)? != 0 {
+                eval_const(nodes, then_expr)
+            } else {
+                eval_const(nodes, else_expr)
+            }
+        }
+        _ => Err(ConstError::NotConstant),
+    }
+}
+
+/// The value of `expr` if it's a constant expression without errors (see
+/// `eval_const`).
+pub(crate) fn const_value(nodes: &Nodes, expr: ExprId) -> Option<i64> {
+    eval_const(nodes, expr).ok()
+}
+
+/// The number of elements along a dimension of an array of size `size`, if
+/// it's a constant of at least 1. A number counts as is, even if it's too
+/// large to be the value of an expression.
+pub(crate) fn array_len(nodes: &Nodes, size: ExprId) -> Option<u64> {
+    let len = match nodes[size].kind {
+        ExprKind::Number(value) => value,
+        _ => u64::try_from(const_value(nodes, size)?).ok()?,
+    };
+    Some(len).filter(|&len| len > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_entry_point, check_program, const_value, Analysis, ArrayLayout,
+        Binding, Builtin, Layout, StructLayout,
+    };
+    use crate::{
+        ast::{Decl, StmtKind},
+        errors::{Diag, DiagBag},
+        extensions::Extensions,
+        lints::{Lint, LintLevel, WarningConfig},
+        parser::ParseResult,
+        scanner::Keyword,
+        source_map::Span,
+        test_support::parse_source,
+    };
+    use std::collections::HashMap;
+
+    fn check(source: &str) -> Vec<Diag> {
+        let (
+            _,
+            ParseResult {
+                program, diag_bag, ..
+            },
+        ) = parse_source(source, Extensions::all());
+        assert!(!diag_bag.has_errors(), "syntax errors in {:?}", source);
+
+        let diag_bag = check_program(&program, &Builtin::ALL).diag_bag;
+        diag_bag.iter().map(|diag| diag.diag.clone()).collect()
+    }
+
+    #[test]
+    fn extension_keywords_are_names_without_their_extension() {
+        let source = "int for; int case;\nvoid f(void) { for = case; break; }";
+        let (_, ParseResult { program, .. }) =
+            parse_source(source, Extensions::none());
+
+        let diag_bag = check_program(&program, &Builtin::ALL).diag_bag;
+        let diags: Vec<_> = diag_bag.iter().collect();
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].diag,
+            Diag::UndeclaredName {
+                name: "break".into(),
+                span: Span::with_usizes(46, 51),
+            }
+        );
+        assert_eq!(
+            diags[0].children[0].message,
+            "`break` is a keyword with `--extension=break`"
+        );
+    }
+
+    #[test]
+    fn break_and_continue_in_loops() {
+        let source = "void f(int x) {\n\
+                      while (x) { if (x) break; else continue; }\n\
+                      for (;;) while (x) break;\n\
+                      for (;;) { { continue; } }\n\
+                      }";
+
+        assert_eq!(check(source), Vec::new());
+    }
+
+    #[test]
+    fn break_may_leave_a_switch_but_continue_may_not() {
+        let diags = check(
+            "void f(int x) {\n\
+             switch (x) { case 1: break; default: continue; }\n\
+             while (x) switch (x) { case 1: continue; }\n\
+             }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![Diag::LoopControlOutsideLoop {
+                keyword: Keyword::Continue,
+                span: Span::with_usizes(53, 62),
+            }]
+        );
+    }
+
+    #[test]
+    fn cases_must_be_different_constants() {
+        let diags = check(
+            "void f(int x) {\n\
+             switch (x) {\n\
+             case 1: case 2 - 1: case x: case -1:\n\
+             default: case 1 / 0: default: ;\n\
+             }\n\
+             }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::DuplicateCase {
+                    value: Some(1),
+                    span: Span::with_usizes(37, 48),
+                    first: Span::with_usizes(29, 36),
+                },
+                Diag::NonConstantCase {
+                    span: Span::with_usizes(54, 55),
+                },
+                Diag::ConstDivisionByZero {
+                    span: Span::with_usizes(80, 85),
+                    divisor: Span::with_usizes(84, 85),
+                },
+                Diag::DuplicateCase {
+                    value: None,
+                    span: Span::with_usizes(87, 95),
+                    first: Span::with_usizes(66, 74),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn global_initializers_must_be_constants() {
+        let diags = check("int x = 1 + 2;\nint y = x;\nint z = 1 / 0;");
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::NonConstantInitializer {
+                    name: "y".into(),
+                    span: Span::with_usizes(23, 24),
+                },
+                Diag::ConstDivisionByZero {
+                    span: Span::with_usizes(34, 39),
+                    divisor: Span::with_usizes(38, 39),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_are_laid_out_row_by_row() {
+        let layout = ArrayLayout::of;
+
+        assert_eq!(
+            layout(&[10]),
+            Some(ArrayLayout {
+                strides: vec![1],
+                len: 10
+            })
+        );
+        assert_eq!(
+            layout(&[3, 4]),
+            Some(ArrayLayout {
+                strides: vec![4, 1],
+                len: 12
+            })
+        );
+        assert_eq!(
+            layout(&[2, 3, 4]),
+            Some(ArrayLayout {
+                strides: vec![12, 4, 1],
+                len: 24
+            })
+        );
+        assert_eq!(layout(&[1 << 32, 1 << 32]), None);
+    }
+
+    #[test]
+    fn arrays_must_fit_in_memory() {
+        let diags = check(
+            "int a[4294967296][4294967296];\n\
+             void f(void) { int b[4294967296][4294967295]; }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![Diag::ArrayTooLarge {
+                name: "a".into(),
+                span: Span::with_usizes(6, 28),
+            }]
+        );
+    }
+
+    #[test]
+    fn only_arrays_are_indexed_structs_have_fields_and_pointers_point() {
+        let diags = check(
+            "struct s { int a; };\n\
+             void f(int i, int *p, struct s v) {\n\
+             i = i[0]; i = v.b; i = *i; i = p[0] + *p + v.a; i = i.a; \
+             i = undeclared[0] + undeclared.a + *undeclared;\n\
+             }",
+        );
+        // Their types are unknown, so nothing else is reported about them.
+        let undeclared = |start| Diag::UndeclaredName {
+            name: "undeclared".into(),
+            span: Span::with_usizes(start, start + 10),
+        };
+
+        assert_eq!(
+            diags,
+            vec![
+                undeclared(118),
+                undeclared(134),
+                undeclared(150),
+                Diag::NotAnArray {
+                    ty: "int".into(),
+                    span: Span::with_usizes(61, 62),
+                },
+                Diag::UnknownField {
+                    field: "b".into(),
+                    ty: "struct s".into(),
+                    span: Span::with_usizes(73, 74),
+                },
+                Diag::NotAPointer {
+                    ty: "int".into(),
+                    span: Span::with_usizes(81, 82),
+                },
+                Diag::UnknownField {
+                    field: "a".into(),
+                    ty: "int".into(),
+                    span: Span::with_usizes(111, 112),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn array_fields_are_assigned_to_one_element_at_a_time() {
+        let diags = check(
+            "struct s { int a; int b[3]; struct s *next; };\n\
+             int g[3];\n\
+             void f(struct s v) { v.b = g; (*v.next).b = g; v.b[1] = g[0]; }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::UnassignableTarget {
+                    name: "v".into(),
+                    is_array: true,
+                    span: Span::with_usizes(78, 81),
+                    decl: Some(Span::with_usizes(73, 74)),
+                },
+                Diag::UnassignableTarget {
+                    name: "".into(),
+                    is_array: true,
+                    span: Span::with_usizes(87, 98),
+                    decl: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn only_variables_and_elements_are_assigned_to() {
+        let diags = check(
+            "int g[4]; int m[2][3];\n\
+             void f(int a[], int *p) { f = 1; input = 2; g = 3; m[0] = 4; \
+             a = p; p = a; g[1] = m[1][2]; }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::UnassignableTarget {
+                    name: "f".into(),
+                    is_array: false,
+                    span: Span::with_usizes(49, 50),
+                    decl: Some(Span::with_usizes(28, 29)),
+                },
+                Diag::UnassignableTarget {
+                    name: "input".into(),
+                    is_array: false,
+                    span: Span::with_usizes(56, 61),
+                    decl: None,
+                },
+                Diag::UnassignableTarget {
+                    name: "g".into(),
+                    is_array: true,
+                    span: Span::with_usizes(67, 68),
+                    decl: Some(Span::with_usizes(4, 5)),
+                },
+                Diag::UnassignableTarget {
+                    name: "m".into(),
+                    is_array: true,
+                    span: Span::with_usizes(74, 78),
+                    decl: Some(Span::with_usizes(14, 15)),
+                },
+                Diag::UnassignableTarget {
+                    name: "a".into(),
+                    is_array: true,
+                    span: Span::with_usizes(84, 85),
+                    decl: Some(Span::with_usizes(34, 35)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn array_sizes_are_positive_constants() {
+        let diags = check(
+            "int n;\nint a[2 * 8]; int b[n]; int c[0]; int d[1 - 3];\n\
+             void f(void) { int e[4 / 0]; int g[-1][2 > 1]; }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::NonConstantArraySize {
+                    name: "b".into(),
+                    span: Span::with_usizes(27, 28),
+                },
+                Diag::ArraySizeNotPositive {
+                    name: "c".into(),
+                    value: 0,
+                    span: Span::with_usizes(37, 38),
+                },
+                Diag::ArraySizeNotPositive {
+                    name: "d".into(),
+                    value: -2,
+                    span: Span::with_usizes(47, 52),
+                },
+                Diag::ConstDivisionByZero {
+                    span: Span::with_usizes(76, 81),
+                    divisor: Span::with_usizes(80, 81),
+                },
+                Diag::ArraySizeNotPositive {
+                    name: "g".into(),
+                    value: -1,
+                    span: Span::with_usizes(90, 92),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn structs_are_laid_out_as_in_c() {
+        let source = "struct a { char c; int i; char d; };\n\
+                      struct b { char c; struct a a[2]; int *p; };\n\
+                      struct e { };";
+        let (_, ParseResult { program, .. }) =
+            parse_source(source, Extensions::all());
+
+        let mut structs = HashMap::new();
+        for decl in &program.decls {
+            let Decl::Struct(struct_decl) = decl else {
+                unreachable!()
+            };
+            let layout =
+                StructLayout::of(&program.nodes, struct_decl, &structs)
+                    .unwrap();
+            structs.insert(struct_decl.name.name.clone(), layout);
+        }
+
+        let fields = |name: &str| {
+            let layout = &structs[name];
+            let offsets: Vec<_> = layout
+                .fields
+                .iter()
+                .map(|(field, offset)| (field.as_str(), *offset))
+                .collect();
+            (offsets, layout.layout)
+        };
+        assert_eq!(
+            fields("a"),
+            (
+                vec![("c", 0), ("i", 4), ("d", 8)],
+                Layout { size: 12, align: 4 }
+            )
+        );
+        assert_eq!(
+            fields("b"),
+            (
+                vec![("c", 0), ("a", 4), ("p", 32)],
+                Layout { size: 40, align: 8 }
+            )
+        );
+        assert_eq!(fields("e"), (vec![], Layout { size: 0, align: 1 }));
+        assert_eq!(structs["b"].offset_of("p"), Some(32));
+        assert_eq!(structs["b"].offset_of("q"), None);
+    }
+
+    #[test]
+    fn structs_are_defined_before_they_are_used() {
+        let diags = check(
+            "struct p { struct q x; struct p *next; };\n\
+             struct r g; struct s *h;\n\
+             struct t f(struct u a, struct v *b) { struct w c; }",
+        );
+        let names: Vec<_> = diags
+            .iter()
+            .map(|diag| match diag {
+                Diag::UnknownStruct { name, .. } => name.as_str(),
+                _ => panic!("unexpected {:?}", diag),
+            })
+            .collect();
+
+        assert_eq!(names, ["q", "r", "t", "u", "w"]);
+        assert_eq!(
+            diags[0],
+            Diag::UnknownStruct {
+                name: "q".into(),
+                span: Span::with_usizes(18, 19),
+            }
+        );
+    }
+
+    #[test]
+    fn fields_have_different_names() {
+        let diags = check("struct p { int x; int y; char x; int *y; };");
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::DuplicateField {
+                    name: "x".into(),
+                    span: Span::with_usizes(30, 31),
+                    first: Span::with_usizes(15, 16),
+                },
+                Diag::DuplicateField {
+                    name: "y".into(),
+                    span: Span::with_usizes(38, 39),
+                    first: Span::with_usizes(22, 23),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn const_variables_are_not_assigned_to() {
+        let diags = check(
+            "const int n = 3; const int m[2]; struct p { int x; };\n\
+             void f(const struct p a, const int *q, int m) {\n\
+             q = &n; m = n; a.x = 1; n = 4;\n\
+             { const int m; m = 1; } m = 1;\n\
+             }\n\
+             void g(void) { m[0] = 1; }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::AssignToConst {
+                    name: "a".into(),
+                    span: Span::with_usizes(117, 120),
+                    decl: Span::with_usizes(76, 77),
+                },
+                Diag::AssignToConst {
+                    name: "n".into(),
+                    span: Span::with_usizes(126, 127),
+                    decl: Span::with_usizes(10, 11),
+                },
+                Diag::AssignToConst {
+                    name: "m".into(),
+                    span: Span::with_usizes(148, 149),
+                    decl: Span::with_usizes(145, 146),
+                },
+                Diag::AssignToConst {
+                    name: "m".into(),
+                    span: Span::with_usizes(181, 185),
+                    decl: Span::with_usizes(27, 28),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn names_are_declared_once_before_they_are_used() {
+        let diags = check(
+            "int g; int f(int a) { int b; return a + g + c; }\n\
+             int g; int f;\n\
+             void h(int b, int b) { int b; { int b; } b = f(d()); }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::UndeclaredName {
+                    name: "c".into(),
+                    span: Span::with_usizes(44, 45),
+                },
+                Diag::Redeclaration {
+                    name: "g".into(),
+                    span: Span::with_usizes(53, 54),
+                    previous: Span::with_usizes(4, 5),
+                },
+                Diag::Redeclaration {
+                    name: "f".into(),
+                    span: Span::with_usizes(60, 61),
+                    previous: Span::with_usizes(11, 12),
+                },
+                Diag::DuplicateParam {
+                    name: "b".into(),
+                    span: Span::with_usizes(81, 82),
+                    first: Span::with_usizes(74, 75),
+                },
+                Diag::Redeclaration {
+                    name: "b".into(),
+                    span: Span::with_usizes(90, 91),
+                    previous: Span::with_usizes(74, 75),
+                },
+                Diag::UndeclaredName {
+                    name: "d".into(),
+                    span: Span::with_usizes(110, 111),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn builtins_are_declared_around_the_file() {
+        let source = "void f(void) { output(input()); }\n\
+                      int input(void) { return 0; }\n\
+                      void g(void) { output(input()); }";
+        let (source_file, ParseResult { program, .. }) =
+            parse_source(source, Extensions::none());
+        let inputs = |builtins: &[Builtin]| {
+            let Analysis {
+                diag_bag,
+                resolutions,
+                ..
+            } = check_program(&program, builtins);
+            let diags: Vec<_> =
+                diag_bag.iter().map(|diag| diag.diag.clone()).collect();
+            let mut bindings: Vec<_> = program
+                .nodes
+                .exprs
+                .iter()
+                .filter(|(_, expr)| {
+                    source_file.span_to_snippet(expr.span) == "input()"
+                })
+                .map(|(id, expr)| (expr.span.start, resolutions.get(id)))
+                .collect();
+            bindings.sort_by_key(|&(start, _)| start);
+            let bindings: Vec<_> =
+                bindings.into_iter().map(|(_, binding)| binding).collect();
+            (diags, bindings)
+        };
+
+        assert_eq!(
+            inputs(&Builtin::ALL),
+            (
+                Vec::new(),
+                vec![
+                    Some(Binding::Builtin(Builtin::Input)),
+                    Some(Binding::Fun(1))
+                ]
+            )
+        );
+        assert_eq!(
+            inputs(&[Builtin::Input]),
+            (
+                vec![
+                    Diag::UndeclaredName {
+                        name: "output".into(),
+                        span: Span::with_usizes(15, 21),
+                    },
+                    Diag::UndeclaredName {
+                        name: "output".into(),
+                        span: Span::with_usizes(79, 85),
+                    },
+                ],
+                vec![
+                    Some(Binding::Builtin(Builtin::Input)),
+                    Some(Binding::Fun(1))
+                ]
+            )
+        );
+        assert_eq!(inputs(&[]).1, vec![None, Some(Binding::Fun(1))]);
+    }
+
+    #[test]
+    fn values_have_the_types_they_are_used_as() {
+        let diags = check(
+            "int *p; const int *c; char s[4]; struct a { int x; }; struct a v;\n\
+             int f(int *q) { return q; }\n\
+             void g(void) { int x; x = p; p = c; c = p; x = s[0]; \
+             f(s); f(&x); p = 0; output(p); v = x; x = output(1); }",
+        );
+
+        let mismatch =
+            |expected: &str, found: &str, span, because| Diag::TypeMismatch {
+                expected: expected.into(),
+                found: found.into(),
+                discards_const: false,
+                span,
+                because,
+            };
+        assert_eq!(
+            diags,
+            vec![
+                Diag::VoidValueUsed {
+                    callee: "output".into(),
+                    span: Span::with_usizes(189, 198),
+                },
+                mismatch(
+                    "int",
+                    "int *",
+                    Span::with_usizes(89, 90),
+                    Some(Span::with_usizes(70, 71)),
+                ),
+                mismatch("int", "int *", Span::with_usizes(120, 121), None),
+                Diag::TypeMismatch {
+                    expected: "int *".into(),
+                    found: "const int *".into(),
+                    discards_const: true,
+                    span: Span::with_usizes(127, 128),
+                    because: None,
+                },
+                mismatch(
+                    "int *",
+                    "char *",
+                    Span::with_usizes(149, 150),
+                    Some(Span::with_usizes(77, 78)),
+                ),
+                mismatch("int", "int *", Span::with_usizes(174, 175), None),
+                mismatch("struct a", "int", Span::with_usizes(182, 183), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn operands_and_conditions_are_numbers_or_pointers() {
+        let diags = check(
+            "struct a { int x; }; struct a v; int s[4]; int t[4]; int *p; \
+             char *c;\n\
+             void f(void) { int x; x = s * 2; x = s < t; x = v + 1; \
+             x = 2 * p; x = -p; x = p - c; p = p + 1 - s[0]; x = p - s; \
+             x = p == 0; x = p < s; }\n\
+             void g(void) { int x; if (v) x = 1; while (s) x = 1; \
+             x = p ? 1 : 0; if (p) x = 0; }",
+        );
+
+        let mismatch = |expected: &str, found: &str, span| Diag::TypeMismatch {
+            expected: expected.into(),
+            found: found.into(),
+            discards_const: false,
+            span,
+            because: None,
+        };
+        assert_eq!(
+            diags,
+            vec![
+                mismatch("int", "int *", Span::with_usizes(96, 97)),
+                mismatch("int", "int *", Span::with_usizes(107, 108)),
+                mismatch("int", "int *", Span::with_usizes(111, 112)),
+                mismatch("int", "struct a", Span::with_usizes(118, 119)),
+                mismatch("int", "int *", Span::with_usizes(133, 134)),
+                mismatch("int", "int *", Span::with_usizes(141, 142)),
+                mismatch("int *", "char *", Span::with_usizes(152, 153)),
+                mismatch("int", "struct a", Span::with_usizes(235, 236)),
+                mismatch("int", "int *", Span::with_usizes(252, 253)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rows_of_array_parameters_have_a_size() {
+        let diags = check(
+            "int f(int m[][3]) { return m[1][2]; }\n\
+             int g(int m[][0]) { return 0; }\n\
+             void main(void) { int a[2][3]; int b[4][2]; f(a); f(b); }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ArraySizeNotPositive {
+                    name: "m".into(),
+                    value: 0,
+                    span: Span::with_usizes(52, 53),
+                },
+                Diag::TypeMismatch {
+                    expected: "int [3] *".into(),
+                    found: "int [2] *".into(),
+                    discards_const: false,
+                    span: Span::with_usizes(122, 123),
+                    because: Some(Span::with_usizes(10, 11)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_are_passed_where_parameters_are_arrays() {
+        let diags = check(
+            "int sum(int a[], int n) { return a[0] + n; }\n\
+             int m[2][3]; int *p;\n\
+             void f(int v[], int x) {\n\
+             sum(m[0], 3); sum(v, x); sum(p, 1); \
+             sum(x, m); sum(m[0][1], v); sum(1, 2);\n\
+             }",
+        );
+        let param_a = Span::with_usizes(8, 15);
+        let param_n = Span::with_usizes(17, 22);
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ArrayArgumentMismatch {
+                    callee: "sum".into(),
+                    param_is_array: true,
+                    span: Span::with_usizes(131, 132),
+                    param: param_a,
+                },
+                Diag::ArrayArgumentMismatch {
+                    callee: "sum".into(),
+                    param_is_array: false,
+                    span: Span::with_usizes(134, 135),
+                    param: param_n,
+                },
+                Diag::ArrayArgumentMismatch {
+                    callee: "sum".into(),
+                    param_is_array: true,
+                    span: Span::with_usizes(142, 149),
+                    param: param_a,
+                },
+                Diag::ArrayArgumentMismatch {
+                    callee: "sum".into(),
+                    param_is_array: false,
+                    span: Span::with_usizes(151, 152),
+                    param: param_n,
+                },
+                Diag::ArrayArgumentMismatch {
+                    callee: "sum".into(),
+                    param_is_array: true,
+                    span: Span::with_usizes(159, 160),
+                    param: param_a,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_has_the_value_of_void() {
+        let diags = check(
+            "void g; void a[5]; struct p { void x; };\n\
+             void h(void b[], void *c) { }\n\
+             void f(void) { void *d; int x; x = f() + 1; f(); output(f()); \
+             for (f(); x; output(x)) ; return output(1); }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::VoidVariable {
+                    name: "g".into(),
+                    is_array: false,
+                    span: Span::with_usizes(5, 6),
+                },
+                Diag::VoidVariable {
+                    name: "a".into(),
+                    is_array: true,
+                    span: Span::with_usizes(13, 14),
+                },
+                Diag::VoidVariable {
+                    name: "x".into(),
+                    is_array: false,
+                    span: Span::with_usizes(35, 36),
+                },
+                Diag::VoidVariable {
+                    name: "b".into(),
+                    is_array: true,
+                    span: Span::with_usizes(53, 54),
+                },
+                Diag::VoidValueUsed {
+                    callee: "f".into(),
+                    span: Span::with_usizes(106, 109),
+                },
+                Diag::VoidValueUsed {
+                    callee: "f".into(),
+                    span: Span::with_usizes(127, 130),
+                },
+                Diag::VoidValueUsed {
+                    callee: "output".into(),
+                    span: Span::with_usizes(166, 175),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn code_that_never_runs_is_unreachable() {
+        let diags = check(
+            "int f(int x) {\n\
+             while (x) { if (x) break; else continue; x = 1; }\n\
+             if (0) { return 1; x = 2; } else x = 3;\n\
+             while (0) x = 4;\n\
+             switch (x) { case 1: return 1; x = 5; case 2: x = 6; }\n\
+             return x; x = 7; { x = 8; }\n\
+             }",
+        );
+        let unreachable =
+            |span: (usize, usize), cause: (usize, usize), condition| {
+                Diag::UnreachableCode {
+                    span: Span::with_usizes(span.0, span.1),
+                    cause: Span::with_usizes(cause.0, cause.1),
+                    condition,
+                }
+            };
+
+        assert_eq!(
+            diags,
+            vec![
+                unreachable((56, 62), (27, 55), None),
+                unreachable((72, 92), (69, 70), Some(false)),
+                unreachable((115, 121), (112, 113), Some(false)),
+                unreachable((153, 159), (143, 152), None),
+                unreachable((187, 204), (177, 186), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn programs_start_at_void_main_void() {
+        let entry_point = |source: &str| {
+            let (_, ParseResult { program, .. }) =
+                parse_source(source, Extensions::all());
+            let mut warning_config = WarningConfig::new();
+            warning_config.set_level(Lint::MainNotLast, LintLevel::Warn);
+            let mut diag_bag =
+                DiagBag::new().with_warning_config(warning_config);
+            diag_bag.extend(check_entry_point(&program));
+            diag_bag.end_phase();
+            diag_bag
+                .iter()
+                .map(|diag| diag.diag.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(entry_point("int x; void main(void) { }"), []);
+        assert_eq!(
+            entry_point("int main; void f(void) { }"),
+            [Diag::MissingMain]
+        );
+        assert_eq!(
+            entry_point("int main(int argc) { }"),
+            [Diag::MainSignature {
+                span: Span::with_usizes(0, 18),
+            }]
+        );
+        assert_eq!(
+            entry_point("const void *main(void) { }"),
+            [Diag::MainSignature {
+                span: Span::with_usizes(0, 22),
+            }]
+        );
+        assert_eq!(
+            entry_point("void main(void) { }\nint x;"),
+            [Diag::MainNotLast {
+                span: Span::with_usizes(5, 9),
+                after: Span::with_usizes(24, 25),
+            }]
+        );
+    }
+
+    #[test]
+    fn dead_stores_are_reported_on_request() {
+        let dead_stores = |source: &str, level| {
+            let (_, ParseResult { program, .. }) =
+                parse_source(source, Extensions::all());
+            let mut warning_config = WarningConfig::new();
+            if let Some(level) = level {
+                warning_config.set_level(Lint::DeadStore, level);
+            }
+            let mut diag_bag =
+                DiagBag::new().with_warning_config(warning_config);
+            diag_bag.extend(check_program(&program, &Builtin::ALL).diag_bag);
+            diag_bag.end_phase();
+            diag_bag
+                .iter()
+                .map(|diag| diag.diag.clone())
+                .collect::<Vec<_>>()
+        };
+        let source = "void f(void) { int x; x = input(); x = 0; output(x); }";
+
+        assert_eq!(dead_stores(source, None), []);
+        assert_eq!(
+            dead_stores(source, Some(LintLevel::Warn)),
+            [Diag::DeadStore {
+                name: "x".into(),
+                span: Span::with_usizes(22, 33),
+            }]
+        );
+        // Not worth it next to errors.
+        assert_eq!(
+            dead_stores(
+                "void f(void) { int x; x = input(); x = y; output(x); }",
+                Some(LintLevel::Warn)
+            ),
+            [Diag::UndeclaredName {
+                name: "y".into(),
+                span: Span::with_usizes(39, 40),
+            }]
+        );
+    }
+
+    /// The diagnostics of `source` once sema is over, with `lint` at `level`.
+    fn check_with_lint(
+        source: &str,
+        lint: Lint,
+        level: LintLevel,
+    ) -> Vec<Diag> {
+        let (_, ParseResult { program, .. }) =
+            parse_source(source, Extensions::all());
+        let mut warning_config = WarningConfig::new();
+        warning_config.set_level(lint, level);
+        let mut diag_bag = DiagBag::new().with_warning_config(warning_config);
+        diag_bag.extend(check_program(&program, &Builtin::ALL).diag_bag);
+        diag_bag.end_phase();
+        diag_bag.iter().map(|diag| diag.diag.clone()).collect()
+    }
+
+    #[test]
+    fn unused_variables_and_parameters_are_reported() {
+        let source = "int g;\n\
+                      int f(int a, int b) { int x; int y; y = a; \
+                      { int g; int a; g = a; } return y; }";
+
+        assert_eq!(
+            check_with_lint(source, Lint::UnusedVariable, LintLevel::Warn),
+            [
+                Diag::UnusedVariable {
+                    name: "b".into(),
+                    is_param: true,
+                    span: Span::with_usizes(24, 25),
+                },
+                Diag::UnusedVariable {
+                    name: "x".into(),
+                    is_param: false,
+                    span: Span::with_usizes(33, 34),
+                },
+            ]
+        );
+        assert_eq!(
+            check_with_lint(source, Lint::UnusedVariable, LintLevel::Allow),
+            []
+        );
+    }
+
+    #[test]
+    fn shadowing_is_reported_on_request() {
+        let source = "int g;\n\
+                      int f(int a, int b) { int x; int y; y = a; \
+                      { int g; int a; g = a; } return y; }";
+
+        assert_eq!(
+            check_with_lint(source, Lint::Shadowing, LintLevel::Warn),
+            [
+                Diag::Shadowing {
+                    name: "g".into(),
+                    span: Span::with_usizes(56, 57),
+                    previous: Span::with_usizes(4, 5),
+                },
+                Diag::Shadowing {
+                    name: "a".into(),
+                    span: Span::with_usizes(63, 64),
+                    previous: Span::with_usizes(17, 18),
+                },
+                Diag::UnusedVariable {
+                    name: "b".into(),
+                    is_param: true,
+                    span: Span::with_usizes(24, 25),
+                },
+                Diag::UnusedVariable {
+                    name: "x".into(),
+                    is_param: false,
+                    span: Span::with_usizes(33, 34),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn names_refer_to_their_innermost_declaration() {
+        let source = "int g;\n\
+                      int f(int a) {\n\
+                      int b; { int a; b = a + g; }\n\
+                      return f(b) + input();\n\
+                      }";
+        let (_, ParseResult { program, .. }) =
+            parse_source(source, Extensions::none());
+        let Decl::Fun(f) = &program.decls[1] else {
+            unreachable!()
+        };
+        let StmtKind::Block(inner) =
+            program.nodes[program.nodes[f.body].stmts[0]].kind
+        else {
+            unreachable!()
+        };
+
+        let Analysis {
+            diag_bag,
+            resolutions,
+            ..
+        } = check_program(&program, &Builtin::ALL);
+        assert!(!diag_bag.has_errors());
+        let mut bindings: Vec<_> = program
+            .nodes
+            .exprs
+            .iter()
+            .filter_map(|(id, expr)| {
+                let binding = resolutions.get(id)?;
+                Some((expr.span.start, binding))
+            })
+            .collect();
+        bindings.sort_by_key(|&(start, _)| start);
+        let bindings: Vec<_> =
+            bindings.into_iter().map(|(_, binding)| binding).collect();
+
+        assert_eq!(
+            bindings,
+            [
+                Binding::Local {
+                    block: f.body,
+                    index: 0
+                },
+                Binding::Local {
+                    block: inner,
+                    index: 0
+                },
+                Binding::Global(0),
+                Binding::Fun(1),
+                Binding::Local {
+                    block: f.body,
+                    index: 0
+                },
+                Binding::Builtin(Builtin::Input),
+            ]
+        );
+    }
+
+    #[test]
+    fn constant_values() {
+        let value = |source: &str| {
+            let source = format!("void f(void) {{ return {}; }}", source);
+            let (_, ParseResult { program, .. }) =
+                parse_source(&source, Extensions::all());
+            let Decl::Fun(f) = &program.decls[0] else {
+                unreachable!()
+            };
+            let stmt = program.nodes[f.body].stmts[0];
+            let StmtKind::Return(Some(expr)) = program.nodes[stmt].kind else {
+                unreachable!()
+            };
+            const_value(&program.nodes, expr)
+        };
+
+        assert_eq!(value("1 + 2 * 3"), Some(7));
+        assert_eq!(value("-(7 / 2)"), Some(-3));
+        assert_eq!(value("(1 < 2) + (1 == 2)"), Some(1));
+        assert_eq!(value("1 / (1 - 1)"), None);
+        assert_eq!(value("1 < 2 ? 3 : 1 / 0"), Some(3));
+        assert_eq!(value("0 ? 3 : x"), None);
+        assert_eq!(value("'a' + 1"), Some(98));
+        assert_eq!(value("\"a\""), None);
+        assert_eq!(value("x + 1"), None);
+        assert_eq!(value("f()"), None);
+        assert_eq!(value("-2147483648"), Some(-2147483648));
+        assert_eq!(value("2147483647 + 1"), None);
+        assert_eq!(value("2147483648"), None);
+        assert_eq!(value("-(-2147483647 - 1)"), None);
+        assert_eq!(value("(-2147483647 - 1) / -1"), None);
+        assert_eq!(value("65536 * 65536"), None);
+    }
+
+    #[test]
+    fn constant_expressions_do_not_overflow_or_divide_by_zero() {
+        let diags = check(
+            "int g = 2147483647 + 1;\n\
+             int a[1 / 0];\n\
+             void f(int x) {\n\
+             switch (x) { case 65536 * 65536: break; }\n\
+             while (x / 0) ;\n\
+             if (0 ? 1 / 0 : 1) ;\n\
+             if (1 / 0 + 4294967296) ;\n\
+             }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::ConstOverflow {
+                    span: Span::with_usizes(8, 22),
+                },
+                Diag::ConstDivisionByZero {
+                    span: Span::with_usizes(30, 35),
+                    divisor: Span::with_usizes(34, 35),
+                },
+                Diag::ConstOverflow {
+                    span: Span::with_usizes(72, 85),
+                },
+                Diag::ConstDivisionByZero {
+                    span: Span::with_usizes(137, 142),
+                    divisor: Span::with_usizes(141, 142),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn break_and_continue_outside_of_loops() {
+        let diags = check(
+            "void f(int x) { if (x) break; while (x) ; continue; }\n\
+             void g(void) { break; }",
+        );
+
+        assert_eq!(
+            diags,
+            vec![
+                Diag::LoopControlOutsideLoop {
+                    keyword: Keyword::Break,
+                    span: Span::with_usizes(23, 29),
+                },
+                Diag::LoopControlOutsideLoop {
+                    keyword: Keyword::Continue,
+                    span: Span::with_usizes(42, 51),
+                },
+                Diag::LoopControlOutsideLoop {
+                    keyword: Keyword::Break,
+                    span: Span::with_usizes(69, 75),
+                },
+            ]
+        );
+    }
+}
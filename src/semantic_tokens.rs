@@ -0,0 +1,220 @@
+//! What each name in a program is, where it's declared and where it's used,
+//! for editors to highlight a function differently from a variable, or a
+//! parameter differently from a global.
+//!
+//! The scanner only knows a name is an identifier. What it names comes from
+//! sema's resolutions, so a name sema couldn't resolve, e.g. an undeclared
+//! one, isn't classified.
+
+use crate::{
+    ast::{Decl, ExprId, ExprKind, Ident, Nodes, Program, VarDecl},
+    sema::{Binding, Resolutions},
+    source_map::Span,
+    visit::{walk_expr, walk_var_decl, Visitor},
+};
+
+/// What a name names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Function,
+    Parameter,
+    /// A variable declared in the body of a function.
+    Local,
+    /// A variable declared at file scope.
+    Global,
+    /// A function every program can call without declaring it.
+    Builtin,
+}
+
+/// A name in a program and what it names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SemanticToken {
+    pub(crate) span: Span,
+    pub(crate) kind: TokenKind,
+    /// Whether it names an array, including an array parameter.
+    pub(crate) is_array: bool,
+    /// Whether it's the name in the declaration, rather than a use.
+    pub(crate) is_declaration: bool,
+}
+
+/// The names in `program` whose names refer to what `resolutions` says, in
+/// source order.
+pub(crate) fn semantic_tokens(
+    program: &Program,
+    resolutions: &Resolutions,
+) -> Vec<SemanticToken> {
+    let mut classifier = Classifier {
+        program,
+        resolutions,
+        tokens: Vec::new(),
+    };
+    classifier.visit_program(program);
+    let mut tokens = classifier.tokens;
+    tokens.sort_by_key(|token| token.span.start);
+    tokens
+}
+
+struct Classifier<'a> {
+    program: &'a Program,
+    resolutions: &'a Resolutions,
+    tokens: Vec<SemanticToken>,
+}
+
+impl Classifier<'_> {
+    fn push(
+        &mut self,
+        name: &Ident,
+        kind: TokenKind,
+        is_array: bool,
+        is_declaration: bool,
+    ) {
+        self.tokens.push(SemanticToken {
+            span: name.span,
+            kind,
+            is_array,
+            is_declaration,
+        });
+    }
+
+    /// What `binding` is, and whether it's an array.
+    fn classify(&self, binding: Binding) -> (TokenKind, bool) {
+        let decls = &self.program.decls;
+        let nodes = &self.program.nodes;
+        match binding {
+            Binding::Global(index) => {
+                let is_array = matches!(
+                    &decls[index],
+                    Decl::Var(var_decl) if !var_decl.sizes.is_empty()
+                );
+                (TokenKind::Global, is_array)
+            }
+            Binding::Fun(_) => (TokenKind::Function, false),
+            Binding::Param { fun, index } => {
+                let is_array = matches!(
+                    &decls[fun],
+                    Decl::Fun(fun_decl) if fun_decl.params[index].is_array
+                );
+                (TokenKind::Parameter, is_array)
+            }
+            Binding::Local { block, index } => {
+                let is_array = !nodes[block].decls[index].sizes.is_empty();
+                (TokenKind::Local, is_array)
+            }
+            Binding::Builtin(_) => (TokenKind::Builtin, false),
+        }
+    }
+}
+
+impl Visitor for Classifier<'_> {
+    fn visit_program(&mut self, program: &Program) {
+        let nodes = &program.nodes;
+        for decl in &program.decls {
+            match decl {
+                Decl::Var(var_decl) => {
+                    let is_array = !var_decl.sizes.is_empty();
+                    self.push(
+                        &var_decl.name,
+                        TokenKind::Global,
+                        is_array,
+                        true,
+                    );
+                    walk_var_decl(self, nodes, var_decl);
+                }
+                Decl::Fun(fun_decl) => {
+                    self.push(&fun_decl.name, TokenKind::Function, false, true);
+                    for param in &fun_decl.params {
+                        let is_array = param.is_array;
+                        self.push(
+                            &param.name,
+                            TokenKind::Parameter,
+                            is_array,
+                            true,
+                        );
+                    }
+                    self.visit_block(nodes, fun_decl.body);
+                }
+                // Neither structs nor their fields are names sema resolves.
+                Decl::Struct(_) => {}
+            }
+        }
+    }
+
+    fn visit_var_decl(&mut self, nodes: &Nodes, var_decl: &VarDecl) {
+        // Only reached for the variables declared in a block.
+        let is_array = !var_decl.sizes.is_empty();
+        self.push(&var_decl.name, TokenKind::Local, is_array, true);
+        walk_var_decl(self, nodes, var_decl);
+    }
+
+    fn visit_expr(&mut self, nodes: &Nodes, expr: ExprId) {
+        if let ExprKind::Var(ref name)
+        | ExprKind::Call {
+            callee: ref name, ..
+        } = nodes[expr].kind
+        {
+            if let Some(binding) = self.resolutions.get(expr) {
+                let (kind, is_array) = self.classify(binding);
+                self.push(name, kind, is_array, false);
+            }
+        }
+        walk_expr(self, nodes, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{semantic_tokens, TokenKind};
+    use crate::{
+        extensions::Extensions,
+        parser::ParseResult,
+        sema::{check_program, Builtin},
+        test_support::parse_source,
+    };
+
+    #[test]
+    fn names_are_classified_by_what_they_name() {
+        let source = "int g[4]; int n;\n\
+                      int f(int a[], int b) { int c; int d[2]; \
+                      c = a[0] + b + g[1] + n + d[0]; return f(a, c) + input() + x; }";
+        let (source_file, ParseResult { program, .. }) =
+            parse_source(source, Extensions::none());
+        let resolutions = check_program(&program, &Builtin::ALL).resolutions;
+
+        let tokens: Vec<_> = semantic_tokens(&program, &resolutions)
+            .into_iter()
+            .map(|token| {
+                (
+                    source_file.span_to_snippet(token.span).to_string(),
+                    token.kind,
+                    token.is_array,
+                    token.is_declaration,
+                )
+            })
+            .collect();
+        let token = |name: &str, kind, is_array, is_declaration| {
+            (name.to_string(), kind, is_array, is_declaration)
+        };
+        assert_eq!(
+            tokens,
+            [
+                token("g", TokenKind::Global, true, true),
+                token("n", TokenKind::Global, false, true),
+                token("f", TokenKind::Function, false, true),
+                token("a", TokenKind::Parameter, true, true),
+                token("b", TokenKind::Parameter, false, true),
+                token("c", TokenKind::Local, false, true),
+                token("d", TokenKind::Local, true, true),
+                token("c", TokenKind::Local, false, false),
+                token("a", TokenKind::Parameter, true, false),
+                token("b", TokenKind::Parameter, false, false),
+                token("g", TokenKind::Global, true, false),
+                token("n", TokenKind::Global, false, false),
+                token("d", TokenKind::Local, true, false),
+                token("f", TokenKind::Function, false, false),
+                token("a", TokenKind::Parameter, true, false),
+                token("c", TokenKind::Local, false, false),
+                token("input", TokenKind::Builtin, false, false),
+            ]
+        );
+    }
+}
@@ -6,7 +6,11 @@ use std::{
 /// A byte position (or offset) into a source file's text buffer. This is used
 /// to map ASTs to soure code by indicating the position in a file from which
 /// an AST node was parsed.
-#[derive(Clone, Copy, PartialEq, Debug)]
+///
+/// Positions are global to a `SourceMap`, so a position also tells which file
+/// it belongs to.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct BytePos(pub usize);
 
 impl BytePos {
@@ -48,7 +52,8 @@ impl Pos for BytePos {
 
 /// A range (span) into a source file's text buffer, indicating a region of
 /// text.
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct Span {
     pub(crate) start: BytePos,
     pub(crate) end: BytePos,
@@ -83,43 +88,68 @@ pub(crate) struct Loc {
 /// providing an interface for text information lookup, such as: line and
 /// column number for a given position; text snippets from spans etc.
 pub(crate) struct SourceFile {
+    /// File's name, as given by the user (e.g. in the command line).
+    pub(crate) name: String,
     /// File's content.
     pub(crate) src: Rc<String>,
+    /// Position of the file's first byte in its `SourceMap`.
+    pub(crate) start_pos: BytePos,
     /// Byte positions following every new line.
     start_pos_of_lines: Vec<BytePos>,
 }
 
 impl SourceFile {
-    /// Constructs a new `SourceFile` from a string (the text buffer).
+    /// Constructs a new `SourceFile` from its name and a string (the text
+    /// buffer).
     ///
     /// Line positions are precomputed by this function.
-    #[allow(dead_code)]
-    pub fn new(source_content: String) -> SourceFile {
-        let mut start_pos_of_lines = vec![BytePos(0)];
+    #[cfg(test)]
+    pub fn new(name: String, source_content: String) -> SourceFile {
+        SourceFile::with_start_pos(name, source_content, BytePos(0))
+    }
+
+    /// Constructs a new `SourceFile` whose first byte is at `start_pos`.
+    pub(crate) fn with_start_pos(
+        name: String,
+        source_content: String,
+        start_pos: BytePos,
+    ) -> SourceFile {
+        let mut start_pos_of_lines = vec![start_pos];
 
         for (i, b) in source_content.bytes().enumerate() {
             if b == b'\n' {
-                start_pos_of_lines.push(BytePos(i + 1));
+                start_pos_of_lines.push(start_pos + BytePos(i + 1));
             }
         }
 
-        start_pos_of_lines.push(BytePos(source_content.len()));
+        start_pos_of_lines.push(start_pos + BytePos(source_content.len()));
 
         SourceFile {
+            name,
             src: Rc::new(source_content),
+            start_pos,
             start_pos_of_lines,
         }
     }
 
+    /// Position just after the file's last byte.
+    pub(crate) fn end_pos(&self) -> BytePos {
+        self.start_pos + BytePos(self.src.len())
+    }
+
+    /// Whether `pos` points into this file, including its end position.
+    pub(crate) fn contains(&self, pos: BytePos) -> bool {
+        self.start_pos <= pos && pos <= self.end_pos()
+    }
+
     /// Returns a string slice represented by a `Span`.
-    #[allow(dead_code)]
     pub(crate) fn span_to_snippet(&self, span: Span) -> &str {
-        let (BytePos(start_idx), BytePos(end_idx)) = (span.start, span.end);
+        let start_idx = (span.start - self.start_pos).to_usize();
+        let end_idx = (span.end - self.start_pos).to_usize();
         &self.src[start_idx..end_idx]
     }
 
     /// Returns the line number for a `BytePos` if such is valid.
-    #[allow(dead_code)]
     pub(crate) fn lookup_line_index(&self, pos: BytePos) -> Option<usize> {
         let pos_index = pos.to_usize();
         for (i, line_pos) in self.start_pos_of_lines.iter().enumerate() {
@@ -132,9 +162,24 @@ impl SourceFile {
         None
     }
 
+    /// Returns the text of the line at `line_index`, without its line break.
+    pub(crate) fn line_text(&self, line_index: usize) -> Option<&str> {
+        let start = (*self.start_pos_of_lines.get(line_index)?
+            - self.start_pos)
+            .to_usize();
+        let end = (*self.start_pos_of_lines.get(line_index + 1)?
+            - self.start_pos)
+            .to_usize();
+
+        if start == end && line_index > 0 {
+            return None;
+        }
+
+        Some(self.src[start..end].trim_end_matches(&['\n', '\r'][..]))
+    }
+
     /// Returns the source information (line/column number etc) of a
     /// `BytePos` if such is valid.
-    #[allow(dead_code)]
     pub fn lookup_source_location(&self, pos: BytePos) -> Option<Loc> {
         self.lookup_line_index(pos).map(|line_index| {
             let line = line_index + 1;
@@ -145,12 +190,60 @@ impl SourceFile {
     }
 }
 
+/// Holds every source file of a compilation.
+///
+/// Files are laid out one after the other in a single space of byte
+/// positions, with a gap of one byte between them so that end positions are
+/// unambiguous. Thus a `BytePos` (and a `Span`) identifies its file too.
+#[derive(Default)]
+pub(crate) struct SourceMap {
+    files: Vec<Rc<SourceFile>>,
+}
+
+impl SourceMap {
+    pub(crate) fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Adds a file to the map, placing it after the last one.
+    pub(crate) fn add_file(
+        &mut self,
+        name: String,
+        source_content: String,
+    ) -> Rc<SourceFile> {
+        let start_pos = match self.files.last() {
+            Some(last_file) => last_file.end_pos() + BytePos(1),
+            None => BytePos(0),
+        };
+        let file = Rc::new(SourceFile::with_start_pos(
+            name,
+            source_content,
+            start_pos,
+        ));
+
+        self.files.push(Rc::clone(&file));
+        file
+    }
+
+    /// Returns the file containing `pos`.
+    pub(crate) fn lookup_file(&self, pos: BytePos) -> Option<&Rc<SourceFile>> {
+        self.files.iter().find(|file| file.contains(pos))
+    }
+
+    pub(crate) fn files(&self) -> &[Rc<SourceFile>] {
+        &self.files
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{BytePos, Loc, Pos, SourceFile, Span};
+    use super::{BytePos, Loc, Pos, SourceFile, SourceMap, Span};
 
     fn create_source_file() -> SourceFile {
-        SourceFile::new("first line.\nsecond line.\nthird line.\n".into())
+        SourceFile::new(
+            "test.cm".into(),
+            "first line.\nsecond line.\nthird line.\n".into(),
+        )
     }
 
     #[test]
@@ -242,6 +335,25 @@ mod tests {
         assert_eq!(None, source_file.lookup_source_location(BytePos(37)));
     }
 
+    #[test]
+    fn line_text_excludes_line_break() {
+        let source_file = create_source_file();
+
+        assert_eq!(Some("first line."), source_file.line_text(0));
+        assert_eq!(Some("second line."), source_file.line_text(1));
+        assert_eq!(Some("third line."), source_file.line_text(2));
+        assert_eq!(None, source_file.line_text(3));
+    }
+
+    #[test]
+    fn line_text_of_last_line_without_line_break() {
+        let source_file = SourceFile::new("test.cm".into(), "a\nb".into());
+
+        assert_eq!(Some("a"), source_file.line_text(0));
+        assert_eq!(Some("b"), source_file.line_text(1));
+        assert_eq!(None, source_file.line_text(2));
+    }
+
     #[test]
     fn span_from_usizes() {
         let span = Span::with_usizes(0, 42);
@@ -249,4 +361,53 @@ mod tests {
         assert_eq!(span.start, Pos::from_usize(0));
         assert_eq!(span.end, Pos::from_usize(42));
     }
+
+    #[test]
+    fn files_in_a_source_map_dont_overlap() {
+        let mut source_map = SourceMap::new();
+
+        let first = source_map.add_file("a.cm".into(), "abc\n".into());
+        let second = source_map.add_file("b.cm".into(), "de".into());
+
+        assert_eq!(first.start_pos, BytePos(0));
+        assert_eq!(first.end_pos(), BytePos(4));
+        assert_eq!(second.start_pos, BytePos(5));
+        assert_eq!(second.end_pos(), BytePos(7));
+    }
+
+    #[test]
+    fn lookup_file_in_source_map() {
+        let mut source_map = SourceMap::new();
+        source_map.add_file("a.cm".into(), "abc\n".into());
+        source_map.add_file("b.cm".into(), "de".into());
+
+        let name_at = |pos| {
+            source_map
+                .lookup_file(BytePos(pos))
+                .map(|file| file.name.as_str())
+        };
+
+        assert_eq!(name_at(0), Some("a.cm"));
+        assert_eq!(name_at(4), Some("a.cm"));
+        assert_eq!(name_at(5), Some("b.cm"));
+        assert_eq!(name_at(7), Some("b.cm"));
+        assert_eq!(name_at(8), None);
+    }
+
+    #[test]
+    fn lookups_in_a_file_not_at_the_start_of_the_source_map() {
+        let mut source_map = SourceMap::new();
+        source_map.add_file("a.cm".into(), "abc\n".into());
+        let file = source_map.add_file("b.cm".into(), "de\nfgh\n".into());
+
+        assert_eq!(
+            Some(Loc {
+                line: 2,
+                col: BytePos(1),
+            }),
+            file.lookup_source_location(BytePos(9))
+        );
+        assert_eq!("fgh", file.span_to_snippet(Span::with_usizes(8, 11)));
+        assert_eq!(Some("fgh"), file.line_text(1));
+    }
 }
@@ -0,0 +1,470 @@
+//! Strength reduction: replacing the multiplications in a loop of a
+//! variable that goes up or down by a constant in each iteration, e.g. an
+//! index into an array times the size of its elements, with a temporary
+//! that goes up or down by as much times the constant, so the loop adds
+//! rather than multiplies.
+//!
+//! A variable is an induction variable of a natural loop (see `cfg`) if
+//! it's an `int` in a slot whose address never escapes (see `licm`), and
+//! everything the loop stores to it is what was loaded from it, plus or
+//! minus a constant, with nothing stored to it in between. A multiplication
+//! by a constant of what was just loaded from it then copies a temporary,
+//! which is set to the variable times the constant before the loop, and
+//! moved along after each of those stores. The load it no longer needs is
+//! left to `dce`.
+
+use crate::{
+    ast::BinOp,
+    cfg::{BlockId, Cfg, Loop},
+    ir::{Fun, Inst, Mem, Operand, Program, Temp, Ty},
+    licm::{escaping_slots, put_before_loop},
+};
+
+/// Reduces the multiplications of induction variables in the loops of the
+/// functions of `program`, returning whether anything changed.
+pub(crate) fn strength_program(program: &mut Program) -> bool {
+    let mut changed = false;
+    for fun in &mut program.funs {
+        changed |= strength_fun(fun);
+    }
+    changed
+}
+
+/// Reduces the multiplications of induction variables in the loops of
+/// `fun`, returning whether anything changed.
+pub(crate) fn strength_fun(fun: &mut Fun) -> bool {
+    let mut changed = false;
+    // A variable and a constant at a time, as each changes the code.
+    while reduce_in_a_loop(fun) {
+        changed = true;
+    }
+    changed
+}
+
+/// A multiplication of an induction variable by a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Mul {
+    /// Where it is.
+    index: usize,
+    slot: usize,
+    by: i32,
+}
+
+/// Reduces the multiplications of an induction variable by a constant in
+/// the innermost loop of `fun` that has some, returning whether there was
+/// one.
+fn reduce_in_a_loop(fun: &mut Fun) -> bool {
+    let cfg = Cfg::new(fun);
+    let mut loops = cfg.loops();
+    loops.sort_by_key(|lp| lp.blocks.len());
+    let escapes = escaping_slots(fun);
+
+    for lp in &loops {
+        let insts: Vec<usize> = lp
+            .blocks
+            .iter()
+            .flat_map(|block| cfg.blocks[block.0].insts.clone())
+            .collect();
+
+        // How much each store to each slot moves it by, or `None` if one of
+        // them doesn't just move it.
+        let mut steps: Vec<Option<Vec<(usize, i32)>>> =
+            vec![Some(Vec::new()); fun.slots.len()];
+        for &index in &insts {
+            if let Inst::Store {
+                addr: Operand::Slot(slot),
+                ..
+            } = fun.code[index]
+            {
+                let step = step(fun, &cfg, index);
+                match (&mut steps[slot], step) {
+                    (Some(steps), Some(step)) => steps.push((index, step)),
+                    (steps, _) => *steps = None,
+                }
+            }
+        }
+        let is_induction = |slot: usize| {
+            !escapes[slot]
+                && steps[slot].as_ref().is_some_and(|steps| !steps.is_empty())
+        };
+
+        let muls: Vec<Mul> = insts
+            .iter()
+            .filter_map(|&index| mul(fun, &cfg, index))
+            .filter(|mul| is_induction(mul.slot))
+            .collect();
+        let Some(&first) = muls.first() else {
+            continue;
+        };
+        let muls: Vec<usize> = muls
+            .iter()
+            .filter(|mul| (mul.slot, mul.by) == (first.slot, first.by))
+            .map(|mul| mul.index)
+            .collect();
+        let steps = steps[first.slot].take().expect("an induction variable");
+        reduce(fun, &cfg, lp, first, &muls, &steps);
+        return true;
+    }
+    false
+}
+
+/// Whether the instruction at `index` of `fun`, whose control-flow graph is
+/// `cfg`, multiplies what was just loaded from a slot by a constant, and if
+/// so, which slot and by how much.
+fn mul(fun: &Fun, cfg: &Cfg, index: usize) -> Option<Mul> {
+    let Inst::Binary {
+        op: BinOp::Mul,
+        lhs,
+        rhs,
+        ..
+    } = fun.code[index]
+    else {
+        return None;
+    };
+    let (temp, by) = match (lhs, rhs) {
+        (Operand::Temp(temp), Operand::Const(by))
+        | (Operand::Const(by), Operand::Temp(temp)) => (temp, by as i32),
+        _ => return None,
+    };
+    let slot = loaded_before(fun, cfg, index, temp)?;
+    Some(Mul { index, slot, by })
+}
+
+/// Whether the instruction at `index` of `fun`, whose control-flow graph is
+/// `cfg`, stores to a slot what was just loaded from it plus or minus a
+/// constant, and if so, how much it adds.
+fn step(fun: &Fun, cfg: &Cfg, index: usize) -> Option<i32> {
+    let Inst::Store {
+        mem: Mem::I32,
+        addr: Operand::Slot(slot),
+        value: Operand::Temp(value),
+    } = fun.code[index]
+    else {
+        return None;
+    };
+    let def = def_before(fun, cfg, index, value)?;
+    let (op, loaded, by) = match fun.code[def] {
+        Inst::Binary {
+            op: op @ (BinOp::Add | BinOp::Sub),
+            lhs: Operand::Temp(loaded),
+            rhs: Operand::Const(by),
+            ..
+        } => (op, loaded, by as i32),
+        Inst::Binary {
+            op: BinOp::Add,
+            lhs: Operand::Const(by),
+            rhs: Operand::Temp(loaded),
+            ..
+        } => (BinOp::Add, loaded, by as i32),
+        _ => return None,
+    };
+    if loaded_before(fun, cfg, index, loaded) != Some(slot) {
+        return None;
+    }
+    match op {
+        BinOp::Sub => Some(by.wrapping_neg()),
+        _ => Some(by),
+    }
+}
+
+/// The slot `temp` is loaded from before the instruction at `index` of
+/// `fun`, whose control-flow graph is `cfg`, if it's an `int` loaded in the
+/// same block, with nothing stored to the slot since.
+fn loaded_before(
+    fun: &Fun,
+    cfg: &Cfg,
+    index: usize,
+    temp: Temp,
+) -> Option<usize> {
+    let def = def_before(fun, cfg, index, temp)?;
+    let Inst::Load {
+        mem: Mem::I32,
+        addr: Operand::Slot(slot),
+        ..
+    } = fun.code[def]
+    else {
+        return None;
+    };
+    let stored = fun.code[def + 1..index].iter().any(|inst| {
+        matches!(inst, Inst::Store { addr: Operand::Slot(to), .. } if *to == slot)
+    });
+    (!stored).then_some(slot)
+}
+
+/// Where `temp` is last assigned before the instruction at `index` of
+/// `fun`, whose control-flow graph is `cfg`, if it's in the same block.
+fn def_before(fun: &Fun, cfg: &Cfg, index: usize, temp: Temp) -> Option<usize> {
+    let block: BlockId = cfg.block_of(index);
+    let start = cfg.blocks[block.0].insts.start;
+    (start..index)
+        .rev()
+        .find(|&def| fun.code[def].dst() == Some(temp))
+}
+
+/// Replaces the multiplications of `fun` at `muls`, all of the variable of
+/// `mul` by the same constant, with copies of a new temporary that follows
+/// the stores moving the variable by `steps`.
+fn reduce(
+    fun: &mut Fun,
+    cfg: &Cfg,
+    lp: &Loop,
+    mul: Mul,
+    muls: &[usize],
+    steps: &[(usize, i32)],
+) {
+    let loaded = Temp(fun.temps.len());
+    let product = Temp(fun.temps.len() + 1);
+    fun.temps.extend([Ty::I32, Ty::I32]);
+
+    for &index in muls {
+        let dst = fun.code[index].dst().expect("a multiplication");
+        fun.code[index] = Inst::Copy {
+            dst,
+            src: Operand::Temp(product),
+        };
+    }
+    let header = cfg.blocks[lp.header.0].label;
+    // From the last, so each leaves where the ones before it are.
+    for &(index, step) in steps.iter().rev() {
+        fun.code.insert(
+            index + 1,
+            Inst::Binary {
+                dst: product,
+                op: BinOp::Add,
+                lhs: Operand::Temp(product),
+                rhs: Operand::Const(i64::from(step.wrapping_mul(mul.by))),
+            },
+        );
+    }
+
+    let cfg = Cfg::new(fun);
+    let lp = cfg
+        .loops()
+        .into_iter()
+        .find(|lp| cfg.blocks[lp.header.0].label == header)
+        .expect("the loop is still there");
+    let init = vec![
+        Inst::Load {
+            dst: loaded,
+            mem: Mem::I32,
+            addr: Operand::Slot(mul.slot),
+        },
+        Inst::Binary {
+            dst: product,
+            op: BinOp::Mul,
+            lhs: Operand::Temp(loaded),
+            rhs: Operand::Const(i64::from(mul.by)),
+        },
+    ];
+    assert!(
+        put_before_loop(fun, &cfg, &lp, init, &[]),
+        "a loop found from the entry is entered"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ir::{dump_ir, Program},
+        passes::{OptLevel, PassManager},
+        regalloc::RegAlloc,
+        test_support::{accept_source, fun_code, lower_source},
+        x86_64::emit_asm,
+    };
+
+    fn optimize(source: &str, level: OptLevel) -> Program {
+        let mut program = lower_source(source);
+        PassManager::for_level(level).run(&mut program, &mut String::new());
+        program
+    }
+
+    #[test]
+    fn indices_into_arrays_are_added_to_rather_than_multiplied() {
+        let code = dump_ir(&optimize(&accept_source("sort.cm"), OptLevel::O2));
+
+        assert_eq!(
+            fun_code(&code, "minloc"),
+            "fun @minloc(%0: ptr, %1: i32, %2: i32) -> i32 {\n\
+             \x20 slot $0 a: 8, align 8\n\
+             \x20 slot $1 low: 4, align 4\n\
+             \x20 slot $2 high: 4, align 4\n\
+             \x20 slot $3 i: 4, align 4\n\
+             \x20 slot $4 x: 4, align 4\n\
+             \x20 slot $5 k: 4, align 4\n\
+             \x20 store ptr $0, %0\n\
+             \x20 store i32 $1, %1\n\
+             \x20 store i32 $2, %2\n\
+             \x20 %3: i32 = load i32 $1\n\
+             \x20 store i32 $5, %3\n\
+             \x20 %4: ptr = load ptr $0\n\
+             \x20 %5: i32 = load i32 $1\n\
+             \x20 %6: i32 = mul %5, 4\n\
+             \x20 %7: ptr = offset %4, %6\n\
+             \x20 %8: i32 = load i32 %7\n\
+             \x20 store i32 $4, %8\n\
+             \x20 %9: i32 = load i32 $1\n\
+             \x20 %10: i32 = add %9, 1\n\
+             \x20 store i32 $3, %10\n\
+             \x20 %12: i32 = load i32 $2\n\
+             \x20 %14: ptr = load ptr $0\n\
+             \x20 %21: ptr = load ptr $0\n\
+             \x20 %30: i32 = load i32 $3\n\
+             \x20 %31: i32 = mul %30, 4\n\
+             \x20 jump L0\n\
+             L0:\n\
+             \x20 %11: i32 = load i32 $3\n\
+             \x20 %13: i32 = lt %11, %12\n\
+             \x20 branch %13, L1, L2\n\
+             L1:\n\
+             \x20 %16: i32 = copy %31\n\
+             \x20 %17: ptr = offset %14, %16\n\
+             \x20 %18: i32 = load i32 %17\n\
+             \x20 %19: i32 = load i32 $4\n\
+             \x20 %20: i32 = lt %18, %19\n\
+             \x20 branch %20, L3, L4\n\
+             L3:\n\
+             \x20 %23: i32 = copy %31\n\
+             \x20 %24: ptr = offset %21, %23\n\
+             \x20 %25: i32 = load i32 %24\n\
+             \x20 store i32 $4, %25\n\
+             \x20 %26: i32 = load i32 $3\n\
+             \x20 store i32 $5, %26\n\
+             \x20 jump L4\n\
+             L4:\n\
+             \x20 %27: i32 = load i32 $3\n\
+             \x20 %28: i32 = add %27, 1\n\
+             \x20 store i32 $3, %28\n\
+             \x20 %31: i32 = add %31, 4\n\
+             \x20 jump L0\n\
+             L2:\n\
+             \x20 %29: i32 = load i32 $5\n\
+             \x20 ret %29\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn only_variables_moved_by_constants_are_reduced() {
+        // `j` is doubled, `k` may be stored to through `p`, and `b` wraps
+        // at 8 bits. `i` goes up, and then down.
+        let code = dump_ir(&optimize(
+            "int v[100];\n\
+             char c[100];\n\
+             void f(int n) {\n\
+             \x20   int i; int j; int *p; int k; char b;\n\
+             \x20   i = 0; j = 1; k = 0; b = 0;\n\
+             \x20   p = &k;\n\
+             \x20   while (i < n) {\n\
+             \x20       v[i] = v[j] + v[k] + c[b];\n\
+             \x20       i = i + 1;\n\
+             \x20       j = j * 2;\n\
+             \x20       *p = k + 1;\n\
+             \x20       k = k + 1;\n\
+             \x20       b = b + 1;\n\
+             \x20   }\n\
+             \x20   i = 10;\n\
+             \x20   do { i = i - 2; v[i] = i; } while (i > 0);\n\
+             }",
+            OptLevel::O2,
+        ));
+
+        assert_eq!(
+            fun_code(&code, "f"),
+            "fun @f(%0: i32) {\n\
+             \x20 slot $0 n: 4, align 4\n\
+             \x20 slot $1 i: 4, align 4\n\
+             \x20 slot $2 j: 4, align 4\n\
+             \x20 slot $3 p: 8, align 8\n\
+             \x20 slot $4 k: 4, align 4\n\
+             \x20 slot $5 b: 1, align 1\n\
+             \x20 store i32 $0, %0\n\
+             \x20 store i32 $1, 0\n\
+             \x20 store i32 $2, 1\n\
+             \x20 store i32 $4, 0\n\
+             \x20 store i8 $5, 0\n\
+             \x20 store ptr $3, $4\n\
+             \x20 %2: i32 = load i32 $0\n\
+             \x20 %25: ptr = load ptr $3\n\
+             \x20 %41: i32 = copy 0\n\
+             \x20 jump L0\n\
+             L0:\n\
+             \x20 %1: i32 = load i32 $1\n\
+             \x20 %3: i32 = lt %1, %2\n\
+             \x20 branch %3, L1, L2\n\
+             L1:\n\
+             \x20 %5: i32 = copy %41\n\
+             \x20 %6: ptr = offset @v, %5\n\
+             \x20 %7: i32 = load i32 $2\n\
+             \x20 %8: i32 = mul %7, 4\n\
+             \x20 %9: ptr = offset @v, %8\n\
+             \x20 %10: i32 = load i32 %9\n\
+             \x20 %11: i32 = load i32 $4\n\
+             \x20 %12: i32 = mul %11, 4\n\
+             \x20 %13: ptr = offset @v, %12\n\
+             \x20 %14: i32 = load i32 %13\n\
+             \x20 %15: i32 = add %10, %14\n\
+             \x20 %16: i32 = load i8 $5\n\
+             \x20 %17: i32 = copy %16\n\
+             \x20 %18: ptr = offset @c, %17\n\
+             \x20 %19: i32 = load i8 %18\n\
+             \x20 %20: i32 = add %15, %19\n\
+             \x20 store i32 %6, %20\n\
+             \x20 %21: i32 = load i32 $1\n\
+             \x20 %22: i32 = add %21, 1\n\
+             \x20 store i32 $1, %22\n\
+             \x20 %41: i32 = add %41, 4\n\
+             \x20 %23: i32 = load i32 $2\n\
+             \x20 %24: i32 = mul %23, 2\n\
+             \x20 store i32 $2, %24\n\
+             \x20 %26: i32 = load i32 $4\n\
+             \x20 %27: i32 = add %26, 1\n\
+             \x20 store i32 %25, %27\n\
+             \x20 %28: i32 = load i32 $4\n\
+             \x20 %29: i32 = add %28, 1\n\
+             \x20 store i32 $4, %29\n\
+             \x20 %30: i32 = load i8 $5\n\
+             \x20 %31: i32 = add %30, 1\n\
+             \x20 store i8 $5, %31\n\
+             \x20 jump L0\n\
+             L2:\n\
+             \x20 store i32 $1, 10\n\
+             \x20 %43: i32 = copy 40\n\
+             \x20 jump L3\n\
+             L3:\n\
+             \x20 %32: i32 = load i32 $1\n\
+             \x20 %33: i32 = sub %32, 2\n\
+             \x20 store i32 $1, %33\n\
+             \x20 %43: i32 = add %43, -8\n\
+             \x20 %35: i32 = copy %43\n\
+             \x20 %36: ptr = offset @v, %35\n\
+             \x20 %37: i32 = load i32 $1\n\
+             \x20 store i32 %36, %37\n\
+             \x20 jump L4\n\
+             L4:\n\
+             \x20 %38: i32 = load i32 $1\n\
+             \x20 %39: i32 = gt %38, 0\n\
+             \x20 branch %39, L3, L5\n\
+             L5:\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn loops_over_arrays_no_longer_multiply() {
+        // The code of the loop of `minloc`, from its condition to the jump
+        // back to it.
+        let loop_code = |level| {
+            let asm = emit_asm(
+                &optimize(&accept_source("sort.cm"), level),
+                RegAlloc::Linear,
+            );
+            let start = asm.find(".Lminloc_0:\n").unwrap();
+            let end = start + asm[start..].find("jmp .Lminloc_0\n").unwrap();
+            asm[start..end].to_string()
+        };
+
+        assert!(loop_code(OptLevel::O1).contains("imull"));
+        assert!(!loop_code(OptLevel::O2).contains("imull"));
+    }
+}
@@ -0,0 +1,380 @@
+//! The symbol table of a program: every scope, from the one of the builtins
+//! to the innermost blocks, with the names declared in it. Shown with
+//! `--emit=symbols`, each scope under the one it's in, after its names:
+//!
+//! ```text
+//! scope builtins
+//!   function input: int (void)
+//!   function output: void (int)
+//!   scope file
+//!     global g: int [10] @ 1:5-1:6
+//!     function main: void (void) @ 2:6-2:10
+//!     scope function main @ 2:1-5:2
+//!       local x: int @ 3:9-3:10
+//! ```
+//!
+//! A name is looked up in the scope it's used in, and then in the ones it's
+//! in, outwards. The parameters of a function are in the same scope as the
+//! variables declared at the start of its body.
+
+use crate::{
+    ast::{BlockId, FunDecl, Nodes, Program, StructDecl, VarDecl},
+    emitter::SpanLocs,
+    sema::Builtin,
+    source_map::{SourceFile, Span},
+    types::Type,
+    visit::{walk_stmt, Visitor},
+};
+use std::fmt::{self, Write};
+
+/// The scopes of a program, each after the one it's in.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SymbolTable {
+    pub(crate) scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Scope {
+    pub(crate) kind: ScopeKind,
+    /// The index of the scope it's in, or `None` for the outermost one.
+    pub(crate) parent: Option<usize>,
+    /// The name of the function it's in, if any.
+    pub(crate) fun: Option<String>,
+    /// The declaration or block it's of, or `None` for the builtins and the
+    /// file, which are the whole program.
+    pub(crate) span: Option<Span>,
+    /// In the order they're declared.
+    pub(crate) symbols: Vec<Symbol>,
+}
+
+/// What a scope is of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScopeKind {
+    /// The functions every program can call without declaring them.
+    Builtins,
+    File,
+    /// The fields of the struct with this name.
+    Struct(String),
+    /// The parameters of a function and the variables declared at the start
+    /// of its body.
+    Function,
+    /// A block in the body of a function.
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Symbol {
+    pub(crate) name: String,
+    pub(crate) kind: SymbolKind,
+    /// Written as in C without the name, e.g. `int [10]`, or `int (int *)`
+    /// for a function taking an `int *` and returning an `int`.
+    pub(crate) ty: String,
+    /// The name in its declaration, or `None` for a builtin.
+    pub(crate) span: Option<Span>,
+}
+
+/// What a name is declared as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Global,
+    Function,
+    Param,
+    Local,
+    Struct,
+    Field,
+}
+
+impl SymbolKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            SymbolKind::Global => "global",
+            SymbolKind::Function => "function",
+            SymbolKind::Param => "param",
+            SymbolKind::Local => "local",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Field => "field",
+        }
+    }
+}
+
+/// The scopes of `program`. A name declared twice in a scope, which sema
+/// reports, is in it twice.
+pub(crate) fn symbol_table(program: &Program) -> SymbolTable {
+    let builtins = Builtin::ALL
+        .iter()
+        .map(|&builtin| Symbol {
+            name: builtin.name().to_string(),
+            kind: SymbolKind::Function,
+            ty: signature(&builtin.return_type(), &builtin.param_types()),
+            span: None,
+        })
+        .collect();
+    let mut builder = Builder {
+        table: SymbolTable {
+            scopes: vec![Scope {
+                kind: ScopeKind::Builtins,
+                parent: None,
+                fun: None,
+                span: None,
+                symbols: builtins,
+            }],
+        },
+        current: 0,
+        fun: None,
+    };
+    builder.enter(ScopeKind::File, None);
+    builder.visit_program(program);
+    builder.table
+}
+
+/// The type of a function returning `return_ty` and taking `params`, as in
+/// C, e.g. `int (int *)`.
+fn signature(return_ty: &Type, params: &[Type]) -> String {
+    let params: Vec<_> = params.iter().map(Type::to_string).collect();
+    if params.is_empty() {
+        format!("{} (void)", return_ty)
+    } else {
+        format!("{} ({})", return_ty, params.join(", "))
+    }
+}
+
+struct Builder {
+    table: SymbolTable,
+    /// The index of the scope being built.
+    current: usize,
+    /// The name of the function being walked, if any.
+    fun: Option<String>,
+}
+
+impl Builder {
+    /// Starts a scope in the current one, making it the current one.
+    fn enter(&mut self, kind: ScopeKind, span: Option<Span>) {
+        self.table.scopes.push(Scope {
+            kind,
+            parent: Some(self.current),
+            fun: self.fun.clone(),
+            span,
+            symbols: Vec::new(),
+        });
+        self.current = self.table.scopes.len() - 1;
+    }
+
+    /// Ends the current scope, making the one it's in the current one.
+    fn exit(&mut self) {
+        self.current = self.table.scopes[self.current]
+            .parent
+            .expect("exited the outermost scope");
+    }
+
+    fn declare(
+        &mut self,
+        name: &str,
+        kind: SymbolKind,
+        ty: String,
+        span: Span,
+    ) {
+        self.table.scopes[self.current].symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            ty,
+            span: Some(span),
+        });
+    }
+
+    fn declare_var(
+        &mut self,
+        nodes: &Nodes,
+        var_decl: &VarDecl,
+        kind: SymbolKind,
+    ) {
+        let ty = Type::of_var_decl(nodes, var_decl).to_string();
+        self.declare(&var_decl.name.name, kind, ty, var_decl.name.span);
+    }
+
+    /// Declares the variables at the start of `block` in the current scope,
+    /// and walks its statements.
+    fn block(&mut self, nodes: &Nodes, block: BlockId) {
+        for var_decl in &nodes[block].decls {
+            self.declare_var(nodes, var_decl, SymbolKind::Local);
+        }
+        for &stmt in &nodes[block].stmts {
+            walk_stmt(self, nodes, stmt);
+        }
+    }
+}
+
+impl Visitor for Builder {
+    fn visit_var_decl(&mut self, nodes: &Nodes, var_decl: &VarDecl) {
+        // Only reached for the variables at file scope.
+        self.declare_var(nodes, var_decl, SymbolKind::Global);
+    }
+
+    fn visit_struct_decl(&mut self, nodes: &Nodes, struct_decl: &StructDecl) {
+        let name = &struct_decl.name;
+        self.declare(
+            &name.name,
+            SymbolKind::Struct,
+            format!("struct {}", name.name),
+            name.span,
+        );
+        self.enter(
+            ScopeKind::Struct(name.name.clone()),
+            Some(struct_decl.span),
+        );
+        for field in &struct_decl.fields {
+            self.declare_var(nodes, field, SymbolKind::Field);
+        }
+        self.exit();
+    }
+
+    fn visit_fun_decl(&mut self, nodes: &Nodes, fun_decl: &FunDecl) {
+        let params: Vec<_> = fun_decl
+            .params
+            .iter()
+            .map(|param| Type::of_param(nodes, param))
+            .collect();
+        let ty = signature(&Type::of_return(fun_decl), &params);
+        let name = &fun_decl.name;
+        self.declare(&name.name, SymbolKind::Function, ty, name.span);
+
+        self.fun = Some(name.name.clone());
+        self.enter(ScopeKind::Function, Some(fun_decl.span));
+        for (param, ty) in fun_decl.params.iter().zip(params) {
+            let ty = ty.to_string();
+            self.declare(
+                &param.name.name,
+                SymbolKind::Param,
+                ty,
+                param.name.span,
+            );
+        }
+        self.block(nodes, fun_decl.body);
+        self.exit();
+        self.fun = None;
+    }
+
+    fn visit_block(&mut self, nodes: &Nodes, block: BlockId) {
+        self.enter(ScopeKind::Block, Some(nodes[block].span));
+        self.block(nodes, block);
+        self.exit();
+    }
+}
+
+impl fmt::Display for ScopeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScopeKind::Builtins => f.write_str("builtins"),
+            ScopeKind::File => f.write_str("file"),
+            ScopeKind::Struct(name) => write!(f, "struct {}", name),
+            ScopeKind::Function => f.write_str("function"),
+            ScopeKind::Block => f.write_str("block"),
+        }
+    }
+}
+
+/// Dumps `table`, of the program parsed from `source_file`.
+pub(crate) fn dump_symbols(
+    table: &SymbolTable,
+    source_file: &SourceFile,
+) -> String {
+    let location = |span: Option<Span>| match span {
+        Some(span) => {
+            let locs = SpanLocs::resolve(source_file, span);
+            format!(
+                " @ {}:{}-{}:{}",
+                locs.line_start,
+                locs.column_start,
+                locs.line_end,
+                locs.column_end
+            )
+        }
+        None => String::new(),
+    };
+
+    let mut depths = Vec::with_capacity(table.scopes.len());
+    let mut out = String::new();
+    for scope in &table.scopes {
+        let depth = scope.parent.map_or(0, |parent| depths[parent] + 1);
+        depths.push(depth);
+        let owner = match (&scope.kind, &scope.fun) {
+            (ScopeKind::Function, Some(fun)) => format!(" {}", fun),
+            (_, Some(fun)) => format!(" in {}", fun),
+            (_, None) => String::new(),
+        };
+        let _ = writeln!(
+            out,
+            "{:indent$}scope {}{}{}",
+            "",
+            scope.kind,
+            owner,
+            location(scope.span),
+            indent = depth * 2,
+        );
+        for symbol in &scope.symbols {
+            let _ = writeln!(
+                out,
+                "{:indent$}{} {}: {}{}",
+                "",
+                symbol.kind.as_str(),
+                symbol.name,
+                symbol.ty,
+                location(symbol.span),
+                indent = (depth + 1) * 2,
+            );
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dump_symbols, symbol_table};
+    use crate::{
+        extensions::Extensions, parser::ParseResult, test_support::parse_source,
+    };
+
+    fn dump(source: &str) -> String {
+        let (
+            source_file,
+            ParseResult {
+                program, diag_bag, ..
+            },
+        ) = parse_source(source, Extensions::all());
+        assert!(!diag_bag.has_errors());
+        dump_symbols(&symbol_table(&program), &source_file)
+    }
+
+    #[test]
+    fn scopes_are_shown_inside_the_ones_they_are_in() {
+        let source = "int g[10];\n\
+                      struct p { int x; char *s; };\n\
+                      int f(int a[], struct p *q) {\n\
+                      \x20   int b;\n\
+                      \x20   while (b) { int c; c = b; }\n\
+                      \x20   return 0;\n\
+                      }\n\
+                      void main(void) { }\n";
+
+        assert_eq!(
+            dump(source),
+            "scope builtins\n\
+             \x20 function input: int (void)\n\
+             \x20 function output: void (int)\n\
+             \x20 scope file\n\
+             \x20   global g: int [10] @ 1:5-1:6\n\
+             \x20   struct p: struct p @ 2:8-2:9\n\
+             \x20   function f: int (int *, struct p *) @ 3:5-3:6\n\
+             \x20   function main: void (void) @ 8:6-8:10\n\
+             \x20   scope struct p @ 2:1-2:30\n\
+             \x20     field x: int @ 2:16-2:17\n\
+             \x20     field s: char * @ 2:25-2:26\n\
+             \x20   scope function f @ 3:1-7:2\n\
+             \x20     param a: int * @ 3:11-3:12\n\
+             \x20     param q: struct p * @ 3:26-3:27\n\
+             \x20     local b: int @ 4:9-4:10\n\
+             \x20     scope block in f @ 5:15-5:32\n\
+             \x20       local c: int @ 5:21-5:22\n\
+             \x20   scope function main @ 8:1-8:20\n"
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Tail call optimization: turning the calls a function makes to itself
+//! right before it returns into jumps back to its start, so a recursion
+//! like that of `gcd` is a loop, and runs in one frame however deep it
+//! goes.
+//!
+//! A call is a tail call if it's to the function it's in, and what's after
+//! it is a `ret` of what it returns, maybe after jumping there. It's
+//! replaced with copies of its arguments to the temporaries the arguments
+//! of the function are in, and a jump to a new label at the start. Where
+//! an argument is another argument of the function, they're all copied to
+//! new temporaries first, as they're assigned at once.
+//!
+//! A function the address of one of whose slots is used other than to load
+//! and store (see `licm`) is left as it is, as each call of it has slots of
+//! its own, and a pointer to one of them might be passed to the next one.
+
+use crate::{
+    ir::{Callee, Fun, Inst, Label, Operand, Program, Temp},
+    licm::escaping_slots,
+};
+
+/// Turns the tail calls of the functions of `program` into jumps, returning
+/// whether anything changed.
+pub(crate) fn tailcall_program(program: &mut Program) -> bool {
+    let mut changed = false;
+    for (index, fun) in program.funs.iter_mut().enumerate() {
+        changed |= tailcall_fun(fun, index);
+    }
+    changed
+}
+
+/// Turns the tail calls of `fun`, the function at `index` of its program,
+/// into jumps, returning whether anything changed.
+pub(crate) fn tailcall_fun(fun: &mut Fun, index: usize) -> bool {
+    if escaping_slots(fun).contains(&true) {
+        return false;
+    }
+    let calls: Vec<usize> = (0..fun.code.len())
+        .filter(|&call| is_tail_call(fun, index, call))
+        .collect();
+    if calls.is_empty() {
+        return false;
+    }
+
+    let start = Label(fun.labels);
+    fun.labels += 1;
+    // From the last, so the ones before are where they were.
+    for &call in calls.iter().rev() {
+        let Inst::Call { ref args, .. } = fun.code[call] else {
+            unreachable!("{:?} isn't a call", fun.code[call]);
+        };
+        let args = args.clone();
+        let mut code = Vec::new();
+        let reads_params =
+            args.iter().enumerate().any(|(param, arg)| match *arg {
+                Operand::Temp(temp) => temp.0 < fun.params && temp.0 != param,
+                _ => false,
+            });
+        let args = if reads_params {
+            args.into_iter()
+                .enumerate()
+                .map(|(param, arg)| {
+                    let temp = Temp(fun.temps.len());
+                    fun.temps.push(fun.temps[param]);
+                    code.push(Inst::Copy {
+                        dst: temp,
+                        src: arg,
+                    });
+                    Operand::Temp(temp)
+                })
+                .collect()
+        } else {
+            args
+        };
+        code.extend(args.into_iter().enumerate().map(|(param, arg)| {
+            Inst::Copy {
+                dst: Temp(param),
+                src: arg,
+            }
+        }));
+        code.push(Inst::Jump(start));
+        // The call and the terminator after it.
+        fun.code.splice(call..call + 2, code);
+    }
+    fun.code
+        .splice(0..0, [Inst::Jump(start), Inst::Label(start)]);
+    true
+}
+
+/// Whether the instruction at `call` of `fun`, the function at `index` of
+/// its program, is a call to itself after which it returns what the call
+/// does.
+fn is_tail_call(fun: &Fun, index: usize, call: usize) -> bool {
+    let Inst::Call {
+        dst,
+        callee: Callee::Fun(callee),
+        ..
+    } = fun.code[call]
+    else {
+        return false;
+    };
+    if callee != index {
+        return false;
+    }
+    let mut next = call + 1;
+    // Jumps are followed, but only as many times as there are instructions,
+    // as they might go round in a loop.
+    for _ in 0..fun.code.len() {
+        match fun.code[next] {
+            Inst::Jump(label) => {
+                next = fun
+                    .code
+                    .iter()
+                    .position(|inst| *inst == Inst::Label(label))
+                    .expect("a label that's jumped to")
+                    + 1;
+            }
+            Inst::Return(value) => {
+                return value == dst.map(Operand::Temp);
+            }
+            Inst::Line(_) => next += 1,
+            _ => return false,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tailcall_fun;
+    use crate::{
+        ast::BinOp,
+        bytecode::{compile, run},
+        errors::Diag,
+        interp::Trap,
+        ir::{dump_ir, Callee, Fun, Inst, Label, Operand, Program, Temp, Ty},
+        passes::{OptLevel, Pass, PassManager},
+        test_support::{ir_after, lower_source},
+    };
+
+    #[test]
+    fn calls_to_itself_before_returning_jump_back_to_the_start() {
+        let code = ir_after(
+            "int gcd(int a, int b) {\n\
+             \x20   if (b == 0) return a;\n\
+             \x20   return gcd(b, a - a / b * b);\n\
+             }\n\
+             void count(int n) { if (n > 0) { output(n); count(n - 1); } }\n\
+             int fact(int n) { if (n < 2) return 1; return n * fact(n - 1); }\n\
+             void main(void) { output(gcd(input(), input())); count(3); output(fact(5)); }",
+            &[Pass::TailCall],
+        );
+
+        assert_eq!(
+            code,
+            "fun @gcd(%0: i32, %1: i32) -> i32 {\n\
+             \x20 slot $0 a: 4, align 4\n\
+             \x20 slot $1 b: 4, align 4\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 store i32 $0, %0\n\
+             \x20 store i32 $1, %1\n\
+             \x20 %2: i32 = load i32 $1\n\
+             \x20 %3: i32 = eq %2, 0\n\
+             \x20 branch %3, L0, L1\n\
+             L0:\n\
+             \x20 %4: i32 = load i32 $0\n\
+             \x20 ret %4\n\
+             L1:\n\
+             \x20 %5: i32 = load i32 $1\n\
+             \x20 %6: i32 = load i32 $0\n\
+             \x20 %7: i32 = load i32 $0\n\
+             \x20 %8: i32 = load i32 $1\n\
+             \x20 %9: i32 = div %7, %8\n\
+             \x20 %10: i32 = load i32 $1\n\
+             \x20 %11: i32 = mul %9, %10\n\
+             \x20 %12: i32 = sub %6, %11\n\
+             \x20 %0: i32 = copy %5\n\
+             \x20 %1: i32 = copy %12\n\
+             \x20 jump L2\n\
+             }\n\
+             \n\
+             fun @count(%0: i32) {\n\
+             \x20 slot $0 n: 4, align 4\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 store i32 $0, %0\n\
+             \x20 %1: i32 = load i32 $0\n\
+             \x20 %2: i32 = gt %1, 0\n\
+             \x20 branch %2, L0, L1\n\
+             L0:\n\
+             \x20 %3: i32 = load i32 $0\n\
+             \x20 call @output(%3)\n\
+             \x20 %4: i32 = load i32 $0\n\
+             \x20 %5: i32 = sub %4, 1\n\
+             \x20 %0: i32 = copy %5\n\
+             \x20 jump L2\n\
+             L1:\n\
+             \x20 ret\n\
+             }\n\
+             \n\
+             fun @fact(%0: i32) -> i32 {\n\
+             \x20 slot $0 n: 4, align 4\n\
+             \x20 store i32 $0, %0\n\
+             \x20 %1: i32 = load i32 $0\n\
+             \x20 %2: i32 = lt %1, 2\n\
+             \x20 branch %2, L0, L1\n\
+             L0:\n\
+             \x20 ret 1\n\
+             L1:\n\
+             \x20 %3: i32 = load i32 $0\n\
+             \x20 %4: i32 = load i32 $0\n\
+             \x20 %5: i32 = sub %4, 1\n\
+             \x20 %6: i32 = call @fact(%5)\n\
+             \x20 %7: i32 = mul %3, %6\n\
+             \x20 ret %7\n\
+             }\n\
+             \n\
+             fun @main() {\n\
+             \x20 %0: i32 = call @input()\n\
+             \x20 %1: i32 = call @input()\n\
+             \x20 %2: i32 = call @gcd(%0, %1)\n\
+             \x20 call @output(%2)\n\
+             \x20 call @count(3)\n\
+             \x20 %3: i32 = call @fact(5)\n\
+             \x20 call @output(%3)\n\
+             \x20 ret\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn arguments_are_assigned_at_once() {
+        // `f(a, b)` returns `f(b, a - 1)` unless `a` is 0.
+        let (a, b, zero, less) = (Temp(0), Temp(1), Temp(2), Temp(3));
+        let mut fun = Fun {
+            name: "f".into(),
+            params: 2,
+            ret: Some(Ty::I32),
+            slots: Vec::new(),
+            temps: vec![Ty::I32; 5],
+            labels: 2,
+            code: vec![
+                Inst::Binary {
+                    dst: zero,
+                    op: BinOp::Equal,
+                    lhs: Operand::Temp(a),
+                    rhs: Operand::Const(0),
+                },
+                Inst::Branch {
+                    cond: Operand::Temp(zero),
+                    then_label: Label(0),
+                    else_label: Label(1),
+                },
+                Inst::Label(Label(0)),
+                Inst::Return(Some(Operand::Temp(b))),
+                Inst::Label(Label(1)),
+                Inst::Binary {
+                    dst: less,
+                    op: BinOp::Sub,
+                    lhs: Operand::Temp(a),
+                    rhs: Operand::Const(1),
+                },
+                Inst::Call {
+                    dst: Some(Temp(4)),
+                    callee: Callee::Fun(0),
+                    args: vec![Operand::Temp(b), Operand::Temp(less)],
+                },
+                Inst::Return(Some(Operand::Temp(Temp(4)))),
+            ],
+        };
+
+        assert!(tailcall_fun(&mut fun, 0));
+
+        let program = Program {
+            globals: Vec::new(),
+            strings: Vec::new(),
+            funs: vec![fun],
+            file: None,
+        };
+        assert_eq!(
+            dump_ir(&program),
+            "fun @f(%0: i32, %1: i32) -> i32 {\n\
+             \x20 jump L2\n\
+             L2:\n\
+             \x20 %2: i32 = eq %0, 0\n\
+             \x20 branch %2, L0, L1\n\
+             L0:\n\
+             \x20 ret %1\n\
+             L1:\n\
+             \x20 %3: i32 = sub %0, 1\n\
+             \x20 %5: i32 = copy %1\n\
+             \x20 %6: i32 = copy %3\n\
+             \x20 %0: i32 = copy %5\n\
+             \x20 %1: i32 = copy %6\n\
+             \x20 jump L2\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn deep_tail_recursion_runs_in_one_frame() {
+        let program = lower_source(
+            "int sum(int n, int acc) { if (n == 0) return acc; return sum(n - 1, acc + n); }\n\
+             void main(void) { output(sum(20000, 0)); }",
+        );
+        let run = |level| {
+            let mut program = program.clone();
+            PassManager::for_level(level).run(&mut program, &mut String::new());
+            let mut output = Vec::new();
+            let result =
+                run(&compile(&program), &mut "".as_bytes(), &mut output);
+            (String::from_utf8(output).unwrap(), result)
+        };
+
+        assert!(matches!(
+            run(OptLevel::O1).1,
+            Err(Diag::BytecodeRuntimeError {
+                trap: Trap::StackOverflow,
+                ..
+            })
+        ));
+        assert_eq!(run(OptLevel::O2), ("200010000\n".to_string(), Ok(())));
+    }
+}
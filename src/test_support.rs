@@ -0,0 +1,654 @@
+//! Checks the diagnostics reported for whole programs against annotations in
+//! the programs themselves.
+//!
+//! An annotation is a `//` comment at the end of the line the diagnostic
+//! points at, naming its severity and, optionally, its code and a part of its
+//! message:
+//!
+//! ```text
+//! int x @; // expect-error: unknown character
+//! int y$;  // expect-error[E0001]
+//! ```
+//!
+//! C- has no `//` comments, so annotations are blanked out with spaces before
+//! compiling, which leaves every position in the program unchanged. Every
+//! diagnostic must match an annotation, and every annotation a diagnostic.
+//!
+//! The programs in `tests/programs` are held to more: the ones in `accept`
+//! can't have any annotation, and the ones in `reject` must have an error
+//! annotation, and a code in every annotation.
+//!
+//! It also checks that no input, however broken, makes the compiler panic:
+//! the programs in `tests/hostile`, every prefix of the programs in
+//! `tests/ui`, and every short string made of troublesome characters must be
+//! compiled, with every extension enabled, and have their diagnostics
+//! rendered in every format without panicking.
+//!
+//! Last, it takes the source of the unit tests of the other modules through
+//! the phases they need before theirs: `parse_source`, `analyze_source`,
+//! `lower_source` and `ir_after`, finds them the programs in
+//! `tests/programs/accept` and the functions in the code of the IR, and runs
+//! what the targets make of a program next to the interpreter.
+
+use crate::{
+    driver::{compile, compile_file},
+    emitter::{new_emitter, ErrorFormat},
+    errors::{DiagBag, Diagnostic, Severity},
+    extensions::Extensions,
+    ice::catch_ice,
+    interp,
+    ir::{self, dump_ir, lower_program},
+    parser::{parse_program, ParseResult},
+    passes::{run_passes, Pass},
+    scanner::scan_words,
+    sema::{self, Analysis, Builtin},
+    source_map::{SourceFile, SourceMap},
+};
+use std::{
+    env, fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+
+const ANNOTATION_PREFIX: &str = "// expect-";
+
+/// A diagnostic a program is expected to produce.
+#[derive(Debug, PartialEq)]
+struct Expectation {
+    /// Line (starting at 1) the diagnostic points at.
+    line: usize,
+    severity: Severity,
+    code: Option<String>,
+    /// A part of the diagnostic's message.
+    message: Option<String>,
+}
+
+impl Expectation {
+    fn matches(&self, line: Option<usize>, diagnostic: &Diagnostic) -> bool {
+        let diag = &diagnostic.diag;
+        line == Some(self.line)
+            && self.severity == diagnostic.severity
+            && self
+                .code
+                .as_deref()
+                .is_none_or(|code| diag.code() == Some(code))
+            && self
+                .message
+                .as_deref()
+                .is_none_or(|message| diag.message().contains(message))
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.severity)?;
+        if let Some(code) = &self.code {
+            write!(f, "[{}]", code)?;
+        }
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn severity_from_name(name: &str) -> Option<Severity> {
+    match name {
+        "fatal" => Some(Severity::Fatal),
+        "error" => Some(Severity::Error),
+        "warning" => Some(Severity::Warning),
+        "note" => Some(Severity::Note),
+        "help" => Some(Severity::Help),
+        _ => None,
+    }
+}
+
+/// Parses an annotation, without its prefix, e.g. `error[E0001]: unknown`.
+fn parse_annotation(line: usize, text: &str) -> Result<Expectation, String> {
+    let (head, message) = match text.split_once(':') {
+        Some((head, message)) => (head, Some(message.trim().to_owned())),
+        None => (text.trim(), None),
+    };
+    let (severity_name, code) = match head.split_once('[') {
+        Some((name, code)) => {
+            let code = code.strip_suffix(']').ok_or_else(|| {
+                format!("line {}: missing `]` after the code", line)
+            })?;
+            (name, Some(code.to_owned()))
+        }
+        None => (head, None),
+    };
+    let severity = severity_from_name(severity_name).ok_or_else(|| {
+        format!("line {}: unknown severity `{}`", line, severity_name)
+    })?;
+
+    Ok(Expectation {
+        line,
+        severity,
+        code,
+        message: message.filter(|message| !message.is_empty()),
+    })
+}
+
+/// Splits an annotated program into the program with its annotations blanked
+/// out and the expectations they describe.
+fn parse_annotations(
+    annotated_source: &str,
+) -> Result<(String, Vec<Expectation>), String> {
+    let mut source = String::with_capacity(annotated_source.len());
+    let mut expectations = Vec::new();
+
+    for (i, line) in annotated_source.split_inclusive('\n').enumerate() {
+        match line.find(ANNOTATION_PREFIX) {
+            Some(start) => {
+                let content = line.trim_end_matches(&['\n', '\r'][..]);
+                let annotation = &content[start + ANNOTATION_PREFIX.len()..];
+                expectations.push(parse_annotation(i + 1, annotation)?);
+
+                source.push_str(&line[..start]);
+                source.push_str(&" ".repeat(content.len() - start));
+                source.push_str(&line[content.len()..]);
+            }
+            None => source.push_str(line),
+        }
+    }
+
+    Ok((source, expectations))
+}
+
+/// Compiles the annotated program and checks its diagnostics, returning a
+/// report of the mismatches if there are any.
+pub(crate) fn check_program(
+    name: &str,
+    annotated_source: &str,
+) -> Result<(), String> {
+    let (source, mut expectations) = parse_annotations(annotated_source)?;
+
+    let mut source_map = SourceMap::new();
+    let source_file = source_map.add_file(name.into(), source);
+    let mut diag_bag = DiagBag::new();
+    compile_file(&source_file, Extensions::none(), &mut diag_bag);
+
+    let mut unexpected = Vec::new();
+    for diagnostic in diag_bag.iter() {
+        let line = diagnostic.diag.span().and_then(|span| {
+            source_file
+                .lookup_line_index(span.start)
+                .map(|index| index + 1)
+        });
+        match expectations
+            .iter()
+            .position(|expectation| expectation.matches(line, diagnostic))
+        {
+            Some(index) => {
+                expectations.remove(index);
+            }
+            None => unexpected.push(format!(
+                "{}: {}: {}",
+                line.map_or("?".to_owned(), |line| line.to_string()),
+                diagnostic.severity,
+                diagnostic.diag.message()
+            )),
+        }
+    }
+
+    if unexpected.is_empty() && expectations.is_empty() {
+        return Ok(());
+    }
+
+    let mut report = format!("{}: diagnostics don't match\n", name);
+    for diagnostic in unexpected {
+        report.push_str(&format!("  unexpected {}\n", diagnostic));
+    }
+    for expectation in expectations {
+        report.push_str(&format!("  missing {}\n", expectation));
+    }
+
+    Err(report)
+}
+
+/// Checks every `.cm` program in `dir`, panicking with a report of all the
+/// programs whose diagnostics don't match.
+pub(crate) fn check_programs_in(dir: &Path) {
+    let failures: Vec<String> = programs_in(dir)
+        .iter()
+        .filter_map(|path| {
+            let annotated_source = fs::read_to_string(path).unwrap();
+            check_program(&path.display().to_string(), &annotated_source).err()
+        })
+        .collect();
+
+    assert!(failures.is_empty(), "\n{}", failures.concat());
+}
+
+/// Checks the corpus of programs in `dir`: the ones in its `accept`
+/// directory must compile without diagnostics, and the ones in its `reject`
+/// directory must report errors, annotated with their exact codes. Panics
+/// with a report of all the programs that don't pass.
+pub(crate) fn check_corpus_in(dir: &Path) {
+    let mut failures = Vec::new();
+
+    for (subdir, accepted) in [("accept", true), ("reject", false)] {
+        for path in programs_in(&dir.join(subdir)) {
+            let annotated_source = fs::read_to_string(&path).unwrap();
+            let name = path.display().to_string();
+            if let Err(report) =
+                check_corpus_program(&name, &annotated_source, accepted)
+            {
+                failures.push(report);
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.concat());
+}
+
+/// Checks an annotated program of the corpus, which must be `accepted` or
+/// rejected.
+fn check_corpus_program(
+    name: &str,
+    annotated_source: &str,
+    accepted: bool,
+) -> Result<(), String> {
+    let (_, expectations) = parse_annotations(annotated_source)
+        .map_err(|err| format!("{}: {}\n", name, err))?;
+
+    if accepted && !expectations.is_empty() {
+        return Err(format!(
+            "{}: an accepted program can't expect diagnostics\n",
+            name
+        ));
+    }
+    let expects_error = expectations.iter().any(|expectation| {
+        matches!(expectation.severity, Severity::Error | Severity::Fatal)
+    });
+    if !accepted && !expects_error {
+        return Err(format!(
+            "{}: a rejected program must expect an error\n",
+            name
+        ));
+    }
+    if let Some(expectation) = expectations
+        .iter()
+        .find(|expectation| expectation.code.is_none())
+    {
+        return Err(format!(
+            "{}: line {}: the annotation must have a code\n",
+            name, expectation.line
+        ));
+    }
+
+    check_program(name, annotated_source)
+}
+
+/// Runs `compile` and renders the diagnostics it reports in every format,
+/// returning the message of the internal compiler error if anything panics.
+fn check_survives(
+    compile: impl FnOnce(&mut SourceMap, &mut DiagBag),
+) -> Result<(), String> {
+    catch_ice(|| {
+        let mut source_map = SourceMap::new();
+        let mut diag_bag = DiagBag::new();
+        compile(&mut source_map, &mut diag_bag);
+        diag_bag.sort_by_position();
+
+        let formats = [
+            (ErrorFormat::Human, false),
+            (ErrorFormat::Human, true),
+            (ErrorFormat::Short, true),
+            (ErrorFormat::Json, false),
+            (ErrorFormat::Sarif, false),
+        ];
+        for (format, color) in formats {
+            let mut emitter =
+                new_emitter(format, &source_map, Box::new(Vec::new()), color);
+            for diagnostic in diag_bag.iter() {
+                emitter.emit(diagnostic).unwrap();
+            }
+            emitter.emit_summary(&diag_bag.summary()).unwrap();
+            emitter.finish().unwrap();
+        }
+    })
+    .map_err(|ice| format!("{} at {}", ice.message, ice.location))
+}
+
+/// The paths of the `.cm` programs in `dir`, sorted.
+fn programs_in(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("can't read {}: {}", dir.display(), err))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "cm"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no programs in {}", dir.display());
+
+    paths
+}
+
+/// Checks that the compiler survives every program in `dir`, read by the
+/// compiler itself, and, if `with_prefixes`, every prefix of them, as if the
+/// programs were cut short.
+pub(crate) fn check_survives_programs_in(dir: &Path, with_prefixes: bool) {
+    let mut failures = Vec::new();
+
+    for path in programs_in(dir) {
+        let name = path.display().to_string();
+        if let Err(ice) = check_survives(|source_map, diag_bag| {
+            compile(&name, Extensions::all(), source_map, diag_bag);
+        }) {
+            failures.push(format!("{}: {}\n", name, ice));
+        }
+        if !with_prefixes {
+            continue;
+        }
+
+        let source = fs::read_to_string(&path).unwrap();
+        for (end, _) in source.char_indices() {
+            let prefix = &source[..end];
+            if let Err(ice) = check_survives(|source_map, diag_bag| {
+                let source_file =
+                    source_map.add_file(name.clone(), prefix.to_owned());
+                compile_file(&source_file, Extensions::all(), diag_bag);
+            }) {
+                failures
+                    .push(format!("{} up to byte {}: {}\n", name, end, ice));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.concat());
+}
+
+/// Checks that the compiler survives every string of up to `max_len`
+/// characters taken from `alphabet`.
+pub(crate) fn check_survives_strings_of(alphabet: &str, max_len: usize) {
+    let chars: Vec<char> = alphabet.chars().collect();
+    let mut sources = vec![String::new()];
+    let mut failures = Vec::new();
+
+    for _ in 0..max_len {
+        sources = sources
+            .iter()
+            .flat_map(|source| {
+                chars.iter().map(move |&ch| format!("{}{}", source, ch))
+            })
+            .collect();
+
+        for source in &sources {
+            if let Err(ice) = check_survives(|source_map, diag_bag| {
+                let source_file =
+                    source_map.add_file("main.cm".into(), source.clone());
+                compile_file(&source_file, Extensions::all(), diag_bag);
+            }) {
+                failures.push(format!("{:?}: {}\n", source, ice));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.concat());
+}
+
+/// Scans and parses `source` as the file `main.cm`, with `extensions`. What
+/// the scanner reports comes first in the diagnostics of the result.
+pub(crate) fn parse_source(
+    source: &str,
+    extensions: Extensions,
+) -> (SourceFile, ParseResult) {
+    let source_file = SourceFile::new("main.cm".into(), source.into());
+    let (words, mut diag_bag) = scan_words(&source_file, extensions);
+    let mut parse_result = parse_program(&source_file, &words, extensions);
+    diag_bag.extend(parse_result.diag_bag);
+    parse_result.diag_bag = diag_bag;
+
+    (source_file, parse_result)
+}
+
+/// Parses `source`, with every extension, and checks it, panicking if it has
+/// any error.
+pub(crate) fn analyze_source(source: &str) -> (SourceFile, Analysis) {
+    let (
+        source_file,
+        ParseResult {
+            program, diag_bag, ..
+        },
+    ) = parse_source(source, Extensions::all());
+    let analysis = sema::check_program(&program, &Builtin::ALL);
+    assert!(
+        !diag_bag.has_errors() && !analysis.diag_bag.has_errors(),
+        "errors in {:?}",
+        source
+    );
+
+    (source_file, analysis)
+}
+
+/// Checks `source` and lowers it into the IR.
+pub(crate) fn lower_source(source: &str) -> ir::Program {
+    let (_, analysis) = analyze_source(source);
+    lower_program(&analysis.hir)
+}
+
+/// The code of `source` after `passes`.
+pub(crate) fn ir_after(source: &str, passes: &[Pass]) -> String {
+    let mut program = lower_source(source);
+    run_passes(&mut program, passes);
+    dump_ir(&program)
+}
+
+/// The paths of the programs in `tests/programs/accept`, sorted.
+pub(crate) fn accept_corpus() -> Vec<PathBuf> {
+    programs_in(&accept_dir())
+}
+
+/// The source of the program `name` in `tests/programs/accept`.
+pub(crate) fn accept_source(name: &str) -> String {
+    fs::read_to_string(accept_dir().join(name)).unwrap()
+}
+
+fn accept_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs/accept")
+}
+
+/// Checks that the program at `path` writes for `input` what it does when
+/// interpreted, once `cc` builds it from the file `file_name`, which `emit`
+/// writes from the IR of the program. Without `cc`, nothing is checked.
+pub(crate) fn check_runs_as_interpreted(
+    path: &Path,
+    input: &str,
+    file_name: &str,
+    emit: impl FnOnce(&ir::Program) -> Vec<u8>,
+) {
+    let source = fs::read_to_string(path).unwrap();
+    let (_, analysis) = analyze_source(&source);
+    let mut expected = Vec::new();
+    interp::run(&analysis.hir, &mut input.as_bytes(), &mut expected).unwrap();
+
+    // Targets run the same program at once, each from its own file name.
+    let name = path.file_stem().unwrap().to_str().unwrap();
+    let dir = env::temp_dir().join(format!(
+        "csub-{}-{}-{}",
+        process::id(),
+        name,
+        file_name
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let (file, exe) = (dir.join(file_name), dir.join("prog"));
+    fs::write(&file, emit(&lower_program(&analysis.hir))).unwrap();
+    let Ok(status) = Command::new("cc").arg(&file).arg("-o").arg(&exe).status()
+    else {
+        return;
+    };
+    assert!(status.success(), "{} didn't build", path.display());
+
+    let mut child = Command::new(&exe)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // A program may exit without reading all of it.
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(output.status.success(), "{} failed", path.display());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(expected).unwrap(),
+        "{}",
+        path.display()
+    );
+}
+
+/// The code of the function `name` in `code`, as `dump_ir` writes it.
+pub(crate) fn fun_code<'a>(code: &'a str, name: &str) -> &'a str {
+    let start = code.find(&format!("fun @{}(", name)).unwrap();
+    let end = start + code[start..].find("\n}\n").unwrap();
+    &code[start..end + 3]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_corpus_in, check_corpus_program, check_program,
+        check_programs_in, check_survives, check_survives_programs_in,
+        check_survives_strings_of, parse_annotation, parse_annotations,
+        Expectation,
+    };
+    use crate::errors::Severity;
+    use std::path::Path;
+
+    #[test]
+    fn parse_annotation_with_code_and_message() {
+        assert_eq!(
+            parse_annotation(3, "error[E0001]: unknown character"),
+            Ok(Expectation {
+                line: 3,
+                severity: Severity::Error,
+                code: Some("E0001".into()),
+                message: Some("unknown character".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_annotation_with_only_severity() {
+        assert_eq!(
+            parse_annotation(1, "warning"),
+            Ok(Expectation {
+                line: 1,
+                severity: Severity::Warning,
+                code: None,
+                message: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_malformed_annotations() {
+        assert_eq!(
+            parse_annotation(2, "mistake: oops"),
+            Err("line 2: unknown severity `mistake`".into())
+        );
+        assert_eq!(
+            parse_annotation(2, "error[E0001: oops"),
+            Err("line 2: missing `]` after the code".into())
+        );
+    }
+
+    #[test]
+    fn annotations_are_blanked_out_keeping_positions() {
+        let (source, expectations) =
+            parse_annotations("int x; // expect-error\nint y;\n").unwrap();
+
+        assert_eq!(source, "int x;                \nint y;\n");
+        assert_eq!(expectations.len(), 1);
+    }
+
+    #[test]
+    fn matching_diagnostics_pass() {
+        assert_eq!(
+            check_program(
+                "main.cm",
+                "int x;\nx @ 1; // expect-error[E0001]: unknown character '@'\n"
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn unexpected_and_missing_diagnostics_are_reported() {
+        assert_eq!(
+            check_program(
+                "main.cm",
+                "int x; // expect-error: unknown character\nx @ 1;\n"
+            ),
+            Err("main.cm: diagnostics don't match\n  \
+                 unexpected 2: error: unknown character '@'\n  \
+                 missing 1: error: unknown character\n"
+                .into())
+        );
+    }
+
+    #[test]
+    fn ui_programs() {
+        check_programs_in(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ui"),
+        );
+    }
+
+    #[test]
+    fn corpus_programs() {
+        check_corpus_in(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs"),
+        );
+    }
+
+    #[test]
+    fn corpus_programs_must_say_whether_and_how_they_fail() {
+        assert_eq!(
+            check_corpus_program("ok.cm", "int x; // expect-warning\n", true),
+            Err("ok.cm: an accepted program can't expect diagnostics\n".into())
+        );
+        assert_eq!(
+            check_corpus_program("bad.cm", "int x;\n", false),
+            Err("bad.cm: a rejected program must expect an error\n".into())
+        );
+        assert_eq!(
+            check_corpus_program("bad.cm", "x @ 1; // expect-error\n", false),
+            Err("bad.cm: line 1: the annotation must have a code\n".into())
+        );
+        assert_eq!(
+            check_corpus_program(
+                "bad.cm",
+                "int x;\nx @ 1; // expect-error[E0001]\n",
+                false
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn panics_are_caught_by_the_survival_check() {
+        assert!(check_survives(|_, _| panic!("oops"))
+            .unwrap_err()
+            .starts_with("oops at src/test_support.rs:"));
+    }
+
+    #[test]
+    fn compiler_survives_hostile_programs() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        check_survives_programs_in(&root.join("tests/hostile"), false);
+        check_survives_programs_in(&root.join("tests/ui"), true);
+        check_survives_programs_in(&root.join("tests/programs/accept"), false);
+        check_survives_programs_in(&root.join("tests/programs/reject"), false);
+    }
+
+    #[test]
+    fn compiler_survives_short_strings_of_troublesome_characters() {
+        check_survives_strings_of(
+            "a0_ \t\n\r/*\"'\\=<>!+-;,()[]{}@$é\u{0}\u{feff}😀\u{202e}\u{301}",
+            2,
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! The types of the values of a program, as sema sees them.
+//!
+//! The syntax tree only has what a declaration spells out, e.g. `int`, two
+//! `*`s and the name `p` for `int **p;`. A `Type` puts those together, e.g. a
+//! pointer to a pointer to an `int`, so that the passes after parsing don't
+//! have to.
+
+use crate::{
+    ast::{FunDecl, Nodes, Param, TypeSpec, VarDecl},
+    sema::array_len,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Type {
+    Int,
+    Char,
+    Void,
+    Pointer(Box<Type>),
+    /// `struct name`, defined elsewhere in the program.
+    Struct(String),
+    /// A value of the type that can't be assigned to, e.g. `const int`.
+    Const(Box<Type>),
+    /// `len` elements of type `elem`. An array of more than one dimension is
+    /// an array of arrays, e.g. `int m[3][4];` is 3 arrays of 4 `int`s.
+    Array {
+        elem: Box<Type>,
+        len: u64,
+    },
+    /// The type of an expression that can't have one, e.g. an index into
+    /// something that isn't an array, so that what's wrong with it isn't
+    /// taken for something wrong with the expressions it's in.
+    Error,
+}
+
+impl Type {
+    /// The type of a variable declared as `var_decl`, one of `nodes`. A size
+    /// that isn't a positive constant, which sema reports, counts as 0.
+    pub(crate) fn of_var_decl(nodes: &Nodes, var_decl: &VarDecl) -> Type {
+        let elem =
+            Type::of_spec(var_decl.is_const, &var_decl.ty, var_decl.pointers);
+        var_decl
+            .sizes
+            .iter()
+            .rev()
+            .fold(elem, |elem, &size| Type::Array {
+                elem: Box::new(elem),
+                len: array_len(nodes, size).unwrap_or(0),
+            })
+    }
+
+    /// The type of a parameter declared as `param`, one of `nodes`. An array
+    /// is passed as a pointer to its first element, so that's what an array
+    /// parameter is, e.g. `int m[][4]` is a pointer to arrays of 4 `int`s.
+    pub(crate) fn of_param(nodes: &Nodes, param: &Param) -> Type {
+        let elem = Type::of_spec(param.is_const, &param.ty, param.pointers);
+        let ty =
+            param
+                .sizes
+                .iter()
+                .rev()
+                .fold(elem, |elem, &size| Type::Array {
+                    elem: Box::new(elem),
+                    len: array_len(nodes, size).unwrap_or(0),
+                });
+        if param.is_array {
+            ty.pointer_to()
+        } else {
+            ty
+        }
+    }
+
+    /// The type of the values `fun_decl` returns.
+    pub(crate) fn of_return(fun_decl: &FunDecl) -> Type {
+        Type::of_spec(
+            fun_decl.return_is_const,
+            &fun_decl.return_ty,
+            fun_decl.return_pointers,
+        )
+    }
+
+    /// The type of `spec`, with a `const` before it if `is_const`, and
+    /// `pointers` `*`s after it. The `const` is of what the pointers point
+    /// to, as in C, e.g. `const int *p` is a pointer to a `const int`.
+    fn of_spec(is_const: bool, spec: &TypeSpec, pointers: u32) -> Type {
+        let base = match spec {
+            TypeSpec::Int => Type::Int,
+            TypeSpec::Char => Type::Char,
+            TypeSpec::Void => Type::Void,
+            TypeSpec::Struct(name) => Type::Struct(name.name.clone()),
+        };
+        let base = if is_const {
+            Type::Const(Box::new(base))
+        } else {
+            base
+        };
+        (0..pointers).fold(base, |ty, _| ty.pointer_to())
+    }
+
+    /// The type of a pointer to a value of this type, i.e. of `&x` for an `x`
+    /// of this type.
+    pub(crate) fn pointer_to(self) -> Type {
+        Type::Pointer(Box::new(self))
+    }
+
+    /// The type of what a pointer of this type points to, i.e. of `*p` for a
+    /// `p` of this type, if it's a pointer.
+    pub(crate) fn pointee(&self) -> Option<&Type> {
+        match self {
+            Type::Pointer(pointee) => Some(pointee),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_pointer(&self) -> bool {
+        matches!(self, Type::Pointer(_))
+    }
+
+    /// This type without a `const`, e.g. `int` for `const int`.
+    pub(crate) fn unqualified(&self) -> &Type {
+        match self {
+            Type::Const(ty) => ty,
+            ty => ty,
+        }
+    }
+
+    /// The type a value of this type has where it's used, as in C: an array
+    /// is the address of its first element there, e.g. `int *` for
+    /// `int [10]`.
+    pub(crate) fn decay(&self) -> Type {
+        match self.unqualified() {
+            Type::Array { elem, .. } => (**elem).clone().pointer_to(),
+            _ => self.clone(),
+        }
+    }
+}
+
+/// How a value of one type becomes a value of another where it's assigned,
+/// passed as an argument or returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Coercion {
+    /// The value is used as is.
+    Identity,
+    /// An array becomes the address of its first element.
+    Decay,
+    /// An `int` becomes a `char`, keeping its lowest byte, or a `char` an
+    /// `int`.
+    Convert,
+}
+
+/// Why a value of one type can't be used where a value of another is
+/// expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mismatch {
+    /// The value is of type `void`, i.e. there's no value.
+    Void,
+    /// A pointer to something `const` where a pointer to something that can
+    /// be assigned to is expected.
+    DiscardsConst,
+    /// The types have nothing to do with each other, e.g. `int` and `int *`.
+    Incompatible,
+}
+
+/// How a value of type `from` becomes a value of type `to` where it's
+/// assigned, passed as an argument or returned. These are C's rules, with
+/// the conversions C- has no use for left out, e.g. from an `int` to a
+/// pointer. Every check of a value against the type it's used as goes
+/// through here, so that they all agree.
+///
+/// A `const` on `to` itself doesn't matter, as it's about assigning to what
+/// has that type, not about the value. Either type being `Type::Error` is
+/// fine, as what's wrong with it is already reported.
+pub(crate) fn coerce(from: &Type, to: &Type) -> Result<Coercion, Mismatch> {
+    let to = to.unqualified();
+    let from = from.unqualified();
+    if *from == Type::Error || *to == Type::Error {
+        return Ok(Coercion::Identity);
+    }
+    if *from == Type::Void {
+        return Err(Mismatch::Void);
+    }
+    let (from, coercion) = match from {
+        Type::Array { .. } => (from.decay(), Coercion::Decay),
+        _ => (from.clone(), Coercion::Identity),
+    };
+    match (&from, to) {
+        (Type::Int | Type::Char, Type::Int | Type::Char) if from == *to => {
+            Ok(Coercion::Identity)
+        }
+        (Type::Int | Type::Char, Type::Int | Type::Char) => {
+            Ok(Coercion::Convert)
+        }
+        (Type::Pointer(from_pointee), Type::Pointer(to_pointee)) => {
+            let (from_base, to_base) =
+                (from_pointee.unqualified(), to_pointee.unqualified());
+            // As in C, a `void *` points to anything.
+            let related = from_base == to_base
+                || [from_base, to_base]
+                    .iter()
+                    .any(|base| matches!(base, Type::Void | Type::Error));
+            if !related {
+                Err(Mismatch::Incompatible)
+            } else if matches!(**from_pointee, Type::Const(_))
+                && !matches!(**to_pointee, Type::Const(_))
+            {
+                Err(Mismatch::DiscardsConst)
+            } else {
+                Ok(coercion)
+            }
+        }
+        (Type::Struct(from_name), Type::Struct(to_name))
+            if from_name == to_name =>
+        {
+            Ok(Coercion::Identity)
+        }
+        _ => Err(Mismatch::Incompatible),
+    }
+}
+
+/// Types are written as in C without the name, e.g. `int *` or `int [3][4]`.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => f.write_str("int"),
+            Type::Char => f.write_str("char"),
+            Type::Void => f.write_str("void"),
+            Type::Struct(name) => write!(f, "struct {}", name),
+            Type::Error => f.write_str("{error}"),
+            Type::Const(ty) => write!(f, "const {}", ty),
+            Type::Pointer(pointee) if matches!(**pointee, Type::Pointer(_)) => {
+                write!(f, "{}*", pointee)
+            }
+            Type::Pointer(pointee) => write!(f, "{} *", pointee),
+            Type::Array { .. } => {
+                let mut elem = self;
+                let mut lens = Vec::new();
+                while let Type::Array { elem: inner, len } = elem {
+                    lens.push(len);
+                    elem = inner;
+                }
+                write!(f, "{} ", elem)?;
+                for len in lens {
+                    write!(f, "[{}]", len)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{coerce, Coercion, Mismatch, Type};
+    use crate::{
+        ast::Decl, extensions::Extensions, parser::ParseResult,
+        test_support::parse_source,
+    };
+
+    #[test]
+    fn declarations_have_the_types_they_spell_out() {
+        let source = "int x; char *s; int m[3][4]; int *a[2]; const int n;\n\
+                      int k[2 * 3 + 1];\n\
+                      struct p { int x; }; struct p *q[2];\n\
+                      char **f(int b[], int **c, const void *d) { }\n\
+                      void g(int m[][4], char t[][2][3]) { }";
+        let (
+            _,
+            ParseResult {
+                program, diag_bag, ..
+            },
+        ) = parse_source(source, Extensions::all());
+        assert!(!diag_bag.has_errors());
+
+        let mut types = Vec::new();
+        for decl in &program.decls {
+            match decl {
+                Decl::Var(var_decl) => types.push(
+                    Type::of_var_decl(&program.nodes, var_decl).to_string(),
+                ),
+                Decl::Struct(_) => {}
+                Decl::Fun(fun_decl) => {
+                    types.push(Type::of_return(fun_decl).to_string());
+                    for param in &fun_decl.params {
+                        types.push(
+                            Type::of_param(&program.nodes, param).to_string(),
+                        );
+                    }
+                }
+            }
+        }
+
+        assert_eq!(
+            types,
+            [
+                "int",
+                "char *",
+                "int [3][4]",
+                "int * [2]",
+                "const int",
+                "int [7]",
+                "struct p * [2]",
+                "char **",
+                "int *",
+                "int **",
+                "const void *",
+                "void",
+                "int [4] *",
+                "char [2][3] *",
+            ]
+        );
+    }
+
+    #[test]
+    fn addresses_and_dereferences_undo_each_other() {
+        let ty = Type::Int.pointer_to();
+
+        assert!(ty.is_pointer());
+        assert_eq!(ty.pointee(), Some(&Type::Int));
+        assert_eq!(ty.clone().pointer_to().pointee(), Some(&ty));
+        assert_eq!(Type::Int.pointee(), None);
+    }
+
+    #[test]
+    fn arrays_decay_to_pointers_to_their_elements() {
+        let array = Type::Array {
+            elem: Box::new(Type::Array {
+                elem: Box::new(Type::Char),
+                len: 4,
+            }),
+            len: 3,
+        };
+
+        assert_eq!(array.decay().to_string(), "char [4] *");
+        assert_eq!(Type::Int.decay(), Type::Int);
+        assert_eq!(Type::Const(Box::new(Type::Int)).unqualified(), &Type::Int);
+    }
+
+    #[test]
+    fn values_are_coerced_as_in_c() {
+        let int = || Type::Int;
+        let const_int = || Type::Const(Box::new(Type::Int));
+        let array = Type::Array {
+            elem: Box::new(Type::Int),
+            len: 3,
+        };
+
+        assert_eq!(coerce(&int(), &int()), Ok(Coercion::Identity));
+        assert_eq!(coerce(&const_int(), &int()), Ok(Coercion::Identity));
+        assert_eq!(coerce(&int(), &const_int()), Ok(Coercion::Identity));
+        assert_eq!(coerce(&Type::Char, &int()), Ok(Coercion::Convert));
+        assert_eq!(coerce(&int(), &Type::Char), Ok(Coercion::Convert));
+        assert_eq!(coerce(&array, &int().pointer_to()), Ok(Coercion::Decay));
+        assert_eq!(
+            coerce(&int().pointer_to(), &const_int().pointer_to()),
+            Ok(Coercion::Identity)
+        );
+        assert_eq!(
+            coerce(&const_int().pointer_to(), &int().pointer_to()),
+            Err(Mismatch::DiscardsConst)
+        );
+        assert_eq!(
+            coerce(&Type::Void.pointer_to(), &Type::Char.pointer_to()),
+            Ok(Coercion::Identity)
+        );
+        assert_eq!(
+            coerce(&Type::Char.pointer_to(), &int().pointer_to()),
+            Err(Mismatch::Incompatible)
+        );
+        assert_eq!(coerce(&array, &int()), Err(Mismatch::Incompatible));
+        assert_eq!(
+            coerce(&int(), &int().pointer_to()),
+            Err(Mismatch::Incompatible)
+        );
+        assert_eq!(
+            coerce(&Type::Struct("p".into()), &Type::Struct("q".into())),
+            Err(Mismatch::Incompatible)
+        );
+        assert_eq!(coerce(&Type::Void, &int()), Err(Mismatch::Void));
+        assert_eq!(coerce(&int(), &Type::Void), Err(Mismatch::Incompatible));
+        assert_eq!(coerce(&Type::Error, &int()), Ok(Coercion::Identity));
+    }
+}
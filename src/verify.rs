@@ -0,0 +1,581 @@
+//! A verifier of the three-address code of a program, run on it after it's
+//! lowered and after every pass, so that a pass breaking it stops the
+//! compiler right there, with the function and the instruction it broke,
+//! rather than a backend miscompiling it.
+//!
+//! Code is checked for what every pass and backend relies on: operands and
+//! labels that are there, a terminator at the end of every block, operands
+//! of the types their instructions take, and temporaries assigned on every
+//! path to where they're read. A constant is of either type, as a null
+//! pointer is a 0.
+
+use crate::{
+    ast::BinOp,
+    cfg::{Cfg, CfgError},
+    ir::{Callee, Fun, Inst, Label, Mem, Operand, Program, Temp, Ty},
+    sema::Builtin,
+};
+use std::fmt;
+
+/// Something wrong with the code of a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct VerifyError {
+    /// The name of the function.
+    pub(crate) fun: String,
+    /// The index of the instruction it's in, if it's in one.
+    pub(crate) inst: Option<usize>,
+    pub(crate) kind: ErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ErrorKind {
+    UnknownTemp(Temp),
+    UnknownSlot(usize),
+    UnknownGlobal(usize),
+    UnknownString(usize),
+    UnknownFun(usize),
+    UnknownLabel(Label),
+    /// A label placed at more than one instruction.
+    DuplicateLabel(Label),
+    /// A jump to a label placed nowhere.
+    UnplacedLabel(Label),
+    /// A function without any code, which falls off its end.
+    NoCode,
+    /// A block of the control-flow graph that doesn't end with a terminator.
+    MissingTerminator,
+    /// A jump back to the start of the function.
+    EntryHasPreds,
+    /// An operand, e.g. "the address", of the wrong type.
+    WrongType {
+        operand: String,
+        expected: Ty,
+        found: Ty,
+    },
+    /// A call with more or fewer arguments than its callee takes.
+    WrongArgCount {
+        expected: usize,
+        found: usize,
+    },
+    /// A call whose result is assigned to a temporary, when the callee
+    /// returns nothing.
+    NoResult,
+    /// A return with a value from a function that returns nothing, or
+    /// without one from a function that returns something.
+    WrongReturn {
+        expected: Option<Ty>,
+    },
+    /// A temporary read where it may not have been assigned.
+    UseBeforeDef(Temp),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "in @{}", self.fun)?;
+        if let Some(inst) = self.inst {
+            write!(f, ", at instruction {}", inst)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnknownTemp(temp) => write!(f, "{} isn't there", temp),
+            ErrorKind::UnknownSlot(slot) => {
+                write!(f, "slot ${} isn't there", slot)
+            }
+            ErrorKind::UnknownGlobal(global) => {
+                write!(f, "global {} isn't there", global)
+            }
+            ErrorKind::UnknownString(string) => {
+                write!(f, "string @.str{} isn't there", string)
+            }
+            ErrorKind::UnknownFun(fun) => {
+                write!(f, "function {} isn't there", fun)
+            }
+            ErrorKind::UnknownLabel(label) => {
+                write!(f, "{} isn't there", label)
+            }
+            ErrorKind::DuplicateLabel(label) => {
+                write!(f, "{} is placed more than once", label)
+            }
+            ErrorKind::UnplacedLabel(label) => {
+                write!(f, "{} is jumped to but placed nowhere", label)
+            }
+            ErrorKind::NoCode => write!(f, "there's no code"),
+            ErrorKind::MissingTerminator => {
+                write!(f, "the block doesn't end with a terminator")
+            }
+            ErrorKind::EntryHasPreds => {
+                write!(f, "the entry block is jumped to")
+            }
+            ErrorKind::WrongType {
+                operand,
+                expected,
+                found,
+            } => write!(f, "{} is {}, not {}", operand, found, expected),
+            ErrorKind::WrongArgCount { expected, found } => {
+                write!(f, "{} arguments are passed, not {}", found, expected)
+            }
+            ErrorKind::NoResult => {
+                write!(f, "the result of a call returning nothing is used")
+            }
+            ErrorKind::WrongReturn { expected: Some(ty) } => {
+                write!(f, "a function returning {} returns nothing", ty)
+            }
+            ErrorKind::WrongReturn { expected: None } => {
+                write!(f, "a function returning nothing returns a value")
+            }
+            ErrorKind::UseBeforeDef(temp) => {
+                write!(f, "{} may be read before it's assigned", temp)
+            }
+        }
+    }
+}
+
+/// Checks the code of every function of `program`, returning what's wrong
+/// with the first one something is.
+pub(crate) fn verify_program(program: &Program) -> Result<(), VerifyError> {
+    for fun in &program.funs {
+        verify_fun(program, fun).map_err(|(inst, kind)| VerifyError {
+            fun: fun.name.clone(),
+            inst,
+            kind,
+        })?;
+    }
+    Ok(())
+}
+
+/// Verifies `program`, panicking with what's wrong with it, as it's a bug
+/// of what was run on it last, `after`.
+pub(crate) fn assert_valid(program: &Program, after: &str) {
+    if let Err(error) = verify_program(program) {
+        panic!("invalid IR after {}: {}", after, error);
+    }
+}
+
+type FunError = (Option<usize>, ErrorKind);
+
+fn verify_fun(program: &Program, fun: &Fun) -> Result<(), FunError> {
+    for (index, inst) in fun.code.iter().enumerate() {
+        check_refs(program, fun, inst).map_err(|kind| (Some(index), kind))?;
+    }
+    check_labels(fun)?;
+
+    if fun.code.is_empty() {
+        return Err((None, ErrorKind::NoCode));
+    }
+    let cfg = Cfg::new(fun);
+    if let Err(error) = cfg.check(fun) {
+        return Err(match error {
+            CfgError::EntryHasPreds => (None, ErrorKind::EntryHasPreds),
+            CfgError::MissingTerminator(block) => (
+                Some(cfg.blocks[block.0].insts.end - 1),
+                ErrorKind::MissingTerminator,
+            ),
+        });
+    }
+
+    for (index, inst) in fun.code.iter().enumerate() {
+        check_types(program, fun, inst).map_err(|kind| (Some(index), kind))?;
+    }
+    check_defs(fun, &cfg)
+}
+
+/// Checks that what `inst` names is there.
+fn check_refs(
+    program: &Program,
+    fun: &Fun,
+    inst: &Inst,
+) -> Result<(), ErrorKind> {
+    let temp = |temp: Temp| {
+        if temp.0 < fun.temps.len() {
+            Ok(())
+        } else {
+            Err(ErrorKind::UnknownTemp(temp))
+        }
+    };
+    if let Some(dst) = inst.dst() {
+        temp(dst)?;
+    }
+    for operand in inst.operands() {
+        match *operand {
+            Operand::Temp(operand) => temp(operand)?,
+            Operand::Const(_) => {}
+            Operand::Slot(slot) if slot >= fun.slots.len() => {
+                return Err(ErrorKind::UnknownSlot(slot))
+            }
+            Operand::Global(global) if global >= program.globals.len() => {
+                return Err(ErrorKind::UnknownGlobal(global))
+            }
+            Operand::Str(string) if string >= program.strings.len() => {
+                return Err(ErrorKind::UnknownString(string))
+            }
+            Operand::Slot(_) | Operand::Global(_) | Operand::Str(_) => {}
+        }
+    }
+    match *inst {
+        Inst::Call {
+            callee: Callee::Fun(index),
+            ..
+        } if index >= program.funs.len() => Err(ErrorKind::UnknownFun(index)),
+        _ => labels(inst)
+            .into_iter()
+            .find(|label| label.0 >= fun.labels)
+            .map_or(Ok(()), |label| Err(ErrorKind::UnknownLabel(label))),
+    }
+}
+
+/// The labels `inst` places or jumps to.
+fn labels(inst: &Inst) -> Vec<Label> {
+    match *inst {
+        Inst::Label(label) | Inst::Jump(label) => vec![label],
+        Inst::Branch {
+            then_label,
+            else_label,
+            ..
+        } => vec![then_label, else_label],
+        _ => Vec::new(),
+    }
+}
+
+/// Checks that each label is placed once at most, and that every one that's
+/// jumped to is.
+fn check_labels(fun: &Fun) -> Result<(), FunError> {
+    let mut placed = vec![false; fun.labels];
+    for (index, inst) in fun.code.iter().enumerate() {
+        if let Inst::Label(label) = *inst {
+            if placed[label.0] {
+                return Err((Some(index), ErrorKind::DuplicateLabel(label)));
+            }
+            placed[label.0] = true;
+        }
+    }
+    for (index, inst) in fun.code.iter().enumerate() {
+        if let Inst::Jump(_) | Inst::Branch { .. } = inst {
+            if let Some(label) =
+                labels(inst).into_iter().find(|label| !placed[label.0])
+            {
+                return Err((Some(index), ErrorKind::UnplacedLabel(label)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the operands of `inst` are of the types it takes.
+fn check_types(
+    program: &Program,
+    fun: &Fun,
+    inst: &Inst,
+) -> Result<(), ErrorKind> {
+    let ty = |operand: &Operand| match *operand {
+        Operand::Temp(temp) => Some(fun.temps[temp.0]),
+        Operand::Const(_) => None,
+        Operand::Slot(_) | Operand::Global(_) | Operand::Str(_) => {
+            Some(Ty::Ptr)
+        }
+    };
+    let expect = |what: &str, found: Option<Ty>, expected: Ty| match found {
+        Some(found) if found != expected => Err(ErrorKind::WrongType {
+            operand: what.to_string(),
+            expected,
+            found,
+        }),
+        _ => Ok(()),
+    };
+    let dst = |dst: Temp| Some(fun.temps[dst.0]);
+    let mem_ty = |mem: Mem| match mem {
+        Mem::I8 | Mem::I32 => Ty::I32,
+        Mem::Ptr => Ty::Ptr,
+    };
+
+    match inst {
+        Inst::Copy { dst: to, src } => {
+            expect("the source", ty(src), fun.temps[to.0])
+        }
+        Inst::Neg { dst: to, src } => {
+            expect("the result", dst(*to), Ty::I32)?;
+            expect("the operand", ty(src), Ty::I32)
+        }
+        Inst::Binary {
+            dst: to,
+            op,
+            lhs,
+            rhs,
+        } => {
+            expect("the result", dst(*to), Ty::I32)?;
+            match op {
+                BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div => {
+                    expect("the left operand", ty(lhs), Ty::I32)?;
+                    expect("the right operand", ty(rhs), Ty::I32)
+                }
+                BinOp::Less
+                | BinOp::LessEqual
+                | BinOp::Greater
+                | BinOp::GreaterEqual
+                | BinOp::Equal
+                | BinOp::NotEqual => match ty(lhs) {
+                    Some(lhs) => expect("the right operand", ty(rhs), lhs),
+                    None => Ok(()),
+                },
+            }
+        }
+        Inst::Offset {
+            dst: to,
+            base,
+            bytes,
+        } => {
+            expect("the result", dst(*to), Ty::Ptr)?;
+            expect("the base", ty(base), Ty::Ptr)?;
+            expect("the offset", ty(bytes), Ty::I32)
+        }
+        Inst::Diff { dst: to, lhs, rhs } => {
+            expect("the result", dst(*to), Ty::I32)?;
+            expect("the left operand", ty(lhs), Ty::Ptr)?;
+            expect("the right operand", ty(rhs), Ty::Ptr)
+        }
+        Inst::Load { dst: to, mem, addr } => {
+            expect("the result", dst(*to), mem_ty(*mem))?;
+            expect("the address", ty(addr), Ty::Ptr)
+        }
+        Inst::Store { mem, addr, value } => {
+            expect("the address", ty(addr), Ty::Ptr)?;
+            expect("the value", ty(value), mem_ty(*mem))
+        }
+        Inst::MemCopy { dst: to, src, .. } => {
+            expect("the destination", ty(to), Ty::Ptr)?;
+            expect("the source", ty(src), Ty::Ptr)
+        }
+        Inst::Call {
+            dst: to,
+            callee,
+            args,
+        } => {
+            let (params, ret) = match *callee {
+                Callee::Fun(index) => {
+                    let callee = &program.funs[index];
+                    (callee.temps[..callee.params].to_vec(), callee.ret)
+                }
+                Callee::Builtin(Builtin::Input) => (Vec::new(), Some(Ty::I32)),
+                Callee::Builtin(Builtin::Output) => (vec![Ty::I32], None),
+            };
+            if args.len() != params.len() {
+                return Err(ErrorKind::WrongArgCount {
+                    expected: params.len(),
+                    found: args.len(),
+                });
+            }
+            for (index, (arg, param)) in args.iter().zip(params).enumerate() {
+                expect(&format!("argument {}", index), ty(arg), param)?;
+            }
+            match (to, ret) {
+                (Some(to), Some(ret)) => expect("the result", dst(*to), ret),
+                (Some(_), None) => Err(ErrorKind::NoResult),
+                (None, _) => Ok(()),
+            }
+        }
+        Inst::Return(value) => match (value, fun.ret) {
+            (Some(value), Some(ret)) => {
+                expect("the return value", ty(value), ret)
+            }
+            (None, None) => Ok(()),
+            (Some(_), None) | (None, Some(_)) => {
+                Err(ErrorKind::WrongReturn { expected: fun.ret })
+            }
+        },
+        Inst::Check { cond, .. } => expect("the condition", ty(cond), Ty::I32),
+        Inst::Label(_)
+        | Inst::Jump(_)
+        | Inst::Branch { .. }
+        | Inst::Line(_) => Ok(()),
+    }
+}
+
+/// Checks that every temporary read is assigned before on every path from
+/// the start of the function, where only the arguments are. Code nothing
+/// jumps to is taken to have everything assigned.
+fn check_defs(fun: &Fun, cfg: &Cfg) -> Result<(), FunError> {
+    let all = vec![true; fun.temps.len()];
+    let mut entry = vec![false; fun.temps.len()];
+    entry[..fun.params].fill(true);
+
+    let assigned_after = |block: usize, mut assigned: Vec<bool>| {
+        for inst in &fun.code[cfg.blocks[block].insts.clone()] {
+            if let Some(dst) = inst.dst() {
+                assigned[dst.0] = true;
+            }
+        }
+        assigned
+    };
+    // What's assigned at the end of each block, until it stops changing.
+    let mut outs = vec![all.clone(); cfg.blocks.len()];
+    let mut ins = outs.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (index, block) in cfg.blocks.iter().enumerate() {
+            let assigned = if index == cfg.entry().0 {
+                entry.clone()
+            } else {
+                let mut assigned = all.clone();
+                for pred in &block.preds {
+                    for (temp, is) in assigned.iter_mut().enumerate() {
+                        *is &= outs[pred.0][temp];
+                    }
+                }
+                assigned
+            };
+            let out = assigned_after(index, assigned.clone());
+            ins[index] = assigned;
+            if out != outs[index] {
+                outs[index] = out;
+                changed = true;
+            }
+        }
+    }
+
+    for (block, assigned) in cfg.blocks.iter().zip(ins) {
+        let mut assigned = assigned;
+        for index in block.insts.clone() {
+            let inst = &fun.code[index];
+            for operand in inst.operands() {
+                if let Operand::Temp(temp) = *operand {
+                    if !assigned[temp.0] {
+                        return Err((
+                            Some(index),
+                            ErrorKind::UseBeforeDef(temp),
+                        ));
+                    }
+                }
+            }
+            if let Some(dst) = inst.dst() {
+                assigned[dst.0] = true;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_program, ErrorKind, VerifyError};
+    use crate::{
+        ast::BinOp,
+        fold::fold_program,
+        ir::{Fun, Inst, Label, Operand, Program, Temp, Ty},
+        test_support::{accept_corpus, lower_source},
+    };
+    use std::fs;
+
+    #[test]
+    fn broken_code_is_reported_where_it_is() {
+        let (t0, t1) = (Temp(0), Temp(1));
+        let verify = |temps: Vec<Ty>, code: Vec<Inst>| {
+            let fun = Fun {
+                name: "f".into(),
+                params: 1,
+                ret: Some(Ty::I32),
+                slots: Vec::new(),
+                temps,
+                labels: 2,
+                code,
+            };
+            let program = Program {
+                funs: vec![fun],
+                ..Program::default()
+            };
+            verify_program(&program).map_err(|error| {
+                assert_eq!(error.fun, "f");
+                (error.inst, error.kind)
+            })
+        };
+        let add = |dst, lhs| Inst::Binary {
+            dst,
+            op: BinOp::Add,
+            lhs,
+            rhs: Operand::Const(1),
+        };
+        let ret = |temp| Inst::Return(Some(Operand::Temp(temp)));
+
+        assert_eq!(verify(vec![Ty::I32], vec![ret(t0)]), Ok(()));
+        assert_eq!(
+            verify(vec![Ty::I32], vec![add(t1, Operand::Temp(t0)), ret(t1)]),
+            Err((Some(0), ErrorKind::UnknownTemp(t1)))
+        );
+        assert_eq!(
+            verify(vec![Ty::I32], vec![Inst::Jump(Label(1))]),
+            Err((Some(0), ErrorKind::UnplacedLabel(Label(1))))
+        );
+        assert_eq!(
+            verify(vec![Ty::I32], vec![add(t0, Operand::Temp(t0))]),
+            Err((Some(0), ErrorKind::MissingTerminator))
+        );
+        assert_eq!(
+            verify(
+                vec![Ty::I32, Ty::Ptr],
+                vec![add(t1, Operand::Temp(t0)), ret(t0)]
+            ),
+            Err((
+                Some(0),
+                ErrorKind::WrongType {
+                    operand: "the result".into(),
+                    expected: Ty::I32,
+                    found: Ty::Ptr,
+                }
+            ))
+        );
+        assert_eq!(
+            verify(vec![Ty::I32], vec![Inst::Return(None)]),
+            Err((
+                Some(0),
+                ErrorKind::WrongReturn {
+                    expected: Some(Ty::I32)
+                }
+            ))
+        );
+        // %1 is only assigned on one of the branches.
+        assert_eq!(
+            verify(
+                vec![Ty::I32, Ty::I32],
+                vec![
+                    Inst::Branch {
+                        cond: Operand::Temp(t0),
+                        then_label: Label(0),
+                        else_label: Label(1),
+                    },
+                    Inst::Label(Label(0)),
+                    add(t1, Operand::Temp(t0)),
+                    Inst::Jump(Label(1)),
+                    Inst::Label(Label(1)),
+                    ret(t1),
+                ]
+            ),
+            Err((Some(5), ErrorKind::UseBeforeDef(t1)))
+        );
+    }
+
+    #[test]
+    fn errors_say_where_they_are() {
+        let error = VerifyError {
+            fun: "main".into(),
+            inst: Some(3),
+            kind: ErrorKind::UseBeforeDef(Temp(2)),
+        };
+        assert_eq!(
+            error.to_string(),
+            "in @main, at instruction 3: %2 may be read before it's assigned"
+        );
+    }
+
+    #[test]
+    fn corpus_programs_are_valid_before_and_after_folding() {
+        for path in accept_corpus() {
+            let mut program = lower_source(&fs::read_to_string(&path).unwrap());
+            assert_eq!(verify_program(&program), Ok(()), "{}", path.display());
+            fold_program(&mut program);
+            assert_eq!(verify_program(&program), Ok(()), "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,619 @@
+//! Walking the abstract syntax tree.
+//!
+//! A pass over the tree implements `Visitor` (or `VisitorMut`, to change the
+//! tree in place), overriding the methods of the nodes it's interested in.
+//! Every method walks the children of its node by default, by calling the
+//! `walk_*` function of the node, which an overriding method can call too to
+//! go on walking:
+//!
+//! ```ignore
+//! struct CallCounter(usize);
+//!
+//! impl Visitor for CallCounter {
+//!     fn visit_expr(&mut self, nodes: &Nodes, expr: ExprId) {
+//!         if let ExprKind::Call { .. } = nodes[expr].kind {
+//!             self.0 += 1;
+//!         }
+//!         walk_expr(self, nodes, expr);
+//!     }
+//! }
+//! ```
+//!
+//! The nodes stored in the arenas of the program are visited by id, along
+//! with the arenas. The others are visited by reference, and the ones without
+//! children in the arenas without them, so that a `VisitorMut` can change
+//! them while the arenas are borrowed.
+
+use crate::ast::{
+    BlockId, Decl, ExprId, ExprKind, FunDecl, Ident, Nodes, Param, Program,
+    StmtId, StmtKind, StructDecl, VarDecl,
+};
+
+/// A pass reading the tree. Children are visited in source order.
+pub(crate) trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_decl(&mut self, nodes: &Nodes, decl: &Decl) {
+        walk_decl(self, nodes, decl);
+    }
+
+    fn visit_var_decl(&mut self, nodes: &Nodes, var_decl: &VarDecl) {
+        walk_var_decl(self, nodes, var_decl);
+    }
+
+    fn visit_fun_decl(&mut self, nodes: &Nodes, fun_decl: &FunDecl) {
+        walk_fun_decl(self, nodes, fun_decl);
+    }
+
+    fn visit_param(&mut self, nodes: &Nodes, param: &Param) {
+        walk_param(self, nodes, param);
+    }
+
+    fn visit_struct_decl(&mut self, nodes: &Nodes, struct_decl: &StructDecl) {
+        walk_struct_decl(self, nodes, struct_decl);
+    }
+
+    fn visit_block(&mut self, nodes: &Nodes, block: BlockId) {
+        walk_block(self, nodes, block);
+    }
+
+    fn visit_stmt(&mut self, nodes: &Nodes, stmt: StmtId) {
+        walk_stmt(self, nodes, stmt);
+    }
+
+    fn visit_expr(&mut self, nodes: &Nodes, expr: ExprId) {
+        walk_expr(self, nodes, expr);
+    }
+
+    /// Visits a name, either where it's declared or where it's used.
+    fn visit_ident(&mut self, _ident: &Ident) {}
+}
+
+pub(crate) fn walk_program<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    program: &Program,
+) {
+    for decl in &program.decls {
+        visitor.visit_decl(&program.nodes, decl);
+    }
+}
+
+pub(crate) fn walk_decl<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    nodes: &Nodes,
+    decl: &Decl,
+) {
+    match decl {
+        Decl::Var(var_decl) => visitor.visit_var_decl(nodes, var_decl),
+        Decl::Fun(fun_decl) => visitor.visit_fun_decl(nodes, fun_decl),
+        Decl::Struct(struct_decl) => {
+            visitor.visit_struct_decl(nodes, struct_decl)
+        }
+    }
+}
+
+pub(crate) fn walk_var_decl<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    nodes: &Nodes,
+    var_decl: &VarDecl,
+) {
+    visitor.visit_ident(&var_decl.name);
+    for &size in &var_decl.sizes {
+        visitor.visit_expr(nodes, size);
+    }
+    if let Some(init) = var_decl.init {
+        visitor.visit_expr(nodes, init);
+    }
+}
+
+pub(crate) fn walk_fun_decl<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    nodes: &Nodes,
+    fun_decl: &FunDecl,
+) {
+    visitor.visit_ident(&fun_decl.name);
+    for param in &fun_decl.params {
+        visitor.visit_param(nodes, param);
+    }
+    visitor.visit_block(nodes, fun_decl.body);
+}
+
+pub(crate) fn walk_param<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    nodes: &Nodes,
+    param: &Param,
+) {
+    visitor.visit_ident(&param.name);
+    for &size in &param.sizes {
+        visitor.visit_expr(nodes, size);
+    }
+}
+
+pub(crate) fn walk_struct_decl<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    nodes: &Nodes,
+    struct_decl: &StructDecl,
+) {
+    visitor.visit_ident(&struct_decl.name);
+    for field in &struct_decl.fields {
+        visitor.visit_var_decl(nodes, field);
+    }
+}
+
+pub(crate) fn walk_block<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    nodes: &Nodes,
+    block: BlockId,
+) {
+    let block = &nodes[block];
+    for var_decl in &block.decls {
+        visitor.visit_var_decl(nodes, var_decl);
+    }
+    for &stmt in &block.stmts {
+        visitor.visit_stmt(nodes, stmt);
+    }
+}
+
+pub(crate) fn walk_stmt<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    nodes: &Nodes,
+    stmt: StmtId,
+) {
+    match nodes[stmt].kind {
+        StmtKind::Expr(expr) => visitor.visit_expr(nodes, expr),
+        StmtKind::Empty
+        | StmtKind::Break
+        | StmtKind::Continue
+        | StmtKind::Return(None) => {}
+        StmtKind::Block(block) => visitor.visit_block(nodes, block),
+        StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr(nodes, cond);
+            visitor.visit_stmt(nodes, then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_stmt(nodes, else_branch);
+            }
+        }
+        StmtKind::While { cond, body } => {
+            visitor.visit_expr(nodes, cond);
+            visitor.visit_stmt(nodes, body);
+        }
+        StmtKind::DoWhile { body, cond } => {
+            visitor.visit_stmt(nodes, body);
+            visitor.visit_expr(nodes, cond);
+        }
+        StmtKind::Switch { cond, ref cases } => {
+            visitor.visit_expr(nodes, cond);
+            for case in cases {
+                if let Some(value) = case.value {
+                    visitor.visit_expr(nodes, value);
+                }
+                for &stmt in &case.stmts {
+                    visitor.visit_stmt(nodes, stmt);
+                }
+            }
+        }
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            for expr in [init, cond, step].into_iter().flatten() {
+                visitor.visit_expr(nodes, expr);
+            }
+            visitor.visit_stmt(nodes, body);
+        }
+        StmtKind::Return(Some(value)) => visitor.visit_expr(nodes, value),
+    }
+}
+
+pub(crate) fn walk_expr<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    nodes: &Nodes,
+    expr: ExprId,
+) {
+    match &nodes[expr].kind {
+        ExprKind::Number(_) | ExprKind::Char(_) | ExprKind::Str(_) => {}
+        ExprKind::Var(ident) => visitor.visit_ident(ident),
+        ExprKind::Index { array, index } => {
+            visitor.visit_expr(nodes, *array);
+            visitor.visit_expr(nodes, *index);
+        }
+        // The field isn't visited: it's only a name within the struct.
+        ExprKind::Member { base, .. } => visitor.visit_expr(nodes, *base),
+        ExprKind::Call { callee, args } => {
+            visitor.visit_ident(callee);
+            for &arg in args {
+                visitor.visit_expr(nodes, arg);
+            }
+        }
+        ExprKind::Assign { target, value } => {
+            visitor.visit_expr(nodes, *target);
+            visitor.visit_expr(nodes, *value);
+        }
+        ExprKind::Unary { operand, .. } => visitor.visit_expr(nodes, *operand),
+        ExprKind::Binary { lhs, rhs, .. } => {
+            visitor.visit_expr(nodes, *lhs);
+            visitor.visit_expr(nodes, *rhs);
+        }
+        ExprKind::Conditional {
+            cond,
+            then_expr,
+            else_expr,
+        } => {
+            visitor.visit_expr(nodes, *cond);
+            visitor.visit_expr(nodes, *then_expr);
+            visitor.visit_expr(nodes, *else_expr);
+        }
+    }
+}
+
+/// A pass changing the tree in place. Children are visited in source order.
+///
+/// The children of a node are looked up before they're visited, so a method
+/// can replace the node it visits, e.g. `nodes[expr] = folded`, and the
+/// walk goes on into the children of the new node.
+pub(crate) trait VisitorMut {
+    fn visit_decl_mut(&mut self, nodes: &mut Nodes, decl: &mut Decl) {
+        walk_decl_mut(self, nodes, decl);
+    }
+
+    fn visit_var_decl_mut(
+        &mut self,
+        nodes: &mut Nodes,
+        var_decl: &mut VarDecl,
+    ) {
+        walk_var_decl_mut(self, nodes, var_decl);
+    }
+
+    fn visit_fun_decl_mut(
+        &mut self,
+        nodes: &mut Nodes,
+        fun_decl: &mut FunDecl,
+    ) {
+        walk_fun_decl_mut(self, nodes, fun_decl);
+    }
+
+    fn visit_param_mut(&mut self, nodes: &mut Nodes, param: &mut Param) {
+        walk_param_mut(self, nodes, param);
+    }
+
+    fn visit_struct_decl_mut(
+        &mut self,
+        nodes: &mut Nodes,
+        struct_decl: &mut StructDecl,
+    ) {
+        walk_struct_decl_mut(self, nodes, struct_decl);
+    }
+
+    fn visit_block_mut(&mut self, nodes: &mut Nodes, block: BlockId) {
+        walk_block_mut(self, nodes, block);
+    }
+
+    fn visit_stmt_mut(&mut self, nodes: &mut Nodes, stmt: StmtId) {
+        walk_stmt_mut(self, nodes, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, nodes: &mut Nodes, expr: ExprId) {
+        walk_expr_mut(self, nodes, expr);
+    }
+
+    /// Visits a name, either where it's declared or where it's used.
+    fn visit_ident_mut(&mut self, _ident: &mut Ident) {}
+}
+
+pub(crate) fn walk_decl_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    nodes: &mut Nodes,
+    decl: &mut Decl,
+) {
+    match decl {
+        Decl::Var(var_decl) => visitor.visit_var_decl_mut(nodes, var_decl),
+        Decl::Fun(fun_decl) => visitor.visit_fun_decl_mut(nodes, fun_decl),
+        Decl::Struct(struct_decl) => {
+            visitor.visit_struct_decl_mut(nodes, struct_decl)
+        }
+    }
+}
+
+pub(crate) fn walk_var_decl_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    nodes: &mut Nodes,
+    var_decl: &mut VarDecl,
+) {
+    visitor.visit_ident_mut(&mut var_decl.name);
+    for &size in &var_decl.sizes {
+        visitor.visit_expr_mut(nodes, size);
+    }
+    if let Some(init) = var_decl.init {
+        visitor.visit_expr_mut(nodes, init);
+    }
+}
+
+pub(crate) fn walk_fun_decl_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    nodes: &mut Nodes,
+    fun_decl: &mut FunDecl,
+) {
+    visitor.visit_ident_mut(&mut fun_decl.name);
+    for param in &mut fun_decl.params {
+        visitor.visit_param_mut(nodes, param);
+    }
+    visitor.visit_block_mut(nodes, fun_decl.body);
+}
+
+pub(crate) fn walk_param_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    nodes: &mut Nodes,
+    param: &mut Param,
+) {
+    visitor.visit_ident_mut(&mut param.name);
+    for &size in &param.sizes {
+        visitor.visit_expr_mut(nodes, size);
+    }
+}
+
+pub(crate) fn walk_struct_decl_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    nodes: &mut Nodes,
+    struct_decl: &mut StructDecl,
+) {
+    visitor.visit_ident_mut(&mut struct_decl.name);
+    for field in &mut struct_decl.fields {
+        visitor.visit_var_decl_mut(nodes, field);
+    }
+}
+
+pub(crate) fn walk_block_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    nodes: &mut Nodes,
+    block: BlockId,
+) {
+    // The declarations are taken out of the block while they're visited, as
+    // they're in the arenas they're visited along with.
+    let mut decls = std::mem::take(&mut nodes[block].decls);
+    for var_decl in &mut decls {
+        visitor.visit_var_decl_mut(nodes, var_decl);
+    }
+    nodes[block].decls = decls;
+    for stmt in nodes[block].stmts.clone() {
+        visitor.visit_stmt_mut(nodes, stmt);
+    }
+}
+
+pub(crate) fn walk_stmt_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    nodes: &mut Nodes,
+    stmt: StmtId,
+) {
+    match nodes[stmt].kind {
+        StmtKind::Expr(expr) => visitor.visit_expr_mut(nodes, expr),
+        StmtKind::Empty
+        | StmtKind::Break
+        | StmtKind::Continue
+        | StmtKind::Return(None) => {}
+        StmtKind::Block(block) => visitor.visit_block_mut(nodes, block),
+        StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr_mut(nodes, cond);
+            visitor.visit_stmt_mut(nodes, then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_stmt_mut(nodes, else_branch);
+            }
+        }
+        StmtKind::While { cond, body } => {
+            visitor.visit_expr_mut(nodes, cond);
+            visitor.visit_stmt_mut(nodes, body);
+        }
+        StmtKind::DoWhile { body, cond } => {
+            visitor.visit_stmt_mut(nodes, body);
+            visitor.visit_expr_mut(nodes, cond);
+        }
+        StmtKind::Switch { cond, ref cases } => {
+            let cases = cases.clone();
+            visitor.visit_expr_mut(nodes, cond);
+            for case in cases {
+                if let Some(value) = case.value {
+                    visitor.visit_expr_mut(nodes, value);
+                }
+                for stmt in case.stmts {
+                    visitor.visit_stmt_mut(nodes, stmt);
+                }
+            }
+        }
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            for expr in [init, cond, step].into_iter().flatten() {
+                visitor.visit_expr_mut(nodes, expr);
+            }
+            visitor.visit_stmt_mut(nodes, body);
+        }
+        StmtKind::Return(Some(value)) => visitor.visit_expr_mut(nodes, value),
+    }
+}
+
+pub(crate) fn walk_expr_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    nodes: &mut Nodes,
+    expr: ExprId,
+) {
+    let (first, second) = match &mut nodes[expr].kind {
+        ExprKind::Number(_) | ExprKind::Char(_) | ExprKind::Str(_) => return,
+        ExprKind::Var(ident) => return visitor.visit_ident_mut(ident),
+        ExprKind::Call { callee, args } => {
+            visitor.visit_ident_mut(callee);
+            for arg in args.clone() {
+                visitor.visit_expr_mut(nodes, arg);
+            }
+            return;
+        }
+        ExprKind::Unary { operand, .. } => {
+            let operand = *operand;
+            return visitor.visit_expr_mut(nodes, operand);
+        }
+        ExprKind::Member { base, .. } => {
+            let base = *base;
+            return visitor.visit_expr_mut(nodes, base);
+        }
+        ExprKind::Conditional {
+            cond,
+            then_expr,
+            else_expr,
+        } => {
+            let children = [*cond, *then_expr, *else_expr];
+            for child in children {
+                visitor.visit_expr_mut(nodes, child);
+            }
+            return;
+        }
+        ExprKind::Index { array, index } => (*array, *index),
+        ExprKind::Assign { target, value } => (*target, *value),
+        ExprKind::Binary { lhs, rhs, .. } => (*lhs, *rhs),
+    };
+    visitor.visit_expr_mut(nodes, first);
+    visitor.visit_expr_mut(nodes, second);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{walk_expr, walk_expr_mut, Visitor, VisitorMut};
+    use crate::{
+        ast::{ExprId, ExprKind, Ident, Nodes, Program},
+        extensions::Extensions,
+        parser::ParseResult,
+        printer::{print_program, Indent},
+        test_support::parse_source,
+    };
+
+    fn parse(source: &str) -> Program {
+        let (
+            _,
+            ParseResult {
+                program, diag_bag, ..
+            },
+        ) = parse_source(source, Extensions::none());
+        assert!(!diag_bag.has_errors(), "errors in {:?}", source);
+        program
+    }
+
+    /// Names in the order they're visited.
+    #[derive(Default)]
+    struct Names(Vec<String>);
+
+    impl Visitor for Names {
+        fn visit_ident(&mut self, ident: &Ident) {
+            self.0.push(ident.name.clone());
+        }
+    }
+
+    #[test]
+    fn visitor_reaches_every_name_in_source_order() {
+        let program = parse(
+            "int g[2];\n\
+             int f(int a, int b[]) {\n\
+                 int c;\n\
+                 if (a) c = b[a]; else while (c) return h(c, g[0]);\n\
+             }",
+        );
+
+        let mut names = Names::default();
+        names.visit_program(&program);
+
+        assert_eq!(
+            names.0,
+            ["g", "f", "a", "b", "c", "a", "c", "b", "a", "c", "h", "c", "g"]
+        );
+    }
+
+    #[test]
+    fn overriding_a_method_can_go_on_walking() {
+        struct CallCounter(usize);
+
+        impl Visitor for CallCounter {
+            fn visit_expr(&mut self, nodes: &Nodes, expr: ExprId) {
+                if let ExprKind::Call { .. } = nodes[expr].kind {
+                    self.0 += 1;
+                }
+                walk_expr(self, nodes, expr);
+            }
+        }
+
+        let program = parse("void f(void) { f(g(1), h(i(2))); }");
+        let mut counter = CallCounter(0);
+        counter.visit_program(&program);
+
+        assert_eq!(counter.0, 4);
+    }
+
+    #[test]
+    fn mutable_visitor_changes_the_tree() {
+        /// Renames `from` to `to`, and doubles every number.
+        struct Rewriter;
+
+        impl VisitorMut for Rewriter {
+            fn visit_expr_mut(&mut self, nodes: &mut Nodes, expr: ExprId) {
+                if let ExprKind::Number(value) = &mut nodes[expr].kind {
+                    *value *= 2;
+                }
+                walk_expr_mut(self, nodes, expr);
+            }
+
+            fn visit_ident_mut(&mut self, ident: &mut Ident) {
+                if ident.name == "from" {
+                    ident.name = "to".into();
+                }
+            }
+        }
+
+        let mut program =
+            parse("int from; void f(int a[]) { from = a[1] + from * 3; }");
+        for decl in &mut program.decls {
+            Rewriter.visit_decl_mut(&mut program.nodes, decl);
+        }
+
+        assert_eq!(
+            print_program(&program, Indent::Spaces(1)),
+            "int to;\n\nvoid f(int a[]) {\n to = a[2] + to * 6;\n}\n"
+        );
+    }
+
+    #[test]
+    fn mutable_visitor_can_replace_nodes() {
+        /// Replaces `x * 1` with `x`.
+        struct Simplifier;
+
+        impl VisitorMut for Simplifier {
+            fn visit_expr_mut(&mut self, nodes: &mut Nodes, expr: ExprId) {
+                walk_expr_mut(self, nodes, expr);
+                if let ExprKind::Binary { lhs, rhs, .. } = nodes[expr].kind {
+                    if nodes[rhs].kind == ExprKind::Number(1) {
+                        nodes[expr] = nodes[lhs].clone();
+                    }
+                }
+            }
+        }
+
+        let mut program = parse("void f(void) { x = (y * 1) * 1 + 2; }");
+        for decl in &mut program.decls {
+            Simplifier.visit_decl_mut(&mut program.nodes, decl);
+        }
+
+        assert_eq!(
+            print_program(&program, Indent::Spaces(1)),
+            "void f(void) {\n x = y + 2;\n}\n"
+        );
+    }
+}
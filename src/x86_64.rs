@@ -0,0 +1,1283 @@
+//! A backend writing the three-address code of a program as x86-64 assembly,
+//! in AT&T syntax, following the System V calling convention. Shown with
+//! `--emit=asm`, it assembles and links with libc into a program for Linux:
+//!
+//! ```text
+//! csub --emit=asm prog.cm > prog.s && cc prog.s -o prog
+//! ```
+//!
+//! Temporaries are kept in the registers a call leaves alone, `%rbx` and
+//! `%r12` to `%r15`, as `regalloc` allocates them, and the ones that don't
+//! fit in 8 bytes of the frame each. The code is otherwise as naive as the
+//! three-address code it's from: an instruction loads what it needs into
+//! `%rax` and `%rcx`, works on them, and stores its result back, unless an
+//! operand or the result can be used where it is. An `i32` is sign-extended
+//! to 64 bits when loaded, so it can be added to an address. What each
+//! instruction is written as is said by the rules of `RULES`, which
+//! `isel` selects from.
+//!
+//! Only `main` is visible outside the file, so the functions and globals of
+//! a program can't clash with the ones of the C library. The builtins are
+//! the functions of the runtime, which are written after the program's. A
+//! check jumps out of the way if it fails, to the end of its function, where
+//! the function of the runtime for it is called.
+
+use crate::{
+    ast::BinOp,
+    frame::{Args, FrameLayout, ParamPlace, X86_64},
+    ir::{
+        Callee, Check, Fun, Global, Inst, Label, Mem, Operand, Program, Temp,
+        Ty,
+    },
+    isel::{select, Class, Field, Kind, Rule, Selector},
+    peephole::{optimize, Line, Pattern},
+    regalloc::{Allocation, Location, RegAlloc},
+    runtime,
+};
+use std::fmt::Write;
+
+/// The registers the first integer arguments of a call are passed in.
+const ARG_REGS: &[&str] = match X86_64.args {
+    Args::Regs { regs, .. } => regs,
+    Args::InTemps => &[],
+};
+
+/// The registers temporaries are allocated to, by their 64-bit, 32-bit and
+/// 8-bit names, those of `X86_64.saved_regs`. A function saves the ones it
+/// uses, and calls leave them as they were.
+const REGS: [(&str, &str, &str); 5] = [
+    ("%rbx", "%ebx", "%bl"),
+    ("%r12", "%r12d", "%r12b"),
+    ("%r13", "%r13d", "%r13b"),
+    ("%r14", "%r14d", "%r14b"),
+    ("%r15", "%r15d", "%r15b"),
+];
+
+/// Writes `program` as assembly, allocating temporaries to registers as
+/// `regalloc` says.
+pub(crate) fn emit_asm(program: &Program, regalloc: RegAlloc) -> String {
+    emit_asm_with_registers(program, regalloc, REGS.len())
+}
+
+/// Writes `program` as assembly, allocating temporaries to at most
+/// `registers` registers as `regalloc` says.
+pub(crate) fn emit_asm_with_registers(
+    program: &Program,
+    regalloc: RegAlloc,
+    registers: usize,
+) -> String {
+    let mut out = String::new();
+
+    // The lines of the code, as `.loc`s, are of this file.
+    if let Some(file) = &program.file {
+        let _ = writeln!(out, "\t.file 1 \"{}\"", escape(file));
+    }
+    // The globals with an initial value are in `.data`, and those that
+    // start out as 0 in `.bss`, which takes no room in the object file.
+    let (data, bss): (Vec<_>, Vec<_>) = program
+        .globals
+        .iter()
+        .partition(|global| initial_value(global).is_some());
+    if !data.is_empty() {
+        out.push_str("\t.data\n");
+    }
+    for global in data {
+        let (directive, init) =
+            initial_value(global).expect("a global in .data without a value");
+        let _ = writeln!(out, "\t.align {}", global.layout.align);
+        let _ = writeln!(out, "{}:", global.name);
+        let _ = writeln!(out, "\t{} {}", directive, init);
+    }
+    if !bss.is_empty() {
+        out.push_str("\t.bss\n");
+    }
+    for global in bss {
+        let _ = writeln!(out, "\t.align {}", global.layout.align);
+        let _ = writeln!(out, "{}:", global.name);
+        let _ = writeln!(out, "\t.zero {}", global.layout.size);
+    }
+
+    if !program.strings.is_empty() {
+        out.push_str("\t.section .rodata\n");
+    }
+    for (index, string) in program.strings.iter().enumerate() {
+        let _ = writeln!(out, ".Lstr{}:", index);
+        let _ = writeln!(out, "\t.asciz \"{}\"", escape(string));
+    }
+
+    out.push_str("\t.text\n");
+    for fun in &program.funs {
+        FunEmitter::new(program, fun, regalloc, registers).emit(&mut out);
+    }
+    for builtin in runtime::used_builtins(program) {
+        out.push_str(runtime::x86_64(builtin));
+    }
+    for check in runtime::used_checks(program) {
+        out.push_str(&runtime::x86_64_check(check));
+    }
+    // Tells the linker the stack needn't be executable.
+    out.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+    out
+}
+
+/// The directive that lays out the initial value of `global`, and the
+/// value, if it has one other than 0.
+fn initial_value(global: &Global) -> Option<(&'static str, i64)> {
+    let directive = match global.layout.size {
+        1 => ".byte",
+        4 => ".long",
+        8 => ".quad",
+        _ => return None,
+    };
+    Some((directive, global.init?))
+}
+
+/// `string` as the contents of an `.asciz` directive.
+fn escape(string: &str) -> String {
+    let mut escaped = String::new();
+    for byte in string.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => {
+                let _ = write!(escaped, "\\{:03o}", byte);
+            }
+        }
+    }
+    escaped
+}
+
+/// The rewrites of the peephole optimizer. The code never has a move
+/// between an instruction setting the flags and the one using them, so a
+/// move can be turned into an instruction that sets them.
+const PATTERNS: [Pattern; 5] = [
+    jump_to_next,
+    branch_over_jump,
+    self_move,
+    load_after_store,
+    zero_with_xor,
+];
+
+/// `jmp L` right before `L:` is dropped.
+fn jump_to_next(lines: &[Line]) -> Option<(usize, Vec<Line>)> {
+    match (lines.first()?.as_inst()?, lines.get(1)?) {
+        (("jmp", args), Line::Label(label)) if args == [label] => {
+            Some((1, Vec::new()))
+        }
+        _ => None,
+    }
+}
+
+/// `jne A; jmp B; A:` is `je B; A:`.
+fn branch_over_jump(lines: &[Line]) -> Option<(usize, Vec<Line>)> {
+    match (
+        lines.first()?.as_inst()?,
+        lines.get(1)?.as_inst()?,
+        lines.get(2)?,
+    ) {
+        (("jne", then_args), ("jmp", else_args), Line::Label(label))
+            if then_args == [label] =>
+        {
+            Some((2, vec![Line::inst(&format!("je {}", else_args[0]))]))
+        }
+        _ => None,
+    }
+}
+
+/// `movq X, X` is dropped. `movl %eax, %eax` isn't, as it clears the upper
+/// half of `%rax`.
+fn self_move(lines: &[Line]) -> Option<(usize, Vec<Line>)> {
+    match lines.first()?.as_inst()? {
+        ("movq", args) if args.len() == 2 && args[0] == args[1] => {
+            Some((1, Vec::new()))
+        }
+        _ => None,
+    }
+}
+
+/// A load of what was just stored takes it from the register it was
+/// stored from: `movl %ecx, M; movl M, %eax` is `movl %ecx, M;
+/// movl %ecx, %eax`, and so for `movq`, and for `movslq` after `movl`.
+fn load_after_store(lines: &[Line]) -> Option<(usize, Vec<Line>)> {
+    let (store, stored) = lines.first()?.as_inst()?;
+    let (load, loaded) = lines.get(1)?.as_inst()?;
+    let [value, memory] = stored[..] else {
+        return None;
+    };
+    let [from, to] = loaded[..] else {
+        return None;
+    };
+    let is_memory = memory.ends_with(')');
+    if !value.starts_with('%') || !is_memory || from != memory {
+        return None;
+    }
+    let replacement = match (store, load) {
+        ("movl", "movl") | ("movq", "movq") if value == to => Vec::new(),
+        ("movl", "movl") | ("movq", "movq") | ("movl", "movslq") => {
+            vec![Line::inst(&format!("{} {}, {}", load, value, to))]
+        }
+        _ => return None,
+    };
+    Some((
+        2,
+        [lines[0].clone()].into_iter().chain(replacement).collect(),
+    ))
+}
+
+/// `movq $0, %reg` is `xorl` of its 32-bit half with itself, which is
+/// shorter, and clears all of it.
+fn zero_with_xor(lines: &[Line]) -> Option<(usize, Vec<Line>)> {
+    match lines.first()?.as_inst()? {
+        ("movq", args) if args.first() == Some(&"$0") => {
+            let reg = reg32(args.get(1)?)?;
+            Some((1, vec![Line::inst(&format!("xorl {}, {}", reg, reg))]))
+        }
+        _ => None,
+    }
+}
+
+/// The 32-bit half of the 64-bit register `reg`, if it's one.
+fn reg32(reg: &str) -> Option<String> {
+    let name = reg.strip_prefix('%')?;
+    if let Some(number) = name.strip_prefix('r').filter(|rest| {
+        rest.chars().all(|c| c.is_ascii_digit()) && !rest.is_empty()
+    }) {
+        return Some(format!("%r{}d", number));
+    }
+    match name {
+        "rax" | "rbx" | "rcx" | "rdx" | "rsi" | "rdi" => {
+            Some(format!("%e{}", &name[1..]))
+        }
+        _ => None,
+    }
+}
+
+struct FunEmitter<'a> {
+    program: &'a Program,
+    fun: &'a Fun,
+    /// Its code, which is gone through by the peephole optimizer before
+    /// it's written out.
+    lines: Vec<Line>,
+    allocation: Allocation,
+    /// Where everything in its frame is, as an offset from `%rbp`.
+    frame: FrameLayout,
+    /// The checks in its code, with their lines, each failing to the
+    /// label at its index, after the code.
+    checks: Vec<(Check, usize)>,
+    /// How many divisions its code has, each with a label of its own.
+    divisions: usize,
+}
+
+/// Where a temporary is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Place {
+    /// The register at this index of `REGS`.
+    Reg(usize),
+    /// The frame, at this offset from `%rbp`.
+    Frame(i64),
+}
+
+impl<'a> FunEmitter<'a> {
+    fn new(
+        program: &'a Program,
+        fun: &'a Fun,
+        regalloc: RegAlloc,
+        registers: usize,
+    ) -> Self {
+        let allocation = regalloc.allocate(fun, registers.min(REGS.len()));
+        let frame = FrameLayout::new(
+            &X86_64,
+            fun.params,
+            fun.slots.iter().map(|slot| slot.layout),
+            allocation.registers_used,
+            allocation.spill_slots,
+        );
+        FunEmitter {
+            program,
+            fun,
+            lines: Vec::new(),
+            allocation,
+            frame,
+            checks: Vec::new(),
+            divisions: 0,
+        }
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        self.lines.push(Line::inst(line.as_ref()));
+    }
+
+    fn emit(mut self, out: &mut String) {
+        let name = &self.fun.name;
+        if name == "main" {
+            let _ = writeln!(out, "\t.globl main");
+        }
+        let _ = writeln!(out, "\t.type {}, @function", name);
+        let _ = writeln!(out, "{}:", name);
+        // The prologue is of the line of the function.
+        let first_line = self.fun.code.iter().find_map(|inst| match inst {
+            Inst::Line(line) => Some(*line),
+            _ => None,
+        });
+        if let Some(line) = first_line {
+            self.line(format!(".loc 1 {}", line));
+        }
+        self.line("pushq %rbp");
+        self.line("movq %rsp, %rbp");
+        if self.frame.size > 0 {
+            self.line(format!("subq ${}, %rsp", self.frame.size));
+        }
+        let saved_regs = self.frame.saved_regs.clone();
+        for ((reg, _, _), offset) in REGS.iter().zip(saved_regs) {
+            self.line(format!("movq {}, {}(%rbp)", reg, offset));
+        }
+        for index in 0..self.fun.params {
+            let temp = self.temp64(Temp(index));
+            match self.frame.params[index] {
+                ParamPlace::Reg(reg) => {
+                    self.line(format!("movq {}, {}", reg, temp))
+                }
+                ParamPlace::Temp => {
+                    unreachable!("x86-64 passes no arguments in temporaries")
+                }
+                ParamPlace::Stack(arg) => match self.place(Temp(index)) {
+                    Place::Reg(_) => {
+                        self.line(format!("movq {}(%rbp), {}", arg, temp))
+                    }
+                    Place::Frame(_) => {
+                        self.line(format!("movq {}(%rbp), %rax", arg));
+                        self.line(format!("movq %rax, {}", temp));
+                    }
+                },
+            }
+        }
+        for inst in &self.fun.code {
+            select(&mut self, RULES, inst);
+        }
+        for (index, (check, line)) in
+            self.checks.clone().into_iter().enumerate()
+        {
+            let label = self.check_label(index);
+            self.lines.push(Line::Label(label));
+            if self.program.file.is_some() {
+                self.line(format!(".loc 1 {}", line));
+            }
+            self.line(format!("movl ${}, %edi", line));
+            self.line(format!("call {}", runtime::check_symbol(check)));
+        }
+        optimize(&mut self.lines, &PATTERNS);
+        for line in &self.lines {
+            let _ = writeln!(out, "{}", line);
+        }
+    }
+
+    fn place(&self, temp: Temp) -> Place {
+        match self.allocation.locations[temp.0] {
+            Location::Reg(reg) => Place::Reg(reg),
+            Location::Spill(slot) => Place::Frame(self.frame.spill_slots[slot]),
+        }
+    }
+
+    /// `temp` as an operand of a 64-bit instruction.
+    fn temp64(&self, temp: Temp) -> String {
+        match self.place(temp) {
+            Place::Reg(reg) => REGS[reg].0.to_string(),
+            Place::Frame(offset) => format!("{}(%rbp)", offset),
+        }
+    }
+
+    /// `temp` as an operand of a 32-bit instruction.
+    fn temp32(&self, temp: Temp) -> String {
+        match self.place(temp) {
+            Place::Reg(reg) => REGS[reg].1.to_string(),
+            Place::Frame(offset) => format!("{}(%rbp)", offset),
+        }
+    }
+
+    /// The 64-bit and 32-bit names of the register to work out `dst` in:
+    /// its own if it's in one, other than that of `clobbered`, which is
+    /// read after the register is written, and `%rax` if not.
+    fn work_reg(
+        &self,
+        dst: Temp,
+        clobbered: Option<&Operand>,
+    ) -> (&'static str, &'static str) {
+        let place = self.place(dst);
+        let is_clobbered = matches!(
+            clobbered,
+            Some(Operand::Temp(temp)) if self.place(*temp) == place
+        );
+        match place {
+            Place::Reg(reg) if !is_clobbered => (REGS[reg].0, REGS[reg].1),
+            _ => ("%rax", "%eax"),
+        }
+    }
+
+    /// Stores `%rax` into `dst`, unless it's worked out in its register.
+    fn finish(&mut self, dst: Temp, work_reg: &str) {
+        if work_reg == "%rax" {
+            self.store(dst);
+        }
+    }
+
+    fn label(&self, label: Label) -> String {
+        format!(".L{}_{}", self.fun.name, label.0)
+    }
+
+    /// The label the check at `index` of `checks` fails to.
+    fn check_label(&self, index: usize) -> String {
+        format!(".L{}.check{}", self.fun.name, index)
+    }
+
+    /// `operand` as an operand of an instruction on `mem`, if it's a
+    /// constant that fits or a temporary in a register.
+    fn direct(&self, operand: &Operand, mem: Mem) -> Option<String> {
+        match *operand {
+            Operand::Const(value) => match mem {
+                Mem::I8 => Some(format!("${}", value as i8)),
+                _ => {
+                    i32::try_from(value).ok().map(|value| format!("${}", value))
+                }
+            },
+            Operand::Temp(temp) => match self.place(temp) {
+                Place::Reg(reg) => Some(
+                    match mem {
+                        Mem::I8 => REGS[reg].2,
+                        Mem::I32 => REGS[reg].1,
+                        Mem::Ptr => REGS[reg].0,
+                    }
+                    .to_string(),
+                ),
+                Place::Frame(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// `operand`, an `i32`, as the source of a 32-bit instruction, loading
+    /// it into `%ecx` if it's neither a temporary nor a constant that fits.
+    fn src32(&mut self, operand: &Operand) -> String {
+        match *operand {
+            Operand::Temp(temp) if self.fun.temps[temp.0] == Ty::I32 => {
+                self.temp32(temp)
+            }
+            Operand::Const(value) if i32::try_from(value).is_ok() => {
+                format!("${}", value)
+            }
+            _ => {
+                self.load(operand, "%rcx");
+                "%ecx".into()
+            }
+        }
+    }
+
+    /// Loads `operand` into the 64-bit register `reg`.
+    fn load(&mut self, operand: &Operand, reg: &str) {
+        let line = match *operand {
+            Operand::Temp(temp) => match self.fun.temps[temp.0] {
+                Ty::I32 => format!("movslq {}, {}", self.temp32(temp), reg),
+                Ty::Ptr => format!("movq {}, {}", self.temp64(temp), reg),
+            },
+            Operand::Const(value) if i32::try_from(value).is_ok() => {
+                format!("movq ${}, {}", value, reg)
+            }
+            Operand::Const(value) => format!("movabsq ${}, {}", value, reg),
+            Operand::Slot(index) => {
+                format!("leaq {}(%rbp), {}", self.frame.slots[index], reg)
+            }
+            Operand::Global(index) => format!(
+                "leaq {}(%rip), {}",
+                self.program.globals[index].name, reg
+            ),
+            Operand::Str(index) => {
+                format!("leaq .Lstr{}(%rip), {}", index, reg)
+            }
+        };
+        self.line(line);
+    }
+
+    /// Stores `%rax` into `dst`.
+    fn store(&mut self, dst: Temp) {
+        match self.fun.temps[dst.0] {
+            Ty::I32 => {
+                let temp = self.temp32(dst);
+                self.line(format!("movl %eax, {}", temp))
+            }
+            Ty::Ptr => {
+                let temp = self.temp64(dst);
+                self.line(format!("movq %rax, {}", temp))
+            }
+        }
+    }
+
+    /// The memory at the address `addr`, which is loaded into `%rax` if it
+    /// isn't that of a variable or in a register.
+    fn memory_at(&mut self, addr: &Operand) -> String {
+        match *addr {
+            Operand::Slot(index) => {
+                return format!("{}(%rbp)", self.frame.slots[index])
+            }
+            Operand::Global(index) => {
+                return format!("{}(%rip)", self.program.globals[index].name)
+            }
+            _ => {}
+        }
+        match self.direct(addr, Mem::Ptr) {
+            Some(reg) if !reg.starts_with('$') => format!("({})", reg),
+            _ => {
+                self.load(addr, "%rax");
+                "(%rax)".into()
+            }
+        }
+    }
+
+    /// Sets the flags to whether `cond` is 0.
+    fn test(&mut self, cond: &Operand) {
+        match (cond, self.direct(cond, Mem::Ptr)) {
+            (Operand::Temp(temp), Some(_)) => {
+                let line = match self.fun.temps[temp.0] {
+                    Ty::I32 => {
+                        let reg = self.temp32(*temp);
+                        format!("testl {}, {}", reg, reg)
+                    }
+                    Ty::Ptr => {
+                        let reg = self.temp64(*temp);
+                        format!("testq {}, {}", reg, reg)
+                    }
+                };
+                self.line(line);
+            }
+            (Operand::Temp(temp), None) => {
+                let line = match self.fun.temps[temp.0] {
+                    Ty::I32 => format!("cmpl $0, {}", self.temp32(*temp)),
+                    Ty::Ptr => format!("cmpq $0, {}", self.temp64(*temp)),
+                };
+                self.line(line);
+            }
+            _ => {
+                self.load(cond, "%rax");
+                self.line("testq %rax, %rax");
+            }
+        }
+    }
+
+    /// The operand of `inst` by the name of its field.
+    fn operand(inst: &Inst, name: &str) -> Operand {
+        Field::from_name(name)
+            .and_then(|field| field.of(inst))
+            .unwrap_or_else(|| panic!("{:?} has no `{}`", inst, name))
+    }
+}
+
+/// How each instruction is selected. An instruction works in `%rax` and
+/// `%rcx`, unless an operand can be used where it is, or its result can be
+/// worked out in the register of its temporary.
+const RULES: &[Rule] = &[
+    Rule::new(Kind::Copy, &["@load src {dst:64}"])
+        .when(&[(Field::Dst, Class::Reg)]),
+    Rule::new(Kind::Copy, &["movl {src:32}, {dst:32}"])
+        .when(&[(Field::Dst, Class::I32), (Field::Src, Class::Imm)]),
+    Rule::new(Kind::Copy, &["movl {src:32}, {dst:32}"])
+        .when(&[(Field::Dst, Class::I32), (Field::Src, Class::Reg)]),
+    Rule::new(Kind::Copy, &["movq {src:64}, {dst:64}"])
+        .when(&[(Field::Dst, Class::Ptr), (Field::Src, Class::Imm)]),
+    Rule::new(Kind::Copy, &["movq {src:64}, {dst:64}"])
+        .when(&[(Field::Dst, Class::Ptr), (Field::Src, Class::Reg)]),
+    Rule::new(Kind::Copy, &["@load src %rax", "@store dst"]),
+    Rule::new(
+        Kind::Neg,
+        &[
+            "@load src {dst:work64}",
+            "negl {dst:work32}",
+            "@finish dst {dst:work64}",
+        ],
+    ),
+    // Addresses are compared as unsigned numbers.
+    Rule::new(Kind::Binary, COMPARE_ADDRESSES)
+        .when(&[(Field::Lhs, Class::Ptr)]),
+    Rule::new(Kind::Binary, COMPARE_ADDRESSES)
+        .when(&[(Field::Rhs, Class::Ptr)]),
+    Rule::new(
+        Kind::Binary,
+        &[
+            "@load lhs {dst:work64/rhs}",
+            "{op:arith} {rhs:src32}, {dst:work32/rhs}",
+            "@finish dst {dst:work64/rhs}",
+        ],
+    )
+    .ops(&[BinOp::Add, BinOp::Sub, BinOp::Mul]),
+    // `idivl` traps on the one quotient that doesn't fit, `INT_MIN / -1`, so
+    // both operands are negated when the divisor is -1, and the quotient
+    // wraps to `INT_MIN`, as everywhere else.
+    Rule::new(
+        Kind::Binary,
+        &[
+            "@load lhs %rax",
+            "@load rhs %rcx",
+            "cmpl $-1, %ecx",
+            "jne {divide}",
+            "negl %eax",
+            "negl %ecx",
+            "{divide}:",
+            "cltd",
+            "idivl %ecx",
+            "@store dst",
+        ],
+    )
+    .ops(&[BinOp::Div]),
+    Rule::new(
+        Kind::Binary,
+        &[
+            "@load lhs %rax",
+            "cmpl {rhs:src32}, %eax",
+            "set{op:signed} %al",
+            "movzbl %al, {dst:work32}",
+            "@finish dst {dst:work64}",
+        ],
+    ),
+    Rule::new(
+        Kind::Offset,
+        &[
+            "@load base {dst:work64/bytes}",
+            "addq {bytes:64}, {dst:work64/bytes}",
+            "@finish dst {dst:work64/bytes}",
+        ],
+    )
+    .when(&[(Field::Bytes, Class::Imm)]),
+    Rule::new(
+        Kind::Offset,
+        &[
+            "@load base {dst:work64/bytes}",
+            "@load bytes %rcx",
+            "addq %rcx, {dst:work64/bytes}",
+            "@finish dst {dst:work64/bytes}",
+        ],
+    ),
+    Rule::new(
+        Kind::Diff,
+        &[
+            "@load lhs %rax",
+            "@load rhs %rcx",
+            "subq %rcx, %rax",
+            "@store dst",
+        ],
+    ),
+    Rule::new(
+        Kind::Load,
+        &[
+            "movzbl {addr:mem}, {dst:work32}",
+            "@finish dst {dst:work64}",
+        ],
+    )
+    .mem(Mem::I8),
+    Rule::new(
+        Kind::Load,
+        &["movl {addr:mem}, {dst:work32}", "@finish dst {dst:work64}"],
+    )
+    .mem(Mem::I32),
+    Rule::new(
+        Kind::Load,
+        &["movq {addr:mem}, {dst:work64}", "@finish dst {dst:work64}"],
+    )
+    .mem(Mem::Ptr),
+    // A `char` is stored by the low byte of any constant.
+    Rule::new(Kind::Store, &["movb {value:8}, {addr:mem}"])
+        .mem(Mem::I8)
+        .when(&[(Field::Value, Class::Const)]),
+    Rule::new(Kind::Store, &["movb {value:8}, {addr:mem}"])
+        .mem(Mem::I8)
+        .when(&[(Field::Value, Class::Reg)]),
+    Rule::new(Kind::Store, &["@load value %rcx", "movb %cl, {addr:mem}"])
+        .mem(Mem::I8),
+    Rule::new(Kind::Store, &["movl {value:32}, {addr:mem}"])
+        .mem(Mem::I32)
+        .when(&[(Field::Value, Class::Imm)]),
+    Rule::new(Kind::Store, &["movl {value:32}, {addr:mem}"])
+        .mem(Mem::I32)
+        .when(&[(Field::Value, Class::Reg)]),
+    Rule::new(Kind::Store, &["@load value %rcx", "movl %ecx, {addr:mem}"])
+        .mem(Mem::I32),
+    Rule::new(Kind::Store, &["movq {value:64}, {addr:mem}"])
+        .mem(Mem::Ptr)
+        .when(&[(Field::Value, Class::Imm)]),
+    Rule::new(Kind::Store, &["movq {value:64}, {addr:mem}"])
+        .mem(Mem::Ptr)
+        .when(&[(Field::Value, Class::Reg)]),
+    Rule::new(Kind::Store, &["@load value %rcx", "movq %rcx, {addr:mem}"])
+        .mem(Mem::Ptr),
+    Rule::new(
+        Kind::MemCopy,
+        &[
+            "@load dst %rdi",
+            "@load src %rsi",
+            "movq ${size}, %rcx",
+            "rep movsb",
+        ],
+    ),
+    Rule::new(Kind::Call, &["@push-args", "call {callee}", "@pop-args"])
+        .when(&[(Field::Dst, Class::Absent)]),
+    Rule::new(
+        Kind::Call,
+        &["@push-args", "call {callee}", "@pop-args", "@store dst"],
+    ),
+    Rule::new(Kind::Label, &["{label}:"]),
+    Rule::new(Kind::Jump, &["jmp {label}"]),
+    Rule::new(Kind::Branch, &["@test cond", "jne {then}", "jmp {else}"]),
+    Rule::new(Kind::Return, &["@exit-status", "@restore", "leave", "ret"])
+        .when(&[(Field::Value, Class::Absent)]),
+    Rule::new(
+        Kind::Return,
+        &["@load value %rax", "@restore", "leave", "ret"],
+    ),
+    Rule::new(Kind::Check, &["@test cond", "je {check}"]),
+    Rule::new(Kind::Line, &[".loc 1 {line}"]),
+];
+
+const COMPARE_ADDRESSES: &[&str] = &[
+    "@load lhs %rax",
+    "@load rhs %rcx",
+    "cmpq %rcx, %rax",
+    "set{op:unsigned} %al",
+    "movzbl %al, {dst:work32}",
+    "@finish dst {dst:work64}",
+];
+
+impl Selector for FunEmitter<'_> {
+    fn fun(&self) -> &Fun {
+        self.fun
+    }
+
+    fn in_register(&self, temp: Temp) -> bool {
+        matches!(self.place(temp), Place::Reg(_))
+    }
+
+    fn is_immediate(&self, value: i64) -> bool {
+        i32::try_from(value).is_ok()
+    }
+
+    /// Writes an operand as `64`, `32` or `8` bits, as `src32`, as the
+    /// memory at it, `mem`, or the register to work out `dst` in, `work64`
+    /// or `work32`, after which comes the operand it mustn't clobber.
+    fn placeholder(&mut self, inst: &Inst, name: &str, form: &str) -> String {
+        match (name, inst) {
+            ("op", Inst::Binary { op, .. }) => {
+                return match (form, op) {
+                    ("arith", BinOp::Add) => "addl",
+                    ("arith", BinOp::Sub) => "subl",
+                    ("arith", BinOp::Mul) => "imull",
+                    ("signed", BinOp::Less) => "l",
+                    ("signed", BinOp::LessEqual) => "le",
+                    ("signed", BinOp::Greater) => "g",
+                    ("signed", BinOp::GreaterEqual) => "ge",
+                    ("unsigned", BinOp::Less) => "b",
+                    ("unsigned", BinOp::LessEqual) => "be",
+                    ("unsigned", BinOp::Greater) => "a",
+                    ("unsigned", BinOp::GreaterEqual) => "ae",
+                    ("signed" | "unsigned", BinOp::Equal) => "e",
+                    ("signed" | "unsigned", BinOp::NotEqual) => "ne",
+                    _ => panic!("{:?} has no `{}` form", op, form),
+                }
+                .into()
+            }
+            ("label", Inst::Label(label) | Inst::Jump(label))
+            | (
+                "then",
+                Inst::Branch {
+                    then_label: label, ..
+                },
+            )
+            | (
+                "else",
+                Inst::Branch {
+                    else_label: label, ..
+                },
+            ) => return self.label(*label),
+            ("size", Inst::MemCopy { size, .. }) => return size.to_string(),
+            ("line", Inst::Line(line)) => return line.to_string(),
+            ("divide", Inst::Binary { .. }) => {
+                self.divisions += 1;
+                return format!(
+                    ".L{}.div{}",
+                    self.fun.name,
+                    self.divisions - 1
+                );
+            }
+            ("check", Inst::Check { check, line, .. }) => {
+                let label = self.check_label(self.checks.len());
+                self.checks.push((*check, *line));
+                return label;
+            }
+            ("callee", Inst::Call { callee, .. }) => {
+                return match *callee {
+                    Callee::Fun(index) => self.program.funs[index].name.clone(),
+                    Callee::Builtin(builtin) => runtime::symbol(builtin).into(),
+                }
+            }
+            _ => {}
+        }
+        let operand = Self::operand(inst, name);
+        let (form, clobbered) = match form.split_once('/') {
+            Some((form, clobbered)) => {
+                (form, Some(Self::operand(inst, clobbered)))
+            }
+            None => (form, None),
+        };
+        match (form, operand) {
+            ("64", Operand::Temp(temp)) => self.temp64(temp),
+            ("32", Operand::Temp(temp)) => self.temp32(temp),
+            ("8", Operand::Temp(temp)) => match self.place(temp) {
+                Place::Reg(reg) => REGS[reg].2.into(),
+                Place::Frame(offset) => format!("{}(%rbp)", offset),
+            },
+            ("64" | "32", Operand::Const(value)) => format!("${}", value),
+            ("8", Operand::Const(value)) => format!("${}", value as i8),
+            ("src32", operand) => self.src32(&operand),
+            ("mem", operand) => self.memory_at(&operand),
+            ("work64" | "work32", Operand::Temp(dst)) => {
+                let (reg64, reg32) = self.work_reg(dst, clobbered.as_ref());
+                if form == "work64" { reg64 } else { reg32 }.into()
+            }
+            _ => panic!("`{}` has no `{}` form for {:?}", name, form, inst),
+        }
+    }
+
+    /// `@load`s an operand into a 64-bit register, `@store`s `%rax` into
+    /// one, or `@finish`es one worked out in a register, `@test`s one, and
+    /// does the parts of calls and returns of more than a line.
+    fn action(&mut self, inst: &Inst, name: &str, args: &str) {
+        let mut args = args.split(' ');
+        let mut operand = || Self::operand(inst, args.next().unwrap());
+        match name {
+            "load" => {
+                let operand = operand();
+                self.load(&operand, args.next().unwrap());
+            }
+            "store" | "finish" => {
+                let Operand::Temp(dst) = operand() else {
+                    panic!("{:?} stores into no temporary", inst)
+                };
+                match args.next() {
+                    Some(reg) => self.finish(dst, reg),
+                    None => self.store(dst),
+                }
+            }
+            "test" => {
+                let cond = operand();
+                self.test(&cond);
+            }
+            // The first six arguments are passed in registers and the rest
+            // on the stack, which is kept aligned to 16 bytes.
+            "push-args" => {
+                let Inst::Call { args, .. } = inst else {
+                    panic!("{:?} isn't a call", inst)
+                };
+                let (on_stack, padding) = X86_64.stack_args(args.len());
+                if padding > 0 {
+                    self.line(format!("subq ${}, %rsp", padding));
+                }
+                for arg in args[args.len() - on_stack..].iter().rev() {
+                    self.load(arg, "%rax");
+                    self.line("pushq %rax");
+                }
+                for (arg, reg) in args.iter().zip(ARG_REGS) {
+                    self.load(arg, reg);
+                }
+            }
+            "pop-args" => {
+                let Inst::Call { args, .. } = inst else {
+                    panic!("{:?} isn't a call", inst)
+                };
+                let (on_stack, padding) = X86_64.stack_args(args.len());
+                let pushed = X86_64.word * on_stack as u64 + padding;
+                if pushed > 0 {
+                    self.line(format!("addq ${}, %rsp", pushed));
+                }
+            }
+            // What `main` returns is the exit status of the program.
+            "exit-status" => {
+                if self.fun.name == "main" {
+                    self.line("xorl %eax, %eax");
+                }
+            }
+            "restore" => {
+                let saved_regs = self.frame.saved_regs.clone();
+                for ((reg, _, _), offset) in REGS.iter().zip(saved_regs) {
+                    self.line(format!("movq {}(%rbp), {}", offset, reg));
+                }
+            }
+            _ => panic!("no action `@{}`", name),
+        }
+    }
+
+    fn emit_line(&mut self, line: String) {
+        match line.strip_suffix(':') {
+            Some(label) => self.lines.push(Line::Label(label.into())),
+            None => self.lines.push(Line::inst(&line)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{emit_asm, emit_asm_with_registers, PATTERNS, REGS};
+    use crate::{
+        extensions::Extensions,
+        ir::{
+            lower_program, lower_program_with_checks, lower_program_with_lines,
+            Checks,
+        },
+        parser::ParseResult,
+        peephole::{optimize, Line},
+        regalloc::RegAlloc,
+        sema::{check_program, Builtin},
+        source_map::SourceFile,
+        test_support::{
+            accept_corpus, analyze_source, check_runs_as_interpreted,
+            parse_source,
+        },
+    };
+    use std::{
+        env, fs,
+        io::Write,
+        process::{Command, Stdio},
+    };
+
+    fn hir(source: &str) -> crate::hir::Program {
+        let (_, analysis) = analyze_source(source);
+        analysis.hir
+    }
+
+    #[test]
+    fn every_temporary_is_in_the_frame_without_registers() {
+        let source = "int n = 3;\n\
+                      int twice(int x) { return x + x; }\n";
+
+        assert_eq!(
+            emit_asm_with_registers(
+                &lower_program(&hir(source)),
+                RegAlloc::Linear,
+                0
+            ),
+            "\t.data\n\
+             \t.align 4\n\
+             n:\n\
+             \t.long 3\n\
+             \t.text\n\
+             \t.type twice, @function\n\
+             twice:\n\
+             \tpushq %rbp\n\
+             \tmovq %rsp, %rbp\n\
+             \tsubq $48, %rsp\n\
+             \tmovq %rdi, -8(%rbp)\n\
+             \tmovslq -8(%rbp), %rcx\n\
+             \tmovl %ecx, -36(%rbp)\n\
+             \tmovl %ecx, %eax\n\
+             \tmovl %eax, -16(%rbp)\n\
+             \tmovl -36(%rbp), %eax\n\
+             \tmovl %eax, -24(%rbp)\n\
+             \tmovslq -16(%rbp), %rax\n\
+             \taddl -24(%rbp), %eax\n\
+             \tmovl %eax, -32(%rbp)\n\
+             \tmovslq %eax, %rax\n\
+             \tleave\n\
+             \tret\n\
+             \t.section .note.GNU-stack,\"\",@progbits\n"
+        );
+    }
+
+    #[test]
+    fn globals_that_start_out_as_zero_are_in_bss() {
+        let source = "int n = 3; int zero; int a[4]; char c = 'a';\n";
+
+        let asm = emit_asm(&lower_program(&hir(source)), RegAlloc::Linear);
+
+        assert_eq!(
+            asm,
+            "\t.data\n\
+             \t.align 4\n\
+             n:\n\
+             \t.long 3\n\
+             \t.align 1\n\
+             c:\n\
+             \t.byte 97\n\
+             \t.bss\n\
+             \t.align 4\n\
+             zero:\n\
+             \t.zero 4\n\
+             \t.align 4\n\
+             a:\n\
+             \t.zero 16\n\
+             \t.text\n\
+             \t.section .note.GNU-stack,\"\",@progbits\n"
+        );
+    }
+
+    #[test]
+    fn temporaries_are_kept_in_registers_calls_leave_alone() {
+        let source = "int twice(int x) { return x + x; }\n\
+                      int f(int y) { return twice(y) * y; }\n";
+        let asm = emit_asm(&lower_program(&hir(source)), RegAlloc::Linear);
+        let f = &asm[asm.find("f:").unwrap()..];
+
+        assert_eq!(
+            f,
+            "f:\n\
+             \tpushq %rbp\n\
+             \tmovq %rsp, %rbp\n\
+             \tsubq $32, %rsp\n\
+             \tmovq %rbx, -8(%rbp)\n\
+             \tmovq %r12, -16(%rbp)\n\
+             \tmovq %r13, -24(%rbp)\n\
+             \tmovq %rdi, %rbx\n\
+             \tmovl %ebx, -28(%rbp)\n\
+             \tmovslq %ebx, %rdi\n\
+             \tcall twice\n\
+             \tmovl %eax, %r12d\n\
+             \tmovl -28(%rbp), %ebx\n\
+             \tmovslq %r12d, %r13\n\
+             \timull %ebx, %r13d\n\
+             \tmovslq %r13d, %rax\n\
+             \tmovq -8(%rbp), %rbx\n\
+             \tmovq -16(%rbp), %r12\n\
+             \tmovq -24(%rbp), %r13\n\
+             \tleave\n\
+             \tret\n\
+             \t.section .note.GNU-stack,\"\",@progbits\n"
+        );
+    }
+
+    #[test]
+    fn peephole_patterns_drop_moves_and_jumps() {
+        let mut lines: Vec<_> = [
+            "movq $0, %rbx",
+            "movq %r12, %r12",
+            "movl %eax, -4(%rbp)",
+            "movslq -4(%rbp), %rcx",
+            "movq %rcx, -16(%rbp)",
+            "movq -16(%rbp), %rcx",
+            "jne .Lf_1",
+            "jmp .Lf_2",
+        ]
+        .into_iter()
+        .map(Line::inst)
+        .chain([Line::Label(".Lf_1".into()), Line::inst("jmp .Lf_3")])
+        .chain([Line::Label(".Lf_3".into()), Line::inst("ret")])
+        .collect();
+
+        optimize(&mut lines, &PATTERNS);
+        let text: Vec<_> = lines.iter().map(Line::to_string).collect();
+        assert_eq!(
+            text,
+            [
+                "\txorl %ebx, %ebx",
+                "\tmovl %eax, -4(%rbp)",
+                "\tmovslq %eax, %rcx",
+                "\tmovq %rcx, -16(%rbp)",
+                "\tje .Lf_2",
+                ".Lf_1:",
+                ".Lf_3:",
+                "\tret",
+            ]
+        );
+    }
+
+    #[test]
+    fn registers_take_fewer_instructions_on_the_corpus() {
+        let instructions = |asm: &str| {
+            asm.lines()
+                .filter(|line| {
+                    line.starts_with('\t') && !line.starts_with("\t.")
+                })
+                .count()
+        };
+        let (mut with, mut without) = (0, 0);
+        for path in accept_corpus() {
+            let source = fs::read_to_string(&path).unwrap();
+            let program = lower_program(&hir(&source));
+            with += instructions(&emit_asm(&program, RegAlloc::Linear));
+            without += instructions(&emit_asm_with_registers(
+                &program,
+                RegAlloc::Linear,
+                0,
+            ));
+        }
+        assert!(with * 10 < without * 9, "{} vs {}", with, without);
+    }
+
+    /// The assembly of `source`, lowered with `checks`.
+    fn checked_asm(source: &str, checks: Checks) -> String {
+        let (source_file, ParseResult { program, .. }) =
+            parse_source(source, Extensions::all());
+        let hir = check_program(&program, &Builtin::ALL).hir;
+        emit_asm(
+            &lower_program_with_checks(&hir, checks, Some(&source_file)),
+            RegAlloc::Linear,
+        )
+    }
+
+    /// Compiles `asm` with `cc` and runs it with `input`, returning its exit
+    /// code and what it wrote to stderr, unless there's no `cc`.
+    fn run_failing(
+        name: &str,
+        asm: &str,
+        input: &str,
+    ) -> Option<(i32, String)> {
+        let dir = env::temp_dir().join(format!(
+            "csub-x86_64-{}-{}",
+            std::process::id(),
+            name
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let (asm_path, exe) = (dir.join("prog.s"), dir.join("prog"));
+        fs::write(&asm_path, asm).unwrap();
+        let Ok(status) = Command::new("cc")
+            .arg(&asm_path)
+            .arg("-o")
+            .arg(&exe)
+            .status()
+        else {
+            return None;
+        };
+        assert!(status.success());
+        let mut child = Command::new(&exe)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        Some((
+            output.status.code().unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn failed_checks_jump_to_a_call_reporting_their_line() {
+        let source = "void main(void) {\n\
+                      \x20   int a[4];\n\
+                      \x20   a[input()] = 1;\n\
+                      }\n";
+        let checks = Checks {
+            bounds: true,
+            ..Checks::default()
+        };
+        let asm = checked_asm(source, checks);
+
+        assert_eq!(asm.matches("je .Lmain.check").count(), 2);
+        assert!(asm.contains(
+            ".Lmain.check1:\n\
+             \tmovl $3, %edi\n\
+             \tcall csub_bounds_error\n"
+        ));
+        assert!(asm.contains("csub_bounds_error:\n"));
+        if let Some(failure) = run_failing("bounds", &asm, "4\n") {
+            assert_eq!(
+                failure,
+                (
+                    1,
+                    "error: index out of the bounds of its array, at line 3\n"
+                        .to_string()
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn divisions_by_zero_report_their_line() {
+        let source = "void main(void) {\n\
+                      \x20   int n;\n\
+                      \x20   n = input();\n\
+                      \x20   output(100 / n);\n\
+                      }\n";
+        let checks = Checks {
+            division: true,
+            ..Checks::default()
+        };
+        let asm = checked_asm(source, checks);
+
+        assert!(asm.contains("csub_division_error:\n"));
+        assert!(!asm.contains("csub_bounds_error"));
+        if let Some(failure) = run_failing("division", &asm, "0\n") {
+            assert_eq!(
+                failure,
+                (1, "error: division by zero, at line 4\n".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn the_quotient_of_the_lowest_int_by_minus_one_wraps() {
+        let source = "void main(void) {\n\
+                      \x20   int n;\n\
+                      \x20   n = (-2147483647 - 1) / input();\n\
+                      }\n";
+        let asm = checked_asm(source, Checks::default());
+
+        assert!(asm.contains(
+            "\tcmpl $-1, %ecx\n\
+             \tjne .Lmain.div0\n\
+             \tnegl %eax\n\
+             \tnegl %ecx\n\
+             .Lmain.div0:\n\
+             \tcltd\n\
+             \tidivl %ecx\n"
+        ));
+        // `idivl` would stop it with a `SIGFPE`, which has no exit code.
+        if let Some(run) = run_failing("wrap", &asm, "-1\n") {
+            assert_eq!(run, (0, String::new()));
+        }
+    }
+
+    #[test]
+    fn lines_are_said_with_loc_directives() {
+        let source = "int sq(int x) {\n\
+                      \x20   return x * x;\n\
+                      }\n";
+        let source_file = SourceFile::new("main.cm".into(), source.into());
+        let program = lower_program_with_lines(
+            &hir(source),
+            Checks::default(),
+            &source_file,
+        );
+        let asm = emit_asm(&program, RegAlloc::Linear);
+
+        assert!(asm.starts_with("\t.file 1 \"main.cm\"\n"));
+        // The prologue is of the line of the function.
+        assert!(asm.contains("sq:\n\t.loc 1 1\n\tpushq %rbp\n"));
+        assert!(asm.contains("\t.loc 1 2\n\tmovl -"));
+    }
+
+    /// Compiles `path` with `cc`, allocating `registers` registers as
+    /// `regalloc` says, runs it with `input` and checks it writes what the
+    /// interpreter does. Skipped where there's no `cc`.
+    #[test]
+    fn corpus_programs_run_as_interpreted() {
+        for path in accept_corpus() {
+            for regalloc in [RegAlloc::Linear, RegAlloc::Graph] {
+                for registers in [REGS.len(), 1, 0] {
+                    check_runs_as_interpreted(
+                        &path,
+                        "5 3 9 1 7 2 8 6 4 10 -1\n",
+                        "prog.s",
+                        |program| {
+                            emit_asm_with_registers(
+                                program, regalloc, registers,
+                            )
+                            .into_bytes()
+                        },
+                    );
+                }
+            }
+        }
+    }
+}